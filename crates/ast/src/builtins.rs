@@ -1,4 +1,4 @@
-use crate::{DataType, ScalarType};
+use crate::{DataType, ScalarType, StructDecl};
 
 #[derive(
     Clone, Copy, Debug, PartialEq, Eq, Hash, strum::AsRefStr, strum::EnumIter, strum::EnumString,
@@ -16,6 +16,17 @@ pub enum BuiltinFn {
     Atan,
     Atanh,
     Atan2,
+    AtomicAdd,
+    AtomicAnd,
+    AtomicCompareExchangeWeak,
+    AtomicExchange,
+    AtomicLoad,
+    AtomicMax,
+    AtomicMin,
+    AtomicOr,
+    AtomicStore,
+    AtomicSub,
+    AtomicXor,
     Ceil,
     Clamp,
     Cos,
@@ -27,6 +38,8 @@ pub enum BuiltinFn {
     Degrees,
     Distance,
     Dot,
+    Dot4I8Packed,
+    Dot4U8Packed,
     Exp,
     Exp2,
     ExtractBits,
@@ -36,6 +49,7 @@ pub enum BuiltinFn {
     Floor,
     Fma,
     Fract,
+    Frexp,
     InsertBits,
     InverseSqrt,
     Ldexp,
@@ -45,7 +59,17 @@ pub enum BuiltinFn {
     Max,
     Min,
     Mix,
+    Modf,
     Normalize,
+    Pack2x16float,
+    Pack2x16snorm,
+    Pack2x16unorm,
+    Pack4x8snorm,
+    Pack4x8unorm,
+    Pack4xI8,
+    Pack4xI8Clamp,
+    Pack4xU8,
+    Pack4xU8Clamp,
     Pow,
     QuantizeToF16,
     Radians,
@@ -63,9 +87,47 @@ pub enum BuiltinFn {
     Smoothstep,
     Sqrt,
     Step,
+    StorageBarrier,
+    SubgroupAdd,
+    SubgroupAll,
+    SubgroupAnd,
+    SubgroupAny,
+    SubgroupBallot,
+    SubgroupBroadcast,
+    SubgroupBroadcastFirst,
+    SubgroupElect,
+    SubgroupExclusiveAdd,
+    SubgroupExclusiveMul,
+    SubgroupInclusiveAdd,
+    SubgroupInclusiveMul,
+    SubgroupMax,
+    SubgroupMin,
+    SubgroupMul,
+    SubgroupOr,
+    SubgroupShuffle,
+    SubgroupShuffleDown,
+    SubgroupShuffleUp,
+    SubgroupShuffleXor,
+    SubgroupXor,
     Tan,
     Tanh,
+    TextureDimensions,
+    TextureGather,
+    TextureGatherCompare,
+    TextureLoad,
+    TextureNumLayers,
+    TextureNumLevels,
+    TextureSampleLevel,
     Trunc,
+    Unpack2x16float,
+    Unpack2x16snorm,
+    Unpack2x16unorm,
+    Unpack4x8snorm,
+    Unpack4x8unorm,
+    Unpack4xI8,
+    Unpack4xU8,
+    WorkgroupBarrier,
+    WorkgroupUniformLoad,
 }
 
 impl BuiltinFn {
@@ -92,6 +154,24 @@ impl BuiltinFn {
             Atan => first_param()?,
             Atanh => first_param()?,
             Atan2 => first_param()?,
+            AtomicAdd | AtomicAnd | AtomicExchange | AtomicLoad | AtomicMax | AtomicMin
+            | AtomicOr | AtomicSub | AtomicXor => match first_param()? {
+                DataType::Ptr(view) => match view.inner.as_ref() {
+                    DataType::Atomic(t) => t.into(),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            AtomicCompareExchangeWeak => match first_param()? {
+                DataType::Ptr(view) => match view.inner.as_ref() {
+                    DataType::Atomic(t) => {
+                        DataType::Struct(StructDecl::atomic_compare_exchange_result(*t))
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            AtomicStore => return None,
             All => Bool.into(),
             Any => Bool.into(),
             ArrayLength => U32.into(),
@@ -104,8 +184,10 @@ impl BuiltinFn {
             CountTrailingZeros => first_param()?,
             Cross => first_param()?,
             Degrees => first_param()?,
-            Distance => F32.into(),
+            Distance => first_param()?.as_scalar()?.into(),
             Dot => first_param()?.as_scalar()?.into(),
+            Dot4I8Packed => I32.into(),
+            Dot4U8Packed => U32.into(),
             ExtractBits => first_param()?,
             Exp => first_param()?,
             Exp2 => first_param()?,
@@ -115,16 +197,27 @@ impl BuiltinFn {
             Floor => first_param()?,
             Fma => first_param()?,
             Fract => first_param()?,
+            Frexp => DataType::Struct(StructDecl::frexp_result(&first_param()?)),
             InsertBits => first_param()?,
             InverseSqrt => first_param()?,
             Ldexp => first_param()?,
-            Length => F32.into(),
+            Length => first_param()?.as_scalar()?.into(),
             Log => first_param()?,
             Log2 => first_param()?,
             Max => first_param()?,
             Min => first_param()?,
             Mix => first_param()?,
+            Modf => DataType::Struct(StructDecl::modf_result(&first_param()?)),
             Normalize => first_param()?,
+            Pack2x16float => U32.into(),
+            Pack2x16snorm => U32.into(),
+            Pack2x16unorm => U32.into(),
+            Pack4x8snorm => U32.into(),
+            Pack4x8unorm => U32.into(),
+            Pack4xI8 => U32.into(),
+            Pack4xI8Clamp => U32.into(),
+            Pack4xU8 => U32.into(),
+            Pack4xU8Clamp => U32.into(),
             Pow => first_param()?,
             QuantizeToF16 => first_param()?,
             Radians => first_param()?,
@@ -142,9 +235,69 @@ impl BuiltinFn {
             Smoothstep => first_param()?,
             Sqrt => first_param()?,
             Step => first_param()?,
+            StorageBarrier => return None,
+            SubgroupAdd => first_param()?,
+            SubgroupAll => Bool.into(),
+            SubgroupAnd => first_param()?,
+            SubgroupAny => Bool.into(),
+            SubgroupBallot => DataType::Vector(4, U32),
+            SubgroupBroadcast => first_param()?,
+            SubgroupBroadcastFirst => first_param()?,
+            SubgroupElect => Bool.into(),
+            SubgroupExclusiveAdd => first_param()?,
+            SubgroupExclusiveMul => first_param()?,
+            SubgroupInclusiveAdd => first_param()?,
+            SubgroupInclusiveMul => first_param()?,
+            SubgroupMax => first_param()?,
+            SubgroupMin => first_param()?,
+            SubgroupMul => first_param()?,
+            SubgroupOr => first_param()?,
+            SubgroupShuffle => first_param()?,
+            SubgroupShuffleDown => first_param()?,
+            SubgroupShuffleUp => first_param()?,
+            SubgroupShuffleXor => first_param()?,
+            SubgroupXor => first_param()?,
             Tan => first_param()?,
             Tanh => first_param()?,
+            TextureDimensions => match first_param()? {
+                DataType::Texture(dim, _) | DataType::DepthTexture(dim) => {
+                    match dim.size_components() {
+                        1 => U32.into(),
+                        n => DataType::Vector(n, U32),
+                    }
+                }
+                _ => return None,
+            },
+            // The component to gather is only passed for non-depth textures.
+            TextureGather => match first_param()? {
+                DataType::DepthTexture(_) => DataType::Vector(4, F32),
+                DataType::Scalar(_) => match first_param()? {
+                    DataType::Texture(_, t) => DataType::Vector(4, t),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            TextureGatherCompare => DataType::Vector(4, F32),
+            TextureLoad | TextureSampleLevel => match first_param()? {
+                DataType::Texture(_, t) => DataType::Vector(4, t),
+                DataType::DepthTexture(_) => F32.into(),
+                _ => return None,
+            },
+            TextureNumLayers => U32.into(),
+            TextureNumLevels => U32.into(),
             Trunc => first_param()?,
+            Unpack2x16float => DataType::Vector(2, F32),
+            Unpack2x16snorm => DataType::Vector(2, F32),
+            Unpack2x16unorm => DataType::Vector(2, F32),
+            Unpack4x8snorm => DataType::Vector(4, F32),
+            Unpack4x8unorm => DataType::Vector(4, F32),
+            Unpack4xI8 => DataType::Vector(4, I32),
+            Unpack4xU8 => DataType::Vector(4, U32),
+            WorkgroupBarrier => return None,
+            WorkgroupUniformLoad => match first_param()? {
+                DataType::Ptr(view) => view.inner.as_ref().clone(),
+                _ => return None,
+            },
         };
 
         Some(ret)
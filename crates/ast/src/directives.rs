@@ -0,0 +1,43 @@
+use derive_more::Display;
+
+#[derive(Clone, Debug, Display, Hash, PartialEq, Eq)]
+pub enum Extension {
+    #[display("subgroups")]
+    Subgroups,
+    #[display("f16")]
+    F16,
+    /// An extension which isn't otherwise supported, which is kept so that it is written back out.
+    #[display("{_0}")]
+    Unknown(String),
+}
+
+#[derive(Clone, Debug, Display, Hash, PartialEq, Eq)]
+pub enum LanguageExtension {
+    #[display("packed_4x8_integer_dot_product")]
+    Packed4x8IntegerDotProduct,
+    /// A language extension which isn't otherwise supported.
+    #[display("{_0}")]
+    Unknown(String),
+}
+
+#[derive(Clone, Copy, Debug, Display, Hash, PartialEq, Eq)]
+pub enum DiagnosticSeverity {
+    #[display("error")]
+    Error,
+    #[display("warning")]
+    Warning,
+    #[display("info")]
+    Info,
+    #[display("off")]
+    Off,
+}
+
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum Directive {
+    #[display("enable {_0};")]
+    Enable(Extension),
+    #[display("requires {_0};")]
+    Requires(LanguageExtension),
+    #[display("diagnostic({_0}, {_1});")]
+    Diagnostic(DiagnosticSeverity, String),
+}
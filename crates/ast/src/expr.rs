@@ -10,15 +10,25 @@ pub enum Lit {
     I32(i32),
     U32(u32),
     F32(f32),
+    /// An `f16` literal, which is stored as an `f32` since Rust has no stable half precision type.
+    F16(f32),
+    /// An unsuffixed integer literal, which is converted to the type required by its context.
+    AbstractInt(i64),
+    /// A floating point literal without an `f` suffix, which is converted to the type required by
+    /// its context.
+    AbstractFloat(f64),
 }
 
 impl Lit {
+    /// Returns the type of the literal, which for abstract literals is the type they are
+    /// concretized to when nothing else constrains them.
     pub fn data_type(&self) -> DataType {
         match self {
             Lit::Bool(_) => ScalarType::Bool.into(),
-            Lit::I32(_) => ScalarType::I32.into(),
+            Lit::I32(_) | Lit::AbstractInt(_) => ScalarType::I32.into(),
             Lit::U32(_) => ScalarType::U32.into(),
-            Lit::F32(_) => ScalarType::F32.into(),
+            Lit::F32(_) | Lit::AbstractFloat(_) => ScalarType::F32.into(),
+            Lit::F16(_) => ScalarType::F16.into(),
         }
     }
 }
@@ -38,6 +48,11 @@ impl Display for Lit {
             }
             Lit::U32(v) => write!(f, "{v}u"),
             Lit::F32(v) => write!(f, "{v}f"),
+            Lit::F16(v) => write!(f, "{v}h"),
+            Lit::AbstractInt(v) => write!(f, "{v}"),
+            // The debug representation always contains a decimal point or an exponent, so that the
+            // literal isn't parsed as an integer.
+            Lit::AbstractFloat(v) => write!(f, "{v:?}"),
         }
     }
 }
@@ -55,6 +70,22 @@ impl TypeConsExpr {
     }
 }
 
+#[derive(Clone, Debug, Display, PartialEq)]
+#[display("bitcast<{data_type}>({inner})")]
+pub struct BitcastExpr {
+    pub data_type: DataType,
+    pub inner: Box<ExprNode>,
+}
+
+impl BitcastExpr {
+    pub fn new(data_type: DataType, inner: impl Into<ExprNode>) -> Self {
+        Self {
+            data_type,
+            inner: Box::new(inner.into()),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
 pub enum UnOp {
     #[display("-")]
@@ -397,6 +428,7 @@ impl FnCallExpr {
 pub enum Expr {
     Lit(Lit),
     TypeCons(TypeConsExpr),
+    Bitcast(BitcastExpr),
     Var(VarExpr),
     Postfix(PostfixExpr),
     UnOp(UnOpExpr),
@@ -429,6 +461,15 @@ impl From<TypeConsExpr> for ExprNode {
     }
 }
 
+impl From<BitcastExpr> for ExprNode {
+    fn from(expr: BitcastExpr) -> Self {
+        ExprNode {
+            data_type: expr.data_type.clone(),
+            expr: expr.into(),
+        }
+    }
+}
+
 impl From<PostfixExpr> for ExprNode {
     fn from(expr: PostfixExpr) -> Self {
         ExprNode {
@@ -3,7 +3,7 @@ use std::fmt::Display;
 use derive_more::Display;
 
 use crate::stmt::Statement;
-use crate::types::DataType;
+use crate::types::{ConstSize, DataType, ScalarType};
 
 #[derive(Debug, Display, PartialEq, Eq)]
 pub enum ShaderStage {
@@ -19,12 +19,49 @@ pub enum ShaderStage {
 pub enum FnAttr {
     #[display("stage({_0})")]
     Stage(ShaderStage),
-    #[display("workgroup_size({_0})")]
-    WorkgroupSize(u32),
+    /// Workgroup size in the x dimension, followed by the optional y and z dimensions.
+    #[display(
+        "workgroup_size({})",
+        _0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    WorkgroupSize(Vec<ConstSize>),
+}
+
+/// Built-in values that can be passed to a compute shader entrypoint.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum BuiltinValue {
+    #[display("local_invocation_id")]
+    LocalInvocationId,
+    #[display("local_invocation_index")]
+    LocalInvocationIndex,
+    #[display("global_invocation_id")]
+    GlobalInvocationId,
+    #[display("workgroup_id")]
+    WorkgroupId,
+    #[display("num_workgroups")]
+    NumWorkgroups,
+    #[display("subgroup_invocation_id")]
+    SubgroupInvocationId,
+    #[display("subgroup_size")]
+    SubgroupSize,
+}
+
+impl BuiltinValue {
+    pub fn data_type(self) -> DataType {
+        match self {
+            BuiltinValue::LocalInvocationIndex
+            | BuiltinValue::SubgroupInvocationId
+            | BuiltinValue::SubgroupSize => DataType::Scalar(ScalarType::U32),
+            _ => DataType::Vector(3, ScalarType::U32),
+        }
+    }
 }
 
 #[derive(Debug, Display, PartialEq, Eq)]
-pub enum FnInputAttr {}
+pub enum FnInputAttr {
+    #[display("builtin({_0})")]
+    Builtin(BuiltinValue),
+}
 
 #[derive(Debug, Display, PartialEq, Eq)]
 pub enum FnOutputAttr {}
@@ -84,6 +84,21 @@ impl GlobalVarDecl {
     }
 }
 
+#[derive(Debug, Display, PartialEq, Eq)]
+pub enum GlobalOverrideAttr {
+    #[display("id({_0})")]
+    Id(u32),
+}
+
+/// A pipeline-overridable constant, whose value may be set when the pipeline is created.
+#[derive(Debug, PartialEq)]
+pub struct GlobalOverrideDecl {
+    pub attrs: Vec<GlobalOverrideAttr>,
+    pub name: String,
+    pub data_type: DataType,
+    pub initializer: Option<ExprNode>,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct GlobalConstDecl {
     pub name: String,
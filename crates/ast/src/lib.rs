@@ -2,6 +2,7 @@ pub mod types;
 pub mod writer;
 
 mod builtins;
+mod directives;
 mod expr;
 mod func;
 mod globals;
@@ -9,6 +10,7 @@ mod stmt;
 mod structs;
 
 pub use builtins::*;
+pub use directives::*;
 pub use expr::*;
 pub use func::*;
 pub use globals::*;
@@ -17,16 +19,35 @@ pub use structs::*;
 
 use std::rc::Rc;
 
-pub use types::{DataType, ScalarType};
+pub use types::{ConstSize, DataType, ScalarType, TextureDimension};
 
 #[derive(Debug, PartialEq)]
 pub struct Module {
+    pub directives: Vec<Directive>,
     pub structs: Vec<Rc<StructDecl>>,
     pub consts: Vec<GlobalConstDecl>,
+    pub overrides: Vec<GlobalOverrideDecl>,
+    pub const_asserts: Vec<ConstAssertStatement>,
     pub vars: Vec<GlobalVarDecl>,
     pub functions: Vec<FnDecl>,
 }
 
+impl Module {
+    /// Returns true if the module contains an `enable` directive for the given extension.
+    pub fn is_enabled(&self, extension: Extension) -> bool {
+        self.directives
+            .iter()
+            .any(|it| matches!(it, Directive::Enable(e) if *e == extension))
+    }
+
+    /// Returns true if the module contains a `requires` directive for the given language extension.
+    pub fn is_required(&self, extension: LanguageExtension) -> bool {
+        self.directives
+            .iter()
+            .any(|it| matches!(it, Directive::Requires(e) if *e == extension))
+    }
+}
+
 struct FmtArgs<'a>(&'a [ExprNode]);
 
 impl std::fmt::Display for FmtArgs<'_> {
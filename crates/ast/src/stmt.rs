@@ -4,7 +4,7 @@ use derive_more::{Display, From};
 use indenter::indented;
 
 use crate::types::DataType;
-use crate::{ExprNode, Postfix};
+use crate::{BinOp, Expr, ExprNode, Postfix, PostfixExpr, UnOp, UnOpExpr, VarExpr};
 
 #[derive(Debug, Display, PartialEq)]
 #[display("let {ident} = {initializer}")]
@@ -32,6 +32,38 @@ impl LetDeclStatement {
     }
 }
 
+#[derive(Debug, Display, PartialEq)]
+#[display("const {ident} = {initializer}")]
+pub struct ConstDeclStatement {
+    pub ident: String,
+    pub initializer: ExprNode,
+}
+
+impl ConstDeclStatement {
+    pub fn new(ident: impl Into<String>, initializer: impl Into<ExprNode>) -> Self {
+        Self {
+            ident: ident.into(),
+            initializer: initializer.into(),
+        }
+    }
+}
+
+/// A `const_assert` statement.
+///
+/// The expression is always written in parentheses, since naga stops parsing the assertion after
+/// a leading parenthesized expression (e.g. `const_assert (a) && b`).
+#[derive(Debug, Display, PartialEq)]
+#[display("const_assert({expr})")]
+pub struct ConstAssertStatement {
+    pub expr: ExprNode,
+}
+
+impl ConstAssertStatement {
+    pub fn new(expr: impl Into<ExprNode>) -> Self {
+        Self { expr: expr.into() }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct VarDeclStatement {
     pub ident: String,
@@ -111,14 +143,14 @@ impl AssignmentLhs {
     }
 }
 
-#[derive(Debug, Display, PartialEq)]
+#[derive(Clone, Debug, Display, PartialEq)]
 pub enum LhsExpr {
     Ident(String),
     #[display("({_0}){_1}")]
     Postfix(Box<LhsExprNode>, Postfix),
-    #[display("*(_0)")]
+    #[display("*({_0})")]
     Deref(Box<LhsExprNode>),
-    #[display("&(_0)")]
+    #[display("&({_0})")]
     AddressOf(Box<LhsExprNode>),
 }
 
@@ -128,13 +160,30 @@ impl From<LhsExprNode> for AssignmentLhs {
     }
 }
 
-#[derive(Debug, Display, PartialEq)]
+#[derive(Clone, Debug, Display, PartialEq)]
 #[display("{expr}")]
 pub struct LhsExprNode {
     pub data_type: DataType,
     pub expr: LhsExpr,
 }
 
+/// Converts an lhs expression into an expression that refers to the same memory location.
+impl From<LhsExprNode> for ExprNode {
+    fn from(node: LhsExprNode) -> Self {
+        let expr = match node.expr {
+            LhsExpr::Ident(ident) => Expr::Var(VarExpr::new(ident)),
+            LhsExpr::Postfix(inner, postfix) => Expr::Postfix(PostfixExpr::new(*inner, postfix)),
+            LhsExpr::Deref(inner) => Expr::UnOp(UnOpExpr::new(UnOp::Deref, *inner)),
+            LhsExpr::AddressOf(inner) => Expr::UnOp(UnOpExpr::new(UnOp::AddressOf, *inner)),
+        };
+
+        ExprNode {
+            data_type: node.data_type,
+            expr,
+        }
+    }
+}
+
 impl LhsExprNode {
     pub fn name(name: String, data_type: DataType) -> LhsExprNode {
         LhsExprNode {
@@ -186,7 +235,7 @@ impl LhsExprNode {
     }
 }
 
-#[derive(Debug, Display, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
 pub enum AssignmentOp {
     #[display("=")]
     Simple,
@@ -206,6 +255,30 @@ pub enum AssignmentOp {
     Or,
     #[display("^=")]
     Xor,
+    #[display("<<=")]
+    LShift,
+    #[display(">>=")]
+    RShift,
+}
+
+impl AssignmentOp {
+    /// Returns the binary operator applied by a compound assignment, or `None` for a simple
+    /// assignment.
+    pub fn bin_op(&self) -> Option<BinOp> {
+        Some(match self {
+            AssignmentOp::Simple => return None,
+            AssignmentOp::Plus => BinOp::Plus,
+            AssignmentOp::Minus => BinOp::Minus,
+            AssignmentOp::Times => BinOp::Times,
+            AssignmentOp::Divide => BinOp::Divide,
+            AssignmentOp::Mod => BinOp::Mod,
+            AssignmentOp::And => BinOp::BitAnd,
+            AssignmentOp::Or => BinOp::BitOr,
+            AssignmentOp::Xor => BinOp::BitXOr,
+            AssignmentOp::LShift => BinOp::LShift,
+            AssignmentOp::RShift => BinOp::RShift,
+        })
+    }
 }
 
 #[derive(Debug, Display, PartialEq)]
@@ -337,11 +410,20 @@ impl Display for ReturnStatement {
 #[derive(Debug, PartialEq)]
 pub struct LoopStatement {
     pub body: Vec<Statement>,
+    pub continuing: Option<ContinuingStatement>,
 }
 
 impl LoopStatement {
     pub fn new(body: Vec<Statement>) -> Self {
-        Self { body }
+        Self {
+            body,
+            continuing: None,
+        }
+    }
+
+    pub fn with_continuing(mut self, continuing: Option<ContinuingStatement>) -> Self {
+        self.continuing = continuing;
+        self
     }
 }
 
@@ -353,6 +435,66 @@ impl Display for LoopStatement {
             writeln!(indented(f), "{}", stmt)?;
         }
 
+        if let Some(continuing) = &self.continuing {
+            writeln!(indented(f), "{}", continuing)?;
+        }
+
+        write!(f, "}}")
+    }
+}
+
+/// The `continuing` block at the end of a `loop`, which is executed at the end of each iteration.
+#[derive(Debug, PartialEq)]
+pub struct ContinuingStatement {
+    pub body: Vec<Statement>,
+    pub break_if: Option<ExprNode>,
+}
+
+impl ContinuingStatement {
+    pub fn new(body: Vec<Statement>, break_if: Option<ExprNode>) -> Self {
+        Self { body, break_if }
+    }
+}
+
+impl Display for ContinuingStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "continuing {{")?;
+
+        for stmt in &self.body {
+            writeln!(indented(f), "{}", stmt)?;
+        }
+
+        if let Some(condition) = &self.break_if {
+            writeln!(indented(f), "break if {condition};")?;
+        }
+
+        write!(f, "}}")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WhileStatement {
+    pub condition: ExprNode,
+    pub body: Vec<Statement>,
+}
+
+impl WhileStatement {
+    pub fn new(condition: impl Into<ExprNode>, body: Vec<Statement>) -> Self {
+        Self {
+            condition: condition.into(),
+            body,
+        }
+    }
+}
+
+impl Display for WhileStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "while ({}) {{", self.condition)?;
+
+        for stmt in &self.body {
+            writeln!(indented(f), "{}", stmt)?;
+        }
+
         write!(f, "}}")
     }
 }
@@ -420,6 +562,8 @@ pub enum ForLoopInit {
 #[derive(Debug, PartialEq)]
 pub enum ForLoopUpdate {
     Assignment(AssignmentStatement),
+    Increment(LhsExprNode),
+    Decrement(LhsExprNode),
 }
 
 #[derive(Debug, PartialEq)]
@@ -467,6 +611,8 @@ impl Display for ForLoopStatement {
         if let Some(update) = &header.update {
             match update {
                 ForLoopUpdate::Assignment(stmt) => stmt.fmt(f)?,
+                ForLoopUpdate::Increment(lhs) => write!(f, "{lhs}++")?,
+                ForLoopUpdate::Decrement(lhs) => write!(f, "{lhs}--")?,
             }
         }
 
@@ -510,8 +656,14 @@ impl Display for FnCallStatement {
 #[derive(Debug, PartialEq, From)]
 pub enum Statement {
     LetDecl(LetDeclStatement),
+    ConstDecl(ConstDeclStatement),
+    ConstAssert(ConstAssertStatement),
     VarDecl(VarDeclStatement),
     Assignment(AssignmentStatement),
+    #[from(skip)]
+    Increment(LhsExprNode),
+    #[from(skip)]
+    Decrement(LhsExprNode),
     Compound(Vec<Statement>),
     If(IfStatement),
     Return(ReturnStatement),
@@ -521,6 +673,7 @@ pub enum Statement {
     Switch(SwitchStatement),
     Fallthrough,
     ForLoop(ForLoopStatement),
+    While(WhileStatement),
     FnCall(FnCallStatement),
 }
 
@@ -540,8 +693,12 @@ impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Statement::LetDecl(stmt) => write!(f, "{stmt};"),
+            Statement::ConstDecl(stmt) => write!(f, "{stmt};"),
+            Statement::ConstAssert(stmt) => write!(f, "{stmt};"),
             Statement::VarDecl(stmt) => write!(f, "{stmt};"),
             Statement::Assignment(stmt) => write!(f, "{stmt};"),
+            Statement::Increment(lhs) => write!(f, "{lhs}++;"),
+            Statement::Decrement(lhs) => write!(f, "{lhs}--;"),
             Statement::Compound(stmts) => {
                 writeln!(f, "{{")?;
 
@@ -559,6 +716,7 @@ impl Display for Statement {
             Statement::Fallthrough => write!(f, "fallthrough;"),
             Statement::Switch(stmt) => stmt.fmt(f),
             Statement::ForLoop(stmt) => stmt.fmt(f),
+            Statement::While(stmt) => stmt.fmt(f),
             Statement::FnCall(stmt) => write!(f, "{stmt};"),
         }
     }
@@ -1,15 +1,18 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
 use std::hash::Hash;
 use std::rc::Rc;
 
 use derive_more::Display;
 
-use crate::types::DataType;
+use crate::types::{DataType, ScalarType};
 
 #[derive(Clone, Debug, Display, Hash, PartialEq, Eq)]
 pub enum StructMemberAttr {
     #[display("align({_0})")]
     Align(u8),
+    #[display("size({_0})")]
+    Size(u32),
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -33,13 +36,23 @@ impl StructMember {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct StructDecl {
     pub name: String,
     pub members: Vec<Rc<StructMember>>,
     accessors: HashMap<DataType, Vec<Rc<StructMember>>>,
 }
 
+impl Debug for StructDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Accessors are derived from the members, and are omitted since their order is not stable.
+        f.debug_struct("StructDecl")
+            .field("name", &self.name)
+            .field("members", &self.members)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Hash for StructDecl {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.name.hash(state);
@@ -86,6 +99,54 @@ impl StructDecl {
             .find(|it| it.name == name)
             .map(|it| &it.data_type)
     }
+
+    /// Returns the type that `frexp` returns for an argument of type `ty` (either `f32` or a
+    /// vector of `f32`), which has `fract` and `exp` members.
+    ///
+    /// Builtin result structs can't be named in WGSL, so they are never declared in a module.
+    pub fn frexp_result(ty: &DataType) -> Rc<StructDecl> {
+        StructDecl::new(
+            format!("__frexp_result_{}", builtin_result_suffix(ty)),
+            vec![
+                StructMember::new(vec![], "fract", ty.clone()),
+                StructMember::new(vec![], "exp", ty.map(ScalarType::I32)),
+            ],
+        )
+    }
+
+    /// Returns the type that `modf` returns for an argument of type `ty` (either `f32` or a
+    /// vector of `f32`), which has `fract` and `whole` members.
+    pub fn modf_result(ty: &DataType) -> Rc<StructDecl> {
+        StructDecl::new(
+            format!("__modf_result_{}", builtin_result_suffix(ty)),
+            vec![
+                StructMember::new(vec![], "fract", ty.clone()),
+                StructMember::new(vec![], "whole", ty.clone()),
+            ],
+        )
+    }
+
+    /// Returns the type that `atomicCompareExchangeWeak` returns for an atomic of type `ty`, which
+    /// has `old_value` and `exchanged` members.
+    pub fn atomic_compare_exchange_result(ty: ScalarType) -> Rc<StructDecl> {
+        StructDecl::new(
+            format!("__atomic_compare_exchange_result<{ty}>"),
+            vec![
+                StructMember::new(vec![], "old_value", ty.into()),
+                StructMember::new(vec![], "exchanged", ScalarType::Bool.into()),
+            ],
+        )
+    }
+}
+
+/// Returns the suffix that the WGSL spec uses in the name of a builtin result struct, e.g. `f32`
+/// or `vec3_f32`.
+fn builtin_result_suffix(ty: &DataType) -> String {
+    match ty {
+        DataType::Scalar(t) => t.to_string(),
+        DataType::Vector(n, t) => format!("vec{n}_{t}"),
+        _ => panic!("invalid argument type for builtin result struct: `{ty}`"),
+    }
 }
 
 /// For a list of struct members, this function will build a mapping from data types to lists of
@@ -95,45 +156,61 @@ fn collect_struct_accessors(
 ) -> HashMap<DataType, Vec<Rc<StructMember>>> {
     let mut accessors = HashMap::new();
 
+    // Members are kept in declaration order so that random selection is deterministic
     fn insert(
-        map: &mut HashMap<DataType, HashSet<Rc<StructMember>>>,
+        map: &mut HashMap<DataType, Vec<Rc<StructMember>>>,
         ty: &DataType,
         member: &Rc<StructMember>,
     ) {
-        map.entry(ty.clone()).or_default().insert(member.clone());
+        let members = map.entry(ty.clone()).or_default();
+        if !members.contains(member) {
+            members.push(member.clone());
+        }
     }
 
-    for member in members {
-        insert(&mut accessors, &member.data_type, member);
+    fn insert_accessible(
+        map: &mut HashMap<DataType, Vec<Rc<StructMember>>>,
+        ty: &DataType,
+        member: &Rc<StructMember>,
+    ) {
+        insert(map, ty, member);
 
-        match &member.data_type {
+        match ty {
             DataType::Scalar(_) => {}
             DataType::Vector(n, ty) => {
                 // Access to component type
-                insert(&mut accessors, &DataType::Scalar(*ty), member);
+                insert(map, &DataType::Scalar(*ty), member);
 
                 // Access to subvectors via swizzling
                 for i in 2..*n {
-                    insert(&mut accessors, &DataType::Vector(i, *ty), member);
+                    insert(map, &DataType::Vector(i, *ty), member);
                 }
             }
-            DataType::Array(_, _) => {
-                // TODO
+            DataType::Array(ty, _) => {
+                // Access to elements via indexing
+                insert_accessible(map, ty, member);
             }
             DataType::Struct(decl) => {
                 for ty in decl.accessible_types() {
-                    insert(&mut accessors, ty, member);
+                    insert(map, ty, member);
                 }
             }
             DataType::Ptr(_) => unreachable!("pointers are not storable"),
             DataType::Ref(_) => unreachable!("references are not storable"),
+            DataType::Texture(_, _) | DataType::DepthTexture(_) => {
+                unreachable!("textures are not storable")
+            }
+            DataType::Sampler | DataType::SamplerComparison => {
+                unreachable!("samplers are not storable")
+            }
+            // Atomics can only be accessed through the atomic builtins.
+            DataType::Atomic(_) => {}
         }
     }
 
-    // Convert the sets into vectors
-    // We use vectors for more efficient random selection later on
+    for member in members {
+        insert_accessible(&mut accessors, &member.data_type, member);
+    }
+
     accessors
-        .into_iter()
-        .map(|(k, v)| (k, v.into_iter().collect()))
-        .collect()
 }
@@ -1,9 +1,10 @@
 use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
 use derive_more::Display;
 
-use crate::{AccessMode, StorageClass, StructDecl};
+use crate::{AccessMode, ExprNode, StorageClass, StructDecl};
 
 #[derive(Clone, Copy, Debug, Display, Hash, PartialEq, Eq)]
 pub enum ScalarType {
@@ -15,6 +16,45 @@ pub enum ScalarType {
     U32,
     #[display("f32")]
     F32,
+    #[display("f16")]
+    F16,
+}
+
+/// The dimensionality of a sampled texture.
+#[derive(Clone, Copy, Debug, Display, Hash, PartialEq, Eq)]
+pub enum TextureDimension {
+    #[display("1d")]
+    D1,
+    #[display("2d")]
+    D2,
+    #[display("2d_array")]
+    D2Array,
+    #[display("3d")]
+    D3,
+    #[display("cube")]
+    Cube,
+    #[display("cube_array")]
+    CubeArray,
+}
+
+impl TextureDimension {
+    /// Returns the number of components in the size returned by `textureDimensions`.
+    pub fn size_components(self) -> u8 {
+        match self {
+            TextureDimension::D1 => 1,
+            TextureDimension::D3 => 3,
+            _ => 2,
+        }
+    }
+
+    /// Returns true if the texture has array layers, which can be queried with
+    /// `textureNumLayers`.
+    pub fn is_arrayed(self) -> bool {
+        matches!(
+            self,
+            TextureDimension::D2Array | TextureDimension::CubeArray
+        )
+    }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -51,19 +91,106 @@ impl Display for MemoryViewType {
     }
 }
 
+/// A size which must be given by a const-expression, such as an array element count or a
+/// workgroup size.
+///
+/// Sizes compare equal if they have the same value, regardless of whether they are written as a
+/// literal, by naming a constant or by an expression. Sizes whose values are unknown compare equal
+/// only if they are written the same way.
+///
+/// Workgroup sizes and the sizes of arrays in the `workgroup` address space may also be given by
+/// an override, in which case the value is the default value of the override. The value is unknown
+/// if the override has no default value, or if the size is an expression which can't be evaluated
+/// when parsing.
+#[derive(Clone)]
+pub enum ConstSize {
+    Lit(u32),
+    Const(String, u32),
+    Override(String, Option<u32>),
+    Expr(Box<ExprNode>, Option<u32>),
+}
+
+impl ConstSize {
+    pub fn value(&self) -> Option<u32> {
+        match self {
+            ConstSize::Lit(v) => Some(*v),
+            ConstSize::Const(_, v) => Some(*v),
+            ConstSize::Override(_, v) => *v,
+            ConstSize::Expr(_, v) => *v,
+        }
+    }
+}
+
+impl From<u32> for ConstSize {
+    fn from(value: u32) -> Self {
+        ConstSize::Lit(value)
+    }
+}
+
+impl PartialEq for ConstSize {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.value(), other.value()) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => self.to_string() == other.to_string(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ConstSize {}
+
+impl Hash for ConstSize {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value().hash(state);
+    }
+}
+
+impl fmt::Debug for ConstSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lit(v) => fmt::Debug::fmt(v, f),
+            Self::Const(name, v) => f.debug_tuple("Const").field(name).field(v).finish(),
+            Self::Override(name, v) => f.debug_tuple("Override").field(name).field(v).finish(),
+            Self::Expr(expr, v) => f
+                .debug_tuple("Expr")
+                .field(&format_args!("{expr}"))
+                .field(v)
+                .finish(),
+        }
+    }
+}
+
+impl Display for ConstSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstSize::Lit(v) => write!(f, "{v}"),
+            ConstSize::Const(name, _) | ConstSize::Override(name, _) => write!(f, "{name}"),
+            ConstSize::Expr(expr, _) => write!(f, "{expr}"),
+        }
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub enum DataType {
     Scalar(ScalarType),
     Vector(u8, ScalarType),
-    Array(Rc<DataType>, Option<u32>),
+    Array(Rc<DataType>, Option<ConstSize>),
     Struct(Rc<StructDecl>),
     Ptr(MemoryViewType),
     Ref(MemoryViewType),
+    Texture(TextureDimension, ScalarType),
+    DepthTexture(TextureDimension),
+    Sampler,
+    SamplerComparison,
+    Atomic(ScalarType),
 }
 
 impl DataType {
     pub fn array(element_type: impl Into<DataType>, size: impl Into<Option<u32>>) -> DataType {
-        DataType::Array(Rc::new(element_type.into()), size.into())
+        DataType::Array(
+            Rc::new(element_type.into()),
+            size.into().map(ConstSize::Lit),
+        )
     }
 
     pub fn map(&self, scalar: ScalarType) -> DataType {
@@ -123,10 +250,27 @@ impl DataType {
         matches!(self.as_scalar(), Some(ScalarType::I32 | ScalarType::U32))
     }
 
+    /// Returns `true` if the data type is a scalar or vector of floats.
+    pub fn is_float(&self) -> bool {
+        matches!(self.as_scalar(), Some(ScalarType::F32 | ScalarType::F16))
+    }
+
     /// Returns `true` if the data type is a scalar or vector of signed integers.
     pub fn is_signed_int(&self) -> bool {
         matches!(self.as_scalar(), Some(ScalarType::I32))
     }
+
+    /// Returns `true` if the data type is a texture or sampler, which are declared at module scope
+    /// without an address space.
+    pub fn is_handle(&self) -> bool {
+        matches!(
+            self,
+            DataType::Texture(..)
+                | DataType::DepthTexture(_)
+                | DataType::Sampler
+                | DataType::SamplerComparison
+        )
+    }
 }
 
 impl fmt::Debug for DataType {
@@ -138,6 +282,11 @@ impl fmt::Debug for DataType {
             Self::Struct(arg0) => f.debug_tuple("Struct").field(&arg0.name).finish(),
             Self::Ptr(arg0) => f.debug_tuple("Ptr").field(arg0).finish(),
             Self::Ref(arg0) => f.debug_tuple("Ref").field(arg0).finish(),
+            Self::Texture(arg0, arg1) => f.debug_tuple("Texture").field(arg0).field(arg1).finish(),
+            Self::DepthTexture(arg0) => f.debug_tuple("DepthTexture").field(arg0).finish(),
+            Self::Sampler => write!(f, "Sampler"),
+            Self::SamplerComparison => write!(f, "SamplerComparison"),
+            Self::Atomic(arg0) => f.debug_tuple("Atomic").field(arg0).finish(),
         }
     }
 }
@@ -157,6 +306,11 @@ impl Display for DataType {
             DataType::Struct(decl) => write!(f, "{}", decl.name),
             DataType::Ptr(view) => write!(f, "ptr<{view}>"),
             DataType::Ref(view) => write!(f, "ref<{view}>"),
+            DataType::Texture(dim, t) => write!(f, "texture_{dim}<{t}>"),
+            DataType::DepthTexture(dim) => write!(f, "texture_depth_{dim}"),
+            DataType::Sampler => write!(f, "sampler"),
+            DataType::SamplerComparison => write!(f, "sampler_comparison"),
+            DataType::Atomic(t) => write!(f, "atomic<{t}>"),
         }
     }
 }
@@ -2,25 +2,22 @@ use std::fmt::{Display, Result, Write};
 
 use indenter::indented;
 
-use crate::{FnAttr, FnDecl, GlobalConstDecl, GlobalVarDecl, Module, StructDecl};
+use crate::{
+    FnAttr, FnDecl, GlobalConstDecl, GlobalOverrideDecl, GlobalVarDecl, Module, StructDecl,
+};
 
-#[derive(Default)]
-pub struct Writer {
-    #[allow(unused)]
-    options: Options,
-}
-
-#[derive(Default)]
-pub struct Options {
-    pub module_scope_constants: bool,
-}
+pub struct Writer;
 
 impl Writer {
-    pub fn new(options: Options) -> Writer {
-        Writer { options }
-    }
-
     pub fn write_module(&self, f: &mut dyn Write, module: &Module) -> Result {
+        for directive in &module.directives {
+            writeln!(f, "{directive}")?;
+        }
+
+        if !module.directives.is_empty() {
+            writeln!(f)?;
+        }
+
         for decl in &module.structs {
             self.write_struct(f, decl)?;
             writeln!(f)?;
@@ -31,6 +28,19 @@ impl Writer {
             writeln!(f)?;
         }
 
+        for decl in &module.overrides {
+            self.write_global_override(f, decl)?;
+            writeln!(f)?;
+        }
+
+        for stmt in &module.const_asserts {
+            writeln!(f, "{stmt};")?;
+        }
+
+        if !module.const_asserts.is_empty() {
+            writeln!(f)?;
+        }
+
         for decl in &module.vars {
             self.write_global_var(f, decl)?;
             writeln!(f)?;
@@ -58,19 +68,25 @@ impl Writer {
     }
 
     pub fn write_global_const(&self, f: &mut dyn Write, decl: &GlobalConstDecl) -> Result {
-        if self.options.module_scope_constants {
-            write!(f, "const")?;
-        } else {
-            write!(f, "let")?;
-        }
-
         writeln!(
             f,
-            " {}: {} = {};",
+            "const {}: {} = {};",
             decl.name, decl.data_type, decl.initializer
         )
     }
 
+    pub fn write_global_override(&self, f: &mut dyn Write, decl: &GlobalOverrideDecl) -> Result {
+        self.write_attrs(f, decl.attrs.iter())?;
+
+        write!(f, "override {}: {}", decl.name, decl.data_type)?;
+
+        if let Some(initializer) = &decl.initializer {
+            write!(f, " = {initializer}")?;
+        }
+
+        writeln!(f, ";")
+    }
+
     pub fn write_global_var(&self, f: &mut dyn Write, decl: &GlobalVarDecl) -> Result {
         self.write_attrs(f, decl.attrs.iter())?;
 
@@ -0,0 +1,48 @@
+//! Digests of outputs, which identify outputs that match exactly without needing the outputs
+//! themselves (e.g. so that a server only has to send the outputs of configs that disagree).
+
+use std::fmt::{self, Display};
+
+use common::Type;
+use reflection_types::PipelineDescription;
+
+use crate::{normalize_execution, ComparisonPolicy};
+
+/// The BLAKE3 hash of an output that was normalized with [`normalize_execution`]. Outputs only
+/// have the same digest if their normalized bytes are the same, so floats that match within
+/// tolerances may still have different digests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Digest(pub [u8; 32]);
+
+impl Digest {
+    pub fn of_normalized(normalized: &[u8]) -> Digest {
+        Digest(*blake3::hash(normalized).as_bytes())
+    }
+
+    /// Normalizes the output buffers of an execution under the policy, and returns their digest.
+    /// Only the tolerances of the policy are ignored, so NaNs and negative zero are canonicalized
+    /// first if it asks for it.
+    pub fn of_execution(
+        buffers: &[Vec<u8>],
+        pipeline_desc: &PipelineDescription,
+        type_descs: &[Type],
+        policy: &ComparisonPolicy,
+    ) -> Digest {
+        Digest::of_normalized(&normalize_execution(
+            buffers,
+            pipeline_desc,
+            type_descs,
+            policy,
+        ))
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
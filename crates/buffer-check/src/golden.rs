@@ -0,0 +1,207 @@
+//! Golden outputs, which are the output buffers of an execution that is known to be correct, saved
+//! so that later executions of the same shader can be checked against them (e.g. to make sure that
+//! a bug stays fixed).
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+
+use common::Type;
+use reflection_types::{PipelineDescription, ResourceKind};
+
+use crate::{diff_normalized, normalize_execution, ComparisonPolicy, ValueDiff};
+
+/// Bytes that golden files start with.
+const MAGIC: &[u8; 8] = b"WGSLGOLD";
+
+/// Version of the golden file format, which is incremented whenever it changes.
+const VERSION: u32 = 1;
+
+/// The contents of the output buffers of an execution, keyed by `group:binding`. This is the same
+/// as the JSON format of expected outputs, so either can be used to check executions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GoldenOutput {
+    pub buffers: BTreeMap<String, Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    /// The pipeline has a storage buffer that the golden output doesn't have contents for.
+    MissingBuffer(String),
+    /// The contents of a buffer are shorter than the buffer, which usually means that the golden
+    /// output was saved for a different shader.
+    TooShort {
+        key: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::MissingBuffer(key) => write!(f, "no expected output for buffer `{key}`"),
+            GoldenError::TooShort {
+                key,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "expected output for buffer `{key}` is {actual} bytes, but the buffer is {expected} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+impl GoldenOutput {
+    /// Creates a golden output from the output buffers of an execution, which are in the same
+    /// order as the storage buffers of the pipeline.
+    pub fn from_execution(buffers: &[Vec<u8>], pipeline_desc: &PipelineDescription) -> Self {
+        let buffers = storage_buffers(pipeline_desc)
+            .zip(buffers)
+            .map(|((key, _), buffer)| (key, buffer.clone()))
+            .collect();
+
+        GoldenOutput { buffers }
+    }
+
+    /// Returns the contents of the buffers in the same order as the output buffers of an
+    /// execution of the pipeline, so that they can be normalized and compared in the same way.
+    /// Buffers that the pipeline doesn't have are ignored.
+    pub fn execution(
+        &self,
+        pipeline_desc: &PipelineDescription,
+    ) -> Result<Vec<Vec<u8>>, GoldenError> {
+        storage_buffers(pipeline_desc)
+            .map(|(key, size)| {
+                let buffer = self
+                    .buffers
+                    .get(&key)
+                    .ok_or_else(|| GoldenError::MissingBuffer(key.clone()))?;
+
+                if buffer.len() < size {
+                    return Err(GoldenError::TooShort {
+                        key,
+                        expected: size,
+                        actual: buffer.len(),
+                    });
+                }
+
+                Ok(buffer.clone())
+            })
+            .collect()
+    }
+
+    /// Returns each value in the output buffers of an execution that differs from the golden
+    /// output under the policy, as with [`diff_normalized`]. The execution passes if there are
+    /// none.
+    pub fn diff(
+        &self,
+        buffers: &[Vec<u8>],
+        pipeline_desc: &PipelineDescription,
+        type_descs: &[Type],
+        policy: &ComparisonPolicy,
+    ) -> Result<Vec<ValueDiff>, GoldenError> {
+        let golden = normalize_execution(
+            &self.execution(pipeline_desc)?,
+            pipeline_desc,
+            type_descs,
+            policy,
+        );
+
+        let output = normalize_execution(buffers, pipeline_desc, type_descs, policy);
+
+        Ok(diff_normalized(
+            &output,
+            &golden,
+            pipeline_desc,
+            type_descs,
+            policy,
+        ))
+    }
+
+    /// Reads a golden output that was written with [`GoldenOutput::write`].
+    pub fn read(mut reader: impl Read) -> io::Result<GoldenOutput> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != *MAGIC {
+            return Err(invalid("not a golden output file"));
+        }
+
+        if read_u32(&mut reader)? != VERSION {
+            return Err(invalid("unsupported golden output file version"));
+        }
+
+        let mut buffers = BTreeMap::new();
+        for _ in 0..read_u32(&mut reader)? {
+            let group = read_u32(&mut reader)?;
+            let binding = read_u32(&mut reader)?;
+
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            let len = u64::from_le_bytes(len);
+
+            // The length isn't trusted to preallocate, since the file may be truncated
+            let mut buffer = vec![];
+            reader.by_ref().take(len).read_to_end(&mut buffer)?;
+            if buffer.len() as u64 != len {
+                return Err(invalid("truncated golden output file"));
+            }
+
+            buffers.insert(format!("{group}:{binding}"), buffer);
+        }
+
+        Ok(GoldenOutput { buffers })
+    }
+
+    /// Writes the golden output in a binary format, which starts with [`MAGIC`] and the format
+    /// version. Each buffer is written as its group and binding (little-endian `u32`s), its length
+    /// (a little-endian `u64`) and its contents.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.buffers.len() as u32).to_le_bytes())?;
+
+        for (key, buffer) in &self.buffers {
+            let (group, binding) = parse_key(key).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid buffer key `{key}`"),
+                )
+            })?;
+
+            writer.write_all(&group.to_le_bytes())?;
+            writer.write_all(&binding.to_le_bytes())?;
+            writer.write_all(&(buffer.len() as u64).to_le_bytes())?;
+            writer.write_all(buffer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the key and size of each storage buffer of the pipeline, in order.
+fn storage_buffers(
+    pipeline_desc: &PipelineDescription,
+) -> impl Iterator<Item = (String, usize)> + '_ {
+    pipeline_desc
+        .resources
+        .iter()
+        .filter(|it| it.kind == ResourceKind::StorageBuffer)
+        .map(|it| (format!("{}:{}", it.group, it.binding), it.size as usize))
+}
+
+fn parse_key(key: &str) -> Option<(u32, u32)> {
+    let (group, binding) = key.split_once(':')?;
+    Some((group.parse().ok()?, binding.parse().ok()?))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
@@ -1,10 +1,134 @@
-use common::Type;
+use std::fmt::{self, Display};
+use std::ops::Range;
+
+use common::{ScalarType, Type};
 use reflection_types::{PipelineDescription, ResourceKind};
+use serde::{Deserialize, Serialize};
+
+mod digest;
+mod golden;
+mod report;
+
+pub use digest::Digest;
+pub use golden::{GoldenError, GoldenOutput};
+pub use report::{ClassReport, ComparisonReport, DiffReport, REPORT_VERSION};
+
+/// Pattern that output buffers are filled with to detect missed writes, which is repeated every 4
+/// bytes. This is `0xdeadbeef` in little endian, which shaders are unlikely to write by chance.
+pub const CANARY: [u8; 4] = [0xef, 0xbe, 0xad, 0xde];
+
+/// Returns a buffer of the given size that is filled with the canary pattern.
+pub fn canary_buffer(size: usize) -> Vec<u8> {
+    CANARY.iter().copied().cycle().take(size).collect()
+}
+
+/// Returns the byte ranges of a buffer that still hold the canary pattern after execution.
+///
+/// Only bytes that hold data (rather than padding) are considered, and each 4 byte word is checked
+/// as a whole so that values which happen to contain some canary bytes aren't reported. Adjacent
+/// ranges are merged.
+pub fn surviving_canaries(buffer: &[u8], type_desc: &Type) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+
+    for (offset, size) in type_desc.ranges() {
+        for start in (offset..offset + size).step_by(CANARY.len()) {
+            let end = (start + CANARY.len()).min(offset + size);
+
+            if !(start..end).all(|i| buffer[i] == CANARY[i % CANARY.len()]) {
+                continue;
+            }
+
+            match ranges.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+    }
+
+    ranges
+}
+
+/// How the values in the outputs of two executions are compared. Integers are always compared
+/// exactly, while floats may be allowed to differ slightly, since many differences between
+/// implementations are only in the precision of floating-point operations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonPolicy {
+    /// Maximum number of units in the last place (ULPs) by which two floats may differ.
+    pub ulps: u32,
+    /// Maximum absolute difference between two floats, which is needed for results close to zero
+    /// since a ULP is tiny there.
+    pub absolute: f32,
+    /// Whether floats are canonicalized when outputs are normalized, so that every NaN has the
+    /// same bits and `-0.0` is `0.0`. Implementations are free to produce either, so they would
+    /// otherwise be mismatches even when the outputs are compared exactly.
+    pub canonicalize_floats: bool,
+}
+
+impl ComparisonPolicy {
+    /// Compares outputs bytewise.
+    pub const EXACT: ComparisonPolicy = ComparisonPolicy {
+        ulps: 0,
+        absolute: 0.0,
+        canonicalize_floats: false,
+    };
+
+    /// Returns whether normalized outputs are compared bytewise, which is the case if floats
+    /// aren't allowed to differ (even if they are canonicalized).
+    pub fn is_exact(&self) -> bool {
+        self.ulps == 0 && self.absolute == 0.0
+    }
+
+    /// Returns whether two floats are equal within the tolerances. NaNs match each other
+    /// regardless of their payload, and infinities only match themselves.
+    pub fn floats_match(&self, a: f32, b: f32) -> bool {
+        if a.to_bits() == b.to_bits() {
+            return true;
+        }
+
+        if self.is_exact() {
+            return false;
+        }
+
+        if a.is_nan() || b.is_nan() {
+            return a.is_nan() && b.is_nan();
+        }
+
+        if a.is_infinite() || b.is_infinite() {
+            return a == b;
+        }
 
+        (a - b).abs() <= self.absolute || ulp_distance(a, b) <= u64::from(self.ulps)
+    }
+}
+
+/// Returns the number of representable floats between `a` and `b`, counting `0.0` and `-0.0` as
+/// the same float.
+fn ulp_distance(a: f32, b: f32) -> u64 {
+    // Maps floats to integers in the same order, so that adjacent floats are adjacent integers
+    fn ordered(x: f32) -> i64 {
+        let magnitude = i64::from(x.to_bits() & 0x7fff_ffff);
+        if x.is_sign_negative() {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    ordered(a).abs_diff(ordered(b))
+}
+
+/// The bits of the NaN that NaNs are replaced with when floats are canonicalized, which is the
+/// quiet NaN without a payload.
+pub const CANONICAL_NAN: u32 = 0x7fc0_0000;
+
+/// Returns the data of each storage buffer of an execution without padding, so that executions
+/// can be compared without the contents of padding (which is undefined) getting in the way.
+/// Floats are canonicalized if the policy asks for it.
 pub fn normalize_execution(
     buffers: &[Vec<u8>],
     pipeline_desc: &PipelineDescription,
     type_descs: &[Type],
+    policy: &ComparisonPolicy,
 ) -> Vec<u8> {
     let mut canonical_data = Vec::new();
 
@@ -16,34 +140,931 @@ pub fn normalize_execution(
         .enumerate()
     {
         let buffer = &buffers[i];
+        let start = canonical_data.len();
 
         for (offset, size) in type_descs[j].ranges() {
             let range = offset..(offset + size);
             canonical_data.extend_from_slice(&buffer[range]);
         }
+
+        if policy.canonicalize_floats {
+            canonicalize_floats(&mut canonical_data[start..], &type_descs[j]);
+        }
     }
 
     canonical_data
 }
+
+/// Replaces each NaN in the data of a buffer (without padding) with [`CANONICAL_NAN`], and `-0.0`
+/// with `0.0`.
+fn canonicalize_floats(data: &mut [u8], type_desc: &Type) {
+    for (bytes, (_, scalar_type)) in data.chunks_exact_mut(4).zip(type_desc.scalars()) {
+        if scalar_type != ScalarType::F32 {
+            continue;
+        }
+
+        let value = f32::from_le_bytes((&*bytes).try_into().unwrap());
+        let canonical = if value.is_nan() {
+            CANONICAL_NAN
+        } else if value == 0.0 {
+            0
+        } else {
+            continue;
+        };
+
+        bytes.copy_from_slice(&canonical.to_le_bytes());
+    }
+}
+
+/// Returns whether two outputs that were normalized with [`normalize_execution`] match under the
+/// policy. Outputs that differ in length never match.
+pub fn normalized_match(
+    a: &[u8],
+    b: &[u8],
+    pipeline_desc: &PipelineDescription,
+    type_descs: &[Type],
+    policy: &ComparisonPolicy,
+) -> bool {
+    if policy.is_exact() || a.len() != b.len() {
+        return a == b;
+    }
+
+    // A normalized output is the data of each storage buffer without padding, which leaves only
+    // the 4 byte scalars in the order of their types
+    let scalar_types = pipeline_desc
+        .resources
+        .iter()
+        .enumerate()
+        .filter(|(_, it)| it.kind == ResourceKind::StorageBuffer)
+        .flat_map(|(j, _)| type_descs[j].scalars())
+        .map(|(_, scalar_type)| scalar_type);
+
+    a.chunks_exact(4)
+        .zip(b.chunks_exact(4))
+        .zip(scalar_types)
+        .all(|((a, b), scalar_type)| match scalar_type {
+            ScalarType::F32 => policy.floats_match(
+                f32::from_le_bytes(a.try_into().unwrap()),
+                f32::from_le_bytes(b.try_into().unwrap()),
+            ),
+            ScalarType::I32 | ScalarType::U32 => a == b,
+        })
+}
+
+/// Returns whether the outputs of all of the executions match under the policy.
 pub fn compare<'a>(
-    mut buffers: impl Iterator<Item = &'a Vec<Vec<u8>>>,
+    buffers: impl Iterator<Item = &'a Vec<Vec<u8>>>,
     pipeline_desc: &PipelineDescription,
     type_descs: &[Type],
+    policy: &ComparisonPolicy,
 ) -> bool {
-    let mut prev_normalized = match buffers.next() {
-        Some(b) => normalize_execution(b, pipeline_desc, type_descs),
+    let mut normalized =
+        buffers.map(|it| normalize_execution(it, pipeline_desc, type_descs, policy));
+
+    let first = match normalized.next() {
+        Some(first) => first,
         None => return true,
     };
 
-    for execution in buffers {
-        let current_normalized = normalize_execution(execution, pipeline_desc, type_descs);
+    // Tolerances aren't transitive, so every output is compared with the same one
+    normalized.all(|it| normalized_match(&first, &it, pipeline_desc, type_descs, policy))
+}
 
-        if current_normalized != prev_normalized {
-            return false;
+/// Outputs that match each other under a comparison policy, and the executions that produced them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputClass<K> {
+    /// The first output in the class, normalized with [`normalize_execution`]. The other outputs
+    /// match it, but may not match each other if floats are compared with tolerances.
+    pub output: Vec<u8>,
+    /// The executions in the class, in the order that they were first seen.
+    pub members: Vec<K>,
+}
+
+/// The outputs of a set of executions, partitioned into classes of matching outputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Consensus<K> {
+    /// Classes ordered by their number of members, largest first. Classes of the same size are in
+    /// the order that they were first seen.
+    pub classes: Vec<OutputClass<K>>,
+}
+
+impl<K: PartialEq> Consensus<K> {
+    /// Returns whether all of the executions produced matching outputs.
+    pub fn is_unanimous(&self) -> bool {
+        self.classes.len() <= 1
+    }
+
+    /// Returns the class with more members than any other, or `None` if there are no executions
+    /// or the largest classes are tied.
+    pub fn majority(&self) -> Option<&OutputClass<K>> {
+        match self.classes.as_slice() {
+            [first, second, ..] if first.members.len() == second.members.len() => None,
+            [first, ..] => Some(first),
+            [] => None,
+        }
+    }
+
+    /// Returns the executions that produced an output other than the majority output, without
+    /// duplicates. This includes executions that are also in the majority, since they produced
+    /// more than one output. If there is no majority, no execution stands out and none are
+    /// returned.
+    pub fn outliers(&self) -> Vec<&K> {
+        if self.majority().is_none() {
+            return vec![];
+        }
+
+        let mut outliers: Vec<&K> = vec![];
+        for class in &self.classes[1..] {
+            for member in &class.members {
+                if !outliers.contains(&member) {
+                    outliers.push(member);
+                }
+            }
+        }
+
+        outliers
+    }
+}
+
+/// Partitions the outputs of executions into classes of matching outputs under the policy.
+///
+/// Each output is added to the first class whose output it matches, so with tolerances the result
+/// depends on the order of the executions. Each key is added to a class at most once, so a key that
+/// is in more than one class produced outputs that didn't match (e.g. a nondeterministic config
+/// that was run more than once).
+pub fn group_executions<'a, K: PartialEq>(
+    executions: impl IntoIterator<Item = (K, &'a [Vec<u8>])>,
+    pipeline_desc: &PipelineDescription,
+    type_descs: &[Type],
+    policy: &ComparisonPolicy,
+) -> Consensus<K> {
+    group_normalized(
+        executions.into_iter().map(|(key, buffers)| {
+            (
+                key,
+                normalize_execution(buffers, pipeline_desc, type_descs, policy),
+            )
+        }),
+        pipeline_desc,
+        type_descs,
+        policy,
+    )
+}
+
+/// Like [`group_executions`], but for outputs that were already normalized with
+/// [`normalize_execution`].
+pub fn group_normalized<K: PartialEq>(
+    outputs: impl IntoIterator<Item = (K, impl AsRef<[u8]>)>,
+    pipeline_desc: &PipelineDescription,
+    type_descs: &[Type],
+    policy: &ComparisonPolicy,
+) -> Consensus<K> {
+    let mut classes: Vec<OutputClass<K>> = vec![];
+
+    for (key, output) in outputs {
+        let output = output.as_ref();
+        let class = classes
+            .iter_mut()
+            .find(|it| normalized_match(output, &it.output, pipeline_desc, type_descs, policy));
+
+        match class {
+            Some(class) => {
+                if !class.members.contains(&key) {
+                    class.members.push(key);
+                }
+            }
+            None => classes.push(OutputClass {
+                output: output.to_vec(),
+                members: vec![key],
+            }),
+        }
+    }
+
+    // The sort is stable, so ties keep the order that they were first seen in
+    classes.sort_by_key(|it| std::cmp::Reverse(it.members.len()));
+
+    Consensus { classes }
+}
+
+/// A value decoded from an output buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scalar {
+    I32(i32),
+    U32(u32),
+    F32(f32),
+}
+
+impl Scalar {
+    fn decode(bytes: &[u8], scalar_type: ScalarType) -> Scalar {
+        let bytes = bytes.try_into().expect("scalars are 4 bytes long");
+        match scalar_type {
+            ScalarType::I32 => Scalar::I32(i32::from_le_bytes(bytes)),
+            ScalarType::U32 => Scalar::U32(u32::from_le_bytes(bytes)),
+            ScalarType::F32 => Scalar::F32(f32::from_le_bytes(bytes)),
         }
+    }
+}
 
-        prev_normalized = current_normalized;
+impl Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scalar::I32(v) => write!(f, "{v}"),
+            Scalar::U32(v) => write!(f, "{v}"),
+            // Floats are printed with a decimal point, and with enough digits to tell them apart
+            Scalar::F32(v) => write!(f, "{v:?}"),
+        }
     }
+}
+
+/// A value that differs between two outputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValueDiff {
+    /// Group and binding of the buffer, e.g. `0:1`.
+    pub binding: String,
+    /// Path of the value from the buffer's variable, e.g. `output.values[3].y`.
+    pub path: String,
+    pub value: Scalar,
+    /// The value in the output that this one was compared with.
+    pub reference: Scalar,
+}
+
+/// Returns each value that differs between two outputs that were normalized with
+/// [`normalize_execution`], in the order of the buffers and their members. Floats that match
+/// under the policy aren't included.
+pub fn diff_normalized(
+    output: &[u8],
+    reference: &[u8],
+    pipeline_desc: &PipelineDescription,
+    type_descs: &[Type],
+    policy: &ComparisonPolicy,
+) -> Vec<ValueDiff> {
+    let mut diffs = vec![];
+    let mut offset = 0;
+
+    for (j, resource) in pipeline_desc
+        .resources
+        .iter()
+        .enumerate()
+        .filter(|(_, it)| it.kind == ResourceKind::StorageBuffer)
+    {
+        let type_desc = &type_descs[j];
+        let len = scalar_count(type_desc) * 4;
+        let (Some(output), Some(reference)) = (
+            output.get(offset..offset + len),
+            reference.get(offset..offset + len),
+        ) else {
+            break;
+        };
+
+        offset += len;
+
+        for (index, (a, b)) in output
+            .chunks_exact(4)
+            .zip(reference.chunks_exact(4))
+            .enumerate()
+        {
+            if a == b {
+                continue;
+            }
+
+            // Paths are only built for the values that differ, since buffers can be large
+            let mut path = resource.name.clone();
+            let scalar_type = scalar_path(type_desc, index, &mut path);
+
+            let value = Scalar::decode(a, scalar_type);
+            let reference = Scalar::decode(b, scalar_type);
 
-    true
+            if let (Scalar::F32(a), Scalar::F32(b)) = (value, reference) {
+                if policy.floats_match(a, b) {
+                    continue;
+                }
+            }
+
+            diffs.push(ValueDiff {
+                binding: format!("{}:{}", resource.group, resource.binding),
+                path,
+                value,
+                reference,
+            });
+        }
+    }
+
+    diffs
+}
+
+/// Returns the number of scalars in the type, including the components of vectors.
+fn scalar_count(type_desc: &Type) -> usize {
+    match type_desc {
+        Type::Scalar { .. } => 1,
+        Type::Vector { size, .. } => size.count() as usize,
+        Type::Matrix { columns, rows, .. } => (columns.count() * rows.count()) as usize,
+        Type::Array { size, element_type } => *size as usize * scalar_count(element_type),
+        Type::Struct { members } => members.iter().map(|it| scalar_count(&it.type_desc)).sum(),
+    }
+}
+
+/// Appends the path of the scalar with the given index (in the order of [`Type::scalars`]) to
+/// `path`, and returns its type.
+fn scalar_path(type_desc: &Type, index: usize, path: &mut String) -> ScalarType {
+    match type_desc {
+        Type::Scalar { scalar_type } => *scalar_type,
+        Type::Vector { scalar_type, .. } => {
+            path.push('.');
+            path.push(['x', 'y', 'z', 'w'][index]);
+            *scalar_type
+        }
+        Type::Matrix {
+            rows, scalar_type, ..
+        } => {
+            let rows = rows.count() as usize;
+            path.push_str(&format!("[{}].", index / rows));
+            path.push(['x', 'y', 'z', 'w'][index % rows]);
+            *scalar_type
+        }
+        Type::Array { element_type, .. } => {
+            let count = scalar_count(element_type);
+            path.push_str(&format!("[{}]", index / count));
+            scalar_path(element_type, index % count, path)
+        }
+        Type::Struct { members } => {
+            let mut index = index;
+            for member in members {
+                let count = scalar_count(&member.type_desc);
+                if index < count {
+                    path.push('.');
+                    path.push_str(&member.name);
+                    return scalar_path(&member.type_desc, index, path);
+                }
+                index -= count;
+            }
+            unreachable!("scalar index is out of bounds of the struct")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{StructMember, VectorSize};
+    use reflection_types::{Limits, PipelineResource};
+
+    use super::*;
+
+    fn member(name: &str, type_desc: Type, size: Option<u32>) -> StructMember {
+        StructMember {
+            name: name.to_owned(),
+            type_desc,
+            align: None,
+            size,
+        }
+    }
+
+    /// ```wgsl
+    /// struct Item {
+    ///     n: i32,
+    ///     v: vec3<f32>,
+    /// }
+    ///
+    /// struct Out {
+    ///     a: u32,
+    ///     @size(8) b: f32,
+    ///     items: array<Item, 2>,
+    /// }
+    /// ```
+    ///
+    /// Only 40 of the 80 bytes of `Out` hold data, since `b` is padded by its size attribute, the
+    /// array and `v` are aligned to 16 bytes and the array elements are padded to 32 bytes.
+    fn padded_type() -> Type {
+        let item = Type::Struct {
+            members: vec![
+                member(
+                    "n",
+                    Type::Scalar {
+                        scalar_type: ScalarType::I32,
+                    },
+                    None,
+                ),
+                member(
+                    "v",
+                    Type::Vector {
+                        size: VectorSize::N3,
+                        scalar_type: ScalarType::F32,
+                    },
+                    None,
+                ),
+            ],
+        };
+
+        Type::Struct {
+            members: vec![
+                member(
+                    "a",
+                    Type::Scalar {
+                        scalar_type: ScalarType::U32,
+                    },
+                    None,
+                ),
+                member(
+                    "b",
+                    Type::Scalar {
+                        scalar_type: ScalarType::F32,
+                    },
+                    Some(8),
+                ),
+                member(
+                    "items",
+                    Type::Array {
+                        size: 2,
+                        element_type: Box::new(item),
+                    },
+                    None,
+                ),
+            ],
+        }
+    }
+
+    /// ```wgsl
+    /// struct Inner {
+    ///     v: vec3<f32>,
+    /// }
+    ///
+    /// struct Elem {
+    ///     inner: Inner,
+    ///     m: mat3x3<f32>,
+    ///     s: u32,
+    /// }
+    ///
+    /// struct Out {
+    ///     elems: array<Elem, 2>,
+    ///     last: f32,
+    /// }
+    /// ```
+    ///
+    /// `Inner` has 4 bytes of tail padding, each column of `m` is padded to 16 bytes, and `Elem`
+    /// has 12 bytes of tail padding, which is also the padding between the array elements.
+    fn matrix_type() -> Type {
+        let vec3 = Type::Vector {
+            size: VectorSize::N3,
+            scalar_type: ScalarType::F32,
+        };
+
+        let inner = Type::Struct {
+            members: vec![member("v", vec3, None)],
+        };
+
+        let elem = Type::Struct {
+            members: vec![
+                member("inner", inner, None),
+                member(
+                    "m",
+                    Type::Matrix {
+                        columns: VectorSize::N3,
+                        rows: VectorSize::N3,
+                        scalar_type: ScalarType::F32,
+                    },
+                    None,
+                ),
+                member(
+                    "s",
+                    Type::Scalar {
+                        scalar_type: ScalarType::U32,
+                    },
+                    None,
+                ),
+            ],
+        };
+
+        Type::Struct {
+            members: vec![
+                member(
+                    "elems",
+                    Type::Array {
+                        size: 2,
+                        element_type: Box::new(elem),
+                    },
+                    None,
+                ),
+                member(
+                    "last",
+                    Type::Scalar {
+                        scalar_type: ScalarType::F32,
+                    },
+                    None,
+                ),
+            ],
+        }
+    }
+
+    fn pipeline(type_desc: &Type) -> PipelineDescription {
+        PipelineDescription {
+            resources: vec![PipelineResource {
+                name: "out".to_owned(),
+                kind: ResourceKind::StorageBuffer,
+                group: 0,
+                binding: 0,
+                init: None,
+                size: type_desc.buffer_size(),
+            }],
+            features: vec![],
+            limits: Limits::MIN,
+            dispatch: [1, 1, 1],
+        }
+    }
+
+    /// Returns the output of an execution that wrote `values` (as bits) to the scalars of the
+    /// type, in order, leaving `padding` in the rest of the buffer.
+    fn execution(type_desc: &Type, values: &[u32], padding: u8) -> Vec<Vec<u8>> {
+        let mut buffer = vec![padding; type_desc.buffer_size() as usize];
+        for ((offset, _), value) in type_desc.scalars().into_iter().zip(values) {
+            buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        vec![buffer]
+    }
+
+    const VALUES: [u32; 10] = [
+        1,
+        0x3f80_0000, // 1.0
+        u32::MAX,    // -1
+        0x4000_0000, // 2.0
+        0x4040_0000, // 3.0
+        0x4080_0000, // 4.0
+        7,
+        0,
+        0x8000_0000, // -0.0
+        0x7fc0_0000, // NaN
+    ];
+
+    fn group(
+        executions: &[(&'static str, Vec<Vec<u8>>)],
+        policy: ComparisonPolicy,
+    ) -> Consensus<&'static str> {
+        let type_descs = [padded_type()];
+        let pipeline_desc = pipeline(&type_descs[0]);
+
+        group_executions(
+            executions.iter().map(|(key, it)| (*key, it.as_slice())),
+            &pipeline_desc,
+            &type_descs,
+            &policy,
+        )
+    }
+
+    #[test]
+    fn padding_is_ignored() {
+        let type_desc = padded_type();
+        let consensus = group(
+            &[
+                ("a", execution(&type_desc, &VALUES, 0x00)),
+                ("b", execution(&type_desc, &VALUES, 0xff)),
+                ("c", execution(&type_desc, &VALUES, 0xab)),
+            ],
+            ComparisonPolicy::EXACT,
+        );
+
+        assert!(consensus.is_unanimous());
+        assert_eq!(consensus.classes[0].members, ["a", "b", "c"]);
+        assert_eq!(consensus.classes[0].output.len(), VALUES.len() * 4);
+        assert!(consensus.outliers().is_empty());
+    }
+
+    #[test]
+    fn matrix_and_nested_struct_layout() {
+        let type_desc = matrix_type();
+
+        assert_eq!(type_desc.buffer_size(), 176);
+        assert_eq!(
+            type_desc.ranges(),
+            [
+                (0, 12),
+                (16, 12),
+                (32, 12),
+                (48, 12),
+                (64, 4),
+                (80, 12),
+                (96, 12),
+                (112, 12),
+                (128, 12),
+                (144, 4),
+                (160, 4),
+            ]
+        );
+
+        let offsets = type_desc
+            .scalars()
+            .into_iter()
+            .map(|(offset, _)| offset)
+            .collect::<Vec<_>>();
+
+        assert_eq!(offsets.len(), 27);
+        assert_eq!(offsets[3..12], [16, 20, 24, 32, 36, 40, 48, 52, 56]);
+    }
+
+    #[test]
+    fn matrix_padding_is_ignored() {
+        let type_desc = matrix_type();
+        let type_descs = [matrix_type()];
+        let pipeline_desc = pipeline(&type_desc);
+
+        let values = (0..27).collect::<Vec<u32>>();
+        let mut wrong = values.clone();
+        wrong[23] = 0;
+
+        let executions = [
+            ("a", execution(&type_desc, &values, 0x00)),
+            ("b", execution(&type_desc, &values, 0xff)),
+            ("c", execution(&type_desc, &wrong, 0x00)),
+        ];
+
+        let consensus = group_executions(
+            executions.iter().map(|(key, it)| (*key, it.as_slice())),
+            &pipeline_desc,
+            &type_descs,
+            &ComparisonPolicy::EXACT,
+        );
+
+        assert_eq!(consensus.majority().unwrap().members, ["a", "b"]);
+        assert_eq!(consensus.outliers(), [&"c"]);
+
+        let diffs = diff_normalized(
+            &consensus.classes[1].output,
+            &consensus.classes[0].output,
+            &pipeline_desc,
+            &type_descs,
+            &ComparisonPolicy::EXACT,
+        );
+
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "out.elems[1].m[2].y");
+    }
+
+    #[test]
+    fn majority_and_outliers() {
+        let type_desc = padded_type();
+
+        // The last component of the last vector is the last scalar before the padding at the end
+        // of the buffer
+        let mut wrong = VALUES;
+        wrong[9] = 0x3f80_0000;
+
+        let consensus = group(
+            &[
+                ("a", execution(&type_desc, &wrong, 0x00)),
+                ("b", execution(&type_desc, &VALUES, 0x00)),
+                ("c", execution(&type_desc, &VALUES, 0xff)),
+                ("d", execution(&type_desc, &wrong, 0xff)),
+                ("e", execution(&type_desc, &VALUES, 0x12)),
+            ],
+            ComparisonPolicy::EXACT,
+        );
+
+        assert_eq!(consensus.classes.len(), 2);
+        assert_eq!(consensus.majority().unwrap().members, ["b", "c", "e"]);
+        assert_eq!(consensus.outliers(), [&"a", &"d"]);
+    }
+
+    #[test]
+    fn tie_has_no_majority() {
+        let type_desc = padded_type();
+
+        let mut wrong = VALUES;
+        wrong[0] = 2;
+
+        let consensus = group(
+            &[
+                ("a", execution(&type_desc, &wrong, 0x00)),
+                ("b", execution(&type_desc, &VALUES, 0x00)),
+            ],
+            ComparisonPolicy::EXACT,
+        );
+
+        assert!(!consensus.is_unanimous());
+        assert_eq!(consensus.majority(), None);
+        assert!(consensus.outliers().is_empty());
+
+        // Ties are in the order that they were first seen
+        assert_eq!(consensus.classes[0].members, ["a"]);
+        assert_eq!(consensus.classes[1].members, ["b"]);
+    }
+
+    #[test]
+    fn nondeterministic_key_is_an_outlier_once() {
+        let type_desc = padded_type();
+
+        let mut first = VALUES;
+        first[6] = 8;
+        let mut second = VALUES;
+        second[6] = 9;
+
+        let consensus = group(
+            &[
+                ("a", execution(&type_desc, &VALUES, 0x00)),
+                ("a", execution(&type_desc, &VALUES, 0xff)),
+                ("b", execution(&type_desc, &VALUES, 0x00)),
+                ("c", execution(&type_desc, &first, 0x00)),
+                ("c", execution(&type_desc, &second, 0x00)),
+                ("c", execution(&type_desc, &VALUES, 0x00)),
+            ],
+            ComparisonPolicy::EXACT,
+        );
+
+        assert_eq!(consensus.classes.len(), 3);
+        assert_eq!(consensus.majority().unwrap().members, ["a", "b", "c"]);
+        assert_eq!(consensus.outliers(), [&"c"]);
+    }
+
+    #[test]
+    fn floats_are_grouped_with_tolerance() {
+        let type_desc = padded_type();
+
+        // 1 ULP away from 2.0 in `items[0].v.x`
+        let mut close = VALUES;
+        close[3] = 0x4000_0001;
+
+        // Integers are compared exactly, even though this is 1 ULP away as a float
+        let mut integer = VALUES;
+        integer[6] = 8;
+
+        let executions = [
+            ("a", execution(&type_desc, &VALUES, 0x00)),
+            ("b", execution(&type_desc, &close, 0xff)),
+            ("c", execution(&type_desc, &integer, 0x00)),
+        ];
+
+        let exact = group(&executions, ComparisonPolicy::EXACT);
+        assert_eq!(exact.classes.len(), 3);
+        assert_eq!(exact.majority(), None);
+
+        let policy = ComparisonPolicy {
+            ulps: 1,
+            ..ComparisonPolicy::EXACT
+        };
+
+        let tolerant = group(&executions, policy);
+        assert_eq!(tolerant.classes.len(), 2);
+        assert_eq!(tolerant.majority().unwrap().members, ["a", "b"]);
+        assert_eq!(tolerant.outliers(), [&"c"]);
+    }
+
+    #[test]
+    fn floats_are_canonicalized() {
+        let type_desc = padded_type();
+        let type_descs = [padded_type()];
+        let pipeline_desc = pipeline(&type_desc);
+
+        // A signalling NaN with a payload, and 0.0 in place of -0.0
+        let mut other = VALUES;
+        other[8] = 0;
+        other[9] = 0x7f80_0001;
+
+        let executions = [
+            ("a", execution(&type_desc, &VALUES, 0x00)),
+            ("b", execution(&type_desc, &other, 0xff)),
+        ];
+
+        let policy = ComparisonPolicy {
+            canonicalize_floats: true,
+            ..ComparisonPolicy::EXACT
+        };
+
+        assert_eq!(group(&executions, ComparisonPolicy::EXACT).classes.len(), 2);
+        assert!(group(&executions, policy).is_unanimous());
+
+        // Integers with the bits of a NaN or -0.0 are left as they are
+        let mut integers = VALUES;
+        integers[0] = 0x8000_0000;
+        integers[2] = 0x7f80_0001;
+
+        let normalized = normalize_execution(
+            &execution(&type_desc, &integers, 0x00),
+            &pipeline_desc,
+            &type_descs,
+            &policy,
+        );
+
+        let words = normalized
+            .chunks_exact(4)
+            .map(|it| u32::from_le_bytes(it.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(words[0], 0x8000_0000);
+        assert_eq!(words[2], 0x7f80_0001);
+        assert_eq!(words[8], 0);
+        assert_eq!(words[9], CANONICAL_NAN);
+    }
+
+    #[test]
+    fn golden_output_round_trip() {
+        let type_desc = padded_type();
+        let type_descs = [padded_type()];
+        let pipeline_desc = pipeline(&type_desc);
+
+        let golden =
+            GoldenOutput::from_execution(&execution(&type_desc, &VALUES, 0x00), &pipeline_desc);
+
+        let mut file = vec![];
+        golden.write(&mut file).unwrap();
+        assert_eq!(GoldenOutput::read(file.as_slice()).unwrap(), golden);
+        assert!(GoldenOutput::read(&file[..file.len() - 1]).is_err());
+
+        // Padding doesn't need to match
+        let same = execution(&type_desc, &VALUES, 0xff);
+        let diffs = golden.diff(&same, &pipeline_desc, &type_descs, &ComparisonPolicy::EXACT);
+        assert!(diffs.unwrap().is_empty());
+
+        let mut wrong = VALUES;
+        wrong[2] = 0;
+        let wrong = execution(&type_desc, &wrong, 0x00);
+        let diffs = golden.diff(
+            &wrong,
+            &pipeline_desc,
+            &type_descs,
+            &ComparisonPolicy::EXACT,
+        );
+        assert_eq!(diffs.unwrap()[0].path, "out.items[0].n");
+
+        let mut short = golden.clone();
+        short.buffers.get_mut("0:0").unwrap().truncate(8);
+        assert!(matches!(
+            short.execution(&pipeline_desc),
+            Err(GoldenError::TooShort { .. })
+        ));
+    }
+
+    #[test]
+    fn outliers_are_diffed_by_path() {
+        let type_desc = padded_type();
+        let type_descs = [padded_type()];
+        let pipeline_desc = pipeline(&type_desc);
+
+        let mut wrong = VALUES;
+        wrong[1] = 0x4000_0000;
+        wrong[7] = 5;
+
+        let consensus = group(
+            &[
+                ("a", execution(&type_desc, &VALUES, 0x00)),
+                ("b", execution(&type_desc, &wrong, 0xff)),
+                ("c", execution(&type_desc, &VALUES, 0xff)),
+            ],
+            ComparisonPolicy::EXACT,
+        );
+
+        let diffs = diff_normalized(
+            &consensus.classes[1].output,
+            &consensus.classes[0].output,
+            &pipeline_desc,
+            &type_descs,
+            &ComparisonPolicy::EXACT,
+        );
+
+        let paths = diffs.iter().map(|it| it.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(paths, ["out.b", "out.items[1].v.x"]);
+        assert_eq!(diffs[0].value, Scalar::F32(2.0));
+        assert_eq!(diffs[1].value, Scalar::F32(f32::from_bits(5)));
+    }
+
+    #[test]
+    fn comparison_report_round_trip() {
+        let type_desc = padded_type();
+        let type_descs = [padded_type()];
+        let pipeline_desc = pipeline(&type_desc);
+
+        let mut wrong = VALUES;
+        wrong[0] = 7;
+        wrong[2] = 8;
+
+        let consensus = group(
+            &[
+                ("a", execution(&type_desc, &VALUES, 0x00)),
+                ("b", execution(&type_desc, &wrong, 0x00)),
+                ("c", execution(&type_desc, &VALUES, 0xff)),
+            ],
+            ComparisonPolicy::EXACT,
+        );
+
+        let report = ComparisonReport::new(
+            &consensus,
+            &pipeline_desc,
+            &type_descs,
+            &ComparisonPolicy::EXACT,
+            1,
+        );
+
+        assert_eq!(report.majority, Some(0));
+        assert_eq!(report.classes[0].configs, ["a", "c"]);
+        assert_eq!(report.classes[0].diff_count, 0);
+        assert_eq!(report.classes[1].configs, ["b"]);
+        assert_eq!(report.classes[1].diff_count, 2);
+        assert_eq!(report.classes[1].diffs[0].path, "out.a");
+        assert_eq!(report.classes[1].diffs.len(), 1);
+        assert_ne!(report.classes[0].digest, report.classes[1].digest);
+
+        let mut file = vec![];
+        report.write(&mut file).unwrap();
+        assert_eq!(ComparisonReport::read(file.as_slice()).unwrap(), report);
+
+        let mut future = serde_json::to_value(&report).unwrap();
+        future["version"] = (REPORT_VERSION + 1).into();
+        assert!(ComparisonReport::read(future.to_string().as_bytes()).is_err());
+    }
 }
@@ -0,0 +1,129 @@
+//! Reports of the outcome of comparing the outputs of executions, as versioned JSON so that they
+//! can be consumed by triage tools and dashboards that don't link against the harness.
+
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+
+use common::Type;
+use reflection_types::PipelineDescription;
+use serde::{Deserialize, Serialize};
+
+use crate::{diff_normalized, ComparisonPolicy, Consensus, Digest, ValueDiff};
+
+/// Version of the report format, which is incremented whenever a field is removed or its meaning
+/// changes. Fields may be added without changing the version.
+pub const REPORT_VERSION: u32 = 1;
+
+/// The outcome of comparing the outputs of a set of executions.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonReport {
+    pub version: u32,
+    pub policy: ComparisonPolicy,
+    /// Index of the class with more members than any other, or `None` if the largest classes are
+    /// tied.
+    pub majority: Option<usize>,
+    /// Classes of matching outputs, in the same order as [`Consensus::classes`].
+    pub classes: Vec<ClassReport>,
+}
+
+/// A class of matching outputs. A config that produced different outputs on different runs is in
+/// more than one class.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClassReport {
+    pub configs: Vec<String>,
+    /// Hex-encoded [`Digest`] of the normalized output of the class.
+    pub digest: String,
+    /// Number of values in which the output differs from the output of the first class, which is
+    /// zero for the first class.
+    pub diff_count: usize,
+    /// The differing values, of which there may be fewer than `diff_count`.
+    pub diffs: Vec<DiffReport>,
+}
+
+/// A value that differs from the output of the first class. The values are formatted as strings,
+/// since JSON can't represent every float (e.g. NaN).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub binding: String,
+    pub path: String,
+    pub value: String,
+    pub reference: String,
+}
+
+impl From<&ValueDiff> for DiffReport {
+    fn from(diff: &ValueDiff) -> Self {
+        DiffReport {
+            binding: diff.binding.clone(),
+            path: diff.path.clone(),
+            value: diff.value.to_string(),
+            reference: diff.reference.to_string(),
+        }
+    }
+}
+
+impl ComparisonReport {
+    /// Creates a report from the outputs of executions that were grouped under the policy. Each
+    /// class is diffed against the first one, keeping at most `max_diffs` values per class.
+    pub fn new<K: Display + PartialEq>(
+        consensus: &Consensus<K>,
+        pipeline_desc: &PipelineDescription,
+        type_descs: &[Type],
+        policy: &ComparisonPolicy,
+        max_diffs: usize,
+    ) -> ComparisonReport {
+        let reference = consensus.classes.first().map(|it| it.output.as_slice());
+        let classes = consensus
+            .classes
+            .iter()
+            .enumerate()
+            .map(|(i, class)| {
+                let diffs = reference
+                    .filter(|_| i > 0)
+                    .map(|reference| {
+                        diff_normalized(&class.output, reference, pipeline_desc, type_descs, policy)
+                    })
+                    .unwrap_or_default();
+
+                ClassReport {
+                    configs: class.members.iter().map(|it| it.to_string()).collect(),
+                    digest: Digest::of_normalized(&class.output).to_string(),
+                    diff_count: diffs.len(),
+                    diffs: diffs.iter().take(max_diffs).map(DiffReport::from).collect(),
+                }
+            })
+            .collect();
+
+        ComparisonReport {
+            version: REPORT_VERSION,
+            policy: *policy,
+            majority: consensus.majority().map(|_| 0),
+            classes,
+        }
+    }
+
+    /// Returns whether all of the executions produced matching outputs.
+    pub fn is_unanimous(&self) -> bool {
+        self.classes.len() <= 1
+    }
+
+    /// Reads a report that was written with [`ComparisonReport::write`], failing if it has a
+    /// different version.
+    pub fn read(reader: impl Read) -> io::Result<ComparisonReport> {
+        let value: serde_json::Value = serde_json::from_reader(reader)?;
+
+        // The version is checked first, since the rest of a report in another version may not
+        // deserialize at all
+        if value.get("version").and_then(|it| it.as_u64()) != Some(REPORT_VERSION.into()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unsupported comparison report version",
+            ));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn write(&self, writer: impl Write) -> io::Result<()> {
+        Ok(serde_json::to_writer(writer, self)?)
+    }
+}
@@ -1,21 +1,46 @@
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ScalarType {
     I32,
     U32,
     F32,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum VectorSize {
     N2,
     N3,
     N4,
 }
 
+impl VectorSize {
+    /// Returns the number of components of a vector of this size.
+    pub fn count(self) -> u32 {
+        match self {
+            VectorSize::N2 => 2,
+            VectorSize::N3 => 3,
+            VectorSize::N4 => 4,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct StructMember {
     pub name: String,
     pub type_desc: Type,
+    /// Alignment specified with an explicit `@align` attribute.
+    pub align: Option<u32>,
+    /// Size specified with an explicit `@size` attribute.
+    pub size: Option<u32>,
+}
+
+impl StructMember {
+    pub fn alignment(&self) -> u32 {
+        self.align.unwrap_or_else(|| self.type_desc.alignment())
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size.unwrap_or_else(|| self.type_desc.size())
+    }
 }
 
 #[derive(Debug)]
@@ -27,6 +52,13 @@ pub enum Type {
         size: VectorSize,
         scalar_type: ScalarType,
     },
+    /// A matrix, which is laid out as an array of its column vectors. The columns of a matrix with
+    /// 3 rows are padded to 16 bytes, like an array of `vec3`s.
+    Matrix {
+        columns: VectorSize,
+        rows: VectorSize,
+        scalar_type: ScalarType,
+    },
     Array {
         size: u32,
         element_type: Box<Type>,
@@ -53,6 +85,7 @@ impl Type {
                 VectorSize::N3 => 12,
                 VectorSize::N4 => 16,
             },
+            Type::Matrix { columns, .. } => columns.count() * self.column_stride(),
             Type::Array { size, element_type } => {
                 size * aligned(element_type.size(), element_type.alignment())
             }
@@ -61,10 +94,9 @@ impl Type {
                 let mut alignment = 0;
 
                 for member in members {
-                    let member_alignment = member.type_desc.alignment();
-                    let member_size = member.type_desc.size();
-                    alignment = u32::max(alignment, member.type_desc.alignment());
-                    size = aligned(size, member_alignment) + member_size;
+                    let member_alignment = member.alignment();
+                    alignment = u32::max(alignment, member_alignment);
+                    size = aligned(size, member_alignment) + member.size();
                 }
 
                 aligned(size, alignment)
@@ -80,15 +112,34 @@ impl Type {
                 VectorSize::N3 => 16,
                 VectorSize::N4 => 16,
             },
+            Type::Matrix { rows, .. } => match rows {
+                VectorSize::N2 => 8,
+                VectorSize::N3 => 16,
+                VectorSize::N4 => 16,
+            },
             Type::Array { element_type, .. } => element_type.alignment(),
             Type::Struct { members } => members
                 .iter()
-                .map(|it| it.type_desc.alignment())
+                .map(|it| it.alignment())
                 .max()
                 .expect("struct must have at least one member"),
         }
     }
 
+    /// Returns the number of bytes between the columns of a matrix, which is the size of a column
+    /// rounded up to its alignment.
+    fn column_stride(&self) -> u32 {
+        match self {
+            Type::Matrix { rows, .. } => aligned(rows.count() * 4, self.alignment()),
+            _ => unreachable!("only matrices have columns"),
+        }
+    }
+
+    /// Returns the offset and size of each range of bytes in the type that holds data, in order.
+    ///
+    /// Padding is skipped wherever the layout rules add it: between struct members and at the end
+    /// of structs (including nested structs), between the elements of arrays whose stride is
+    /// larger than their element, and between the columns of matrices with 3 rows.
     pub fn ranges(&self) -> Vec<(usize, usize)> {
         let mut ranges = vec![];
 
@@ -96,6 +147,12 @@ impl Type {
             match type_desc {
                 Type::Scalar { .. } => acc.push((offset as _, type_desc.size() as _)),
                 Type::Vector { .. } => acc.push((offset as _, type_desc.size() as _)),
+                Type::Matrix { columns, rows, .. } => {
+                    for column in 0..columns.count() {
+                        let offset = offset + column * type_desc.column_stride();
+                        acc.push((offset as _, (rows.count() * 4) as _));
+                    }
+                }
                 Type::Array { size, element_type } => {
                     let element_size = element_type.size();
                     let alignment = element_type.alignment();
@@ -105,12 +162,12 @@ impl Type {
                     }
                 }
                 Type::Struct { members } => {
+                    // Only the bytes occupied by the member's type are real data. Any padding
+                    // added by an `@align` or `@size` attribute is skipped.
                     for member in members {
-                        let alignment = member.type_desc.alignment();
-                        offset = aligned(offset, alignment);
+                        offset = aligned(offset, member.alignment());
                         collect_ranges(acc, offset, &member.type_desc);
-                        let size = member.type_desc.size();
-                        offset += size;
+                        offset += member.size();
                     }
                 }
             }
@@ -120,6 +177,54 @@ impl Type {
 
         ranges
     }
+
+    /// Returns the offset and type of each scalar in the type, including the components of
+    /// vectors, in the same order as [`Type::ranges`]. Every scalar is 4 bytes long.
+    pub fn scalars(&self) -> Vec<(usize, ScalarType)> {
+        let mut scalars = vec![];
+
+        fn collect_scalars(acc: &mut Vec<(usize, ScalarType)>, mut offset: u32, type_desc: &Type) {
+            match type_desc {
+                Type::Scalar { scalar_type } => acc.push((offset as _, *scalar_type)),
+                Type::Vector { size, scalar_type } => {
+                    for i in 0..size.count() {
+                        acc.push(((offset + i * 4) as _, *scalar_type));
+                    }
+                }
+                Type::Matrix {
+                    columns,
+                    rows,
+                    scalar_type,
+                } => {
+                    for column in 0..columns.count() {
+                        let offset = offset + column * type_desc.column_stride();
+                        for i in 0..rows.count() {
+                            acc.push(((offset + i * 4) as _, *scalar_type));
+                        }
+                    }
+                }
+                Type::Array { size, element_type } => {
+                    let element_size = element_type.size();
+                    let alignment = element_type.alignment();
+                    for _ in 0..*size {
+                        collect_scalars(acc, offset, element_type);
+                        offset = aligned(offset + element_size, alignment);
+                    }
+                }
+                Type::Struct { members } => {
+                    for member in members {
+                        offset = aligned(offset, member.alignment());
+                        collect_scalars(acc, offset, &member.type_desc);
+                        offset += member.size();
+                    }
+                }
+            }
+        }
+
+        collect_scalars(&mut scalars, 0, self);
+
+        scalars
+    }
 }
 
 impl TryFrom<&ast::ScalarType> for ScalarType {
@@ -131,6 +236,7 @@ impl TryFrom<&ast::ScalarType> for ScalarType {
             ast::ScalarType::I32 => Ok(ScalarType::I32),
             ast::ScalarType::U32 => Ok(ScalarType::U32),
             ast::ScalarType::F32 => Ok(ScalarType::F32),
+            ast::ScalarType::F16 => Err("f16 is not supported in buffers"),
         }
     }
 }
@@ -153,7 +259,11 @@ impl TryFrom<&ast::DataType> for Type {
                 scalar_type: scalar.try_into()?,
             }),
             ast::DataType::Array(inner, size) => Ok(Type::Array {
-                size: size.ok_or("runtime sized arrays are not supported")?,
+                size: size
+                    .as_ref()
+                    .ok_or("runtime sized arrays are not supported")?
+                    .value()
+                    .ok_or("arrays of unknown size are not supported")?,
                 element_type: Box::new(inner.as_ref().try_into()?),
             }),
             ast::DataType::Struct(decl) => {
@@ -162,9 +272,21 @@ impl TryFrom<&ast::DataType> for Type {
                 for member in &decl.members {
                     let type_desc = Type::try_from(&member.data_type)?;
 
+                    let mut align = None;
+                    let mut size = None;
+
+                    for attr in &member.attrs {
+                        match attr {
+                            ast::StructMemberAttr::Align(n) => align = Some(u32::from(*n)),
+                            ast::StructMemberAttr::Size(n) => size = Some(*n),
+                        }
+                    }
+
                     members.push(StructMember {
                         name: member.name.clone(),
                         type_desc,
+                        align,
+                        size,
                     });
                 }
 
@@ -172,6 +294,16 @@ impl TryFrom<&ast::DataType> for Type {
             }
             ast::DataType::Ptr(_) => Err("pointers are not storable"),
             ast::DataType::Ref(_) => Err("references are not storable"),
+            ast::DataType::Texture(_, _) | ast::DataType::DepthTexture(_) => {
+                Err("textures are not storable")
+            }
+            ast::DataType::Sampler | ast::DataType::SamplerComparison => {
+                Err("samplers are not storable")
+            }
+            // Atomics have the same layout as the underlying scalar.
+            ast::DataType::Atomic(scalar) => Ok(Type::Scalar {
+                scalar_type: scalar.try_into()?,
+            }),
         }
     }
 }
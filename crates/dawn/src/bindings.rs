@@ -5,6 +5,7 @@ use std::ffi::{c_void, CString};
 use std::mem::zeroed;
 use std::os::raw::c_char;
 use std::ptr::{null, null_mut};
+use std::sync::{Arc, Mutex};
 
 fn make_string_view(ptr: *const c_char) -> WGPUStringView {
     if ptr.is_null() {
@@ -24,7 +25,13 @@ pub struct Instance(*mut c_void);
 pub struct AdapterInfo {
     pub name: String,
     pub backend: WGPUBackendType,
+    pub vendor_id: u32,
     pub device_id: u32,
+    /// Description of the driver, which usually includes its version.
+    pub driver: String,
+    pub features: Vec<WGPUFeatureName>,
+    /// Whether the adapter is a software rasterizer, e.g. SwiftShader.
+    pub software: bool,
 }
 
 impl Instance {
@@ -38,18 +45,33 @@ impl Instance {
         }
     }
 
+    pub fn has_wgsl_language_feature(&self, feature: WGPUWGSLLanguageFeatureName) -> bool {
+        unsafe { dawn::instance_has_wgsl_language_feature(self.0, feature) }
+    }
+
     pub fn enumerate_adapters(&self) -> Vec<AdapterInfo> {
         #[allow(non_upper_case_globals)]
-        unsafe extern "C" fn cb(info: *const WGPUAdapterInfo, userdata: *mut c_void) {
-            let info_ref = &*info;
-            let name_str = if !info_ref.device.data.is_null() {
-                let slice = std::slice::from_raw_parts(
-                    info_ref.device.data as *const u8,
-                    info_ref.device.length,
-                );
-                String::from_utf8_lossy(slice).to_string()
+        unsafe extern "C" fn cb(
+            info: *const WGPUAdapterInfo,
+            features: *const WGPUSupportedFeatures,
+            userdata: *mut c_void,
+        ) {
+            let string = |view: WGPUStringView| {
+                if view.data.is_null() {
+                    return None;
+                }
+
+                let slice = std::slice::from_raw_parts(view.data as *const u8, view.length);
+                Some(String::from_utf8_lossy(slice).to_string())
+            };
+
+            let name_str = string((*info).device).unwrap_or_else(|| "Unknown Adapter".to_owned());
+            let driver = string((*info).description).unwrap_or_default();
+
+            let features = if (*features).features.is_null() {
+                vec![]
             } else {
-                String::from("Unknown Adapter")
+                std::slice::from_raw_parts((*features).features, (*features).featureCount).to_vec()
             };
 
             (userdata as *mut Vec<AdapterInfo>)
@@ -58,7 +80,11 @@ impl Instance {
                 .push(AdapterInfo {
                     name: name_str,
                     backend: (*info).backendType,
+                    vendor_id: (*info).vendorID,
                     device_id: (*info).deviceID,
+                    driver,
+                    features,
+                    software: (*info).adapterType == WGPUAdapterType_WGPUAdapterType_CPU,
                 });
         }
 
@@ -71,16 +97,72 @@ impl Instance {
         adapters
     }
 
-    pub fn create_device(&self, backend: WGPUBackendType, device_id: u32) -> Option<Device> {
+    pub fn create_device(
+        &self,
+        backend: WGPUBackendType,
+        device_id: u32,
+        features: &[WGPUFeatureName],
+        enabled_toggles: &[&str],
+        disabled_toggles: &[&str],
+    ) -> Option<Device> {
         let callback: WGPUUncapturedErrorCallback = Some(default_error_callback);
-        let handle =
-            unsafe { dawn::create_device(self.0, backend, device_id, callback, null_mut()) };
+        let errors = Arc::new(Mutex::new(vec![]));
+        let lost = Arc::new(Mutex::new(None));
+
+        // The callbacks may be called after the device is dropped (e.g. when the instance is
+        // deleted), so they hold their own references to the errors and lost message, which are
+        // never released.
+        let error_userdata = Arc::into_raw(errors.clone()) as *mut c_void;
+        let lost_callback: WGPUDeviceLostCallback = Some(device_lost_callback);
+        let lost_userdata = Arc::into_raw(lost.clone()) as *mut c_void;
+
+        // The strings must outlive the pointers to them, which are only used while creating the
+        // device
+        let enabled_toggles = enabled_toggles
+            .iter()
+            .map(|it| CString::new(*it).unwrap())
+            .collect::<Vec<_>>();
+        let disabled_toggles = disabled_toggles
+            .iter()
+            .map(|it| CString::new(*it).unwrap())
+            .collect::<Vec<_>>();
+
+        let enabled_toggle_ptrs = enabled_toggles
+            .iter()
+            .map(|it| it.as_ptr())
+            .collect::<Vec<_>>();
+        let disabled_toggle_ptrs = disabled_toggles
+            .iter()
+            .map(|it| it.as_ptr())
+            .collect::<Vec<_>>();
+
+        let handle = unsafe {
+            dawn::create_device(
+                self.0,
+                backend,
+                device_id,
+                features.as_ptr(),
+                features.len(),
+                enabled_toggle_ptrs.as_ptr(),
+                enabled_toggle_ptrs.len(),
+                disabled_toggle_ptrs.as_ptr(),
+                disabled_toggle_ptrs.len(),
+                callback,
+                error_userdata,
+                lost_callback,
+                lost_userdata,
+            )
+        };
 
         if handle.is_null() {
             panic!("failed to create dawn device");
         }
 
-        let device = Device { handle };
+        let device = Device {
+            handle,
+            errors,
+            lost,
+        };
 
         Some(device)
     }
@@ -100,11 +182,45 @@ impl Drop for Instance {
     }
 }
 
+/// The kind of operation which caused an error reported by the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Creating the shader module or compute pipeline failed.
+    Compilation,
+    /// Any other operation failed validation.
+    Validation,
+    /// The device ran out of memory or hit an internal error.
+    Runtime,
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
 pub struct Device {
     handle: *mut crate::webgpu::WGPUDeviceImpl,
+    errors: Arc<Mutex<Vec<DeviceError>>>,
+    lost: Arc<Mutex<Option<String>>>,
 }
 
 impl Device {
+    /// Returns the first error reported by the device, if any. Later errors are usually caused by
+    /// the first, e.g. by using an invalid pipeline.
+    ///
+    /// Events must be processed by the instance for errors to be reported.
+    pub fn first_error(&self) -> Option<DeviceError> {
+        self.errors.lock().unwrap().first().cloned()
+    }
+
+    /// Returns the message describing why the device was lost, if it was lost unexpectedly.
+    ///
+    /// Events must be processed by the instance for device loss to be detected.
+    pub fn lost_message(&self) -> Option<String> {
+        self.lost.lock().unwrap().clone()
+    }
+
     pub fn create_queue(&self) -> DeviceQueue {
         DeviceQueue {
             handle: unsafe { wgpuDeviceGetQueue(self.handle).assert_not_null() },
@@ -113,7 +229,7 @@ impl Device {
 
     pub fn create_shader_module(&self, source: &str) -> ShaderModule {
         let source = CString::new(source).unwrap();
-        ErrorScope::new(self, "shader module creation failed").execute(|| unsafe {
+        ErrorScope::compilation(self, "shader module creation failed").execute(|| unsafe {
             let wgsl_descriptor = WGPUShaderSourceWGSL {
                 chain: WGPUChainedStruct {
                     sType: WGPUSType_WGPUSType_ShaderSourceWGSL,
@@ -138,7 +254,7 @@ impl Device {
         shader_module: &ShaderModule,
         entrypoint: &str,
     ) -> ComputePipeline {
-        ErrorScope::new(self, "compute pipeline creation failed").execute(|| unsafe {
+        ErrorScope::compilation(self, "compute pipeline creation failed").execute(|| unsafe {
             let entrypoint_c = CString::new(entrypoint).unwrap();
             let label_c = CString::new(format!("Pipeline: {}", entrypoint)).unwrap();
             ComputePipeline {
@@ -167,7 +283,7 @@ impl Device {
         size: usize,
         usage: DeviceBufferUsage,
     ) -> DeviceBuffer {
-        ErrorScope::new(self, "buffer creation failed").execute(|| unsafe {
+        ErrorScope::validation(self, "buffer creation failed").execute(|| unsafe {
             DeviceBuffer {
                 handle: wgpuDeviceCreateBuffer(
                     self.handle,
@@ -184,12 +300,67 @@ impl Device {
         })
     }
 
+    pub fn create_texture(
+        &self,
+        dimension: WGPUTextureDimension,
+        size: [u32; 3],
+        mip_level_count: u32,
+        format: WGPUTextureFormat,
+    ) -> Texture {
+        ErrorScope::validation(self, "texture creation failed").execute(|| unsafe {
+            let [width, height, depth_or_array_layers] = size;
+            Texture {
+                handle: wgpuDeviceCreateTexture(
+                    self.handle,
+                    &WGPUTextureDescriptor {
+                        usage: WGPUTextureUsage_TextureBinding | WGPUTextureUsage_CopyDst,
+                        dimension,
+                        size: WGPUExtent3D {
+                            width,
+                            height,
+                            depthOrArrayLayers: depth_or_array_layers,
+                        },
+                        format,
+                        mipLevelCount: mip_level_count,
+                        sampleCount: 1,
+                        ..zeroed()
+                    },
+                )
+                .assert_not_null(),
+            }
+        })
+    }
+
+    pub fn create_sampler(&self, compare: WGPUCompareFunction) -> Sampler {
+        ErrorScope::validation(self, "sampler creation failed").execute(|| unsafe {
+            Sampler {
+                handle: wgpuDeviceCreateSampler(
+                    self.handle,
+                    &WGPUSamplerDescriptor {
+                        addressModeU: WGPUAddressMode_WGPUAddressMode_ClampToEdge,
+                        addressModeV: WGPUAddressMode_WGPUAddressMode_ClampToEdge,
+                        addressModeW: WGPUAddressMode_WGPUAddressMode_ClampToEdge,
+                        magFilter: WGPUFilterMode_WGPUFilterMode_Nearest,
+                        minFilter: WGPUFilterMode_WGPUFilterMode_Nearest,
+                        mipmapFilter: WGPUMipmapFilterMode_WGPUMipmapFilterMode_Nearest,
+                        lodMinClamp: 0.0,
+                        lodMaxClamp: 32.0,
+                        compare,
+                        maxAnisotropy: 1,
+                        ..zeroed()
+                    },
+                )
+                .assert_not_null(),
+            }
+        })
+    }
+
     pub fn create_bind_group(
         &self,
         layout: &BindGroupLayout,
         entries: &[BindGroupEntry],
     ) -> BindGroup {
-        ErrorScope::new(self, "bind group creation failed").execute(|| unsafe {
+        ErrorScope::validation(self, "bind group creation failed").execute(|| unsafe {
             let entries = entries.iter().map(|e| e.into()).collect::<Vec<_>>();
             BindGroup {
                 handle: wgpuDeviceCreateBindGroup(
@@ -208,7 +379,7 @@ impl Device {
     }
 
     pub fn create_command_encoder(&self) -> CommandEncoder {
-        ErrorScope::new(self, "command encoder creation failed").execute(|| unsafe {
+        ErrorScope::validation(self, "command encoder creation failed").execute(|| unsafe {
             CommandEncoder {
                 handle: wgpuDeviceCreateCommandEncoder(self.handle, &zeroed()).assert_not_null(),
             }
@@ -240,6 +411,34 @@ impl DeviceQueue {
             wgpuQueueSubmit(self.handle, 1, &commands.handle);
         }
     }
+
+    /// Writes tightly packed texel data to the first mip level of a texture.
+    pub fn write_texture(&self, texture: &Texture, data: &[u8], texel_size: u32, size: [u32; 3]) {
+        let [width, height, depth_or_array_layers] = size;
+        unsafe {
+            wgpuQueueWriteTexture(
+                self.handle,
+                &WGPUTexelCopyTextureInfo {
+                    texture: texture.handle,
+                    mipLevel: 0,
+                    origin: WGPUOrigin3D { x: 0, y: 0, z: 0 },
+                    aspect: WGPUTextureAspect_WGPUTextureAspect_All,
+                },
+                data.as_ptr() as *const c_void,
+                data.len(),
+                &WGPUTexelCopyBufferLayout {
+                    offset: 0,
+                    bytesPerRow: width * texel_size,
+                    rowsPerImage: height,
+                },
+                &WGPUExtent3D {
+                    width,
+                    height,
+                    depthOrArrayLayers: depth_or_array_layers,
+                },
+            );
+        }
+    }
 }
 
 impl Drop for DeviceQueue {
@@ -305,20 +504,33 @@ bitflags::bitflags! {
 }
 
 impl DeviceBuffer {
-    pub fn map_async(&self, mode: DeviceBufferMapMode, size: usize) -> oneshot::Receiver<()> {
+    /// Maps the buffer for reading, returning a receiver for the result. Mapping fails if the
+    /// device is lost, in which case the receiver yields the error message.
+    pub fn map_async(
+        &self,
+        mode: DeviceBufferMapMode,
+        size: usize,
+    ) -> oneshot::Receiver<Result<(), String>> {
         unsafe {
             unsafe extern "C" fn map_callback(
                 res: WGPUMapAsyncStatus,
-                _message: WGPUStringView,
+                message: WGPUStringView,
                 userdata1: *mut c_void,
                 _userdata2: *mut c_void,
             ) {
-                assert_eq!(res, WGPUMapAsyncStatus_WGPUMapAsyncStatus_Success);
-                let mut tx = Box::from_raw(userdata1 as *mut Option<oneshot::Sender<()>>);
-                (*tx).take().unwrap().send(()).unwrap();
+                let result = if res == WGPUMapAsyncStatus_WGPUMapAsyncStatus_Success {
+                    Ok(())
+                } else {
+                    Err(string_from_view(message))
+                };
+
+                let mut tx =
+                    Box::from_raw(userdata1 as *mut Option<oneshot::Sender<Result<(), String>>>);
+                // ignore send errors if receiver dropped
+                let _ = (*tx).take().unwrap().send(result);
             }
 
-            let (tx, rx) = oneshot::channel::<()>();
+            let (tx, rx) = oneshot::channel::<Result<(), String>>();
             let tx = Box::new(Some(tx));
 
             let callback_info = WGPUBufferMapCallbackInfo {
@@ -364,6 +576,62 @@ impl Drop for DeviceBuffer {
     }
 }
 
+pub struct Texture {
+    handle: WGPUTexture,
+}
+
+impl Texture {
+    pub fn create_view(&self, dimension: WGPUTextureViewDimension) -> TextureView {
+        unsafe {
+            TextureView {
+                handle: wgpuTextureCreateView(
+                    self.handle,
+                    &WGPUTextureViewDescriptor {
+                        dimension,
+                        mipLevelCount: u32::MAX,
+                        arrayLayerCount: u32::MAX,
+                        aspect: WGPUTextureAspect_WGPUTextureAspect_All,
+                        ..zeroed()
+                    },
+                )
+                .assert_not_null(),
+            }
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            wgpuTextureRelease(self.handle);
+        }
+    }
+}
+
+pub struct TextureView {
+    handle: WGPUTextureView,
+}
+
+impl Drop for TextureView {
+    fn drop(&mut self) {
+        unsafe {
+            wgpuTextureViewRelease(self.handle);
+        }
+    }
+}
+
+pub struct Sampler {
+    handle: WGPUSampler,
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            wgpuSamplerRelease(self.handle);
+        }
+    }
+}
+
 pub struct BindGroupLayout {
     handle: WGPUBindGroupLayout,
 }
@@ -380,19 +648,35 @@ impl Drop for BindGroupLayout {
 
 pub struct BindGroupEntry<'a> {
     pub binding: u32,
-    pub buffer: &'a DeviceBuffer,
-    pub size: usize,
+    pub resource: BindingResource<'a>,
+}
+
+pub enum BindingResource<'a> {
+    Buffer {
+        buffer: &'a DeviceBuffer,
+        size: usize,
+    },
+    TextureView(&'a TextureView),
+    Sampler(&'a Sampler),
 }
 
 impl<'a> From<&BindGroupEntry<'a>> for WGPUBindGroupEntry {
     fn from(entry: &BindGroupEntry<'a>) -> Self {
+        let (buffer, size, texture_view, sampler) = match entry.resource {
+            BindingResource::Buffer { buffer, size } => {
+                (buffer.handle, size as _, null_mut(), null_mut())
+            }
+            BindingResource::TextureView(view) => (null_mut(), 0, view.handle, null_mut()),
+            BindingResource::Sampler(sampler) => (null_mut(), 0, null_mut(), sampler.handle),
+        };
+
         WGPUBindGroupEntry {
             binding: entry.binding,
-            buffer: entry.buffer.handle,
+            buffer,
             offset: 0,
-            size: entry.size as _,
-            sampler: null_mut(),
-            textureView: null_mut(),
+            size,
+            sampler,
+            textureView: texture_view,
             nextInChain: null_mut(),
         }
     }
@@ -527,22 +811,35 @@ impl<T> PointerExt for *mut T {
     }
 }
 
-struct ErrorScope<'a> {
-    device: &'a Device,
-    message: &'a str,
+/// Records validation errors raised by the operations in a block as errors of the given kind.
+struct ErrorScope {
+    device: *mut WGPUDeviceImpl,
+    errors: Arc<Mutex<Vec<DeviceError>>>,
+    kind: ErrorKind,
+    message: &'static str,
 }
 
-impl<'a> ErrorScope<'a> {
-    fn new(device: &'a Device, message: &'a str) -> Self {
-        ErrorScope { device, message }
+impl ErrorScope {
+    fn compilation(device: &Device, message: &'static str) -> Self {
+        Self::new(device, ErrorKind::Compilation, message)
+    }
+
+    fn validation(device: &Device, message: &'static str) -> Self {
+        Self::new(device, ErrorKind::Validation, message)
+    }
+
+    fn new(device: &Device, kind: ErrorKind, message: &'static str) -> Self {
+        ErrorScope {
+            device: device.handle,
+            errors: device.errors.clone(),
+            kind,
+            message,
+        }
     }
 
     fn execute<T>(self, block: impl FnOnce() -> T) -> T {
         unsafe {
-            wgpuDevicePushErrorScope(
-                self.device.handle,
-                WGPUErrorFilter_WGPUErrorFilter_Validation,
-            );
+            wgpuDevicePushErrorScope(self.device, WGPUErrorFilter_WGPUErrorFilter_Validation);
         }
 
         unsafe extern "C" fn callback(
@@ -558,18 +855,14 @@ impl<'a> ErrorScope<'a> {
                 return;
             }
 
-            if !message.data.is_null() {
-                let slice = std::slice::from_raw_parts(message.data as *const u8, message.length);
-                let message_str = String::from_utf8_lossy(slice);
-                eprintln!("WGPU Error: {}", message_str);
-            }
-
-            eprintln!("Error: {}", scope.message);
-            std::process::abort();
+            scope.errors.lock().unwrap().push(DeviceError {
+                kind: scope.kind,
+                message: format!("{}: {}", scope.message, string_from_view(message)),
+            });
         }
 
         let result = block();
-        let device_handle = self.device.handle;
+        let device_handle = self.device;
 
         let boxed_scope = Box::new(self);
         let userdata = Box::into_raw(boxed_scope) as *mut c_void;
@@ -590,30 +883,50 @@ impl<'a> ErrorScope<'a> {
     }
 }
 
-unsafe extern "C" fn default_error_callback(
+/// Converts a string view into an owned string, which is empty if the view is null.
+unsafe fn string_from_view(view: WGPUStringView) -> String {
+    if view.data.is_null() {
+        return String::new();
+    }
+
+    let slice = std::slice::from_raw_parts(view.data as *const u8, view.length);
+    String::from_utf8_lossy(slice).into_owned()
+}
+
+unsafe extern "C" fn device_lost_callback(
     _device: *const *mut WGPUDeviceImpl,
-    error_type: WGPUErrorType,
+    reason: WGPUDeviceLostReason,
     message: WGPUStringView,
-    _userdata1: *mut c_void,
+    userdata1: *mut c_void,
     _userdata2: *mut c_void,
 ) {
-    if !message.data.is_null() {
-        let slice = std::slice::from_raw_parts(message.data as *const u8, message.length);
-        let message_str = String::from_utf8_lossy(slice);
-        eprintln!("{message_str}");
+    // The device is also reported as lost when it is destroyed or when the instance is deleted,
+    // which isn't a failure.
+    if reason != WGPUDeviceLostReason_WGPUDeviceLostReason_Unknown {
+        return;
     }
 
+    let lost = &*(userdata1 as *const Mutex<Option<String>>);
+    *lost.lock().unwrap() = Some(string_from_view(message));
+}
+
+unsafe extern "C" fn default_error_callback(
+    _device: *const *mut WGPUDeviceImpl,
+    error_type: WGPUErrorType,
+    message: WGPUStringView,
+    userdata1: *mut c_void,
+    _userdata2: *mut c_void,
+) {
     #[allow(non_upper_case_globals)]
-    match error_type {
-        WGPUErrorType_WGPUErrorType_Validation => {
-            panic!("validation error");
-        }
-        WGPUErrorType_WGPUErrorType_OutOfMemory => {
-            panic!("out of memory");
-        }
-        WGPUErrorType_WGPUErrorType_Unknown => {
-            panic!("an unknown error occurred");
-        }
-        _ => {}
-    }
+    let kind = match error_type {
+        WGPUErrorType_WGPUErrorType_NoError => return,
+        WGPUErrorType_WGPUErrorType_Validation => ErrorKind::Validation,
+        _ => ErrorKind::Runtime,
+    };
+
+    let errors = &*(userdata1 as *const Mutex<Vec<DeviceError>>);
+    errors.lock().unwrap().push(DeviceError {
+        kind,
+        message: string_from_view(message),
+    });
 }
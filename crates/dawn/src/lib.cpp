@@ -48,9 +48,16 @@ extern "C" void delete_instance(dawn::native::Instance* instance) {
     delete instance;
 }
 
+extern "C" bool instance_has_wgsl_language_feature(
+    const dawn::native::Instance* instance,
+    WGPUWGSLLanguageFeatureName feature
+) {
+    return wgpuInstanceHasWGSLLanguageFeature(instance->Get(), feature);
+}
+
 extern "C" void enumerate_adapters(
     const dawn::native::Instance* instance,
-    void(*callback)(const WGPUAdapterInfo*, void*),
+    void(*callback)(const WGPUAdapterInfo*, const WGPUSupportedFeatures*, void*),
     void* userdata
 ) {
     if (callback == nullptr) return;
@@ -65,7 +72,12 @@ extern "C" void enumerate_adapters(
 
         wgpuAdapterGetInfo(adapterHandle, &info);
 
-        callback(&info, userdata);
+        WGPUSupportedFeatures features = {};
+        wgpuAdapterGetFeatures(adapterHandle, &features);
+
+        callback(&info, &features, userdata);
+
+        wgpuSupportedFeaturesFreeMembers(features);
     }
 }
 
@@ -73,8 +85,16 @@ extern "C" WGPUDevice create_device(
     const dawn::native::Instance* instance,
     WGPUBackendType backendType,
     uint32_t deviceID,
+    const WGPUFeatureName* requiredFeatures,
+    size_t requiredFeatureCount,
+    const char* const* enabledToggles,
+    size_t enabledToggleCount,
+    const char* const* disabledToggles,
+    size_t disabledToggleCount,
     WGPUUncapturedErrorCallback errorCallback,
-    void* errorUserdata
+    void* errorUserdata,
+    WGPUDeviceLostCallback lostCallback,
+    void* lostUserdata
 ) {
     WGPURequestAdapterOptions options = {};
     auto native_adapters = instance->EnumerateAdapters(&options);
@@ -86,22 +106,18 @@ extern "C" WGPUDevice create_device(
         wgpuAdapterGetInfo(adapter_handle, &info);
 
         if (info.backendType == backendType && info.deviceID == deviceID) {
-            const char* enabledToggles[] = {
-//                "dump_shaders", "disable_symbol_renaming",
-                "use_dxc"
-            };
-            const char* disabledToggles[] = {
-            };
-
+            // Useful toggles for debugging: "dump_shaders", "disable_symbol_renaming"
             WGPUDawnTogglesDescriptor toggles = {};
             toggles.chain.sType = WGPUSType_DawnTogglesDescriptor;
-            toggles.enabledToggleCount = sizeof(enabledToggles) / sizeof(const char*);
+            toggles.enabledToggleCount = enabledToggleCount;
             toggles.enabledToggles = enabledToggles;
-            toggles.disabledToggleCount = sizeof(disabledToggles) / sizeof(const char*);
+            toggles.disabledToggleCount = disabledToggleCount;
             toggles.disabledToggles = disabledToggles;
 
             WGPUDeviceDescriptor descriptor = {};
             descriptor.nextInChain = reinterpret_cast<WGPUChainedStruct*>(&toggles);
+            descriptor.requiredFeatures = requiredFeatures;
+            descriptor.requiredFeatureCount = requiredFeatureCount;
 
             WGPUUncapturedErrorCallbackInfo errorCallbackInfo = {};
             errorCallbackInfo.callback = errorCallback;
@@ -109,6 +125,13 @@ extern "C" WGPUDevice create_device(
 
             descriptor.uncapturedErrorCallbackInfo = errorCallbackInfo;
 
+            WGPUDeviceLostCallbackInfo lostCallbackInfo = {};
+            lostCallbackInfo.mode = WGPUCallbackMode_AllowSpontaneous;
+            lostCallbackInfo.callback = lostCallback;
+            lostCallbackInfo.userdata1 = lostUserdata;
+
+            descriptor.deviceLostCallbackInfo = lostCallbackInfo;
+
             WGPUDevice device = wgpuAdapterCreateDevice(adapter_handle, &descriptor);
 
             if (device) {
@@ -13,12 +13,15 @@ pub mod webgpu {
 }
 
 mod dawn {
-    use std::ffi::c_void;
+    use std::ffi::{c_char, c_void};
 
     use crate::webgpu;
 
-    pub type EnumerateAdapterCallback =
-        unsafe extern "C" fn(*const webgpu::WGPUAdapterInfo, *mut c_void);
+    pub type EnumerateAdapterCallback = unsafe extern "C" fn(
+        *const webgpu::WGPUAdapterInfo,
+        *const webgpu::WGPUSupportedFeatures,
+        *mut c_void,
+    );
 
     extern "C" {
         pub fn new_instance() -> *mut c_void;
@@ -27,6 +30,11 @@ mod dawn {
 
         pub fn instance_process_events(instance: *const c_void);
 
+        pub fn instance_has_wgsl_language_feature(
+            instance: *mut c_void,
+            feature: webgpu::WGPUWGSLLanguageFeatureName,
+        ) -> bool;
+
         pub fn enumerate_adapters(
             instance: *mut c_void,
             callback: Option<EnumerateAdapterCallback>,
@@ -37,8 +45,16 @@ mod dawn {
             instance: *mut c_void,
             backend_type: webgpu::WGPUBackendType,
             device_id: u32,
+            required_features: *const webgpu::WGPUFeatureName,
+            required_feature_count: usize,
+            enabled_toggles: *const *const c_char,
+            enabled_toggle_count: usize,
+            disabled_toggles: *const *const c_char,
+            disabled_toggle_count: usize,
             callback: webgpu::WGPUUncapturedErrorCallback,
             userdata: *mut c_void,
+            lost_callback: webgpu::WGPUDeviceLostCallback,
+            lost_userdata: *mut c_void,
         ) -> webgpu::WGPUDevice;
     }
 }
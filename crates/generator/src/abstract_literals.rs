@@ -0,0 +1,280 @@
+//! Rewrites literals as abstract literals, which are converted by WGSL's automatic conversion
+//! rules to the type that is required by their context.
+//!
+//! Literals are only rewritten where their context determines the type they are converted to,
+//! which is always the type of the original literal. At most one operand of each binary operator
+//! is rewritten, so that the operation itself is still evaluated with concrete types. The values
+//! of the literals are preserved exactly, so the shader computes the same results as before.
+
+use std::str::FromStr;
+
+use ast::types::DataType;
+use ast::{
+    AssignmentLhs, BinOp, BuiltinFn, Else, Expr, ExprNode, ForLoopInit, ForLoopUpdate, LhsExpr,
+    LhsExprNode, Lit, Module, Postfix, Statement,
+};
+use rand::Rng;
+
+/// Builtins whose parameters all have the same type, which is determined by any argument that
+/// remains concrete.
+const UNIFORM_BUILTINS: &[BuiltinFn] = {
+    use BuiltinFn::*;
+    &[
+        Atan2, Clamp, Distance, Fma, Max, Min, Mix, Pow, Smoothstep, Step,
+    ]
+};
+
+pub fn make_literals_abstract(module: &mut Module, rng: &mut impl Rng) {
+    let mut rewriter = Rewriter { rng };
+
+    for decl in &mut module.consts {
+        rewriter.expr(&mut decl.initializer, true);
+    }
+
+    for decl in &mut module.vars {
+        if let Some(init) = &mut decl.initializer {
+            rewriter.expr(init, true);
+        }
+    }
+
+    for decl in &mut module.functions {
+        rewriter.stmts(&mut decl.body);
+    }
+}
+
+struct Rewriter<'a, R> {
+    rng: &'a mut R,
+}
+
+impl<R: Rng> Rewriter<'_, R> {
+    fn stmts(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match stmt {
+            // The types of `let` and `const` declarations are inferred from their initializers.
+            Statement::LetDecl(decl) => self.expr(&mut decl.initializer, false),
+            Statement::ConstDecl(decl) => self.expr(&mut decl.initializer, false),
+            Statement::ConstAssert(stmt) => self.expr(&mut stmt.expr, false),
+            Statement::VarDecl(decl) => {
+                let typed = decl.data_type.is_some();
+                if let Some(init) = &mut decl.initializer {
+                    self.expr(init, typed);
+                }
+            }
+            Statement::Assignment(stmt) => {
+                let typed = match &mut stmt.lhs {
+                    AssignmentLhs::Phony => false,
+                    AssignmentLhs::Expr(lhs) => {
+                        self.lhs_expr(lhs);
+                        true
+                    }
+                };
+
+                self.expr(&mut stmt.rhs, typed);
+            }
+            Statement::Increment(lhs) | Statement::Decrement(lhs) => self.lhs_expr(lhs),
+            Statement::Compound(stmts) => self.stmts(stmts),
+            Statement::If(stmt) => {
+                self.expr(&mut stmt.condition, false);
+                self.stmts(&mut stmt.body);
+
+                let mut else_ = stmt.else_.as_deref_mut();
+                while let Some(e) = else_ {
+                    match e {
+                        Else::If(stmt) => {
+                            self.expr(&mut stmt.condition, false);
+                            self.stmts(&mut stmt.body);
+                            else_ = stmt.else_.as_deref_mut();
+                        }
+                        Else::Else(body) => {
+                            self.stmts(body);
+                            else_ = None;
+                        }
+                    }
+                }
+            }
+            Statement::Return(stmt) => {
+                if let Some(value) = &mut stmt.value {
+                    self.expr(value, true);
+                }
+            }
+            Statement::Loop(stmt) => {
+                self.stmts(&mut stmt.body);
+
+                if let Some(continuing) = &mut stmt.continuing {
+                    self.stmts(&mut continuing.body);
+
+                    if let Some(condition) = &mut continuing.break_if {
+                        self.expr(condition, false);
+                    }
+                }
+            }
+            Statement::Switch(stmt) => {
+                self.expr(&mut stmt.selector, false);
+
+                for case in &mut stmt.cases {
+                    self.stmts(&mut case.body);
+                }
+
+                self.stmts(&mut stmt.default);
+            }
+            Statement::ForLoop(stmt) => {
+                if let Some(ForLoopInit::VarDecl(decl)) = &mut stmt.header.init {
+                    let typed = decl.data_type.is_some();
+                    if let Some(init) = &mut decl.initializer {
+                        self.expr(init, typed);
+                    }
+                }
+
+                if let Some(condition) = &mut stmt.header.condition {
+                    self.expr(condition, false);
+                }
+
+                match &mut stmt.header.update {
+                    Some(ForLoopUpdate::Assignment(stmt)) => {
+                        let typed = match &mut stmt.lhs {
+                            AssignmentLhs::Phony => false,
+                            AssignmentLhs::Expr(lhs) => {
+                                self.lhs_expr(lhs);
+                                true
+                            }
+                        };
+
+                        self.expr(&mut stmt.rhs, typed);
+                    }
+                    Some(ForLoopUpdate::Increment(lhs) | ForLoopUpdate::Decrement(lhs)) => {
+                        self.lhs_expr(lhs)
+                    }
+                    None => {}
+                }
+
+                self.stmts(&mut stmt.body);
+            }
+            Statement::While(stmt) => {
+                self.expr(&mut stmt.condition, false);
+                self.stmts(&mut stmt.body);
+            }
+            Statement::FnCall(stmt) => self.args(&stmt.ident, &mut stmt.args),
+            Statement::Break | Statement::Continue | Statement::Fallthrough => {}
+        }
+    }
+
+    fn lhs_expr(&mut self, node: &mut LhsExprNode) {
+        match &mut node.expr {
+            LhsExpr::Ident(_) => {}
+            LhsExpr::Postfix(inner, postfix) => {
+                self.lhs_expr(inner);
+                self.postfix(postfix);
+            }
+            LhsExpr::Deref(inner) | LhsExpr::AddressOf(inner) => self.lhs_expr(inner),
+        }
+    }
+
+    /// Rewrites the literals within an expression, where `converted` is true if the context of the
+    /// expression converts it to its own type.
+    fn expr(&mut self, node: &mut ExprNode, converted: bool) {
+        match &mut node.expr {
+            Expr::Lit(lit) => {
+                if converted && self.rng.gen_bool(0.5) {
+                    *lit = self.abstract_lit(*lit);
+                }
+            }
+            Expr::TypeCons(expr) => {
+                for arg in &mut expr.args {
+                    // Conversions between scalar types are left alone, since an abstract value is
+                    // converted differently than a concrete one.
+                    let converted = match &node.data_type {
+                        DataType::Scalar(ty) | DataType::Vector(_, ty) => {
+                            arg.data_type == DataType::Scalar(*ty)
+                        }
+                        _ => true,
+                    };
+
+                    self.expr(arg, converted);
+                }
+            }
+            Expr::Bitcast(expr) => self.expr(&mut expr.inner, false),
+            Expr::Var(_) => {}
+            Expr::Postfix(expr) => {
+                self.expr(&mut expr.inner, false);
+                self.postfix(&mut expr.postfix);
+            }
+            Expr::UnOp(expr) => self.expr(&mut expr.inner, false),
+            Expr::BinOp(expr) => {
+                let is_lit = |node: &ExprNode| matches!(node.expr, Expr::Lit(_));
+
+                let (left, right) = match expr.op {
+                    // The left operand of a shift determines its type, while the right operand is
+                    // always converted to `u32`.
+                    BinOp::LShift | BinOp::RShift => (false, true),
+                    _ => match (is_lit(&expr.left), is_lit(&expr.right)) {
+                        (true, true) => {
+                            let left = self.rng.gen_bool(0.5);
+                            (left, !left)
+                        }
+                        // Otherwise at most one of the operands is a literal, and the other one
+                        // remains concrete.
+                        (left, right) => (left, right),
+                    },
+                };
+
+                self.expr(&mut expr.left, left);
+                self.expr(&mut expr.right, right);
+            }
+            Expr::FnCall(expr) => self.args(&expr.ident, &mut expr.args),
+        }
+    }
+
+    fn postfix(&mut self, postfix: &mut Postfix) {
+        match postfix {
+            // Indices may have any integer type.
+            Postfix::Index(index) => self.expr(index, true),
+            Postfix::Member(_) => {}
+        }
+    }
+
+    /// Rewrites the literals within the arguments of a function call.
+    ///
+    /// The parameters of user-defined functions always have concrete types. For builtins, only a
+    /// single argument is rewritten, and only if it has the same type as every other argument.
+    fn args(&mut self, ident: &str, args: &mut [ExprNode]) {
+        let Ok(builtin) = BuiltinFn::from_str(ident) else {
+            for arg in args {
+                self.expr(arg, true);
+            }
+
+            return;
+        };
+
+        let rewritten = (UNIFORM_BUILTINS.contains(&builtin) && args.len() > 1)
+            .then(|| self.rng.gen_range(0..args.len()));
+
+        for (i, arg) in args.iter_mut().enumerate() {
+            self.expr(arg, rewritten == Some(i));
+        }
+    }
+
+    /// Returns an abstract literal with the same value as `lit`.
+    fn abstract_lit(&mut self, lit: Lit) -> Lit {
+        match lit {
+            Lit::I32(v) => Lit::AbstractInt(v.into()),
+            Lit::U32(v) => Lit::AbstractInt(v.into()),
+            // Integral values (except for -0.0) can also be written as an abstract integer, which
+            // is converted to `f32` exactly.
+            Lit::F32(v)
+                if v.fract() == 0.0
+                    && v.abs() < (1u64 << 53) as f32
+                    && !(v == 0.0 && v.is_sign_negative())
+                    && self.rng.gen_bool(0.5) =>
+            {
+                Lit::AbstractInt(v as i64)
+            }
+            Lit::F32(v) => Lit::AbstractFloat(v.into()),
+            lit => lit,
+        }
+    }
+}
@@ -1,63 +1,134 @@
+mod bool_vectors;
+mod builtin_structs;
+mod call_chains;
+mod consts;
 mod cx;
+mod dead_code;
 mod expr;
+mod features;
 mod fns;
+mod invocations;
+mod oracle;
+mod profiles;
 mod scope;
 mod stmt;
 mod structs;
+mod subgroups;
+mod swizzles;
+mod textures;
+mod uniformity;
 mod utils;
 
 pub mod builtins;
 
+pub use oracle::OracleModule;
+
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use ast::types::{DataType, MemoryViewType};
 use ast::{
-    AccessMode, AssignmentLhs, AssignmentOp, AssignmentStatement, FnAttr, FnDecl, GlobalVarAttr,
-    GlobalVarDecl, LetDeclStatement, Module, Postfix, PostfixExpr, ShaderStage, Statement,
-    StorageClass, VarExpr, VarQualifier,
+    AccessMode, AssignmentLhs, AssignmentOp, AssignmentStatement, ConstAssertStatement,
+    DiagnosticSeverity, Directive, Extension, FnAttr, FnDecl, GlobalConstDecl, GlobalVarAttr,
+    GlobalVarDecl, LanguageExtension, LetDeclStatement, Lit, Module, Postfix, PostfixExpr,
+    ShaderStage, Statement, StorageClass, VarExpr, VarQualifier,
 };
-use rand::prelude::{SliceRandom, StdRng};
+use rand::prelude::SliceRandom;
 use rand::Rng;
+use rand_chacha::ChaCha12Rng;
 use rand_distr::{Binomial, Distribution, StandardNormal};
 
 use crate::gen::scope::Scope;
-use crate::Options;
+use crate::{Options, RngVersion};
 
 use self::cx::Context;
+use self::features::{Feature, FeatureSet};
+use self::invocations::Invocations;
 use self::structs::StructKind;
+use self::textures::Texture;
 
 #[derive(Default)]
 struct FnState {
     is_loop: bool,
     block_depth: u32,
     expression_depth: u32,
+    // set while control flow is uniform across the workgroup, ignoring divergence
+    uniform_control_flow: bool,
+    // set once an invocation may have left the function or a loop early in non-uniform control
+    // flow, after which control flow is never uniform again
+    diverged: bool,
 }
 
 pub struct Generator<'a> {
-    rng: &'a mut StdRng,
+    rng: &'a mut ChaCha12Rng,
     options: Rc<Options>,
+    features: FeatureSet,
+    max_expr_depth: u32,
+    // number of statements that can still be generated before reaching the size budget
+    stmt_budget: u32,
     cx: Context,
     return_type: Option<DataType>,
     fn_state: FnState,
     global_scope: Scope,
     scope: Scope,
     current_block: Vec<Statement>,
+    consts: Vec<GlobalConstDecl>,
+    const_asserts: Vec<ConstAssertStatement>,
+    const_count: u32,
+    // values of the constants, which allows them to be used as case selectors and in asserts
+    const_values: HashMap<String, Lit>,
+    // set if each invocation writes to a separate element of the output buffer
+    invocations: Option<Invocations>,
+    textures: Vec<Texture>,
+    // variables in the workgroup address space, which are only accessed in uniform control flow
+    workgroup_vars: Vec<(String, DataType)>,
     f32_dist: StandardNormal,
     i32_dist: Binomial,
     u32_dist: Binomial,
 }
 
 impl<'a> Generator<'a> {
-    pub fn new(rng: &'a mut StdRng, options: Rc<Options>) -> Self {
+    pub fn new(rng: &'a mut ChaCha12Rng, options: Rc<Options>) -> Self {
+        let mut features = if options.swarm {
+            FeatureSet::random(rng)
+        } else {
+            FeatureSet::all()
+        };
+
+        if let Some(profile) = options.profile {
+            for feature in profile.disabled_features() {
+                features.remove(*feature);
+            }
+        }
+
+        let max_expr_depth = options
+            .max_expr_depth
+            .or_else(|| options.profile.map(|it| it.max_expr_depth()))
+            .unwrap_or(5);
+
+        let stmt_budget = options.max_total_stmts.unwrap_or(u32::MAX);
+
+        tracing::info!("enabled features: {features}");
+
         Generator {
             rng,
             options: options.clone(),
-            cx: Context::new(options),
+            cx: Context::new(options, &features),
+            features,
+            max_expr_depth,
+            stmt_budget,
             return_type: None,
             fn_state: FnState::default(),
             global_scope: Scope::empty(),
             scope: Scope::empty(),
             current_block: vec![],
+            consts: vec![],
+            const_asserts: vec![],
+            const_count: 0,
+            const_values: HashMap::new(),
+            invocations: None,
+            textures: vec![],
+            workgroup_vars: vec![],
             f32_dist: StandardNormal,
             i32_dist: Binomial::new(i32::MAX as u64 * 2, 0.5)
                 .expect("failed to create binomial distribution"),
@@ -66,16 +137,29 @@ impl<'a> Generator<'a> {
         }
     }
 
+    /// Returns the number of workgroups that the generated shader must be dispatched with, if it
+    /// depends on the layout of the output buffer.
+    pub fn dispatch(&self) -> Option<[u32; 3]> {
+        self.invocations.map(|it| it.dispatch)
+    }
+
+    /// Returns the textures that are bound to the generated shader.
+    pub fn textures(&self) -> &[Texture] {
+        &self.textures
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn gen_module(&mut self) -> Module {
-        let struct_count = self
-            .rng
-            .gen_range(self.options.min_structs..=self.options.max_structs);
+        let struct_count = if self.features.contains(Feature::Structs) {
+            self.rng
+                .gen_range(self.options.min_structs..=self.options.max_structs)
+        } else {
+            0
+        };
 
         for i in 1..=struct_count {
             let name = format!("Struct_{}", i);
-            let decl = self.gen_struct(name);
-            self.cx.types.insert(decl);
+            self.gen_struct(name);
         }
 
         let ub_type_decl =
@@ -83,8 +167,24 @@ impl<'a> Generator<'a> {
         let sb_type_decl =
             self.gen_struct_with("StorageBuffer".to_owned(), StructKind::HostShareable);
 
+        // Since version 5, each invocation of a multi-invocation shader writes its output to a
+        // separate element of the output buffer, which allows the output to depend on the
+        // invocation IDs.
+        if self.options.multi_invocation && self.options.rng_version >= RngVersion::V5 {
+            self.invocations = Some(self.gen_invocations());
+        }
+
+        let out_buf_type = match &self.invocations {
+            Some(invocations) => invocations::invocation_output_type(
+                invocations,
+                DataType::Struct(sb_type_decl.clone()),
+            ),
+            None => DataType::Struct(sb_type_decl.clone()),
+        };
+
         self.global_scope
             .insert_readonly("u_input".to_owned(), DataType::Struct(ub_type_decl.clone()));
+        self.global_scope.mark_uniform("u_input".to_owned());
 
         let mut global_vars = vec![
             GlobalVarDecl {
@@ -104,36 +204,79 @@ impl<'a> Generator<'a> {
                     access_mode: Some(AccessMode::ReadWrite),
                 }),
                 name: "s_output".to_owned(),
-                data_type: DataType::Struct(sb_type_decl.clone()),
+                data_type: out_buf_type.clone(),
                 initializer: None,
             },
         ];
 
+        if self.features.contains(Feature::Consts) {
+            for _ in 0..self.rng.gen_range(0..=5) {
+                self.gen_global_const();
+            }
+        }
+
         for i in 0..self.rng.gen_range(0..=5) {
             let name = format!("global{i}");
             global_vars.push(self.gen_global_var(name));
         }
 
-        let entrypoint = self.gen_entrypoint_function(
-            DataType::Struct(ub_type_decl.clone()),
-            DataType::Struct(sb_type_decl.clone()),
-        );
+        if self.options.enable_barriers {
+            global_vars.extend(self.gen_workgroup_vars());
+        }
 
-        let Context { types, fns } =
-            std::mem::replace(&mut self.cx, Context::new(self.options.clone()));
+        if self.options.enable_textures {
+            self.textures = self.gen_textures(2);
+            global_vars.extend(self.textures.iter().map(Texture::decl));
+            global_vars.extend(self.gen_sampler_decls(2 + self.textures.len() as u32));
+        }
+
+        let entrypoint =
+            self.gen_entrypoint_function(DataType::Struct(ub_type_decl.clone()), out_buf_type);
+
+        if self.features.contains(Feature::Consts) {
+            for _ in 0..self.rng.gen_range(0..=2) {
+                self.gen_global_const_assert();
+            }
+        }
+
+        let Context { types, fns } = std::mem::replace(
+            &mut self.cx,
+            Context::new(self.options.clone(), &self.features),
+        );
 
         let mut functions = fns.into_fns();
 
         functions.push(entrypoint);
 
+        let mut directives = vec![];
+
+        if self.options.enable_subgroups {
+            directives.push(Directive::Enable(Extension::Subgroups));
+            // The entrypoint runs with a single invocation, so subgroup operations in non-uniform
+            // control flow are still well defined.
+            directives.push(Directive::Diagnostic(
+                DiagnosticSeverity::Off,
+                "subgroup_uniformity".to_owned(),
+            ));
+        }
+
+        if self.options.enable_packed_dot_products {
+            directives.push(Directive::Requires(
+                LanguageExtension::Packed4x8IntegerDotProduct,
+            ));
+        }
+
         Module {
+            directives,
             structs: {
                 let mut structs = types.into_structs();
                 structs.push(ub_type_decl);
                 structs.push(sb_type_decl);
                 structs
             },
-            consts: vec![],
+            consts: std::mem::take(&mut self.consts),
+            overrides: vec![],
+            const_asserts: std::mem::take(&mut self.const_asserts),
             vars: global_vars,
             functions,
         }
@@ -142,8 +285,10 @@ impl<'a> Generator<'a> {
     fn gen_global_var(&mut self, name: String) -> GlobalVarDecl {
         let mut data_type = self.cx.types.select(self.rng);
 
-        if self.rng.gen_bool(0.5) {
-            data_type = DataType::Array(Rc::new(data_type), Some(self.rng.gen_range(1..=32)));
+        if self.features.contains(Feature::Arrays) && self.rng.gen_bool(0.5) {
+            let size = self.rng.gen_range(1..=32);
+            let size = self.gen_const_size(size);
+            data_type = DataType::Array(Rc::new(data_type), Some(size));
         }
 
         let mem_view = MemoryViewType::new(data_type.clone(), StorageClass::Private);
@@ -152,7 +297,11 @@ impl<'a> Generator<'a> {
         self.global_scope.insert_mutable(name.clone(), ref_type);
 
         let initializer = if self.rng.gen_bool(0.5) {
-            Some(self.gen_const_expr(&data_type))
+            if self.options.rng_version >= RngVersion::V8 {
+                Some(self.gen_private_var_initializer(&data_type))
+            } else {
+                Some(self.gen_const_expr(&data_type))
+            }
         } else {
             None
         };
@@ -171,8 +320,24 @@ impl<'a> Generator<'a> {
 
     #[tracing::instrument(skip(self))]
     fn gen_entrypoint_function(&mut self, in_buf_type: DataType, out_buf_type: DataType) -> FnDecl {
-        let stmt_count = self.rng.gen_range(5..10);
-        let (_, block) = self.with_scope(self.global_scope.clone(), |this| {
+        let stmt_count = self
+            .rng
+            .gen_range(self.options.fn_min_stmts..=self.options.fn_max_stmts);
+        let inputs = match self.invocations {
+            Some(_) => invocations::invocation_inputs(),
+            None => vec![],
+        };
+
+        let mut function_scope = self.global_scope.clone();
+
+        for input in &inputs {
+            function_scope.insert_readonly(input.name.clone(), input.data_type.clone());
+        }
+
+        // Every invocation starts executing the entrypoint together.
+        self.fn_state.uniform_control_flow = true;
+
+        let (_, block) = self.with_scope(function_scope, |this| {
             let (scope, mut block) = this.gen_stmt_block(stmt_count);
 
             if let Some(Statement::Return(_)) = block.last() {
@@ -193,8 +358,24 @@ impl<'a> Generator<'a> {
                     .into(),
                 );
 
-                let out_lhs = AssignmentLhs::name("s_output", out_buf_type.clone());
-                let out_rhs = this.gen_expr(&out_buf_type);
+                let (out_lhs, out_type) = match (this.invocations, &out_buf_type) {
+                    (Some(invocations), DataType::Array(element_type, _)) => (
+                        invocations::invocation_output_lhs(&invocations, out_buf_type.clone()),
+                        element_type.as_ref().clone(),
+                    ),
+                    _ => (
+                        AssignmentLhs::name("s_output", out_buf_type.clone()),
+                        out_buf_type.clone(),
+                    ),
+                };
+
+                // The output is computed by the call chain if there is one, so that the chain
+                // can't be removed as dead code.
+                let out_rhs = match this.options.max_call_depth {
+                    Some(depth) if depth > 0 => this.gen_call_chain(&out_type, depth),
+                    _ => this.gen_expr(&out_type),
+                };
+
                 this.current_block
                     .push(AssignmentStatement::new(out_lhs, AssignmentOp::Simple, out_rhs).into());
             });
@@ -202,13 +383,35 @@ impl<'a> Generator<'a> {
             std::mem::replace(&mut this.current_block, prev_block)
         });
 
+        // By default the harness dispatches a single workgroup which must contain a single
+        // invocation. Before version 5, every invocation of a multi-invocation shader computes the
+        // same output so the result doesn't depend on the number of invocations.
+        let workgroup_size = if let Some(invocations) = self.invocations {
+            invocations
+                .workgroup_size
+                .into_iter()
+                .map(|size| self.gen_const_size(size))
+                .collect()
+        } else if self.options.multi_invocation {
+            let x = self.rng.gen_range(1..=16);
+            let y = self.rng.gen_range(1..=4);
+            let z = self.rng.gen_range(1..=4);
+            vec![
+                self.gen_const_size(x),
+                self.gen_const_size(y),
+                self.gen_const_size(z),
+            ]
+        } else {
+            vec![self.gen_const_size(1)]
+        };
+
         FnDecl {
             attrs: vec![
                 FnAttr::Stage(ShaderStage::Compute),
-                FnAttr::WorkgroupSize(1),
+                FnAttr::WorkgroupSize(workgroup_size),
             ],
             name: "main".to_owned(),
-            inputs: vec![],
+            inputs,
             output: None,
             body: block,
         }
@@ -0,0 +1,99 @@
+use ast::types::{DataType, ScalarType};
+use ast::{BinOp, BinOpExpr, BuiltinFn, ExprNode, FnCallExpr};
+use rand::prelude::SliceRandom;
+use rand::Rng;
+
+use crate::RngVersion;
+
+use super::features::Feature;
+
+impl super::Generator<'_> {
+    /// Returns true if a boolean vector expression can be generated for the given type, which is
+    /// either reduced to a scalar bool with `all`/`any` or used as the condition of a vector
+    /// `select`.
+    pub fn can_gen_bool_vector_expr(&self, ty: &DataType) -> bool {
+        if self.options.rng_version < RngVersion::V3
+            || !self.features.contains(Feature::Vectors)
+            || !self.features.contains(Feature::Builtins)
+        {
+            return false;
+        }
+
+        match ty {
+            DataType::Scalar(ScalarType::Bool) => {
+                self.options.allows_builtin(BuiltinFn::All)
+                    || self.options.allows_builtin(BuiltinFn::Any)
+            }
+            DataType::Vector(_, _) => self.options.allows_builtin(BuiltinFn::Select),
+            _ => false,
+        }
+    }
+
+    /// Generates an expression of the given type which is computed from a boolean vector.
+    pub fn gen_bool_vector_expr(&mut self, ty: &DataType) -> ExprNode {
+        self.fn_state.expression_depth += 1;
+
+        let expr = match ty {
+            DataType::Scalar(ScalarType::Bool) => {
+                let builtin = [BuiltinFn::All, BuiltinFn::Any]
+                    .into_iter()
+                    .filter(|it| self.options.allows_builtin(*it))
+                    .collect::<Vec<_>>()
+                    .choose(&mut self.rng)
+                    .copied()
+                    .unwrap();
+
+                let n = self.rng.gen_range(2..=4);
+                let arg = self.gen_bool_vector(n);
+
+                FnCallExpr::new(builtin.as_ref(), vec![arg]).into_node(ty.clone())
+            }
+            DataType::Vector(n, _) => {
+                let f = self.gen_expr(ty);
+                let t = self.gen_expr(ty);
+                let cond = self.gen_bool_vector(*n);
+
+                FnCallExpr::new(BuiltinFn::Select.as_ref(), vec![f, t, cond]).into_node(ty.clone())
+            }
+            _ => unreachable!(),
+        };
+
+        self.fn_state.expression_depth -= 1;
+
+        expr
+    }
+
+    /// Generates a `vec<bool>` with `n` components, which is usually a component-wise comparison
+    /// of two numeric vectors.
+    fn gen_bool_vector(&mut self, n: u8) -> ExprNode {
+        if self.rng.gen_bool(0.3) {
+            return self.gen_expr(&DataType::Vector(n, ScalarType::Bool));
+        }
+
+        let op = [
+            BinOp::Equal,
+            BinOp::NotEqual,
+            BinOp::Less,
+            BinOp::LessEqual,
+            BinOp::Greater,
+            BinOp::GreaterEqual,
+        ]
+        .choose(&mut self.rng)
+        .copied()
+        .unwrap();
+
+        let operand_ty = [ScalarType::I32, ScalarType::U32, ScalarType::F32]
+            .choose(&mut self.rng)
+            .copied()
+            .unwrap();
+
+        let operand_ty = DataType::Vector(n, operand_ty);
+
+        self.fn_state.expression_depth += 1;
+        let l = self.gen_expr(&operand_ty);
+        let r = self.gen_expr(&operand_ty);
+        self.fn_state.expression_depth -= 1;
+
+        BinOpExpr::new(op, l, r).into()
+    }
+}
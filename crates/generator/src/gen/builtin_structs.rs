@@ -0,0 +1,61 @@
+use ast::types::{DataType, ScalarType};
+use ast::{BuiltinFn, ExprNode, FnCallExpr, Postfix, PostfixExpr};
+use rand::prelude::SliceRandom;
+
+use crate::RngVersion;
+
+use super::features::Feature;
+
+impl super::Generator<'_> {
+    /// Returns true if a member of the struct returned by `frexp` or `modf` can be generated for
+    /// the given type.
+    pub fn can_gen_builtin_struct_member(&self, ty: &DataType) -> bool {
+        self.options.rng_version >= RngVersion::V7
+            && self.features.contains(Feature::Builtins)
+            && !self.builtin_struct_members(ty).is_empty()
+    }
+
+    /// Generates a call to `frexp` or `modf`, and accesses a member of the returned struct.
+    ///
+    /// The result struct types can't be named, so the call is never assigned to a variable and
+    /// the member is always accessed directly.
+    pub fn gen_builtin_struct_member(&mut self, ty: &DataType) -> ExprNode {
+        let (builtin, member) = self
+            .builtin_struct_members(ty)
+            .choose(&mut self.rng)
+            .copied()
+            .unwrap();
+
+        let arg_ty = ty.map(ScalarType::F32);
+
+        self.fn_state.expression_depth += 1;
+        let arg = self.gen_expr(&arg_ty);
+        self.fn_state.expression_depth -= 1;
+
+        let struct_ty = builtin.return_type(std::iter::once(&arg_ty)).unwrap();
+        let call = FnCallExpr::new(builtin.as_ref(), vec![arg]).into_node(struct_ty);
+
+        PostfixExpr::new(call, Postfix::member(member)).into()
+    }
+
+    /// Returns the builtins and result struct members which produce a value of the given type.
+    fn builtin_struct_members(&self, ty: &DataType) -> Vec<(BuiltinFn, &'static str)> {
+        let candidates: &[_] = match ty {
+            DataType::Scalar(ScalarType::F32) | DataType::Vector(_, ScalarType::F32) => &[
+                (BuiltinFn::Frexp, "fract"),
+                (BuiltinFn::Modf, "fract"),
+                (BuiltinFn::Modf, "whole"),
+            ],
+            DataType::Scalar(ScalarType::I32) | DataType::Vector(_, ScalarType::I32) => {
+                &[(BuiltinFn::Frexp, "exp")]
+            }
+            _ => &[],
+        };
+
+        candidates
+            .iter()
+            .copied()
+            .filter(|(builtin, _)| self.options.allows_builtin(*builtin))
+            .collect()
+    }
+}
@@ -4,6 +4,7 @@ use std::rc::Rc;
 use ast::{BuiltinFn, DataType, ScalarType};
 
 use crate::gen::cx::Func;
+use crate::{Options, RngVersion};
 
 use super::cx::Overload;
 
@@ -20,11 +21,19 @@ pub const TINT_EXTRAS: &[BuiltinFn] = {
     &[CountLeadingZeros, CountTrailingZeros, Refract]
 };
 
-pub fn gen_builtins() -> HashMap<DataType, Vec<Rc<Func>>> {
+/// Returns the overloads of all builtins that may be generated, including those which are otherwise
+/// disabled by default but were explicitly enabled in `options`.
+pub fn gen_builtins(options: &Options) -> HashMap<DataType, Vec<Rc<Func>>> {
     use BuiltinFn::*;
     use DataType::*;
     use ScalarType::*;
 
+    let enabled = options.enabled_fns.as_slice();
+
+    // The packed 8-bit integer builtins require the `packed_4x8_integer_dot_product` language
+    // extension. Older versions generated the packing builtins without requiring it.
+    let packed_4x8 = options.enable_packed_dot_products || options.rng_version < RngVersion::V9;
+
     let mut map = HashMap::<DataType, Vec<Rc<Func>>>::new();
 
     for s_ty in [I32, U32, F32] {
@@ -91,62 +100,120 @@ pub fn gen_builtins() -> HashMap<DataType, Vec<Rc<Func>>> {
     }
 
     for ty in scalar_and_vectors_of(F32) {
-        for builtin in [
-            // Acos - // TODO: recondition,
-            // Acosh - not implemented in tint/naga,
-            // Asin - // TODO: recondition,
-            // Asinh - not implemnted in tint/naga,
-            // Atan - // TODO: recondition,
-            // Atanh - not implemented in tint/naga,
-            Ceil, // Cos,
-            // Cosh,
-            // Degrees,
-            Exp, Exp2, Floor, Fract,
-            // InverseSqrt - // TODO: recondition,
-            // Log - // TODO: recondition,
-            // Log2 - // TODO: recondition,
-            // QuantizeToF16 - buggy,
-            // Radians,
-            Round, Saturate, Sign,
-            // Sin,
-            // Sinh,
-            // Sqrt - // TODO: recondition,
-            // Tan - // TODO: recondition,
-            // Tanh - // TODO: recondition,
-            Trunc,
-        ] {
+        for builtin in [Ceil, Exp, Exp2, Floor, Fract, Round, Saturate, Sign, Trunc] {
             map.add(builtin, [ty.clone()], ty.clone());
         }
 
-        for builtin in [Max, Min /*, Pow */, Step] {
+        for builtin in [Max, Min, Step] {
             map.add(builtin, [ty.clone(), ty.clone()], ty.clone());
         }
 
-        // for builtin in [Fma, Mix, Smoothstep] {
-        //     map.add(builtin, [ty.clone(), ty.clone(), ty.clone()], ty.clone());
-        // }
+        // The following builtins are only generated if explicitly enabled, since they are either
+        // not reconditioned or not implemented by all compilers.
+
+        for builtin in [
+            Acos,  // TODO: recondition
+            Acosh, // not implemented in tint/naga
+            Asin,  // TODO: recondition
+            Asinh, // not implemented in tint/naga
+            Atan,  // TODO: recondition
+            Atanh, // not implemented in tint/naga
+            Cos,
+            Cosh,
+            Degrees,
+            InverseSqrt,
+            Log,
+            Log2,
+            QuantizeToF16, // buggy
+            Radians,
+            Sin,
+            Sinh,
+            Sqrt,
+            Tan,  // TODO: recondition
+            Tanh, // TODO: recondition
+        ] {
+            map.add_if_enabled(enabled, builtin, [ty.clone()], ty.clone());
+        }
+
+        for builtin in [Atan2, Pow] {
+            map.add_if_enabled(enabled, builtin, [ty.clone(), ty.clone()], ty.clone());
+        }
+
+        for builtin in [Fma, Mix, Smoothstep] {
+            map.add_if_enabled(
+                enabled,
+                builtin,
+                [ty.clone(), ty.clone(), ty.clone()],
+                ty.clone(),
+            );
+        }
+
+        map.add_if_enabled(enabled, Distance, [ty.clone(), ty.clone()], F32);
+        map.add_if_enabled(enabled, Length, [ty.clone()], F32);
+
+        // https://github.com/gfx-rs/naga/issues/1908
+        map.add_if_enabled(enabled, Ldexp, [ty.clone(), ty.map(I32)], ty.clone());
+    }
+
+    for builtin in [Pack4x8snorm, Pack4x8unorm] {
+        map.add(builtin, [Vector(4, F32)], U32);
+    }
+
+    for builtin in [Pack2x16snorm, Pack2x16unorm, Pack2x16float] {
+        map.add(builtin, [Vector(2, F32)], U32);
+    }
+
+    if packed_4x8 {
+        map.add(Pack4xI8, [Vector(4, I32)], U32);
+        map.add(Pack4xI8Clamp, [Vector(4, I32)], U32);
+        map.add(Pack4xU8, [Vector(4, U32)], U32);
+        map.add(Pack4xU8Clamp, [Vector(4, U32)], U32);
+    }
+
+    for builtin in [Unpack4x8snorm, Unpack4x8unorm] {
+        map.add(builtin, [Scalar(U32)], Vector(4, F32));
+    }
+
+    for builtin in [Unpack2x16snorm, Unpack2x16unorm, Unpack2x16float] {
+        map.add(builtin, [Scalar(U32)], Vector(2, F32));
+    }
+
+    if packed_4x8 {
+        map.add(Unpack4xI8, [Scalar(U32)], Vector(4, I32));
+        map.add(Unpack4xU8, [Scalar(U32)], Vector(4, U32));
+    }
 
-        // map.add(Distance, [ty.clone(), ty.clone()], F32);
-        // map.add(Ldexp, [ty.clone(), ty.map(I32)], ty.clone()); // https://github.com/gfx-rs/naga/issues/1908
-        // map.add(Length, [ty.clone()], F32);
+    if options.enable_packed_dot_products {
+        map.add(Dot4I8Packed, [Scalar(U32), Scalar(U32)], I32);
+        map.add(Dot4U8Packed, [Scalar(U32), Scalar(U32)], U32);
     }
 
-    // map.add(Cross, [Vector(3, F32), Vector(3, F32)], Vector(3, F32));
+    map.add_if_enabled(
+        enabled,
+        Cross,
+        [Vector(3, F32), Vector(3, F32)],
+        Vector(3, F32),
+    );
 
-    // for ty in vectors_of(F32) {
-    //     map.add(
-    //         FaceForward,
-    //         [ty.clone(), ty.clone(), ty.clone()],
-    //         ty.clone(),
-    //     );
+    for ty in vectors_of(F32) {
+        map.add_if_enabled(
+            enabled,
+            FaceForward,
+            [ty.clone(), ty.clone(), ty.clone()],
+            ty.clone(),
+        );
 
-    //     map.add(Reflect, [ty.clone(), ty.clone()], ty.clone());
+        map.add_if_enabled(enabled, Normalize, [ty.clone()], ty.clone());
+        map.add_if_enabled(enabled, Reflect, [ty.clone(), ty.clone()], ty.clone());
 
-    //     // Unimplemented in naga
-    //     if enabled.contains(&Refract) {
-    //         map.add(Refract, [ty.clone(), ty.clone(), F32.into()], ty.clone());
-    //     }
-    // }
+        // Unimplemented in naga
+        map.add_if_enabled(
+            enabled,
+            Refract,
+            [ty.clone(), ty.clone(), F32.into()],
+            ty.clone(),
+        );
+    }
 
     map
 }
@@ -158,6 +225,18 @@ trait HashMapExt {
         params: impl Into<Vec<DataType>>,
         return_type: impl Into<DataType>,
     );
+
+    fn add_if_enabled(
+        &mut self,
+        enabled: &[BuiltinFn],
+        builtin: BuiltinFn,
+        params: impl Into<Vec<DataType>>,
+        return_type: impl Into<DataType>,
+    ) {
+        if enabled.contains(&builtin) {
+            self.add(builtin, params, return_type);
+        }
+    }
 }
 
 impl HashMapExt for HashMap<DataType, Vec<Rc<Func>>> {
@@ -0,0 +1,113 @@
+use std::mem;
+
+use ast::types::DataType;
+use ast::{ExprNode, FnCallExpr, FnDecl, FnInput, FnOutput, ReturnStatement, Statement, VarExpr};
+use rand::Rng;
+
+impl super::Generator<'_> {
+    /// Generates a chain of `depth` functions, each of which calls the next one, and returns a
+    /// call to the first function in the chain.
+    ///
+    /// Every function in the chain has the same parameters, which are passed on to the next
+    /// function either directly or as part of a new expression. The functions aren't called from
+    /// anywhere else, so the chain can't be recursive.
+    pub fn gen_call_chain(&mut self, return_type: &DataType, depth: u32) -> ExprNode {
+        let params = (0..self.rng.gen_range(1..=4))
+            .map(|_| self.cx.types.select(self.rng))
+            .collect::<Vec<_>>();
+
+        // The chain is generated from the innermost function outwards, so that each function can
+        // call the one that was generated before it.
+        let mut callee = None;
+        for level in (0..depth).rev() {
+            let name = format!("chain_{level}");
+            let decl = self.gen_chain_fn(name.clone(), &params, return_type, callee.take());
+            self.cx.fns.insert_uncallable(decl);
+            callee = Some(name);
+        }
+
+        self.fn_state.expression_depth += 1;
+        let args = params.iter().map(|ty| self.gen_expr(ty)).collect();
+        self.fn_state.expression_depth -= 1;
+
+        FnCallExpr::new(callee.unwrap(), args).into_node(return_type.clone())
+    }
+
+    /// Generates a function in a call chain, which returns the result of calling `callee` at the
+    /// end of its body, or computes its own result if it is the innermost function.
+    fn gen_chain_fn(
+        &mut self,
+        name: String,
+        params: &[DataType],
+        return_type: &DataType,
+        callee: Option<String>,
+    ) -> FnDecl {
+        let saved_state = mem::take(&mut self.fn_state);
+
+        let stmt_count = self
+            .rng
+            .gen_range(self.options.fn_min_stmts..=self.options.fn_max_stmts);
+
+        let inputs = params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| FnInput {
+                attrs: vec![],
+                name: format!("arg_{i}"),
+                data_type: ty.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut function_scope = self.global_scope.clone();
+
+        for input in &inputs {
+            function_scope.insert_readonly(input.name.clone(), input.data_type.clone());
+        }
+
+        let (_, block) = self.with_scope(function_scope, |this| {
+            let Some(callee) = callee else {
+                return this.gen_stmt_block_with_return(stmt_count, Some(return_type.clone()));
+            };
+
+            let saved_return_type = this.return_type.replace(return_type.clone());
+            let (scope, mut block) = this.gen_stmt_block(stmt_count);
+            this.return_type = saved_return_type;
+
+            // The call to the next function must be reachable.
+            if let Some(Statement::Return(_)) = block.last() {
+                block.pop();
+            }
+
+            this.with_scope(scope, |this| {
+                let args = inputs
+                    .iter()
+                    .map(|input| {
+                        if this.rng.gen_bool(0.5) {
+                            VarExpr::new(&input.name).into_node(input.data_type.clone())
+                        } else {
+                            this.gen_expr(&input.data_type)
+                        }
+                    })
+                    .collect();
+
+                let call = FnCallExpr::new(callee, args).into_node(return_type.clone());
+                block.push(ReturnStatement::new(call).into());
+            });
+
+            block
+        });
+
+        self.fn_state = saved_state;
+
+        FnDecl {
+            attrs: vec![],
+            name,
+            inputs,
+            output: Some(FnOutput {
+                attrs: vec![],
+                data_type: return_type.clone(),
+            }),
+            body: block,
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+
+use ast::types::{DataType, ScalarType};
+use ast::{
+    BinOp, BinOpExpr, ConstAssertStatement, ConstDeclStatement, ConstSize, ExprNode,
+    GlobalConstDecl, Lit, Statement, TypeConsExpr, UnOp, UnOpExpr, VarExpr,
+};
+use rand::prelude::SliceRandom;
+use rand::Rng;
+
+use crate::RngVersion;
+
+use super::features::Feature;
+use super::scope::Scope;
+
+impl super::Generator<'_> {
+    /// Generates a module-scope constant of a random scalar type and makes it available in the
+    /// global scope.
+    pub fn gen_global_const(&mut self) {
+        let data_type = self.gen_const_type();
+        let name = self.next_const_name();
+        let initializer = self.gen_const_initializer(&name, &data_type);
+        self.insert_global_const(name, data_type, initializer);
+    }
+
+    /// Generates a function-scope `const` declaration.
+    pub fn gen_const_stmt(&mut self) -> Statement {
+        let data_type = self.gen_const_type();
+        let name = self.next_const_name();
+        let initializer = self.gen_const_initializer(&name, &data_type);
+        ConstDeclStatement::new(name, initializer).into()
+    }
+
+    /// Generates a size with the given value, which is either written as a literal or by naming a
+    /// new module-scope constant.
+    pub fn gen_const_size(&mut self, value: u32) -> ConstSize {
+        if !self.features.contains(Feature::Consts) || self.rng.gen_bool(0.5) {
+            return ConstSize::Lit(value);
+        }
+
+        let name = self.next_const_name();
+        self.const_values.insert(name.clone(), Lit::U32(value));
+        self.insert_global_const(name.clone(), ScalarType::U32.into(), Lit::U32(value).into());
+        ConstSize::Const(name, value)
+    }
+
+    /// Generates an i32 case selector which is distinct from the values in `existing`.
+    ///
+    /// This will reuse an i32 constant that is in scope if one with an unused value is available.
+    pub fn gen_case_selector(&mut self, existing: &mut HashSet<i32>) -> ExprNode {
+        let ty = DataType::Scalar(ScalarType::I32);
+
+        let consts = self
+            .scope
+            .of_type(&ty)
+            .iter()
+            .filter_map(|(name, _)| match self.const_values.get(name)? {
+                Lit::I32(value) => Some((name, *value)),
+                _ => None,
+            })
+            .filter(|(_, value)| !existing.contains(value))
+            .map(|(name, value)| (name.clone(), value))
+            .collect::<Vec<_>>();
+
+        if let Some((name, value)) = consts.choose(self.rng).filter(|_| self.rng.gen_bool(0.5)) {
+            existing.insert(*value);
+            return VarExpr::new(name).into_node(ty);
+        }
+
+        let value = loop {
+            let value = self.gen_i32();
+            if existing.insert(value) {
+                break value;
+            }
+        };
+
+        Lit::I32(value).into()
+    }
+
+    /// Generates a module-scope `const_assert`, if there are any module-scope constants.
+    pub fn gen_global_const_assert(&mut self) {
+        let consts = self.known_consts(&self.global_scope);
+        if !consts.is_empty() {
+            let stmt = self.gen_const_assert(consts);
+            self.const_asserts.push(stmt);
+        }
+    }
+
+    /// Returns true if there are constants in scope that can be used in a `const_assert`.
+    pub fn can_gen_const_assert_stmt(&self) -> bool {
+        self.features.contains(Feature::Consts) && !self.known_consts(&self.scope).is_empty()
+    }
+
+    /// Generates a function-scope `const_assert`.
+    pub fn gen_const_assert_stmt(&mut self) -> Statement {
+        let consts = self.known_consts(&self.scope);
+        self.gen_const_assert(consts).into()
+    }
+
+    /// Generates an assertion that holds trivially, by comparing some of the given constants with
+    /// their values.
+    fn gen_const_assert(&mut self, consts: Vec<(String, Lit)>) -> ConstAssertStatement {
+        let count = self.rng.gen_range(1..=consts.len().min(3));
+
+        let checks = consts
+            .choose_multiple(self.rng, count)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let expr = checks
+            .into_iter()
+            .map(|(name, lit)| {
+                let var = VarExpr::new(name).into_node(lit.data_type());
+
+                if let Lit::Bool(value) = lit {
+                    return if value {
+                        var
+                    } else {
+                        UnOpExpr::new(UnOp::Not, var).into()
+                    };
+                }
+
+                let op = [BinOp::Equal, BinOp::LessEqual, BinOp::GreaterEqual]
+                    .choose(self.rng)
+                    .copied()
+                    .unwrap();
+
+                BinOpExpr::new(op, var, lit).into()
+            })
+            .reduce(|acc, it| BinOpExpr::new(BinOp::LogAnd, acc, it).into())
+            .unwrap();
+
+        ConstAssertStatement::new(expr)
+    }
+
+    /// Generates the initializer of a module-scope `var<private>`, which is a const-expression
+    /// whose scalar components may name module-scope constants.
+    pub fn gen_private_var_initializer(&mut self, ty: &DataType) -> ExprNode {
+        let args = match ty {
+            DataType::Scalar(_) => {
+                let consts = self
+                    .consts
+                    .iter()
+                    .filter(|it| it.data_type == *ty)
+                    .map(|it| it.name.clone())
+                    .collect::<Vec<_>>();
+
+                return match consts.choose(self.rng) {
+                    Some(name) if self.rng.gen_bool(0.5) => {
+                        VarExpr::new(name).into_node(ty.clone())
+                    }
+                    _ => self.gen_const_expr(ty),
+                };
+            }
+            DataType::Vector(n, t) => (0..*n)
+                .map(|_| self.gen_private_var_initializer(&DataType::Scalar(*t)))
+                .collect(),
+            DataType::Array(ty, Some(n)) => (0..n.value().unwrap())
+                .map(|_| self.gen_private_var_initializer(ty))
+                .collect(),
+            DataType::Struct(decl) => decl
+                .members
+                .iter()
+                .map(|it| self.gen_private_var_initializer(&it.data_type))
+                .collect(),
+            _ => return self.gen_const_expr(ty),
+        };
+
+        TypeConsExpr::new(ty.clone(), args).into()
+    }
+
+    fn known_consts(&self, scope: &Scope) -> Vec<(String, Lit)> {
+        [
+            ScalarType::Bool,
+            ScalarType::I32,
+            ScalarType::U32,
+            ScalarType::F32,
+        ]
+        .into_iter()
+        .flat_map(|ty| scope.of_type(&ty.into()))
+        // Naga rejects function-scope constants in `const_assert` as runtime expressions, so we
+        // only use module-scope constants. Version 1 predates this, so it uses any constant in
+        // scope.
+        .filter(|(name, _)| {
+            self.options.rng_version == RngVersion::V1
+                || self.consts.iter().any(|it| it.name == *name)
+        })
+        .filter_map(|(name, _)| Some((name.clone(), *self.const_values.get(name)?)))
+        .collect()
+    }
+
+    fn gen_const_type(&mut self) -> DataType {
+        // Constants are restricted to scalars so that the reconditioner is able to evaluate any
+        // const-expressions in which they are used.
+        [
+            ScalarType::Bool,
+            ScalarType::I32,
+            ScalarType::U32,
+            ScalarType::F32,
+        ]
+        .choose(self.rng)
+        .copied()
+        .unwrap()
+        .into()
+    }
+
+    fn gen_const_initializer(&mut self, name: &str, data_type: &DataType) -> ExprNode {
+        let lit = self.gen_lit(data_type);
+        self.const_values.insert(name.to_owned(), lit);
+        lit.into()
+    }
+
+    fn insert_global_const(&mut self, name: String, data_type: DataType, initializer: ExprNode) {
+        self.global_scope
+            .insert_readonly(name.clone(), data_type.clone());
+
+        self.consts.push(GlobalConstDecl {
+            name,
+            data_type,
+            initializer,
+        });
+    }
+
+    fn next_const_name(&mut self) -> String {
+        // Constants are numbered across the whole module so that names are never shadowed.
+        let name = format!("const_{}", self.const_count);
+        self.const_count += 1;
+        name
+    }
+}
@@ -9,6 +9,7 @@ use rand::Rng;
 
 use crate::Options;
 
+use super::features::{Feature, FeatureSet};
 use super::{builtins, utils};
 
 pub struct Context {
@@ -17,10 +18,10 @@ pub struct Context {
 }
 
 impl Context {
-    pub fn new(options: Rc<Options>) -> Context {
+    pub fn new(options: Rc<Options>, features: &FeatureSet) -> Context {
         Context {
-            types: TypeContext::new(),
-            fns: FnContext::new(options),
+            types: TypeContext::new(features.contains(Feature::Vectors)),
+            fns: FnContext::new(options, features),
         }
     }
 }
@@ -33,7 +34,9 @@ pub struct FnSignature {
 }
 
 pub struct TypeContext {
-    types: Vec<Rc<StructDecl>>,
+    /// Struct types along with the most restrictive filter that they satisfy.
+    types: Vec<(Rc<StructDecl>, SelectionFilter)>,
+    vectors: bool,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -43,13 +46,27 @@ pub enum SelectionFilter {
     Uniform,
 }
 
+impl SelectionFilter {
+    /// Returns true if a type which satisfies `other` also satisfies this filter.
+    fn accepts(self, other: SelectionFilter) -> bool {
+        match self {
+            SelectionFilter::Any => true,
+            SelectionFilter::HostShareable => other != SelectionFilter::Any,
+            SelectionFilter::Uniform => other == SelectionFilter::Uniform,
+        }
+    }
+}
+
 impl TypeContext {
-    pub fn new() -> Self {
-        TypeContext { types: Vec::new() }
+    pub fn new(vectors: bool) -> Self {
+        TypeContext {
+            types: Vec::new(),
+            vectors,
+        }
     }
 
-    pub fn insert(&mut self, decl: Rc<StructDecl>) {
-        self.types.push(decl);
+    pub fn insert(&mut self, decl: Rc<StructDecl>, filter: SelectionFilter) {
+        self.types.push((decl, filter));
     }
 
     pub fn select(&self, rng: &mut impl Rng) -> DataType {
@@ -74,19 +91,22 @@ impl TypeContext {
             User,
         }
 
-        let allowed: &[DataTypeKind] = if matches!(
-            filter,
-            SelectionFilter::HostShareable | SelectionFilter::Uniform
-        ) || self.types.is_empty()
-        {
-            &[DataTypeKind::Scalar, DataTypeKind::Vector]
-        } else {
-            &[
-                DataTypeKind::Scalar,
-                DataTypeKind::Vector,
-                DataTypeKind::User,
-            ]
-        };
+        let structs = self
+            .types
+            .iter()
+            .filter(|(_, it)| filter.accepts(*it))
+            .map(|(decl, _)| decl)
+            .collect::<Vec<_>>();
+
+        let mut allowed = vec![DataTypeKind::Scalar];
+
+        if self.vectors {
+            allowed.push(DataTypeKind::Vector);
+        }
+
+        if !structs.is_empty() {
+            allowed.push(DataTypeKind::User);
+        }
 
         match allowed.choose(rng).unwrap() {
             DataTypeKind::Scalar => DataType::Scalar(allowed_scalars.choose(rng).copied().unwrap()),
@@ -94,12 +114,27 @@ impl TypeContext {
                 rng.gen_range(2..=4),
                 allowed_scalars.choose(rng).copied().unwrap(),
             ),
-            DataTypeKind::User => DataType::Struct(self.types.choose(rng).cloned().unwrap()),
+            DataTypeKind::User => DataType::Struct((*structs.choose(rng).unwrap()).clone()),
         }
     }
 
-    pub fn into_structs(self) -> Vec<Rc<StructDecl>> {
+    pub fn select_struct(&self, rng: &mut impl Rng) -> Option<DataType> {
+        self.types
+            .choose(rng)
+            .map(|(decl, _)| DataType::Struct(decl.clone()))
+    }
+
+    /// Returns the struct types through which a value of the given type can be accessed.
+    pub fn structs_containing(&self, ty: &DataType) -> Vec<DataType> {
         self.types
+            .iter()
+            .filter(|(decl, _)| decl.accessible_types().any(|it| it == ty))
+            .map(|(decl, _)| DataType::Struct(decl.clone()))
+            .collect()
+    }
+
+    pub fn into_structs(self) -> Vec<Rc<StructDecl>> {
+        self.types.into_iter().map(|(decl, _)| decl).collect()
     }
 }
 
@@ -131,9 +166,38 @@ pub struct FnContext {
 }
 
 impl FnContext {
-    pub fn new(_options: Rc<Options>) -> Self {
+    pub fn new(options: Rc<Options>, features: &FeatureSet) -> Self {
+        let mut map = if features.contains(Feature::Builtins) {
+            builtins::gen_builtins(&options)
+        } else {
+            HashMap::new()
+        };
+
+        let is_vector = |ty: &DataType| matches!(ty, DataType::Vector(_, _));
+
+        // Builtins that are explicitly enabled take precedence over the profile, but not over
+        // features that are disabled for this shader.
+        let is_allowed = |builtin: BuiltinFn, overload: &Overload| {
+            if !features.contains(Feature::Vectors)
+                && (is_vector(&overload.return_type) || overload.params.iter().any(is_vector))
+            {
+                return false;
+            }
+
+            options.allows_builtin(builtin)
+        };
+
+        for fns in map.values_mut() {
+            fns.retain(|func| match func.as_ref() {
+                Func::Builtin(builtin, overload) => is_allowed(*builtin, overload),
+                Func::User(_) => true,
+            });
+        }
+
+        map.retain(|_, fns| !fns.is_empty());
+
         FnContext {
-            map: builtins::gen_builtins(),
+            map,
             decls: vec![],
             count: 0,
         }
@@ -181,6 +245,11 @@ impl FnContext {
         func
     }
 
+    /// Adds a function to the module without allowing calls to it to be generated.
+    pub fn insert_uncallable(&mut self, decl: FnDecl) {
+        self.decls.push(decl);
+    }
+
     pub fn next_fn(&mut self) -> String {
         self.count += 1;
         format!("func_{}", self.count)
@@ -0,0 +1,85 @@
+use ast::types::{DataType, ScalarType};
+use ast::{
+    BinOp, BinOpExpr, ExprNode, IfStatement, LetDeclStatement, Lit, Statement, UnOp, UnOpExpr,
+    VarExpr,
+};
+use rand::Rng;
+
+impl super::Generator<'_> {
+    /// Returns true if dead code injection is enabled and an integer can be read from the input
+    /// buffer.
+    pub fn can_gen_dead_code_stmt(&self) -> bool {
+        self.options.dead_code && self.input_int_type().is_some()
+    }
+
+    /// Generates an `if` statement whose body is unreachable, since its condition is always false.
+    ///
+    /// The condition is computed from a value in the input buffer, so that compilers can't easily
+    /// prove that the body is dead. The body can therefore contain arbitrary code which must still
+    /// be compiled correctly, even though it is never executed.
+    pub fn gen_dead_code_stmt(&mut self) -> Statement {
+        let condition = self.gen_opaque_false_expr();
+
+        let max_count = self
+            .rng
+            .gen_range(self.options.block_min_stmts..=self.options.block_max_stmts);
+
+        IfStatement::new(condition, self.gen_stmt_block(max_count).1).into()
+    }
+
+    /// Generates a boolean expression which is false for any value in the input buffer.
+    fn gen_opaque_false_expr(&mut self) -> ExprNode {
+        let scalar_ty = self.input_int_type().unwrap();
+        let ty = DataType::Scalar(scalar_ty);
+        let value = self.gen_input_accessor(scalar_ty);
+
+        // The accessor may contain index expressions with side effects, so we store the value in
+        // a variable to make sure that every use of it refers to the same value.
+        let ident = self.scope.next_name();
+        self.current_block
+            .push(LetDeclStatement::new(ident.clone(), value).into());
+
+        let x = VarExpr::new(ident).into_node(ty);
+
+        let lit = |v: u32| match scalar_ty {
+            ScalarType::I32 => Lit::I32(v as i32),
+            _ => Lit::U32(v),
+        };
+
+        match self.rng.gen_range(0..3) {
+            // Squares are always 0 or 1 modulo 4, even when the multiplication overflows.
+            0 => BinOpExpr::new(
+                BinOp::Equal,
+                BinOpExpr::new(
+                    BinOp::BitAnd,
+                    BinOpExpr::new(BinOp::Times, x.clone(), x),
+                    lit(3),
+                ),
+                lit(2),
+            )
+            .into(),
+            // The product of two consecutive integers is always even.
+            1 => BinOpExpr::new(
+                BinOp::Equal,
+                BinOpExpr::new(
+                    BinOp::BitAnd,
+                    BinOpExpr::new(
+                        BinOp::Times,
+                        x.clone(),
+                        BinOpExpr::new(BinOp::Plus, x, lit(1)),
+                    ),
+                    lit(1),
+                ),
+                lit(1),
+            )
+            .into(),
+            // A value never shares any bits with its complement.
+            _ => BinOpExpr::new(
+                BinOp::NotEqual,
+                BinOpExpr::new(BinOp::BitAnd, x.clone(), UnOpExpr::new(UnOp::BitNot, x)),
+                lit(0),
+            )
+            .into(),
+        }
+    }
+}
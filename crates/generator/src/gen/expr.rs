@@ -3,11 +3,14 @@ use rand::Rng;
 
 use ast::types::{DataType, MemoryViewType, ScalarType};
 use ast::{
-    BinOp, BinOpExpr, Expr, ExprNode, FnCallExpr, FnInput, Lit, Postfix, PostfixExpr, StructDecl,
-    TypeConsExpr, UnOp, UnOpExpr, VarDeclStatement, VarExpr,
+    BinOp, BinOpExpr, BitcastExpr, Expr, ExprNode, FnCallExpr, FnInput, Lit, Postfix, PostfixExpr,
+    StructDecl, TypeConsExpr, UnOp, UnOpExpr, VarDeclStatement, VarExpr,
 };
 
+use crate::RngVersion;
+
 use super::cx::Func;
+use super::features::Feature;
 
 #[derive(Clone, Copy, Debug)]
 enum ExprType {
@@ -16,7 +19,14 @@ enum ExprType {
     Var,
     UnOp,
     BinOp,
+    Bitcast,
     FnCall,
+    Subgroup,
+    BoolVector,
+    TextureQuery,
+    TextureGather,
+    WorkgroupUniformLoad,
+    Swizzle,
 }
 
 impl super::Generator<'_> {
@@ -30,23 +40,44 @@ impl super::Generator<'_> {
             DataType::Struct(_) => allowed.push(ExprType::TypeCons),
             DataType::Ptr(view) => return self.gen_pointer_expr(view),
             DataType::Ref(_) => panic!("explicit request to generate ref expression: `{ty}`"),
+            DataType::Texture(_, _) | DataType::DepthTexture(_) => {
+                panic!("explicit request to generate texture expression")
+            }
+            DataType::Sampler | DataType::SamplerComparison => {
+                panic!("explicit request to generate sampler expression")
+            }
+            DataType::Atomic(_) => panic!("explicit request to generate atomic expression"),
         }
 
-        if self.fn_state.expression_depth < 5 {
+        if self.fn_state.expression_depth < self.max_expr_depth {
             // Unary operators are available for all scalars and vectors.
             if matches!(ty, DataType::Scalar(_) | DataType::Vector(_, _)) {
                 allowed.push(ExprType::UnOp);
             }
 
             // Binary operators are available for all scalars, and for {i32,u32,f32} vectors.
+            // Component-wise comparisons and `&`/`|` are also available for bool vectors.
             if matches!(
                 ty,
                 DataType::Scalar(_)
                     | DataType::Vector(_, ScalarType::I32 | ScalarType::U32 | ScalarType::F32)
-            ) {
+            ) || (self.options.rng_version >= RngVersion::V3
+                && matches!(ty, DataType::Vector(_, ScalarType::Bool)))
+            {
                 allowed.push(ExprType::BinOp);
             }
 
+            // Bitcasts are available for {i32,u32,f32} scalars and vectors.
+            if self.features.contains(Feature::Bitcasts)
+                && matches!(
+                    ty,
+                    DataType::Scalar(ScalarType::I32 | ScalarType::U32 | ScalarType::F32)
+                        | DataType::Vector(_, ScalarType::I32 | ScalarType::U32 | ScalarType::F32)
+                )
+            {
+                allowed.push(ExprType::Bitcast);
+            }
+
             // Function calls are available if we have a function that returns the target type,
             // or we are able to generate a new function.
             // TODO: naga currently has issues with functions that return arrays:
@@ -57,6 +88,30 @@ impl super::Generator<'_> {
             {
                 allowed.push(ExprType::FnCall);
             }
+
+            if self.can_gen_subgroup_expr(ty) {
+                allowed.push(ExprType::Subgroup);
+            }
+
+            if self.can_gen_bool_vector_expr(ty) {
+                allowed.push(ExprType::BoolVector);
+            }
+
+            if self.can_gen_texture_query(ty) {
+                allowed.push(ExprType::TextureQuery);
+            }
+
+            if self.can_gen_texture_gather(ty) {
+                allowed.push(ExprType::TextureGather);
+            }
+
+            if self.can_gen_swizzle_expr(ty) {
+                allowed.push(ExprType::Swizzle);
+            }
+
+            if self.can_gen_workgroup_uniform_load(ty) {
+                allowed.push(ExprType::WorkgroupUniformLoad);
+            }
         }
 
         if !self.scope.of_type(ty).is_empty() {
@@ -70,13 +125,20 @@ impl super::Generator<'_> {
             ExprType::TypeCons => self.gen_type_cons_expr(ty),
             ExprType::UnOp => self.gen_un_op_expr(ty),
             ExprType::BinOp => self.gen_bin_op_expr(ty),
+            ExprType::Bitcast => self.gen_bitcast_expr(ty),
             ExprType::Var => self.gen_var_expr(ty),
             ExprType::FnCall => self.gen_fn_call_expr(ty),
+            ExprType::Subgroup => self.gen_subgroup_expr(ty),
+            ExprType::BoolVector => self.gen_bool_vector_expr(ty),
+            ExprType::TextureQuery => self.gen_texture_query(ty),
+            ExprType::TextureGather => self.gen_texture_gather(ty),
+            ExprType::WorkgroupUniformLoad => self.gen_workgroup_uniform_load(ty),
+            ExprType::Swizzle => self.gen_swizzle_expr(ty),
         }
     }
 
     fn can_gen_fn(&self, _return_type: &DataType) -> bool {
-        self.cx.fns.len() < self.options.max_fns
+        self.features.contains(Feature::Functions) && self.cx.fns.len() < self.options.max_fns
     }
 
     fn gen_pointer_expr(&mut self, mem_view: &MemoryViewType) -> ExprNode {
@@ -133,7 +195,13 @@ impl super::Generator<'_> {
                 .iter()
                 .map(|it| self.gen_expr(&it.data_type))
                 .collect(),
-            DataType::Ptr(_) | DataType::Ref(_) => unimplemented!("no type constructor for `{ty}`"),
+            DataType::Ptr(_)
+            | DataType::Ref(_)
+            | DataType::Texture(_, _)
+            | DataType::DepthTexture(_)
+            | DataType::Sampler
+            | DataType::SamplerComparison
+            | DataType::Atomic(_) => unimplemented!("no type constructor for `{ty}`"),
         };
 
         self.fn_state.expression_depth -= 1;
@@ -147,14 +215,22 @@ impl super::Generator<'_> {
             DataType::Vector(n, t) => (0..*n)
                 .map(|_| self.gen_const_expr(&DataType::Scalar(*t)))
                 .collect(),
-            DataType::Array(ty, Some(n)) => (0..*n).map(|_| self.gen_const_expr(ty)).collect(),
+            DataType::Array(ty, Some(n)) => (0..n.value().unwrap())
+                .map(|_| self.gen_const_expr(ty))
+                .collect(),
             DataType::Array(_, None) => panic!("runtime sized array is not constructable"),
             DataType::Struct(decl) => decl
                 .members
                 .iter()
                 .map(|it| self.gen_const_expr(&it.data_type))
                 .collect(),
-            DataType::Ptr(_) | DataType::Ref(_) => unimplemented!("no type constructor for `{ty}`"),
+            DataType::Ptr(_)
+            | DataType::Ref(_)
+            | DataType::Texture(_, _)
+            | DataType::DepthTexture(_)
+            | DataType::Sampler
+            | DataType::SamplerComparison
+            | DataType::Atomic(_) => unimplemented!("no type constructor for `{ty}`"),
         };
 
         TypeConsExpr::new(ty.clone(), args).into()
@@ -171,6 +247,25 @@ impl super::Generator<'_> {
         UnOpExpr::new(op, expr).into()
     }
 
+    fn gen_bitcast_expr(&mut self, ty: &DataType) -> ExprNode {
+        self.fn_state.expression_depth += 1;
+
+        // The source type has the same number of components, but a different 32-bit scalar type.
+        let scalar = [ScalarType::I32, ScalarType::U32, ScalarType::F32]
+            .into_iter()
+            .filter(|it| Some(*it) != ty.as_scalar())
+            .collect::<Vec<_>>()
+            .choose(&mut self.rng)
+            .copied()
+            .unwrap();
+
+        let expr = self.gen_expr(&ty.map(scalar));
+
+        self.fn_state.expression_depth -= 1;
+
+        BitcastExpr::new(ty.clone(), expr).into()
+    }
+
     fn gen_bin_op_expr(&mut self, ty: &DataType) -> ExprNode {
         self.fn_state.expression_depth += 1;
 
@@ -228,7 +323,14 @@ impl super::Generator<'_> {
             _ => l_ty.clone(),
         };
 
-        let r = self.gen_expr(&r_ty);
+        let r = match op {
+            // The right operand is only evaluated depending on the value of the left operand.
+            BinOp::LogAnd | BinOp::LogOr => {
+                let uniform = self.is_uniform_expr(&l);
+                self.with_control_flow(uniform, |this| this.gen_expr(&r_ty))
+            }
+            _ => self.gen_expr(&r_ty),
+        };
 
         self.fn_state.expression_depth -= 1;
 
@@ -251,6 +353,12 @@ impl super::Generator<'_> {
     }
 
     fn gen_fn_call_expr(&mut self, ty: &DataType) -> ExprNode {
+        // `frexp` and `modf` are only two of the builtins, so they are chosen about as often as
+        // any other builtin despite needing a separate code path.
+        if self.can_gen_builtin_struct_member(ty) && self.rng.gen_bool(0.05) {
+            return self.gen_builtin_struct_member(ty);
+        }
+
         let expr = self.gen_raw_fn_call_expr(ty);
 
         if expr.data_type == *ty {
@@ -263,8 +371,8 @@ impl super::Generator<'_> {
     }
 
     fn gen_raw_fn_call_expr(&mut self, ty: &DataType) -> ExprNode {
-        // Produce a function call with p=0.8 or p=1 if max functions reached
-        if self.cx.fns.len() > self.options.max_fns || self.rng.gen_bool(0.8) {
+        // Produce a function call with p=0.8 or p=1 if no more functions can be generated
+        if !self.can_gen_fn(ty) || self.rng.gen_bool(0.8) {
             if let Some(func) = self.cx.fns.select(self.rng, ty) {
                 let (name, params, return_type) = match func.as_ref() {
                     Func::Builtin(builtin, overload) => (
@@ -287,7 +395,18 @@ impl super::Generator<'_> {
             }
         }
 
-        // Otherwise generate a new function with the target return type
+        // Otherwise generate a new function with the target return type, or one which returns a
+        // struct containing it so that the caller needs to extract the value from the result
+        let return_type = if self.options.rng_version >= RngVersion::V4 && self.rng.gen_bool(0.3) {
+            self.cx
+                .types
+                .structs_containing(ty)
+                .choose(&mut self.rng)
+                .cloned()
+                .unwrap_or_else(|| ty.clone())
+        } else {
+            ty.clone()
+        };
 
         let arg_count: i32 = self.rng.gen_range(0..5);
 
@@ -304,7 +423,15 @@ impl super::Generator<'_> {
                 UnOpExpr::new(UnOp::AddressOf, var_expr).into()
             } else {
                 self.fn_state.expression_depth += 1;
-                let data_type = self.cx.types.select(self.rng);
+                // Structs are passed by value more often, since they are otherwise only chosen
+                // as often as scalars and vectors.
+                let data_type =
+                    if self.options.rng_version >= RngVersion::V4 && self.rng.gen_bool(0.3) {
+                        self.cx.types.select_struct(self.rng)
+                    } else {
+                        None
+                    };
+                let data_type = data_type.unwrap_or_else(|| self.cx.types.select(self.rng));
                 let expr = self.gen_expr(&data_type);
                 self.fn_state.expression_depth -= 1;
                 expr
@@ -319,15 +446,15 @@ impl super::Generator<'_> {
             args.push(expr);
         }
 
-        let decl = self.gen_fn(params, ty);
+        let decl = self.gen_fn(params, &return_type);
 
         // Add the new function to the context
         let func = self.cx.fns.insert(decl);
 
-        FnCallExpr::new(func.ident(), args).into_node(ty.clone())
+        FnCallExpr::new(func.ident(), args).into_node(return_type)
     }
 
-    fn gen_accessor(&mut self, target: &DataType, expr: ExprNode) -> ExprNode {
+    pub fn gen_accessor(&mut self, target: &DataType, expr: ExprNode) -> ExprNode {
         match expr.data_type.dereference() {
             DataType::Scalar(_) => unreachable!(),
             DataType::Vector(n, _) => self.gen_vector_accessor(*n, target, expr),
@@ -335,16 +462,34 @@ impl super::Generator<'_> {
             DataType::Struct(decl) => self.gen_struct_accessor(&decl.clone(), target, expr),
             DataType::Ptr(_) => self.gen_pointer_deref(target, expr),
             DataType::Ref(_) => todo!(),
+            DataType::Texture(_, _)
+            | DataType::DepthTexture(_)
+            | DataType::Sampler
+            | DataType::SamplerComparison
+            | DataType::Atomic(_) => unreachable!(),
         }
     }
 
     fn gen_vector_accessor(&mut self, size: u8, target: &DataType, expr: ExprNode) -> ExprNode {
+        if target.is_scalar() && self.can_gen_dynamic_index() && self.rng.gen_bool(0.3) {
+            let index = self.gen_dynamic_index();
+            return PostfixExpr::new(expr, Postfix::index(index)).into();
+        }
+
         let accessor = super::utils::gen_vector_accessor(self.rng, size, target);
         PostfixExpr::new(expr, Postfix::member(accessor)).into()
     }
 
     fn gen_array_accessor(&mut self, target: &DataType, expr: ExprNode) -> ExprNode {
-        let index = self.gen_expr(&ScalarType::U32.into());
+        let index = if self.can_gen_dynamic_index() && self.rng.gen_bool(0.5) {
+            self.gen_dynamic_index()
+        } else {
+            self.fn_state.expression_depth += 1;
+            let index = self.gen_expr(&ScalarType::U32.into());
+            self.fn_state.expression_depth -= 1;
+            index
+        };
+
         let expr: ExprNode = PostfixExpr::new(expr, Postfix::index(index)).into();
 
         if expr.data_type.dereference() == target {
@@ -354,6 +499,49 @@ impl super::Generator<'_> {
         self.gen_accessor(target, expr)
     }
 
+    fn can_gen_dynamic_index(&self) -> bool {
+        self.options.rng_version >= RngVersion::V2
+            && self.fn_state.expression_depth < self.max_expr_depth
+            && self.input_int_type().is_some()
+    }
+
+    /// Generates an index which is read from the input buffer, so that its value is only known at
+    /// runtime. Out of bounds indices are clamped by the reconditioner.
+    fn gen_dynamic_index(&mut self) -> ExprNode {
+        let ty = self.input_int_type().unwrap();
+
+        self.fn_state.expression_depth += 1;
+        let index = self.gen_input_accessor(ty);
+        self.fn_state.expression_depth -= 1;
+
+        index
+    }
+
+    /// Returns an integer type which can be read from the input buffer, if there is one.
+    pub fn input_int_type(&self) -> Option<ScalarType> {
+        [ScalarType::U32, ScalarType::I32].into_iter().find(|ty| {
+            self.scope
+                .of_type(&DataType::Scalar(*ty))
+                .iter()
+                .any(|(name, _)| name == "u_input")
+        })
+    }
+
+    /// Generates an expression which reads a value of the given type from the input buffer.
+    pub fn gen_input_accessor(&mut self, ty: ScalarType) -> ExprNode {
+        let ty = DataType::Scalar(ty);
+
+        let (_, input_ty) = self
+            .scope
+            .of_type(&ty)
+            .iter()
+            .find(|(name, _)| name == "u_input")
+            .unwrap();
+
+        let input = VarExpr::new("u_input").into_node(input_ty.clone());
+        self.gen_accessor(&ty, input)
+    }
+
     fn gen_struct_accessor(
         &mut self,
         decl: &StructDecl,
@@ -381,7 +569,7 @@ impl super::Generator<'_> {
     }
 
     #[tracing::instrument(skip(self))]
-    fn gen_lit(&mut self, ty: &DataType) -> Lit {
+    pub fn gen_lit(&mut self, ty: &DataType) -> Lit {
         tracing::info!("generating lit with {:?}", ty);
 
         match ty {
@@ -390,6 +578,7 @@ impl super::Generator<'_> {
                 ScalarType::I32 => Lit::I32(self.gen_i32()),
                 ScalarType::U32 => Lit::U32(self.gen_u32()),
                 ScalarType::F32 => Lit::F32(self.gen_f32()),
+                ScalarType::F16 => unimplemented!("f16 literals are not generated"),
             },
             _ => unreachable!(),
         }
@@ -406,6 +595,11 @@ impl super::Generator<'_> {
             DataType::Struct(_) => unreachable!(),
             DataType::Ptr(_) => todo!(),
             DataType::Ref(_) => todo!(),
+            DataType::Texture(_, _)
+            | DataType::DepthTexture(_)
+            | DataType::Sampler
+            | DataType::SamplerComparison
+            | DataType::Atomic(_) => unreachable!(),
         };
 
         match scalar_ty {
@@ -415,7 +609,7 @@ impl super::Generator<'_> {
                 .choose(&mut self.rng)
                 .copied()
                 .unwrap(),
-            ScalarType::F32 => UnOp::Neg,
+            ScalarType::F32 | ScalarType::F16 => UnOp::Neg,
         }
     }
 
@@ -428,6 +622,11 @@ impl super::Generator<'_> {
             DataType::Struct(_) => unreachable!(),
             DataType::Ptr(_) => todo!(),
             DataType::Ref(_) => todo!(),
+            DataType::Texture(_, _)
+            | DataType::DepthTexture(_)
+            | DataType::Sampler
+            | DataType::SamplerComparison
+            | DataType::Atomic(_) => unreachable!(),
         };
 
         let allowed: &[BinOp] = match scalar_ty {
@@ -453,7 +652,9 @@ impl super::Generator<'_> {
                 BinOp::LShift,
                 BinOp::RShift,
             ],
-            ScalarType::F32 => &[BinOp::Plus, BinOp::Minus, BinOp::Times, BinOp::Divide],
+            ScalarType::F32 | ScalarType::F16 => {
+                &[BinOp::Plus, BinOp::Minus, BinOp::Times, BinOp::Divide]
+            }
         };
 
         let mut allowed = allowed.to_vec();
@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use rand::Rng;
+
+/// Language features which can be individually disabled for a shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// `if` statements.
+    Ifs,
+    /// `loop` and `for` statements.
+    Loops,
+    /// `switch` statements.
+    Switches,
+    /// Vector types.
+    Vectors,
+    /// Arrays in structs and global variables.
+    Arrays,
+    /// User-defined structs (other than the input and output buffer types).
+    Structs,
+    /// Calls to built-in functions.
+    Builtins,
+    /// User-defined functions.
+    Functions,
+    /// `bitcast` expressions.
+    Bitcasts,
+    /// `const` declarations and `const_assert` statements.
+    Consts,
+}
+
+impl Feature {
+    pub const ALL: &'static [Feature] = &[
+        Feature::Ifs,
+        Feature::Loops,
+        Feature::Switches,
+        Feature::Vectors,
+        Feature::Arrays,
+        Feature::Structs,
+        Feature::Builtins,
+        Feature::Functions,
+        Feature::Bitcasts,
+        Feature::Consts,
+    ];
+}
+
+/// The set of language features which are enabled when generating a shader.
+#[derive(Clone, Debug)]
+pub struct FeatureSet(HashSet<Feature>);
+
+impl FeatureSet {
+    pub fn all() -> FeatureSet {
+        FeatureSet(Feature::ALL.iter().copied().collect())
+    }
+
+    /// Enables each feature independently with probability 0.5.
+    ///
+    /// This is known as swarm testing - omitting features from some programs makes it more likely
+    /// for the remaining features to interact with each other in unusual ways (e.g. a shader with no
+    /// control flow is more likely to contain deeply nested expressions).
+    pub fn random(rng: &mut impl Rng) -> FeatureSet {
+        FeatureSet(
+            Feature::ALL
+                .iter()
+                .copied()
+                .filter(|_| rng.gen_bool(0.5))
+                .collect(),
+        )
+    }
+
+    pub fn remove(&mut self, feature: Feature) {
+        self.0.remove(&feature);
+    }
+
+    pub fn contains(&self, feature: Feature) -> bool {
+        self.0.contains(&feature)
+    }
+}
+
+impl Display for FeatureSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let features = Feature::ALL
+            .iter()
+            .filter(|it| self.contains(**it))
+            .map(|it| format!("{it:?}"))
+            .collect::<Vec<_>>();
+
+        write!(f, "[{}]", features.join(", "))
+    }
+}
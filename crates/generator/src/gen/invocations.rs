@@ -0,0 +1,101 @@
+use std::rc::Rc;
+
+use ast::types::{DataType, MemoryViewType};
+use ast::{
+    AccessMode, AssignmentLhs, BinOp, BinOpExpr, BuiltinValue, ConstSize, ExprNode, FnInput,
+    FnInputAttr, Lit, Postfix, PostfixExpr, StorageClass, VarExpr,
+};
+use rand::Rng;
+
+const GLOBAL_ID: &str = "global_id";
+const LOCAL_INDEX: &str = "local_index";
+
+/// Workgroup size and number of workgroups with which a multi-invocation shader is dispatched.
+#[derive(Clone, Copy, Debug)]
+pub struct Invocations {
+    pub workgroup_size: [u32; 3],
+    pub dispatch: [u32; 3],
+}
+
+impl Invocations {
+    /// Returns the total number of invocations in each dimension.
+    fn extent(&self) -> [u32; 3] {
+        [0, 1, 2].map(|i| self.workgroup_size[i] * self.dispatch[i])
+    }
+
+    /// Returns the total number of invocations, each of which writes to a separate element of the
+    /// output buffer.
+    pub fn count(&self) -> u32 {
+        self.extent().iter().product()
+    }
+}
+
+impl super::Generator<'_> {
+    pub fn gen_invocations(&mut self) -> Invocations {
+        Invocations {
+            workgroup_size: [
+                self.rng.gen_range(1..=16),
+                self.rng.gen_range(1..=4),
+                self.rng.gen_range(1..=4),
+            ],
+            dispatch: [
+                self.rng.gen_range(1..=2),
+                self.rng.gen_range(1..=2),
+                self.rng.gen_range(1..=2),
+            ],
+        }
+    }
+}
+
+/// Returns the entrypoint parameters through which the invocation IDs are read.
+pub fn invocation_inputs() -> Vec<FnInput> {
+    [
+        (GLOBAL_ID, BuiltinValue::GlobalInvocationId),
+        (LOCAL_INDEX, BuiltinValue::LocalInvocationIndex),
+    ]
+    .into_iter()
+    .map(|(name, value)| FnInput {
+        attrs: vec![FnInputAttr::Builtin(value)],
+        name: name.to_owned(),
+        data_type: value.data_type(),
+    })
+    .collect()
+}
+
+/// Returns the element of the output buffer which belongs to the current invocation, which is
+/// found by flattening its global invocation ID.
+pub fn invocation_output_lhs(invocations: &Invocations, out_buf_type: DataType) -> AssignmentLhs {
+    let [width, height, _] = invocations.extent();
+
+    let id = |component: &str| -> ExprNode {
+        let global_id =
+            VarExpr::new(GLOBAL_ID).into_node(BuiltinValue::GlobalInvocationId.data_type());
+        PostfixExpr::new(global_id, Postfix::member(component)).into()
+    };
+
+    // global_id.x + global_id.y * width + global_id.z * width * height
+    let index = BinOpExpr::new(
+        BinOp::Plus,
+        BinOpExpr::new(
+            BinOp::Plus,
+            id("x"),
+            BinOpExpr::new(BinOp::Times, id("y"), Lit::U32(width)),
+        ),
+        BinOpExpr::new(BinOp::Times, id("z"), Lit::U32(width * height)),
+    );
+
+    let mem_view = MemoryViewType {
+        access_mode: AccessMode::ReadWrite,
+        ..MemoryViewType::new(out_buf_type, StorageClass::Storage)
+    };
+
+    AssignmentLhs::array_index("s_output", DataType::Ref(mem_view), index.into())
+}
+
+/// Returns the type of the output buffer, with an element for each invocation.
+pub fn invocation_output_type(invocations: &Invocations, element_type: DataType) -> DataType {
+    DataType::Array(
+        Rc::new(element_type),
+        Some(ConstSize::Lit(invocations.count())),
+    )
+}
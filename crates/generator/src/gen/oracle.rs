@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ast::types::{DataType, MemoryViewType, ScalarType};
+use ast::{
+    AccessMode, AssignmentLhs, AssignmentOp, AssignmentStatement, BinOp, BinOpExpr, BitcastExpr,
+    Expr, ExprNode, FnAttr, FnCallExpr, FnDecl, GlobalVarAttr, GlobalVarDecl, LetDeclStatement,
+    Lit, Module, Postfix, PostfixExpr, ShaderStage, StorageClass, StructDecl, StructMember, UnOp,
+    UnOpExpr, VarExpr, VarQualifier,
+};
+use rand::prelude::SliceRandom;
+use rand::Rng;
+
+use super::structs::FIELD_NAMES;
+
+/// A shader generated in oracle mode, along with its inputs and the output that it must produce.
+pub struct OracleModule {
+    pub module: Module,
+    /// Contents of the uniform buffers, keyed by `group:binding`.
+    pub input_data: BTreeMap<String, Vec<u8>>,
+    /// Expected contents of the storage buffers after execution, keyed by `group:binding`.
+    pub expected_output: BTreeMap<String, Vec<u8>>,
+}
+
+/// Values of the input buffer members and `let` declarations that are in scope.
+#[derive(Default)]
+struct Env {
+    inputs: HashMap<String, Lit>,
+    lets: HashMap<String, Lit>,
+}
+
+impl super::Generator<'_> {
+    /// Generates a shader whose output is known ahead of time.
+    ///
+    /// The entry point is a sequence of `let` declarations computing integer expressions from the
+    /// input buffer, whose results are stored to the output buffer. Each expression is evaluated on
+    /// the host as it is generated, using WGSL's wrapping semantics for integer arithmetic.
+    ///
+    /// Only operations that are well-defined for every input are used, so the shader doesn't need
+    /// to be reconditioned. Expressions also always contain a value read from the input buffer, so
+    /// that compilers can't evaluate them as const-expressions (which are rejected if they
+    /// overflow).
+    pub fn gen_oracle_module(&mut self) -> OracleModule {
+        let mut env = Env::default();
+
+        let ub_members = self.gen_oracle_members();
+        let sb_members = self.gen_oracle_members();
+
+        let mut input = vec![];
+        for (name, ty) in &ub_members {
+            let value = self.gen_oracle_lit(*ty);
+            input.extend(lit_bytes(value));
+            env.inputs.insert(name.clone(), value);
+        }
+
+        let ub_type = oracle_struct("UniformBuffer", &ub_members);
+        let sb_type = oracle_struct("StorageBuffer", &sb_members);
+
+        let input_view = MemoryViewType::new(ub_type.clone(), StorageClass::Uniform);
+        let mut leaves = ub_members
+            .iter()
+            .map(|(name, _)| {
+                PostfixExpr::new(
+                    VarExpr::new("u_input").into_node(DataType::Ref(input_view.clone())),
+                    Postfix::member(name),
+                )
+                .into()
+            })
+            .collect::<Vec<ExprNode>>();
+
+        let mut body = vec![];
+        let stmt_count = self
+            .rng
+            .gen_range(self.options.fn_min_stmts..=self.options.fn_max_stmts);
+
+        for i in 0..stmt_count {
+            let ty = self.gen_oracle_type();
+            let expr = self.gen_oracle_expr(ty, &leaves, false, 0);
+            let value = eval(&expr, &env);
+            let name = format!("var_{i}");
+
+            env.lets.insert(name.clone(), value);
+            leaves.push(VarExpr::new(&name).into_node(ty.into()));
+            body.push(LetDeclStatement::new(name, expr).into());
+        }
+
+        let mut output = vec![];
+        for (name, ty) in &sb_members {
+            let expr = self.gen_oracle_expr(*ty, &leaves, false, 0);
+            output.extend(lit_bytes(eval(&expr, &env)));
+
+            body.push(
+                AssignmentStatement::new(
+                    AssignmentLhs::member("s_output".to_owned(), sb_type.clone(), name.clone()),
+                    AssignmentOp::Simple,
+                    expr,
+                )
+                .into(),
+            );
+        }
+
+        let vars = vec![
+            GlobalVarDecl {
+                attrs: vec![GlobalVarAttr::Group(0), GlobalVarAttr::Binding(0)],
+                qualifier: Some(VarQualifier {
+                    storage_class: StorageClass::Uniform,
+                    access_mode: None,
+                }),
+                name: "u_input".to_owned(),
+                data_type: ub_type.clone(),
+                initializer: None,
+            },
+            GlobalVarDecl {
+                attrs: vec![GlobalVarAttr::Group(0), GlobalVarAttr::Binding(1)],
+                qualifier: Some(VarQualifier {
+                    storage_class: StorageClass::Storage,
+                    access_mode: Some(AccessMode::ReadWrite),
+                }),
+                name: "s_output".to_owned(),
+                data_type: sb_type.clone(),
+                initializer: None,
+            },
+        ];
+
+        let entrypoint = FnDecl {
+            attrs: vec![
+                FnAttr::Stage(ShaderStage::Compute),
+                FnAttr::WorkgroupSize(vec![ast::ConstSize::Lit(1)]),
+            ],
+            name: "main".to_owned(),
+            inputs: vec![],
+            output: None,
+            body,
+        };
+
+        OracleModule {
+            input_data: BTreeMap::from([("0:0".to_owned(), padded(input, &ub_type))]),
+            expected_output: BTreeMap::from([("0:1".to_owned(), padded(output, &sb_type))]),
+            module: Module {
+                directives: vec![],
+                structs: [ub_type, sb_type]
+                    .into_iter()
+                    .map(|it| match it {
+                        DataType::Struct(decl) => decl,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+                consts: vec![],
+                overrides: vec![],
+                const_asserts: vec![],
+                vars,
+                functions: vec![entrypoint],
+            },
+        }
+    }
+
+    fn gen_oracle_members(&mut self) -> Vec<(String, ScalarType)> {
+        let count = self
+            .rng
+            .gen_range(self.options.min_struct_members..=self.options.max_struct_members)
+            .clamp(1, FIELD_NAMES.len() as u32);
+
+        FIELD_NAMES[..count as usize]
+            .iter()
+            .map(|name| (name.to_string(), self.gen_oracle_type()))
+            .collect()
+    }
+
+    fn gen_oracle_type(&mut self) -> ScalarType {
+        *[ScalarType::I32, ScalarType::U32].choose(self.rng).unwrap()
+    }
+
+    fn gen_oracle_lit(&mut self, ty: ScalarType) -> Lit {
+        match ty {
+            ScalarType::I32 => Lit::I32(self.gen_i32()),
+            _ => Lit::U32(self.gen_u32()),
+        }
+    }
+
+    /// Generates an integer expression of the given type.
+    ///
+    /// If `allow_const` is false, the expression will always contain at least one of the `leaves`,
+    /// which are values that are only known at runtime.
+    fn gen_oracle_expr(
+        &mut self,
+        ty: ScalarType,
+        leaves: &[ExprNode],
+        allow_const: bool,
+        depth: u32,
+    ) -> ExprNode {
+        let data_type = DataType::Scalar(ty);
+
+        if depth >= self.max_expr_depth || self.rng.gen_bool(0.3) {
+            let all_leaves = leaves;
+            let leaves = leaves
+                .iter()
+                .filter(|it| it.data_type.dereference() == &data_type)
+                .collect::<Vec<_>>();
+
+            if allow_const && (leaves.is_empty() || self.rng.gen_bool(0.3)) {
+                return self.gen_oracle_lit(ty).into();
+            }
+
+            if let Some(leaf) = leaves.choose(self.rng) {
+                return (*leaf).clone();
+            }
+
+            // There are no runtime values of this type, so we convert one of the other type.
+            let other = match ty {
+                ScalarType::I32 => ScalarType::U32,
+                _ => ScalarType::I32,
+            };
+
+            let inner = self.gen_oracle_expr(other, all_leaves, false, depth + 1);
+            return BitcastExpr::new(data_type, inner).into();
+        }
+
+        let depth = depth + 1;
+
+        match self.rng.gen_range(0..6) {
+            0 | 1 => {
+                let op = *[
+                    BinOp::Plus,
+                    BinOp::Minus,
+                    BinOp::Times,
+                    BinOp::BitAnd,
+                    BinOp::BitOr,
+                    BinOp::BitXOr,
+                ]
+                .choose(self.rng)
+                .unwrap();
+
+                let (l, r) = self.gen_oracle_operands(ty, leaves, depth);
+                BinOpExpr::new(op, l, r).into()
+            }
+            2 => {
+                let op = *[BinOp::LShift, BinOp::RShift].choose(self.rng).unwrap();
+                let l = self.gen_oracle_expr(ty, leaves, false, depth);
+                let r = self.gen_oracle_expr(ScalarType::U32, leaves, true, depth);
+
+                // The shift amount is masked so that it is always less than the bit width.
+                let r = BinOpExpr::new(BinOp::BitAnd, r, Lit::U32(31));
+                BinOpExpr::new(op, l, r).into()
+            }
+            3 => {
+                let ops: &[UnOp] = match ty {
+                    ScalarType::I32 => &[UnOp::Neg, UnOp::BitNot],
+                    _ => &[UnOp::BitNot],
+                };
+
+                let op = *ops.choose(self.rng).unwrap();
+                UnOpExpr::new(op, self.gen_oracle_expr(ty, leaves, false, depth)).into()
+            }
+            4 => {
+                let (ident, args) = match self.rng.gen_range(0..4) {
+                    0 | 1 => {
+                        let (l, r) = self.gen_oracle_operands(ty, leaves, depth);
+                        (["min", "max"].choose(self.rng).unwrap(), vec![l, r])
+                    }
+                    _ => (
+                        ["abs", "countOneBits", "reverseBits"]
+                            .choose(self.rng)
+                            .unwrap(),
+                        vec![self.gen_oracle_expr(ty, leaves, false, depth)],
+                    ),
+                };
+
+                FnCallExpr::new(*ident, args).into_node(data_type)
+            }
+            _ => {
+                let op = *[
+                    BinOp::Less,
+                    BinOp::LessEqual,
+                    BinOp::Greater,
+                    BinOp::GreaterEqual,
+                    BinOp::Equal,
+                    BinOp::NotEqual,
+                ]
+                .choose(self.rng)
+                .unwrap();
+
+                let cmp_ty = self.gen_oracle_type();
+                let (l, r) = self.gen_oracle_operands(cmp_ty, leaves, depth);
+                let condition = BinOpExpr::new(op, l, r);
+
+                let f = self.gen_oracle_expr(ty, leaves, true, depth);
+                let t = self.gen_oracle_expr(ty, leaves, true, depth);
+
+                FnCallExpr::new("select", vec![f, t, condition.into()]).into_node(data_type)
+            }
+        }
+    }
+
+    /// Generates a pair of operands, at most one of which is constant.
+    fn gen_oracle_operands(
+        &mut self,
+        ty: ScalarType,
+        leaves: &[ExprNode],
+        depth: u32,
+    ) -> (ExprNode, ExprNode) {
+        let l = self.gen_oracle_expr(ty, leaves, true, depth);
+        let r = self.gen_oracle_expr(ty, leaves, !is_const(&l), depth);
+
+        if self.rng.gen_bool(0.5) {
+            (l, r)
+        } else {
+            (r, l)
+        }
+    }
+}
+
+fn is_const(expr: &ExprNode) -> bool {
+    match &expr.expr {
+        Expr::Lit(_) => true,
+        Expr::Var(_) | Expr::Postfix(_) => false,
+        Expr::TypeCons(expr) => expr.args.iter().all(is_const),
+        Expr::Bitcast(expr) => is_const(&expr.inner),
+        Expr::UnOp(expr) => is_const(&expr.inner),
+        Expr::BinOp(expr) => is_const(&expr.left) && is_const(&expr.right),
+        Expr::FnCall(expr) => expr.args.iter().all(is_const),
+    }
+}
+
+/// Evaluates an expression generated by `gen_oracle_expr`.
+fn eval(expr: &ExprNode, env: &Env) -> Lit {
+    match &expr.expr {
+        Expr::Lit(lit) => *lit,
+        Expr::Var(var) => env.lets[&var.ident],
+        Expr::Postfix(PostfixExpr {
+            postfix: Postfix::Member(member),
+            ..
+        }) => env.inputs[member],
+        Expr::Bitcast(expr) => match (&expr.data_type, eval(&expr.inner, env)) {
+            (DataType::Scalar(ScalarType::I32), Lit::U32(v)) => Lit::I32(v as i32),
+            (DataType::Scalar(ScalarType::U32), Lit::I32(v)) => Lit::U32(v as u32),
+            (_, v) => v,
+        },
+        Expr::UnOp(expr) => match (expr.op, eval(&expr.inner, env)) {
+            (UnOp::Neg, Lit::I32(v)) => Lit::I32(v.wrapping_neg()),
+            (UnOp::BitNot, Lit::I32(v)) => Lit::I32(!v),
+            (UnOp::BitNot, Lit::U32(v)) => Lit::U32(!v),
+            (op, v) => unreachable!("unsupported unary operation `{op}` on `{v}`"),
+        },
+        Expr::BinOp(expr) => eval_bin_op(expr.op, eval(&expr.left, env), eval(&expr.right, env)),
+        Expr::FnCall(call) => {
+            let args = call.args.iter().map(|it| eval(it, env)).collect::<Vec<_>>();
+            match (call.ident.as_str(), args.as_slice()) {
+                ("select", [f, t, Lit::Bool(c)]) => {
+                    if *c {
+                        *t
+                    } else {
+                        *f
+                    }
+                }
+                ("min", [Lit::I32(a), Lit::I32(b)]) => Lit::I32(*a.min(b)),
+                ("min", [Lit::U32(a), Lit::U32(b)]) => Lit::U32(*a.min(b)),
+                ("max", [Lit::I32(a), Lit::I32(b)]) => Lit::I32(*a.max(b)),
+                ("max", [Lit::U32(a), Lit::U32(b)]) => Lit::U32(*a.max(b)),
+                // The absolute value of the most negative i32 is itself.
+                ("abs", [Lit::I32(v)]) => Lit::I32(v.wrapping_abs()),
+                ("abs", [Lit::U32(v)]) => Lit::U32(*v),
+                ("countOneBits", [Lit::I32(v)]) => Lit::I32(v.count_ones() as i32),
+                ("countOneBits", [Lit::U32(v)]) => Lit::U32(v.count_ones()),
+                ("reverseBits", [Lit::I32(v)]) => Lit::I32(v.reverse_bits()),
+                ("reverseBits", [Lit::U32(v)]) => Lit::U32(v.reverse_bits()),
+                (ident, args) => unreachable!("unsupported call to `{ident}` with {args:?}"),
+            }
+        }
+        _ => unreachable!("unsupported expression `{expr}`"),
+    }
+}
+
+fn eval_bin_op(op: BinOp, l: Lit, r: Lit) -> Lit {
+    macro_rules! int_op {
+        ($ty:ident, $l:expr, $r:expr) => {
+            match op {
+                BinOp::Plus => Lit::$ty($l.wrapping_add($r)),
+                BinOp::Minus => Lit::$ty($l.wrapping_sub($r)),
+                BinOp::Times => Lit::$ty($l.wrapping_mul($r)),
+                BinOp::BitAnd => Lit::$ty($l & $r),
+                BinOp::BitOr => Lit::$ty($l | $r),
+                BinOp::BitXOr => Lit::$ty($l ^ $r),
+                BinOp::Less => Lit::Bool($l < $r),
+                BinOp::LessEqual => Lit::Bool($l <= $r),
+                BinOp::Greater => Lit::Bool($l > $r),
+                BinOp::GreaterEqual => Lit::Bool($l >= $r),
+                BinOp::Equal => Lit::Bool($l == $r),
+                BinOp::NotEqual => Lit::Bool($l != $r),
+                op => unreachable!("unsupported binary operation `{op}`"),
+            }
+        };
+    }
+
+    match (l, r) {
+        // Shift amounts are always masked to be less than 32.
+        (Lit::I32(l), Lit::U32(r)) if op == BinOp::LShift => Lit::I32(l << r),
+        (Lit::I32(l), Lit::U32(r)) if op == BinOp::RShift => Lit::I32(l >> r),
+        (Lit::U32(l), Lit::U32(r)) if op == BinOp::LShift => Lit::U32(l << r),
+        (Lit::U32(l), Lit::U32(r)) if op == BinOp::RShift => Lit::U32(l >> r),
+        (Lit::I32(l), Lit::I32(r)) => int_op!(I32, l, r),
+        (Lit::U32(l), Lit::U32(r)) => int_op!(U32, l, r),
+        (l, r) => unreachable!("unsupported operands `{l}` and `{r}` for `{op}`"),
+    }
+}
+
+fn oracle_struct(name: &str, members: &[(String, ScalarType)]) -> DataType {
+    DataType::Struct(StructDecl::new(
+        name,
+        members
+            .iter()
+            .map(|(name, ty)| StructMember::new(vec![], name.clone(), DataType::Scalar(*ty)))
+            .collect(),
+    ))
+}
+
+fn lit_bytes(lit: Lit) -> [u8; 4] {
+    match lit {
+        Lit::I32(v) => v.to_le_bytes(),
+        Lit::U32(v) => v.to_le_bytes(),
+        lit => unreachable!("unexpected value `{lit}`"),
+    }
+}
+
+/// Pads the contents of a buffer of 4-byte scalar members to the size of the buffer type.
+fn padded(mut bytes: Vec<u8>, data_type: &DataType) -> Vec<u8> {
+    let size = common::Type::try_from(data_type).unwrap().buffer_size();
+    bytes.resize(size as usize, 0);
+    bytes
+}
@@ -0,0 +1,61 @@
+use ast::BuiltinFn;
+
+use crate::Profile;
+
+use super::features::Feature;
+
+impl Profile {
+    /// Returns the features which are never used with this profile.
+    pub fn disabled_features(self) -> &'static [Feature] {
+        match self {
+            Profile::LoopHeavy
+            | Profile::UniformityStress
+            | Profile::EarlyReturn
+            | Profile::SwizzleHeavy => &[],
+            Profile::ArithmeticOnly => &[
+                Feature::Ifs,
+                Feature::Loops,
+                Feature::Switches,
+                Feature::Bitcasts,
+            ],
+        }
+    }
+
+    /// Returns the maximum expression depth to use unless overridden by `--max-expr-depth`.
+    pub fn max_expr_depth(self) -> u32 {
+        match self {
+            Profile::LoopHeavy
+            | Profile::UniformityStress
+            | Profile::EarlyReturn
+            | Profile::SwizzleHeavy => 3,
+            Profile::ArithmeticOnly => 8,
+        }
+    }
+
+    /// Returns true if calls to the given builtin may be generated with this profile.
+    pub fn allows_builtin(self, builtin: BuiltinFn) -> bool {
+        use BuiltinFn::*;
+
+        match self {
+            Profile::LoopHeavy
+            | Profile::UniformityStress
+            | Profile::EarlyReturn
+            | Profile::SwizzleHeavy => true,
+            Profile::ArithmeticOnly => matches!(
+                builtin,
+                Abs | Ceil
+                    | Clamp
+                    | Dot
+                    | Floor
+                    | Fract
+                    | Max
+                    | Min
+                    | Round
+                    | Saturate
+                    | Sign
+                    | Step
+                    | Trunc
+            ),
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::iter;
 use ast::types::{DataType, MemoryViewType};
 use rand::prelude::IteratorRandom;
 use rand::Rng;
-use rpds::{HashTrieMap, Vector};
+use rpds::{HashTrieMap, HashTrieSet, Vector};
 
 use super::utils;
 
@@ -13,6 +13,8 @@ pub struct Scope {
     symbols: HashTrieMap<DataType, Vec<(String, DataType)>>,
     mutables: Vector<(String, DataType)>,
     references: Vector<(String, MemoryViewType)>,
+    // names whose values are known to be uniform across the workgroup
+    uniforms: HashTrieSet<String>,
 }
 
 impl Scope {
@@ -22,6 +24,7 @@ impl Scope {
             symbols: HashTrieMap::new(),
             mutables: Vector::new(),
             references: Vector::new(),
+            uniforms: HashTrieSet::new(),
         }
     }
 
@@ -37,6 +40,10 @@ impl Scope {
         self.symbols.get(ty).map(Vec::as_slice).unwrap_or(&[])
     }
 
+    pub fn mutables(&self) -> impl Iterator<Item = &(String, DataType)> {
+        self.mutables.iter()
+    }
+
     pub fn choose_mutable(&self, rng: &mut impl Rng) -> (&String, &DataType) {
         #[allow(clippy::map_identity)]
         self.mutables
@@ -68,7 +75,19 @@ impl Scope {
         self.mutables.push_back_mut((name, data_type));
     }
 
+    /// Records that the value of a symbol is uniform across the workgroup.
+    pub fn mark_uniform(&mut self, name: String) {
+        self.uniforms.insert_mut(name);
+    }
+
+    pub fn is_uniform(&self, name: &str) -> bool {
+        self.uniforms.contains(name)
+    }
+
     fn insert_symbol(&mut self, name: &str, ty: &DataType) {
+        // The symbol may shadow a uniform one with the same name.
+        self.uniforms.remove_mut(name);
+
         for key in iter::once(ty.clone()).chain(utils::accessible_types_of(ty)) {
             let symbols = if let Some(symbols) = self.symbols.get_mut(&key) {
                 symbols
@@ -3,22 +3,30 @@ use std::mem;
 
 use ast::types::{DataType, MemoryViewType, ScalarType};
 use ast::{
-    AssignmentLhs, AssignmentOp, AssignmentStatement, BinOp, BinOpExpr, Expr, ExprNode,
-    ForLoopHeader, ForLoopInit, ForLoopStatement, ForLoopUpdate, IfStatement, LetDeclStatement,
-    LhsExprNode, Lit, LoopStatement, ReturnStatement, Statement, StorageClass, SwitchCase,
-    SwitchStatement, UnOp, UnOpExpr, VarDeclStatement, VarExpr,
+    AssignmentLhs, AssignmentOp, AssignmentStatement, BinOp, BinOpExpr, ExprNode, ForLoopHeader,
+    ForLoopInit, ForLoopStatement, ForLoopUpdate, IfStatement, LetDeclStatement, LhsExprNode, Lit,
+    LoopStatement, ReturnStatement, Statement, StorageClass, SwitchCase, SwitchStatement, UnOp,
+    UnOpExpr, VarDeclStatement, VarExpr,
 };
 use rand::prelude::SliceRandom;
 use rand::Rng;
 
+use crate::Profile;
+
+use super::features::Feature;
 use super::scope::Scope;
 use super::utils::is_terminal_stmt;
+use super::FnState;
 
 #[derive(Clone, Copy)]
 enum StatementType {
     LetDecl,
+    ConstDecl,
+    ConstAssert,
     VarDecl,
     Assignment,
+    PhonyAssignment,
+    DeadCode,
     // Compound,
     If,
     Return,
@@ -27,6 +35,63 @@ enum StatementType {
     ForLoop,
     Break,
     Continue,
+    Barrier,
+    WorkgroupStore,
+    SwizzleAssignment,
+}
+
+fn base_weight(t: StatementType) -> u32 {
+    match t {
+        StatementType::LetDecl => 10,
+        StatementType::ConstDecl => 3,
+        StatementType::ConstAssert => 1,
+        StatementType::VarDecl => 10,
+        StatementType::Assignment => 10,
+        StatementType::PhonyAssignment => 3,
+        StatementType::DeadCode => 3,
+        // StatementType::Compound => 1,
+        StatementType::If => 5,
+        StatementType::Return => 1,
+        StatementType::Loop => 5,
+        StatementType::Switch => 5,
+        StatementType::ForLoop => 5,
+        StatementType::Break => 5,
+        StatementType::Continue => 5,
+        StatementType::Barrier => 2,
+        StatementType::WorkgroupStore => 3,
+        StatementType::SwizzleAssignment => 10,
+    }
+}
+
+/// Returns how much more likely a statement type is to be generated with the given profile.
+fn profile_multiplier(profile: Option<Profile>, t: StatementType, state: &FnState) -> u32 {
+    match (profile, t) {
+        (
+            Some(Profile::LoopHeavy),
+            StatementType::Loop
+            | StatementType::ForLoop
+            | StatementType::Break
+            | StatementType::Continue,
+        ) => 4,
+        (
+            Some(Profile::UniformityStress),
+            StatementType::If
+            | StatementType::Switch
+            | StatementType::Return
+            | StatementType::Break
+            | StatementType::Continue,
+        ) => 4,
+        (
+            Some(Profile::EarlyReturn),
+            StatementType::If | StatementType::Loop | StatementType::ForLoop,
+        ) => 4,
+        // Returns at the top level of a function would end it early, so only nested returns are
+        // generated.
+        (Some(Profile::EarlyReturn), StatementType::Return) if state.is_loop => 16,
+        (Some(Profile::EarlyReturn), StatementType::Return) if state.block_depth > 1 => 8,
+        (Some(Profile::EarlyReturn), StatementType::Return) => 0,
+        _ => 1,
+    }
 }
 
 impl super::Generator<'_> {
@@ -34,9 +99,14 @@ impl super::Generator<'_> {
         let mut allowed = vec![
             StatementType::LetDecl,
             StatementType::VarDecl,
+            StatementType::PhonyAssignment,
             StatementType::Return,
         ];
 
+        if self.features.contains(Feature::Consts) {
+            allowed.push(StatementType::ConstDecl);
+        }
+
         if self.fn_state.is_loop {
             allowed.push(StatementType::Break);
             allowed.push(StatementType::Continue);
@@ -46,34 +116,50 @@ impl super::Generator<'_> {
             allowed.push(StatementType::Assignment);
         }
 
+        if self.can_gen_const_assert_stmt() {
+            allowed.push(StatementType::ConstAssert);
+        }
+
+        if self.can_gen_swizzle_assignment_stmt() {
+            allowed.push(StatementType::SwizzleAssignment);
+        }
+
+        if self.can_gen_barrier_stmt() {
+            allowed.extend_from_slice(&[StatementType::Barrier, StatementType::WorkgroupStore]);
+        }
+
         if self.fn_state.block_depth < self.options.max_block_depth {
-            allowed.extend_from_slice(&[
-                // StatementType::Compound,
-                StatementType::If,
-                StatementType::Loop,
-                StatementType::Switch,
-                StatementType::ForLoop,
-            ]);
+            // allowed.push(StatementType::Compound);
+
+            if self.features.contains(Feature::Ifs) {
+                allowed.push(StatementType::If);
+            }
+
+            if self.features.contains(Feature::Loops) {
+                allowed.extend_from_slice(&[StatementType::Loop, StatementType::ForLoop]);
+            }
+
+            if self.features.contains(Feature::Switches) {
+                allowed.push(StatementType::Switch);
+            }
+
+            if self.can_gen_dead_code_stmt() {
+                allowed.push(StatementType::DeadCode);
+            }
         }
 
-        let weights = |t: &StatementType| match t {
-            StatementType::LetDecl => 10,
-            StatementType::VarDecl => 10,
-            StatementType::Assignment => 10,
-            // StatementType::Compound => 1,
-            StatementType::If => 5,
-            StatementType::Return => 1,
-            StatementType::Loop => 5,
-            StatementType::Switch => 5,
-            StatementType::ForLoop => 5,
-            StatementType::Break => 5,
-            StatementType::Continue => 5,
-        };
+        let profile = self.options.profile;
+        let state = &self.fn_state;
+        let weights = |t: &StatementType| base_weight(*t) * profile_multiplier(profile, *t, state);
 
         match allowed.choose_weighted(self.rng, weights).unwrap() {
             StatementType::LetDecl => self.gen_let_stmt(),
+            StatementType::ConstDecl => self.gen_const_stmt(),
+            StatementType::ConstAssert => self.gen_const_assert_stmt(),
             StatementType::VarDecl => self.gen_var_stmt(),
-            StatementType::Assignment => self.gen_assignment_stmt().into(),
+            StatementType::Assignment => self.gen_assignment_or_inc_dec_stmt(),
+            StatementType::PhonyAssignment => self.gen_phony_assignment_stmt(),
+            StatementType::DeadCode => self.gen_dead_code_stmt(),
             // StatementType::Compound => self.gen_compound_stmt(),
             StatementType::If => self.gen_if_stmt(),
             StatementType::Return => self.gen_return_stmt(),
@@ -82,6 +168,9 @@ impl super::Generator<'_> {
             StatementType::ForLoop => self.gen_for_stmt(),
             StatementType::Break => Statement::Break,
             StatementType::Continue => Statement::Continue,
+            StatementType::Barrier => self.gen_barrier_stmt(),
+            StatementType::WorkgroupStore => self.gen_workgroup_store_stmt(),
+            StatementType::SwizzleAssignment => self.gen_swizzle_assignment_stmt(),
         }
     }
 
@@ -102,11 +191,83 @@ impl super::Generator<'_> {
         VarDeclStatement::new(self.scope.next_name(), None, Some(self.gen_expr(&ty))).into()
     }
 
+    /// Generates a phony assignment (`_ = expr;`), which forces an expression to be evaluated even
+    /// though its result is discarded.
+    fn gen_phony_assignment_stmt(&mut self) -> Statement {
+        let ty = self.cx.types.select(self.rng);
+        AssignmentStatement::new(
+            AssignmentLhs::Phony,
+            AssignmentOp::Simple,
+            self.gen_expr(&ty),
+        )
+        .into()
+    }
+
+    /// Generates an assignment, or an increment/decrement if the chosen lhs is an integer.
+    fn gen_assignment_or_inc_dec_stmt(&mut self) -> Statement {
+        let lhs = self.gen_assignment_lhs();
+
+        if matches!(
+            lhs.data_type.dereference(),
+            DataType::Scalar(ScalarType::I32 | ScalarType::U32)
+        ) && self.rng.gen_bool(0.2)
+        {
+            return if self.rng.gen_bool(0.5) {
+                Statement::Increment(lhs)
+            } else {
+                Statement::Decrement(lhs)
+            };
+        }
+
+        self.gen_assignment_with_lhs(lhs).into()
+    }
+
     fn gen_assignment_stmt(&mut self) -> AssignmentStatement {
+        let lhs = self.gen_assignment_lhs();
+        self.gen_assignment_with_lhs(lhs)
+    }
+
+    pub fn gen_assignment_with_lhs(&mut self, lhs: LhsExprNode) -> AssignmentStatement {
+        let data_type = lhs.data_type.dereference().clone();
+        let op = self.gen_assignment_op(&data_type);
+
+        // The shift value must always be unsigned.
+        let rhs_type = match op {
+            AssignmentOp::LShift | AssignmentOp::RShift => data_type.map(ScalarType::U32),
+            _ => data_type,
+        };
+
+        let rhs = self.gen_expr(&rhs_type);
+
+        AssignmentStatement::new(lhs.into(), op, rhs)
+    }
+
+    fn gen_assignment_op(&mut self, data_type: &DataType) -> AssignmentOp {
+        use AssignmentOp::*;
+
+        let compound_ops: &[AssignmentOp] = match data_type {
+            DataType::Scalar(ty) | DataType::Vector(_, ty) => match ty {
+                ScalarType::Bool => &[And, Or],
+                ScalarType::I32 | ScalarType::U32 => &[
+                    Plus, Minus, Times, Divide, Mod, And, Or, Xor, LShift, RShift,
+                ],
+                ScalarType::F32 | ScalarType::F16 => &[Plus, Minus, Times, Divide],
+            },
+            _ => &[],
+        };
+
+        if compound_ops.is_empty() || self.rng.gen_bool(0.5) {
+            return Simple;
+        }
+
+        compound_ops.choose(self.rng).copied().unwrap()
+    }
+
+    fn gen_assignment_lhs(&mut self) -> LhsExprNode {
         let (name, data_type) = self.scope.choose_mutable(self.rng);
 
         let data_type = data_type.clone();
-        let lhs = match &data_type {
+        match &data_type {
             DataType::Vector(n, ty) if self.rng.gen_bool(0.7) => {
                 let accessor =
                     super::utils::gen_vector_accessor(self.rng, *n, &DataType::Scalar(*ty));
@@ -118,11 +279,7 @@ impl super::Generator<'_> {
                 self.gen_expr(&ScalarType::U32.into()),
             ),
             _ => LhsExprNode::name(name.clone(), data_type),
-        };
-
-        let rhs = self.gen_expr(lhs.data_type.dereference());
-
-        AssignmentStatement::new(lhs.into(), AssignmentOp::Simple, rhs)
+        }
     }
 
     // fn gen_compound_stmt(&mut self) -> Statement {
@@ -137,11 +294,11 @@ impl super::Generator<'_> {
             .rng
             .gen_range(self.options.block_min_stmts..=self.options.block_max_stmts);
 
-        IfStatement::new(
-            self.gen_expr(&DataType::Scalar(ScalarType::Bool)),
-            self.gen_stmt_block(max_count).1,
-        )
-        .into()
+        let condition = self.gen_expr(&DataType::Scalar(ScalarType::Bool));
+        let uniform = self.is_uniform_expr(&condition);
+        let body = self.with_control_flow(uniform, |this| this.gen_stmt_block(max_count).1);
+
+        IfStatement::new(condition, body).into()
     }
 
     fn gen_return_stmt(&mut self) -> Statement {
@@ -160,14 +317,20 @@ impl super::Generator<'_> {
             .gen_range(self.options.block_min_stmts..=self.options.block_max_stmts);
 
         let is_loop = mem::replace(&mut self.fn_state.is_loop, true);
-        let body = self.gen_stmt_block(max_count).1;
+        let body = self.with_control_flow(false, |this| this.gen_stmt_block(max_count).1);
         self.fn_state.is_loop = is_loop;
+        self.fn_state.diverged = true;
 
         LoopStatement::new(body).into()
     }
 
     fn gen_switch_stmt(&mut self) -> Statement {
         let selector = self.gen_expr(&DataType::Scalar(ScalarType::I32));
+        let uniform = self.is_uniform_expr(&selector);
+        self.with_control_flow(uniform, |this| this.gen_switch_stmt_with_selector(selector))
+    }
+
+    fn gen_switch_stmt_with_selector(&mut self, selector: ExprNode) -> Statement {
         let case_count: u32 = self.rng.gen_range(0..=4);
         let mut existing_cases = HashSet::new();
         let cases = (0..case_count)
@@ -176,13 +339,7 @@ impl super::Generator<'_> {
                     .rng
                     .gen_range(self.options.block_min_stmts..=self.options.block_max_stmts);
 
-                let value = loop {
-                    let value = self.gen_i32();
-                    if !existing_cases.contains(&value) {
-                        existing_cases.insert(value);
-                        break value;
-                    }
-                };
+                let selector = self.gen_case_selector(&mut existing_cases);
 
                 let body = self.gen_stmt_block(block_size).1;
 
@@ -191,13 +348,7 @@ impl super::Generator<'_> {
                 //     body.push(Statement::Fallthrough);
                 // }
 
-                SwitchCase {
-                    selector: ExprNode {
-                        data_type: DataType::Scalar(ScalarType::I32),
-                        expr: Expr::Lit(Lit::I32(value)),
-                    },
-                    body,
-                }
+                SwitchCase { selector, body }
             })
             .collect();
 
@@ -209,111 +360,124 @@ impl super::Generator<'_> {
     }
 
     fn gen_for_stmt(&mut self) -> Statement {
-        let (_, stmt) = self.with_scope(self.scope.clone(), |this| {
-            let (init, condition, update) = if this.rng.gen_bool(0.8) {
-                let loop_var = this.scope.next_name();
-                let loop_var_type = DataType::Scalar(ScalarType::I32);
-
-                let init_value = if this.rng.gen_bool(0.7) {
-                    Some(Lit::I32(this.gen_i32()).into())
-                } else if this.rng.gen_bool(0.5) {
-                    Some(this.gen_expr(&loop_var_type))
-                } else {
-                    None
-                };
-
-                // Specify the type explicitly if we didn't generate an initializer (otherwise let it be
-                // inferred).
-                let init_type = if init_value.is_none() {
-                    Some(loop_var_type.clone())
-                } else {
-                    None
-                };
-
-                let init = ForLoopInit::VarDecl(VarDeclStatement::new(
-                    loop_var.clone(),
-                    init_type,
-                    init_value,
-                ));
-
-                this.scope.insert_mutable(
-                    loop_var.clone(),
-                    DataType::Ref(MemoryViewType::new(
-                        loop_var_type.clone(),
-                        StorageClass::Function,
-                    )),
-                );
-
-                const COMPARISON_OPS: &[BinOp] = &[
-                    BinOp::Less,
-                    BinOp::LessEqual,
-                    BinOp::Greater,
-                    BinOp::GreaterEqual,
-                    BinOp::Equal,
-                    BinOp::NotEqual,
-                ];
-
-                let condition = match this.rng.gen_range(0..=9) {
-                    0..=1 => None,
-                    2..=5 => Some(this.gen_expr(&DataType::Scalar(ScalarType::Bool))),
-                    6..=9 => Some(
-                        BinOpExpr::new(
-                            *COMPARISON_OPS.choose(this.rng).unwrap(),
-                            VarExpr::new(loop_var.clone()).into_node(loop_var_type.clone()),
-                            Lit::I32(this.gen_i32()),
-                        )
-                        .into(),
-                    ),
-                    _ => unreachable!(),
-                };
+        // The condition and update are evaluated in every iteration, so they are in the same
+        // control flow as the body.
+        let (_, stmt) = self.with_control_flow(false, |this| {
+            this.with_scope(this.scope.clone(), |this| {
+                let (init, condition, update) = if this.rng.gen_bool(0.8) {
+                    let loop_var = this.scope.next_name();
+                    let loop_var_type = DataType::Scalar(ScalarType::I32);
+
+                    let init_value = if this.rng.gen_bool(0.7) {
+                        Some(Lit::I32(this.gen_i32()).into())
+                    } else if this.rng.gen_bool(0.5) {
+                        Some(this.gen_expr(&loop_var_type))
+                    } else {
+                        None
+                    };
 
-                let update = if this.rng.gen_bool(0.8) {
-                    let assignment_op = if this.rng.gen_bool(0.5) {
-                        AssignmentOp::Plus
+                    // Specify the type explicitly if we didn't generate an initializer (otherwise let it be
+                    // inferred).
+                    let init_type = if init_value.is_none() {
+                        Some(loop_var_type.clone())
                     } else {
-                        AssignmentOp::Minus
+                        None
                     };
 
-                    let lhs = AssignmentLhs::name(loop_var, loop_var_type.clone());
-                    let stmt = if this.rng.gen_bool(0.7) {
-                        AssignmentStatement::new(lhs, assignment_op, Lit::I32(1))
+                    let init = ForLoopInit::VarDecl(VarDeclStatement::new(
+                        loop_var.clone(),
+                        init_type,
+                        init_value,
+                    ));
+
+                    this.scope.insert_mutable(
+                        loop_var.clone(),
+                        DataType::Ref(MemoryViewType::new(
+                            loop_var_type.clone(),
+                            StorageClass::Function,
+                        )),
+                    );
+
+                    const COMPARISON_OPS: &[BinOp] = &[
+                        BinOp::Less,
+                        BinOp::LessEqual,
+                        BinOp::Greater,
+                        BinOp::GreaterEqual,
+                        BinOp::Equal,
+                        BinOp::NotEqual,
+                    ];
+
+                    let condition = match this.rng.gen_range(0..=9) {
+                        0..=1 => None,
+                        2..=5 => Some(this.gen_expr(&DataType::Scalar(ScalarType::Bool))),
+                        6..=9 => Some(
+                            BinOpExpr::new(
+                                *COMPARISON_OPS.choose(this.rng).unwrap(),
+                                VarExpr::new(loop_var.clone()).into_node(loop_var_type.clone()),
+                                Lit::I32(this.gen_i32()),
+                            )
+                            .into(),
+                        ),
+                        _ => unreachable!(),
+                    };
+
+                    let update = if this.rng.gen_bool(0.8) {
+                        let lhs = LhsExprNode::name(loop_var, loop_var_type.clone());
+                        let increment = this.rng.gen_bool(0.5);
+
+                        Some(match this.rng.gen_range(0..10) {
+                            0..=3 if increment => ForLoopUpdate::Increment(lhs),
+                            0..=3 => ForLoopUpdate::Decrement(lhs),
+                            4..=6 => {
+                                let assignment_op = if increment {
+                                    AssignmentOp::Plus
+                                } else {
+                                    AssignmentOp::Minus
+                                };
+
+                                ForLoopUpdate::Assignment(AssignmentStatement::new(
+                                    lhs.into(),
+                                    assignment_op,
+                                    Lit::I32(1),
+                                ))
+                            }
+                            _ => ForLoopUpdate::Assignment(this.gen_assignment_stmt()),
+                        })
                     } else {
-                        this.gen_assignment_stmt()
+                        None
                     };
 
-                    Some(ForLoopUpdate::Assignment(stmt))
+                    (Some(init), condition, update)
                 } else {
-                    None
-                };
+                    let condition = if this.rng.gen_bool(0.5) {
+                        Some(this.gen_expr(&DataType::Scalar(ScalarType::Bool)))
+                    } else {
+                        None
+                    };
 
-                (Some(init), condition, update)
-            } else {
-                let condition = if this.rng.gen_bool(0.5) {
-                    Some(this.gen_expr(&DataType::Scalar(ScalarType::Bool)))
-                } else {
-                    None
+                    (None, condition, None)
                 };
 
-                (None, condition, None)
-            };
-
-            let body_size = this
-                .rng
-                .gen_range(this.options.block_min_stmts..=this.options.block_max_stmts);
+                let body_size = this
+                    .rng
+                    .gen_range(this.options.block_min_stmts..=this.options.block_max_stmts);
 
-            let header = ForLoopHeader {
-                init,
-                condition,
-                update,
-            };
+                let header = ForLoopHeader {
+                    init,
+                    condition,
+                    update,
+                };
 
-            let is_loop = mem::replace(&mut this.fn_state.is_loop, true);
-            let body = this.gen_stmt_block(body_size).1;
-            this.fn_state.is_loop = is_loop;
+                let is_loop = mem::replace(&mut this.fn_state.is_loop, true);
+                let body = this.gen_stmt_block(body_size).1;
+                this.fn_state.is_loop = is_loop;
 
-            ForLoopStatement::new(header, body)
+                ForLoopStatement::new(header, body)
+            })
         });
 
+        self.fn_state.diverged = true;
+
         stmt.into()
     }
 
@@ -324,18 +488,37 @@ impl super::Generator<'_> {
             let prev_block = std::mem::take(&mut this.current_block);
 
             for _ in 0..max_count {
+                if this.stmt_budget == 0 {
+                    break;
+                }
+
+                this.stmt_budget -= 1;
+
                 let stmt = this.gen_stmt();
 
                 // If we generated a variable declaration, track it in the environment
                 if let Statement::LetDecl(stmt) = &stmt {
                     this.scope
                         .insert_readonly(stmt.ident.clone(), stmt.initializer.data_type.clone());
+
+                    if this.is_uniform_control_flow() && this.is_uniform_expr(&stmt.initializer) {
+                        this.scope.mark_uniform(stmt.ident.clone());
+                    }
+                } else if let Statement::ConstDecl(stmt) = &stmt {
+                    this.scope
+                        .insert_readonly(stmt.ident.clone(), stmt.initializer.data_type.clone());
                 } else if let Statement::VarDecl(stmt) = &stmt {
                     let mem_view =
                         MemoryViewType::new(stmt.inferred_type().clone(), StorageClass::Function);
                     let data_type = DataType::Ref(mem_view);
                     this.scope.insert_mutable(stmt.ident.clone(), data_type);
                 } else if is_terminal_stmt(&stmt) {
+                    // Invocations which leave in non-uniform control flow skip the rest of the
+                    // function or loop, which is then non-uniform.
+                    if !this.is_uniform_control_flow() {
+                        this.fn_state.diverged = true;
+                    }
+
                     // Return/break/continue/fallthrough must be the last statement in the block
                     this.current_block.push(stmt);
                     break;
@@ -2,11 +2,13 @@ use std::rc::Rc;
 
 use ast::types::DataType;
 use ast::{StructDecl, StructMember, StructMemberAttr};
+use rand::prelude::SliceRandom;
 use rand::Rng;
 
 use super::cx::SelectionFilter;
+use super::features::Feature;
 
-const FIELD_NAMES: &[&str] = &["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
+pub const FIELD_NAMES: &[&str] = &["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum StructKind {
@@ -15,9 +17,41 @@ pub enum StructKind {
     UniformBuffer,
 }
 
+impl StructKind {
+    pub fn filter(self) -> SelectionFilter {
+        match self {
+            StructKind::Default => SelectionFilter::Any,
+            StructKind::HostShareable => SelectionFilter::HostShareable,
+            StructKind::UniformBuffer => SelectionFilter::Uniform,
+        }
+    }
+}
+
 impl super::Generator<'_> {
-    pub fn gen_struct(&mut self, name: String) -> Rc<StructDecl> {
-        self.gen_struct_with(name, StructKind::Default)
+    /// Generates a struct of a random kind and makes it available for use in other types.
+    pub fn gen_struct(&mut self, name: String) {
+        let kind = [
+            StructKind::Default,
+            StructKind::HostShareable,
+            StructKind::UniformBuffer,
+        ]
+        .choose(self.rng)
+        .copied()
+        .unwrap();
+
+        let decl = self.gen_struct_with(name, kind);
+
+        // Struct members in the uniform address space must be aligned to 16 bytes. Since `@align`
+        // attributes aren't taken into account by naga when computing struct alignment, we only
+        // nest structs in uniform buffers if they are already aligned to 16 bytes.
+        let filter = match kind {
+            StructKind::UniformBuffer if !struct_alignment(&decl).is_multiple_of(16) => {
+                SelectionFilter::HostShareable
+            }
+            kind => kind.filter(),
+        };
+
+        self.cx.types.insert(decl, filter);
     }
 
     pub fn gen_struct_with(&mut self, name: String, kind: StructKind) -> Rc<StructDecl> {
@@ -25,33 +59,97 @@ impl super::Generator<'_> {
             .rng
             .gen_range(self.options.min_struct_members..=self.options.max_struct_members);
 
-        let filter = match kind {
-            StructKind::Default => SelectionFilter::Any,
-            StructKind::HostShareable => SelectionFilter::HostShareable,
-            StructKind::UniformBuffer => SelectionFilter::Uniform,
+        let types = (0..member_count)
+            .map(|_| {
+                let data_type = self.cx.types.select_with_filter(self.rng, kind.filter());
+
+                // Arrays in the uniform address space must have a stride which is a multiple of
+                // 16, so we only generate them in other structs.
+                if kind != StructKind::UniformBuffer
+                    && self.features.contains(Feature::Arrays)
+                    && self.rng.gen_bool(0.2)
+                {
+                    DataType::array(data_type, self.rng.gen_range(1..=4))
+                } else {
+                    data_type
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Naga doesn't take `@align` attributes into account when computing the alignment of the
+        // struct itself, so we make sure that they never increase it.
+        let max_align = match kind {
+            StructKind::HostShareable => types
+                .iter()
+                .map(|it| common::Type::try_from(it).unwrap().alignment())
+                .max()
+                .unwrap_or(0),
+            _ => 0,
         };
 
-        let mut members = (0..member_count)
-            .map(|i| {
-                StructMember::new(
-                    vec![],
-                    FIELD_NAMES[i as usize].to_owned(),
-                    self.cx.types.select_with_filter(self.rng, filter),
-                )
+        let members = types
+            .into_iter()
+            .enumerate()
+            .map(|(i, data_type)| {
+                let attrs = match kind {
+                    StructKind::Default => vec![],
+                    StructKind::HostShareable => self.gen_layout_attrs(&data_type, max_align),
+                    StructKind::UniformBuffer => uniform_layout_attrs(&data_type),
+                };
+
+                StructMember::new(attrs, FIELD_NAMES[i].to_owned(), data_type)
             })
             .collect::<Vec<_>>();
 
-        if matches!(kind, StructKind::HostShareable | StructKind::UniformBuffer) {
-            for member in &mut members {
-                if let DataType::Struct(_) = member.data_type {
-                    Rc::get_mut(member)
-                        .unwrap()
-                        .attrs
-                        .push(StructMemberAttr::Align(16));
-                }
-            }
+        StructDecl::new(name, members)
+    }
+
+    /// Generates random `@align` and `@size` attributes for a member of a host-shareable struct.
+    fn gen_layout_attrs(&mut self, data_type: &DataType, max_align: u32) -> Vec<StructMemberAttr> {
+        let layout = common::Type::try_from(data_type).unwrap();
+        let mut attrs = vec![];
+
+        if self.rng.gen_bool(0.25) {
+            // Any power of two which is at least the natural alignment is valid.
+            let align = [1, 2, 4]
+                .into_iter()
+                .map(|n| layout.alignment() * n)
+                .filter(|it| *it <= max_align)
+                .filter_map(|it| u8::try_from(it).ok())
+                .collect::<Vec<_>>();
+            attrs.push(StructMemberAttr::Align(*align.choose(self.rng).unwrap()));
         }
 
-        StructDecl::new(name, members)
+        if self.rng.gen_bool(0.25) {
+            let padding = 4 * self.rng.gen_range(1..=4);
+            attrs.push(StructMemberAttr::Size(layout.size() + padding));
+        }
+
+        attrs
+    }
+}
+
+/// Returns the attributes needed for a member of a struct in the uniform address space.
+///
+/// Struct members must be aligned to 16 bytes, and must be followed by at least
+/// `roundUp(16, SizeOf(S))` bytes before the next member.
+fn uniform_layout_attrs(data_type: &DataType) -> Vec<StructMemberAttr> {
+    if !matches!(data_type, DataType::Struct(_)) {
+        return vec![];
+    }
+
+    let size = common::Type::try_from(data_type).unwrap().size();
+    let mut attrs = vec![StructMemberAttr::Align(16)];
+
+    if !size.is_multiple_of(16) {
+        attrs.push(StructMemberAttr::Size(size.next_multiple_of(16)));
     }
+
+    attrs
+}
+
+fn struct_alignment(decl: &Rc<StructDecl>) -> u32 {
+    common::Type::try_from(&DataType::Struct(decl.clone()))
+        .unwrap()
+        .alignment()
 }
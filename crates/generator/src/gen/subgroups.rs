@@ -0,0 +1,100 @@
+use ast::types::{DataType, ScalarType};
+use ast::{BinOp, BinOpExpr, BuiltinFn, ExprNode, FnCallExpr, Lit, TypeConsExpr};
+use rand::prelude::SliceRandom;
+
+#[derive(Clone, Copy, Debug)]
+enum SubgroupPattern {
+    Reduction,
+    Broadcast,
+    BroadcastFirst,
+    Ballot,
+}
+
+impl super::Generator<'_> {
+    /// Returns true if subgroups are enabled and a subgroup expression can be generated for the
+    /// given type.
+    pub fn can_gen_subgroup_expr(&self, ty: &DataType) -> bool {
+        self.options.enable_subgroups
+            && matches!(
+                ty,
+                DataType::Scalar(_)
+                    | DataType::Vector(_, ScalarType::I32 | ScalarType::U32 | ScalarType::F32)
+            )
+    }
+
+    /// Generates an expression using subgroup builtins.
+    ///
+    /// Only reductions that are independent of the order in which invocations are combined are
+    /// generated (e.g. no float sums or prefix scans). Broadcasts and ballots are only applied to
+    /// the result of a reduction, which is uniform across the subgroup, so their results don't
+    /// depend on which invocation is selected or on the subgroup size.
+    pub fn gen_subgroup_expr(&mut self, ty: &DataType) -> ExprNode {
+        // Broadcasts are only defined for numeric types.
+        let allowed: &[SubgroupPattern] = match ty {
+            DataType::Scalar(ScalarType::Bool) => {
+                &[SubgroupPattern::Reduction, SubgroupPattern::Ballot]
+            }
+            _ => &[
+                SubgroupPattern::Reduction,
+                SubgroupPattern::Broadcast,
+                SubgroupPattern::BroadcastFirst,
+            ],
+        };
+
+        self.fn_state.expression_depth += 1;
+
+        let expr = match allowed.choose(&mut self.rng).copied().unwrap() {
+            SubgroupPattern::Reduction => self.gen_subgroup_reduction(ty),
+            SubgroupPattern::Broadcast => {
+                let value = self.gen_subgroup_reduction(ty);
+                let id = ExprNode::from(Lit::U32(0));
+                FnCallExpr::new(BuiltinFn::SubgroupBroadcast.as_ref(), vec![value, id])
+                    .into_node(ty.clone())
+            }
+            SubgroupPattern::BroadcastFirst => {
+                let value = self.gen_subgroup_reduction(ty);
+                FnCallExpr::new(BuiltinFn::SubgroupBroadcastFirst.as_ref(), vec![value])
+                    .into_node(ty.clone())
+            }
+            SubgroupPattern::Ballot => {
+                // any(subgroupBallot(<uniform bool>) != vec4<u32>(0u))
+                let ballot_ty = DataType::Vector(4, ScalarType::U32);
+                let predicate = self.gen_subgroup_reduction(ty);
+                let ballot = FnCallExpr::new(BuiltinFn::SubgroupBallot.as_ref(), vec![predicate])
+                    .into_node(ballot_ty.clone());
+                let zero = TypeConsExpr::new(ballot_ty, vec![Lit::U32(0).into()]);
+                let cmp = BinOpExpr::new(BinOp::NotEqual, ballot, zero).into();
+                FnCallExpr::new(BuiltinFn::Any.as_ref(), vec![cmp]).into_node(ty.clone())
+            }
+        };
+
+        self.fn_state.expression_depth -= 1;
+
+        expr
+    }
+
+    fn gen_subgroup_reduction(&mut self, ty: &DataType) -> ExprNode {
+        use BuiltinFn::*;
+
+        let allowed: &[BuiltinFn] = match ty.as_scalar().unwrap() {
+            ScalarType::Bool => &[SubgroupAll, SubgroupAny],
+            ScalarType::I32 | ScalarType::U32 => &[
+                SubgroupAdd,
+                SubgroupMul,
+                SubgroupMin,
+                SubgroupMax,
+                SubgroupAnd,
+                SubgroupOr,
+                SubgroupXor,
+            ],
+            // Float addition and multiplication are not associative, so the result would depend
+            // on the reduction order.
+            ScalarType::F32 | ScalarType::F16 => &[SubgroupMin, SubgroupMax],
+        };
+
+        let builtin = allowed.choose(&mut self.rng).copied().unwrap();
+        let arg = self.gen_expr(ty);
+
+        FnCallExpr::new(builtin.as_ref(), vec![arg]).into_node(ty.clone())
+    }
+}
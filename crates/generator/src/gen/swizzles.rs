@@ -0,0 +1,147 @@
+use ast::types::DataType;
+use ast::{
+    AssignmentLhs, AssignmentOp, AssignmentStatement, ExprNode, LetDeclStatement, LhsExprNode,
+    Postfix, PostfixExpr, Statement, TypeConsExpr, VarExpr,
+};
+use rand::prelude::{IteratorRandom, SliceRandom};
+use rand::Rng;
+
+use crate::Profile;
+
+use super::features::Feature;
+
+const XYZW: [char; 4] = ['x', 'y', 'z', 'w'];
+const RGBA: [char; 4] = ['r', 'g', 'b', 'a'];
+
+impl super::Generator<'_> {
+    /// Returns true if swizzles are favoured, vectors are enabled and one can be generated for the
+    /// given type.
+    pub fn can_gen_swizzle_expr(&self, ty: &DataType) -> bool {
+        self.options.profile == Some(Profile::SwizzleHeavy)
+            && self.features.contains(Feature::Vectors)
+            && matches!(ty, DataType::Scalar(_) | DataType::Vector(_, _))
+    }
+
+    /// Generates a swizzle of an arbitrary vector expression, which may itself be a swizzle.
+    pub fn gen_swizzle_expr(&mut self, ty: &DataType) -> ExprNode {
+        let (n, scalar_ty) = match ty {
+            DataType::Scalar(t) => (1, *t),
+            DataType::Vector(n, t) => (*n, *t),
+            _ => unreachable!(),
+        };
+
+        let size = self.rng.gen_range(2..=4);
+
+        self.fn_state.expression_depth += 1;
+        let inner = self.gen_expr(&DataType::Vector(size, scalar_ty));
+        self.fn_state.expression_depth -= 1;
+
+        let components = self.gen_swizzle_components(size, n);
+        PostfixExpr::new(inner, Postfix::member(self.gen_swizzle(&components))).into()
+    }
+
+    /// Returns true if swizzles are favoured, vectors are enabled and a vector variable can be
+    /// assigned to.
+    pub fn can_gen_swizzle_assignment_stmt(&self) -> bool {
+        self.options.profile == Some(Profile::SwizzleHeavy)
+            && self.features.contains(Feature::Vectors)
+            && self
+                .scope
+                .mutables()
+                .any(|(_, it)| it.dereference().is_vector())
+    }
+
+    /// Generates an assignment to a single component of a vector variable (e.g. `v.y = value`),
+    /// or to a subvector (e.g. `v.zx = value`).
+    ///
+    /// WGSL doesn't allow assigning to a swizzle with multiple components, so a subvector write is
+    /// written as an assignment to the whole vector, which reads the remaining components from
+    /// the vector itself, e.g. `v = vec3<f32>(value.y, v.y, value.x)`.
+    pub fn gen_swizzle_assignment_stmt(&mut self) -> Statement {
+        let (name, data_type) = self
+            .scope
+            .mutables()
+            .filter(|(_, it)| it.dereference().is_vector())
+            .choose(self.rng)
+            .cloned()
+            .unwrap();
+
+        let vector_ty = data_type.dereference().clone();
+        let DataType::Vector(size, scalar_ty) = vector_ty else {
+            unreachable!()
+        };
+
+        if self.rng.gen_bool(0.5) {
+            let component = self.gen_swizzle_components(size, 1);
+            let lhs = LhsExprNode::member(name, vector_ty, self.gen_swizzle(&component));
+            return self.gen_assignment_with_lhs(lhs).into();
+        }
+
+        // The written components are distinct, but may be in any order.
+        let count = self.rng.gen_range(2..=size);
+        let mut written = (0..size).choose_multiple(self.rng, count as usize);
+        written.shuffle(self.rng);
+
+        // The value is stored in a `let` so that it is only evaluated once.
+        let value = self.gen_expr(&DataType::Vector(count, scalar_ty));
+        let value_ident = self.scope.next_name();
+        self.current_block
+            .push(LetDeclStatement::new(value_ident.clone(), value.clone()).into());
+
+        let value = VarExpr::new(value_ident).into_node(value.data_type);
+        let var = VarExpr::new(&name).into_node(data_type);
+
+        // Consecutive components which are read from the same vector are grouped into a single
+        // swizzle, e.g. `vec4<f32>(value.xz, v.z, value.y)`.
+        let mut args: Vec<(ExprNode, Vec<u8>)> = vec![];
+        for i in 0..size {
+            let (source, component) = match written.iter().position(|it| *it == i) {
+                Some(j) => (&value, j as u8),
+                None => (&var, i),
+            };
+
+            match args.last_mut() {
+                Some((last, components)) if last == source => components.push(component),
+                _ => args.push((source.clone(), vec![component])),
+            }
+        }
+
+        let args = args
+            .into_iter()
+            .map(|(source, components)| {
+                let swizzle = self.gen_swizzle(&components);
+                PostfixExpr::new(source, Postfix::member(swizzle)).into()
+            })
+            .collect();
+
+        AssignmentStatement::new(
+            AssignmentLhs::name(name, vector_ty.clone()),
+            AssignmentOp::Simple,
+            TypeConsExpr::new(vector_ty, args),
+        )
+        .into()
+    }
+
+    /// Generates the indices of `n` components of a vector with `size` components, which repeat
+    /// more often than they would by chance.
+    fn gen_swizzle_components(&mut self, size: u8, n: u8) -> Vec<u8> {
+        let mut components = vec![];
+
+        for _ in 0..n {
+            let component = match components.choose(self.rng) {
+                Some(prev) if self.rng.gen_bool(0.3) => *prev,
+                _ => self.rng.gen_range(0..size),
+            };
+
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// Writes a swizzle with either the `xyzw` or `rgba` component names.
+    fn gen_swizzle(&mut self, components: &[u8]) -> String {
+        let names = if self.rng.gen_bool(0.5) { XYZW } else { RGBA };
+        components.iter().map(|it| names[*it as usize]).collect()
+    }
+}
@@ -0,0 +1,325 @@
+use ast::types::{DataType, ScalarType, TextureDimension};
+use ast::{
+    BuiltinFn, ExprNode, FnCallExpr, GlobalVarAttr, GlobalVarDecl, Lit, TypeConsExpr, VarExpr,
+};
+use rand::prelude::SliceRandom;
+use rand::Rng;
+
+use crate::RngVersion;
+
+use super::features::Feature;
+
+const SAMPLER: &str = "nearest_sampler";
+const COMPARISON_SAMPLER: &str = "comparison_sampler";
+
+/// A texture which is bound to the shader, along with the size that the harness creates it with.
+///
+/// Only textures which can be gathered from are given contents, since every other texture is only
+/// ever queried for its size.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    pub name: String,
+    pub dimension: TextureDimension,
+    /// The type of the sampled values, or `None` for a depth texture.
+    pub sampled_type: Option<ScalarType>,
+    pub binding: u32,
+    /// Width, height, and depth or number of array layers.
+    pub size: [u32; 3],
+    pub mip_level_count: u32,
+    /// Texels of the first mip level, tightly packed as `rgba8unorm` or `depth16unorm`.
+    pub data: Option<Vec<u8>>,
+}
+
+impl Texture {
+    fn data_type(&self) -> DataType {
+        match self.sampled_type {
+            Some(sampled_type) => DataType::Texture(self.dimension, sampled_type),
+            None => DataType::DepthTexture(self.dimension),
+        }
+    }
+
+    /// Returns true if `textureGather` (or `textureGatherCompare` for depth textures) can be
+    /// called on this texture.
+    ///
+    /// Only `f32` textures are gathered from, since the sampler binding that is derived from the
+    /// shader expects a filterable texture.
+    fn is_gatherable(&self) -> bool {
+        matches!(
+            self.dimension,
+            TextureDimension::D2 | TextureDimension::D2Array
+        ) && matches!(self.sampled_type, Some(ScalarType::F32) | None)
+    }
+
+    /// Returns the type of `textureDimensions` for this texture.
+    fn dimensions_type(&self) -> DataType {
+        match self.dimension.size_components() {
+            1 => ScalarType::U32.into(),
+            n => DataType::Vector(n, ScalarType::U32),
+        }
+    }
+
+    pub fn decl(&self) -> GlobalVarDecl {
+        GlobalVarDecl {
+            attrs: vec![
+                GlobalVarAttr::Group(0),
+                GlobalVarAttr::Binding(self.binding as i32),
+            ],
+            qualifier: None,
+            name: self.name.clone(),
+            data_type: self.data_type(),
+            initializer: None,
+        }
+    }
+}
+
+impl super::Generator<'_> {
+    /// Generates the textures which are bound to the shader, starting at the given binding.
+    pub fn gen_textures(&mut self, first_binding: u32) -> Vec<Texture> {
+        use TextureDimension::*;
+
+        let gathers_enabled = self.options.rng_version >= RngVersion::V6;
+
+        // Textures are kept smaller once they can be gathered from, since their contents are then
+        // recorded in the metadata.
+        let max_size = if gathers_enabled { 16 } else { 64 };
+
+        (0..self.rng.gen_range(1..=3))
+            .map(|i| {
+                let is_depth = gathers_enabled && self.rng.gen_bool(0.5);
+
+                let dimension = if is_depth {
+                    [D2, D2Array, Cube, CubeArray].as_slice()
+                } else {
+                    [D1, D2, D2Array, D3, Cube, CubeArray].as_slice()
+                }
+                .choose(&mut self.rng)
+                .copied()
+                .unwrap();
+
+                let sampled_type = if is_depth {
+                    None
+                } else {
+                    [ScalarType::F32, ScalarType::I32, ScalarType::U32]
+                        .choose(&mut self.rng)
+                        .copied()
+                };
+
+                let width: u32 = self.rng.gen_range(1..=max_size);
+                let height: u32 = self.rng.gen_range(1..=max_size);
+
+                let size = match dimension {
+                    D1 => [width, 1, 1],
+                    D2 => [width, height, 1],
+                    D2Array => [width, height, self.rng.gen_range(1..=4)],
+                    D3 => [width.min(16), height.min(16), self.rng.gen_range(1..=16)],
+                    // Cube faces must be square.
+                    Cube => [width, width, 6],
+                    CubeArray => [width, width, 6 * self.rng.gen_range(1..=2)],
+                };
+
+                // 1D textures can't have mipmaps, and the largest dimension (excluding array
+                // layers) bounds the number of levels otherwise.
+                let max_mip_level_count = match dimension {
+                    D1 => 1,
+                    D3 => size.iter().max().unwrap().ilog2() + 1,
+                    _ => size[0].max(size[1]).ilog2() + 1,
+                };
+
+                let mut texture = Texture {
+                    name: format!("texture_{i}"),
+                    dimension,
+                    sampled_type,
+                    binding: first_binding + i,
+                    size,
+                    mip_level_count: self.rng.gen_range(1..=max_mip_level_count),
+                    data: None,
+                };
+
+                if gathers_enabled && texture.is_gatherable() {
+                    texture.data = Some(self.gen_texture_data(&texture));
+                }
+
+                texture
+            })
+            .collect()
+    }
+
+    /// Generates the contents of the first mip level of a texture.
+    ///
+    /// Colour components are either 0 or 1, so that every texel is exactly representable, and depth
+    /// values are (close to) multiples of 1/4, so that comparisons against a depth reference are
+    /// never close.
+    fn gen_texture_data(&mut self, texture: &Texture) -> Vec<u8> {
+        let texel_count = texture.size.iter().product::<u32>();
+
+        match texture.sampled_type {
+            // rgba8unorm
+            Some(_) => (0..texel_count * 4)
+                .map(|_| if self.rng.gen_bool(0.5) { u8::MAX } else { 0 })
+                .collect(),
+            // depth16unorm
+            None => (0..texel_count)
+                .flat_map(|_| {
+                    let depth = (self.rng.gen_range(0..=4u32) * u16::MAX as u32 / 4) as u16;
+                    depth.to_le_bytes()
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the declarations of the samplers which are needed to gather from the textures,
+    /// starting at the given binding.
+    pub fn gen_sampler_decls(&self, first_binding: u32) -> Vec<GlobalVarDecl> {
+        let gathered = self.textures.iter().filter(|it| it.data.is_some());
+        let mut samplers = vec![];
+
+        if gathered.clone().any(|it| it.sampled_type.is_some()) {
+            samplers.push((SAMPLER, DataType::Sampler));
+        }
+
+        if gathered.clone().any(|it| it.sampled_type.is_none()) {
+            samplers.push((COMPARISON_SAMPLER, DataType::SamplerComparison));
+        }
+
+        samplers
+            .into_iter()
+            .zip(first_binding..)
+            .map(|((name, data_type), binding)| GlobalVarDecl {
+                attrs: vec![
+                    GlobalVarAttr::Group(0),
+                    GlobalVarAttr::Binding(binding as i32),
+                ],
+                qualifier: None,
+                name: name.to_owned(),
+                data_type,
+                initializer: None,
+            })
+            .collect()
+    }
+
+    /// Returns true if a query of a texture's size or number of mip levels or array layers can be
+    /// generated for the given type.
+    pub fn can_gen_texture_query(&self, ty: &DataType) -> bool {
+        self.features.contains(Feature::Builtins) && !self.texture_queries(ty).is_empty()
+    }
+
+    /// Generates a call to `textureDimensions`, `textureNumLayers` or `textureNumLevels`.
+    pub fn gen_texture_query(&mut self, ty: &DataType) -> ExprNode {
+        let (texture, builtin) = self
+            .texture_queries(ty)
+            .choose(&mut self.rng)
+            .cloned()
+            .unwrap();
+
+        let mut args = vec![VarExpr::new(&texture.name).into_node(texture.data_type())];
+
+        // The mip level is always in bounds, since the result is indeterminate otherwise.
+        if builtin == BuiltinFn::TextureDimensions
+            && texture.mip_level_count > 1
+            && self.rng.gen_bool(0.5)
+        {
+            args.push(self.gen_index_lit(texture.mip_level_count));
+        }
+
+        FnCallExpr::new(builtin.as_ref(), args).into_node(ty.clone())
+    }
+
+    /// Returns the queries which produce a value of the given type.
+    fn texture_queries(&self, ty: &DataType) -> Vec<(Texture, BuiltinFn)> {
+        let mut queries = vec![];
+
+        for texture in &self.textures {
+            if texture.dimensions_type() == *ty {
+                queries.push((texture.clone(), BuiltinFn::TextureDimensions));
+            }
+
+            if *ty == DataType::Scalar(ScalarType::U32) {
+                queries.push((texture.clone(), BuiltinFn::TextureNumLevels));
+
+                if texture.dimension.is_arrayed() {
+                    queries.push((texture.clone(), BuiltinFn::TextureNumLayers));
+                }
+            }
+        }
+
+        queries.retain(|(_, builtin)| self.options.allows_builtin(*builtin));
+        queries
+    }
+
+    /// Returns true if a call to `textureGather` or `textureGatherCompare` can be generated for
+    /// the given type.
+    pub fn can_gen_texture_gather(&self, ty: &DataType) -> bool {
+        self.features.contains(Feature::Builtins) && !self.texture_gathers(ty).is_empty()
+    }
+
+    /// Generates a call to `textureGather` or `textureGatherCompare`.
+    ///
+    /// The coordinates always fall on a corner between texels, so that every GPU gathers the same
+    /// 2x2 block of texels regardless of its precision, and the array index is always in bounds.
+    pub fn gen_texture_gather(&mut self, ty: &DataType) -> ExprNode {
+        let (texture, builtin) = self
+            .texture_gathers(ty)
+            .choose(&mut self.rng)
+            .cloned()
+            .unwrap();
+
+        let mut args = vec![];
+
+        if texture.sampled_type.is_some() {
+            args.push(self.gen_index_lit(4));
+        }
+
+        args.push(VarExpr::new(&texture.name).into_node(texture.data_type()));
+        args.push(match builtin {
+            BuiltinFn::TextureGather => VarExpr::new(SAMPLER).into_node(DataType::Sampler),
+            _ => VarExpr::new(COMPARISON_SAMPLER).into_node(DataType::SamplerComparison),
+        });
+
+        let [width, height, layers] = texture.size;
+        let coords = [width, height]
+            .map(|n| Lit::F32(self.rng.gen_range(0..=n) as f32 / n as f32).into())
+            .to_vec();
+
+        args.push(TypeConsExpr::new(DataType::Vector(2, ScalarType::F32), coords).into());
+
+        if texture.dimension.is_arrayed() {
+            args.push(self.gen_index_lit(layers));
+        }
+
+        // Depth values are close to multiples of 1/4, so the reference is halfway between two of
+        // them.
+        if builtin == BuiltinFn::TextureGatherCompare {
+            let depth_ref = [0.125, 0.375, 0.625, 0.875].choose(&mut self.rng).unwrap();
+            args.push(Lit::F32(*depth_ref).into());
+        }
+
+        FnCallExpr::new(builtin.as_ref(), args).into_node(ty.clone())
+    }
+
+    /// Returns the gathers which produce a value of the given type.
+    fn texture_gathers(&self, ty: &DataType) -> Vec<(Texture, BuiltinFn)> {
+        if *ty != DataType::Vector(4, ScalarType::F32) {
+            return vec![];
+        }
+
+        self.textures
+            .iter()
+            .filter(|texture| texture.data.is_some())
+            .map(|texture| match texture.sampled_type {
+                Some(_) => (texture.clone(), BuiltinFn::TextureGather),
+                None => (texture.clone(), BuiltinFn::TextureGatherCompare),
+            })
+            .filter(|(_, builtin)| self.options.allows_builtin(*builtin))
+            .collect()
+    }
+
+    /// Generates a `u32` or `i32` literal which is less than `n`.
+    fn gen_index_lit(&mut self, n: u32) -> ExprNode {
+        let index = self.rng.gen_range(0..n);
+        if self.rng.gen_bool(0.5) {
+            Lit::U32(index).into()
+        } else {
+            Lit::I32(index as i32).into()
+        }
+    }
+}
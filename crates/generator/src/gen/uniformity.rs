@@ -0,0 +1,170 @@
+use std::mem;
+use std::str::FromStr;
+
+use ast::types::{DataType, MemoryViewType};
+use ast::{
+    AssignmentLhs, AssignmentOp, AssignmentStatement, BuiltinFn, Expr, ExprNode, FnCallExpr,
+    FnCallStatement, GlobalVarDecl, Postfix, Statement, StorageClass, UnOp, UnOpExpr, VarExpr,
+    VarQualifier,
+};
+use rand::prelude::SliceRandom;
+use rand::Rng;
+
+impl super::Generator<'_> {
+    /// Generates the variables in the workgroup address space, which are read with
+    /// `workgroupUniformLoad`.
+    pub fn gen_workgroup_vars(&mut self) -> Vec<GlobalVarDecl> {
+        (0..self.rng.gen_range(1..=3))
+            .map(|i| {
+                let name = format!("workgroup{i}");
+                let data_type = self.cx.types.select(self.rng);
+
+                self.workgroup_vars.push((name.clone(), data_type.clone()));
+
+                GlobalVarDecl {
+                    attrs: vec![],
+                    qualifier: Some(VarQualifier {
+                        storage_class: StorageClass::WorkGroup,
+                        access_mode: None,
+                    }),
+                    name,
+                    data_type,
+                    initializer: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns true if control flow at the current point is uniform across the workgroup.
+    ///
+    /// This is a conservative approximation of the uniformity analysis of WGSL, which only ever
+    /// considers control flow in the entrypoint to be uniform. Loops are bounded by the
+    /// reconditioner with counters in the private address space, which the analysis treats as
+    /// non-uniform, so control flow is never uniform within or after a loop either.
+    pub fn is_uniform_control_flow(&self) -> bool {
+        self.fn_state.uniform_control_flow && !self.fn_state.diverged
+    }
+
+    /// Runs `f` with control flow which is only uniform if it is currently uniform and `uniform`
+    /// is true, e.g. in the body of an `if` statement with a (non-)uniform condition.
+    pub fn with_control_flow<T>(&mut self, uniform: bool, f: impl FnOnce(&mut Self) -> T) -> T {
+        let prev = self.fn_state.uniform_control_flow;
+        self.fn_state.uniform_control_flow = prev && uniform;
+        let res = f(self);
+        self.fn_state.uniform_control_flow = prev;
+        res
+    }
+
+    /// Returns true if the value of an expression is known to be uniform across the workgroup,
+    /// assuming that it is evaluated in uniform control flow.
+    pub fn is_uniform_expr(&self, node: &ExprNode) -> bool {
+        match &node.expr {
+            Expr::Lit(_) => true,
+            Expr::TypeCons(expr) => expr.args.iter().all(|it| self.is_uniform_expr(it)),
+            // Textures and samplers are always uniform, while mutable variables and pointers are
+            // not tracked at all.
+            Expr::Var(expr) => {
+                self.scope.is_uniform(&expr.ident)
+                    || self.const_values.contains_key(&expr.ident)
+                    || node.data_type.is_handle()
+            }
+            Expr::Postfix(expr) => {
+                self.is_uniform_expr(&expr.inner)
+                    && match &expr.postfix {
+                        Postfix::Index(index) => self.is_uniform_expr(index),
+                        Postfix::Member(_) => true,
+                    }
+            }
+            Expr::UnOp(expr) => {
+                !matches!(expr.op, UnOp::AddressOf | UnOp::Deref)
+                    && self.is_uniform_expr(&expr.inner)
+            }
+            Expr::BinOp(expr) => {
+                self.is_uniform_expr(&expr.left) && self.is_uniform_expr(&expr.right)
+            }
+            Expr::Bitcast(expr) => self.is_uniform_expr(&expr.inner),
+            // The results of user-defined functions are conservatively assumed to be non-uniform,
+            // as are the results of subgroup operations, which may differ between subgroups.
+            Expr::FnCall(expr) => match BuiltinFn::from_str(&expr.ident) {
+                Ok(BuiltinFn::WorkgroupUniformLoad) => true,
+                Ok(builtin) if !builtin.as_ref().starts_with("subgroup") => {
+                    expr.args.iter().all(|it| self.is_uniform_expr(it))
+                }
+                _ => false,
+            },
+        }
+    }
+
+    /// Returns true if barriers are enabled and can be generated at the current point.
+    pub fn can_gen_barrier_stmt(&self) -> bool {
+        self.options.enable_barriers && self.is_uniform_control_flow()
+    }
+
+    /// Generates a call to `workgroupBarrier` or `storageBarrier`.
+    pub fn gen_barrier_stmt(&mut self) -> Statement {
+        let builtin = [BuiltinFn::WorkgroupBarrier, BuiltinFn::StorageBarrier]
+            .choose(self.rng)
+            .copied()
+            .unwrap();
+
+        FnCallStatement::new(builtin.as_ref().to_owned(), vec![]).into()
+    }
+
+    /// Generates a store of a uniform value to a workgroup variable.
+    ///
+    /// Every invocation stores the same value, and the store is preceded by a barrier so that no
+    /// invocation can still be loading the previous value.
+    pub fn gen_workgroup_store_stmt(&mut self) -> Statement {
+        let (name, data_type) = self.workgroup_vars.choose(self.rng).cloned().unwrap();
+
+        self.current_block.push(
+            FnCallStatement::new(BuiltinFn::WorkgroupBarrier.as_ref().to_owned(), vec![]).into(),
+        );
+
+        // Loads of workgroup variables aren't generated within the value, since they would race
+        // with the store.
+        let uniform_control_flow = mem::replace(&mut self.fn_state.uniform_control_flow, false);
+        let mut value = self.gen_expr(&data_type);
+        self.fn_state.uniform_control_flow = uniform_control_flow;
+
+        if !self.is_uniform_expr(&value) {
+            value = self.gen_const_expr(&data_type);
+        }
+
+        AssignmentStatement::new(
+            AssignmentLhs::name(name, data_type),
+            AssignmentOp::Simple,
+            value,
+        )
+        .into()
+    }
+
+    /// Returns true if a call to `workgroupUniformLoad` can be generated for the given type.
+    pub fn can_gen_workgroup_uniform_load(&self, ty: &DataType) -> bool {
+        self.options.enable_barriers
+            && self.is_uniform_control_flow()
+            && self.workgroup_vars.iter().any(|(_, it)| it == ty)
+    }
+
+    /// Generates a call to `workgroupUniformLoad`, whose result is uniform.
+    pub fn gen_workgroup_uniform_load(&mut self, ty: &DataType) -> ExprNode {
+        let name = self
+            .workgroup_vars
+            .iter()
+            .filter(|(_, it)| it == ty)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>()
+            .choose(self.rng)
+            .cloned()
+            .unwrap();
+
+        let var = VarExpr::new(name).into_node(DataType::Ref(MemoryViewType::new(
+            ty.clone(),
+            StorageClass::WorkGroup,
+        )));
+
+        let ptr = UnOpExpr::new(UnOp::AddressOf, var).into();
+
+        FnCallExpr::new(BuiltinFn::WorkgroupUniformLoad.as_ref(), vec![ptr]).into_node(ty.clone())
+    }
+}
@@ -47,6 +47,11 @@ pub fn accessible_types_of(ty: &DataType) -> Vec<DataType> {
         DataType::Array(ty, _) => vec![(**ty).clone()],
         DataType::Struct(decl) => decl.accessible_types().cloned().collect(),
         DataType::Ptr(view) | DataType::Ref(view) => accessible_types_of(&view.inner),
+        DataType::Texture(_, _)
+        | DataType::DepthTexture(_)
+        | DataType::Sampler
+        | DataType::SamplerComparison
+        | DataType::Atomic(_) => vec![],
     }
 }
 
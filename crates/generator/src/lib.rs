@@ -1,20 +1,22 @@
+mod abstract_literals;
 mod gen;
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{self, BufWriter};
 use std::path::Path;
 use std::rc::Rc;
 use std::str::FromStr;
 
-use ast::{BuiltinFn, StorageClass, VarQualifier};
+use ast::{BuiltinFn, Module, StorageClass, VarQualifier};
 use clap::Parser;
 use eyre::{bail, eyre};
+use reflection::Requirements;
 
-pub use gen::{builtins, Generator};
-use rand::prelude::StdRng;
+pub use gen::{builtins, Generator, OracleModule};
 use rand::rngs::OsRng;
 use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
 use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::EnvFilter;
 
@@ -35,24 +37,192 @@ impl FromStr for Preset {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Favours loops, along with `break` and `continue` statements.
+    LoopHeavy,
+    /// Favours deeply nested control flow with early exits, for stressing uniformity analysis.
+    UniformityStress,
+    /// Generates straight-line code with deeply nested arithmetic expressions.
+    ArithmeticOnly,
+    /// Favours functions with multiple conditional `return` statements nested inside loops.
+    EarlyReturn,
+    /// Favours vector swizzles, including repeated components, and writes to vector components.
+    SwizzleHeavy,
+}
+
+impl FromStr for Profile {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "loop-heavy" => Ok(Profile::LoopHeavy),
+            "uniformity-stress" => Ok(Profile::UniformityStress),
+            "arithmetic-only" => Ok(Profile::ArithmeticOnly),
+            "early-return" => Ok(Profile::EarlyReturn),
+            "swizzle-heavy" => Ok(Profile::SwizzleHeavy),
+            _ => Err(
+                "invalid profile - must be one of {loop-heavy, uniformity-stress, \
+                arithmetic-only, early-return, swizzle-heavy}",
+            ),
+        }
+    }
+}
+
+/// Version of the generation algorithm.
+///
+/// A seed produces byte-identical shaders for a given version, across wgslsmith releases. Any
+/// change to the generator that would produce a different shader for an existing seed must be
+/// gated on a new version, with the previous behaviour kept for older versions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RngVersion {
+    V1,
+    /// Adds dynamic indexing of vectors and arrays with values from the input buffer.
+    V2,
+    /// Adds component-wise operations on boolean vectors, `all`/`any` and vector `select`s.
+    V3,
+    /// Adds helper functions which return a struct containing the value needed by the caller.
+    V4,
+    /// Gives each invocation of a multi-invocation shader its own element of the output buffer,
+    /// and makes the invocation IDs available to expressions.
+    V5,
+    /// Adds depth textures and samplers, which are read with `textureGather` and
+    /// `textureGatherCompare`.
+    V6,
+    /// Adds calls to `frexp` and `modf`, whose result struct members are accessed directly.
+    V7,
+    /// Initializes private module-scope variables with const-expressions which may name
+    /// module-scope constants.
+    V8,
+    /// Only generates the packed 8-bit integer builtins (e.g. `pack4xI8` and `unpack4xU8`) with
+    /// `--enable-packed-dot-products`, since they need the same language extension as the dot
+    /// products.
+    V9,
+}
+
+impl RngVersion {
+    pub const LATEST: RngVersion = RngVersion::V9;
+
+    pub fn number(self) -> u32 {
+        match self {
+            RngVersion::V1 => 1,
+            RngVersion::V2 => 2,
+            RngVersion::V3 => 3,
+            RngVersion::V4 => 4,
+            RngVersion::V5 => 5,
+            RngVersion::V6 => 6,
+            RngVersion::V7 => 7,
+            RngVersion::V8 => 8,
+            RngVersion::V9 => 9,
+        }
+    }
+}
+
+impl FromStr for RngVersion {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" => Ok(RngVersion::V1),
+            "2" => Ok(RngVersion::V2),
+            "3" => Ok(RngVersion::V3),
+            "4" => Ok(RngVersion::V4),
+            "5" => Ok(RngVersion::V5),
+            "6" => Ok(RngVersion::V6),
+            "7" => Ok(RngVersion::V7),
+            "8" => Ok(RngVersion::V8),
+            "9" => Ok(RngVersion::V9),
+            "latest" => Ok(RngVersion::LATEST),
+            _ => Err("invalid rng version - must be one of {1, 2, 3, 4, 5, 6, 7, 8, 9, latest}"),
+        }
+    }
+}
+
 #[derive(Parser)]
 pub struct Options {
     /// Optional u64 to seed the random generator
     #[clap(action)]
     pub seed: Option<u64>,
 
+    /// Version of the generation algorithm to use, which allows shaders to be reproduced from
+    /// seeds that were recorded with older versions of wgslsmith
+    #[clap(long, action, default_value = "latest")]
+    pub rng_version: RngVersion,
+
     /// Print ast instead of WGSL code
     #[clap(short, long, action)]
     pub debug: bool,
 
-    /// Enable built-in functions that are disabled by default
-    #[clap(long = "enable-fn", action)]
+    /// Enable a built-in function that is disabled by default (e.g. `--enable-builtin ldexp`)
+    #[clap(long = "enable-builtin", alias = "enable-fn", action)]
     pub enabled_fns: Vec<BuiltinFn>,
 
+    /// Disable a built-in function (e.g. `--disable-builtin extractBits`)
+    #[clap(long = "disable-builtin", action)]
+    pub disabled_fns: Vec<BuiltinFn>,
+
     /// Whether to enable generating pointers.
     #[clap(long, action)]
     pub enable_pointers: bool,
 
+    /// Whether to enable generating subgroup operations.
+    ///
+    /// This adds `enable subgroups;` to the shader, which requires an adapter with subgroup
+    /// support to execute.
+    #[clap(long, action)]
+    pub enable_subgroups: bool,
+
+    /// Whether to enable generating the packed 8-bit integer dot products `dot4I8Packed` and
+    /// `dot4U8Packed`, and the builtins that pack and unpack 8-bit integers (e.g. `pack4xI8`).
+    ///
+    /// This adds `requires packed_4x8_integer_dot_product;` to the shader, which requires an
+    /// implementation that supports the language extension to execute.
+    #[clap(long, action)]
+    pub enable_packed_dot_products: bool,
+
+    /// Whether to enable generating textures, whose sizes are queried with `textureDimensions`,
+    /// `textureNumLayers` and `textureNumLevels`, and samplers with which `textureGather` and
+    /// `textureGatherCompare` are called.
+    ///
+    /// The size and contents of each texture are recorded in the metadata comment at the top of
+    /// the shader.
+    #[clap(long, action)]
+    pub enable_textures: bool,
+
+    /// Whether to enable generating `workgroupBarrier`, `storageBarrier` and
+    /// `workgroupUniformLoad`.
+    ///
+    /// These are only generated where control flow is provably uniform, so that the shader passes
+    /// the uniformity analysis of WGSL.
+    #[clap(long, action)]
+    pub enable_barriers: bool,
+
+    /// Run the shader with multiple invocations, by choosing a random workgroup size and number of
+    /// workgroups to dispatch.
+    ///
+    /// The number of workgroups is recorded in the metadata comment at the top of the shader.
+    #[clap(long, action)]
+    pub multi_invocation: bool,
+
+    /// Enable a random subset of language features for each shader (swarm testing).
+    ///
+    /// The subset is chosen based on the seed, so shaders can still be reproduced.
+    #[clap(long, action)]
+    pub swarm: bool,
+
+    /// Inject unreachable blocks of code, guarded by conditions which are always false but are
+    /// computed from the input buffer so that they can't easily be evaluated by compilers.
+    #[clap(long, action)]
+    pub dead_code: bool,
+
+    /// Generate a shader whose expected output is computed during generation.
+    ///
+    /// The shader only contains integer expressions over values from the input buffer, which are
+    /// evaluated on the host. The expected contents of the output buffer are recorded in an
+    /// `// Expected output:` comment at the top of the shader, which is checked by the harness.
+    #[clap(long, action)]
+    pub oracle: bool,
+
     /// Skips the static pointer aliasing checks.
     ///
     /// This is only useful if reconditioning and pointer support is enabled.
@@ -83,10 +253,28 @@ pub struct Options {
     #[clap(long, action, default_value = "3")]
     pub max_block_depth: u32,
 
+    /// Maximum nested expression depth [default: 5, or depending on the profile]
+    #[clap(long, action)]
+    pub max_expr_depth: Option<u32>,
+
     /// Maximum number of function to generate
     #[clap(long, action, default_value = "5")]
     pub max_fns: u32,
 
+    /// Depth of a chain of nested function calls through which the output is computed
+    ///
+    /// Each function in the chain calls the next one with arguments derived from its own
+    /// parameters. The chain is in addition to the functions limited by `--max-fns`.
+    #[clap(long, action)]
+    pub max_call_depth: Option<u32>,
+
+    /// Maximum total number of statements to generate in the module, including nested statements
+    ///
+    /// This doesn't include statements which are needed for the module to be valid (e.g. returning
+    /// a value from a function).
+    #[clap(long, action)]
+    pub max_total_stmts: Option<u32>,
+
     /// Minimum number of structs to generate (excluding input and output)
     #[clap(long, action, default_value = "1")]
     pub min_structs: u32,
@@ -107,6 +295,22 @@ pub struct Options {
     #[clap(long, action)]
     pub preset: Option<Preset>,
 
+    /// Generation profile which adjusts the statement mix, expression depth and enabled builtins
+    /// to target specific compiler areas.
+    ///
+    /// Must be one of {loop-heavy, uniformity-stress, arithmetic-only, early-return,
+    /// swizzle-heavy}.
+    #[clap(long, action)]
+    pub profile: Option<Profile>,
+
+    /// Write literals as abstract (unsuffixed) literals wherever their type is determined by
+    /// WGSL's automatic conversion rules, e.g. `x * 2` or `vec2<u32>(1, 2)`.
+    ///
+    /// The values of the literals are unchanged, so the shader computes the same results as it
+    /// does without this flag.
+    #[clap(long, action)]
+    pub abstract_literals: bool,
+
     /// Recondition the resulting program to remove UB
     #[clap(long, action)]
     pub recondition: bool,
@@ -116,7 +320,39 @@ pub struct Options {
     pub output: String,
 }
 
+impl Options {
+    /// Returns true if calls to the given builtin may be generated, taking into account the
+    /// builtins that were explicitly enabled or disabled and the profile.
+    pub fn allows_builtin(&self, builtin: BuiltinFn) -> bool {
+        if self.disabled_fns.contains(&builtin) {
+            return false;
+        }
+
+        self.enabled_fns.contains(&builtin)
+            || self.profile.is_none_or(|it| it.allows_builtin(builtin))
+    }
+}
+
 pub fn run(mut options: Options) -> eyre::Result<()> {
+    for (name, min, max) in [
+        ("fn-stmts", options.fn_min_stmts, options.fn_max_stmts),
+        (
+            "block-stmts",
+            options.block_min_stmts,
+            options.block_max_stmts,
+        ),
+        ("structs", options.min_structs, options.max_structs),
+        (
+            "struct-members",
+            options.min_struct_members,
+            options.max_struct_members,
+        ),
+    ] {
+        if min > max {
+            bail!("minimum {name} ({min}) must not be greater than the maximum ({max})");
+        }
+    }
+
     if let Some(preset) = &options.preset {
         match preset {
             Preset::Tint => {
@@ -133,6 +369,11 @@ pub fn run(mut options: Options) -> eyre::Result<()> {
         }
     }
 
+    if options.multi_invocation && options.enable_subgroups {
+        // The results of subgroup reductions would depend on the subgroup size.
+        bail!("--multi-invocation can't be used with --enable-subgroups");
+    }
+
     let options = Rc::new(options);
 
     tracing_subscriber::fmt()
@@ -155,23 +396,59 @@ pub fn run(mut options: Options) -> eyre::Result<()> {
 
     tracing::info!("generating shader from seed: {}", seed);
 
-    let mut rng = StdRng::seed_from_u64(seed);
-    let mut shader = Generator::new(&mut rng, options.clone()).gen_module();
+    // `StdRng` doesn't guarantee reproducible output across releases of `rand`, so we explicitly
+    // use the algorithm that it currently wraps.
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let mut generator = Generator::new(&mut rng, options.clone());
+    let (mut shader, oracle) = if options.oracle {
+        let oracle = generator.gen_oracle_module();
+        (
+            oracle.module,
+            Some((oracle.input_data, oracle.expected_output)),
+        )
+    } else {
+        (generator.gen_module(), None)
+    };
+
+    let dispatch = generator.dispatch();
+    let textures = generator
+        .textures()
+        .iter()
+        .map(|it| {
+            let size = serde_json::json!({
+                "size": it.size,
+                "mip_level_count": it.mip_level_count,
+            });
+
+            (format!("0:{}", it.binding), size)
+        })
+        .collect::<serde_json::Map<_, _>>();
+
+    // Texture contents are passed to the harness in the same way as uniform buffers.
+    let texture_data = generator
+        .textures()
+        .iter()
+        .filter_map(|it| Some((format!("0:{}", it.binding), it.data.clone()?)))
+        .collect::<Vec<_>>();
 
     if options.recondition {
+        shader = reconditioner::recondition_with(
+            shader,
+            reconditioner::Options {
+                only_loops: options.preset == Some(Preset::Tint),
+                ..Default::default()
+            },
+        )
+        .ast;
+
+        // Aliased pointer arguments are legalized by the reconditioner, so the checks are done on
+        // its output.
         if options.enable_pointers
             && !options.skip_pointer_checks
             && !reconditioner::analysis::analyse(&shader)
         {
             bail!("rejected shader due to possible invalid aliasing");
         }
-
-        shader = reconditioner::recondition_with(
-            shader,
-            reconditioner::Options {
-                only_loops: options.preset == Some(Preset::Tint),
-            },
-        );
     }
 
     let mut output: Box<dyn io::Write> = if options.output == "-" {
@@ -184,33 +461,68 @@ pub fn run(mut options: Options) -> eyre::Result<()> {
     };
 
     if !options.debug {
-        let mut init_data = HashMap::new();
+        let (mut init_data, expected_output) = match oracle {
+            // The inputs were chosen during generation, since the expected output depends on them.
+            Some((init_data, expected_output)) => (init_data, Some(expected_output)),
+            None => (gen_init_data(&shader, &mut rng)?, None),
+        };
+
+        init_data.extend(texture_data);
+
+        let mut metadata = serde_json::to_value(&init_data)?;
+
+        if options.multi_invocation {
+            // The number of workgroups is chosen during generation if the output buffer has an
+            // element for each invocation.
+            let dispatch: [u32; 3] = dispatch.unwrap_or_else(|| {
+                [
+                    rng.gen_range(1..=4),
+                    rng.gen_range(1..=4),
+                    rng.gen_range(1..=4),
+                ]
+            });
+
+            metadata["dispatch"] = serde_json::to_value(dispatch)?;
+        }
 
-        for var in &shader.vars {
-            if let Some(VarQualifier { storage_class, .. }) = &var.qualifier {
-                if *storage_class != StorageClass::Uniform {
-                    continue;
-                }
+        if !textures.is_empty() {
+            metadata["textures"] = textures.into();
+        }
 
-                let type_desc = common::Type::try_from(&var.data_type).map_err(|e| eyre!(e))?;
+        // The required features and limits are recorded so that the harness can skip configs
+        // which aren't able to run the shader.
+        let requirements = reflection::requirements(&shader);
+        if requirements != Requirements::default() {
+            metadata["requires"] = serde_json::to_value(requirements)?;
+        }
 
-                let group = var.group_index().unwrap();
-                let binding = var.binding_index().unwrap();
+        writeln!(output, "// {metadata}")?;
+        writeln!(output, "// Seed: {seed}")?;
+        writeln!(output, "// RNG version: {}", options.rng_version.number())?;
 
-                let size = type_desc.buffer_size();
-                let data: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        if let Some(expected_output) = expected_output {
+            let expected_output = serde_json::to_string(&expected_output)?;
+            writeln!(output, "// Expected output: {expected_output}")?;
+        }
 
-                init_data.insert(format!("{group}:{binding}"), data);
+        for (label, builtins) in [
+            ("Enabled", &options.enabled_fns),
+            ("Disabled", &options.disabled_fns),
+        ] {
+            if !builtins.is_empty() {
+                let names = builtins.iter().map(|it| it.as_ref()).collect::<Vec<_>>();
+                writeln!(output, "// {label} builtins: {}", names.join(", "))?;
             }
         }
 
-        let init_data = serde_json::to_string(&init_data)?;
-
-        writeln!(output, "// {init_data}")?;
-        writeln!(output, "// Seed: {seed}")?;
         writeln!(output)?;
     }
 
+    // This is done last so that the rest of the output is the same as without abstract literals.
+    if options.abstract_literals {
+        abstract_literals::make_literals_abstract(&mut shader, &mut rng);
+    }
+
     if options.debug {
         writeln!(output, "{shader:#?}")?;
     } else {
@@ -223,8 +535,71 @@ pub fn run(mut options: Options) -> eyre::Result<()> {
             }
         }
 
-        ast::writer::Writer::default().write_module(&mut Output(&mut output), &shader)?;
+        ast::writer::Writer.write_module(&mut Output(&mut output), &shader)?;
     }
 
     Ok(())
 }
+
+/// Generates random contents for each uniform buffer in the shader, keyed by `group:binding`.
+fn gen_init_data(shader: &Module, rng: &mut impl Rng) -> eyre::Result<BTreeMap<String, Vec<u8>>> {
+    let mut init_data = BTreeMap::new();
+
+    for var in &shader.vars {
+        if let Some(VarQualifier { storage_class, .. }) = &var.qualifier {
+            if *storage_class != StorageClass::Uniform {
+                continue;
+            }
+
+            let type_desc = common::Type::try_from(&var.data_type).map_err(|e| eyre!(e))?;
+
+            let group = var.group_index().unwrap();
+            let binding = var.binding_index().unwrap();
+
+            let size = type_desc.buffer_size();
+            let data: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+
+            init_data.insert(format!("{group}:{binding}"), data);
+        }
+    }
+
+    Ok(init_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_shaders(args: &[&str]) -> Vec<String> {
+        let options = Rc::new(Options::parse_from(
+            std::iter::once("generator").chain(args.iter().copied()),
+        ));
+
+        (0..64)
+            .map(|seed| {
+                let mut rng = ChaCha12Rng::seed_from_u64(seed);
+                let module = Generator::new(&mut rng, options.clone()).gen_module();
+
+                let mut shader = String::new();
+                ast::writer::Writer
+                    .write_module(&mut shader, &module)
+                    .unwrap();
+
+                shader
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packed_4x8_builtins_require_flag() {
+        // Matches `pack4xI8`, `pack4xU8`, their clamping variants and the unpacking builtins, but
+        // not `pack4x8snorm` and `pack4x8unorm`, which don't need the language extension
+        let uses_packed_4x8 =
+            |shader: &String| shader.contains("pack4xI8") || shader.contains("pack4xU8");
+
+        assert!(!gen_shaders(&[]).iter().any(uses_packed_4x8));
+        assert!(gen_shaders(&["--enable-packed-dot-products"])
+            .iter()
+            .any(uses_packed_4x8));
+    }
+}
@@ -7,27 +7,68 @@ use std::path::Path;
 use std::time::Duration;
 use std::{fmt, io};
 
+use ast::{StorageClass, VarQualifier};
 use eyre::{eyre, Context};
-use reflection::PipelineDescription;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use reflection::{PipelineDescription, Requirements, ResourceKind};
+use serde::{Deserialize, Serialize};
 
-pub use printer::{ExecutionEvent, ExecutionResult, Printer};
-use types::ConfigId;
+pub use printer::{
+    environment_json, BenchStats, ExecutionEvent, ExecutionResult, OutputFormat, Printer,
+};
+use types::{AdapterFilter, BackendOptions, ConfigId, RetryPolicy, Variant};
 
-pub fn read_input_data(
-    shader: &str,
-    input_data: Option<&str>,
-) -> eyre::Result<HashMap<String, Vec<u8>>> {
+/// Shader metadata, as written by the generator on the first line of the shader.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct InputData {
+    /// Number of workgroups to dispatch in each dimension, which defaults to a single workgroup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispatch: Option<[u32; 3]>,
+    /// Sizes of the textures, keyed by `group:binding`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub textures: HashMap<String, TextureSize>,
+    /// Features and limits required by the shader, in addition to those that are reflected from
+    /// the shader itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<Requirements>,
+    /// Dawn toggles and wgpu instance flags to execute the shader with, e.g. to reproduce a bug
+    /// that only occurs without robustness.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend_options: Option<BackendOptions>,
+    /// Initial contents of the uniform buffers and textures, keyed by `group:binding`.
+    #[serde(flatten)]
+    pub buffers: HashMap<String, Vec<u8>>,
+}
+
+/// The size with which a texture is created.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TextureSize {
+    /// Width, height, and depth or number of array layers.
+    pub size: [u32; 3],
+    pub mip_level_count: u32,
+}
+
+pub fn read_input_data(shader: &str, input_data: Option<&str>) -> eyre::Result<InputData> {
+    Ok(find_input_data(shader, input_data)?.unwrap_or_default())
+}
+
+/// Reads the input data from `input_data` if it is given, which is either a JSON string or the path
+/// to a JSON file. Otherwise it is read from an `inputs.json` file in the shader's directory or a
+/// JSON file with the same name as the shader, if there is one.
+pub fn find_input_data(shader: &str, input_data: Option<&str>) -> eyre::Result<Option<InputData>> {
     match input_data {
         Some(input_data) => {
             // Try parsing value as json string
             match serde_json::from_str(input_data)
                 .wrap_err_with(|| eyre!("failed to parse input data"))
             {
-                Ok(input_data) => Ok(input_data),
+                Ok(input_data) => Ok(Some(input_data)),
                 // On failure, try treating value as file path
                 Err(parse_err) => match File::open(input_data) {
                     // File opened successfully, parse the contents as json
                     Ok(file) => serde_json::from_reader(file)
+                        .map(Some)
                         .wrap_err_with(|| eyre!("failed to parse input data")),
                     // File not found, return original parsing error
                     Err(e) if e.kind() == io::ErrorKind::NotFound => Err(parse_err),
@@ -41,22 +82,93 @@ pub fn read_input_data(
             if shader != "-" {
                 if let Some(path) = Path::new(shader).parent().map(|it| it.join("inputs.json")) {
                     if path.exists() {
-                        return Ok(serde_json::from_reader(File::open(path)?)?);
+                        return Ok(Some(serde_json::from_reader(File::open(path)?)?));
                     }
                 }
 
                 let path = Path::new(shader).with_extension("json");
                 if path.exists() {
-                    return Ok(serde_json::from_reader(File::open(path)?)?);
+                    return Ok(Some(serde_json::from_reader(File::open(path)?)?));
                 }
             }
 
-            // Default to no input data
-            Ok(Default::default())
+            Ok(None)
         }
     }
 }
 
+/// Generates random contents for each uniform buffer in the shader, for shaders that don't come
+/// with input data. The same seed always generates the same data.
+pub fn generate_input_data(shader: &str, seed: u64) -> InputData {
+    let module = parser::parse(shader);
+
+    // `StdRng` doesn't guarantee reproducible output across releases of `rand`, so the algorithm
+    // that it currently wraps is used explicitly, as in the generator.
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    let mut input_data = InputData::default();
+
+    for var in &module.vars {
+        if let Some(VarQualifier {
+            storage_class: StorageClass::Uniform,
+            ..
+        }) = &var.qualifier
+        {
+            let type_desc =
+                common::Type::try_from(&var.data_type).expect("invalid type for uniform buffer");
+
+            let group = var
+                .group_index()
+                .expect("resource variable must have group attribute");
+
+            let binding = var
+                .binding_index()
+                .expect("resource variable must have binding attribute");
+
+            let data = (0..type_desc.buffer_size()).map(|_| rng.gen()).collect();
+            input_data
+                .buffers
+                .insert(format!("{group}:{binding}"), data);
+        }
+    }
+
+    input_data
+}
+
+/// Reads the expected contents of the output buffers, keyed by `group:binding`.
+///
+/// If `expected_output` isn't provided, this looks for an `// Expected output:` comment in the
+/// shader source (which is written by the generator in oracle mode).
+pub fn read_expected_output(
+    source: &str,
+    expected_output: Option<&str>,
+) -> eyre::Result<Option<HashMap<String, Vec<u8>>>> {
+    let expected_output = match expected_output {
+        Some(expected_output) => expected_output,
+        None => match source
+            .lines()
+            .find_map(|line| line.strip_prefix("// Expected output:"))
+        {
+            Some(json) => json.trim(),
+            None => return Ok(None),
+        },
+    };
+
+    // Try parsing value as json string
+    match serde_json::from_str(expected_output)
+        .wrap_err_with(|| eyre!("failed to parse expected output"))
+    {
+        Ok(expected_output) => Ok(Some(expected_output)),
+        // On failure, try treating value as file path
+        Err(parse_err) => match File::open(expected_output) {
+            Ok(file) => serde_json::from_reader(file)
+                .map(Some)
+                .wrap_err_with(|| eyre!("failed to parse expected output")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(parse_err),
+            Err(e) => Err(e.into()),
+        },
+    }
+}
+
 pub fn read_shader_from_path(path: &str) -> eyre::Result<String> {
     let mut input: Box<dyn io::Read> = match path {
         "-" => Box::new(std::io::stdin()),
@@ -75,14 +187,44 @@ pub fn read_shader_from_path(path: &str) -> eyre::Result<String> {
 
 pub fn reflect_shader(
     shader: &str,
-    mut input_data: HashMap<String, Vec<u8>>,
+    mut input_data: InputData,
 ) -> (PipelineDescription, Vec<common::Type>) {
     let module = parser::parse(shader);
 
     let (mut pipeline_desc, type_descs) = reflection::reflect(&module, |resource| {
-        input_data.remove(&format!("{}:{}", resource.group, resource.binding))
+        input_data
+            .buffers
+            .remove(&format!("{}:{}", resource.group, resource.binding))
     });
 
+    if let Some(dispatch) = input_data.dispatch {
+        pipeline_desc.dispatch = dispatch;
+    }
+
+    if let Some(requires) = input_data.requires {
+        for feature in requires.features {
+            if !pipeline_desc.features.contains(&feature) {
+                pipeline_desc.features.push(feature);
+            }
+        }
+
+        pipeline_desc.limits = pipeline_desc.limits.max(requires.limits);
+    }
+
+    for resource in &mut pipeline_desc.resources {
+        if let ResourceKind::Texture(desc) = &mut resource.kind {
+            let key = format!("{}:{}", resource.group, resource.binding);
+            if let Some(texture) = input_data.textures.remove(&key) {
+                desc.size = texture.size;
+                desc.mip_level_count = texture.mip_level_count;
+            }
+
+            if let Some(init) = &mut resource.init {
+                init.resize(desc.data_size(), 0);
+            }
+        }
+    }
+
     let mut resource_vars = HashSet::new();
 
     for resource in &pipeline_desc.resources {
@@ -91,9 +233,21 @@ pub fn reflect_shader(
 
     utils::remove_accessed_vars(&mut resource_vars, &module);
 
-    pipeline_desc
-        .resources
-        .retain(|resource| !resource_vars.contains(&resource.name));
+    // The type descriptors line up with the buffers, so the types of the buffers that are removed
+    // are removed with them
+    let mut types = type_descs.into_iter();
+    let mut type_descs = vec![];
+
+    pipeline_desc.resources.retain(|resource| {
+        let is_used = !resource_vars.contains(&resource.name);
+        if resource.kind.is_buffer() {
+            let type_desc = types.next().expect("buffer must have a type descriptor");
+            if is_used {
+                type_descs.push(type_desc);
+            }
+        }
+        is_used
+    });
 
     (pipeline_desc, type_descs)
 }
@@ -101,6 +255,9 @@ pub fn reflect_shader(
 #[derive(Debug)]
 pub enum ExecutionError {
     NoDefaultConfigs,
+    NoOracleConfig,
+    /// The run was cancelled before all of the configs had executed the shader.
+    Cancelled,
     Io(io::Error),
     Encode(bincode::error::EncodeError),
     Decode(bincode::error::DecodeError),
@@ -111,6 +268,8 @@ impl fmt::Display for ExecutionError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ExecutionError::NoDefaultConfigs => write!(f, "no suitable default configs found"),
+            ExecutionError::NoOracleConfig => write!(f, "no suitable software config found"),
+            ExecutionError::Cancelled => write!(f, "the run was cancelled"),
             ExecutionError::Io(e) => e.fmt(f),
             ExecutionError::Encode(e) => e.fmt(f),
             ExecutionError::Decode(e) => e.fmt(f),
@@ -146,28 +305,39 @@ impl From<eyre::Report> for ExecutionError {
 }
 
 pub trait Executor {
+    #[allow(clippy::too_many_arguments)]
     fn execute(
         &self,
         shader: &str,
         pipeline_desc: &PipelineDescription,
         configs: &[ConfigId],
+        oracle: bool,
+        variants: &[Variant],
+        filter: &AdapterFilter,
+        options: &BackendOptions,
         timeout: Option<Duration>,
+        retry: RetryPolicy,
         parallelism: Option<usize>,
         on_event: &mut (dyn FnMut(ExecutionEvent) -> Result<(), ExecutionError> + Send),
     ) -> Result<(), ExecutionError>;
 }
 
 pub mod cli {
-    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::{BufReader, BufWriter};
+    use std::path::PathBuf;
     use std::time::Duration;
 
+    use buffer_check::{ComparisonPolicy, ComparisonReport, GoldenOutput};
     use clap::Parser;
     use color_eyre::Help;
-    use eyre::eyre;
+    use eyre::{eyre, Context};
+    use reflection::ResourceKind;
     use serde::Serialize;
-    use types::ConfigId;
+    use types::{AdapterFilter, BackendOptions, ConfigId, RetryPolicy, Variant};
 
-    use crate::{ExecutionEvent, ExecutionResult, Executor};
+    use crate::printer::{diff_json, environment_json, MAX_PRINTED_DIFFS};
+    use crate::{ExecutionEvent, ExecutionResult, Executor, InputData, OutputFormat, Printer};
 
     #[derive(Parser)]
     pub struct RunOptions {
@@ -176,9 +346,28 @@ pub mod cli {
         pub shader: String,
 
         /// Input data for uniform buffers.
+        ///
+        /// Defaults to an `inputs.json` file in the shader's directory or a JSON file with the same
+        /// name as the shader. If there is neither, random contents are generated for the uniform
+        /// buffers and printed, so that the run can be reproduced by passing them back.
         #[clap(action)]
         pub input_data: Option<String>,
 
+        /// Seed for the contents of the uniform buffers when they are generated.
+        ///
+        /// With `--fuzz-inputs`, each set of inputs is generated from the next seed.
+        #[clap(long, action, default_value = "0")]
+        pub seed: u64,
+
+        /// Execute the shader with this many sets of random contents for the uniform buffers, and
+        /// compare the outputs of the configurations for each of them.
+        ///
+        /// Many miscompilations only change the output for particular input values. The rest of
+        /// the input data (e.g. the dispatch size and textures) is kept, and the run stops at the
+        /// first set of inputs whose result isn't ok.
+        #[clap(long, value_parser = clap::value_parser!(u32).range(1..), conflicts_with_all = &["expected-output", "expect", "save-outputs", "report"])]
+        pub fuzz_inputs: Option<u32>,
+
         /// List of configurations to test.
         ///
         /// Configurations must be specified using their IDs. Use the `list` command to see available
@@ -188,80 +377,481 @@ pub mod cli {
         #[clap(short, long = "config", action)]
         pub configs: Vec<ConfigId>,
 
+        /// Use the output of a software adapter (e.g. lavapipe, SwiftShader or WARP) as the
+        /// reference when checking for mismatches.
+        ///
+        /// One of the specified configurations is used if possible, otherwise a software
+        /// configuration is executed in addition to them.
+        #[clap(long, action)]
+        pub oracle: bool,
+
+        /// Also execute each Dawn D3D12 configuration with FXC instead of DXC, and compare the
+        /// outputs as those of a separate configuration (e.g. `dawn:dx12:9348:fxc`).
+        ///
+        /// Many D3D12 bugs only affect one of the HLSL compilers.
+        #[clap(long, action)]
+        pub fxc: bool,
+
+        /// Also execute each wgpu Vulkan configuration with a SPIR-V module that is compiled by naga
+        /// and passed through to the driver, and compare the outputs as those of a separate
+        /// configuration (e.g. `wgpu:vk:9348:spirv`).
+        ///
+        /// This requires adapters that support wgpu's experimental passthrough shaders.
+        #[clap(long, action)]
+        pub spirv: bool,
+
+        /// Only select default and oracle configurations whose adapter name or driver matches
+        /// this regex.
+        #[clap(long, value_parser = parse_adapter_filter)]
+        pub adapter_filter: Option<String>,
+
+        /// Only select default and oracle configurations whose adapter has one of these PCI vendor
+        /// IDs.
+        ///
+        /// Vendors can be given as a hex ID (e.g. `10de`) or by name: `amd`, `apple`, `arm`,
+        /// `intel`, `nvidia` or `qualcomm`.
+        #[clap(long = "vendor", value_parser = parse_vendor)]
+        pub vendors: Vec<u32>,
+
+        /// Dawn toggle to enable when creating the device, e.g. `disable_robustness`.
+        ///
+        /// Toggles are also passed to Chrome, which uses Dawn.
+        #[clap(long = "enable-toggle")]
+        pub enable_toggles: Vec<String>,
+
+        /// Dawn toggle to disable when creating the device, e.g. `use_dxc`.
+        #[clap(long = "disable-toggle")]
+        pub disable_toggles: Vec<String>,
+
+        /// wgpu instance flag to set, e.g. `validation` or `gpu_based_validation`.
+        #[clap(long = "enable-wgpu-flag")]
+        pub enable_wgpu_flags: Vec<String>,
+
+        /// wgpu instance flag to clear, even if it is set by default.
+        #[clap(long = "disable-wgpu-flag")]
+        pub disable_wgpu_flags: Vec<String>,
+
+        /// Record an API trace of each wgpu execution in a subdirectory of this directory, which
+        /// can be replayed with wgpu's `player` without wgslsmith.
+        ///
+        /// The harness must be built with the `trace` feature. Other implementations can't be
+        /// traced, and when using a remote harness the directory is on the server.
+        #[clap(long, action)]
+        pub trace_dir: Option<PathBuf>,
+
         /// Timeout in seconds.
         ///
         /// Use 0 to disable the timeout. Note that the timeout is per-execution rather than a global timeout.
         #[clap(long, action, default_value = "45")]
         pub timeout: u64,
 
+        /// Number of times to retry an execution that fails in a way that may be flaky.
+        ///
+        /// Crashes, device losses and failures to create the device are retried, while compilation
+        /// and validation failures and timeouts are not. An execution that succeeds on a retry is
+        /// reported as a success, otherwise its failure is reported once every attempt has failed.
+        #[clap(long, action, default_value = "0")]
+        pub retries: u32,
+
+        /// Milliseconds to wait before the first retry, which doubles for each later retry.
+        #[clap(long, action, default_value = "1000")]
+        pub retry_backoff: u64,
+
         /// Limit the number of parallel configurations executing at once.
         ///
-        /// If not provided, execution will spawn a thread for every configuration.
+        /// If not provided, execution will spawn a thread for every adapter. Configurations that
+        /// share an adapter (e.g. Dawn and wgpu on the same GPU) are always executed one at a time.
         #[clap(long, short = 'j', action)]
         pub parallelism: Option<usize>,
 
+        /// Expected contents of the output buffers, as a JSON string or path to a JSON file.
+        ///
+        /// If provided, the output of each configuration is checked against it. Defaults to the
+        /// contents of an `// Expected output:` comment in the shader, if there is one.
+        #[clap(long, action)]
+        pub expected_output: Option<String>,
+
+        /// Path to a golden output file to check the output of each configuration against, which
+        /// was saved by a previous run with `--save-outputs`.
+        #[clap(long, action, conflicts_with = "expected-output")]
+        pub expect: Option<PathBuf>,
+
+        /// Save the correct output of the run to a golden output file, which can be checked
+        /// against later with `--expect`.
+        ///
+        /// This is the output of the oracle if there is one, and otherwise the output that most
+        /// configurations agree on. Nothing is saved if the run fails, or if there isn't a
+        /// majority.
+        #[clap(long, action)]
+        pub save_outputs: Option<PathBuf>,
+
+        /// Write a JSON report of how the outputs of the configurations compared.
+        ///
+        /// The report lists each class of matching outputs with its configurations, the digest of
+        /// the output and the values in which it differs from the most common output. The format
+        /// is versioned, so that the report can be consumed by other tools.
+        #[clap(long, action)]
+        pub report: Option<PathBuf>,
+
+        /// Number of times to execute each configuration.
+        ///
+        /// Outputs that differ between runs of the same configuration are reported separately from
+        /// mismatches between configurations, since they point to data races or reads of
+        /// uninitialized memory rather than miscompilations.
+        #[clap(long, value_parser = clap::value_parser!(u32).range(1..), default_value = "1")]
+        pub repeat: u32,
+
+        /// Fill the output buffers with a canary pattern instead of zeros, and report any data in
+        /// them that still holds the pattern after execution.
+        ///
+        /// This detects miscompilations that drop writes to the output buffers. Buffers with input
+        /// data are left as they are.
+        #[clap(long, action)]
+        pub canary: bool,
+
+        /// Number of units in the last place (ULPs) by which floats in the outputs may differ
+        /// without being reported as a mismatch.
+        ///
+        /// Integers are always compared exactly. By default, outputs are compared bytewise.
+        #[clap(long, action, default_value = "0")]
+        pub float_ulps: u32,
+
+        /// Absolute difference by which floats in the outputs may differ without being reported as
+        /// a mismatch.
+        ///
+        /// This is needed as well as `--float-ulps` for results close to zero, where a ULP is tiny.
+        #[clap(long, action, default_value = "0")]
+        pub float_tolerance: f32,
+
+        /// Treat all NaNs in the outputs as equal regardless of their bits, and `-0.0` as equal to
+        /// `0.0`.
+        ///
+        /// Floats are canonicalized before any other comparison, so this also applies when they
+        /// are compared exactly.
+        #[clap(long, action)]
+        pub canonicalize_floats: bool,
+
+        /// Format of the output.
+        ///
+        /// With `json`, each event of the run is printed as a JSON object on its own line, which
+        /// includes how long each execution took and digests of the output buffers.
+        #[clap(long, value_enum, default_value = "human")]
+        pub format: OutputFormat,
+
         /// Print all unique outputs, as well as their corresponding configurations.
         ///
-        /// Configs that timed out are ignored.
+        /// Configs that timed out are printed separately.
         #[clap(long, action, default_value = "false")]
         pub print_consensus: bool,
     }
 
+    fn parse_adapter_filter(value: &str) -> Result<String, regex::Error> {
+        regex::Regex::new(value)?;
+        Ok(value.to_owned())
+    }
+
+    fn parse_vendor(value: &str) -> Result<u32, String> {
+        match value.to_lowercase().as_str() {
+            "amd" => Ok(0x1002),
+            "apple" => Ok(0x106b),
+            "arm" => Ok(0x13b5),
+            "intel" => Ok(0x8086),
+            "nvidia" => Ok(0x10de),
+            "qualcomm" => Ok(0x5143),
+            id => u32::from_str_radix(id.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("expected a hex vendor ID or vendor name, got `{value}`")),
+        }
+    }
+
     pub fn run(options: RunOptions, executor: &dyn Executor) -> eyre::Result<()> {
         let shader = super::read_shader_from_path(&options.shader)?;
-        let input_data = super::read_input_data(&options.shader, options.input_data.as_deref())?;
-        let (pipeline_desc, type_descs) = super::reflect_shader(&shader, input_data);
+        let input_data = super::find_input_data(&options.shader, options.input_data.as_deref())?;
+        let printer = Printer::with_format(options.format);
 
-        let printer = super::Printer::new();
+        let result = match options.fuzz_inputs {
+            None => {
+                let expected_output = match &options.expect {
+                    Some(path) => Some(
+                        GoldenOutput::read(BufReader::new(File::open(path)?))
+                            .wrap_err_with(|| eyre!("failed to read `{}`", path.display()))?,
+                    ),
+                    None => {
+                        super::read_expected_output(&shader, options.expected_output.as_deref())?
+                            .map(|it| GoldenOutput {
+                                buffers: it.into_iter().collect(),
+                            })
+                    }
+                };
 
-        let mut executions: Vec<(ConfigId, Vec<Vec<u8>>)> = vec![];
-        let mut is_fail = false;
-        let mut on_event = |event: ExecutionEvent| {
-            printer.print_execution_event(&event, &pipeline_desc)?;
-            if let ExecutionEvent::Success(config, buffers) = event {
-                executions.push((config, buffers));
-            } else if let ExecutionEvent::Failure(_) = event {
-                is_fail = true
+                let input_data = match input_data {
+                    Some(input_data) => input_data,
+                    None => {
+                        let input_data = super::generate_input_data(&shader, options.seed);
+                        if !input_data.buffers.is_empty() {
+                            printer.print_generated_inputs(options.seed, &input_data)?;
+                        }
+                        input_data
+                    }
+                };
+
+                run_with_inputs(
+                    &options,
+                    &shader,
+                    input_data,
+                    expected_output,
+                    &printer,
+                    executor,
+                    true,
+                )?
+            }
+            Some(count) => {
+                let input_data = input_data.unwrap_or_default();
+                if super::generate_input_data(&shader, options.seed)
+                    .buffers
+                    .is_empty()
+                {
+                    return Err(eyre!("the shader doesn't have any uniform buffers to fuzz"));
+                }
+
+                // The expected output is left out, since it only holds for the original inputs
+                let mut result = ExecutionResult::Ok;
+                for index in 0..count {
+                    let seed = options.seed.wrapping_add(index.into());
+
+                    let mut input_data = input_data.clone();
+                    input_data
+                        .buffers
+                        .extend(super::generate_input_data(&shader, seed).buffers);
+                    printer.print_generated_inputs(seed, &input_data)?;
+
+                    result = run_with_inputs(
+                        &options,
+                        &shader,
+                        input_data,
+                        None,
+                        &printer,
+                        executor,
+                        index == 0,
+                    )?;
+
+                    if !matches!(result, ExecutionResult::Ok) {
+                        break;
+                    }
+                }
+                result
             }
-            Ok(())
         };
 
+        let code = match result {
+            ExecutionResult::Ok => 0,
+            ExecutionResult::Mismatch | ExecutionResult::MissedWrites => 1,
+            // 2 is used by clap for usage errors, so timeouts use a code that nothing else does
+            ExecutionResult::Timeout => 4,
+            ExecutionResult::Nondeterministic => 3,
+            ExecutionResult::Failure => unreachable!("failed executions panic instead"),
+        };
+
+        printer.print_execution_result(result)?;
+
+        if code != 0 {
+            std::process::exit(code);
+        }
+
+        Ok(())
+    }
+
+    /// Executes the shader with one set of input data, and compares the outputs of the configs.
+    /// The environment and default configs are only printed if this is the first set of inputs.
+    fn run_with_inputs(
+        options: &RunOptions,
+        shader: &str,
+        mut input_data: InputData,
+        expected_output: Option<GoldenOutput>,
+        printer: &Printer,
+        executor: &dyn Executor,
+        first: bool,
+    ) -> eyre::Result<ExecutionResult> {
+        // Toggles and flags from the command line are applied on top of those in the input data
+        let mut backend_options = input_data.backend_options.take().unwrap_or_default();
+        backend_options.extend(BackendOptions {
+            enable_toggles: options.enable_toggles.clone(),
+            disable_toggles: options.disable_toggles.clone(),
+            enable_wgpu_flags: options.enable_wgpu_flags.clone(),
+            disable_wgpu_flags: options.disable_wgpu_flags.clone(),
+            trace_dir: options.trace_dir.clone(),
+        });
+
+        let (mut pipeline_desc, type_descs) = super::reflect_shader(shader, input_data);
+
+        let policy = ComparisonPolicy {
+            ulps: options.float_ulps,
+            absolute: options.float_tolerance,
+            canonicalize_floats: options.canonicalize_floats,
+        };
+
+        // The output buffers that are filled with the canary pattern, along with the indices of
+        // their execution result and type descriptor
+        let mut canary_buffers = vec![];
+        if options.canary {
+            for (i, (j, resource)) in pipeline_desc
+                .resources
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, it)| it.kind == ResourceKind::StorageBuffer)
+                .enumerate()
+            {
+                if resource.init.is_none() {
+                    resource.init = Some(buffer_check::canary_buffer(resource.size as usize));
+                    canary_buffers.push((i, j, format!("{}:{}", resource.group, resource.binding)));
+                }
+            }
+        }
+
+        // Buffers are ordered in the same way as the execution results, so that they can be
+        // normalized and compared in the same way.
+        let expected_output = expected_output
+            .map(|it| it.execution(&pipeline_desc))
+            .transpose()?
+            .map(|it| buffer_check::normalize_execution(&it, &pipeline_desc, &type_descs, &policy));
+
+        let mut executions: Vec<(ConfigId, Vec<Vec<u8>>)> = vec![];
+        let mut timeouts: Vec<ConfigId> = vec![];
+        let mut oracle: Option<ConfigId> = None;
+        let mut environment = None;
+        let mut is_fail = false;
+
         let timeout = if options.timeout == 0 {
             None
         } else {
             Some(Duration::from_secs(options.timeout))
         };
 
-        executor
-            .execute(
-                &shader,
-                &pipeline_desc,
-                &options.configs,
-                timeout,
-                options.parallelism,
-                &mut on_event,
-            )
-            .map_err(|e| match e {
-                crate::ExecutionError::NoDefaultConfigs => {
-                    eyre!("failed to find any suitable default configurations")
-                        .with_note(|| "use the `list` command to see all available configurations")
+        let retry = RetryPolicy {
+            retries: options.retries,
+            backoff: Duration::from_millis(options.retry_backoff),
+        };
+
+        let variants = [(options.fxc, Variant::Fxc), (options.spirv, Variant::Spirv)]
+            .into_iter()
+            .filter_map(|(enabled, variant)| enabled.then_some(variant))
+            .collect::<Vec<_>>();
+
+        let filter = AdapterFilter {
+            pattern: options.adapter_filter.clone(),
+            vendors: options.vendors.clone(),
+        };
+
+        for run in 0..options.repeat {
+            let mut on_event = |event: ExecutionEvent| {
+                // The environment, default configs and oracle are the same for every run, so they
+                // are only printed once
+                if (first && run == 0)
+                    || !matches!(
+                        event,
+                        ExecutionEvent::Environment(_)
+                            | ExecutionEvent::UsingDefaultConfigs(_)
+                            | ExecutionEvent::UsingOracle(_)
+                    )
+                {
+                    printer.print_execution_event(&event, &pipeline_desc, &type_descs, &policy)?;
+                }
+
+                match event {
+                    ExecutionEvent::Success(config, buffers, _) => {
+                        executions.push((config, buffers))
+                    }
+                    ExecutionEvent::CompilationFailure(..)
+                    | ExecutionEvent::ValidationFailure(..)
+                    | ExecutionEvent::RuntimeFailure(..)
+                    | ExecutionEvent::DeviceLost(..)
+                    | ExecutionEvent::Hang(..) => is_fail = true,
+                    ExecutionEvent::Timeout(config) if !timeouts.contains(&config) => {
+                        timeouts.push(config)
+                    }
+                    ExecutionEvent::UsingOracle(config) => oracle = Some(config),
+                    ExecutionEvent::Environment(it) => environment = Some(it),
+                    _ => {}
                 }
-                crate::ExecutionError::Other(e) => e,
-                e => eyre!(e),
-            })?;
+                Ok(())
+            };
+
+            executor
+                .execute(
+                    shader,
+                    &pipeline_desc,
+                    &options.configs,
+                    options.oracle,
+                    &variants,
+                    &filter,
+                    &backend_options,
+                    timeout,
+                    retry,
+                    options.parallelism,
+                    &mut on_event,
+                )
+                .map_err(|e| match e {
+                    crate::ExecutionError::NoDefaultConfigs => eyre!(
+                        "failed to find any suitable default configurations"
+                    )
+                    .with_note(|| "use the `list` command to see all available configurations"),
+                    crate::ExecutionError::NoOracleConfig => {
+                        eyre!("failed to find a software configuration to use as the oracle")
+                            .with_note(|| {
+                                "use the `list` command to see all available configurations"
+                            })
+                    }
+                    crate::ExecutionError::Other(e) => e,
+                    e => eyre!(e),
+                })?;
+        }
+
+        // The environment is also needed to triage crashes, so it is printed before failing
+        if options.print_consensus {
+            if let Some(environment) = &environment {
+                eprintln!("output-environment: {}", environment_json(environment));
+            }
+        }
 
         if is_fail {
             panic!("one or more executions failed");
         }
 
-        let mut buffers_to_configs: HashMap<Vec<u8>, Vec<ConfigId>> = HashMap::new();
+        let matches = |a: &[u8], b: &[u8]| {
+            buffer_check::normalized_match(a, b, &pipeline_desc, &type_descs, &policy)
+        };
+
+        // The distinct outputs of each config, in the order that they were first seen. Configs
+        // with more than one output are nondeterministic.
+        let mut outputs: Vec<(ConfigId, Vec<Vec<u8>>)> = vec![];
         for (config, execution) in executions.iter() {
             let normalized =
-                buffer_check::normalize_execution(execution, &pipeline_desc, &type_descs);
-            buffers_to_configs
-                .entry(normalized)
-                .or_default()
-                .push(config.clone());
+                buffer_check::normalize_execution(execution, &pipeline_desc, &type_descs, &policy);
+            match outputs.iter_mut().find(|(it, _)| it == config) {
+                Some((_, distinct)) => {
+                    if !distinct.iter().any(|it| matches(it, &normalized)) {
+                        distinct.push(normalized);
+                    }
+                }
+                None => outputs.push((config.clone(), vec![normalized])),
+            }
+        }
+
+        let consensus = buffer_check::group_normalized(
+            outputs
+                .iter()
+                .flat_map(|(config, distinct)| distinct.iter().map(move |it| (config, it))),
+            &pipeline_desc,
+            &type_descs,
+            &policy,
+        );
+
+        if let Some(path) = &options.report {
+            let report =
+                ComparisonReport::new(&consensus, &pipeline_desc, &type_descs, &policy, usize::MAX);
+
+            let file = File::create(path)
+                .wrap_err_with(|| eyre!("failed to create `{}`", path.display()))?;
+            report.write(BufWriter::new(file))?;
         }
 
         if options.print_consensus {
@@ -269,29 +859,206 @@ pub mod cli {
             struct ConsensusEntry<'a> {
                 output: &'a [u8],
                 configs: Vec<String>,
+                /// The values that differ from the first entry, which is the most common output.
+                #[serde(skip_serializing_if = "Vec::is_empty")]
+                diffs: Vec<serde_json::Value>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                diff_count: Option<usize>,
             }
 
-            let report: Vec<ConsensusEntry> = buffers_to_configs
+            let reference = consensus.classes.first().map(|it| it.output.as_slice());
+            let report: Vec<ConsensusEntry> = consensus
+                .classes
                 .iter()
-                .map(|(buf, configs)| ConsensusEntry {
-                    output: buf,
-                    configs: configs.iter().map(|it| it.to_string()).collect(),
+                .enumerate()
+                .map(|(i, class)| {
+                    let diffs = reference
+                        .filter(|_| i > 0)
+                        .map(|reference| {
+                            buffer_check::diff_normalized(
+                                &class.output,
+                                reference,
+                                &pipeline_desc,
+                                &type_descs,
+                                &policy,
+                            )
+                        })
+                        .unwrap_or_default();
+
+                    ConsensusEntry {
+                        output: &class.output,
+                        configs: class.members.iter().map(|it| it.to_string()).collect(),
+                        diffs: diffs
+                            .iter()
+                            .take(MAX_PRINTED_DIFFS)
+                            .map(diff_json)
+                            .collect(),
+                        diff_count: (!diffs.is_empty()).then_some(diffs.len()),
+                    }
                 })
                 .collect();
 
             if let Ok(json_str) = serde_json::to_string(&report) {
                 eprintln!("output-consensus: {}", json_str);
             }
+
+            // The report is also printed so that it can be saved with test cases, while the
+            // consensus above is what the outputs of different harnesses are grouped by
+            let report = ComparisonReport::new(
+                &consensus,
+                &pipeline_desc,
+                &type_descs,
+                &policy,
+                MAX_PRINTED_DIFFS,
+            );
+            if let Ok(json_str) = serde_json::to_string(&report) {
+                eprintln!("output-report: {}", json_str);
+            }
+
+            if !timeouts.is_empty() {
+                let timeouts: Vec<String> = timeouts.iter().map(|it| it.to_string()).collect();
+                if let Ok(json_str) = serde_json::to_string(&timeouts) {
+                    eprintln!("output-timeouts: {}", json_str);
+                }
+            }
         }
 
-        if buffers_to_configs.len() <= 1 {
-            printer.print_execution_result(ExecutionResult::Ok)?;
-        } else {
-            printer.print_execution_result(ExecutionResult::Mismatch)?;
+        let mut is_nondeterministic = false;
+        for (config, distinct) in outputs.iter() {
+            if distinct.len() > 1 {
+                printer.print_nondeterministic(config, distinct.len(), options.repeat)?;
+                is_nondeterministic = true;
+            }
+        }
+
+        // Only the first run of each config which missed writes is reported
+        let mut has_missed_writes = false;
+        let mut checked_configs: Vec<&ConfigId> = vec![];
+        for (config, execution) in executions.iter() {
+            if checked_configs.contains(&config) {
+                continue;
+            }
+
+            let missed_writes = canary_buffers
+                .iter()
+                .map(|(i, j, key)| {
+                    let ranges = buffer_check::surviving_canaries(&execution[*i], &type_descs[*j]);
+                    (key.as_str(), ranges)
+                })
+                .filter(|(_, ranges)| !ranges.is_empty())
+                .collect::<Vec<_>>();
 
-            std::process::exit(1);
+            if !missed_writes.is_empty() {
+                printer.print_missed_writes(config, &missed_writes)?;
+                checked_configs.push(config);
+                has_missed_writes = true;
+            }
         }
 
-        Ok(())
+        let deterministic = buffer_check::group_normalized(
+            outputs
+                .iter()
+                .filter(|(_, distinct)| distinct.len() == 1)
+                .map(|(config, distinct)| (config, &distinct[0])),
+            &pipeline_desc,
+            &type_descs,
+            &policy,
+        );
+
+        if let Some(path) = &options.save_outputs {
+            // The oracle is trusted over the other configs, as long as it is deterministic
+            let config = match &oracle {
+                Some(oracle) => deterministic
+                    .classes
+                    .iter()
+                    .flat_map(|it| &it.members)
+                    .find(|it| **it == oracle)
+                    .copied(),
+                None => deterministic.majority().map(|it| it.members[0]),
+            };
+
+            let (_, execution) = config
+                .and_then(|config| executions.iter().find(|(it, _)| it == config))
+                .ok_or_else(|| eyre!("failed to find a correct output to save"))
+                .with_note(|| {
+                    "outputs are only saved from the oracle or the output that most configs agree on"
+                })?;
+
+            let file = File::create(path)
+                .wrap_err_with(|| eyre!("failed to create `{}`", path.display()))?;
+            GoldenOutput::from_execution(execution, &pipeline_desc).write(BufWriter::new(file))?;
+        }
+
+        // Nondeterministic configs are left out of the comparisons below, since their output
+        // depends on the run rather than on the config.
+        //
+        // An expected output takes precedence over the oracle. If the oracle timed out, the
+        // outputs are only checked against each other.
+        let reference = match expected_output {
+            Some(expected_output) => Some((None, expected_output)),
+            None => oracle.and_then(|oracle| {
+                let (_, distinct) = outputs.iter().find(|(config, _)| *config == oracle)?;
+                Some((Some(oracle), distinct[0].clone()))
+            }),
+        };
+
+        if let Some((oracle, reference)) = reference {
+            let mut is_wrong = false;
+
+            for (config, distinct) in outputs.iter() {
+                if oracle.as_ref() == Some(config) || distinct.len() > 1 {
+                    continue;
+                }
+
+                let is_match = matches(&distinct[0], &reference);
+                printer.print_output_check(config, oracle.as_ref(), is_match)?;
+
+                if !is_match {
+                    let diffs = buffer_check::diff_normalized(
+                        &distinct[0],
+                        &reference,
+                        &pipeline_desc,
+                        &type_descs,
+                        &policy,
+                    );
+                    printer.print_output_diff(config, oracle.as_ref(), &diffs)?;
+                    is_wrong = true;
+                }
+            }
+
+            if is_wrong {
+                return Ok(ExecutionResult::Mismatch);
+            }
+        }
+
+        // Without a reference, the configs that disagree are compared with the most common output
+        // (or the first one seen, if there is no majority)
+        if let [reference, rest @ ..] = deterministic.classes.as_slice() {
+            for class in rest {
+                let diffs = buffer_check::diff_normalized(
+                    &class.output,
+                    &reference.output,
+                    &pipeline_desc,
+                    &type_descs,
+                    &policy,
+                );
+
+                for config in &class.members {
+                    printer.print_output_diff(config, Some(reference.members[0]), &diffs)?;
+                }
+            }
+        }
+
+        Ok(if !deterministic.is_unanimous() {
+            ExecutionResult::Mismatch
+        } else if has_missed_writes {
+            ExecutionResult::MissedWrites
+        } else if is_nondeterministic {
+            ExecutionResult::Nondeterministic
+        } else if !timeouts.is_empty() {
+            ExecutionResult::Timeout
+        } else {
+            ExecutionResult::Ok
+        })
     }
 }
@@ -1,30 +1,264 @@
 use bincode::{Decode, Encode};
+use buffer_check::{ComparisonPolicy, Digest, ValueDiff};
 use chrono::Local;
+use clap::ValueEnum;
+use common::Type;
 use reflection::{PipelineDescription, ResourceKind};
+use serde_json::{json, Value};
+use std::fmt::{self, Display};
 use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-use types::{Config, ConfigId};
+use types::{Config, ConfigId, Environment, Timings, Unsupported};
+
+use crate::InputData;
 
 #[derive(Decode, Encode)]
 pub enum ExecutionEvent {
+    /// The machine and build of the harness that executes the configs, which is reported before
+    /// any of them start.
+    Environment(Environment),
     UsingDefaultConfigs(Vec<ConfigId>),
+    UsingOracle(ConfigId),
+    /// The config's adapter is in use by other requests to the server, with the given number of
+    /// executions ahead of this one. This is reported again whenever the number changes.
+    Queued(ConfigId, usize),
     Start(ConfigId),
-    Success(ConfigId, Vec<Vec<u8>>),
-    Failure(Vec<u8>),
-    Timeout,
+    /// The config was skipped because it can't execute the shader.
+    Unsupported(ConfigId, Unsupported),
+    /// The execution succeeded with the contents of the storage buffers.
+    Success(ConfigId, Vec<Vec<u8>>, Timings),
+    CompilationFailure(ConfigId, Vec<u8>),
+    ValidationFailure(ConfigId, Vec<u8>),
+    RuntimeFailure(ConfigId, Vec<u8>),
+    DeviceLost(ConfigId, Vec<u8>),
+    Timeout(ConfigId),
+    /// The execution was stopped by the server's watchdog, and its device was torn down.
+    Hang(ConfigId),
 }
 
 pub enum ExecutionResult {
     Ok,
     Mismatch,
+    Timeout,
+    Nondeterministic,
+    MissedWrites,
+    /// An execution failed, which is only reported by batches since a single run panics instead.
+    Failure,
+}
+
+impl ExecutionResult {
+    fn name(&self) -> &'static str {
+        match self {
+            ExecutionResult::Ok => "ok",
+            ExecutionResult::Mismatch => "mismatch",
+            ExecutionResult::Timeout => "timeout",
+            ExecutionResult::Nondeterministic => "nondeterministic",
+            ExecutionResult::MissedWrites => "missed_writes",
+            ExecutionResult::Failure => "failure",
+        }
+    }
+}
+
+/// Number of differing values that are printed for each output, so that a miscompilation which
+/// changes a whole buffer doesn't flood the output.
+pub(crate) const MAX_PRINTED_DIFFS: usize = 16;
+
+/// Summary of the durations of one stage of a config's executions in a benchmark.
+#[derive(Clone, Copy, Debug)]
+pub struct BenchStats {
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    pub stddev: Duration,
+}
+
+impl BenchStats {
+    /// Summarizes the samples, or returns `None` if there are none.
+    pub fn of(samples: &[Duration]) -> Option<BenchStats> {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+
+        let len = sorted.len();
+        let median = match len {
+            0 => return None,
+            _ if len.is_multiple_of(2) => (sorted[len / 2 - 1] + sorted[len / 2]) / 2,
+            _ => sorted[len / 2],
+        };
+
+        let secs = sorted.iter().map(Duration::as_secs_f64).collect::<Vec<_>>();
+        let mean = secs.iter().sum::<f64>() / len as f64;
+        let variance = secs.iter().map(|it| (it - mean).powi(2)).sum::<f64>() / len as f64;
+
+        Some(BenchStats {
+            min: sorted[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            max: sorted[len - 1],
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+
+    fn to_json(self) -> Value {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        json!({
+            "min_ms": ms(self.min),
+            "median_ms": ms(self.median),
+            "mean_ms": ms(self.mean),
+            "max_ms": ms(self.max),
+            "stddev_ms": ms(self.stddev),
+        })
+    }
+}
+
+impl Display for BenchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "median {:.2?}, mean {:.2?} ± {:.2?}, min {:.2?}, max {:.2?}",
+            self.median, self.mean, self.stddev, self.min, self.max
+        )
+    }
+}
+
+/// How the results of a run are printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored text for reading in a terminal.
+    #[default]
+    Human,
+    /// A JSON object on each line, with an `event` field that gives its kind.
+    Json,
 }
 
 #[derive(Default)]
-pub struct Printer;
+pub struct Printer {
+    format: OutputFormat,
+    /// When each config that is currently executing was started, which is used to report how long
+    /// its execution took.
+    starts: Mutex<Vec<(ConfigId, Instant)>>,
+}
 
 impl Printer {
     pub fn new() -> Printer {
-        Printer
+        Printer::default()
+    }
+
+    pub fn with_format(format: OutputFormat) -> Printer {
+        Printer {
+            format,
+            ..Printer::default()
+        }
+    }
+
+    fn print_json(&self, value: Value) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer(&mut stdout, &value)?;
+        writeln!(stdout)
+    }
+
+    /// Returns the time in milliseconds since the config started executing, if it was started.
+    fn elapsed_ms(&self, config: &ConfigId) -> Option<u128> {
+        let mut starts = self.starts.lock().unwrap();
+        let index = starts.iter().position(|(it, _)| it == config)?;
+        let (_, start) = starts.swap_remove(index);
+        Some(start.elapsed().as_millis())
+    }
+
+    fn execution_event_json(
+        &self,
+        event: &ExecutionEvent,
+        pipeline_desc: &PipelineDescription,
+        type_descs: &[Type],
+        policy: &ComparisonPolicy,
+    ) -> Value {
+        let failure = |kind: &str, config: &ConfigId, stderr: &[u8]| {
+            json!({
+                "event": kind,
+                "config": config.to_string(),
+                "elapsed_ms": self.elapsed_ms(config),
+                "message": String::from_utf8_lossy(stderr),
+            })
+        };
+
+        match event {
+            ExecutionEvent::Environment(environment) => {
+                let mut value = environment_json(environment);
+                value["event"] = json!("environment");
+                value
+            }
+            ExecutionEvent::UsingDefaultConfigs(configs) => json!({
+                "event": "default_configs",
+                "configs": configs.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            }),
+            ExecutionEvent::UsingOracle(config) => {
+                json!({ "event": "oracle", "config": config.to_string() })
+            }
+            ExecutionEvent::Queued(config, ahead) => json!({
+                "event": "queued",
+                "config": config.to_string(),
+                "ahead": ahead,
+            }),
+            ExecutionEvent::Start(config) => {
+                json!({ "event": "start", "config": config.to_string() })
+            }
+            ExecutionEvent::Unsupported(config, reason) => json!({
+                "event": "unsupported",
+                "config": config.to_string(),
+                "reason": reason.to_string(),
+            }),
+            ExecutionEvent::Success(config, buffers, timings) => {
+                let digest = Digest::of_execution(buffers, pipeline_desc, type_descs, policy);
+                let buffers = pipeline_desc
+                    .resources
+                    .iter()
+                    .filter(|it| it.kind == ResourceKind::StorageBuffer)
+                    .zip(buffers)
+                    .map(|(resource, buffer)| {
+                        json!({
+                            "binding": format!("{}:{}", resource.group, resource.binding),
+                            "size": buffer.len(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                json!({
+                    "event": "success",
+                    "config": config.to_string(),
+                    "digest": digest.to_string(),
+                    "elapsed_ms": self.elapsed_ms(config),
+                    "timings": {
+                        "shader_module_ms": timings.shader_module.as_secs_f64() * 1000.0,
+                        "pipeline_ms": timings.pipeline.as_secs_f64() * 1000.0,
+                        "dispatch_ms": timings.dispatch.as_secs_f64() * 1000.0,
+                    },
+                    "buffers": buffers,
+                })
+            }
+            ExecutionEvent::CompilationFailure(config, stderr) => {
+                failure("compilation_failure", config, stderr)
+            }
+            ExecutionEvent::ValidationFailure(config, stderr) => {
+                failure("validation_failure", config, stderr)
+            }
+            ExecutionEvent::RuntimeFailure(config, stderr) => {
+                failure("runtime_failure", config, stderr)
+            }
+            ExecutionEvent::DeviceLost(config, stderr) => failure("device_lost", config, stderr),
+            ExecutionEvent::Timeout(config) => json!({
+                "event": "timeout",
+                "config": config.to_string(),
+                "elapsed_ms": self.elapsed_ms(config),
+            }),
+            ExecutionEvent::Hang(config) => json!({
+                "event": "hang",
+                "config": config.to_string(),
+                "elapsed_ms": self.elapsed_ms(config),
+            }),
+        }
     }
 }
 
@@ -38,25 +272,24 @@ impl Printer {
             .max()
             .unwrap_or(0);
 
+        // Software adapters are marked after their name, which is included in the column width
         let name_width = configs
             .iter()
-            .map(|it| it.adapter_name.len())
+            .map(|it| it.adapter_name.len() + if it.software { 11 } else { 0 })
             .max()
-            .unwrap_or(0);
+            .unwrap_or(0)
+            .max("Adapter Name".len());
 
         stdout.set_color(&dimmed())?;
 
-        writeln!(&mut stdout, "{:<id_width$} | Adapter Name", "ID")?;
+        writeln!(
+            &mut stdout,
+            "{:<id_width$} | Vendor | {:<name_width$} | Driver",
+            "ID", "Adapter Name"
+        )?;
 
-        for _ in 0..id_width + 1 {
-            write!(&mut stdout, "-")?;
-        }
-
-        write!(&mut stdout, "+")?;
-
-        for _ in 0..name_width + 1 {
-            write!(&mut stdout, "-")?;
-        }
+        let columns = [id_width + 1, 8, name_width + 2, 7].map(|width| "-".repeat(width));
+        write!(&mut stdout, "{}", columns.join("+"))?;
 
         stdout.reset()?;
         writeln!(&mut stdout)?;
@@ -72,12 +305,64 @@ impl Printer {
             write!(&mut stdout, " | ")?;
 
             stdout.reset()?;
-            writeln!(&mut stdout, "{name}")?;
+            write!(&mut stdout, "{:04x}  ", config.vendor_id)?;
+
+            stdout.set_color(&dimmed())?;
+            write!(&mut stdout, " | ")?;
+
+            stdout.reset()?;
+            write!(&mut stdout, "{name}")?;
+
+            let mut padding = name_width - name.len();
+
+            if config.software {
+                stdout.set_color(&dimmed())?;
+                write!(&mut stdout, " (software)")?;
+                stdout.reset()?;
+                padding -= 11;
+            }
+
+            stdout.set_color(&dimmed())?;
+            write!(&mut stdout, "{:padding$} | ", "")?;
+
+            stdout.reset()?;
+            writeln!(&mut stdout, "{}", config.driver)?;
         }
 
         Ok(())
     }
 
+    fn print_environment(&self, environment: &Environment) -> io::Result<()> {
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        stdout.set_color(&dimmed())?;
+        write!(&mut stdout, "environment: {}", environment.os)?;
+
+        // Commits are shortened in the same way as by git
+        if let Some(commit) = &environment.dawn_commit {
+            write!(&mut stdout, ", dawn {}", &commit[..commit.len().min(7)])?;
+        }
+
+        if let Some(commit) = &environment.wgpu_commit {
+            write!(&mut stdout, ", wgpu {}", &commit[..commit.len().min(7)])?;
+        }
+
+        writeln!(&mut stdout)?;
+
+        for config in &environment.configs {
+            write!(&mut stdout, "  {}: {}", config.id, config.adapter_name)?;
+            if !config.driver.is_empty() {
+                write!(&mut stdout, " ({})", config.driver)?;
+            }
+            writeln!(&mut stdout)?;
+        }
+
+        stdout.reset()?;
+        writeln!(&mut stdout)?;
+
+        Ok(())
+    }
+
     fn print_default_configs(&self, configs: &[ConfigId]) -> io::Result<()> {
         let mut stdout = StandardStream::stdout(ColorChoice::Auto);
 
@@ -99,6 +384,40 @@ impl Printer {
         Ok(())
     }
 
+    fn print_oracle(&self, config: &ConfigId) -> io::Result<()> {
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        write!(&mut stdout, "using oracle: ")?;
+        self.print_config(&mut stdout, config)?;
+        writeln!(&mut stdout)?;
+        writeln!(&mut stdout)?;
+
+        Ok(())
+    }
+
+    fn print_queued(&self, config: &ConfigId, ahead: usize) -> io::Result<()> {
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+        write!(&mut stdout, "[{}]", Local::now().format("%H:%M:%S"))?;
+        stdout.reset()?;
+
+        write!(&mut stdout, " queued ")?;
+        self.print_config(&mut stdout, config)?;
+
+        stdout.set_color(&dimmed())?;
+        let executions = if ahead == 1 {
+            "execution"
+        } else {
+            "executions"
+        };
+        write!(&mut stdout, " ({ahead} {executions} ahead)")?;
+        stdout.reset()?;
+        writeln!(&mut stdout)?;
+
+        Ok(())
+    }
+
     fn print_pre_execution(
         &self,
         config: &ConfigId,
@@ -133,10 +452,26 @@ impl Printer {
         Ok(())
     }
 
+    fn print_unsupported(&self, config: &ConfigId, reason: &Unsupported) -> io::Result<()> {
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        stdout.set_color(&yellow())?;
+        write!(&mut stdout, "skipping ")?;
+        stdout.reset()?;
+
+        self.print_config(&mut stdout, config)?;
+
+        writeln!(&mut stdout, ": {reason}")?;
+        writeln!(&mut stdout)?;
+
+        Ok(())
+    }
+
     fn print_post_execution(
         &self,
         config: &ConfigId,
         buffers: &[Vec<u8>],
+        timings: &Timings,
         pipeline_desc: &PipelineDescription,
     ) -> io::Result<()> {
         let mut stdout = StandardStream::stdout(ColorChoice::Auto);
@@ -163,6 +498,15 @@ impl Printer {
             writeln!(&mut stdout, "  none")?;
         }
 
+        stdout.set_color(&dimmed())?;
+        write!(
+            &mut stdout,
+            "timings: shader module {:.2?}, pipeline {:.2?}, dispatch {:.2?}",
+            timings.shader_module, timings.pipeline, timings.dispatch
+        )?;
+        stdout.reset()?;
+        writeln!(&mut stdout)?;
+
         writeln!(&mut stdout)?;
 
         Ok(())
@@ -175,46 +519,346 @@ impl Printer {
         Ok(())
     }
 
+    fn print_failure(&self, label: &str, config: &ConfigId, stderr: &[u8]) -> io::Result<()> {
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+        stdout.set_color(&red())?;
+        write!(stdout, "{label}")?;
+        stdout.reset()?;
+        write!(stdout, " (")?;
+        self.print_config(&mut stdout, config)?;
+        writeln!(stdout, ")")?;
+        stdout.write_all(stderr)?;
+        writeln!(stdout)?;
+        Ok(())
+    }
+
+    /// Prints an event of an execution. The types of the output buffers and the comparison policy
+    /// are used to compute the digests of outputs in JSON events.
     pub fn print_execution_event(
         &self,
         event: &ExecutionEvent,
         pipeline_desc: &PipelineDescription,
+        type_descs: &[Type],
+        policy: &ComparisonPolicy,
     ) -> io::Result<()> {
+        if let ExecutionEvent::Start(config) = event {
+            let mut starts = self.starts.lock().unwrap();
+            starts.retain(|(it, _)| it != config);
+            starts.push((config.clone(), Instant::now()));
+        }
+
+        if self.format == OutputFormat::Json {
+            let value = self.execution_event_json(event, pipeline_desc, type_descs, policy);
+            return self.print_json(value);
+        }
+
         match event {
+            ExecutionEvent::Environment(environment) => self.print_environment(environment),
             ExecutionEvent::UsingDefaultConfigs(configs) => self.print_default_configs(configs),
+            ExecutionEvent::UsingOracle(config) => self.print_oracle(config),
+            ExecutionEvent::Queued(config, ahead) => self.print_queued(config, *ahead),
             ExecutionEvent::Start(config) => self.print_pre_execution(config, pipeline_desc),
-            ExecutionEvent::Success(config, buffers) => {
-                self.print_post_execution(config, buffers, pipeline_desc)
+            ExecutionEvent::Unsupported(config, reason) => self.print_unsupported(config, reason),
+            ExecutionEvent::Success(config, buffers, timings) => {
+                self.print_post_execution(config, buffers, timings, pipeline_desc)
             }
-            ExecutionEvent::Failure(stderr) => {
-                std::io::stdout().write_all(stderr)?;
-                println!();
-                Ok(())
+            ExecutionEvent::CompilationFailure(config, stderr) => {
+                self.print_failure("compilation failure", config, stderr)
+            }
+            ExecutionEvent::ValidationFailure(config, stderr) => {
+                self.print_failure("validation failure", config, stderr)
+            }
+            ExecutionEvent::RuntimeFailure(config, stderr) => {
+                self.print_failure("runtime failure", config, stderr)
             }
-            ExecutionEvent::Timeout => {
+            ExecutionEvent::DeviceLost(config, stderr) => {
+                self.print_failure("device lost", config, stderr)
+            }
+            ExecutionEvent::Timeout(config) => {
                 let mut stdout = StandardStream::stdout(ColorChoice::Auto);
                 stdout.set_color(&yellow())?;
-                writeln!(stdout, "timeout")?;
+                write!(stdout, "timeout")?;
+                stdout.reset()?;
+                write!(stdout, " (")?;
+                self.print_config(&mut stdout, config)?;
+                writeln!(stdout, ")")?;
+                writeln!(stdout)?;
+                Ok(())
+            }
+            ExecutionEvent::Hang(config) => {
+                let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+                stdout.set_color(&red())?;
+                write!(stdout, "hang")?;
                 stdout.reset()?;
+                write!(stdout, " (")?;
+                self.print_config(&mut stdout, config)?;
+                writeln!(stdout, ")")?;
                 writeln!(stdout)?;
                 Ok(())
             }
         }
     }
 
-    pub fn print_execution_result(&self, result: ExecutionResult) -> io::Result<()> {
+    /// Prints input data with generated uniform buffers, which reproduces the run when it is passed
+    /// back to the harness.
+    pub fn print_generated_inputs(&self, seed: u64, input_data: &InputData) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            return self.print_json(json!({
+                "event": "generated_inputs",
+                "seed": seed,
+                "inputs": input_data,
+            }));
+        }
+
         let mut stdout = StandardStream::stdout(ColorChoice::Auto);
 
-        match result {
-            ExecutionResult::Ok => {
-                stdout.set_color(&green())?;
-                writeln!(stdout, "ok")?;
-                stdout.reset()?;
+        writeln!(stdout, "generated inputs from seed {seed}:")?;
+        stdout.set_color(&dimmed())?;
+        serde_json::to_writer(&mut stdout, input_data)?;
+        stdout.reset()?;
+        writeln!(stdout)?;
+        writeln!(stdout)?;
+
+        Ok(())
+    }
+
+    /// Prints whether the output of a config matches the expected output, or the output of the
+    /// oracle if there is one.
+    pub fn print_output_check(
+        &self,
+        config: &ConfigId,
+        oracle: Option<&ConfigId>,
+        matches: bool,
+    ) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            return self.print_json(json!({
+                "event": "output_check",
+                "config": config.to_string(),
+                "oracle": oracle.map(ToString::to_string),
+                "matches": matches,
+            }));
+        }
+
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        match oracle {
+            Some(oracle) => {
+                write!(&mut stdout, "oracle output (")?;
+                self.print_config(&mut stdout, oracle)?;
+                write!(&mut stdout, " vs ")?;
             }
-            ExecutionResult::Mismatch => {
-                stdout.set_color(&red())?;
-                writeln!(stdout, "mismatch")?;
-                stdout.reset()?;
+            None => write!(&mut stdout, "expected output (")?,
+        }
+
+        self.print_config(&mut stdout, config)?;
+        write!(&mut stdout, "): ")?;
+
+        if matches {
+            stdout.set_color(&green())?;
+            writeln!(stdout, "pass")?;
+        } else {
+            stdout.set_color(&red())?;
+            writeln!(stdout, "fail")?;
+        }
+
+        stdout.reset()?;
+
+        Ok(())
+    }
+
+    /// Prints the values in which the output of a config differs from the output of `reference`,
+    /// or from the expected output if there is no reference config.
+    pub fn print_output_diff(
+        &self,
+        config: &ConfigId,
+        reference: Option<&ConfigId>,
+        diffs: &[ValueDiff],
+    ) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            return self.print_json(json!({
+                "event": "output_diff",
+                "config": config.to_string(),
+                "reference": reference.map(ToString::to_string),
+                "count": diffs.len(),
+                "diffs": diffs.iter().take(MAX_PRINTED_DIFFS).map(diff_json).collect::<Vec<_>>(),
+            }));
+        }
+
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        self.print_config(&mut stdout, config)?;
+        write!(stdout, " differs from ")?;
+        match reference {
+            Some(reference) => self.print_config(&mut stdout, reference)?,
+            None => write!(stdout, "the expected output")?,
+        }
+        let values = if diffs.len() == 1 { "value" } else { "values" };
+        writeln!(stdout, " in {} {values}:", diffs.len())?;
+
+        for diff in diffs.iter().take(MAX_PRINTED_DIFFS) {
+            writeln!(
+                stdout,
+                "  {} {}: {} vs {}",
+                diff.binding, diff.path, diff.value, diff.reference
+            )?;
+        }
+
+        if diffs.len() > MAX_PRINTED_DIFFS {
+            stdout.set_color(&dimmed())?;
+            write!(stdout, "  ... {} more", diffs.len() - MAX_PRINTED_DIFFS)?;
+            stdout.reset()?;
+            writeln!(stdout)?;
+        }
+
+        writeln!(stdout)?;
+
+        Ok(())
+    }
+
+    /// Prints that the output of a config differed between repeated runs.
+    pub fn print_nondeterministic(
+        &self,
+        config: &ConfigId,
+        outputs: usize,
+        runs: u32,
+    ) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            return self.print_json(json!({
+                "event": "nondeterministic",
+                "config": config.to_string(),
+                "outputs": outputs,
+                "runs": runs,
+            }));
+        }
+
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        stdout.set_color(&red())?;
+        write!(stdout, "nondeterministic output")?;
+        stdout.reset()?;
+        write!(stdout, " (")?;
+        self.print_config(&mut stdout, config)?;
+        writeln!(stdout, "): {outputs} distinct outputs in {runs} runs")?;
+
+        Ok(())
+    }
+
+    /// Prints the ranges of each output buffer that still held the canary pattern after a config
+    /// executed the shader, keyed by `group:binding`.
+    pub fn print_missed_writes(
+        &self,
+        config: &ConfigId,
+        missed_writes: &[(&str, Vec<Range<usize>>)],
+    ) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            let buffers = missed_writes
+                .iter()
+                .map(|(key, ranges)| {
+                    let ranges = ranges
+                        .iter()
+                        .map(|it| [it.start, it.end])
+                        .collect::<Vec<_>>();
+                    (key.to_string(), json!(ranges))
+                })
+                .collect::<serde_json::Map<_, _>>();
+
+            return self.print_json(json!({
+                "event": "missed_writes",
+                "config": config.to_string(),
+                "buffers": buffers,
+            }));
+        }
+
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        stdout.set_color(&red())?;
+        write!(stdout, "missed writes")?;
+        stdout.reset()?;
+        write!(stdout, " (")?;
+        self.print_config(&mut stdout, config)?;
+        writeln!(stdout, "):")?;
+
+        for (key, ranges) in missed_writes {
+            writeln!(stdout, "  {key} : {ranges:?}")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn print_execution_result(&self, result: ExecutionResult) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            return self.print_json(json!({ "event": "result", "result": result.name() }));
+        }
+
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+        write_execution_result(&mut stdout, &result)?;
+        writeln!(stdout)?;
+        Ok(())
+    }
+
+    /// Prints how long a config took to compile and execute the shader over the iterations of a
+    /// benchmark. Compilation is the time taken to create the shader module and the pipeline.
+    pub fn print_bench_result(
+        &self,
+        config: &ConfigId,
+        iterations: usize,
+        compilation: BenchStats,
+        execution: BenchStats,
+    ) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            return self.print_json(json!({
+                "event": "bench_result",
+                "config": config.to_string(),
+                "iterations": iterations,
+                "compilation": compilation.to_json(),
+                "execution": execution.to_json(),
+            }));
+        }
+
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        self.print_config(&mut stdout, config)?;
+        writeln!(stdout, " ({iterations} iterations)")?;
+        writeln!(stdout, "  compilation: {compilation}")?;
+        writeln!(stdout, "  execution:   {execution}")?;
+        writeln!(stdout)?;
+
+        Ok(())
+    }
+
+    /// Prints the result of a shader in a batch, followed by the outcome of each config unless the
+    /// result is ok.
+    pub fn print_batch_result(
+        &self,
+        shader: &str,
+        result: ExecutionResult,
+        outcomes: &[(ConfigId, String)],
+    ) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            let configs = outcomes
+                .iter()
+                .map(|(config, outcome)| (config.to_string(), json!(outcome)))
+                .collect::<serde_json::Map<_, _>>();
+
+            return self.print_json(json!({
+                "event": "batch_result",
+                "shader": shader,
+                "result": result.name(),
+                "configs": configs,
+            }));
+        }
+
+        let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+        write!(stdout, "{shader}: ")?;
+        write_execution_result(&mut stdout, &result)?;
+        writeln!(stdout)?;
+
+        if !matches!(result, ExecutionResult::Ok) {
+            for (config, outcome) in outcomes {
+                write!(stdout, "  ")?;
+                self.print_config(&mut stdout, config)?;
+                writeln!(stdout, ": {outcome}")?;
             }
         }
 
@@ -222,6 +866,49 @@ impl Printer {
     }
 }
 
+fn write_execution_result(stdout: &mut StandardStream, result: &ExecutionResult) -> io::Result<()> {
+    let color = match result {
+        ExecutionResult::Ok => green(),
+        ExecutionResult::Timeout => yellow(),
+        ExecutionResult::Mismatch
+        | ExecutionResult::Nondeterministic
+        | ExecutionResult::MissedWrites
+        | ExecutionResult::Failure => red(),
+    };
+
+    stdout.set_color(&color)?;
+    write!(stdout, "{}", result.name().replace('_', " "))?;
+    stdout.reset()
+}
+
+/// Describes a value that differs between two outputs as JSON. The values are formatted as strings,
+/// since JSON can't represent every float (e.g. NaN).
+pub(crate) fn diff_json(diff: &ValueDiff) -> Value {
+    json!({
+        "binding": diff.binding,
+        "path": diff.path,
+        "value": diff.value.to_string(),
+        "reference": diff.reference.to_string(),
+    })
+}
+
+/// Describes the environment as JSON, which is also printed for `--print-consensus` so that it can
+/// be saved with test cases.
+pub fn environment_json(environment: &Environment) -> Value {
+    json!({
+        "os": environment.os,
+        "dawn_commit": environment.dawn_commit,
+        "wgpu_commit": environment.wgpu_commit,
+        "adapters": environment.configs.iter().map(|config| json!({
+            "config": config.id.to_string(),
+            "name": config.adapter_name,
+            "vendor_id": config.vendor_id,
+            "driver": config.driver,
+            "software": config.software,
+        })).collect::<Vec<_>>(),
+    })
+}
+
 fn dimmed() -> ColorSpec {
     let mut spec = ColorSpec::new();
     spec.set_dimmed(true);
@@ -16,6 +16,8 @@ pub fn remove_accessed_vars(vars: &mut HashSet<String>, module: &Module) {
 fn visit_stmt(vars: &mut HashSet<String>, stmt: &Statement) {
     match stmt {
         Statement::LetDecl(decl) => visit_expr(vars, &decl.initializer),
+        Statement::ConstDecl(decl) => visit_expr(vars, &decl.initializer),
+        Statement::ConstAssert(stmt) => visit_expr(vars, &stmt.expr),
         Statement::VarDecl(decl) => {
             if let Some(init) = &decl.initializer {
                 visit_expr(vars, init);
@@ -29,6 +31,7 @@ fn visit_stmt(vars: &mut HashSet<String>, stmt: &Statement) {
 
             visit_expr(vars, &stmt.rhs);
         }
+        Statement::Increment(lhs) | Statement::Decrement(lhs) => visit_lhs_expr(vars, lhs),
         Statement::Compound(stmts) => {
             for stmt in stmts {
                 visit_stmt(vars, stmt);
@@ -72,6 +75,16 @@ fn visit_stmt(vars: &mut HashSet<String>, stmt: &Statement) {
             for stmt in &stmt.body {
                 visit_stmt(vars, stmt);
             }
+
+            if let Some(continuing) = &stmt.continuing {
+                for stmt in &continuing.body {
+                    visit_stmt(vars, stmt);
+                }
+
+                if let Some(condition) = &continuing.break_if {
+                    visit_expr(vars, condition);
+                }
+            }
         }
         Statement::Break => {}
         Statement::Switch(stmt) => {
@@ -112,6 +125,9 @@ fn visit_stmt(vars: &mut HashSet<String>, stmt: &Statement) {
 
                         visit_expr(vars, &stmt.rhs);
                     }
+                    ForLoopUpdate::Increment(lhs) | ForLoopUpdate::Decrement(lhs) => {
+                        visit_lhs_expr(vars, lhs)
+                    }
                 }
             }
 
@@ -119,6 +135,13 @@ fn visit_stmt(vars: &mut HashSet<String>, stmt: &Statement) {
                 visit_stmt(vars, stmt);
             }
         }
+        Statement::While(stmt) => {
+            visit_expr(vars, &stmt.condition);
+
+            for stmt in &stmt.body {
+                visit_stmt(vars, stmt);
+            }
+        }
         Statement::FnCall(stmt) => {
             for arg in &stmt.args {
                 visit_expr(vars, arg);
@@ -151,6 +174,7 @@ fn visit_expr(vars: &mut HashSet<String>, node: &ExprNode) {
                 visit_expr(vars, arg);
             }
         }
+        Expr::Bitcast(expr) => visit_expr(vars, &expr.inner),
         Expr::Var(expr) => {
             vars.remove(expr.ident.as_str());
         }
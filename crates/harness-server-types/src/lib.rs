@@ -1,13 +1,50 @@
+use std::io::{self, Read, Write};
 use std::time::Duration;
 
 use bincode::{Decode, Encode};
 use reflection_types::PipelineDescription;
-use types::{Config, ConfigId};
+use types::{
+    AdapterFilter, BackendOptions, Config, ConfigId, Environment, RetryPolicy, Timings,
+    Unsupported, Variant,
+};
+
+/// Version of the protocol between clients and the server, which changes whenever a message does.
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Sent before the protocol version, so that a peer which doesn't send one (e.g. because it was
+/// built before the protocol was versioned) can be told apart from one with a different version.
+const MAGIC: [u8; 4] = *b"wgsl";
+
+/// Writes the protocol version, which both ends send before any messages. It isn't encoded with
+/// bincode, so that it can be read by every version regardless of how the messages have changed.
+pub fn write_version(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&PROTOCOL_VERSION.to_le_bytes())
+}
+
+/// Reads the protocol version of the other end, or `None` if it started with something else.
+pub fn read_version(reader: &mut impl Read) -> io::Result<Option<u32>> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+
+    let (magic, version) = bytes.split_at(4);
+    if magic != MAGIC {
+        return Ok(None);
+    }
+
+    Ok(Some(u32::from_le_bytes(version.try_into().unwrap())))
+}
 
 #[derive(Debug, Decode, Encode)]
 pub enum Request {
     List,
-    Run(RunRequest),
+    Run(Box<RunRequest>),
+    Status,
+    /// Cancels the run with the given job ID, which is sent on a new connection since the run's
+    /// connection is busy receiving its events.
+    Cancel(u64),
+    /// Fetches the output buffers of a run whose outputs were sent as digests, once it has ended.
+    Fetch(FetchRequest),
 }
 
 #[derive(Debug, Decode, Encode)]
@@ -15,26 +52,107 @@ pub struct ListResponse {
     pub configs: Vec<Config>,
 }
 
+/// Describes the server and how busy it is, so that tools which distribute shaders across servers
+/// can check that one is up and pick the least loaded.
+#[derive(Debug, Decode, Encode)]
+pub struct StatusResponse {
+    /// Version of the harness that the server was built from.
+    pub version: String,
+    pub protocol_version: u32,
+    /// The machine and build of the server, with every config that it can execute.
+    pub environment: Environment,
+    /// Number of executions that are waiting for an adapter.
+    pub queued: usize,
+    /// Number of adapters that are currently executing a shader.
+    pub running: usize,
+    /// How long the server has been running.
+    pub uptime: Duration,
+    /// How often the workers reused a compiled pipeline, or `None` if the server doesn't use
+    /// workers.
+    pub pipeline_cache: Option<CacheStats>,
+}
+
+/// Number of executions whose pipeline was or wasn't found in the cache of their worker.
+#[derive(Clone, Copy, Debug, Default, Decode, Encode)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Decode, Encode)]
+pub struct CancelResponse {
+    /// Whether the job was found. It may have already finished.
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Decode, Encode)]
+pub struct FetchRequest {
+    pub job: u64,
+    pub configs: Vec<ConfigId>,
+}
+
+#[derive(Debug, Decode, Encode)]
+pub struct FetchResponse {
+    /// The output buffers of each requested config, in the same order, or `None` if the server
+    /// doesn't have them (e.g. because they were already fetched, or the server has since run too
+    /// many other jobs to keep them).
+    pub outputs: Vec<Option<Vec<Vec<u8>>>>,
+}
+
+/// How the outputs of successful executions are sent to the client.
+#[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq)]
+pub enum OutputMode {
+    /// The output buffers are sent with each execution.
+    Buffers,
+    /// Only a digest of each output is sent, and the server keeps the buffers until the client
+    /// fetches the ones that it needs. NaNs and negative zero are canonicalized before the digest
+    /// is computed if `canonicalize_floats` is set.
+    Digests { canonicalize_floats: bool },
+}
+
 #[derive(Debug, Decode, Encode)]
 pub struct RunRequest {
     pub shader: String,
     pub pipeline_desc: PipelineDescription,
     pub configs: Vec<ConfigId>,
+    pub oracle: bool,
+    pub variants: Vec<Variant>,
+    pub filter: AdapterFilter,
+    pub options: BackendOptions,
     pub timeout: Option<Duration>,
+    pub retry: RetryPolicy,
+    pub outputs: OutputMode,
 }
 
 #[derive(Debug, Decode, Encode)]
 pub enum RunMessage {
+    /// The ID of the run, which can be used to cancel it. This is sent before any other messages.
+    Job(u64),
+    Environment(Environment),
     UsingDefaultConfigs(Vec<ConfigId>),
+    UsingOracle(ConfigId),
+    /// The number of executions that are ahead of the config's execution on its adapter.
+    ExecQueued(ConfigId, usize),
     ExecStart(ConfigId),
-    ExecSuccess(ConfigId, Vec<Vec<u8>>),
-    ExecFailure(Vec<u8>),
-    ExecTimeout,
+    /// The config was skipped because it can't execute the shader.
+    ExecUnsupported(ConfigId, Unsupported),
+    ExecSuccess(ConfigId, Vec<Vec<u8>>, Timings),
+    /// A successful execution in [`OutputMode::Digests`], with the BLAKE3 digest of its normalized
+    /// output.
+    ExecDigest(ConfigId, [u8; 32], Timings),
+    ExecCompilationFailure(ConfigId, Vec<u8>),
+    ExecValidationFailure(ConfigId, Vec<u8>),
+    ExecRuntimeFailure(ConfigId, Vec<u8>),
+    ExecDeviceLost(ConfigId, Vec<u8>),
+    ExecTimeout(ConfigId),
+    ExecHang(ConfigId),
     End(Result<(), RunError>),
 }
 
 #[derive(Debug, Decode, Encode)]
 pub enum RunError {
     NoDefaultConfigs,
+    NoOracleConfig,
+    Cancelled,
     InternalServerError,
 }
@@ -1,12 +1,22 @@
 use std::fmt::{Display, Write};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use bincode::{Decode, Encode};
+use reflection_types::{Feature, Limits, PipelineDescription};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq)]
 pub enum Implementation {
     Dawn,
     Wgpu,
+    /// Shaders are interpreted on the host, rather than executed by a WebGPU implementation.
+    Cpu,
+    /// Shaders are executed by the WebGPU implementation of a browser, which is controlled through
+    /// WebDriver.
+    Browser,
 }
 
 #[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq)]
@@ -14,13 +24,52 @@ pub enum BackendType {
     Dx12 = 3,
     Metal = 4,
     Vulkan = 5,
+    Interpreter = 6,
+    Chrome = 7,
+    Firefox = 8,
 }
 
-#[derive(Clone, Debug, Decode, Encode)]
+/// A different way of executing shaders on the same adapter, whose outputs are compared as those of
+/// a separate config.
+#[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq)]
+pub enum Variant {
+    /// Dawn compiles HLSL with FXC instead of DXC.
+    Fxc,
+    /// The shader is compiled to SPIR-V by naga in the harness, and passed through to the driver
+    /// by wgpu.
+    Spirv,
+}
+
+impl Variant {
+    /// Returns true if configs with the given implementation and backend can be run with this
+    /// variant.
+    pub fn is_supported_by(self, implementation: Implementation, backend: BackendType) -> bool {
+        match self {
+            Variant::Fxc => (implementation, backend) == (Implementation::Dawn, BackendType::Dx12),
+            Variant::Spirv => {
+                (implementation, backend) == (Implementation::Wgpu, BackendType::Vulkan)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Decode, Encode, PartialEq, Eq)]
 pub struct ConfigId {
     pub implementation: Implementation,
     pub backend: BackendType,
     pub device_id: u32,
+    pub variant: Option<Variant>,
+}
+
+impl ConfigId {
+    /// Returns the ID of the config that runs on the same adapter without a variant, which is the
+    /// one that is listed by `query_configs`.
+    pub fn base(&self) -> ConfigId {
+        ConfigId {
+            variant: None,
+            ..self.clone()
+        }
+    }
 }
 
 impl FromStr for ConfigId {
@@ -32,25 +81,49 @@ impl FromStr for ConfigId {
         let imp = tokens.next().ok_or("missing implementation segment")?;
         let backend = tokens.next().ok_or("missing backend segment")?;
         let device = tokens.next().ok_or("missing device id segment")?;
+        let variant = tokens.next();
 
         if tokens.next().is_some() {
             return Err("unexpected tokens");
         }
 
-        Ok(ConfigId {
+        let id = ConfigId {
             implementation: match imp {
                 "dawn" => Implementation::Dawn,
                 "wgpu" => Implementation::Wgpu,
+                "cpu" => Implementation::Cpu,
+                "browser" => Implementation::Browser,
                 _ => return Err("invalid implementation"),
             },
             backend: match backend {
                 "dx12" => BackendType::Dx12,
                 "mtl" => BackendType::Metal,
                 "vk" => BackendType::Vulkan,
+                "interp" => BackendType::Interpreter,
+                "chrome" => BackendType::Chrome,
+                "firefox" => BackendType::Firefox,
                 _ => return Err("invalid backend"),
             },
             device_id: device.parse().map_err(|_| "invalid device id")?,
-        })
+            variant: match variant {
+                None => None,
+                Some("fxc") => Some(Variant::Fxc),
+                Some("spirv") => Some(Variant::Spirv),
+                Some(_) => return Err("invalid variant"),
+            },
+        };
+
+        match id.variant {
+            Some(variant) if !variant.is_supported_by(id.implementation, id.backend) => {
+                return Err(match variant {
+                    Variant::Fxc => "the fxc variant is only supported by dawn:dx12 configs",
+                    Variant::Spirv => "the spirv variant is only supported by wgpu:vk configs",
+                })
+            }
+            _ => {}
+        }
+
+        Ok(id)
     }
 }
 
@@ -59,20 +132,34 @@ impl Display for ConfigId {
         let impl_id = match self.implementation {
             Implementation::Dawn => "dawn",
             Implementation::Wgpu => "wgpu",
+            Implementation::Cpu => "cpu",
+            Implementation::Browser => "browser",
         };
 
         let backend_id = match self.backend {
             BackendType::Dx12 => "dx12",
             BackendType::Metal => "mtl",
             BackendType::Vulkan => "vk",
+            BackendType::Interpreter => "interp",
+            BackendType::Chrome => "chrome",
+            BackendType::Firefox => "firefox",
         };
 
         let device = self.device_id;
 
-        let id_width =
-            impl_id.len() + backend_id.len() + ((self.device_id as f64).log10() as usize) + 3;
+        let variant = match self.variant {
+            None => "",
+            Some(Variant::Fxc) => ":fxc",
+            Some(Variant::Spirv) => ":spirv",
+        };
+
+        let id_width = impl_id.len()
+            + backend_id.len()
+            + ((self.device_id as f64).log10() as usize)
+            + variant.len()
+            + 3;
 
-        write!(f, "{impl_id}:{backend_id}:{device}")?;
+        write!(f, "{impl_id}:{backend_id}:{device}{variant}")?;
 
         if let Some(width) = f.width() {
             for _ in 0..width - id_width {
@@ -87,14 +174,29 @@ impl Display for ConfigId {
 #[derive(Debug)]
 pub struct Adapter {
     pub name: String,
+    /// PCI vendor ID of the device, or 0 if the backend doesn't report one.
+    pub vendor_id: u32,
     pub device_id: u32,
+    /// Description of the driver, which usually includes its version. This is empty if the
+    /// backend doesn't report one.
+    pub driver: String,
     pub backend: BackendType,
+    pub features: Vec<Feature>,
+    /// The limits that devices are created with.
+    pub limits: Limits,
+    /// Whether the adapter is a software rasterizer, such as lavapipe, SwiftShader or WARP.
+    pub software: bool,
 }
 
-#[derive(Debug, Decode, Encode)]
+#[derive(Clone, Debug, Decode, Encode)]
 pub struct Config {
     pub id: ConfigId,
     pub adapter_name: String,
+    pub vendor_id: u32,
+    pub driver: String,
+    pub features: Vec<Feature>,
+    pub limits: Limits,
+    pub software: bool,
 }
 
 impl Config {
@@ -104,8 +206,195 @@ impl Config {
                 implementation: imp,
                 backend: adapter.backend,
                 device_id: adapter.device_id,
+                variant: None,
             },
             adapter_name: adapter.name,
+            vendor_id: adapter.vendor_id,
+            driver: adapter.driver,
+            features: adapter.features,
+            limits: adapter.limits,
+            software: adapter.software,
+        }
+    }
+
+    /// Returns true if the adapter supports all of the features and limits required by the
+    /// pipeline.
+    pub fn supports(&self, pipeline_desc: &PipelineDescription) -> bool {
+        self.check_support(pipeline_desc).is_ok()
+    }
+
+    /// Returns why the adapter can't execute the pipeline, if it doesn't support the features or
+    /// limits that it requires.
+    pub fn check_support(&self, pipeline_desc: &PipelineDescription) -> Result<(), Unsupported> {
+        let missing = pipeline_desc
+            .features
+            .iter()
+            .filter(|it| !self.features.contains(it))
+            .copied()
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err(Unsupported::MissingFeatures(missing));
+        }
+
+        if !pipeline_desc.limits.is_within(&self.limits) {
+            return Err(Unsupported::LimitsTooLow {
+                required: pipeline_desc.limits,
+                available: self.limits,
+            });
         }
+
+        Ok(())
     }
 }
+
+/// The reason that a config is skipped for a shader.
+#[derive(Clone, Debug, Decode, Encode)]
+pub enum Unsupported {
+    /// The config's adapter wasn't found on the machine that executes the shader.
+    AdapterNotFound,
+    /// The adapter doesn't support these features, which the shader requires.
+    MissingFeatures(Vec<Feature>),
+    /// The shader requires higher limits than the adapter supports.
+    LimitsTooLow { required: Limits, available: Limits },
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unsupported::AdapterNotFound => write!(f, "adapter not found"),
+            Unsupported::MissingFeatures(features) => {
+                write!(f, "missing required features {features:?}")
+            }
+            Unsupported::LimitsTooLow {
+                required,
+                available,
+            } => write!(
+                f,
+                "required limits {required:?} exceed the adapter's limits {available:?}"
+            ),
+        }
+    }
+}
+
+/// Describes the machine that executed a shader and the build of the harness, so that saved
+/// results can still be triaged weeks later on a different machine.
+#[derive(Clone, Debug, Decode, Encode)]
+pub struct Environment {
+    /// Operating system and architecture, along with the distribution and kernel on Linux.
+    pub os: String,
+    /// Commit of the Dawn checkout that the harness was built from, if it is known.
+    pub dawn_commit: Option<String>,
+    /// Commit of the wgpu checkout that the harness was built from, if it is known.
+    pub wgpu_commit: Option<String>,
+    /// The adapters of the executed configs, which describe their drivers.
+    pub configs: Vec<Config>,
+}
+
+/// Restricts the adapters that default and oracle configs are selected from, e.g. to pin the
+/// devices that are used on a machine with several GPUs.
+#[derive(Clone, Debug, Default, Decode, Encode)]
+pub struct AdapterFilter {
+    /// Regex which must match the adapter name or driver description.
+    pub pattern: Option<String>,
+    /// PCI vendor IDs, one of which the adapter must have unless this is empty.
+    pub vendors: Vec<u32>,
+}
+
+impl AdapterFilter {
+    /// Returns true if the config's adapter passes the filter. Invalid patterns don't match any
+    /// adapters.
+    pub fn matches(&self, config: &Config) -> bool {
+        let matches_pattern = match &self.pattern {
+            Some(pattern) => Regex::new(pattern)
+                .is_ok_and(|it| it.is_match(&config.adapter_name) || it.is_match(&config.driver)),
+            None => true,
+        };
+
+        matches_pattern && (self.vendors.is_empty() || self.vendors.contains(&config.vendor_id))
+    }
+}
+
+/// How executions that fail in a way that may be flaky are retried, e.g. when the device is lost or
+/// the adapter can't be found while the GPU is being reset. The default doesn't retry.
+#[derive(Clone, Copy, Debug, Default, Decode, Encode)]
+pub struct RetryPolicy {
+    /// Number of times that a failing execution is retried before its failure is reported.
+    pub retries: u32,
+    /// How long to wait before the first retry, which doubles for each later one.
+    pub backoff: Duration,
+}
+
+/// Options that are applied when creating the device, which make it possible to check whether a
+/// bug depends on e.g. robustness or the HLSL compiler that is used.
+#[derive(Clone, Debug, Default, Decode, Encode, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BackendOptions {
+    /// Dawn toggles to enable, e.g. `disable_robustness`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enable_toggles: Vec<String>,
+    /// Dawn toggles to disable, e.g. `use_dxc`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable_toggles: Vec<String>,
+    /// wgpu instance flags to set, e.g. `validation`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enable_wgpu_flags: Vec<String>,
+    /// wgpu instance flags to clear, even if they are set by default.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable_wgpu_flags: Vec<String>,
+    /// Directory in which wgpu devices record an API trace, in a subdirectory named after their
+    /// config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_dir: Option<PathBuf>,
+}
+
+impl BackendOptions {
+    /// Adds the toggles and flags from `other`, which take precedence over these if they conflict.
+    /// The trace directory of `other` replaces this one if it has one.
+    pub fn extend(&mut self, other: BackendOptions) {
+        fn merge(enable: &mut Vec<String>, disable: &mut Vec<String>, other: Vec<String>) {
+            disable.retain(|it| !other.contains(it));
+            for name in other {
+                if !enable.contains(&name) {
+                    enable.push(name);
+                }
+            }
+        }
+
+        merge(
+            &mut self.enable_toggles,
+            &mut self.disable_toggles,
+            other.enable_toggles,
+        );
+        merge(
+            &mut self.disable_toggles,
+            &mut self.enable_toggles,
+            other.disable_toggles,
+        );
+        merge(
+            &mut self.enable_wgpu_flags,
+            &mut self.disable_wgpu_flags,
+            other.enable_wgpu_flags,
+        );
+        merge(
+            &mut self.disable_wgpu_flags,
+            &mut self.enable_wgpu_flags,
+            other.disable_wgpu_flags,
+        );
+
+        if other.trace_dir.is_some() {
+            self.trace_dir = other.trace_dir;
+        }
+    }
+}
+
+/// How long the stages of a successful execution took. Stages that an implementation doesn't have
+/// separately (e.g. the interpreter doesn't compile a pipeline) take no time.
+#[derive(Clone, Copy, Debug, Default, Decode, Encode)]
+pub struct Timings {
+    /// Creating the shader module, which includes parsing and validating the shader.
+    pub shader_module: Duration,
+    /// Creating the compute pipeline, which is usually when the backend compiles the shader.
+    pub pipeline: Duration,
+    /// Submitting the dispatch until the outputs have been read back.
+    pub dispatch: Duration,
+}
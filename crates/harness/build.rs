@@ -0,0 +1,41 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Records the commits of the Dawn and wgpu checkouts that the harness is built with, which are
+/// reported with every run.
+fn main() {
+    let root = Path::new("../..");
+
+    let dawn_src_dir = env::var("DAWN_SRC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| root.join("external/dawn"));
+
+    println!("cargo:rerun-if-env-changed=DAWN_SRC_DIR");
+
+    for (name, dir) in [("DAWN", dawn_src_dir), ("WGPU", root.join("external/wgpu"))] {
+        // Checkouts without git (e.g. source archives) just don't report a commit
+        if let Some(commit) = git(&dir, &["rev-parse", "HEAD"]) {
+            println!("cargo:rustc-env={name}_COMMIT={commit}");
+        }
+
+        if let Some(head) = git(&dir, &["rev-parse", "--git-path", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}", dir.join(head).display());
+        }
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+}
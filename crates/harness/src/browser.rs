@@ -0,0 +1,311 @@
+//! Executes shaders with the WebGPU implementation of a browser, which is controlled through a
+//! WebDriver server such as chromedriver or geckodriver.
+//!
+//! Browsers are only available if the URL of their WebDriver server is given by an environment
+//! variable, e.g. `WGSLSMITH_CHROMEDRIVER=http://localhost:9515`.
+
+mod webdriver;
+
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use eyre::eyre;
+use reflection::{
+    Feature, Limits, PipelineDescription, ResourceKind, TextureDimension, TextureSampleType,
+};
+use serde_json::{json, Value};
+
+use crate::{BackendError, BackendOptions, BackendType, ConfigId, ExecutionOutput, Timings};
+use webdriver::Session;
+
+/// Describes the browser's WebGPU adapter.
+const QUERY_SCRIPT: &str = include_str!("browser/query.js");
+
+/// Runs the shader and returns its storage buffers.
+const RUN_SCRIPT: &str = include_str!("browser/run.js");
+
+#[derive(Clone, Copy, Debug)]
+enum Browser {
+    Chrome,
+    Firefox,
+}
+
+impl Browser {
+    const ALL: [Browser; 2] = [Browser::Chrome, Browser::Firefox];
+
+    fn from_backend(backend: BackendType) -> Option<Browser> {
+        match backend {
+            BackendType::Chrome => Some(Browser::Chrome),
+            BackendType::Firefox => Some(Browser::Firefox),
+            _ => None,
+        }
+    }
+
+    fn backend(self) -> BackendType {
+        match self {
+            Browser::Chrome => BackendType::Chrome,
+            Browser::Firefox => BackendType::Firefox,
+        }
+    }
+
+    /// Returns the environment variable that holds the URL of the browser's WebDriver server.
+    fn driver_var(self) -> &'static str {
+        match self {
+            Browser::Chrome => "WGSLSMITH_CHROMEDRIVER",
+            Browser::Firefox => "WGSLSMITH_GECKODRIVER",
+        }
+    }
+
+    fn driver_url(self) -> Option<String> {
+        std::env::var(self.driver_var()).ok()
+    }
+
+    /// Returns the capabilities of a new session, which runs the browser headless with WebGPU
+    /// enabled.
+    ///
+    /// Chrome uses Dawn, so the toggles in `options` are passed on to it. Firefox doesn't provide
+    /// a way to set wgpu's instance flags, so they are ignored.
+    fn capabilities(self, options: &BackendOptions) -> Value {
+        match self {
+            Browser::Chrome => {
+                let mut args = vec![
+                    "--headless=new".to_owned(),
+                    "--enable-unsafe-webgpu".to_owned(),
+                    "--enable-features=Vulkan".to_owned(),
+                ];
+
+                if !options.enable_toggles.is_empty() {
+                    args.push(format!(
+                        "--enable-dawn-features={}",
+                        options.enable_toggles.join(",")
+                    ));
+                }
+
+                if !options.disable_toggles.is_empty() {
+                    args.push(format!(
+                        "--disable-dawn-features={}",
+                        options.disable_toggles.join(",")
+                    ));
+                }
+
+                json!({
+                    "browserName": "chrome",
+                    "goog:chromeOptions": { "args": args },
+                })
+            }
+            Browser::Firefox => json!({
+                "browserName": "firefox",
+                "moz:firefoxOptions": {
+                    "args": ["-headless"],
+                    "prefs": { "dom.webgpu.enabled": true },
+                },
+            }),
+        }
+    }
+}
+
+impl Display for Browser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Browser::Chrome => write!(f, "Chrome"),
+            Browser::Firefox => write!(f, "Firefox"),
+        }
+    }
+}
+
+pub fn get_adapters() -> Vec<types::Adapter> {
+    Browser::ALL
+        .into_iter()
+        .filter_map(|browser| {
+            let url = browser.driver_url()?;
+            query_adapter(browser, &url).unwrap_or_else(|e| {
+                log::warn!("failed to query the WebGPU adapter of {browser}: {e:#}");
+                None
+            })
+        })
+        .collect()
+}
+
+fn query_adapter(browser: Browser, url: &str) -> eyre::Result<Option<types::Adapter>> {
+    let session = Session::new(url, browser.capabilities(&BackendOptions::default()))?;
+    let info = session.execute_async(QUERY_SCRIPT, vec![])?;
+
+    if info.is_null() {
+        return Ok(None);
+    }
+
+    if let Some(error) = info["error"].as_str() {
+        return Err(eyre!("{error}"));
+    }
+
+    let names = |key: &str| {
+        info[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+
+    let features = names("features")
+        .iter()
+        .filter_map(|name| match name.as_str() {
+            "subgroups" => Some(Feature::Subgroups),
+            "shader-f16" => Some(Feature::ShaderF16),
+            _ => None,
+        })
+        .chain(
+            names("languageFeatures")
+                .iter()
+                .filter(|name| *name == "packed_4x8_integer_dot_product")
+                .map(|_| Feature::Packed4x8IntegerDotProduct),
+        )
+        .collect();
+
+    Ok(Some(types::Adapter {
+        name: format!("{browser}: {}", info["name"].as_str().unwrap_or_default()),
+        // Browsers don't expose PCI IDs
+        vendor_id: 0,
+        device_id: 0,
+        driver: format!("{browser} {}", session.browser_version),
+        backend: browser.backend(),
+        features,
+        // Devices are always created with the default limits
+        limits: Limits::DEFAULT,
+        software: info["software"].as_bool().unwrap_or(false),
+    }))
+}
+
+/// Converts the pipeline description to the JSON that is passed to the run script.
+fn describe_pipeline(meta: &PipelineDescription) -> Value {
+    let features = meta
+        .features
+        .iter()
+        .filter_map(|feature| match feature {
+            Feature::Subgroups => Some("subgroups"),
+            Feature::ShaderF16 => Some("shader-f16"),
+            // Push constants aren't exposed by WebGPU, so adapters never advertise them
+            Feature::PushConstants => None,
+            // Language extensions don't need to be requested from the device
+            Feature::Packed4x8IntegerDotProduct => None,
+        })
+        .collect::<Vec<_>>();
+
+    let resources = meta
+        .resources
+        .iter()
+        .map(|resource| {
+            let mut value = match &resource.kind {
+                ResourceKind::StorageBuffer => json!({ "kind": "storage" }),
+                ResourceKind::ReadOnlyStorageBuffer => json!({ "kind": "read-only-storage" }),
+                ResourceKind::UniformBuffer => json!({ "kind": "uniform" }),
+                ResourceKind::Texture(desc) => json!({
+                    "kind": "texture",
+                    "dimension": match desc.dimension {
+                        TextureDimension::D1 => "1d",
+                        TextureDimension::D3 => "3d",
+                        _ => "2d",
+                    },
+                    "viewDimension": match desc.dimension {
+                        TextureDimension::D1 => "1d",
+                        TextureDimension::D2 => "2d",
+                        TextureDimension::D2Array => "2d-array",
+                        TextureDimension::D3 => "3d",
+                        TextureDimension::Cube => "cube",
+                        TextureDimension::CubeArray => "cube-array",
+                    },
+                    "format": match desc.sample_type {
+                        TextureSampleType::Float => "rgba8unorm",
+                        TextureSampleType::Sint => "rgba8sint",
+                        TextureSampleType::Uint => "rgba8uint",
+                        TextureSampleType::Depth => "depth16unorm",
+                    },
+                    "size": desc.size,
+                    "mipLevelCount": desc.mip_level_count,
+                    "texelSize": desc.texel_size(),
+                }),
+                ResourceKind::Sampler { comparison } => json!({
+                    "kind": "sampler",
+                    "comparison": comparison,
+                }),
+            };
+
+            value["group"] = json!(resource.group);
+            value["binding"] = json!(resource.binding);
+            value["size"] = json!(resource.size);
+            value["init"] = json!(resource.init);
+            value
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "features": features,
+        "resources": resources,
+        "dispatch": meta.dispatch,
+    })
+}
+
+/// A browser session, which can execute any number of shaders. Each shader is executed with a new
+/// device, but starting the browser is by far the slowest part of an execution.
+pub struct Context {
+    browser: Browser,
+    session: Session,
+}
+
+impl Context {
+    pub fn new(options: &BackendOptions, config: &ConfigId) -> eyre::Result<Context> {
+        let browser = Browser::from_backend(config.backend)
+            .ok_or_else(|| eyre!("unsupported backend: {config}"))?;
+
+        let url = browser
+            .driver_url()
+            .ok_or_else(|| eyre!("{} is not set", browser.driver_var()))?;
+
+        let session = Session::new(&url, browser.capabilities(options))?;
+
+        Ok(Context { browser, session })
+    }
+
+    pub fn run(&self, shader: &str, meta: &PipelineDescription) -> eyre::Result<ExecutionOutput> {
+        // Firefox uses naga, which doesn't accept `enable subgroups;` yet - subgroup operations
+        // are instead allowed by requesting the feature when creating the device
+        let shader = match self.browser {
+            Browser::Firefox if meta.features.contains(&Feature::Subgroups) => {
+                shader.replace("enable subgroups;", "")
+            }
+            _ => shader.to_owned(),
+        };
+
+        let mut result = self
+            .session
+            .execute_async(RUN_SCRIPT, vec![json!(shader), describe_pipeline(meta)])?;
+
+        if let Some(error) = result.get("error") {
+            let message = error["message"].as_str().unwrap_or_default().to_owned();
+            return Err(match error["kind"].as_str() {
+                Some("compilation") => BackendError::Compilation(message),
+                Some("validation") => BackendError::Validation(message),
+                Some("device-lost") => BackendError::DeviceLost(message),
+                _ => BackendError::Runtime(message),
+            }
+            .into());
+        }
+
+        let buffers = serde_json::from_value(result["buffers"].take())?;
+
+        let time = |stage: &str| {
+            Duration::from_secs_f64(result["timings"][stage].as_f64().unwrap_or_default() / 1000.0)
+        };
+
+        Ok(ExecutionOutput {
+            buffers,
+            timings: Timings {
+                shader_module: time("shaderModule"),
+                pipeline: time("pipeline"),
+                dispatch: time("dispatch"),
+            },
+            cache_hit: None,
+        })
+    }
+}
@@ -0,0 +1,28 @@
+// Describes the browser's default WebGPU adapter, or returns null if WebGPU isn't available.
+//
+// Arguments: callback
+
+const done = arguments[arguments.length - 1];
+
+(async () => {
+  if (!navigator.gpu) {
+    return null;
+  }
+
+  const adapter = await navigator.gpu.requestAdapter();
+  if (!adapter) {
+    return null;
+  }
+
+  // Older browsers only provide the adapter info through `requestAdapterInfo`
+  const info = adapter.info ?? (await adapter.requestAdapterInfo?.()) ?? {};
+  const name =
+    info.description || [info.vendor, info.architecture].filter(Boolean).join(" ");
+
+  return {
+    name: name || "unknown adapter",
+    software: info.isFallbackAdapter ?? adapter.isFallbackAdapter ?? false,
+    features: [...adapter.features],
+    languageFeatures: [...(navigator.gpu.wgslLanguageFeatures ?? [])],
+  };
+})().then(done, (e) => done({ error: String(e) }));
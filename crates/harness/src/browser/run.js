@@ -0,0 +1,229 @@
+// Runs a compute shader with the browser's WebGPU implementation, and returns the contents of its
+// storage buffers and how long each stage took, or the error that it failed with.
+//
+// Arguments: shader, pipeline description, callback
+
+const [shader, desc, done] = arguments;
+
+const failure = (kind, message) => ({ error: { kind, message } });
+
+// Executes the shader on the device, and returns the result that is passed to the callback
+const execute = async (device) => {
+  // Devices are also reported as lost when they are destroyed, which isn't a failure
+  let lost = null;
+  device.lost.then((info) => {
+    if (info.reason !== "destroyed") {
+      lost = info.message;
+    }
+  });
+
+  // Later errors are usually caused by the first (e.g. by using an invalid bind group), so only it
+  // is kept
+  let uncaptured = null;
+  device.addEventListener("uncapturederror", (event) => {
+    uncaptured ??= event.error;
+  });
+
+  // Device loss is reported in preference to other errors, since it usually causes them
+  const checkDevice = () => {
+    if (lost !== null) {
+      return failure("device-lost", lost);
+    }
+
+    if (uncaptured !== null) {
+      const kind = uncaptured instanceof GPUValidationError ? "validation" : "runtime";
+      return failure(kind, uncaptured.message);
+    }
+
+    return null;
+  };
+
+  // Backend shader compilation errors are reported as internal errors
+  device.pushErrorScope("internal");
+  device.pushErrorScope("validation");
+
+  let start = performance.now();
+  const module = device.createShaderModule({ code: shader });
+  const shaderModuleTime = performance.now() - start;
+
+  // Pipelines may be compiled asynchronously, so compilation is only known to have finished once
+  // its errors have been reported
+  start = performance.now();
+  const pipeline = device.createComputePipeline({
+    layout: "auto",
+    compute: { module, entryPoint: "main" },
+  });
+
+  const validationError = await device.popErrorScope();
+  const internalError = await device.popErrorScope();
+  const pipelineTime = performance.now() - start;
+
+  if (validationError || internalError) {
+    const info = await module.getCompilationInfo();
+    const messages = info.messages
+      .filter((message) => message.type === "error")
+      .map((message) => `${message.lineNum}:${message.linePos}: ${message.message}`);
+
+    return failure(
+      "compilation",
+      [(validationError ?? internalError).message, ...messages].join("\n"),
+    );
+  }
+
+  // Bind group entries, keyed by group
+  const entries = new Map();
+  const outputs = [];
+
+  for (const resource of desc.resources) {
+    const binding = resource.binding;
+
+    if (!entries.has(resource.group)) {
+      entries.set(resource.group, []);
+    }
+
+    const groupEntries = entries.get(resource.group);
+
+    switch (resource.kind) {
+      case "storage": {
+        const buffer = device.createBuffer({
+          size: resource.size,
+          usage: GPUBufferUsage.STORAGE | GPUBufferUsage.COPY_SRC,
+          mappedAtCreation: Boolean(resource.init),
+        });
+
+        if (resource.init) {
+          new Uint8Array(buffer.getMappedRange()).set(resource.init);
+          buffer.unmap();
+        }
+
+        const staging = device.createBuffer({
+          size: resource.size,
+          usage: GPUBufferUsage.COPY_DST | GPUBufferUsage.MAP_READ,
+        });
+
+        groupEntries.push({ binding, resource: { buffer } });
+        outputs.push({ buffer, staging, size: resource.size });
+        break;
+      }
+      case "uniform":
+      case "read-only-storage": {
+        const buffer = device.createBuffer({
+          size: resource.size,
+          usage: resource.kind === "uniform" ? GPUBufferUsage.UNIFORM : GPUBufferUsage.STORAGE,
+          mappedAtCreation: true,
+        });
+
+        if (resource.init) {
+          new Uint8Array(buffer.getMappedRange()).set(resource.init);
+        }
+
+        buffer.unmap();
+
+        groupEntries.push({ binding, resource: { buffer } });
+        break;
+      }
+      case "texture": {
+        const [width, height, depthOrArrayLayers] = resource.size;
+
+        const texture = device.createTexture({
+          dimension: resource.dimension,
+          format: resource.format,
+          size: { width, height, depthOrArrayLayers },
+          mipLevelCount: resource.mipLevelCount,
+          usage: GPUTextureUsage.TEXTURE_BINDING | GPUTextureUsage.COPY_DST,
+        });
+
+        if (resource.init) {
+          device.queue.writeTexture(
+            { texture },
+            new Uint8Array(resource.init),
+            { bytesPerRow: width * resource.texelSize, rowsPerImage: height },
+            { width, height, depthOrArrayLayers },
+          );
+        }
+
+        groupEntries.push({
+          binding,
+          resource: texture.createView({ dimension: resource.viewDimension }),
+        });
+        break;
+      }
+      case "sampler": {
+        // Samplers use nearest filtering and clamp to the edge by default
+        const sampler = device.createSampler({
+          compare: resource.comparison ? "less" : undefined,
+        });
+
+        groupEntries.push({ binding, resource: sampler });
+        break;
+      }
+    }
+  }
+
+  const encoder = device.createCommandEncoder();
+  const pass = encoder.beginComputePass();
+  pass.setPipeline(pipeline);
+
+  for (const [group, groupEntries] of entries) {
+    const bindGroup = device.createBindGroup({
+      layout: pipeline.getBindGroupLayout(group),
+      entries: groupEntries,
+    });
+
+    pass.setBindGroup(group, bindGroup);
+  }
+
+  pass.dispatchWorkgroups(...desc.dispatch);
+  pass.end();
+
+  for (const { buffer, staging, size } of outputs) {
+    encoder.copyBufferToBuffer(buffer, 0, staging, 0, size);
+  }
+
+  start = performance.now();
+  device.queue.submit([encoder.finish()]);
+
+  const mappings = await Promise.allSettled(
+    outputs.map(({ staging }) => staging.mapAsync(GPUMapMode.READ)),
+  );
+
+  // Mapping fails if the device was lost or an earlier operation failed, which is reported in
+  // preference to the errors from mapping
+  const error = checkDevice();
+  if (error) {
+    return error;
+  }
+
+  const rejected = mappings.find((mapping) => mapping.status === "rejected");
+  if (rejected) {
+    return failure("runtime", String(rejected.reason));
+  }
+
+  const buffers = outputs.map(({ staging }) =>
+    Array.from(new Uint8Array(staging.getMappedRange())),
+  );
+
+  const timings = {
+    shaderModule: shaderModuleTime,
+    pipeline: pipelineTime,
+    dispatch: performance.now() - start,
+  };
+
+  return { buffers, timings };
+};
+
+(async () => {
+  const adapter = await navigator.gpu?.requestAdapter();
+  if (!adapter) {
+    return failure("runtime", "webgpu is not available");
+  }
+
+  const device = await adapter.requestDevice({ requiredFeatures: desc.features });
+
+  // The page is reused by later shaders, so the device is destroyed even if the execution fails
+  try {
+    return await execute(device);
+  } finally {
+    device.destroy();
+  }
+})().then(done, (e) => done(failure("runtime", String(e))));
@@ -0,0 +1,100 @@
+//! A minimal client for the parts of the W3C WebDriver protocol that are needed to run scripts in a
+//! browser.
+
+use eyre::eyre;
+use serde_json::{json, Value};
+
+/// A browser session, which is deleted (closing the browser) when dropped.
+pub struct Session {
+    url: String,
+    id: String,
+    /// Version of the browser, as reported when the session was created.
+    pub browser_version: String,
+}
+
+impl Session {
+    /// Starts a new session on the WebDriver server at `url` and opens a blank page, which is a
+    /// secure context so that WebGPU is exposed to scripts.
+    pub fn new(url: &str, capabilities: Value) -> eyre::Result<Session> {
+        let url = url.trim_end_matches('/').to_owned();
+
+        let value = request(
+            "POST",
+            &format!("{url}/session"),
+            Some(json!({ "capabilities": { "alwaysMatch": capabilities } })),
+        )?;
+
+        let id = value["sessionId"]
+            .as_str()
+            .ok_or_else(|| eyre!("webdriver didn't return a session id"))?
+            .to_owned();
+
+        let browser_version = value["capabilities"]["browserVersion"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+
+        let session = Session {
+            url,
+            id,
+            browser_version,
+        };
+
+        // Scripts may run for as long as the shader does, which is limited by the harness instead
+        session.command("POST", "timeouts", Some(json!({ "script": null })))?;
+        session.command("POST", "url", Some(json!({ "url": "about:blank" })))?;
+
+        Ok(session)
+    }
+
+    /// Runs an asynchronous script, which receives `args` followed by a callback that must be
+    /// called with the result.
+    pub fn execute_async(&self, script: &str, args: Vec<Value>) -> eyre::Result<Value> {
+        self.command(
+            "POST",
+            "execute/async",
+            Some(json!({ "script": script, "args": args })),
+        )
+    }
+
+    fn command(&self, method: &str, path: &str, body: Option<Value>) -> eyre::Result<Value> {
+        request(
+            method,
+            &format!("{}/session/{}/{path}", self.url, self.id),
+            body,
+        )
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let url = format!("{}/session/{}", self.url, self.id);
+        if let Err(e) = request("DELETE", &url, None) {
+            log::warn!("failed to delete webdriver session: {e:#}");
+        }
+    }
+}
+
+/// Sends a request to the WebDriver server and returns the `value` of its response.
+fn request(method: &str, url: &str, body: Option<Value>) -> eyre::Result<Value> {
+    let request = ureq::request(method, url);
+
+    let response = match body {
+        Some(body) => request.send_json(body),
+        None => request.call(),
+    };
+
+    match response {
+        Ok(response) => Ok(response.into_json::<Value>()?["value"].take()),
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_json::<Value>()?;
+            let error = &body["value"];
+            Err(eyre!(
+                "webdriver request failed with status {status}: {}: {}",
+                error["error"].as_str().unwrap_or("unknown error"),
+                error["message"].as_str().unwrap_or_default(),
+            ))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,56 @@
+//! Cancellation of runs from another thread, e.g. when a client of the server asks for its run to be
+//! stopped because an execution is stuck.
+
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use frontend::ExecutionError;
+
+/// How often blocking operations which can't be woken up (e.g. waiting for an execution process)
+/// check whether the run was cancelled.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stops a run once cancelled. Executions that are running are killed, and the run fails with
+/// [`ExecutionError::Cancelled`] without executing any more configs.
+#[derive(Default)]
+pub struct Cancellation {
+    cancelled: Mutex<bool>,
+    changed: Condvar,
+}
+
+impl Cancellation {
+    pub fn new() -> Cancellation {
+        Cancellation::default()
+    }
+
+    pub fn cancel(&self) {
+        *self.cancelled.lock().expect("cancellation mutex poisoned") = true;
+        self.changed.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.lock().expect("cancellation mutex poisoned")
+    }
+
+    /// Fails if the run was cancelled.
+    pub(crate) fn check(&self) -> Result<(), ExecutionError> {
+        match self.is_cancelled() {
+            true => Err(ExecutionError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    /// Sleeps for the given duration, but fails as soon as the run is cancelled.
+    pub(crate) fn sleep(&self, duration: Duration) -> Result<(), ExecutionError> {
+        let cancelled = self.cancelled.lock().expect("cancellation mutex poisoned");
+        let (cancelled, _) = self
+            .changed
+            .wait_timeout_while(cancelled, duration, |cancelled| !*cancelled)
+            .expect("cancellation mutex poisoned");
+
+        match *cancelled {
+            true => Err(ExecutionError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
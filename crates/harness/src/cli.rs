@@ -1,14 +1,23 @@
+use std::io::{BufReader, Write};
 use std::marker::PhantomData;
 use std::time::Duration;
 
+use bincode::error::DecodeError;
+use buffer_check::ComparisonPolicy;
 use clap::Parser;
+use eyre::eyre;
 use frontend::cli::RunOptions;
-use frontend::ExecutionError;
+use frontend::{BenchStats, ExecutionError, ExecutionResult, OutputFormat};
 use reflection::PipelineDescription;
-use types::ConfigId;
+use types::{AdapterFilter, BackendOptions, ConfigId, RetryPolicy, Timings, Variant};
 
-use crate::{ExecutionEvent, ExecutionInput, ExecutionOutput, HarnessHost};
+use crate::{
+    BackendError, BatchShader, ContextCache, ExecutionEvent, ExecutionInput, HarnessHost,
+    WorkerOutput,
+};
 
+// Commands are only parsed once, so the size of the run options doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Parser)]
 pub enum Command {
     /// Lists available configurations that can be used to execute a shader.
@@ -17,8 +26,18 @@ pub enum Command {
     /// Runs a wgsl shader against one or more configurations.
     Run(RunOptions),
 
+    /// Runs many wgsl shaders against one or more configurations, reusing the device of each
+    /// configuration between shaders.
+    RunBatch(BatchOptions),
+
+    /// Measures how long one or more configurations take to compile and execute a wgsl shader.
+    Bench(BenchOptions),
+
     #[clap(hide(true))]
     Exec {
+        /// Keep running and execute each shader that is written to stdin, until it is closed.
+        #[clap(long, action)]
+        worker: bool,
         #[clap(action)]
         config: ConfigId,
     },
@@ -27,11 +46,96 @@ pub enum Command {
     Serve(crate::server::Options),
 }
 
+#[derive(Parser)]
+pub struct BatchOptions {
+    /// Paths to the wgsl shaders to be executed.
+    ///
+    /// The input data of each shader is read from an `inputs.json` file in its directory or a
+    /// JSON file with the same name, if there is one.
+    #[clap(action, required = true)]
+    shaders: Vec<String>,
+
+    /// List of configurations to test.
+    ///
+    /// If no configurations are provided, defaults will be selected for the execution platform.
+    #[clap(short, long = "config", action)]
+    configs: Vec<ConfigId>,
+
+    /// Timeout in seconds.
+    ///
+    /// Use 0 to disable the timeout. Note that the timeout is per-execution rather than a global timeout.
+    #[clap(long, action, default_value = "45")]
+    timeout: u64,
+
+    /// Limit the number of parallel configurations executing at once.
+    ///
+    /// If not provided, execution will spawn a thread for every configuration.
+    #[clap(long, short = 'j', action)]
+    parallelism: Option<usize>,
+
+    /// Format of the output.
+    ///
+    /// With `json`, the result of each shader is printed as a JSON object on its own line.
+    #[clap(long, value_enum, default_value = "human")]
+    format: OutputFormat,
+}
+
+#[derive(Parser)]
+pub struct BenchOptions {
+    /// Path to the wgsl shader to benchmark (use '-' for stdin).
+    #[clap(action, default_value = "-")]
+    shader: String,
+
+    /// Input data for uniform buffers.
+    ///
+    /// Defaults to an `inputs.json` file in the shader's directory or a JSON file with the same
+    /// name as the shader. If there is neither, the uniform buffers are zeroed.
+    #[clap(action)]
+    input_data: Option<String>,
+
+    /// List of configurations to benchmark.
+    ///
+    /// If no configurations are provided, defaults will be selected for the execution platform.
+    #[clap(short, long = "config", action)]
+    configs: Vec<ConfigId>,
+
+    /// Number of iterations that are measured.
+    #[clap(short = 'n', long, value_parser = clap::value_parser!(u32).range(1..), default_value = "10")]
+    iterations: u32,
+
+    /// Number of iterations to run before the measured ones, which aren't included in the
+    /// statistics.
+    #[clap(long, action, default_value = "1")]
+    warmup: u32,
+
+    /// Timeout in seconds.
+    ///
+    /// Use 0 to disable the timeout. Note that the timeout is per-execution rather than a global timeout.
+    #[clap(long, action, default_value = "45")]
+    timeout: u64,
+
+    /// Format of the output.
+    ///
+    /// With `json`, the statistics of each configuration are printed as a JSON object on its own
+    /// line.
+    #[clap(long, value_enum, default_value = "human")]
+    format: OutputFormat,
+}
+
 pub fn run<Host: HarnessHost>(command: Command) -> eyre::Result<()> {
     match command {
         Command::List => list(),
         Command::Run(options) => execute::<Host>(options),
-        Command::Exec { config } => internal_run(config),
+        Command::RunBatch(options) => execute_batch::<Host>(options),
+        Command::Bench(options) => bench::<Host>(options),
+        Command::Exec {
+            worker: false,
+            config,
+        } => internal_run(config),
+        Command::Exec {
+            worker: true,
+            config,
+        } => worker_run(config),
         Command::Serve(options) => crate::server::run::<Host>(options),
     }
 }
@@ -46,15 +150,187 @@ fn internal_run(config: ConfigId) -> eyre::Result<()> {
     let input: ExecutionInput =
         bincode::decode_from_std_read(&mut std::io::stdin(), bincode::config::standard())?;
 
-    let output = ExecutionOutput {
-        buffers: crate::execute_config(&input.shader, &input.pipeline_desc, &config)?,
-    };
+    let mut output =
+        match crate::execute_config(&input.shader, &input.pipeline_desc, &input.options, &config) {
+            Ok(output) => output,
+            Err(e) => match e.downcast_ref::<BackendError>() {
+                Some(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(error.exit_code());
+                }
+                None => return Err(e),
+            },
+        };
 
+    crate::transport::write_output(input.output_path.as_deref(), &mut output)?;
     bincode::encode_into_std_write(output, &mut std::io::stdout(), bincode::config::standard())?;
 
     Ok(())
 }
 
+fn worker_run(config: ConfigId) -> eyre::Result<()> {
+    let mut stdin = BufReader::new(std::io::stdin());
+    let mut stdout = std::io::stdout();
+    let mut contexts = ContextCache::default();
+
+    loop {
+        let input: ExecutionInput =
+            match bincode::decode_from_std_read(&mut stdin, bincode::config::standard()) {
+                Ok(input) => input,
+                // The harness closes stdin once it no longer needs the worker
+                Err(DecodeError::UnexpectedEnd { .. }) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+
+        let output =
+            match contexts.execute(&input.shader, &input.pipeline_desc, &input.options, &config) {
+                Ok(mut output) => {
+                    crate::transport::write_output(input.output_path.as_deref(), &mut output)?;
+                    WorkerOutput::Success(output)
+                }
+                Err(e) => match e.downcast_ref::<BackendError>() {
+                    Some(error) => WorkerOutput::Failure {
+                        exit_code: error.exit_code(),
+                        message: error.to_string(),
+                    },
+                    None => return Err(e),
+                },
+            };
+
+        bincode::encode_into_std_write(output, &mut stdout, bincode::config::standard())?;
+        stdout.flush()?;
+    }
+}
+
+/// Executes the shader on each config once per iteration. Every execution is in a new process, so
+/// that nothing is cached between iterations and the compilation time is measured each time.
+fn bench<Host: HarnessHost>(options: BenchOptions) -> eyre::Result<()> {
+    let shader = frontend::read_shader_from_path(&options.shader)?;
+    let mut input_data = frontend::read_input_data(&options.shader, options.input_data.as_deref())?;
+    let backend_options = input_data.backend_options.take().unwrap_or_default();
+    let (pipeline_desc, type_descs) = frontend::reflect_shader(&shader, input_data);
+
+    let timeout = if options.timeout == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(options.timeout))
+    };
+
+    let printer = frontend::Printer::with_format(options.format);
+
+    // Default configs are selected by the first iteration, and configs that fail are left out of
+    // the later iterations
+    let mut configs = options.configs.clone();
+    let mut timings: Vec<(ConfigId, Vec<Timings>)> = vec![];
+    let mut is_fail = false;
+
+    for iteration in 0..options.warmup + options.iterations {
+        let mut events = vec![];
+
+        // Configs are executed one at a time, so that they don't slow each other down
+        crate::execute::<Host, _>(
+            &shader,
+            &pipeline_desc,
+            &configs,
+            false,
+            &[],
+            &AdapterFilter::default(),
+            &backend_options,
+            timeout,
+            RetryPolicy::default(),
+            Some(1),
+            None,
+            None,
+            None,
+            |event| {
+                events.push(event);
+                Ok(())
+            },
+        )
+        .map_err(|e| match e {
+            ExecutionError::NoDefaultConfigs => {
+                eyre!("failed to find any suitable default configurations")
+            }
+            ExecutionError::Other(e) => e,
+            e => eyre!(e),
+        })?;
+
+        for event in events {
+            let failed = match &event {
+                ExecutionEvent::Environment(_) | ExecutionEvent::UsingDefaultConfigs(_)
+                    if iteration > 0 =>
+                {
+                    continue
+                }
+                ExecutionEvent::Queued(..) | ExecutionEvent::Start(_) => continue,
+                ExecutionEvent::Environment(_) => None,
+                ExecutionEvent::UsingDefaultConfigs(defaults) => {
+                    configs = defaults.clone();
+                    None
+                }
+                ExecutionEvent::Success(config, _, execution_timings) => {
+                    if iteration >= options.warmup {
+                        match timings.iter_mut().find(|(it, _)| it == config) {
+                            Some((_, timings)) => timings.push(*execution_timings),
+                            None => timings.push((config.clone(), vec![*execution_timings])),
+                        }
+                    }
+                    continue;
+                }
+                ExecutionEvent::Unsupported(config, _) => Some(config.clone()),
+                ExecutionEvent::UsingOracle(_) => unreachable!("benchmarks don't use an oracle"),
+                ExecutionEvent::CompilationFailure(config, _)
+                | ExecutionEvent::ValidationFailure(config, _)
+                | ExecutionEvent::RuntimeFailure(config, _)
+                | ExecutionEvent::DeviceLost(config, _)
+                | ExecutionEvent::Timeout(config)
+                | ExecutionEvent::Hang(config) => {
+                    is_fail = true;
+                    Some(config.clone())
+                }
+            };
+
+            printer.print_execution_event(
+                &event,
+                &pipeline_desc,
+                &type_descs,
+                &ComparisonPolicy::default(),
+            )?;
+
+            if let Some(config) = failed {
+                configs.retain(|it| *it != config);
+                timings.retain(|(it, _)| *it != config);
+            }
+        }
+
+        // Default configs would be selected again if every config was left out
+        if configs.is_empty() {
+            break;
+        }
+    }
+
+    for (config, timings) in &timings {
+        let compilation = timings
+            .iter()
+            .map(|it| it.shader_module + it.pipeline)
+            .collect::<Vec<_>>();
+        let execution = timings.iter().map(|it| it.dispatch).collect::<Vec<_>>();
+
+        printer.print_bench_result(
+            config,
+            timings.len(),
+            BenchStats::of(&compilation).expect("configs have at least one iteration"),
+            BenchStats::of(&execution).expect("configs have at least one iteration"),
+        )?;
+    }
+
+    if is_fail {
+        return Err(eyre!("one or more executions failed"));
+    }
+
+    Ok(())
+}
+
 pub fn execute<Host: HarnessHost>(options: RunOptions) -> eyre::Result<()> {
     struct Executor<Host>(PhantomData<Host>);
 
@@ -70,7 +346,12 @@ pub fn execute<Host: HarnessHost>(options: RunOptions) -> eyre::Result<()> {
             shader: &str,
             pipeline_desc: &PipelineDescription,
             configs: &[ConfigId],
+            oracle: bool,
+            variants: &[Variant],
+            filter: &AdapterFilter,
+            options: &BackendOptions,
             timeout: Option<Duration>,
+            retry: RetryPolicy,
             parallelism: Option<usize>,
             on_event: &mut (dyn FnMut(ExecutionEvent) -> Result<(), ExecutionError> + Send),
         ) -> Result<(), ExecutionError> {
@@ -78,8 +359,16 @@ pub fn execute<Host: HarnessHost>(options: RunOptions) -> eyre::Result<()> {
                 shader,
                 pipeline_desc,
                 configs,
+                oracle,
+                variants,
+                filter,
+                options,
                 timeout,
+                retry,
                 parallelism,
+                None,
+                None,
+                None,
                 on_event,
             )
         }
@@ -87,3 +376,132 @@ pub fn execute<Host: HarnessHost>(options: RunOptions) -> eyre::Result<()> {
 
     frontend::cli::run(options, &Executor::<Host>::new())
 }
+
+fn execute_batch<Host: HarnessHost>(options: BatchOptions) -> eyre::Result<()> {
+    let mut shaders = vec![];
+    let mut type_descs = vec![];
+
+    for path in &options.shaders {
+        let shader = frontend::read_shader_from_path(path)?;
+        let mut input_data = frontend::read_input_data(path, None)?;
+        let backend_options = input_data.backend_options.take().unwrap_or_default();
+        let (pipeline_desc, types) = frontend::reflect_shader(&shader, input_data);
+
+        shaders.push(BatchShader {
+            shader,
+            pipeline_desc,
+            options: backend_options,
+        });
+
+        type_descs.push(types);
+    }
+
+    let timeout = if options.timeout == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(options.timeout))
+    };
+
+    let results =
+        crate::execute_batch::<Host>(&shaders, &options.configs, timeout, options.parallelism)
+            .map_err(|e| match e {
+                ExecutionError::NoDefaultConfigs => {
+                    eyre!("failed to find any suitable default configurations")
+                }
+                ExecutionError::Other(e) => e,
+                e => eyre!(e),
+            })?;
+
+    let printer = frontend::Printer::with_format(options.format);
+    let mut is_mismatch = false;
+
+    for ((path, events), (shader, type_descs)) in options
+        .shaders
+        .iter()
+        .zip(results)
+        .zip(shaders.iter().zip(&type_descs))
+    {
+        // Each distinct output is numbered in the order that it was first seen, so that the
+        // configs which agree can be identified
+        let mut outputs: Vec<Vec<u8>> = vec![];
+        let mut outcomes = vec![];
+        let mut is_fail = false;
+        let mut is_timeout = false;
+
+        for event in events {
+            let (config, outcome) = match event {
+                ExecutionEvent::Success(config, buffers, _) => {
+                    let normalized = buffer_check::normalize_execution(
+                        &buffers,
+                        &shader.pipeline_desc,
+                        type_descs,
+                        &buffer_check::ComparisonPolicy::EXACT,
+                    );
+
+                    let index = match outputs.iter().position(|it| *it == normalized) {
+                        Some(index) => index,
+                        None => {
+                            outputs.push(normalized);
+                            outputs.len() - 1
+                        }
+                    };
+
+                    (config, format!("output {}", index + 1))
+                }
+                ExecutionEvent::Unsupported(config, _) => (config, "unsupported".to_owned()),
+                ExecutionEvent::CompilationFailure(config, _) => {
+                    is_fail = true;
+                    (config, "compilation failure".to_owned())
+                }
+                ExecutionEvent::ValidationFailure(config, _) => {
+                    is_fail = true;
+                    (config, "validation failure".to_owned())
+                }
+                ExecutionEvent::RuntimeFailure(config, _) => {
+                    is_fail = true;
+                    (config, "runtime failure".to_owned())
+                }
+                ExecutionEvent::DeviceLost(config, _) => {
+                    is_fail = true;
+                    (config, "device lost".to_owned())
+                }
+                ExecutionEvent::Hang(config) => {
+                    is_fail = true;
+                    (config, "hang".to_owned())
+                }
+                ExecutionEvent::Timeout(config) => {
+                    is_timeout = true;
+                    (config, "timeout".to_owned())
+                }
+                ExecutionEvent::Environment(_)
+                | ExecutionEvent::UsingDefaultConfigs(_)
+                | ExecutionEvent::UsingOracle(_)
+                | ExecutionEvent::Queued(..)
+                | ExecutionEvent::Start(_) => unreachable!("not an execution result"),
+            };
+
+            outcomes.push((config, outcome));
+        }
+
+        // Mismatches are reported in preference to failures, since they are what is being
+        // fuzzed for
+        let result = if outputs.len() > 1 {
+            is_mismatch = true;
+            ExecutionResult::Mismatch
+        } else if is_fail {
+            ExecutionResult::Failure
+        } else if is_timeout {
+            ExecutionResult::Timeout
+        } else {
+            ExecutionResult::Ok
+        };
+
+        printer.print_batch_result(path, result, &outcomes)?;
+    }
+
+    if is_mismatch {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
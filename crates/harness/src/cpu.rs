@@ -0,0 +1,280 @@
+//! Executes shaders on the host by interpreting naga's IR, which gives reference outputs that don't
+//! depend on any GPU driver.
+
+mod builtin;
+mod interpreter;
+mod layout;
+mod ops;
+mod scheduler;
+mod texture;
+mod value;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{AddressSpace, EntryPoint, GlobalVariable, Handle, Module, ShaderStage};
+use reflection::{Feature, Limits, PipelineDescription, ResourceKind};
+
+use crate::{BackendError, ExecutionOutput, Timings};
+use interpreter::{Builtins, Invocation, Shared};
+use scheduler::Scheduler;
+use texture::Texture;
+use value::Value;
+
+/// Stack size of the thread that runs each invocation, which needs to be large enough for deeply
+/// nested blocks and calls.
+const STACK_SIZE: usize = 16 * 1024 * 1024;
+
+pub fn get_adapters() -> Vec<types::Adapter> {
+    vec![types::Adapter {
+        name: "naga IR interpreter".to_owned(),
+        vendor_id: 0,
+        device_id: 0,
+        driver: String::new(),
+        backend: types::BackendType::Interpreter,
+        features: vec![Feature::ShaderF16, Feature::Packed4x8IntegerDotProduct],
+        limits: Limits::DEFAULT,
+        software: true,
+    }]
+}
+
+/// Interprets the shader. Parsing and validating it is timed as creating the shader module, and
+/// processing its overrides as creating the pipeline.
+pub fn run(shader: &str, meta: &PipelineDescription) -> eyre::Result<ExecutionOutput> {
+    let start = Instant::now();
+
+    let module = naga::front::wgsl::parse_str(shader)
+        .map_err(|e| BackendError::Compilation(e.emit_to_string(shader)))?;
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| BackendError::Compilation(e.emit_to_string(shader)))?;
+
+    let shader_module_time = start.elapsed();
+    let start = Instant::now();
+
+    // This replaces overrides with their default values, which fails if any of them don't have one
+    let (module, _) = naga::back::pipeline_constants::process_overrides(
+        &module,
+        &info,
+        Some((ShaderStage::Compute, "main")),
+        &Default::default(),
+    )
+    .map_err(|e| BackendError::Compilation(e.to_string()))?;
+
+    let entry_point = module
+        .entry_points
+        .iter()
+        .find(|it| it.stage == ShaderStage::Compute && it.name == "main")
+        .ok_or_else(|| {
+            BackendError::Compilation("missing compute entry point `main`".to_owned())
+        })?;
+
+    let pipeline_time = start.elapsed();
+    let start = Instant::now();
+
+    let mut memory = HashMap::new();
+    let mut textures = HashMap::new();
+
+    for (handle, global) in module.global_variables.iter() {
+        let Some(binding) = &global.binding else {
+            continue;
+        };
+
+        // Resources that the shader never accesses are left out of the pipeline description
+        let Some(resource) = meta
+            .resources
+            .iter()
+            .find(|it| it.group == binding.group && it.binding == binding.binding)
+        else {
+            continue;
+        };
+
+        match resource.kind {
+            ResourceKind::StorageBuffer
+            | ResourceKind::ReadOnlyStorageBuffer
+            | ResourceKind::UniformBuffer => {
+                let mut bytes = vec![0; resource.size as usize];
+                if let Some(init) = &resource.init {
+                    let len = bytes.len().min(init.len());
+                    bytes[..len].copy_from_slice(&init[..len]);
+                }
+                memory.insert(handle, layout::read(&module, global.ty, &bytes)?);
+            }
+            ResourceKind::Texture(ref desc) => {
+                textures.insert(handle, Texture::new(desc.clone(), resource.init.as_deref()));
+            }
+            ResourceKind::Sampler { .. } => {}
+        }
+    }
+
+    let memory = Mutex::new(memory);
+
+    let [x, y, z] = meta.dispatch;
+    for workgroup_id in
+        (0..z).flat_map(|z| (0..y).flat_map(move |y| (0..x).map(move |x| [x, y, z])))
+    {
+        // Workgroup variables are zeroed at the start of each workgroup
+        for (handle, global) in module.global_variables.iter() {
+            if global.space == AddressSpace::WorkGroup {
+                let value = Value::zero(&module, global.ty)?;
+                memory.lock().unwrap().insert(handle, value);
+            }
+        }
+
+        run_workgroup(
+            &Shared {
+                module: &module,
+                memory: &memory,
+                textures: &textures,
+                scheduler: &Scheduler::new(invocation_count(entry_point)),
+            },
+            entry_point,
+            workgroup_id,
+            meta.dispatch,
+        )?;
+    }
+
+    let memory = memory.into_inner().unwrap();
+
+    let buffers = meta
+        .resources
+        .iter()
+        .filter(|it| it.kind == ResourceKind::StorageBuffer)
+        .map(|resource| {
+            let mut bytes = vec![0; resource.size as usize];
+
+            if let Some((handle, global)) = find_global(&module, resource.group, resource.binding) {
+                layout::write(&module, global.ty, &memory[&handle], &mut bytes)?;
+            }
+
+            Ok(bytes)
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    Ok(ExecutionOutput {
+        buffers,
+        timings: Timings {
+            shader_module: shader_module_time,
+            pipeline: pipeline_time,
+            dispatch: start.elapsed(),
+        },
+        cache_hit: None,
+    })
+}
+
+fn find_global(
+    module: &Module,
+    group: u32,
+    binding: u32,
+) -> Option<(Handle<GlobalVariable>, &GlobalVariable)> {
+    module.global_variables.iter().find(|(_, global)| {
+        global
+            .binding
+            .as_ref()
+            .is_some_and(|it| it.group == group && it.binding == binding)
+    })
+}
+
+fn invocation_count(entry_point: &EntryPoint) -> usize {
+    entry_point.workgroup_size.iter().product::<u32>() as usize
+}
+
+/// Runs each invocation of a workgroup on its own thread, and returns the error of the first
+/// invocation that failed.
+fn run_workgroup(
+    shared: &Shared,
+    entry_point: &EntryPoint,
+    workgroup_id: [u32; 3],
+    num_workgroups: [u32; 3],
+) -> eyre::Result<()> {
+    let [width, height, _] = entry_point.workgroup_size;
+
+    thread::scope(|scope| {
+        let threads = (0..invocation_count(entry_point))
+            .map(|index| {
+                let local_invocation_index = index as u32;
+                let local_invocation_id = [
+                    local_invocation_index % width,
+                    local_invocation_index / width % height,
+                    local_invocation_index / (width * height),
+                ];
+
+                let builtins = Builtins {
+                    global_invocation_id: [0, 1, 2].map(|i| {
+                        workgroup_id[i] * entry_point.workgroup_size[i] + local_invocation_id[i]
+                    }),
+                    local_invocation_id,
+                    local_invocation_index,
+                    workgroup_id,
+                    num_workgroups,
+                };
+
+                thread::Builder::new()
+                    .stack_size(STACK_SIZE)
+                    .spawn_scoped(scope, move || {
+                        shared.scheduler.run(index, || {
+                            Invocation::new(shared, index)?.run(entry_point, &builtins)
+                        })
+                    })
+                    .expect("failed to spawn invocation thread")
+            })
+            .collect::<Vec<_>>();
+
+        threads.into_iter().try_for_each(|thread| {
+            thread
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use reflection::PipelineResource;
+
+    use super::*;
+
+    /// Runs a shader with a single storage buffer of `u32`s bound at `@group(0) @binding(0)`, and
+    /// returns the contents of the buffer afterwards.
+    pub(super) fn run_shader(shader: &str, init: &[u32]) -> Vec<u32> {
+        let meta = PipelineDescription {
+            resources: vec![PipelineResource {
+                name: "out".to_owned(),
+                kind: ResourceKind::StorageBuffer,
+                group: 0,
+                binding: 0,
+                init: Some(init.iter().flat_map(|it| it.to_le_bytes()).collect()),
+                size: (init.len() * 4) as u32,
+            }],
+            features: vec![],
+            limits: Limits::DEFAULT,
+            dispatch: [2, 1, 1],
+        };
+
+        let output = run(shader, &meta).unwrap();
+
+        output.buffers[0]
+            .chunks(4)
+            .map(|it| u32::from_le_bytes(it.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn storage_buffer() {
+        let shader = r#"
+            @group(0) @binding(0)
+            var<storage, read_write> out: array<u32, 4>;
+
+            @compute @workgroup_size(2)
+            fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+                out[id.x] = out[id.x] * 10u + id.x;
+            }
+        "#;
+
+        assert_eq!(run_shader(shader, &[1, 2, 3, 4]), [10, 21, 32, 43]);
+    }
+}
@@ -0,0 +1,333 @@
+//! Evaluation of WGSL's numeric builtin functions.
+
+use eyre::{bail, eyre};
+use half::f16;
+use naga::MathFunction;
+
+use super::ops::{self, componentwise, componentwise2, float1, float2, float3};
+use super::value::Value;
+
+pub fn math(fun: MathFunction, args: Vec<Value>) -> eyre::Result<Value> {
+    use MathFunction as M;
+
+    let mut args = args.into_iter();
+    let mut arg = || {
+        args.next()
+            .ok_or_else(|| eyre!("missing argument for {fun:?}"))
+    };
+
+    Ok(match fun {
+        M::Abs => componentwise(arg()?, |v| match v {
+            Value::I32(v) => Ok(Value::I32(v.wrapping_abs())),
+            Value::U32(v) => Ok(Value::U32(v)),
+            v => float1(v, f32::abs),
+        })?,
+        M::Min => min_max(arg()?, arg()?, false)?,
+        M::Max => min_max(arg()?, arg()?, true)?,
+        M::Clamp => {
+            let (e, low, high) = (arg()?, arg()?, arg()?);
+            min_max(min_max(e, low, true)?, high, false)?
+        }
+        M::Saturate => float1(arg()?, |v| v.clamp(0.0, 1.0))?,
+        M::Cos => float1(arg()?, f32::cos)?,
+        M::Cosh => float1(arg()?, f32::cosh)?,
+        M::Sin => float1(arg()?, f32::sin)?,
+        M::Sinh => float1(arg()?, f32::sinh)?,
+        M::Tan => float1(arg()?, f32::tan)?,
+        M::Tanh => float1(arg()?, f32::tanh)?,
+        M::Acos => float1(arg()?, f32::acos)?,
+        M::Asin => float1(arg()?, f32::asin)?,
+        M::Atan => float1(arg()?, f32::atan)?,
+        M::Atan2 => float2(arg()?, arg()?, f32::atan2)?,
+        M::Asinh => float1(arg()?, f32::asinh)?,
+        M::Acosh => float1(arg()?, f32::acosh)?,
+        M::Atanh => float1(arg()?, f32::atanh)?,
+        M::Radians => float1(arg()?, f32::to_radians)?,
+        M::Degrees => float1(arg()?, f32::to_degrees)?,
+        M::Ceil => float1(arg()?, f32::ceil)?,
+        M::Floor => float1(arg()?, f32::floor)?,
+        M::Round => float1(arg()?, f32::round_ties_even)?,
+        M::Fract => float1(arg()?, |v| v - v.floor())?,
+        M::Trunc => float1(arg()?, f32::trunc)?,
+        M::Modf => {
+            let e = arg()?;
+            split(
+                float1(e.clone(), |v| v - v.trunc())?,
+                float1(e, f32::trunc)?,
+            )
+        }
+        M::Frexp => {
+            let e = arg()?;
+            let fract = float1(e.clone(), |v| frexp(v).0)?;
+            let exp = componentwise(e, |v| Ok(Value::I32(frexp(v.as_float()?).1)))?;
+            split(fract, exp)
+        }
+        M::Ldexp => componentwise2(arg()?, arg()?, |e1, e2| {
+            let exp = e2.as_i32()?;
+            float1(e1, |v| v * 2f32.powi(exp))
+        })?,
+        M::Exp => float1(arg()?, f32::exp)?,
+        M::Exp2 => float1(arg()?, f32::exp2)?,
+        M::Log => float1(arg()?, f32::ln)?,
+        M::Log2 => float1(arg()?, f32::log2)?,
+        M::Pow => float2(arg()?, arg()?, f32::powf)?,
+        M::Dot => dot(arg()?, arg()?)?,
+        M::Dot4I8Packed => {
+            let (a, b) = (arg()?.as_u32()?, arg()?.as_u32()?);
+            Value::I32(
+                (0..4)
+                    .map(|i| (a >> (8 * i)) as i8 as i32 * (b >> (8 * i)) as i8 as i32)
+                    .sum(),
+            )
+        }
+        M::Dot4U8Packed => {
+            let (a, b) = (arg()?.as_u32()?, arg()?.as_u32()?);
+            Value::U32(
+                (0..4)
+                    .map(|i| (a >> (8 * i)) as u8 as u32 * (b >> (8 * i)) as u8 as u32)
+                    .sum(),
+            )
+        }
+        M::Cross => {
+            let (a, b) = (arg()?.into_components()?, arg()?.into_components()?);
+            let product = |i: usize, j: usize| {
+                ops::binary(
+                    naga::BinaryOperator::Subtract,
+                    ops::binary(naga::BinaryOperator::Multiply, a[i].clone(), b[j].clone())?,
+                    ops::binary(naga::BinaryOperator::Multiply, a[j].clone(), b[i].clone())?,
+                )
+            };
+            Value::Vector(vec![product(1, 2)?, product(2, 0)?, product(0, 1)?])
+        }
+        M::Distance => {
+            let difference = ops::binary(naga::BinaryOperator::Subtract, arg()?, arg()?)?;
+            length(difference)?
+        }
+        M::Length => length(arg()?)?,
+        M::Normalize => {
+            let e = arg()?;
+            let length = length(e.clone())?;
+            ops::binary(naga::BinaryOperator::Divide, e, length)?
+        }
+        M::FaceForward => {
+            let (e1, e2, e3) = (arg()?, arg()?, arg()?);
+            if dot(e2, e3)?.as_float()? < 0.0 {
+                e1
+            } else {
+                ops::unary(naga::UnaryOperator::Negate, e1)?
+            }
+        }
+        M::Reflect => {
+            // e1 - 2 * dot(e2, e1) * e2
+            let (e1, e2) = (arg()?, arg()?);
+            let d = dot(e2.clone(), e1.clone())?;
+            let scaled = float2(e2, d, |v, d| 2.0 * d * v)?;
+            ops::binary(naga::BinaryOperator::Subtract, e1, scaled)?
+        }
+        M::Refract => {
+            let (e1, e2, e3) = (arg()?, arg()?, arg()?);
+            let d = dot(e2.clone(), e1.clone())?.as_float()?;
+            let eta = e3.as_float()?;
+            let k = 1.0 - eta * eta * (1.0 - d * d);
+            if k < 0.0 {
+                float1(e1, |_| 0.0)?
+            } else {
+                let incident = float1(e1, |v| eta * v)?;
+                let normal = float1(e2, |v| (eta * d + k.sqrt()) * v)?;
+                ops::binary(naga::BinaryOperator::Subtract, incident, normal)?
+            }
+        }
+        M::Sign => componentwise(arg()?, |v| match v {
+            Value::I32(v) => Ok(Value::I32(v.signum())),
+            v => float1(v, |v| if v == 0.0 { 0.0 } else { v.signum() }),
+        })?,
+        M::Fma => float3(arg()?, arg()?, arg()?, |a, b, c| a * b + c)?,
+        M::Mix => float3(arg()?, arg()?, arg()?, |a, b, t| a * (1.0 - t) + b * t)?,
+        M::Step => float2(arg()?, arg()?, |edge, x| if edge <= x { 1.0 } else { 0.0 })?,
+        M::SmoothStep => float3(arg()?, arg()?, arg()?, |low, high, x| {
+            let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        })?,
+        M::Sqrt => float1(arg()?, f32::sqrt)?,
+        M::InverseSqrt => float1(arg()?, |v| 1.0 / v.sqrt())?,
+        M::QuantizeToF16 => float1(arg()?, |v| f16::from_f32(v).to_f32())?,
+        M::CountTrailingZeros => integer1(arg()?, u32::trailing_zeros)?,
+        M::CountLeadingZeros => integer1(arg()?, u32::leading_zeros)?,
+        M::CountOneBits => integer1(arg()?, u32::count_ones)?,
+        M::ReverseBits => integer1(arg()?, u32::reverse_bits)?,
+        M::ExtractBits => {
+            let (e, offset, count) = (arg()?, arg()?.as_u32()?, arg()?.as_u32()?);
+            let offset = offset.min(32);
+            let count = count.min(32 - offset);
+            componentwise(e, |e| {
+                Ok(match e {
+                    _ if count == 0 => match e {
+                        Value::I32(_) => Value::I32(0),
+                        _ => Value::U32(0),
+                    },
+                    // The extracted bits are sign extended by shifting them to the top first
+                    Value::I32(v) => Value::I32((v << (32 - offset - count)) >> (32 - count)),
+                    Value::U32(v) => Value::U32((v << (32 - offset - count)) >> (32 - count)),
+                    e => bail!("expected integer, found {e:?}"),
+                })
+            })?
+        }
+        M::InsertBits => {
+            let (e, newbits) = (arg()?, arg()?);
+            let (offset, count) = (arg()?.as_u32()?, arg()?.as_u32()?);
+            let offset = offset.min(32);
+            let count = count.min(32 - offset);
+            let mask = (((1u64 << count) - 1) << offset) as u32;
+            componentwise2(e, newbits, |e, newbits| {
+                let insert = |e: u32, newbits: u32| match count {
+                    0 => e,
+                    _ => (e & !mask) | ((newbits << offset) & mask),
+                };
+                Ok(match (e, newbits) {
+                    (Value::I32(e), Value::I32(n)) => Value::I32(insert(e as u32, n as u32) as i32),
+                    (Value::U32(e), Value::U32(n)) => Value::U32(insert(e, n)),
+                    (e, n) => bail!("expected integers, found {e:?} and {n:?}"),
+                })
+            })?
+        }
+        M::FirstTrailingBit => componentwise(arg()?, |v| {
+            Ok(match v {
+                Value::I32(0) => Value::I32(-1),
+                Value::I32(v) => Value::I32(v.trailing_zeros() as i32),
+                Value::U32(0) => Value::U32(u32::MAX),
+                Value::U32(v) => Value::U32(v.trailing_zeros()),
+                v => bail!("expected integer, found {v:?}"),
+            })
+        })?,
+        M::FirstLeadingBit => componentwise(arg()?, |v| {
+            Ok(match v {
+                // For signed integers, this finds the most significant bit that differs from the
+                // sign bit
+                Value::I32(0 | -1) => Value::I32(-1),
+                Value::I32(v) if v < 0 => Value::I32(31 - (!v).leading_zeros() as i32),
+                Value::I32(v) => Value::I32(31 - v.leading_zeros() as i32),
+                Value::U32(0) => Value::U32(u32::MAX),
+                Value::U32(v) => Value::U32(31 - v.leading_zeros()),
+                v => bail!("expected integer, found {v:?}"),
+            })
+        })?,
+        M::Pack4x8snorm => pack(arg()?, 8, |v| {
+            let v = v.as_float()?;
+            Ok((0.5 + 127.0 * v.clamp(-1.0, 1.0)).floor() as i8 as u8 as u32)
+        })?,
+        M::Pack4x8unorm => pack(arg()?, 8, |v| {
+            Ok((0.5 + 255.0 * v.as_float()?.clamp(0.0, 1.0)).floor() as u32)
+        })?,
+        M::Pack2x16snorm => pack(arg()?, 16, |v| {
+            let v = v.as_float()?;
+            Ok((0.5 + 32767.0 * v.clamp(-1.0, 1.0)).floor() as i16 as u16 as u32)
+        })?,
+        M::Pack2x16unorm => pack(arg()?, 16, |v| {
+            Ok((0.5 + 65535.0 * v.as_float()?.clamp(0.0, 1.0)).floor() as u32)
+        })?,
+        M::Pack2x16float => pack(arg()?, 16, |v| {
+            Ok(f16::from_f32(v.as_float()?).to_bits() as u32)
+        })?,
+        M::Pack4xI8 => pack(arg()?, 8, |v| Ok(v.as_i32()? as u8 as u32))?,
+        M::Pack4xU8 => pack(arg()?, 8, |v| Ok(v.as_u32()? as u8 as u32))?,
+        M::Pack4xI8Clamp => pack(arg()?, 8, |v| Ok(v.as_i32()?.clamp(-128, 127) as u8 as u32))?,
+        M::Pack4xU8Clamp => pack(arg()?, 8, |v| Ok(v.as_u32()?.min(255)))?,
+        M::Unpack4x8snorm => unpack(arg()?, 8, |v| {
+            Value::F32((v as u8 as i8 as f32 / 127.0).max(-1.0))
+        })?,
+        M::Unpack4x8unorm => unpack(arg()?, 8, |v| Value::F32(v as u8 as f32 / 255.0))?,
+        M::Unpack2x16snorm => unpack(arg()?, 16, |v| {
+            Value::F32((v as u16 as i16 as f32 / 32767.0).max(-1.0))
+        })?,
+        M::Unpack2x16unorm => unpack(arg()?, 16, |v| Value::F32(v as u16 as f32 / 65535.0))?,
+        M::Unpack2x16float => unpack(arg()?, 16, |v| {
+            Value::F32(f16::from_bits(v as u16).to_f32())
+        })?,
+        M::Unpack4xI8 => unpack(arg()?, 8, |v| Value::I32(v as u8 as i8 as i32))?,
+        M::Unpack4xU8 => unpack(arg()?, 8, |v| Value::U32(v as u8 as u32))?,
+        M::Outer | M::Inverse | M::Transpose | M::Determinant => {
+            bail!("{fun:?} is not supported by the interpreter")
+        }
+    })
+}
+
+fn min_max(a: Value, b: Value, max: bool) -> eyre::Result<Value> {
+    componentwise2(a, b, |a, b| {
+        Ok(match (a, b) {
+            (Value::I32(a), Value::I32(b)) => Value::I32(if max { a.max(b) } else { a.min(b) }),
+            (Value::U32(a), Value::U32(b)) => Value::U32(if max { a.max(b) } else { a.min(b) }),
+            (a, b) => float2(a, b, |a, b| if max { a.max(b) } else { a.min(b) })?,
+        })
+    })
+}
+
+/// Returns the struct produced by `modf` or `frexp`, whose members are the two results.
+fn split(first: Value, second: Value) -> Value {
+    Value::Composite(vec![first, second])
+}
+
+/// Splits a float into a fraction with magnitude in `[0.5, 1)` and an exponent, such that
+/// `fract * 2^exp == v`.
+fn frexp(v: f32) -> (f32, i32) {
+    if v == 0.0 || !v.is_finite() {
+        return (v, 0);
+    }
+
+    // Subnormals are normalised first, so that their exponent can be read from the bits
+    let (v, bias) = if v.is_normal() {
+        (v, 0)
+    } else {
+        (v * 2f32.powi(32), -32)
+    };
+
+    let bits = v.to_bits();
+    let exp = ((bits >> 23) & 0xff) as i32 - 126;
+    let fract = f32::from_bits((bits & 0x807f_ffff) | (126 << 23));
+
+    (fract, exp + bias)
+}
+
+fn dot(a: Value, b: Value) -> eyre::Result<Value> {
+    let products = ops::binary(naga::BinaryOperator::Multiply, a, b)?.into_components()?;
+    products
+        .into_iter()
+        .try_fold(None, |acc, it| match acc {
+            None => Ok(Some(it)),
+            Some(acc) => ops::binary(naga::BinaryOperator::Add, acc, it).map(Some),
+        })?
+        .ok_or_else(|| eyre!("dot product of empty vectors"))
+}
+
+fn length(e: Value) -> eyre::Result<Value> {
+    match e {
+        Value::Vector(_) => float1(dot(e.clone(), e)?, f32::sqrt),
+        e => float1(e, f32::abs),
+    }
+}
+
+/// Applies a bitwise function to each component of an `i32` or `u32` vector or scalar.
+fn integer1(value: Value, f: impl Fn(u32) -> u32) -> eyre::Result<Value> {
+    componentwise(value, |value| match value {
+        Value::I32(v) => Ok(Value::I32(f(v as u32) as i32)),
+        Value::U32(v) => Ok(Value::U32(f(v))),
+        value => bail!("expected integer, found {value:?}"),
+    })
+}
+
+/// Packs each component of a vector into `bits` bits of a `u32`, starting from the least
+/// significant bits.
+fn pack(value: Value, bits: u32, f: impl Fn(&Value) -> eyre::Result<u32>) -> eyre::Result<Value> {
+    let mut packed = 0;
+    for (i, component) in value.components()?.iter().enumerate() {
+        packed |= f(component)? << (bits * i as u32);
+    }
+    Ok(Value::U32(packed))
+}
+
+/// Unpacks each `bits`-bit component of a `u32` into a vector.
+fn unpack(value: Value, bits: u32, f: impl Fn(u32) -> Value) -> eyre::Result<Value> {
+    let packed = value.as_u32()?;
+    Ok(Value::Vector(
+        (0..32 / bits).map(|i| f(packed >> (bits * i))).collect(),
+    ))
+}
@@ -0,0 +1,719 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use eyre::{bail, eyre};
+use naga::{
+    AddressSpace, AtomicFunction, BinaryOperator, Binding, Block, BuiltIn, EntryPoint, Expression,
+    Function, GlobalVariable, Handle, ImageQuery, LocalVariable, MathFunction, Module, Statement,
+    SwitchValue, TypeInner,
+};
+
+use super::builtin;
+use super::ops;
+use super::scheduler::Scheduler;
+use super::texture::Texture;
+use super::value::{Pointer, Root, Value};
+
+/// State that is shared by the invocations of a workgroup.
+pub struct Shared<'a> {
+    pub module: &'a Module,
+    /// Values of the storage, uniform and workgroup variables.
+    pub memory: &'a Mutex<HashMap<Handle<GlobalVariable>, Value>>,
+    pub textures: &'a HashMap<Handle<GlobalVariable>, Texture>,
+    pub scheduler: &'a Scheduler,
+}
+
+/// Values of the compute shader builtin inputs of an invocation.
+pub struct Builtins {
+    pub global_invocation_id: [u32; 3],
+    pub local_invocation_id: [u32; 3],
+    pub local_invocation_index: u32,
+    pub workgroup_id: [u32; 3],
+    pub num_workgroups: [u32; 3],
+}
+
+/// How control leaves a statement.
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<Value>),
+}
+
+struct Frame<'a> {
+    function: &'a Function,
+    arguments: Vec<Value>,
+    locals: HashMap<Handle<LocalVariable>, Value>,
+    /// Values of the function's expressions, which are filled in as they are evaluated.
+    ///
+    /// Expressions that don't depend on the state of the invocation, such as literals, aren't
+    /// covered by an `Emit` statement, so they are evaluated when they are first used instead.
+    values: Vec<Option<Value>>,
+}
+
+pub struct Invocation<'a> {
+    shared: &'a Shared<'a>,
+    index: usize,
+    private: HashMap<Handle<GlobalVariable>, Value>,
+    frames: Vec<Frame<'a>>,
+}
+
+impl<'a> Invocation<'a> {
+    pub fn new(shared: &'a Shared<'a>, index: usize) -> eyre::Result<Invocation<'a>> {
+        let module = shared.module;
+        let mut private = HashMap::new();
+
+        for (handle, global) in module.global_variables.iter() {
+            if global.space == AddressSpace::Private {
+                let value = match global.init {
+                    Some(init) => evaluate_const(module, init)?,
+                    None => Value::zero(module, global.ty)?,
+                };
+
+                private.insert(handle, value);
+            }
+        }
+
+        Ok(Invocation {
+            shared,
+            index,
+            private,
+            frames: vec![],
+        })
+    }
+
+    pub fn run(&mut self, entry_point: &'a EntryPoint, builtins: &Builtins) -> eyre::Result<()> {
+        let module = self.shared.module;
+
+        let arguments = entry_point
+            .function
+            .arguments
+            .iter()
+            .map(
+                |argument| match (&argument.binding, &module.types[argument.ty].inner) {
+                    (Some(binding), _) => builtin_value(binding, builtins),
+                    (None, TypeInner::Struct { members, .. }) => Ok(Value::Composite(
+                        members
+                            .iter()
+                            .map(|member| match &member.binding {
+                                Some(binding) => builtin_value(binding, builtins),
+                                None => bail!("entry point input has no binding"),
+                            })
+                            .collect::<eyre::Result<_>>()?,
+                    )),
+                    (None, _) => bail!("entry point input has no binding"),
+                },
+            )
+            .collect::<eyre::Result<_>>()?;
+
+        self.call(&entry_point.function, arguments)?;
+
+        Ok(())
+    }
+
+    fn frame(&self) -> &Frame<'a> {
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame<'a> {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn call(
+        &mut self,
+        function: &'a Function,
+        arguments: Vec<Value>,
+    ) -> eyre::Result<Option<Value>> {
+        self.frames.push(Frame {
+            function,
+            arguments,
+            locals: HashMap::new(),
+            values: vec![None; function.expressions.len()],
+        });
+
+        for (handle, local) in function.local_variables.iter() {
+            let value = match local.init {
+                Some(init) => self.eval(init)?,
+                None => Value::zero(self.shared.module, local.ty)?,
+            };
+
+            self.frame_mut().locals.insert(handle, value);
+        }
+
+        let flow = self.block(&function.body)?;
+
+        self.frames.pop();
+
+        Ok(match flow {
+            Flow::Return(value) => value,
+            _ => None,
+        })
+    }
+
+    fn block(&mut self, block: &'a Block) -> eyre::Result<Flow> {
+        for statement in block.iter() {
+            match self.statement(statement)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, statement: &'a Statement) -> eyre::Result<Flow> {
+        match *statement {
+            Statement::Emit(ref range) => {
+                for handle in range.clone() {
+                    let value = self.evaluate(handle)?;
+                    self.set(handle, value);
+                }
+            }
+            Statement::Block(ref block) => return self.block(block),
+            Statement::If {
+                condition,
+                ref accept,
+                ref reject,
+            } => {
+                return match self.eval(condition)?.as_bool()? {
+                    true => self.block(accept),
+                    false => self.block(reject),
+                };
+            }
+            Statement::Switch {
+                selector,
+                ref cases,
+            } => {
+                let selector = self.eval(selector)?;
+
+                let start = cases
+                    .iter()
+                    .position(|case| match (case.value, &selector) {
+                        (SwitchValue::I32(value), Value::I32(selector)) => value == *selector,
+                        (SwitchValue::U32(value), Value::U32(selector)) => value == *selector,
+                        _ => false,
+                    })
+                    .or_else(|| {
+                        cases
+                            .iter()
+                            .position(|case| case.value == SwitchValue::Default)
+                    })
+                    .ok_or_else(|| eyre!("switch has no default case"))?;
+
+                for case in &cases[start..] {
+                    match self.block(&case.body)? {
+                        Flow::Next if case.fall_through => {}
+                        Flow::Next | Flow::Break => break,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            Statement::Loop {
+                ref body,
+                ref continuing,
+                break_if,
+            } => loop {
+                match self.block(body)? {
+                    Flow::Next | Flow::Continue => {}
+                    Flow::Break => break,
+                    flow => return Ok(flow),
+                }
+
+                match self.block(continuing)? {
+                    Flow::Next | Flow::Continue => {}
+                    Flow::Break => break,
+                    flow => return Ok(flow),
+                }
+
+                if let Some(break_if) = break_if {
+                    if self.eval(break_if)?.as_bool()? {
+                        break;
+                    }
+                }
+            },
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Return { value } => {
+                let value = match value {
+                    Some(value) => Some(self.eval(value)?),
+                    None => None,
+                };
+
+                return Ok(Flow::Return(value));
+            }
+            Statement::ControlBarrier(_) => self.shared.scheduler.barrier(self.index),
+            // Invocations don't run concurrently, so every write is visible immediately
+            Statement::MemoryBarrier(_) => {}
+            Statement::Store { pointer, value } => {
+                let pointer = self.eval(pointer)?;
+                let value = self.eval(value)?;
+                self.store(pointer.as_pointer()?, value)?;
+            }
+            Statement::Atomic {
+                pointer,
+                ref fun,
+                value,
+                result,
+            } => {
+                let pointer = self.eval(pointer)?;
+                let pointer = pointer.as_pointer()?;
+                let value = self.eval(value)?;
+                let old = self.load(pointer)?;
+
+                let binary = |op| ops::binary(op, old.clone(), value.clone());
+                let math = |fun| builtin::math(fun, vec![old.clone(), value.clone()]);
+
+                let (new, result_value) = match *fun {
+                    AtomicFunction::Add => (binary(BinaryOperator::Add)?, old),
+                    AtomicFunction::Subtract => (binary(BinaryOperator::Subtract)?, old),
+                    AtomicFunction::And => (binary(BinaryOperator::And)?, old),
+                    AtomicFunction::ExclusiveOr => (binary(BinaryOperator::ExclusiveOr)?, old),
+                    AtomicFunction::InclusiveOr => (binary(BinaryOperator::InclusiveOr)?, old),
+                    AtomicFunction::Min => (math(MathFunction::Min)?, old),
+                    AtomicFunction::Max => (math(MathFunction::Max)?, old),
+                    AtomicFunction::Exchange { compare: None } => (value, old),
+                    AtomicFunction::Exchange {
+                        compare: Some(compare),
+                    } => {
+                        let exchanged = old == self.eval(compare)?;
+                        let new = if exchanged { value } else { old.clone() };
+                        (new, Value::Composite(vec![old, Value::Bool(exchanged)]))
+                    }
+                };
+
+                self.store(pointer, new)?;
+
+                if let Some(result) = result {
+                    self.set(result, result_value);
+                }
+            }
+            Statement::WorkGroupUniformLoad { pointer, result } => {
+                let pointer = self.eval(pointer)?;
+                self.shared.scheduler.barrier(self.index);
+                let value = self.load(pointer.as_pointer()?)?;
+                self.shared.scheduler.barrier(self.index);
+                self.set(result, value);
+            }
+            Statement::Call {
+                function,
+                ref arguments,
+                result,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|&argument| self.eval(argument))
+                    .collect::<eyre::Result<_>>()?;
+
+                let value = self.call(&self.shared.module.functions[function], arguments)?;
+
+                if let Some(result) = result {
+                    let value = value.ok_or_else(|| eyre!("function didn't return a value"))?;
+                    self.set(result, value);
+                }
+            }
+            ref statement => bail!("statement is not supported by the interpreter: {statement:?}"),
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn set(&mut self, handle: Handle<Expression>, value: Value) {
+        self.frame_mut().values[handle.index()] = Some(value);
+    }
+
+    /// Returns the value of an expression, evaluating it if it hasn't been already.
+    fn eval(&mut self, handle: Handle<Expression>) -> eyre::Result<Value> {
+        if let Some(value) = &self.frame().values[handle.index()] {
+            return Ok(value.clone());
+        }
+
+        let value = self.evaluate(handle)?;
+        self.set(handle, value.clone());
+
+        Ok(value)
+    }
+
+    fn evaluate(&mut self, handle: Handle<Expression>) -> eyre::Result<Value> {
+        let module = self.shared.module;
+        let function = self.frame().function;
+
+        Ok(match function.expressions[handle] {
+            Expression::Literal(ref literal) => Value::from_literal(literal)?,
+            Expression::Constant(constant) => {
+                evaluate_const(module, module.constants[constant].init)?
+            }
+            Expression::ZeroValue(ty) => Value::zero(module, ty)?,
+            Expression::Compose { ty, ref components } => {
+                let components = components
+                    .iter()
+                    .map(|&component| self.eval(component))
+                    .collect::<eyre::Result<_>>()?;
+
+                Value::compose(module, ty, components)
+            }
+            Expression::Access { base, index } => {
+                let index = self.eval(index)?.as_index()?;
+                self.access(base, index)?
+            }
+            Expression::AccessIndex { base, index } => self.access(base, index)?,
+            Expression::Splat { size, value } => {
+                Value::Vector(vec![self.eval(value)?; size as usize])
+            }
+            Expression::Swizzle {
+                size,
+                vector,
+                pattern,
+            } => {
+                let vector = self.eval(vector)?;
+                Value::Vector(
+                    pattern[..size as usize]
+                        .iter()
+                        .map(|&component| vector.component(component as u32).cloned())
+                        .collect::<eyre::Result<_>>()?,
+                )
+            }
+            Expression::FunctionArgument(index) => self
+                .frame()
+                .arguments
+                .get(index as usize)
+                .cloned()
+                .ok_or_else(|| eyre!("missing function argument {index}"))?,
+            Expression::GlobalVariable(global) => match module.global_variables[global].space {
+                AddressSpace::Handle => Value::Handle(global),
+                _ => Value::Pointer(Pointer {
+                    root: Root::Global(global),
+                    path: vec![],
+                }),
+            },
+            Expression::LocalVariable(local) => Value::Pointer(Pointer {
+                root: Root::Local(self.frames.len() - 1, local),
+                path: vec![],
+            }),
+            Expression::Load { pointer } => {
+                let pointer = self.eval(pointer)?;
+                self.load(pointer.as_pointer()?)?
+            }
+            Expression::ImageSample {
+                image,
+                gather: Some(component),
+                coordinate,
+                array_index,
+                depth_ref,
+                offset: None,
+                ..
+            } => {
+                let texture = self.texture(image)?;
+
+                let coordinate = self.eval(coordinate)?;
+                let coords = match coordinate.components()? {
+                    [u, v] => [u.as_float()?, v.as_float()?],
+                    _ => bail!("gathering is only supported for 2d textures"),
+                };
+
+                let layer = self.eval_index(array_index)?;
+                let depth_ref = match depth_ref {
+                    Some(depth_ref) => Some(self.eval(depth_ref)?.as_float()?),
+                    None => None,
+                };
+
+                texture.gather(component as usize, coords, layer, depth_ref)?
+            }
+            Expression::ImageLoad {
+                image,
+                coordinate,
+                array_index,
+                sample: None,
+                level,
+            } => {
+                let texture = self.texture(image)?;
+
+                let mut coords = [0; 3];
+                match self.eval(coordinate)? {
+                    Value::Vector(components) => {
+                        for (coord, component) in coords.iter_mut().zip(&components) {
+                            *coord = component.as_index()?;
+                        }
+                    }
+                    component => coords[0] = component.as_index()?,
+                }
+
+                let layer = self.eval_index(array_index)?;
+                let level = self.eval_index(level)?;
+
+                texture.load(coords, layer, level)
+            }
+            Expression::ImageQuery { image, query } => {
+                let texture = self.texture(image)?;
+                let level = match query {
+                    ImageQuery::Size { level } => self.eval_index(level)?,
+                    _ => 0,
+                };
+
+                texture.query(query, level)
+            }
+            Expression::Unary { op, expr } => ops::unary(op, self.eval(expr)?)?,
+            Expression::Binary { op, left, right } => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                ops::binary(op, left, right)?
+            }
+            Expression::Select {
+                condition,
+                accept,
+                reject,
+            } => {
+                let condition = self.eval(condition)?;
+                let accept = self.eval(accept)?;
+                let reject = self.eval(reject)?;
+                ops::select(condition, accept, reject)?
+            }
+            Expression::Relational { fun, argument } => ops::relational(fun, self.eval(argument)?)?,
+            Expression::Math {
+                fun,
+                arg,
+                arg1,
+                arg2,
+                arg3,
+            } => {
+                let args = [Some(arg), arg1, arg2, arg3]
+                    .into_iter()
+                    .flatten()
+                    .map(|arg| self.eval(arg))
+                    .collect::<eyre::Result<_>>()?;
+
+                builtin::math(fun, args)?
+            }
+            Expression::As {
+                expr,
+                kind,
+                convert,
+            } => {
+                let value = self.eval(expr)?;
+                match convert {
+                    Some(width) => ops::convert(value, kind, width)?,
+                    None => ops::bitcast(value, kind)?,
+                }
+            }
+            Expression::ArrayLength(pointer) => {
+                let pointer = self.eval(pointer)?;
+                let array = self.load(pointer.as_pointer()?)?;
+                Value::U32(array.components()?.len() as u32)
+            }
+            Expression::CallResult(_)
+            | Expression::AtomicResult { .. }
+            | Expression::WorkGroupUniformLoadResult { .. } => {
+                bail!("result expression was used before it was produced")
+            }
+            ref expression => {
+                bail!("expression is not supported by the interpreter: {expression:?}")
+            }
+        })
+    }
+
+    /// Evaluates an optional index, which defaults to zero.
+    fn eval_index(&mut self, handle: Option<Handle<Expression>>) -> eyre::Result<u32> {
+        match handle {
+            Some(handle) => self.eval(handle)?.as_index(),
+            None => Ok(0),
+        }
+    }
+
+    /// Accesses a component of a value, or creates a pointer to a component of the value that a
+    /// pointer refers to.
+    fn access(&mut self, base: Handle<Expression>, index: u32) -> eyre::Result<Value> {
+        Ok(match self.eval(base)? {
+            Value::Pointer(mut pointer) => {
+                pointer.path.push(index);
+                Value::Pointer(pointer)
+            }
+            value => value.component(index)?.clone(),
+        })
+    }
+
+    fn texture(&mut self, image: Handle<Expression>) -> eyre::Result<&'a Texture> {
+        let handle = self.eval(image)?.as_handle()?;
+        self.shared
+            .textures
+            .get(&handle)
+            .ok_or_else(|| eyre!("texture is not bound to a resource"))
+    }
+
+    fn load(&mut self, pointer: &Pointer) -> eyre::Result<Value> {
+        self.with_variable(pointer.root, |variable| {
+            let mut value = &*variable;
+            for &index in &pointer.path {
+                value = value.component(index)?;
+            }
+            Ok(value.clone())
+        })
+    }
+
+    fn store(&mut self, pointer: &Pointer, new: Value) -> eyre::Result<()> {
+        self.with_variable(pointer.root, |variable| {
+            let mut value = variable;
+            for &index in &pointer.path {
+                value = value.component_mut(index)?;
+            }
+            *value = new;
+            Ok(())
+        })
+    }
+
+    fn with_variable<T>(
+        &mut self,
+        root: Root,
+        f: impl FnOnce(&mut Value) -> eyre::Result<T>,
+    ) -> eyre::Result<T> {
+        match root {
+            Root::Local(depth, local) => f(self.frames[depth]
+                .locals
+                .get_mut(&local)
+                .ok_or_else(|| eyre!("local variable has not been initialised"))?),
+            Root::Global(global) => match self.shared.module.global_variables[global].space {
+                AddressSpace::Private => f(self.private.get_mut(&global).unwrap()),
+                _ => {
+                    let mut memory = self.shared.memory.lock().unwrap();
+                    f(memory
+                        .get_mut(&global)
+                        .ok_or_else(|| eyre!("global variable is not bound to a resource"))?)
+                }
+            },
+        }
+    }
+}
+
+fn builtin_value(binding: &Binding, builtins: &Builtins) -> eyre::Result<Value> {
+    let vector = |v: [u32; 3]| Value::Vector(v.map(Value::U32).to_vec());
+
+    Ok(match binding {
+        Binding::BuiltIn(BuiltIn::GlobalInvocationId) => vector(builtins.global_invocation_id),
+        Binding::BuiltIn(BuiltIn::LocalInvocationId) => vector(builtins.local_invocation_id),
+        Binding::BuiltIn(BuiltIn::LocalInvocationIndex) => {
+            Value::U32(builtins.local_invocation_index)
+        }
+        Binding::BuiltIn(BuiltIn::WorkGroupId) => vector(builtins.workgroup_id),
+        Binding::BuiltIn(BuiltIn::NumWorkGroups) => vector(builtins.num_workgroups),
+        binding => bail!("entry point input is not supported by the interpreter: {binding:?}"),
+    })
+}
+
+/// Evaluates an expression in the module's global expression arena, such as the initializer of a
+/// constant.
+pub fn evaluate_const(module: &Module, handle: Handle<Expression>) -> eyre::Result<Value> {
+    Ok(match module.global_expressions[handle] {
+        Expression::Literal(ref literal) => Value::from_literal(literal)?,
+        Expression::Constant(constant) => evaluate_const(module, module.constants[constant].init)?,
+        Expression::ZeroValue(ty) => Value::zero(module, ty)?,
+        Expression::Compose { ty, ref components } => Value::compose(
+            module,
+            ty,
+            components
+                .iter()
+                .map(|&component| evaluate_const(module, component))
+                .collect::<eyre::Result<_>>()?,
+        ),
+        Expression::Splat { size, value } => {
+            Value::Vector(vec![evaluate_const(module, value)?; size as usize])
+        }
+        ref expression => bail!("unsupported constant expression: {expression:?}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::run_shader;
+
+    #[test]
+    fn switch_falls_through_to_shared_case() {
+        // Each selector of a case after the first is a separate naga case, which falls through to
+        // the case with the body
+        let shader = r#"
+            @group(0) @binding(0)
+            var<storage, read_write> out: array<u32, 4>;
+
+            @compute @workgroup_size(1)
+            fn main() {
+                for (var i = 0u; i < 4u; i++) {
+                    switch i {
+                        case 0u: { out[i] = 10u; }
+                        case 1u, 2u: { out[i] = 20u; }
+                        default: { out[i] = 30u; }
+                    }
+                }
+            }
+        "#;
+
+        assert_eq!(run_shader(shader, &[0; 4]), [10, 20, 20, 30]);
+    }
+
+    #[test]
+    fn loop_break_if() {
+        let shader = r#"
+            @group(0) @binding(0)
+            var<storage, read_write> out: array<u32, 2>;
+
+            @compute @workgroup_size(1)
+            fn main() {
+                var i = 0u;
+                loop {
+                    out[0] += 1u;
+                    continuing {
+                        i++;
+                        break if i >= 5u;
+                    }
+                }
+                out[1] = i;
+            }
+        "#;
+
+        // Each of the two workgroups runs the loop five times
+        assert_eq!(run_shader(shader, &[0; 2]), [10, 5]);
+    }
+
+    #[test]
+    fn workgroup_uniform_load_waits_for_writes() {
+        // The last invocation writes the value, so the others only see it if the load waits for
+        // every invocation to reach it
+        let shader = r#"
+            @group(0) @binding(0)
+            var<storage, read_write> out: array<u32, 8>;
+
+            var<workgroup> w: u32;
+
+            @compute @workgroup_size(4)
+            fn main(
+                @builtin(local_invocation_index) index: u32,
+                @builtin(workgroup_id) group: vec3<u32>,
+            ) {
+                if index == 3u {
+                    w = group.x + 1u;
+                }
+                out[group.x * 4u + index] = workgroupUniformLoad(&w);
+            }
+        "#;
+
+        assert_eq!(run_shader(shader, &[0; 8]), [1, 1, 1, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn workgroup_barrier() {
+        let shader = r#"
+            @group(0) @binding(0)
+            var<storage, read_write> out: array<u32, 8>;
+
+            var<workgroup> w: array<u32, 4>;
+
+            @compute @workgroup_size(4)
+            fn main(
+                @builtin(global_invocation_id) id: vec3<u32>,
+                @builtin(local_invocation_index) index: u32,
+            ) {
+                w[index] = id.x;
+                workgroupBarrier();
+                out[id.x] = w[3u - index];
+            }
+        "#;
+
+        assert_eq!(run_shader(shader, &[0; 8]), [3, 2, 1, 0, 7, 6, 5, 4]);
+    }
+}
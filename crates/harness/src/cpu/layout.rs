@@ -0,0 +1,154 @@
+//! Conversion between values and the bytes of host-shareable buffers, following the memory layout
+//! that naga computes for each type.
+
+use eyre::bail;
+use half::f16;
+use naga::{ArraySize, Handle, Module, Scalar, ScalarKind, Type, TypeInner, VectorSize};
+
+use super::value::Value;
+
+/// Decodes a value of the given type from the start of `bytes`. The length of a runtime-sized
+/// array is determined by the number of bytes that are available.
+///
+/// Bytes beyond the end of `bytes` are treated as zero.
+pub fn read(module: &Module, ty: Handle<Type>, bytes: &[u8]) -> eyre::Result<Value> {
+    Ok(match module.types[ty].inner {
+        TypeInner::Scalar(scalar) | TypeInner::Atomic(scalar) => read_scalar(scalar, bytes)?,
+        TypeInner::Vector { size, scalar } => read_vector(size, scalar, bytes)?,
+        TypeInner::Matrix {
+            columns,
+            rows,
+            scalar,
+        } => {
+            let stride = column_stride(rows, scalar);
+            Value::Composite(
+                (0..columns as usize)
+                    .map(|i| read_vector(rows, scalar, tail(bytes, i * stride)))
+                    .collect::<eyre::Result<_>>()?,
+            )
+        }
+        TypeInner::Array { base, size, stride } => {
+            let stride = stride as usize;
+            let count = match size {
+                ArraySize::Constant(size) => size.get() as usize,
+                ArraySize::Dynamic => bytes.len() / stride,
+                ArraySize::Pending(_) => bail!("array size depends on an override"),
+            };
+
+            Value::Composite(
+                (0..count)
+                    .map(|i| read(module, base, tail(bytes, i * stride)))
+                    .collect::<eyre::Result<_>>()?,
+            )
+        }
+        TypeInner::Struct { ref members, .. } => Value::Composite(
+            members
+                .iter()
+                .map(|member| read(module, member.ty, tail(bytes, member.offset as usize)))
+                .collect::<eyre::Result<_>>()?,
+        ),
+        ref inner => bail!("type is not host-shareable: {inner:?}"),
+    })
+}
+
+/// Encodes a value of the given type into the start of `bytes`. Padding bytes are left untouched,
+/// as are any bytes of the value that don't fit.
+pub fn write(
+    module: &Module,
+    ty: Handle<Type>,
+    value: &Value,
+    bytes: &mut [u8],
+) -> eyre::Result<()> {
+    match module.types[ty].inner {
+        TypeInner::Scalar(_) | TypeInner::Atomic(_) => write_scalar(value, bytes)?,
+        TypeInner::Vector { scalar, .. } => {
+            for (i, component) in value.components()?.iter().enumerate() {
+                write_scalar(component, tail_mut(bytes, i * scalar.width as usize))?;
+            }
+        }
+        TypeInner::Matrix { rows, scalar, .. } => {
+            let stride = column_stride(rows, scalar);
+            for (i, column) in value.components()?.iter().enumerate() {
+                for (j, component) in column.components()?.iter().enumerate() {
+                    let offset = i * stride + j * scalar.width as usize;
+                    write_scalar(component, tail_mut(bytes, offset))?;
+                }
+            }
+        }
+        TypeInner::Array { base, stride, .. } => {
+            for (i, element) in value.components()?.iter().enumerate() {
+                write(module, base, element, tail_mut(bytes, i * stride as usize))?;
+            }
+        }
+        TypeInner::Struct { ref members, .. } => {
+            for (member, value) in members.iter().zip(value.components()?) {
+                write(
+                    module,
+                    member.ty,
+                    value,
+                    tail_mut(bytes, member.offset as usize),
+                )?;
+            }
+        }
+        ref inner => bail!("type is not host-shareable: {inner:?}"),
+    }
+
+    Ok(())
+}
+
+/// Returns the distance between the columns of a matrix, which are aligned like vectors.
+fn column_stride(rows: VectorSize, scalar: Scalar) -> usize {
+    let width = scalar.width as usize;
+    match rows {
+        VectorSize::Bi => 2 * width,
+        _ => 4 * width,
+    }
+}
+
+fn tail(bytes: &[u8], offset: usize) -> &[u8] {
+    bytes.get(offset..).unwrap_or_default()
+}
+
+fn tail_mut(bytes: &mut [u8], offset: usize) -> &mut [u8] {
+    let offset = offset.min(bytes.len());
+    &mut bytes[offset..]
+}
+
+fn read_vector(size: VectorSize, scalar: Scalar, bytes: &[u8]) -> eyre::Result<Value> {
+    Ok(Value::Vector(
+        (0..size as usize)
+            .map(|i| read_scalar(scalar, tail(bytes, i * scalar.width as usize)))
+            .collect::<eyre::Result<_>>()?,
+    ))
+}
+
+fn read_scalar(scalar: Scalar, bytes: &[u8]) -> eyre::Result<Value> {
+    let mut buffer = [0; 4];
+    let len = bytes.len().min(scalar.width as usize);
+    buffer[..len].copy_from_slice(&bytes[..len]);
+
+    let bits = u32::from_le_bytes(buffer);
+
+    Ok(match (scalar.kind, scalar.width) {
+        (ScalarKind::Sint, 4) => Value::I32(bits as i32),
+        (ScalarKind::Uint, 4) => Value::U32(bits),
+        (ScalarKind::Float, 4) => Value::F32(f32::from_bits(bits)),
+        (ScalarKind::Float, 2) => Value::F16(f16::from_bits(bits as u16)),
+        _ => bail!("scalar type is not host-shareable: {scalar:?}"),
+    })
+}
+
+fn write_scalar(value: &Value, bytes: &mut [u8]) -> eyre::Result<()> {
+    let encoded = match *value {
+        Value::I32(v) => v.to_le_bytes().to_vec(),
+        Value::U32(v) => v.to_le_bytes().to_vec(),
+        Value::F32(v) => v.to_le_bytes().to_vec(),
+        Value::F16(v) => v.to_le_bytes().to_vec(),
+        _ => bail!("value is not host-shareable: {value:?}"),
+    };
+
+    let len = bytes.len().min(encoded.len());
+    bytes[..len].copy_from_slice(&encoded[..len]);
+
+    Ok(())
+}
@@ -0,0 +1,387 @@
+//! Operators and conversions, which follow the WGSL rules for runtime expressions.
+
+use eyre::{bail, eyre};
+use half::f16;
+use naga::{BinaryOperator, RelationalFunction, ScalarKind, UnaryOperator};
+
+use super::value::Value;
+
+/// Applies `f` to each component of a vector, or to a scalar.
+pub fn componentwise(
+    value: Value,
+    f: impl Fn(Value) -> eyre::Result<Value>,
+) -> eyre::Result<Value> {
+    match value {
+        Value::Vector(components) => Ok(Value::Vector(
+            components.into_iter().map(f).collect::<eyre::Result<_>>()?,
+        )),
+        value => f(value),
+    }
+}
+
+/// Applies `f` to each pair of components of two vectors. A scalar operand is broadcast to the
+/// size of the other operand.
+pub fn componentwise2(
+    a: Value,
+    b: Value,
+    f: impl Fn(Value, Value) -> eyre::Result<Value>,
+) -> eyre::Result<Value> {
+    match (a, b) {
+        (Value::Vector(a), Value::Vector(b)) => Ok(Value::Vector(
+            a.into_iter()
+                .zip(b)
+                .map(|(a, b)| f(a, b))
+                .collect::<eyre::Result<_>>()?,
+        )),
+        (Value::Vector(a), b) => Ok(Value::Vector(
+            a.into_iter()
+                .map(|a| f(a, b.clone()))
+                .collect::<eyre::Result<_>>()?,
+        )),
+        (a, Value::Vector(b)) => Ok(Value::Vector(
+            b.into_iter()
+                .map(|b| f(a.clone(), b))
+                .collect::<eyre::Result<_>>()?,
+        )),
+        (a, b) => f(a, b),
+    }
+}
+
+/// Applies `f` to each triple of components of three vectors, broadcasting scalar operands.
+pub fn componentwise3(
+    a: Value,
+    b: Value,
+    c: Value,
+    f: impl Fn(Value, Value, Value) -> eyre::Result<Value>,
+) -> eyre::Result<Value> {
+    let size = [&a, &b, &c].into_iter().find_map(|it| match it {
+        Value::Vector(components) => Some(components.len()),
+        _ => None,
+    });
+
+    let Some(size) = size else {
+        return f(a, b, c);
+    };
+
+    let [a, b, c] = [a, b, c].map(|it| match it {
+        Value::Vector(components) => components,
+        scalar => vec![scalar; size],
+    });
+
+    Ok(Value::Vector(
+        a.into_iter()
+            .zip(b)
+            .zip(c)
+            .map(|((a, b), c)| f(a, b, c))
+            .collect::<eyre::Result<_>>()?,
+    ))
+}
+
+/// Applies a floating point function to each component of an `f32` or `f16` vector or scalar.
+/// Operations on `f16` are carried out in single precision and rounded afterwards.
+pub fn float1(value: Value, f: impl Fn(f32) -> f32) -> eyre::Result<Value> {
+    componentwise(value, |value| match value {
+        Value::F32(v) => Ok(Value::F32(f(v))),
+        Value::F16(v) => Ok(Value::F16(f16::from_f32(f(v.to_f32())))),
+        value => bail!("expected float, found {value:?}"),
+    })
+}
+
+pub fn float2(a: Value, b: Value, f: impl Fn(f32, f32) -> f32) -> eyre::Result<Value> {
+    componentwise2(a, b, |a, b| match (a, b) {
+        (Value::F32(a), Value::F32(b)) => Ok(Value::F32(f(a, b))),
+        (Value::F16(a), Value::F16(b)) => Ok(Value::F16(f16::from_f32(f(a.to_f32(), b.to_f32())))),
+        (a, b) => bail!("expected floats, found {a:?} and {b:?}"),
+    })
+}
+
+pub fn float3(
+    a: Value,
+    b: Value,
+    c: Value,
+    f: impl Fn(f32, f32, f32) -> f32,
+) -> eyre::Result<Value> {
+    componentwise3(a, b, c, |a, b, c| match (a, b, c) {
+        (Value::F32(a), Value::F32(b), Value::F32(c)) => Ok(Value::F32(f(a, b, c))),
+        (Value::F16(a), Value::F16(b), Value::F16(c)) => Ok(Value::F16(f16::from_f32(f(
+            a.to_f32(),
+            b.to_f32(),
+            c.to_f32(),
+        )))),
+        (a, b, c) => bail!("expected floats, found {a:?}, {b:?} and {c:?}"),
+    })
+}
+
+pub fn unary(op: UnaryOperator, value: Value) -> eyre::Result<Value> {
+    componentwise(value, |value| {
+        Ok(match (op, value) {
+            (UnaryOperator::Negate, Value::I32(v)) => Value::I32(v.wrapping_neg()),
+            (UnaryOperator::Negate, Value::F32(v)) => Value::F32(-v),
+            (UnaryOperator::Negate, Value::F16(v)) => Value::F16(-v),
+            (UnaryOperator::LogicalNot, Value::Bool(v)) => Value::Bool(!v),
+            (UnaryOperator::BitwiseNot, Value::I32(v)) => Value::I32(!v),
+            (UnaryOperator::BitwiseNot, Value::U32(v)) => Value::U32(!v),
+            (op, value) => bail!("invalid operand for {op:?}: {value:?}"),
+        })
+    })
+}
+
+pub fn binary(op: BinaryOperator, left: Value, right: Value) -> eyre::Result<Value> {
+    componentwise2(left, right, |left, right| binary_scalar(op, left, right))
+}
+
+fn binary_scalar(op: BinaryOperator, left: Value, right: Value) -> eyre::Result<Value> {
+    use BinaryOperator::*;
+
+    Ok(match (left, right) {
+        (Value::I32(l), Value::I32(r)) => match op {
+            Add => Value::I32(l.wrapping_add(r)),
+            Subtract => Value::I32(l.wrapping_sub(r)),
+            Multiply => Value::I32(l.wrapping_mul(r)),
+            // Division by zero and overflowing division evaluate to the dividend
+            Divide => Value::I32(l.checked_div(r).unwrap_or(l)),
+            Modulo => Value::I32(l.checked_rem(r).unwrap_or(0)),
+            And => Value::I32(l & r),
+            ExclusiveOr => Value::I32(l ^ r),
+            InclusiveOr => Value::I32(l | r),
+            _ => compare(op, l, r)?,
+        },
+        (Value::U32(l), Value::U32(r)) => match op {
+            Add => Value::U32(l.wrapping_add(r)),
+            Subtract => Value::U32(l.wrapping_sub(r)),
+            Multiply => Value::U32(l.wrapping_mul(r)),
+            Divide => Value::U32(l.checked_div(r).unwrap_or(l)),
+            Modulo => Value::U32(l.checked_rem(r).unwrap_or(0)),
+            And => Value::U32(l & r),
+            ExclusiveOr => Value::U32(l ^ r),
+            InclusiveOr => Value::U32(l | r),
+            // The shift amount is taken modulo the bit width
+            ShiftLeft => Value::U32(l.wrapping_shl(r)),
+            ShiftRight => Value::U32(l.wrapping_shr(r)),
+            _ => compare(op, l, r)?,
+        },
+        (Value::I32(l), Value::U32(r)) => match op {
+            ShiftLeft => Value::I32(l.wrapping_shl(r)),
+            ShiftRight => Value::I32(l.wrapping_shr(r)),
+            _ => bail!("invalid operands for {op:?}: {l:?} and {r:?}"),
+        },
+        (Value::F32(l), Value::F32(r)) => match float_arithmetic(op, l, r) {
+            Some(result) => Value::F32(result),
+            None => compare(op, l, r)?,
+        },
+        (Value::F16(l), Value::F16(r)) => match float_arithmetic(op, l.to_f32(), r.to_f32()) {
+            Some(result) => Value::F16(f16::from_f32(result)),
+            None => compare(op, l, r)?,
+        },
+        (Value::Bool(l), Value::Bool(r)) => match op {
+            And | LogicalAnd => Value::Bool(l && r),
+            InclusiveOr | LogicalOr => Value::Bool(l || r),
+            Equal => Value::Bool(l == r),
+            NotEqual => Value::Bool(l != r),
+            _ => bail!("invalid operands for {op:?}: {l:?} and {r:?}"),
+        },
+        (l, r) => bail!("invalid operands for {op:?}: {l:?} and {r:?}"),
+    })
+}
+
+fn float_arithmetic(op: BinaryOperator, l: f32, r: f32) -> Option<f32> {
+    Some(match op {
+        BinaryOperator::Add => l + r,
+        BinaryOperator::Subtract => l - r,
+        BinaryOperator::Multiply => l * r,
+        BinaryOperator::Divide => l / r,
+        // WGSL defines the remainder in terms of truncated division, rather than as an exact
+        // operation like fmod
+        BinaryOperator::Modulo => l - r * (l / r).trunc(),
+        _ => return None,
+    })
+}
+
+fn compare<T: PartialOrd + std::fmt::Debug>(op: BinaryOperator, l: T, r: T) -> eyre::Result<Value> {
+    Ok(Value::Bool(match op {
+        BinaryOperator::Equal => l == r,
+        BinaryOperator::NotEqual => l != r,
+        BinaryOperator::Less => l < r,
+        BinaryOperator::LessEqual => l <= r,
+        BinaryOperator::Greater => l > r,
+        BinaryOperator::GreaterEqual => l >= r,
+        _ => bail!("invalid operands for {op:?}: {l:?} and {r:?}"),
+    }))
+}
+
+/// Selects `accept` if `condition` is true, otherwise `reject`. A vector condition selects each
+/// component separately.
+pub fn select(condition: Value, accept: Value, reject: Value) -> eyre::Result<Value> {
+    match condition {
+        Value::Vector(condition) => Ok(Value::Vector(
+            condition
+                .iter()
+                .zip(accept.into_components()?)
+                .zip(reject.into_components()?)
+                .map(|((c, a), r)| Ok(if c.as_bool()? { a } else { r }))
+                .collect::<eyre::Result<_>>()?,
+        )),
+        condition => Ok(if condition.as_bool()? { accept } else { reject }),
+    }
+}
+
+pub fn relational(fun: RelationalFunction, value: Value) -> eyre::Result<Value> {
+    match fun {
+        RelationalFunction::All | RelationalFunction::Any => {
+            let components = match value {
+                Value::Vector(components) => components,
+                value => vec![value],
+            };
+
+            let mut bools = components.iter().map(Value::as_bool);
+
+            Ok(Value::Bool(match fun {
+                RelationalFunction::All => {
+                    bools.try_fold(true, |acc, it| Ok::<_, eyre::Report>(acc && it?))?
+                }
+                _ => bools.try_fold(false, |acc, it| Ok::<_, eyre::Report>(acc || it?))?,
+            }))
+        }
+        RelationalFunction::IsNan => {
+            componentwise(value, |v| Ok(Value::Bool(v.as_float()?.is_nan())))
+        }
+        RelationalFunction::IsInf => {
+            componentwise(value, |v| Ok(Value::Bool(v.as_float()?.is_infinite())))
+        }
+    }
+}
+
+/// Converts each component to a scalar of the given kind and width. Conversions from floating
+/// point to integers round towards zero and saturate.
+pub fn convert(value: Value, kind: ScalarKind, width: u8) -> eyre::Result<Value> {
+    componentwise(value, |value| {
+        Ok(match (kind, width) {
+            (ScalarKind::Bool, _) => Value::Bool(match value {
+                Value::Bool(v) => v,
+                Value::I32(v) => v != 0,
+                Value::U32(v) => v != 0,
+                ref v => v.as_float()? != 0.0,
+            }),
+            (ScalarKind::Sint, 4) => Value::I32(match value {
+                Value::Bool(v) => v as i32,
+                Value::I32(v) => v,
+                Value::U32(v) => v as i32,
+                ref v => v.as_float()? as i32,
+            }),
+            (ScalarKind::Uint, 4) => Value::U32(match value {
+                Value::Bool(v) => v as u32,
+                Value::I32(v) => v as u32,
+                Value::U32(v) => v,
+                ref v => v.as_float()? as u32,
+            }),
+            (ScalarKind::Float, 4) => Value::F32(match value {
+                Value::Bool(v) => v as u32 as f32,
+                Value::I32(v) => v as f32,
+                Value::U32(v) => v as f32,
+                ref v => v.as_float()?,
+            }),
+            (ScalarKind::Float, 2) => Value::F16(match value {
+                Value::Bool(v) => f16::from_f32(v as u32 as f32),
+                Value::I32(v) => f16::from_f32(v as f32),
+                Value::U32(v) => f16::from_f32(v as f32),
+                Value::F16(v) => v,
+                ref v => f16::from_f32(v.as_float()?),
+            }),
+            _ => bail!("unsupported conversion of {value:?} to {kind:?}{width}"),
+        })
+    })
+}
+
+/// Reinterprets the bits of each 32-bit component as a scalar of the given kind.
+pub fn bitcast(value: Value, kind: ScalarKind) -> eyre::Result<Value> {
+    componentwise(value, |value| {
+        let bits = match value {
+            Value::I32(v) => v as u32,
+            Value::U32(v) => v,
+            Value::F32(v) => v.to_bits(),
+            ref value => return Err(eyre!("unsupported bitcast of {value:?}")),
+        };
+
+        Ok(match kind {
+            ScalarKind::Sint => Value::I32(bits as i32),
+            ScalarKind::Uint => Value::U32(bits),
+            ScalarKind::Float => Value::F32(f32::from_bits(bits)),
+            _ => bail!("unsupported bitcast to {kind:?}"),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn i32_op(op: BinaryOperator, l: i32, r: i32) -> Value {
+        binary_scalar(op, Value::I32(l), Value::I32(r)).unwrap()
+    }
+
+    fn u32_op(op: BinaryOperator, l: u32, r: u32) -> Value {
+        binary_scalar(op, Value::U32(l), Value::U32(r)).unwrap()
+    }
+
+    #[test]
+    fn overflowing_division() {
+        assert_eq!(
+            i32_op(BinaryOperator::Divide, i32::MIN, -1),
+            Value::I32(i32::MIN)
+        );
+        assert_eq!(i32_op(BinaryOperator::Modulo, i32::MIN, -1), Value::I32(0));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(i32_op(BinaryOperator::Divide, -7, 0), Value::I32(-7));
+        assert_eq!(i32_op(BinaryOperator::Modulo, -7, 0), Value::I32(0));
+        assert_eq!(u32_op(BinaryOperator::Divide, 7, 0), Value::U32(7));
+        assert_eq!(u32_op(BinaryOperator::Modulo, 7, 0), Value::U32(0));
+    }
+
+    #[test]
+    fn shift_amount_is_modulo_bit_width() {
+        assert_eq!(u32_op(BinaryOperator::ShiftLeft, 1, 33), Value::U32(2));
+        assert_eq!(u32_op(BinaryOperator::ShiftRight, 8, 32), Value::U32(8));
+        assert_eq!(
+            binary_scalar(BinaryOperator::ShiftRight, Value::I32(-8), Value::U32(34)).unwrap(),
+            Value::I32(-2)
+        );
+    }
+
+    #[test]
+    fn float_remainder_truncates() {
+        let rem = |l: f32, r: f32| {
+            binary_scalar(BinaryOperator::Modulo, Value::F32(l), Value::F32(r)).unwrap()
+        };
+
+        assert_eq!(rem(7.5, 2.0), Value::F32(1.5));
+        assert_eq!(rem(-7.5, 2.0), Value::F32(-1.5));
+        assert_eq!(rem(7.5, -2.0), Value::F32(1.5));
+    }
+
+    #[test]
+    fn float_to_integer_conversion_saturates() {
+        let to_i32 = |v: f32| convert(Value::F32(v), ScalarKind::Sint, 4).unwrap();
+        let to_u32 = |v: f32| convert(Value::F32(v), ScalarKind::Uint, 4).unwrap();
+
+        assert_eq!(to_i32(1e10), Value::I32(i32::MAX));
+        assert_eq!(to_i32(-1e10), Value::I32(i32::MIN));
+        assert_eq!(to_i32(-2.75), Value::I32(-2));
+        assert_eq!(to_u32(5e9), Value::U32(u32::MAX));
+        assert_eq!(to_u32(-1.0), Value::U32(0));
+    }
+
+    #[test]
+    fn nan_converts_to_zero() {
+        assert_eq!(
+            convert(Value::F32(f32::NAN), ScalarKind::Sint, 4).unwrap(),
+            Value::I32(0)
+        );
+        assert_eq!(
+            convert(Value::F16(f16::NAN), ScalarKind::Uint, 4).unwrap(),
+            Value::U32(0)
+        );
+    }
+}
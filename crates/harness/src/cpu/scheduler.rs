@@ -0,0 +1,134 @@
+use std::sync::{Condvar, Mutex};
+
+/// Runs the invocations of a workgroup one at a time in a fixed order, so that execution is
+/// deterministic.
+///
+/// Each invocation runs on its own thread, but only the invocation whose turn it is may make
+/// progress. The turn is passed on to the next unfinished invocation when an invocation reaches a
+/// barrier or finishes, so every invocation reaches a barrier before any of them continue past it.
+pub struct Scheduler {
+    state: Mutex<State>,
+    turn_changed: Condvar,
+}
+
+struct State {
+    turn: Option<usize>,
+    finished: Vec<bool>,
+}
+
+impl Scheduler {
+    pub fn new(invocations: usize) -> Scheduler {
+        Scheduler {
+            state: Mutex::new(State {
+                turn: (invocations > 0).then_some(0),
+                finished: vec![false; invocations],
+            }),
+            turn_changed: Condvar::new(),
+        }
+    }
+
+    /// Runs an invocation once it is its turn. The turn is passed on when the invocation finishes,
+    /// even if it panics, so that the others aren't blocked forever.
+    pub fn run<T>(&self, invocation: usize, f: impl FnOnce() -> T) -> T {
+        struct Finish<'a>(&'a Scheduler, usize);
+
+        impl Drop for Finish<'_> {
+            fn drop(&mut self) {
+                self.0.pass(self.1, true);
+            }
+        }
+
+        self.wait(invocation);
+        let _finish = Finish(self, invocation);
+        f()
+    }
+
+    /// Blocks until it is the given invocation's turn.
+    fn wait(&self, invocation: usize) {
+        let mut state = self.state.lock().unwrap();
+        while state.turn != Some(invocation) {
+            state = self.turn_changed.wait(state).unwrap();
+        }
+    }
+
+    /// Passes the turn on and waits for the other invocations to reach the barrier.
+    pub fn barrier(&self, invocation: usize) {
+        self.pass(invocation, false);
+        self.wait(invocation);
+    }
+
+    fn pass(&self, invocation: usize, finished: bool) {
+        let mut state = self.state.lock().unwrap();
+        let count = state.finished.len();
+
+        state.finished[invocation] |= finished;
+        state.turn = (1..=count)
+            .map(|offset| (invocation + offset) % count)
+            .find(|&next| !state.finished[next]);
+
+        self.turn_changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn barrier_waits_for_every_invocation() {
+        let scheduler = Scheduler::new(3);
+        let events = Mutex::new(vec![]);
+
+        thread::scope(|scope| {
+            // Spawned in reverse so that the order doesn't depend on when the threads start
+            for invocation in (0..3).rev() {
+                let (scheduler, events) = (&scheduler, &events);
+                scope.spawn(move || {
+                    scheduler.run(invocation, || {
+                        events.lock().unwrap().push(("before", invocation));
+                        scheduler.barrier(invocation);
+                        events.lock().unwrap().push(("after", invocation));
+                    })
+                });
+            }
+        });
+
+        assert_eq!(
+            events.into_inner().unwrap(),
+            [
+                ("before", 0),
+                ("before", 1),
+                ("before", 2),
+                ("after", 0),
+                ("after", 1),
+                ("after", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn finished_invocations_are_skipped() {
+        let scheduler = Scheduler::new(2);
+        let events = Mutex::new(vec![]);
+
+        thread::scope(|scope| {
+            let (scheduler, events) = (&scheduler, &events);
+            scope.spawn(move || {
+                scheduler.run(0, || {
+                    events.lock().unwrap().push(("before", 0));
+                    scheduler.barrier(0);
+                    events.lock().unwrap().push(("after", 0));
+                })
+            });
+            // Returns without reaching the barrier, as after an early `return`
+            scope.spawn(move || scheduler.run(1, || events.lock().unwrap().push(("return", 1))));
+        });
+
+        assert_eq!(
+            events.into_inner().unwrap(),
+            [("before", 0), ("return", 1), ("after", 0)]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use eyre::bail;
+use naga::ImageQuery;
+use reflection::{TextureDescriptor, TextureDimension, TextureSampleType};
+
+use super::value::Value;
+
+/// A texture that is bound to the pipeline, with the contents of its first mip level.
+pub struct Texture {
+    pub desc: TextureDescriptor,
+    pub data: Vec<u8>,
+}
+
+impl Texture {
+    pub fn new(desc: TextureDescriptor, init: Option<&[u8]>) -> Texture {
+        let mut data = vec![0; desc.data_size()];
+
+        if let Some(init) = init {
+            let len = data.len().min(init.len());
+            data[..len].copy_from_slice(&init[..len]);
+        }
+
+        Texture { desc, data }
+    }
+
+    fn layers(&self) -> u32 {
+        match self.desc.dimension {
+            TextureDimension::D2Array => self.desc.size[2],
+            TextureDimension::CubeArray => self.desc.size[2] / 6,
+            _ => 1,
+        }
+    }
+
+    /// Returns the width, height and depth of the given mip level.
+    fn level_size(&self, level: u32) -> [u32; 3] {
+        let [width, height, depth] = self.desc.size;
+        let depth = match self.desc.dimension {
+            TextureDimension::D3 => depth,
+            _ => 1,
+        };
+
+        [width, height, depth].map(|it| (it >> level.min(31)).max(1))
+    }
+
+    /// Returns the result of a query. The level is only used when querying the size.
+    pub fn query(&self, query: ImageQuery, level: u32) -> Value {
+        match query {
+            ImageQuery::Size { .. } => {
+                let [width, height, depth] = self.level_size(level);
+                match self.desc.dimension {
+                    TextureDimension::D1 => Value::U32(width),
+                    TextureDimension::D3 => Value::Vector(vec![
+                        Value::U32(width),
+                        Value::U32(height),
+                        Value::U32(depth),
+                    ]),
+                    _ => Value::Vector(vec![Value::U32(width), Value::U32(height)]),
+                }
+            }
+            ImageQuery::NumLevels => Value::U32(self.desc.mip_level_count),
+            ImageQuery::NumLayers => Value::U32(self.layers()),
+            ImageQuery::NumSamples => Value::U32(1),
+        }
+    }
+
+    /// Returns the texel at the given coordinates, or zero if they are out of bounds. Only the
+    /// first mip level has any contents.
+    pub fn load(&self, coords: [u32; 3], layer: u32, level: u32) -> Value {
+        let [width, height, depth] = self.level_size(level);
+        let [x, y, z] = coords;
+
+        let in_bounds = x < width
+            && y < height
+            && z < depth
+            && layer < self.layers()
+            && level < self.desc.mip_level_count;
+
+        if !in_bounds || level > 0 {
+            return self.texel(None);
+        }
+
+        // Either the z coordinate or the layer is zero, depending on the dimension
+        let index = (((z + layer) * height + y) * width + x) as usize;
+        let texel_size = self.desc.texel_size() as usize;
+
+        self.texel(self.data.get(index * texel_size..(index + 1) * texel_size))
+    }
+
+    fn texel(&self, bytes: Option<&[u8]>) -> Value {
+        let bytes = bytes.unwrap_or(&[0; 4]);
+        match self.desc.sample_type {
+            TextureSampleType::Float => Value::Vector(
+                bytes
+                    .iter()
+                    .map(|&it| Value::F32(it as f32 / 255.0))
+                    .collect(),
+            ),
+            TextureSampleType::Sint => Value::Vector(
+                bytes
+                    .iter()
+                    .map(|&it| Value::I32(it as i8 as i32))
+                    .collect(),
+            ),
+            TextureSampleType::Uint => {
+                Value::Vector(bytes.iter().map(|&it| Value::U32(it as u32)).collect())
+            }
+            TextureSampleType::Depth => {
+                Value::F32(u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0)
+            }
+        }
+    }
+
+    /// Gathers a component from the 2x2 block of texels that would be used for bilinear filtering
+    /// at the given coordinates, using a sampler with clamp-to-edge addressing. If a depth
+    /// reference is given, each texel is instead compared against it using the `less` function.
+    pub fn gather(
+        &self,
+        component: usize,
+        coords: [f32; 2],
+        layer: u32,
+        depth_ref: Option<f32>,
+    ) -> eyre::Result<Value> {
+        if !matches!(
+            self.desc.dimension,
+            TextureDimension::D2 | TextureDimension::D2Array
+        ) {
+            bail!(
+                "gathering from {:?} textures is not supported by the interpreter",
+                self.desc.dimension
+            );
+        }
+
+        let [width, height, _] = self.desc.size;
+        let layer = layer.min(self.layers() - 1);
+
+        let texel_coords = |coord: f32, size: u32| {
+            let low = (coord * size as f32 - 0.5).floor() as i64;
+            [low, low + 1].map(|it| it.clamp(0, size as i64 - 1) as u32)
+        };
+
+        let [x0, x1] = texel_coords(coords[0], width);
+        let [y0, y1] = texel_coords(coords[1], height);
+
+        // The order of the texels is defined by the WGSL spec
+        [[x0, y1], [x1, y1], [x1, y0], [x0, y0]]
+            .into_iter()
+            .map(|[x, y]| {
+                let texel = self.load([x, y, 0], layer, 0);
+                let value = match texel {
+                    Value::Vector(components) => components[component].as_float()?,
+                    texel => texel.as_float()?,
+                };
+
+                Ok(Value::F32(match depth_ref {
+                    Some(depth_ref) if depth_ref < value => 1.0,
+                    Some(_) => 0.0,
+                    None => value,
+                }))
+            })
+            .collect::<eyre::Result<_>>()
+            .map(Value::Vector)
+    }
+}
@@ -0,0 +1,208 @@
+use eyre::{bail, eyre};
+use half::f16;
+use naga::{
+    ArraySize, GlobalVariable, Handle, Literal, LocalVariable, Module, Scalar, ScalarKind, Type,
+    TypeInner,
+};
+
+/// A value produced by evaluating an expression.
+///
+/// Vectors and matrix columns are stored as `Vector`, while matrices, arrays and structs are all
+/// stored as `Composite`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    F32(f32),
+    F16(f16),
+    Vector(Vec<Value>),
+    Composite(Vec<Value>),
+    Pointer(Pointer),
+    /// A texture or sampler, which is identified by the global variable it is bound to.
+    Handle(Handle<GlobalVariable>),
+}
+
+/// Refers to a component of a variable, by following `path` from the root variable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pointer {
+    pub root: Root,
+    pub path: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Root {
+    Global(Handle<GlobalVariable>),
+    /// A local variable of the function at the given depth of the call stack.
+    Local(usize, Handle<LocalVariable>),
+}
+
+impl Value {
+    pub fn from_literal(literal: &Literal) -> eyre::Result<Value> {
+        Ok(match *literal {
+            Literal::Bool(v) => Value::Bool(v),
+            Literal::I32(v) => Value::I32(v),
+            Literal::U32(v) => Value::U32(v),
+            Literal::F32(v) => Value::F32(v),
+            Literal::F16(v) => Value::F16(v),
+            _ => bail!("unsupported literal: {literal:?}"),
+        })
+    }
+
+    pub fn zero_scalar(scalar: Scalar) -> eyre::Result<Value> {
+        Ok(match (scalar.kind, scalar.width) {
+            (ScalarKind::Bool, _) => Value::Bool(false),
+            (ScalarKind::Sint, 4) => Value::I32(0),
+            (ScalarKind::Uint, 4) => Value::U32(0),
+            (ScalarKind::Float, 4) => Value::F32(0.0),
+            (ScalarKind::Float, 2) => Value::F16(f16::ZERO),
+            _ => bail!("unsupported scalar type: {scalar:?}"),
+        })
+    }
+
+    /// Returns the zero value of a constructible type.
+    pub fn zero(module: &Module, ty: Handle<Type>) -> eyre::Result<Value> {
+        Ok(match module.types[ty].inner {
+            TypeInner::Scalar(scalar) | TypeInner::Atomic(scalar) => Value::zero_scalar(scalar)?,
+            TypeInner::Vector { size, scalar } => {
+                Value::Vector(vec![Value::zero_scalar(scalar)?; size as usize])
+            }
+            TypeInner::Matrix {
+                columns,
+                rows,
+                scalar,
+            } => Value::Composite(vec![
+                Value::Vector(vec![
+                    Value::zero_scalar(scalar)?;
+                    rows as usize
+                ]);
+                columns as usize
+            ]),
+            TypeInner::Array {
+                base,
+                size: ArraySize::Constant(size),
+                ..
+            } => Value::Composite(vec![Value::zero(module, base)?; size.get() as usize]),
+            TypeInner::Struct { ref members, .. } => Value::Composite(
+                members
+                    .iter()
+                    .map(|member| Value::zero(module, member.ty))
+                    .collect::<eyre::Result<_>>()?,
+            ),
+            ref inner => bail!("type has no zero value: {inner:?}"),
+        })
+    }
+
+    /// Constructs a value of the given type from its components. Vector components of a vector
+    /// are flattened, as in `vec4(v.xy, 1.0, 2.0)`.
+    pub fn compose(module: &Module, ty: Handle<Type>, components: Vec<Value>) -> Value {
+        match module.types[ty].inner {
+            TypeInner::Vector { .. } => Value::Vector(
+                components
+                    .into_iter()
+                    .flat_map(|component| match component {
+                        Value::Vector(components) => components,
+                        component => vec![component],
+                    })
+                    .collect(),
+            ),
+            _ => Value::Composite(components),
+        }
+    }
+
+    pub fn as_bool(&self) -> eyre::Result<bool> {
+        match *self {
+            Value::Bool(v) => Ok(v),
+            _ => Err(self.mismatch("bool")),
+        }
+    }
+
+    pub fn as_u32(&self) -> eyre::Result<u32> {
+        match *self {
+            Value::U32(v) => Ok(v),
+            _ => Err(self.mismatch("u32")),
+        }
+    }
+
+    pub fn as_i32(&self) -> eyre::Result<i32> {
+        match *self {
+            Value::I32(v) => Ok(v),
+            _ => Err(self.mismatch("i32")),
+        }
+    }
+
+    /// Returns the value of an `i32` or `u32` that is used as an index. Negative indices wrap
+    /// around to large unsigned values, so that they are clamped like any other out of bounds
+    /// index.
+    pub fn as_index(&self) -> eyre::Result<u32> {
+        match *self {
+            Value::I32(v) => Ok(v as u32),
+            Value::U32(v) => Ok(v),
+            _ => Err(self.mismatch("index")),
+        }
+    }
+
+    /// Returns the value of an `f32` or `f16`.
+    pub fn as_float(&self) -> eyre::Result<f32> {
+        match *self {
+            Value::F32(v) => Ok(v),
+            Value::F16(v) => Ok(v.to_f32()),
+            _ => Err(self.mismatch("float")),
+        }
+    }
+
+    pub fn as_pointer(&self) -> eyre::Result<&Pointer> {
+        match self {
+            Value::Pointer(pointer) => Ok(pointer),
+            _ => Err(self.mismatch("pointer")),
+        }
+    }
+
+    pub fn as_handle(&self) -> eyre::Result<Handle<GlobalVariable>> {
+        match *self {
+            Value::Handle(handle) => Ok(handle),
+            _ => Err(self.mismatch("texture or sampler")),
+        }
+    }
+
+    /// Returns the components of a vector or composite.
+    pub fn components(&self) -> eyre::Result<&[Value]> {
+        match self {
+            Value::Vector(components) | Value::Composite(components) => Ok(components),
+            _ => Err(self.mismatch("vector or composite")),
+        }
+    }
+
+    pub fn into_components(self) -> eyre::Result<Vec<Value>> {
+        match self {
+            Value::Vector(components) | Value::Composite(components) => Ok(components),
+            _ => Err(self.mismatch("vector or composite")),
+        }
+    }
+
+    /// Returns a component of a vector or composite. Out of bounds indices are clamped to the last
+    /// component, which matches the behaviour of naga's `Restrict` bounds check policy.
+    pub fn component(&self, index: u32) -> eyre::Result<&Value> {
+        let components = self.components()?;
+        let index = (index as usize).min(components.len().saturating_sub(1));
+        components
+            .get(index)
+            .ok_or_else(|| eyre!("indexed an empty composite"))
+    }
+
+    pub fn component_mut(&mut self, index: u32) -> eyre::Result<&mut Value> {
+        match self {
+            Value::Vector(components) | Value::Composite(components) => {
+                let index = (index as usize).min(components.len().saturating_sub(1));
+                components
+                    .get_mut(index)
+                    .ok_or_else(|| eyre!("indexed an empty composite"))
+            }
+            _ => Err(self.mismatch("vector or composite")),
+        }
+    }
+
+    fn mismatch(&self, expected: &str) -> eyre::Report {
+        eyre!("expected {expected}, found {self:?}")
+    }
+}
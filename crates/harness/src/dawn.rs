@@ -1,12 +1,31 @@
 use color_eyre::eyre::eyre;
 use dawn::webgpu::{
     WGPUBackendType_WGPUBackendType_D3D12, WGPUBackendType_WGPUBackendType_Metal,
-    WGPUBackendType_WGPUBackendType_Vulkan, WGPUBool,
+    WGPUBackendType_WGPUBackendType_Vulkan, WGPUBool, WGPUCompareFunction_WGPUCompareFunction_Less,
+    WGPUCompareFunction_WGPUCompareFunction_Undefined, WGPUFeatureName,
+    WGPUFeatureName_WGPUFeatureName_ShaderF16, WGPUFeatureName_WGPUFeatureName_Subgroups,
+    WGPUTextureDimension_WGPUTextureDimension_1D, WGPUTextureDimension_WGPUTextureDimension_2D,
+    WGPUTextureDimension_WGPUTextureDimension_3D, WGPUTextureFormat_WGPUTextureFormat_Depth16Unorm,
+    WGPUTextureFormat_WGPUTextureFormat_RGBA8Sint, WGPUTextureFormat_WGPUTextureFormat_RGBA8Uint,
+    WGPUTextureFormat_WGPUTextureFormat_RGBA8Unorm,
+    WGPUTextureViewDimension_WGPUTextureViewDimension_1D,
+    WGPUTextureViewDimension_WGPUTextureViewDimension_2D,
+    WGPUTextureViewDimension_WGPUTextureViewDimension_2DArray,
+    WGPUTextureViewDimension_WGPUTextureViewDimension_3D,
+    WGPUTextureViewDimension_WGPUTextureViewDimension_Cube,
+    WGPUTextureViewDimension_WGPUTextureViewDimension_CubeArray,
+    WGPUWGSLLanguageFeatureName_WGPUWGSLLanguageFeatureName_Packed4x8IntegerDotProduct,
 };
 use dawn::*;
-use reflection::{PipelineDescription, ResourceKind};
+use reflection::{
+    Feature, PipelineDescription, ResourceKind, TextureDescriptor, TextureDimension,
+    TextureSampleType,
+};
+
+use std::time::{Duration, Instant};
 
-use crate::ConfigId;
+use crate::pipeline_cache::PipelineCache;
+use crate::{BackendError, BackendOptions, ConfigId, ExecutionOutput, Timings, Variant};
 
 enum BufferSet {
     Storage {
@@ -15,172 +34,426 @@ enum BufferSet {
         storage: DeviceBuffer,
         read: DeviceBuffer,
     },
-    Uniform {
+    /// A uniform or read-only storage buffer, which isn't read back.
+    Input {
         binding: u32,
         size: usize,
         buffer: DeviceBuffer,
     },
+    Texture {
+        binding: u32,
+        view: TextureView,
+    },
+    Sampler {
+        binding: u32,
+        sampler: Sampler,
+    },
 }
 
 pub fn get_adapters() -> Vec<types::Adapter> {
-    Instance::new()
+    let instance = Instance::new();
+
+    // Language extensions are supported by the instance as a whole, rather than by each adapter
+    let mut language_features = vec![];
+
+    if instance.has_wgsl_language_feature(
+        WGPUWGSLLanguageFeatureName_WGPUWGSLLanguageFeatureName_Packed4x8IntegerDotProduct,
+    ) {
+        language_features.push(Feature::Packed4x8IntegerDotProduct);
+    }
+
+    instance
         .enumerate_adapters()
         .into_iter()
         .filter_map(|it| {
             #[allow(non_upper_case_globals)]
             Some(types::Adapter {
                 name: it.name,
+                vendor_id: it.vendor_id,
                 device_id: it.device_id,
+                driver: it.driver,
                 backend: match it.backend {
                     WGPUBackendType_WGPUBackendType_D3D12 => crate::BackendType::Dx12,
                     WGPUBackendType_WGPUBackendType_Metal => crate::BackendType::Metal,
                     WGPUBackendType_WGPUBackendType_Vulkan => crate::BackendType::Vulkan,
                     _ => return None,
                 },
+                features: it
+                    .features
+                    .into_iter()
+                    .filter_map(|feature| match feature {
+                        WGPUFeatureName_WGPUFeatureName_Subgroups => Some(Feature::Subgroups),
+                        WGPUFeatureName_WGPUFeatureName_ShaderF16 => Some(Feature::ShaderF16),
+                        _ => None,
+                    })
+                    .chain(language_features.iter().copied())
+                    .collect(),
+                // Devices are always created with the default limits
+                limits: reflection::Limits::DEFAULT,
+                software: it.software,
             })
         })
         .collect()
 }
 
-pub async fn run(
-    shader: &str,
-    meta: &PipelineDescription,
-    config: &ConfigId,
-) -> color_eyre::Result<Vec<Vec<u8>>> {
-    let backend = match config.backend {
-        crate::BackendType::Dx12 => WGPUBackendType_WGPUBackendType_D3D12,
-        crate::BackendType::Metal => WGPUBackendType_WGPUBackendType_Metal,
-        crate::BackendType::Vulkan => WGPUBackendType_WGPUBackendType_Vulkan,
+/// Returns the device feature which must be requested for the given feature, if any.
+fn feature_name(feature: &Feature) -> Option<WGPUFeatureName> {
+    match feature {
+        Feature::Subgroups => Some(WGPUFeatureName_WGPUFeatureName_Subgroups),
+        Feature::ShaderF16 => Some(WGPUFeatureName_WGPUFeatureName_ShaderF16),
+        // Dawn doesn't support push constants, so this is never requested
+        Feature::PushConstants => None,
+        // Language extensions don't need to be requested from the device
+        Feature::Packed4x8IntegerDotProduct => None,
+    }
+}
+
+fn create_texture_view(
+    device: &Device,
+    queue: &DeviceQueue,
+    desc: &TextureDescriptor,
+    init: Option<&[u8]>,
+) -> TextureView {
+    let dimension = match desc.dimension {
+        TextureDimension::D1 => WGPUTextureDimension_WGPUTextureDimension_1D,
+        TextureDimension::D3 => WGPUTextureDimension_WGPUTextureDimension_3D,
+        _ => WGPUTextureDimension_WGPUTextureDimension_2D,
     };
 
-    let instance = Instance::new();
+    let format = match desc.sample_type {
+        TextureSampleType::Float => WGPUTextureFormat_WGPUTextureFormat_RGBA8Unorm,
+        TextureSampleType::Sint => WGPUTextureFormat_WGPUTextureFormat_RGBA8Sint,
+        TextureSampleType::Uint => WGPUTextureFormat_WGPUTextureFormat_RGBA8Uint,
+        TextureSampleType::Depth => WGPUTextureFormat_WGPUTextureFormat_Depth16Unorm,
+    };
+
+    let view_dimension = match desc.dimension {
+        TextureDimension::D1 => WGPUTextureViewDimension_WGPUTextureViewDimension_1D,
+        TextureDimension::D2 => WGPUTextureViewDimension_WGPUTextureViewDimension_2D,
+        TextureDimension::D2Array => WGPUTextureViewDimension_WGPUTextureViewDimension_2DArray,
+        TextureDimension::D3 => WGPUTextureViewDimension_WGPUTextureViewDimension_3D,
+        TextureDimension::Cube => WGPUTextureViewDimension_WGPUTextureViewDimension_Cube,
+        TextureDimension::CubeArray => WGPUTextureViewDimension_WGPUTextureViewDimension_CubeArray,
+    };
+
+    let texture = device.create_texture(dimension, desc.size, desc.mip_level_count, format);
+
+    if let Some(init) = init {
+        queue.write_texture(&texture, init, desc.texel_size(), desc.size);
+    }
+
+    texture.create_view(view_dimension)
+}
+
+/// Returns an error if the device was lost or has reported an error. Device loss is reported in
+/// preference to other errors, since it usually causes them.
+fn check_device(device: &Device) -> Result<(), BackendError> {
+    if let Some(message) = device.lost_message() {
+        return Err(BackendError::DeviceLost(message));
+    }
 
-    let device = instance
-        .create_device(backend, config.device_id)
-        .ok_or_else(|| eyre!("no adapter found matching id: {config}"))?;
+    match device.first_error() {
+        Some(DeviceError { kind, message }) => Err(match kind {
+            ErrorKind::Compilation => BackendError::Compilation(message),
+            ErrorKind::Validation => BackendError::Validation(message),
+            ErrorKind::Runtime => BackendError::Runtime(message),
+        }),
+        None => Ok(()),
+    }
+}
 
-    let queue = device.create_queue();
-    let shader_module = device.create_shader_module(shader);
-    let pipeline = device.create_compute_pipeline(&shader_module, "main");
+/// A device which can execute any number of shaders that only require its features.
+pub struct Context {
+    // The pipelines, queue and device must be released before the instance that created them
+    pipelines: PipelineCache<ComputePipeline>,
+    queue: DeviceQueue,
+    device: Device,
+    instance: Instance,
+}
 
-    // important: this will catch compilation errors
-    instance.process_events();
+impl Context {
+    pub fn new(
+        features: &[Feature],
+        options: &BackendOptions,
+        config: &ConfigId,
+    ) -> color_eyre::Result<Context> {
+        let backend = match config.backend {
+            crate::BackendType::Dx12 => WGPUBackendType_WGPUBackendType_D3D12,
+            crate::BackendType::Metal => WGPUBackendType_WGPUBackendType_Metal,
+            crate::BackendType::Vulkan => WGPUBackendType_WGPUBackendType_Vulkan,
+            crate::BackendType::Interpreter
+            | crate::BackendType::Chrome
+            | crate::BackendType::Firefox => return Err(eyre!("unsupported backend: {config}")),
+        };
 
-    let mut buffer_sets = vec![];
+        let instance = Instance::new();
 
-    let mapped: WGPUBool = 0;
+        let features = features.iter().filter_map(feature_name).collect::<Vec<_>>();
 
-    for resource in &meta.resources {
-        let size = resource.size as usize;
-        match resource.kind {
-            ResourceKind::StorageBuffer => {
-                let storage = device.create_buffer(
-                    mapped,
-                    size,
-                    DeviceBufferUsage::STORAGE | DeviceBufferUsage::COPY_SRC,
-                );
+        let mut enabled_toggles = options
+            .enable_toggles
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
 
-                let read = device.create_buffer(
-                    mapped,
-                    size,
-                    DeviceBufferUsage::COPY_DST | DeviceBufferUsage::MAP_READ,
-                );
+        let mut disabled_toggles = options
+            .disable_toggles
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
 
-                buffer_sets.push(BufferSet::Storage {
-                    binding: resource.binding,
-                    size,
-                    storage,
-                    read,
-                });
+        // DXC is used unless it is explicitly disabled, or the config is the FXC variant
+        if config.variant == Some(Variant::Fxc) {
+            enabled_toggles.retain(|it| *it != "use_dxc");
+            if !disabled_toggles.contains(&"use_dxc") {
+                disabled_toggles.push("use_dxc");
+            }
+        } else if !disabled_toggles.contains(&"use_dxc") && !enabled_toggles.contains(&"use_dxc") {
+            enabled_toggles.push("use_dxc");
+        }
+
+        let device = instance
+            .create_device(
+                backend,
+                config.device_id,
+                &features,
+                &enabled_toggles,
+                &disabled_toggles,
+            )
+            .ok_or_else(|| eyre!("no adapter found matching id: {config}"))?;
+
+        let queue = device.create_queue();
+
+        Ok(Context {
+            pipelines: PipelineCache::default(),
+            queue,
+            device,
+            instance,
+        })
+    }
+
+    pub async fn run(
+        &self,
+        shader: &str,
+        meta: &PipelineDescription,
+    ) -> color_eyre::Result<ExecutionOutput> {
+        let Context {
+            pipelines,
+            queue,
+            device,
+            instance,
+        } = self;
+
+        let cached = pipelines.get(shader);
+        let cache_hit = cached.is_some();
+
+        let (pipeline, shader_module_time, pipeline_time) = match cached {
+            Some(pipeline) => (pipeline, Duration::ZERO, Duration::ZERO),
+            None => {
+                let start = Instant::now();
+                let shader_module = device.create_shader_module(shader);
+                let shader_module_time = start.elapsed();
+
+                let start = Instant::now();
+                let pipeline = device.create_compute_pipeline(&shader_module, "main");
+                let pipeline_time = start.elapsed();
+
+                // important: this will catch compilation errors
+                instance.process_events();
+                check_device(device)?;
+
+                let pipeline = pipelines.insert(shader, pipeline);
+                (pipeline, shader_module_time, pipeline_time)
             }
-            ResourceKind::UniformBuffer => {
-                let mapped: WGPUBool = 1;
+        };
+
+        let mut buffer_sets = vec![];
+
+        let mapped: WGPUBool = 0;
 
-                let mut buffer = device.create_buffer(mapped, size, DeviceBufferUsage::UNIFORM);
+        for resource in &meta.resources {
+            let size = resource.size as usize;
+            match resource.kind {
+                ResourceKind::StorageBuffer => {
+                    let mut storage = device.create_buffer(
+                        resource.init.is_some() as WGPUBool,
+                        size,
+                        DeviceBufferUsage::STORAGE | DeviceBufferUsage::COPY_SRC,
+                    );
 
-                if let Some(init) = resource.init.as_deref() {
-                    buffer.get_mapped_range(size).copy_from_slice(init);
+                    if let Some(init) = resource.init.as_deref() {
+                        storage.get_mapped_range(size).copy_from_slice(init);
+                        storage.unmap();
+                    }
+
+                    let read = device.create_buffer(
+                        mapped,
+                        size,
+                        DeviceBufferUsage::COPY_DST | DeviceBufferUsage::MAP_READ,
+                    );
+
+                    buffer_sets.push(BufferSet::Storage {
+                        binding: resource.binding,
+                        size,
+                        storage,
+                        read,
+                    });
                 }
+                ResourceKind::UniformBuffer | ResourceKind::ReadOnlyStorageBuffer => {
+                    let mapped: WGPUBool = 1;
+
+                    let usage = if resource.kind == ResourceKind::UniformBuffer {
+                        DeviceBufferUsage::UNIFORM
+                    } else {
+                        DeviceBufferUsage::STORAGE
+                    };
+
+                    let mut buffer = device.create_buffer(mapped, size, usage);
+
+                    if let Some(init) = resource.init.as_deref() {
+                        buffer.get_mapped_range(size).copy_from_slice(init);
+                    }
 
-                buffer.unmap();
+                    buffer.unmap();
 
-                buffer_sets.push(BufferSet::Uniform {
+                    buffer_sets.push(BufferSet::Input {
+                        binding: resource.binding,
+                        size,
+                        buffer,
+                    })
+                }
+                ResourceKind::Texture(ref desc) => buffer_sets.push(BufferSet::Texture {
+                    binding: resource.binding,
+                    view: create_texture_view(device, queue, desc, resource.init.as_deref()),
+                }),
+                ResourceKind::Sampler { comparison } => buffer_sets.push(BufferSet::Sampler {
                     binding: resource.binding,
-                    size,
-                    buffer,
-                })
+                    sampler: device.create_sampler(if comparison {
+                        WGPUCompareFunction_WGPUCompareFunction_Less
+                    } else {
+                        WGPUCompareFunction_WGPUCompareFunction_Undefined
+                    }),
+                }),
             }
         }
-    }
 
-    let bind_group_entries = buffer_sets
-        .iter()
-        .map(|buffers| match buffers {
-            BufferSet::Storage {
-                binding,
-                size,
+        // Each resource has a buffer set, so they can be matched up with their groups
+        let bind_groups = meta
+            .bind_groups()
+            .into_iter()
+            .map(|group| {
+                let entries = meta
+                    .resources
+                    .iter()
+                    .zip(&buffer_sets)
+                    .filter(|(resource, _)| resource.group == group)
+                    .map(|(_, buffers)| match buffers {
+                        BufferSet::Storage {
+                            binding,
+                            size,
+                            storage,
+                            ..
+                        } => BindGroupEntry {
+                            binding: *binding,
+                            resource: BindingResource::Buffer {
+                                buffer: storage,
+                                size: *size,
+                            },
+                        },
+                        BufferSet::Input {
+                            binding,
+                            size,
+                            buffer,
+                        } => BindGroupEntry {
+                            binding: *binding,
+                            resource: BindingResource::Buffer {
+                                buffer,
+                                size: *size,
+                            },
+                        },
+                        BufferSet::Texture { binding, view, .. } => BindGroupEntry {
+                            binding: *binding,
+                            resource: BindingResource::TextureView(view),
+                        },
+                        BufferSet::Sampler { binding, sampler } => BindGroupEntry {
+                            binding: *binding,
+                            resource: BindingResource::Sampler(sampler),
+                        },
+                    })
+                    .collect::<Vec<_>>();
+
+                let layout = pipeline.get_bind_group_layout(group);
+                (group, device.create_bind_group(&layout, &entries))
+            })
+            .collect::<Vec<_>>();
+
+        let encoder = device.create_command_encoder();
+
+        {
+            let compute_pass = encoder.begin_compute_pass();
+            compute_pass.set_pipeline(&pipeline);
+            for (group, bind_group) in &bind_groups {
+                compute_pass.set_bind_group(*group, bind_group);
+            }
+            let [x, y, z] = meta.dispatch;
+            compute_pass.dispatch(x, y, z);
+        }
+
+        for buffers in &buffer_sets {
+            if let BufferSet::Storage {
                 storage,
-                ..
-            } => BindGroupEntry {
-                binding: *binding,
-                buffer: storage,
-                size: *size,
-            },
-            BufferSet::Uniform {
-                binding,
+                read,
                 size,
-                buffer,
-            } => BindGroupEntry {
-                binding: *binding,
-                buffer,
-                size: *size,
-            },
-        })
-        .collect::<Vec<_>>();
+                ..
+            } = buffers
+            {
+                encoder.copy_buffer_to_buffer(storage, read, *size);
+            }
+        }
 
-    let bind_group =
-        device.create_bind_group(&pipeline.get_bind_group_layout(0), &bind_group_entries);
+        let commands = encoder.finish();
 
-    let encoder = device.create_command_encoder();
+        let start = Instant::now();
+        queue.submit(&commands);
 
-    {
-        let compute_pass = encoder.begin_compute_pass();
-        compute_pass.set_pipeline(&pipeline);
-        compute_pass.set_bind_group(0, &bind_group);
-        compute_pass.dispatch(1, 1, 1);
-    }
+        let mut results = vec![];
+        for buffers in &buffer_sets {
+            if let BufferSet::Storage { read, size, .. } = buffers {
+                let mut rx = read.map_async(DeviceBufferMapMode::READ, *size);
 
-    for buffers in &buffer_sets {
-        if let BufferSet::Storage {
-            storage,
-            read,
-            size,
-            ..
-        } = buffers
-        {
-            encoder.copy_buffer_to_buffer(storage, read, *size);
-        }
-    }
+                let map_result = loop {
+                    if let Some(result) = rx.try_recv().unwrap() {
+                        break result;
+                    }
 
-    let commands = encoder.finish();
+                    instance.process_events();
+                    std::thread::sleep(Duration::from_millis(16));
+                };
 
-    queue.submit(&commands);
+                // Mapping fails if the device was lost or an earlier operation failed, which is
+                // reported in preference to the mapping error
+                check_device(device)?;
 
-    let mut results = vec![];
-    for buffers in &buffer_sets {
-        if let BufferSet::Storage { read, size, .. } = buffers {
-            let mut rx = read.map_async(DeviceBufferMapMode::READ, *size);
+                map_result.map_err(|e| eyre!("buffer mapping failed: {e}"))?;
 
-            while rx.try_recv().unwrap().is_none() {
-                instance.process_events();
-                std::thread::sleep(std::time::Duration::from_millis(16));
+                let bytes = read.get_const_mapped_range(*size);
+
+                results.push(bytes.to_vec());
             }
+        }
 
-            let bytes = read.get_const_mapped_range(*size);
+        instance.process_events();
+        check_device(device)?;
 
-            results.push(bytes.to_vec());
-        }
+        // Buffers are polled every 16ms, which limits the precision of the dispatch time
+        Ok(ExecutionOutput {
+            buffers: results,
+            timings: Timings {
+                shader_module: shader_module_time,
+                pipeline: pipeline_time,
+                dispatch: start.elapsed(),
+            },
+            cache_hit: Some(cache_hit),
+        })
     }
-
-    Ok(results)
 }
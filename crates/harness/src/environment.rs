@@ -0,0 +1,44 @@
+//! Describes the machine that executes shaders and the build of the harness, which is reported at
+//! the start of every run.
+
+use std::fs;
+
+use types::{Config, ConfigId, Environment};
+
+/// Returns the environment of a run, including the adapters of the base configs of `configs`
+/// that are among the `available` configs.
+pub fn capture(configs: &[ConfigId], available: &[Config]) -> Environment {
+    Environment {
+        os: describe_os(),
+        dawn_commit: option_env!("DAWN_COMMIT").map(str::to_owned),
+        wgpu_commit: option_env!("WGPU_COMMIT").map(str::to_owned),
+        configs: available
+            .iter()
+            .filter(|it| configs.iter().any(|config| config.base() == it.id))
+            .cloned()
+            .collect(),
+    }
+}
+
+fn describe_os() -> String {
+    let os = format!("{} {}", std::env::consts::OS, std::env::consts::ARCH);
+
+    // Mesa and the proprietary drivers are often tied to the distribution and kernel, so they are
+    // worth knowing on Linux
+    match linux_release() {
+        Some(release) => format!("{os} ({release})"),
+        None => os,
+    }
+}
+
+fn linux_release() -> Option<String> {
+    let os_release = fs::read_to_string("/etc/os-release").ok()?;
+    let name = os_release
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))?
+        .trim_matches('"');
+
+    let kernel = fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+
+    Some(format!("{name}, kernel {}", kernel.trim()))
+}
@@ -1,44 +1,83 @@
+mod browser;
+mod cancel;
+mod cpu;
 mod dawn;
+mod environment;
+mod pipeline_cache;
+mod queue;
 mod server;
+mod transport;
 mod wgpu;
+mod worker;
 
 pub mod cli;
 
+pub use cancel::Cancellation;
+pub use queue::DeviceQueues;
+pub use worker::WorkerPool;
+
+use std::fmt::{self, Display};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::Mutex;
-use std::time::Duration;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use frontend::{ExecutionError, ExecutionEvent};
 use futures::executor::block_on;
 use process_control::{ChildExt, Control};
-use reflection::PipelineDescription;
-use types::{BackendType, Config, ConfigId, Implementation};
+use reflection::{Feature, Limits, PipelineDescription};
+use types::{
+    AdapterFilter, BackendOptions, BackendType, Config, ConfigId, Implementation, RetryPolicy,
+    Timings, Unsupported, Variant,
+};
 
 pub trait HarnessHost {
     fn exec_command() -> Command;
 }
 
 pub fn query_configs() -> Vec<Config> {
-    let mut configurations = vec![];
-
-    configurations.extend(
-        wgpu::get_adapters()
-            .into_iter()
-            .map(|adapter| Config::new(Implementation::Wgpu, adapter)),
-    );
+    query_configs_of(&[
+        Implementation::Wgpu,
+        Implementation::Dawn,
+        Implementation::Cpu,
+        Implementation::Browser,
+    ])
+}
 
-    configurations.extend(
-        dawn::get_adapters()
-            .into_iter()
-            .map(|adapter| Config::new(Implementation::Dawn, adapter)),
-    );
+/// Queries the adapters of the given implementations only, which avoids e.g. starting a browser
+/// when no browser configs are used.
+fn query_configs_of(implementations: &[Implementation]) -> Vec<Config> {
+    implementations
+        .iter()
+        .flat_map(|&implementation| {
+            let adapters = match implementation {
+                Implementation::Wgpu => wgpu::get_adapters(),
+                Implementation::Dawn => dawn::get_adapters(),
+                Implementation::Cpu => cpu::get_adapters(),
+                Implementation::Browser => browser::get_adapters(),
+            };
+
+            adapters
+                .into_iter()
+                .map(move |adapter| Config::new(implementation, adapter))
+        })
+        .collect()
+}
 
-    configurations
+/// Selects a default config for each implementation and backend, considering only adapters that
+/// support all of the features and limits required by the pipeline. Hardware adapters are preferred
+/// over software adapters, since those are more likely to have bugs.
+pub fn default_configs(pipeline_desc: &PipelineDescription) -> Vec<ConfigId> {
+    default_configs_from(&query_configs(), pipeline_desc)
 }
 
-pub fn default_configs() -> Vec<ConfigId> {
+fn default_configs_from(
+    available: &[Config],
+    pipeline_desc: &PipelineDescription,
+) -> Vec<ConfigId> {
     let mut configs = vec![];
-    let available = query_configs();
 
     let targets = [
         (Implementation::Dawn, BackendType::Dx12),
@@ -47,12 +86,19 @@ pub fn default_configs() -> Vec<ConfigId> {
         (Implementation::Wgpu, BackendType::Dx12),
         (Implementation::Wgpu, BackendType::Metal),
         (Implementation::Wgpu, BackendType::Vulkan),
+        (Implementation::Browser, BackendType::Chrome),
+        (Implementation::Browser, BackendType::Firefox),
     ];
 
     for target in targets {
-        if let Some(config) = available
-            .iter()
-            .find(|it| target == (it.id.implementation, it.id.backend))
+        let candidates = available.iter().filter(|it| {
+            target == (it.id.implementation, it.id.backend) && it.supports(pipeline_desc)
+        });
+
+        if let Some(config) = candidates
+            .clone()
+            .find(|it| !it.software)
+            .or_else(|| candidates.clone().next())
         {
             configs.push(config.id.clone());
         }
@@ -61,34 +107,396 @@ pub fn default_configs() -> Vec<ConfigId> {
     configs
 }
 
-#[derive(bincode::Encode)]
+/// Selects a software config to use as the oracle, whose output is treated as the reference when
+/// checking for mismatches. One of `configs` is used if possible, otherwise the CPU interpreter is
+/// preferred over the other software configs since it doesn't depend on any driver.
+pub fn oracle_config(
+    available: &[Config],
+    configs: &[ConfigId],
+    pipeline_desc: &PipelineDescription,
+) -> Option<ConfigId> {
+    let software = available
+        .iter()
+        .filter(|it| it.software && it.supports(pipeline_desc));
+
+    software
+        .clone()
+        .find(|it| configs.contains(&it.id))
+        .or_else(|| {
+            software
+                .clone()
+                .find(|it| it.id.implementation == Implementation::Cpu)
+        })
+        .or_else(|| software.clone().next())
+        .map(|it| it.id.clone())
+}
+
+/// Checks that the adapter of a config is available and supports the features and limits required
+/// by the pipeline.
+fn check_support(
+    available: &[Config],
+    config: &ConfigId,
+    pipeline_desc: &PipelineDescription,
+) -> Result<(), Unsupported> {
+    available
+        .iter()
+        .find(|it| it.id == config.base())
+        .ok_or(Unsupported::AdapterNotFound)?
+        .check_support(pipeline_desc)
+}
+
+/// Exit code of an execution process whose device was lost.
+const DEVICE_LOST_EXIT_CODE: i32 = 3;
+
+/// Exit code of an execution process which failed to create the shader module or pipeline.
+const COMPILATION_FAILURE_EXIT_CODE: i32 = 4;
+
+/// Exit code of an execution process in which any other operation failed validation.
+const VALIDATION_FAILURE_EXIT_CODE: i32 = 5;
+
+/// Exit code of an execution process in which the device reported an error while running.
+const RUNTIME_FAILURE_EXIT_CODE: i32 = 6;
+
+/// Exit code of an execution process which failed to create its device, before it ran the shader.
+const STARTUP_FAILURE_EXIT_CODE: i32 = 7;
+
+/// Error returned by a backend when the device reports an error while executing a shader.
+#[derive(Debug)]
+pub enum BackendError {
+    /// The shader module or compute pipeline couldn't be created, which usually indicates a bug in
+    /// the shader compiler.
+    Compilation(String),
+    /// Another operation was rejected by validation, e.g. creating the bind group.
+    Validation(String),
+    /// The device reported an error while running, e.g. because it ran out of memory.
+    Runtime(String),
+    /// The device was lost, e.g. because the driver crashed or the GPU was reset.
+    DeviceLost(String),
+    /// The adapter or device couldn't be created, which doesn't depend on the shader.
+    Startup(String),
+}
+
+impl BackendError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            BackendError::Compilation(_) => COMPILATION_FAILURE_EXIT_CODE,
+            BackendError::Validation(_) => VALIDATION_FAILURE_EXIT_CODE,
+            BackendError::Runtime(_) => RUNTIME_FAILURE_EXIT_CODE,
+            BackendError::DeviceLost(_) => DEVICE_LOST_EXIT_CODE,
+            BackendError::Startup(_) => STARTUP_FAILURE_EXIT_CODE,
+        }
+    }
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::Compilation(message) => write!(f, "compilation failed: {message}"),
+            BackendError::Validation(message) => write!(f, "validation failed: {message}"),
+            BackendError::Runtime(message) => write!(f, "runtime error: {message}"),
+            BackendError::DeviceLost(message) => write!(f, "device lost: {message}"),
+            BackendError::Startup(message) => write!(f, "failed to create device: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+#[derive(Clone, Copy, bincode::Encode)]
 struct ExecutionArgs<'a> {
     pub shader: &'a str,
     pub pipeline_desc: &'a PipelineDescription,
+    pub options: &'a BackendOptions,
+    /// File that the execution process writes the output buffers to instead of stdout, which is
+    /// only used for large outputs.
+    pub output_path: Option<&'a Path>,
 }
 
 #[derive(bincode::Decode)]
 struct ExecutionInput {
     pub shader: String,
     pub pipeline_desc: PipelineDescription,
+    pub options: BackendOptions,
+    pub output_path: Option<PathBuf>,
 }
 
-#[derive(bincode::Decode, bincode::Encode)]
-struct ExecutionOutput {
+/// The contents of the storage buffers after executing a shader, along with how long it took.
+///
+/// The buffers are encoded in chunks rather than as a single length-prefixed slice (see
+/// [`transport`]), so that they can be streamed from an execution process without buffering.
+pub struct ExecutionOutput {
     pub buffers: Vec<Vec<u8>>,
+    /// The shader module and pipeline timings are zero if the pipeline was cached.
+    pub timings: Timings,
+    /// Whether the pipeline was reused from an earlier execution of the same shader on the same
+    /// device, or `None` if the backend doesn't cache pipelines.
+    pub cache_hit: Option<bool>,
+}
+
+/// Output of a worker for each execution. Workers keep running after an execution fails, so the
+/// exit code that the execution process would have exited with is sent instead.
+#[derive(bincode::Decode, bincode::Encode)]
+enum WorkerOutput {
+    Success(ExecutionOutput),
+    Failure { exit_code: i32, message: String },
+}
+
+/// The result of executing a shader in an execution process or worker.
+enum Outcome {
+    Success(ExecutionOutput),
+    /// The exit code of the execution, which is `None` if the process was killed by a signal,
+    /// along with its stderr.
+    Failure(Option<i32>, Vec<u8>),
+    Timeout,
+    /// The execution took longer than the watchdog of the worker pool allows.
+    Hang,
 }
 
+impl Outcome {
+    /// Whether the execution failed in a way that may not be caused by the shader, e.g. a crash of
+    /// the driver, so that it is worth retrying. Compilation and validation failures are always
+    /// reproducible, and timeouts and hangs would take too long to retry.
+    fn is_retryable(&self) -> bool {
+        match self {
+            Outcome::Failure(code, _) => !matches!(
+                code,
+                Some(COMPILATION_FAILURE_EXIT_CODE | VALIDATION_FAILURE_EXIT_CODE)
+            ),
+            Outcome::Success(_) | Outcome::Timeout | Outcome::Hang => false,
+        }
+    }
+
+    fn is_startup_failure(&self) -> bool {
+        matches!(self, Outcome::Failure(Some(STARTUP_FAILURE_EXIT_CODE), _))
+    }
+
+    fn into_event(self, config: ConfigId) -> ExecutionEvent {
+        match self {
+            Outcome::Success(output) => {
+                ExecutionEvent::Success(config, output.buffers, output.timings)
+            }
+            Outcome::Failure(code, stderr) => match code {
+                Some(DEVICE_LOST_EXIT_CODE) => ExecutionEvent::DeviceLost(config, stderr),
+                Some(COMPILATION_FAILURE_EXIT_CODE) => {
+                    ExecutionEvent::CompilationFailure(config, stderr)
+                }
+                Some(VALIDATION_FAILURE_EXIT_CODE) => {
+                    ExecutionEvent::ValidationFailure(config, stderr)
+                }
+                // Startup failures, panics and crashes of the execution process are also runtime
+                // failures
+                _ => ExecutionEvent::RuntimeFailure(config, stderr),
+            },
+            Outcome::Timeout => ExecutionEvent::Timeout(config),
+            Outcome::Hang => ExecutionEvent::Hang(config),
+        }
+    }
+}
+
+/// Executes the shader in a new execution process, which exits once it is done.
+fn execute_in_child<Host: HarnessHost>(
+    config: &ConfigId,
+    args: ExecutionArgs,
+    timeout: Option<Duration>,
+    cancel: &Cancellation,
+) -> Result<Outcome, ExecutionError> {
+    let mut child = Host::exec_command()
+        .arg(config.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+
+    bincode::encode_into_std_write(args, &mut stdin, bincode::config::standard())?;
+
+    // The outputs are read on other threads while the process is waited for, so that it can't
+    // block on a full pipe. The output buffers are decoded as they arrive, rather than after
+    // reading all of stdout, so that they are only held in memory once.
+    let output = transport::decode_in_background(child.stdout.take().unwrap());
+    let stderr = read_to_end_in_background(child.stderr.take().unwrap());
+
+    // The process is waited for in short slices, so that it can be killed if the run is cancelled
+    let start = Instant::now();
+    let status = loop {
+        let remaining = timeout.map(|it| it.saturating_sub(start.elapsed()));
+        if remaining == Some(Duration::ZERO) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(Outcome::Timeout);
+        }
+
+        let slice = remaining.map_or(cancel::POLL_INTERVAL, |it| it.min(cancel::POLL_INTERVAL));
+        if let Some(status) = child.controlled().time_limit(slice).wait()? {
+            break status;
+        }
+
+        if cancel.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(ExecutionError::Cancelled);
+        }
+    };
+
+    let output = output.join().expect("stdout reader panicked");
+    let stderr = stderr.join().expect("stderr reader panicked")?;
+
+    if status.success() {
+        Ok(Outcome::Success(output?))
+    } else {
+        let code = status.code().and_then(|it| i32::try_from(it).ok());
+        Ok(Outcome::Failure(code, stderr))
+    }
+}
+
+fn read_to_end_in_background(
+    mut pipe: impl Read + Send + 'static,
+) -> JoinHandle<io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut bytes = vec![];
+        pipe.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn execute<Host: HarnessHost, E: FnMut(ExecutionEvent) -> Result<(), ExecutionError> + Send>(
     shader: &str,
     pipeline_desc: &PipelineDescription,
     configs: &[ConfigId],
+    oracle: bool,
+    variants: &[Variant],
+    filter: &AdapterFilter,
+    options: &BackendOptions,
     timeout: Option<Duration>,
+    retry: RetryPolicy,
+    parallelism: Option<usize>,
+    workers: Option<&WorkerPool>,
+    queues: Option<&DeviceQueues>,
+    cancel: Option<&Cancellation>,
+    on_event: E,
+) -> Result<(), ExecutionError> {
+    let never_cancelled = Cancellation::new();
+    let cancel = cancel.unwrap_or(&never_cancelled);
+
+    execute_with(
+        shader,
+        pipeline_desc,
+        configs,
+        oracle,
+        variants,
+        filter,
+        options,
+        retry,
+        parallelism,
+        queues,
+        cancel,
+        |config, args| {
+            transport::with_output_file(args, |args| match workers {
+                Some(workers) => workers.execute::<Host>(config, args, timeout, cancel),
+                None => execute_in_child::<Host>(config, args, timeout, cancel),
+            })
+        },
+        on_event,
+    )
+}
+
+/// Executes the shader on threads of the calling process, instead of in separate execution
+/// processes. This avoids the cost of starting a process and encoding the shader and outputs for
+/// each execution, for tools which embed the harness.
+///
+/// Nothing isolates the caller from the drivers, so a crash takes it down and a hang blocks it
+/// forever (there is no timeout). Panics are caught and reported as runtime failures.
+#[cfg(feature = "in-process")]
+#[allow(clippy::too_many_arguments)]
+pub fn execute_in_process<E: FnMut(ExecutionEvent) -> Result<(), ExecutionError> + Send>(
+    shader: &str,
+    pipeline_desc: &PipelineDescription,
+    configs: &[ConfigId],
+    oracle: bool,
+    variants: &[Variant],
+    filter: &AdapterFilter,
+    options: &BackendOptions,
+    retry: RetryPolicy,
+    parallelism: Option<usize>,
+    on_event: E,
+) -> Result<(), ExecutionError> {
+    execute_with(
+        shader,
+        pipeline_desc,
+        configs,
+        oracle,
+        variants,
+        filter,
+        options,
+        retry,
+        parallelism,
+        None,
+        &Cancellation::new(),
+        |config, args| {
+            let result = std::panic::catch_unwind(|| {
+                execute_config(args.shader, args.pipeline_desc, args.options, config)
+            });
+
+            Ok(match result {
+                Ok(Ok(output)) => Outcome::Success(output),
+                Ok(Err(e)) => match e.downcast_ref::<BackendError>() {
+                    Some(error) => {
+                        Outcome::Failure(Some(error.exit_code()), error.to_string().into())
+                    }
+                    None => Outcome::Failure(None, format!("{e:?}").into()),
+                },
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|it| it.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    Outcome::Failure(None, format!("panicked: {message}").into())
+                }
+            })
+        },
+        on_event,
+    )
+}
+
+/// Selects the configs and reports the result of executing the shader on each of them, which is
+/// done by `run`. If `queues` are given, each execution waits for its turn on the adapter, which
+/// may be in use by other requests. Once `cancel` is cancelled, no more configs are executed.
+#[allow(clippy::too_many_arguments)]
+fn execute_with<E: FnMut(ExecutionEvent) -> Result<(), ExecutionError> + Send>(
+    shader: &str,
+    pipeline_desc: &PipelineDescription,
+    configs: &[ConfigId],
+    oracle: bool,
+    variants: &[Variant],
+    filter: &AdapterFilter,
+    options: &BackendOptions,
+    retry: RetryPolicy,
     parallelism: Option<usize>,
+    queues: Option<&DeviceQueues>,
+    cancel: &Cancellation,
+    run: impl Fn(&ConfigId, ExecutionArgs) -> Result<Outcome, ExecutionError> + Sync,
     mut on_event: E,
 ) -> Result<(), ExecutionError> {
-    let default_configs;
-    let configs = if configs.is_empty() {
-        default_configs = crate::default_configs();
+    let needs_features = !pipeline_desc.features.is_empty() || pipeline_desc.limits != Limits::MIN;
+    let available = if configs.is_empty() || needs_features || oracle {
+        crate::query_configs()
+    } else {
+        vec![]
+    };
+
+    // Explicit configs are already pinned, so the filter only applies to the configs that are
+    // selected automatically
+    let selectable = available
+        .iter()
+        .filter(|it| filter.matches(it))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut configs = if configs.is_empty() {
+        let default_configs = crate::default_configs_from(&selectable, pipeline_desc);
 
         if default_configs.is_empty() {
             return Err(ExecutionError::NoDefaultConfigs);
@@ -96,17 +504,84 @@ pub fn execute<Host: HarnessHost, E: FnMut(ExecutionEvent) -> Result<(), Executi
 
         on_event(ExecutionEvent::UsingDefaultConfigs(default_configs.clone()))?;
 
-        default_configs.as_slice()
+        default_configs
+    } else if needs_features {
+        let mut supported = vec![];
+
+        // Skip any configs that don't advertise support for the features and limits required by
+        // the shader
+        for config in configs {
+            match check_support(&available, config, pipeline_desc) {
+                Ok(()) => supported.push(config.clone()),
+                Err(reason) => on_event(ExecutionEvent::Unsupported(config.clone(), reason))?,
+            }
+        }
+
+        supported
     } else {
-        configs
+        configs.to_vec()
     };
 
+    if oracle {
+        let oracle = crate::oracle_config(&selectable, &configs, pipeline_desc)
+            .ok_or(ExecutionError::NoOracleConfig)?;
+
+        if !configs.contains(&oracle) {
+            configs.push(oracle.clone());
+        }
+
+        on_event(ExecutionEvent::UsingOracle(oracle))?;
+    }
+
+    // Each config is also run with the variants that it supports, e.g. Dawn D3D12 configs with
+    // FXC as well as DXC
+    let variant_configs = variants
+        .iter()
+        .flat_map(|variant| {
+            configs
+                .iter()
+                .filter(|it| {
+                    it.variant.is_none() && variant.is_supported_by(it.implementation, it.backend)
+                })
+                .map(|it| ConfigId {
+                    variant: Some(*variant),
+                    ..it.clone()
+                })
+        })
+        .filter(|it| !configs.contains(it))
+        .collect::<Vec<_>>();
+
+    configs.extend(variant_configs);
+
+    // The adapters are only queried for the environment if they weren't needed to select the
+    // configs, and then only for the implementations that are used
+    let available = if available.is_empty() {
+        let mut implementations = vec![];
+        for config in &configs {
+            if !implementations.contains(&config.implementation) {
+                implementations.push(config.implementation);
+            }
+        }
+
+        query_configs_of(&implementations)
+    } else {
+        available
+    };
+
+    on_event(ExecutionEvent::Environment(environment::capture(
+        &configs, &available,
+    )))?;
+
+    // Configs on the same adapter are executed one after another, so that e.g. a device lost on
+    // one of them can't affect the others, while configs on different adapters run concurrently
+    let groups = group_by_affinity(&configs);
+
     let on_event = Mutex::new(on_event);
-    let configs_iter = Mutex::new(configs.iter());
+    let groups_iter = Mutex::new(groups.iter());
     let num_threads = if let Some(p) = parallelism {
-        p.min(configs.len())
+        p.min(groups.len())
     } else {
-        configs.len()
+        groups.len()
     };
 
     std::thread::scope(|s| {
@@ -114,64 +589,262 @@ pub fn execute<Host: HarnessHost, E: FnMut(ExecutionEvent) -> Result<(), Executi
 
         for _ in 0..num_threads {
             let on_event = &on_event;
-            let configs_iter = &configs_iter;
+            let groups_iter = &groups_iter;
+            let run = &run;
 
             handles.push(s.spawn(move || -> Result<(), ExecutionError> {
                 loop {
-                    let config = {
-                        let mut iter = configs_iter.lock().expect("iter mutex poisoned");
+                    let group = {
+                        let mut iter = groups_iter.lock().expect("iter mutex poisoned");
                         match iter.next() {
-                            Some(c) => c.clone(),
+                            Some(group) => group,
                             None => return Ok(()),
                         }
                     };
 
-                    {
-                        let mut lock = on_event.lock().expect("event mutex poisoned");
-                        lock(ExecutionEvent::Start(config.clone()))?;
-                    }
+                    for config in group {
+                        cancel.check()?;
 
-                    let mut child = Host::exec_command()
-                        .arg(config.to_string())
-                        .stdin(Stdio::piped())
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .spawn()?;
+                        let turn = match queues {
+                            Some(queues) => queues.wait_for_turn(config, cancel, |ahead| {
+                                let mut lock = on_event.lock().expect("event mutex poisoned");
+                                lock(ExecutionEvent::Queued(config.clone(), ahead))
+                            })?,
+                            None => None,
+                        };
 
-                    let mut stdin = child.stdin.take().unwrap();
+                        {
+                            let mut lock = on_event.lock().expect("event mutex poisoned");
+                            lock(ExecutionEvent::Start(config.clone()))?;
+                        }
 
-                    bincode::encode_into_std_write(
-                        ExecutionArgs {
+                        let args = ExecutionArgs {
                             shader,
                             pipeline_desc,
-                        },
-                        &mut stdin,
-                        bincode::config::standard(),
-                    )?;
-
-                    let mut child = child.controlled_with_output();
-                    if let Some(timeout) = timeout {
-                        child = child.time_limit(timeout).terminate_for_timeout();
+                            options,
+                            output_path: None,
+                        };
+
+                        let outcome = run_with_retries(config, args, retry, cancel, run)?;
+
+                        // The next execution on the adapter doesn't need to wait for the outcome
+                        // to be reported
+                        drop(turn);
+
+                        let mut lock = on_event.lock().expect("event mutex poisoned");
+                        lock(outcome.into_event(config.clone()))?;
                     }
+                }
+            }));
+        }
 
-                    let output = match child.wait()? {
-                        Some(output) => output,
-                        None => {
-                            let mut lock = on_event.lock().expect("event mutex poisoned");
-                            lock(ExecutionEvent::Timeout)?;
-                            continue;
-                        }
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Runs the execution again while it fails in a way that may be flaky, waiting before each retry.
+/// A success on any attempt is reported, since the failures weren't caused by the shader. If every
+/// attempt fails, the first failure that got past creating the device is reported, since later
+/// attempts may fail differently after e.g. a GPU reset.
+fn run_with_retries(
+    config: &ConfigId,
+    args: ExecutionArgs,
+    retry: RetryPolicy,
+    cancel: &Cancellation,
+    run: impl Fn(&ConfigId, ExecutionArgs) -> Result<Outcome, ExecutionError>,
+) -> Result<Outcome, ExecutionError> {
+    let mut failure: Option<Outcome> = None;
+    let mut backoff = retry.backoff;
+
+    for attempt in 0..=retry.retries {
+        if attempt > 0 {
+            log::info!(
+                "retrying {config} in {backoff:?} (attempt {attempt} of {})",
+                retry.retries
+            );
+            cancel.sleep(backoff)?;
+            backoff *= 2;
+        }
+
+        let outcome = run(config, args)?;
+
+        if !outcome.is_retryable() {
+            if failure.is_some() && matches!(outcome, Outcome::Success(_)) {
+                log::warn!(
+                    "{config} succeeded after failing {attempt} times, the failure is flaky"
+                );
+            }
+
+            return Ok(outcome);
+        }
+
+        if failure
+            .as_ref()
+            .is_none_or(|it| it.is_startup_failure() && !outcome.is_startup_failure())
+        {
+            failure = Some(outcome);
+        }
+    }
+
+    Ok(failure.expect("at least one attempt is made"))
+}
+
+/// The adapter that a config executes shaders on, which is shared with any other config that has
+/// the same affinity.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Affinity {
+    /// A physical adapter, identified by its PCI device ID. This is the same across implementations
+    /// and backends, e.g. for Dawn and wgpu on Vulkan.
+    Device(u32),
+    /// Browsers don't report which GPU they use, so they are only assumed to share one with each
+    /// other.
+    Browser,
+    /// The interpreter doesn't use an adapter, so it doesn't share one with any other config.
+    None,
+}
+
+impl Affinity {
+    fn of(config: &ConfigId) -> Affinity {
+        match config.implementation {
+            Implementation::Dawn | Implementation::Wgpu => Affinity::Device(config.device_id),
+            Implementation::Browser => Affinity::Browser,
+            Implementation::Cpu => Affinity::None,
+        }
+    }
+}
+
+/// Groups the configs that share an adapter, keeping them in the order that they were given.
+fn group_by_affinity(configs: &[ConfigId]) -> Vec<Vec<ConfigId>> {
+    let mut groups: Vec<Vec<ConfigId>> = vec![];
+
+    for config in configs {
+        let affinity = Affinity::of(config);
+        let group = match affinity {
+            Affinity::None => None,
+            _ => groups
+                .iter_mut()
+                .find(|group| Affinity::of(&group[0]) == affinity),
+        };
+
+        match group {
+            Some(group) => group.push(config.clone()),
+            None => groups.push(vec![config.clone()]),
+        }
+    }
+
+    groups
+}
+
+/// A shader to execute as part of a batch. Its inputs are the initial contents of the resources in
+/// its pipeline description.
+pub struct BatchShader {
+    pub shader: String,
+    pub pipeline_desc: PipelineDescription,
+    pub options: BackendOptions,
+}
+
+/// Executes each shader against each config, and returns the result of each execution, indexed by
+/// shader and then by config.
+///
+/// Each config executes the shaders one after another in a worker, which reuses its device for
+/// consecutive shaders with the same features and options. If no configs are given, the defaults
+/// for a shader without any requirements are used. Shaders that require features or limits which a
+/// config doesn't support are reported as unsupported by it.
+pub fn execute_batch<Host: HarnessHost>(
+    shaders: &[BatchShader],
+    configs: &[ConfigId],
+    timeout: Option<Duration>,
+    parallelism: Option<usize>,
+) -> Result<Vec<Vec<ExecutionEvent>>, ExecutionError> {
+    let has_requirements = |pipeline_desc: &PipelineDescription| {
+        !pipeline_desc.features.is_empty() || pipeline_desc.limits != Limits::MIN
+    };
+
+    let available =
+        if configs.is_empty() || shaders.iter().any(|it| has_requirements(&it.pipeline_desc)) {
+            crate::query_configs()
+        } else {
+            vec![]
+        };
+
+    let configs = if configs.is_empty() {
+        let pipeline_desc = PipelineDescription {
+            resources: vec![],
+            features: vec![],
+            limits: Limits::MIN,
+            dispatch: [1, 1, 1],
+        };
+
+        let default_configs = crate::default_configs_from(&available, &pipeline_desc);
+
+        if default_configs.is_empty() {
+            return Err(ExecutionError::NoDefaultConfigs);
+        }
+
+        default_configs
+    } else {
+        configs.to_vec()
+    };
+
+    let check_support = |config: &ConfigId, pipeline_desc: &PipelineDescription| {
+        if has_requirements(pipeline_desc) {
+            check_support(&available, config, pipeline_desc)
+        } else {
+            Ok(())
+        }
+    };
+
+    let workers = WorkerPool::new();
+    let never_cancelled = Cancellation::new();
+    let results = Mutex::new(
+        shaders
+            .iter()
+            .map(|_| configs.iter().map(|_| None).collect::<Vec<_>>())
+            .collect::<Vec<_>>(),
+    );
+    let configs_iter = Mutex::new(configs.iter().enumerate());
+    let num_threads = if let Some(p) = parallelism {
+        p.min(configs.len())
+    } else {
+        configs.len()
+    };
+
+    std::thread::scope(|s| -> Result<(), ExecutionError> {
+        let mut handles = vec![];
+
+        for _ in 0..num_threads {
+            handles.push(s.spawn(|| -> Result<(), ExecutionError> {
+                loop {
+                    let Some((index, config)) =
+                        configs_iter.lock().expect("iter mutex poisoned").next()
+                    else {
+                        return Ok(());
                     };
 
-                    let mut lock = on_event.lock().expect("event mutex poisoned");
-                    if output.status.success() {
-                        let (output, _): (ExecutionOutput, _) = bincode::decode_from_slice(
-                            &output.stdout,
-                            bincode::config::standard(),
-                        )?;
-                        lock(ExecutionEvent::Success(config, output.buffers))?;
-                    } else {
-                        lock(ExecutionEvent::Failure(output.stderr))?;
+                    for (shader_index, shader) in shaders.iter().enumerate() {
+                        let event = match check_support(config, &shader.pipeline_desc) {
+                            Ok(()) => {
+                                let args = ExecutionArgs {
+                                    shader: &shader.shader,
+                                    pipeline_desc: &shader.pipeline_desc,
+                                    options: &shader.options,
+                                    output_path: None,
+                                };
+
+                                transport::with_output_file(args, |args| {
+                                    workers.execute::<Host>(config, args, timeout, &never_cancelled)
+                                })?
+                                .into_event(config.clone())
+                            }
+                            Err(reason) => ExecutionEvent::Unsupported(config.clone(), reason),
+                        };
+
+                        let mut results = results.lock().expect("results mutex poisoned");
+                        results[shader_index][index] = Some(event);
                     }
                 }
             }));
@@ -182,16 +855,113 @@ pub fn execute<Host: HarnessHost, E: FnMut(ExecutionEvent) -> Result<(), Executi
         }
 
         Ok(())
-    })
+    })?;
+
+    let results = results.into_inner().expect("results mutex poisoned");
+
+    Ok(results
+        .into_iter()
+        .map(|events| events.into_iter().map(Option::unwrap).collect())
+        .collect())
+}
+
+/// The device that a config executes shaders with.
+enum Context {
+    Dawn(dawn::Context),
+    Wgpu(wgpu::Context),
+    // The interpreter has no device to configure
+    Cpu,
+    Browser(browser::Context),
+}
+
+impl Context {
+    fn new(
+        config: &ConfigId,
+        features: &[Feature],
+        options: &BackendOptions,
+    ) -> eyre::Result<Context> {
+        if options.trace_dir.is_some() && config.implementation != Implementation::Wgpu {
+            log::warn!("API traces can only be recorded by wgpu, so {config} won't be traced");
+        }
+
+        // Nothing has been executed yet, so any failure is reported as a startup failure which can
+        // be retried
+        Self::create(config, features, options)
+            .map_err(|e| BackendError::Startup(format!("{e:#}")).into())
+    }
+
+    fn create(
+        config: &ConfigId,
+        features: &[Feature],
+        options: &BackendOptions,
+    ) -> eyre::Result<Context> {
+        Ok(match config.implementation {
+            Implementation::Dawn => Context::Dawn(dawn::Context::new(features, options, config)?),
+            Implementation::Wgpu => {
+                Context::Wgpu(block_on(wgpu::Context::new(features, options, config))?)
+            }
+            Implementation::Cpu => Context::Cpu,
+            Implementation::Browser => Context::Browser(browser::Context::new(options, config)?),
+        })
+    }
+
+    fn run(
+        &self,
+        shader: &str,
+        pipeline_desc: &PipelineDescription,
+    ) -> eyre::Result<ExecutionOutput> {
+        match self {
+            Context::Dawn(context) => block_on(context.run(shader, pipeline_desc)),
+            Context::Wgpu(context) => block_on(context.run(shader, pipeline_desc)),
+            Context::Cpu => cpu::run(shader, pipeline_desc),
+            Context::Browser(context) => context.run(shader, pipeline_desc),
+        }
+    }
+}
+
+/// Keeps the context of the previous execution of a worker, which is reused if the next shader
+/// requires the same features and options.
+#[derive(Default)]
+struct ContextCache {
+    cached: Option<(Vec<Feature>, BackendOptions, Context)>,
+}
+
+impl ContextCache {
+    fn execute(
+        &mut self,
+        shader: &str,
+        pipeline_desc: &PipelineDescription,
+        options: &BackendOptions,
+        config: &ConfigId,
+    ) -> eyre::Result<ExecutionOutput> {
+        let context = match self.cached.take() {
+            Some((features, cached_options, context))
+                if features == pipeline_desc.features && cached_options == *options =>
+            {
+                context
+            }
+            _ => Context::new(config, &pipeline_desc.features, options)?,
+        };
+
+        // The context is only put back if the execution succeeded, since a failure may have left
+        // the device lost or in an error state that would be attributed to the next shader
+        let output = context.run(shader, pipeline_desc)?;
+
+        // A traced device records everything that it executes, so it isn't reused for later
+        // shaders
+        if options.trace_dir.is_none() {
+            self.cached = Some((pipeline_desc.features.clone(), options.clone(), context));
+        }
+
+        Ok(output)
+    }
 }
 
 pub fn execute_config(
     shader: &str,
     pipeline_desc: &PipelineDescription,
+    options: &BackendOptions,
     config: &ConfigId,
-) -> eyre::Result<Vec<Vec<u8>>> {
-    match config.implementation {
-        Implementation::Dawn => block_on(dawn::run(shader, pipeline_desc, config)),
-        Implementation::Wgpu => block_on(wgpu::run(shader, pipeline_desc, config)),
-    }
+) -> eyre::Result<ExecutionOutput> {
+    Context::new(config, &pipeline_desc.features, options)?.run(shader, pipeline_desc)
 }
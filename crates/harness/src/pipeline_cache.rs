@@ -0,0 +1,78 @@
+//! Caches the pipelines that a device has compiled, so that a worker which is sent the same shader
+//! again (e.g. by a reducer that re-checks a candidate) doesn't compile it again.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Number of pipelines that are kept for each device. Reducers usually only go back to the last few
+/// shaders that they tried, so older pipelines are unlikely to be used again.
+const CAPACITY: usize = 32;
+
+/// Pipelines keyed by the shader that they were compiled from. The config and backend options are
+/// part of the device that owns the cache, so they don't need to be part of the key, and overrides
+/// are always given their default values.
+pub(crate) struct PipelineCache<P> {
+    /// Entries in the order that they were last used, most recently used last.
+    entries: RefCell<Vec<Entry<P>>>,
+}
+
+struct Entry<P> {
+    hash: u64,
+    shader: String,
+    pipeline: Rc<P>,
+}
+
+impl<P> Default for PipelineCache<P> {
+    fn default() -> Self {
+        PipelineCache {
+            entries: RefCell::new(vec![]),
+        }
+    }
+}
+
+impl<P> PipelineCache<P> {
+    /// Returns the pipeline that was compiled from the shader, if it is cached.
+    pub fn get(&self, shader: &str) -> Option<Rc<P>> {
+        let hash = hash(shader);
+        let mut entries = self.entries.borrow_mut();
+
+        // The shader is compared as well as its hash, since a collision would silently execute the
+        // wrong shader
+        let index = entries
+            .iter()
+            .position(|it| it.hash == hash && it.shader == shader)?;
+
+        let entry = entries.remove(index);
+        let pipeline = entry.pipeline.clone();
+        entries.push(entry);
+
+        Some(pipeline)
+    }
+
+    /// Caches a pipeline that was compiled from the shader, which must have compiled without
+    /// errors, evicting the least recently used pipeline if the cache is full.
+    pub fn insert(&self, shader: &str, pipeline: P) -> Rc<P> {
+        let pipeline = Rc::new(pipeline);
+        let mut entries = self.entries.borrow_mut();
+
+        if entries.len() == CAPACITY {
+            entries.remove(0);
+        }
+
+        entries.push(Entry {
+            hash: hash(shader),
+            shader: shader.to_owned(),
+            pipeline: pipeline.clone(),
+        });
+
+        pipeline
+    }
+}
+
+fn hash(shader: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    shader.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,182 @@
+//! Queues for the adapters of a server, so that executions from concurrent requests never run on
+//! the same adapter at once.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use frontend::ExecutionError;
+use types::ConfigId;
+
+use crate::cancel::{self, Cancellation};
+use crate::Affinity;
+
+/// Serializes the executions on each adapter across all requests, in the order that they were
+/// queued. Configs that don't use an adapter (i.e. the interpreter) are never queued.
+#[derive(Default)]
+pub struct DeviceQueues {
+    queues: Mutex<HashMap<Affinity, Arc<Queue>>>,
+}
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+impl Queue {
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().expect("queue mutex poisoned")
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Tickets of the executions that are waiting, in the order that they will run.
+    waiting: VecDeque<u64>,
+    next_ticket: u64,
+    /// Whether an execution is currently running on the adapter.
+    busy: bool,
+}
+
+impl DeviceQueues {
+    pub fn new() -> DeviceQueues {
+        DeviceQueues::default()
+    }
+
+    /// Returns the number of executions that are waiting for an adapter.
+    pub fn queued(&self) -> usize {
+        self.all()
+            .iter()
+            .map(|queue| queue.state().waiting.len())
+            .sum()
+    }
+
+    /// Returns the number of adapters that are currently executing a shader.
+    pub fn running(&self) -> usize {
+        self.all().iter().filter(|queue| queue.state().busy).count()
+    }
+
+    fn all(&self) -> Vec<Arc<Queue>> {
+        let queues = self.queues.lock().expect("device queues mutex poisoned");
+        queues.values().cloned().collect()
+    }
+
+    /// Waits until the config's adapter is free and every execution that was queued for it before
+    /// has run. `on_wait` is called with the number of executions that are ahead whenever it
+    /// changes, and the wait is abandoned if it fails or the run is cancelled.
+    ///
+    /// The adapter is held until the returned turn is dropped.
+    pub(crate) fn wait_for_turn(
+        &self,
+        config: &ConfigId,
+        cancel: &Cancellation,
+        mut on_wait: impl FnMut(usize) -> Result<(), ExecutionError>,
+    ) -> Result<Option<Turn>, ExecutionError> {
+        let affinity = Affinity::of(config);
+        if affinity == Affinity::None {
+            return Ok(None);
+        }
+
+        let queue = self
+            .queues
+            .lock()
+            .expect("device queues mutex poisoned")
+            .entry(affinity)
+            .or_default()
+            .clone();
+
+        let mut waiting = Waiting::new(queue);
+        let mut reported = None;
+
+        loop {
+            cancel.check()?;
+
+            let mut state = waiting.state();
+            let position = state
+                .waiting
+                .iter()
+                .position(|it| *it == waiting.ticket)
+                .expect("ticket is queued until its turn");
+
+            if position == 0 && !state.busy {
+                state.waiting.pop_front();
+                state.busy = true;
+                drop(state);
+                waiting.done = true;
+                return Ok(Some(Turn {
+                    queue: waiting.queue.clone(),
+                }));
+            }
+
+            let ahead = position + usize::from(state.busy);
+            if reported != Some(ahead) {
+                // The lock isn't held while reporting, since that may block on the client
+                drop(state);
+                on_wait(ahead)?;
+                reported = Some(ahead);
+            } else {
+                // Cancellation doesn't wake up the queue, so it is checked periodically
+                let (state, _) = waiting
+                    .queue
+                    .changed
+                    .wait_timeout(state, cancel::POLL_INTERVAL)
+                    .expect("queue mutex poisoned");
+                drop(state);
+            }
+        }
+    }
+}
+
+/// An execution's place in a queue, which is given up if it stops waiting before its turn, e.g.
+/// because its client disconnected.
+struct Waiting {
+    queue: Arc<Queue>,
+    ticket: u64,
+    done: bool,
+}
+
+impl Waiting {
+    fn new(queue: Arc<Queue>) -> Waiting {
+        let ticket = {
+            let mut state = queue.state();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.waiting.push_back(ticket);
+            ticket
+        };
+
+        Waiting {
+            queue,
+            ticket,
+            done: false,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.queue.state()
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if !self.done {
+            let mut state = self.state();
+            state.waiting.retain(|it| *it != self.ticket);
+            self.queue.changed.notify_all();
+        }
+    }
+}
+
+/// Exclusive use of an adapter, which is passed on to the next execution in its queue when
+/// dropped.
+pub(crate) struct Turn {
+    queue: Arc<Queue>,
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        let mut state = self.queue.state();
+        state.busy = false;
+        self.queue.changed.notify_all();
+    }
+}
@@ -1,13 +1,25 @@
+use buffer_check::{ComparisonPolicy, Digest};
 use clap::Parser;
-use color_eyre::eyre::{self, eyre};
+use color_eyre::eyre::{self, eyre, WrapErr};
 use frontend::{ExecutionError, ExecutionEvent};
-use server_types::{ListResponse, Request, RunError, RunMessage, RunRequest};
-use std::io::{self, BufReader, BufWriter};
-use std::net::TcpListener;
-use std::sync::Mutex;
-use threadpool::ThreadPool;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use server_types::{
+    self, CancelResponse, FetchRequest, FetchResponse, ListResponse, OutputMode, Request, RunError,
+    RunMessage, RunRequest, StatusResponse, PROTOCOL_VERSION,
+};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use types::ConfigId;
 
-use crate::HarnessHost;
+use crate::{Cancellation, DeviceQueues, HarnessHost, WorkerPool};
 
 #[derive(Parser)]
 pub struct Options {
@@ -15,46 +27,175 @@ pub struct Options {
     #[clap(short, long, action, default_value = "localhost:0")]
     address: String,
 
-    /// Number of worker threads to use for running shaders in parallel.
+    /// Limit the number of parallel configurations executing a shader at once.
+    ///
+    /// If not provided, execution will spawn a thread for every adapter. Configurations that share
+    /// an adapter are always executed one at a time.
+    #[clap(long, short = 'j', action)]
+    config_parallelism: Option<usize>,
+
+    /// Keep an execution process running for each config between requests, instead of starting
+    /// a new one for every execution.
     ///
-    /// Defaults to the number of available CPUs.
+    /// This avoids the cost of starting the process and loading the driver for each shader. A
+    /// worker which crashes or times out is replaced by a new one.
     #[clap(long, action)]
-    parallelism: Option<usize>,
+    workers: bool,
 
-    /// Limit the number of parallel configurations executing a shader at once.
+    /// Maximum time in seconds that a worker may spend executing a shader, even if the request
+    /// has no timeout.
     ///
-    /// If not provided, execution will spawn a thread for every configuration.
-    #[clap(long, short = 'j', action)]
+    /// A worker which exceeds it is assumed to be stuck on a hung GPU submission. It is killed,
+    /// which tears down its device, and the shader is reported as a hang. The next shader for that
+    /// config is executed by a new worker with a new device.
+    #[clap(long, action, requires = "workers")]
+    watchdog: Option<u64>,
+
+    /// Path to a PEM file with the certificate chain of the server, which makes clients connect
+    /// with TLS.
+    ///
+    /// Clients must then use a `tls://` address with a host name that matches the certificate.
+    #[clap(long, action, requires = "tls-key")]
+    tls_cert: Option<PathBuf>,
+
+    /// Path to a PEM file with the private key of the server's certificate.
+    #[clap(long, action, requires = "tls-cert")]
+    tls_key: Option<PathBuf>,
+}
+
+/// How long a client may take to complete the TLS handshake and send its request. Without it, a
+/// client that never sends a full request (e.g. an older one that doesn't send a protocol version
+/// and waits for a response to its first message) would hold a thread forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of runs whose outputs are kept for their clients to fetch. A client fetches the outputs
+/// as soon as its run ends, so only the most recent runs need to be kept.
+const RETAINED_JOBS: usize = 16;
+
+/// A connection from a client, which may be encrypted with TLS.
+trait Connection: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Connection for T {}
+
+/// State which is shared by the connections of all clients.
+struct Server {
+    started: Instant,
+    tls: Option<Arc<ServerConfig>>,
     config_parallelism: Option<usize>,
+    workers: Option<WorkerPool>,
+    queues: DeviceQueues,
+    jobs: Jobs,
+    outputs: RetainedOutputs,
 }
 
+/// The runs that are in progress, which can be cancelled by their job ID.
+#[derive(Default)]
+struct Jobs {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, Arc<Cancellation>>>,
+}
+
+impl Jobs {
+    fn start(&self) -> (u64, Arc<Cancellation>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = Arc::new(Cancellation::new());
+        self.running().insert(id, cancel.clone());
+        (id, cancel)
+    }
+
+    fn finish(&self, id: u64) {
+        self.running().remove(&id);
+    }
+
+    fn cancel(&self, id: u64) -> bool {
+        match self.running().get(&id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn running(&self) -> MutexGuard<'_, HashMap<u64, Arc<Cancellation>>> {
+        self.running.lock().expect("jobs mutex poisoned")
+    }
+}
+
+/// The output buffers of each config that executed a shader successfully in a run.
+type Outputs = Vec<(ConfigId, Vec<Vec<u8>>)>;
+
+/// The outputs of runs whose outputs were sent as digests, by job ID, oldest first.
+#[derive(Default)]
+struct RetainedOutputs {
+    jobs: Mutex<VecDeque<(u64, Outputs)>>,
+}
+
+impl RetainedOutputs {
+    fn retain(&self, id: u64, outputs: Outputs) {
+        let mut jobs = self.jobs.lock().expect("outputs mutex poisoned");
+        if jobs.len() == RETAINED_JOBS {
+            jobs.pop_front();
+        }
+
+        jobs.push_back((id, outputs));
+    }
+
+    /// Removes the outputs of a job, since each client only fetches them once.
+    fn take(&self, id: u64) -> Option<Outputs> {
+        let mut jobs = self.jobs.lock().expect("outputs mutex poisoned");
+        let index = jobs.iter().position(|(it, _)| *it == id)?;
+        jobs.remove(index).map(|(_, outputs)| outputs)
+    }
+}
+
+/// Serves each client on its own thread, so any number of them can be connected at once. Their
+/// executions are queued on each adapter, so that shaders from different clients never run on the
+/// same adapter at the same time.
 pub fn run<Host: HarnessHost>(options: Options) -> eyre::Result<()> {
-    let parallelism = options
-        .parallelism
-        .unwrap_or_else(|| std::thread::available_parallelism().unwrap().get());
+    let workers = options.workers.then(|| match options.watchdog {
+        Some(watchdog) => WorkerPool::with_watchdog(Duration::from_secs(watchdog)),
+        None => WorkerPool::new(),
+    });
+
+    let tls = match (&options.tls_cert, &options.tls_key) {
+        (Some(cert), Some(key)) => Some(tls_config(cert, key)?),
+        _ => None,
+    };
 
-    let pool = ThreadPool::new(parallelism);
-    println!("Using thread pool with {parallelism} threads");
+    let server = Arc::new(Server {
+        started: Instant::now(),
+        tls,
+        config_parallelism: options.config_parallelism,
+        workers,
+        queues: DeviceQueues::new(),
+        jobs: Jobs::default(),
+        outputs: RetainedOutputs::default(),
+    });
 
     let listener = TcpListener::bind(options.address).unwrap();
     let address = listener.local_addr().unwrap();
     println!("Server listening at {address}");
 
-    for stream in listener.incoming() {
-        pool.execute(move || {
-            let stream = stream.unwrap();
-
-            let mut reader = BufReader::new(&stream);
+    if server.tls.is_some() {
+        println!("Clients must connect with TLS");
+    }
 
-            let req =
-                bincode::decode_from_std_read(&mut reader, bincode::config::standard()).unwrap();
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("failed to accept connection: {e}");
+                continue;
+            }
+        };
 
-            let writer = BufWriter::new(&stream);
-            match req {
-                Request::List => handle_list_request(writer).unwrap(),
-                Request::Run(req) => {
-                    handle_run_request::<Host, _>(req, writer, options.config_parallelism).unwrap()
-                }
+        let server = server.clone();
+        thread::spawn(move || {
+            // Clients may disconnect at any time (e.g. when a fuzzer is stopped), and anyone can
+            // connect, so errors only affect their own connection and are logged briefly
+            if let Err(e) = handle_connection::<Host>(stream, &server) {
+                eprintln!("{e:#}");
             }
         });
     }
@@ -62,6 +203,93 @@ pub fn run<Host: HarnessHost>(options: Options) -> eyre::Result<()> {
     Ok(())
 }
 
+fn handle_connection<Host: HarnessHost>(stream: TcpStream, server: &Server) -> eyre::Result<()> {
+    let peer = stream.peer_addr()?;
+
+    // The timeout is an option of the socket, so it can still be cleared through a clone once the
+    // stream has been wrapped
+    let socket = stream.try_clone()?;
+    socket.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut reader = BufReader::new(accept(stream, server.tls.clone())?);
+
+    let version = server_types::read_version(&mut reader)
+        .wrap_err_with(|| format!("failed to read protocol version from {peer}"))?;
+
+    // The server's version is sent even if they differ, so that the client can report it
+    server_types::write_version(reader.get_mut())?;
+    reader.get_mut().flush()?;
+
+    if version != Some(PROTOCOL_VERSION) {
+        // The request can't be decoded, but it is read until the client hangs up since closing a
+        // socket with unread data resets the connection, which may discard the server's version
+        let _ = io::copy(&mut reader, &mut io::sink());
+
+        return Err(match version {
+            Some(version) => eyre!(
+                "client {peer} uses protocol v{version}, but the server uses v{PROTOCOL_VERSION}"
+            ),
+            None => eyre!(
+                "client {peer} didn't send a protocol version, it may be older than the server"
+            ),
+        });
+    }
+
+    let req = bincode::decode_from_std_read(&mut reader, bincode::config::standard())
+        .wrap_err_with(|| format!("failed to read request from {peer}"))?;
+
+    socket.set_read_timeout(None)?;
+
+    // The client only sends a single request, so nothing is left in the reader's buffer
+    let writer = BufWriter::new(reader.into_inner());
+    match req {
+        Request::List => handle_list_request(writer),
+        Request::Run(req) => handle_run_request::<Host, _>(*req, writer, socket, server),
+        Request::Status => handle_status_request(writer, server),
+        Request::Cancel(id) => handle_cancel_request(writer, id, peer, server),
+        Request::Fetch(req) => handle_fetch_request(writer, req, server),
+    }
+    .wrap_err_with(|| format!("failed to handle request from {peer}"))
+}
+
+fn tls_config(cert: &Path, key: &Path) -> eyre::Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|it| it.collect::<Result<Vec<_>, _>>())
+        .wrap_err_with(|| format!("failed to read certificates from {}", cert.display()))?;
+
+    let key = PrivateKeyDer::from_pem_file(key)
+        .wrap_err_with(|| format!("failed to read private key from {}", key.display()))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(Arc::new(config))
+}
+
+/// Completes the TLS handshake with the client if the server uses TLS, so that a client which
+/// doesn't is rejected before its request is read.
+fn accept(
+    mut stream: TcpStream,
+    tls: Option<Arc<ServerConfig>>,
+) -> eyre::Result<Box<dyn Connection>> {
+    let Some(tls) = tls else {
+        return Ok(Box::new(stream));
+    };
+
+    let peer = stream.peer_addr()?;
+    let mut connection = ServerConnection::new(tls)?;
+    while connection.is_handshaking() {
+        connection
+            .complete_io(&mut stream)
+            .wrap_err_with(|| format!("TLS handshake with {peer} failed"))?;
+    }
+
+    Ok(Box::new(StreamOwned::new(connection, stream)))
+}
+
 fn handle_list_request(mut writer: impl io::Write) -> eyre::Result<()> {
     let configs = crate::query_configs();
     let res = ListResponse { configs };
@@ -69,49 +297,214 @@ fn handle_list_request(mut writer: impl io::Write) -> eyre::Result<()> {
     Ok(())
 }
 
+fn handle_status_request(mut writer: impl io::Write, server: &Server) -> eyre::Result<()> {
+    let available = crate::query_configs();
+    let configs = available.iter().map(|it| it.id.clone()).collect::<Vec<_>>();
+
+    let res = StatusResponse {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        protocol_version: PROTOCOL_VERSION,
+        environment: crate::environment::capture(&configs, &available),
+        queued: server.queues.queued(),
+        running: server.queues.running(),
+        uptime: server.started.elapsed(),
+        pipeline_cache: server.workers.as_ref().map(WorkerPool::cache_stats),
+    };
+
+    send(&mut writer, res)?;
+    Ok(())
+}
+
+fn handle_cancel_request(
+    mut writer: impl io::Write,
+    id: u64,
+    peer: SocketAddr,
+    server: &Server,
+) -> eyre::Result<()> {
+    let cancelled = server.jobs.cancel(id);
+    if cancelled {
+        println!("Job {id} was cancelled by {peer}");
+    }
+
+    send(&mut writer, CancelResponse { cancelled })?;
+    Ok(())
+}
+
+fn handle_fetch_request(
+    mut writer: impl io::Write,
+    req: FetchRequest,
+    server: &Server,
+) -> eyre::Result<()> {
+    let outputs = server.outputs.take(req.job).unwrap_or_default();
+    let outputs = req
+        .configs
+        .iter()
+        .map(|config| {
+            let (_, buffers) = outputs.iter().find(|(it, _)| it == config)?;
+            Some(buffers.clone())
+        })
+        .collect();
+
+    send(&mut writer, FetchResponse { outputs })?;
+    writer.flush()?;
+    Ok(())
+}
+
 fn handle_run_request<Host: HarnessHost, W: io::Write + Send>(
     req: RunRequest,
     writer: W,
-    config_parallelism: Option<usize>,
+    socket: TcpStream,
+    server: &Server,
+) -> eyre::Result<()> {
+    let (id, cancel) = server.jobs.start();
+    let result = run_job::<Host, _>(id, &cancel, req, writer, socket, server);
+    server.jobs.finish(id);
+    result
+}
+
+fn run_job<Host: HarnessHost, W: io::Write + Send>(
+    id: u64,
+    cancel: &Arc<Cancellation>,
+    req: RunRequest,
+    mut writer: W,
+    socket: TcpStream,
+    server: &Server,
 ) -> eyre::Result<()> {
+    send(&mut writer, RunMessage::Job(id))?;
+    writer.flush()?;
+
     let writer = Mutex::new(writer);
+    let disconnected = Arc::new(AtomicBool::new(false));
+
+    // The client doesn't send anything after its request, so the socket only becomes readable
+    // once it hangs up, which cancels the run instead of leaving e.g. a hung execution running
+    let watcher = socket.try_clone()?;
+    thread::spawn({
+        let cancel = cancel.clone();
+        let disconnected = disconnected.clone();
+        move || {
+            let _ = watcher.peek(&mut [0]);
+            disconnected.store(true, Ordering::Relaxed);
+            cancel.cancel();
+        }
+    });
+
+    // In digest mode, the type of each buffer is needed to normalize its output, and the buffers
+    // are kept until the client fetches them
+    let digests = match req.outputs {
+        OutputMode::Buffers => None,
+        OutputMode::Digests {
+            canonicalize_floats,
+        } => {
+            let (_, type_descs) = frontend::reflect_shader(&req.shader, Default::default());
+            let policy = ComparisonPolicy {
+                canonicalize_floats,
+                ..ComparisonPolicy::EXACT
+            };
+            Some((type_descs, policy))
+        }
+    };
+
+    let outputs = Mutex::new(vec![]);
 
     let on_event = |e| {
         let message = match e {
+            ExecutionEvent::Environment(environment) => RunMessage::Environment(environment),
             ExecutionEvent::UsingDefaultConfigs(configs) => {
                 RunMessage::UsingDefaultConfigs(configs)
             }
+            ExecutionEvent::UsingOracle(config) => RunMessage::UsingOracle(config),
+            ExecutionEvent::Queued(config, ahead) => RunMessage::ExecQueued(config, ahead),
             ExecutionEvent::Start(config) => RunMessage::ExecStart(config),
-            ExecutionEvent::Success(config, buffers) => RunMessage::ExecSuccess(config, buffers),
-            ExecutionEvent::Failure(stderr) => RunMessage::ExecFailure(stderr),
-            ExecutionEvent::Timeout => RunMessage::ExecTimeout,
+            ExecutionEvent::Unsupported(config, reason) => {
+                RunMessage::ExecUnsupported(config, reason)
+            }
+            ExecutionEvent::Success(config, buffers, timings) => match &digests {
+                Some((type_descs, policy)) => {
+                    let digest =
+                        Digest::of_execution(&buffers, &req.pipeline_desc, type_descs, policy);
+                    outputs
+                        .lock()
+                        .expect("outputs mutex poisoned")
+                        .push((config.clone(), buffers));
+                    RunMessage::ExecDigest(config, digest.0, timings)
+                }
+                None => RunMessage::ExecSuccess(config, buffers, timings),
+            },
+            ExecutionEvent::CompilationFailure(config, stderr) => {
+                RunMessage::ExecCompilationFailure(config, stderr)
+            }
+            ExecutionEvent::ValidationFailure(config, stderr) => {
+                RunMessage::ExecValidationFailure(config, stderr)
+            }
+            ExecutionEvent::RuntimeFailure(config, stderr) => {
+                RunMessage::ExecRuntimeFailure(config, stderr)
+            }
+            ExecutionEvent::DeviceLost(config, stderr) => {
+                RunMessage::ExecDeviceLost(config, stderr)
+            }
+            ExecutionEvent::Timeout(config) => RunMessage::ExecTimeout(config),
+            ExecutionEvent::Hang(config) => RunMessage::ExecHang(config),
         };
 
         let mut writer = writer.lock().expect("writer mutex poisoned");
-        send(&mut *writer, message)?;
-        writer.flush()?;
-        Ok(())
+        let sent = send(&mut *writer, message)
+            .map_err(ExecutionError::from)
+            .and_then(|_| Ok(writer.flush()?));
+
+        if sent.is_err() {
+            disconnected.store(true, Ordering::Relaxed);
+        }
+
+        sent
     };
 
     let result = crate::execute::<Host, _>(
         &req.shader,
         &req.pipeline_desc,
         &req.configs,
+        req.oracle,
+        &req.variants,
+        &req.filter,
+        &req.options,
         req.timeout,
-        config_parallelism,
+        req.retry,
+        server.config_parallelism,
+        server.workers.as_ref(),
+        Some(&server.queues),
+        Some(cancel),
         on_event,
-    )
-    .map_err(|e| match e {
+    );
+
+    // The client may have gone away while its executions were queued or running, e.g. because it
+    // was stopped, which isn't a failure of the server
+    if disconnected.load(Ordering::Relaxed) {
+        return Err(eyre!("client disconnected"));
+    }
+
+    let result = result.map_err(|e| match e {
         ExecutionError::NoDefaultConfigs => RunError::NoDefaultConfigs,
+        ExecutionError::NoOracleConfig => RunError::NoOracleConfig,
+        ExecutionError::Cancelled => RunError::Cancelled,
         e => {
             eprintln!("{:?}", eyre!(e));
             RunError::InternalServerError
         }
     });
 
+    // The outputs are kept before the run ends, since the client fetches them once it has
+    let outputs = outputs.into_inner().expect("outputs mutex poisoned");
+    if !outputs.is_empty() {
+        server.outputs.retain(id, outputs);
+    }
+
     // Lock writer one last time to send the final result
     let mut writer = writer.lock().expect("writer mutex poisoned");
     send(&mut *writer, RunMessage::End(result))?;
+    writer.flush()?;
+
+    // Wakes up the watcher, which is otherwise left waiting if the client keeps the connection open
+    let _ = socket.shutdown(Shutdown::Read);
 
     Ok(())
 }
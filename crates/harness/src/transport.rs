@@ -0,0 +1,233 @@
+//! Transfers output buffers from execution processes. They are streamed through stdout in chunks,
+//! or passed through a memory-mapped temporary file if they are large.
+
+use std::fs::OpenOptions;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::thread::JoinHandle;
+
+use bincode::de::read::Reader;
+use bincode::de::{BorrowDecoder, Decoder};
+use bincode::enc::write::Writer;
+use bincode::enc::Encoder;
+use bincode::error::{DecodeError, EncodeError};
+use bincode::{BorrowDecode, Decode, Encode};
+use frontend::ExecutionError;
+use memmap2::{Mmap, MmapMut};
+use reflection::{PipelineDescription, ResourceKind};
+use tempfile::NamedTempFile;
+
+use crate::{ExecutionArgs, ExecutionOutput, Outcome, Timings};
+
+/// Maximum size of a chunk of an output buffer on the wire. Buffers are decoded a chunk at a time,
+/// so a corrupted length can't make the decoder allocate much more than was actually sent.
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Outputs that are smaller than this are sent over stdout, since creating and mapping a file
+/// costs more than copying them through the pipe.
+const THRESHOLD: u64 = 1 << 20;
+
+/// Offers the execution process a file to write the output buffers to if they are large, and reads
+/// them from the file if the execution succeeds.
+pub(crate) fn with_output_file(
+    args: ExecutionArgs,
+    run: impl FnOnce(ExecutionArgs) -> Result<Outcome, ExecutionError>,
+) -> Result<Outcome, ExecutionError> {
+    let file = OutputFile::for_pipeline(args.pipeline_desc)?;
+    let args = ExecutionArgs {
+        output_path: file.as_ref().map(|it| it.path()),
+        ..args
+    };
+
+    let mut outcome = run(args)?;
+
+    if let (Some(file), Outcome::Success(output)) = (&file, &mut outcome) {
+        output.buffers = file.read_buffers()?;
+    }
+
+    Ok(outcome)
+}
+
+/// Moves the output buffers to the file that the harness offered, if it offered one, from the
+/// execution process.
+pub(crate) fn write_output(path: Option<&Path>, output: &mut ExecutionOutput) -> io::Result<()> {
+    if let Some(path) = path {
+        write_buffers(path, &output.buffers)?;
+        output.buffers.clear();
+    }
+
+    Ok(())
+}
+
+/// Encodes each buffer as a sequence of chunks, each prefixed by its length and none longer than
+/// [`CHUNK_SIZE`], followed by an empty chunk.
+impl Encode for ExecutionOutput {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.timings.encode(encoder)?;
+        self.cache_hit.encode(encoder)?;
+        self.buffers.len().encode(encoder)?;
+
+        for buffer in &self.buffers {
+            for chunk in buffer.chunks(CHUNK_SIZE) {
+                chunk.len().encode(encoder)?;
+                encoder.writer().write(chunk)?;
+            }
+
+            0usize.encode(encoder)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Decode for ExecutionOutput {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let timings = Timings::decode(decoder)?;
+        let cache_hit = Option::decode(decoder)?;
+        let count = usize::decode(decoder)?;
+
+        // The count isn't trusted to preallocate, for the same reason as the chunk lengths
+        let mut buffers = vec![];
+        for _ in 0..count {
+            let mut buffer = vec![];
+            loop {
+                let len = usize::decode(decoder)?;
+                if len == 0 {
+                    break;
+                }
+
+                if len > CHUNK_SIZE {
+                    return Err(DecodeError::Other("output buffer chunk is too long"));
+                }
+
+                decoder.claim_bytes_read(len)?;
+
+                let start = buffer.len();
+                buffer.resize(start + len, 0);
+                decoder.reader().read(&mut buffer[start..])?;
+            }
+
+            buffers.push(buffer);
+        }
+
+        Ok(ExecutionOutput {
+            buffers,
+            timings,
+            cache_hit,
+        })
+    }
+}
+
+impl<'de> BorrowDecode<'de> for ExecutionOutput {
+    fn borrow_decode<D: BorrowDecoder<'de>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        ExecutionOutput::decode(decoder)
+    }
+}
+
+/// Decodes the output of an execution process from its stdout on another thread, as it is written.
+///
+/// The rest of stdout is always read, even if decoding fails, so that the process can't block on a
+/// full pipe (an error while reading it means that the pipe is already closed). A process that
+/// fails usually doesn't write any output, so the error is only relevant if it exits successfully.
+pub(crate) fn decode_in_background(
+    pipe: impl Read + Send + 'static,
+) -> JoinHandle<Result<ExecutionOutput, DecodeError>> {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let output = bincode::decode_from_std_read(&mut reader, bincode::config::standard());
+        let _ = io::copy(&mut reader, &mut io::sink());
+        output
+    })
+}
+
+/// A file that the execution process writes the output buffers to, which is deleted when dropped.
+///
+/// Each buffer is written as its length in bytes (a little-endian `u64`) followed by its contents,
+/// and the file is as long as all of the buffers.
+struct OutputFile {
+    file: NamedTempFile,
+}
+
+impl OutputFile {
+    /// Creates a file for the outputs of the pipeline if they are large enough for it to be faster
+    /// than stdout.
+    fn for_pipeline(pipeline_desc: &PipelineDescription) -> io::Result<Option<OutputFile>> {
+        let size: u64 = pipeline_desc
+            .resources
+            .iter()
+            .filter(|it| it.kind == ResourceKind::StorageBuffer)
+            .map(|it| u64::from(it.size))
+            .sum();
+
+        if size < THRESHOLD {
+            return Ok(None);
+        }
+
+        let file = tempfile::Builder::new()
+            .prefix("wgslsmith-output-")
+            .tempfile()?;
+
+        Ok(Some(OutputFile { file }))
+    }
+
+    fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Reads the buffers that the execution process wrote to the file.
+    fn read_buffers(&self) -> io::Result<Vec<Vec<u8>>> {
+        if self.file.as_file().metadata()?.len() == 0 {
+            return Ok(vec![]);
+        }
+
+        // SAFETY: The execution process has finished writing to the file, and nothing else knows
+        // its name, so it isn't modified while it is mapped.
+        let map = unsafe { Mmap::map(self.file.as_file())? };
+
+        let mut buffers = vec![];
+        let mut rest = &map[..];
+
+        while !rest.is_empty() {
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, "truncated output file");
+
+            let (len, data) = rest.split_first_chunk::<8>().ok_or_else(invalid)?;
+            let len = usize::try_from(u64::from_le_bytes(*len)).map_err(|_| invalid())?;
+            if data.len() < len {
+                return Err(invalid());
+            }
+
+            let (buffer, next) = data.split_at(len);
+            buffers.push(buffer.to_vec());
+            rest = next;
+        }
+
+        Ok(buffers)
+    }
+}
+
+fn write_buffers(path: &Path, buffers: &[Vec<u8>]) -> io::Result<()> {
+    let size = buffers.iter().map(|it| 8 + it.len() as u64).sum();
+
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    file.set_len(size)?;
+
+    if size == 0 {
+        return Ok(());
+    }
+
+    // SAFETY: The harness doesn't read the file until this process has finished executing the
+    // shader, and nothing else knows its name.
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    let mut rest = &mut map[..];
+
+    for buffer in buffers {
+        let (len, data) = rest.split_at_mut(8);
+        len.copy_from_slice(&(buffer.len() as u64).to_le_bytes());
+
+        let (data, next) = data.split_at_mut(buffer.len());
+        data.copy_from_slice(buffer);
+        rest = next;
+    }
+
+    Ok(())
+}
@@ -1,14 +1,32 @@
 use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::ConfigId;
+use crate::pipeline_cache::PipelineCache;
+use crate::{BackendError, BackendOptions, ConfigId, ExecutionOutput, Timings, Variant};
+use ast::{Directive, Extension};
 use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use reflection::{PipelineDescription, ResourceKind};
+use naga::back::spv;
+use naga::proc::{BoundsCheckPolicies, BoundsCheckPolicy};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use reflection::{
+    Feature, PipelineDescription, PipelineResource, ResourceKind, TextureDescriptor,
+    TextureDimension, TextureSampleType,
+};
 use wgpu::wgt::PollType::Wait;
 use wgpu::{
-    Backends, BindGroupDescriptor, BindGroupEntry, Buffer, BufferDescriptor, BufferUsages,
-    CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor, DeviceDescriptor,
-    DxcShaderModel, Instance, Limits, MapMode, ShaderModuleDescriptor, ShaderSource,
+    AddressMode, Backends, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, CompareFunction,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, DeviceDescriptor,
+    DeviceLostReason, DxcShaderModel, ErrorFilter, ExperimentalFeatures, Extent3d, Features,
+    FilterMode, Instance, InstanceFlags, Limits, MapMode, MipmapFilterMode, Origin3d,
+    PipelineLayout, PipelineLayoutDescriptor, Queue, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderModuleDescriptorPassthrough, ShaderSource,
+    ShaderStages, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, Trace,
+    WgslLanguageFeatures,
 };
 
 pub fn get_adapters() -> Vec<types::Adapter> {
@@ -17,6 +35,16 @@ pub fn get_adapters() -> Vec<types::Adapter> {
         ..Default::default()
     });
 
+    // Language extensions are supported by the instance as a whole, rather than by each adapter
+    let mut language_features = vec![];
+
+    if instance
+        .wgsl_language_features()
+        .contains(WgslLanguageFeatures::Packed4x8IntegerDotProduct)
+    {
+        language_features.push(Feature::Packed4x8IntegerDotProduct);
+    }
+
     let adapters = futures::executor::block_on(instance.enumerate_adapters(Backends::all()));
     adapters
         .into_iter()
@@ -24,7 +52,11 @@ pub fn get_adapters() -> Vec<types::Adapter> {
             let info = adapter.get_info();
             Some(types::Adapter {
                 name: info.name,
+                vendor_id: info.vendor,
                 device_id: info.device,
+                driver: format!("{} {}", info.driver, info.driver_info)
+                    .trim()
+                    .to_owned(),
                 backend: match info.backend {
                     wgpu::Backend::Vulkan => crate::BackendType::Vulkan,
                     wgpu::Backend::Metal => crate::BackendType::Metal,
@@ -33,229 +65,739 @@ pub fn get_adapters() -> Vec<types::Adapter> {
                     wgpu::Backend::BrowserWebGpu => return None,
                     _ => return None,
                 },
+                features: [
+                    (Features::SUBGROUP, Feature::Subgroups),
+                    (Features::SHADER_F16, Feature::ShaderF16),
+                    (Features::IMMEDIATES, Feature::PushConstants),
+                ]
+                .into_iter()
+                .filter(|(wgpu_feature, _)| adapter.features().contains(*wgpu_feature))
+                .map(|(_, feature)| feature)
+                .chain(language_features.iter().copied())
+                .collect(),
+                // Devices are always created with the default limits
+                limits: reflection::Limits::DEFAULT,
+                software: info.device_type == wgpu::DeviceType::Cpu,
             })
         })
         .collect()
 }
 
-pub async fn run(
-    shader: &str,
-    meta: &PipelineDescription,
-    config: &ConfigId,
-) -> Result<Vec<Vec<u8>>> {
-    let backend = match config.backend {
-        crate::BackendType::Dx12 => wgpu::Backend::Dx12,
-        crate::BackendType::Metal => wgpu::Backend::Metal,
-        crate::BackendType::Vulkan => wgpu::Backend::Vulkan,
+fn create_texture_view(
+    device: &Device,
+    queue: &Queue,
+    desc: &TextureDescriptor,
+    init: Option<&[u8]>,
+) -> TextureView {
+    let [width, height, depth_or_array_layers] = desc.size;
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers,
     };
 
-    let dx12_shader_compiler = wgpu::Dx12Compiler::DynamicDxc {
-        dxc_path: "./dxcompiler.dll".to_owned(),
-        max_shader_model: DxcShaderModel::V6_7,
-    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Texture"),
+        size,
+        mip_level_count: desc.mip_level_count,
+        sample_count: 1,
+        dimension: match desc.dimension {
+            TextureDimension::D1 => wgpu::TextureDimension::D1,
+            TextureDimension::D3 => wgpu::TextureDimension::D3,
+            _ => wgpu::TextureDimension::D2,
+        },
+        format: match desc.sample_type {
+            TextureSampleType::Float => TextureFormat::Rgba8Unorm,
+            TextureSampleType::Sint => TextureFormat::Rgba8Sint,
+            TextureSampleType::Uint => TextureFormat::Rgba8Uint,
+            TextureSampleType::Depth => TextureFormat::Depth16Unorm,
+        },
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
 
-    let instance = Instance::new(&wgpu::InstanceDescriptor {
-        backends: Backends::all(),
-        backend_options: wgpu::BackendOptions {
-            gl: Default::default(),
-            dx12: wgpu::Dx12BackendOptions {
-                shader_compiler: dx12_shader_compiler,
-                ..Default::default()
+    if let Some(init) = init {
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
             },
-            noop: Default::default(),
-        },
+            init,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * desc.texel_size()),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+    }
+
+    texture.create_view(&TextureViewDescriptor {
+        dimension: Some(texture_view_dimension(desc.dimension)),
         ..Default::default()
-    });
+    })
+}
 
-    let adapters = instance.enumerate_adapters(Backends::all()).await;
-    let adapter = adapters
-        .into_iter()
-        .find(|adapter| {
-            let info = adapter.get_info();
-            info.device == config.device_id && info.backend == backend
+fn texture_view_dimension(dimension: TextureDimension) -> TextureViewDimension {
+    match dimension {
+        TextureDimension::D1 => TextureViewDimension::D1,
+        TextureDimension::D2 => TextureViewDimension::D2,
+        TextureDimension::D2Array => TextureViewDimension::D2Array,
+        TextureDimension::D3 => TextureViewDimension::D3,
+        TextureDimension::Cube => TextureViewDimension::Cube,
+        TextureDimension::CubeArray => TextureViewDimension::CubeArray,
+    }
+}
+
+fn create_sampler(device: &Device, comparison: bool) -> Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        label: Some("Sampler"),
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Nearest,
+        min_filter: FilterMode::Nearest,
+        mipmap_filter: MipmapFilterMode::Nearest,
+        compare: comparison.then_some(CompareFunction::Less),
+        ..Default::default()
+    })
+}
+
+/// Creates the layout of the resources in each bind group, which is needed by passthrough shaders
+/// since wgpu can't reflect it from them.
+fn create_pipeline_layout(device: &Device, meta: &PipelineDescription) -> PipelineLayout {
+    let entry = |resource: &PipelineResource| BindGroupLayoutEntry {
+        binding: resource.binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: match &resource.kind {
+            ResourceKind::StorageBuffer => BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            ResourceKind::ReadOnlyStorageBuffer => BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            ResourceKind::UniformBuffer => BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            ResourceKind::Texture(desc) => BindingType::Texture {
+                sample_type: match desc.sample_type {
+                    TextureSampleType::Float => wgpu::TextureSampleType::Float { filterable: true },
+                    TextureSampleType::Sint => wgpu::TextureSampleType::Sint,
+                    TextureSampleType::Uint => wgpu::TextureSampleType::Uint,
+                    TextureSampleType::Depth => wgpu::TextureSampleType::Depth,
+                },
+                view_dimension: texture_view_dimension(desc.dimension),
+                multisampled: false,
+            },
+            ResourceKind::Sampler { comparison: true } => {
+                BindingType::Sampler(SamplerBindingType::Comparison)
+            }
+            ResourceKind::Sampler { comparison: false } => {
+                BindingType::Sampler(SamplerBindingType::Filtering)
+            }
+        },
+        count: None,
+    };
+
+    // Groups that have no resources are given empty layouts, since the layouts are indexed by
+    // group
+    let group_count = meta.bind_groups().last().map_or(0, |it| it + 1);
+    let bind_group_layouts = (0..group_count)
+        .map(|group| {
+            let entries = meta
+                .resources
+                .iter()
+                .filter(|it| it.group == group)
+                .map(entry)
+                .collect::<Vec<_>>();
+
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &entries,
+            })
         })
-        .ok_or_else(|| eyre!("no adapter found matching id: {config}"))?;
+        .collect::<Vec<_>>();
 
-    let device_descriptor = DeviceDescriptor {
-        required_limits: Limits {
-            // This is needed to support swiftshader
-            max_storage_textures_per_shader_stage: 4,
-            ..Default::default()
+    device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+        immediate_size: 0,
+    })
+}
+
+/// Compiles the shader to SPIR-V with naga, using the same bounds checks that wgpu applies to WGSL
+/// shaders so that the outputs are comparable.
+fn compile_spirv(shader: &str, meta: &PipelineDescription) -> Result<Vec<u32>, BackendError> {
+    let module = naga::front::wgsl::parse_str(shader)
+        .map_err(|e| BackendError::Compilation(e.emit_to_string(shader)))?;
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| BackendError::Compilation(e.emit_to_string(shader)))?;
+
+    let (module, info) = naga::back::pipeline_constants::process_overrides(
+        &module,
+        &info,
+        Some((naga::ShaderStage::Compute, "main")),
+        &Default::default(),
+    )
+    .map_err(|e| BackendError::Compilation(e.to_string()))?;
+
+    let options = spv::Options {
+        // Subgroup operations need SPIR-V 1.3
+        lang_version: if meta.features.contains(&Feature::Subgroups) {
+            (1, 3)
+        } else {
+            (1, 0)
+        },
+        bounds_check_policies: BoundsCheckPolicies {
+            index: BoundsCheckPolicy::Restrict,
+            buffer: BoundsCheckPolicy::Restrict,
+            image_load: BoundsCheckPolicy::Restrict,
+            binding_array: BoundsCheckPolicy::Unchecked,
         },
         ..Default::default()
     };
 
-    let (device, queue) = adapter.request_device(&device_descriptor).await?;
+    let pipeline_options = spv::PipelineOptions {
+        shader_stage: naga::ShaderStage::Compute,
+        entry_point: "main".to_owned(),
+    };
+
+    spv::write_vec(&module, &info, &options, Some(&pipeline_options))
+        .map_err(|e| BackendError::Compilation(e.to_string()))
+}
+
+fn wgpu_features(features: &[Feature]) -> Features {
+    features
+        .iter()
+        .fold(Features::empty(), |acc, feature| match feature {
+            Feature::Subgroups => acc | Features::SUBGROUP,
+            Feature::ShaderF16 => acc | Features::SHADER_F16,
+            Feature::PushConstants => acc | Features::IMMEDIATES,
+            // Language extensions don't need to be requested from the device
+            Feature::Packed4x8IntegerDotProduct => acc,
+        })
+}
 
-    let preprocessor_opts = preprocessor::Options {
-        module_scope_constants: false,
+/// Returns the default instance flags, with the flags in `options` set or cleared.
+fn instance_flags(options: &BackendOptions) -> Result<InstanceFlags> {
+    let flag = |name: &String| {
+        InstanceFlags::from_name(&name.to_uppercase())
+            .ok_or_else(|| eyre!("unknown wgpu instance flag: {name}"))
     };
 
-    let preprocessed = preprocessor::preprocess(preprocessor_opts, shader.to_owned());
-    let shader_module = device.create_shader_module(ShaderModuleDescriptor {
-        label: None,
-        source: ShaderSource::Wgsl(Cow::Owned(preprocessed)),
-    });
+    let mut flags = InstanceFlags::default();
 
-    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
-        entry_point: Some("main"),
-        label: None,
-        module: &shader_module,
-        layout: None,
-        cache: None,
-        compilation_options: wgpu::PipelineCompilationOptions::default(),
-    });
+    for name in &options.enable_wgpu_flags {
+        flags |= flag(name)?;
+    }
 
-    let mut resource_buffers = vec![];
+    for name in &options.disable_wgpu_flags {
+        flags -= flag(name)?;
+    }
 
-    enum ResourceBuffer {
-        Storage {
-            binding: u32,
-            size: u64,
-            gpu_buffer: Buffer,
-            staging_buffer: Buffer,
-        },
-        Uniform {
-            binding: u32,
-            buffer: Buffer,
-        },
+    Ok(flags)
+}
+
+/// Returns where the device records its API trace, which is a subdirectory of the trace directory
+/// so that configs which are executed at the same time don't overwrite each other's traces.
+#[cfg(feature = "trace")]
+fn trace(options: &BackendOptions, config: &ConfigId) -> Result<Trace> {
+    let Some(dir) = &options.trace_dir else {
+        return Ok(Trace::Off);
+    };
+
+    let dir = dir.join(config.to_string().replace(':', "-"));
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(Trace::Directory(dir))
+}
+
+#[cfg(not(feature = "trace"))]
+fn trace(options: &BackendOptions, _: &ConfigId) -> Result<Trace> {
+    if options.trace_dir.is_some() {
+        log::warn!("the harness was built without the `trace` feature, so no trace is recorded");
     }
 
-    for resource in &meta.resources {
-        let size = resource.size as u64;
-        match resource.kind {
-            ResourceKind::StorageBuffer => {
-                let gpu_buffer = device.create_buffer(&BufferDescriptor {
-                    label: Some("Storage GPU Buffer"),
-                    usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
-                    size,
-                    mapped_at_creation: false,
-                });
+    Ok(Trace::Off)
+}
 
-                let staging_buffer = device.create_buffer(&BufferDescriptor {
-                    label: Some("Storage Staging Buffer"),
-                    usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-                    size,
-                    mapped_at_creation: false,
-                });
+/// A device which can execute any number of shaders that only require its features.
+pub struct Context {
+    device: Device,
+    queue: Queue,
+    lost: Arc<Mutex<Option<String>>>,
+    uncaptured: Arc<Mutex<Option<BackendError>>>,
+    spirv: bool,
+    pipelines: PipelineCache<ComputePipeline>,
+}
 
-                resource_buffers.push(ResourceBuffer::Storage {
-                    binding: resource.binding,
-                    size,
-                    gpu_buffer,
-                    staging_buffer,
-                });
-            }
-            ResourceKind::UniformBuffer => {
-                let buffer = device.create_buffer(&BufferDescriptor {
-                    label: Some("Uniform Buffer"),
-                    usage: BufferUsages::UNIFORM,
-                    size,
-                    mapped_at_creation: true,
-                });
+impl Context {
+    pub async fn new(
+        features: &[Feature],
+        options: &BackendOptions,
+        config: &ConfigId,
+    ) -> Result<Context> {
+        let backend = match config.backend {
+            crate::BackendType::Dx12 => wgpu::Backend::Dx12,
+            crate::BackendType::Metal => wgpu::Backend::Metal,
+            crate::BackendType::Vulkan => wgpu::Backend::Vulkan,
+            crate::BackendType::Interpreter
+            | crate::BackendType::Chrome
+            | crate::BackendType::Firefox => return Err(eyre!("unsupported backend: {config}")),
+        };
+
+        let dx12_shader_compiler = wgpu::Dx12Compiler::DynamicDxc {
+            dxc_path: "./dxcompiler.dll".to_owned(),
+            max_shader_model: DxcShaderModel::V6_7,
+        };
+
+        let instance = Instance::new(&wgpu::InstanceDescriptor {
+            backends: Backends::all(),
+            flags: instance_flags(options)?,
+            backend_options: wgpu::BackendOptions {
+                gl: Default::default(),
+                dx12: wgpu::Dx12BackendOptions {
+                    shader_compiler: dx12_shader_compiler,
+                    ..Default::default()
+                },
+                noop: Default::default(),
+            },
+            ..Default::default()
+        });
+
+        let adapters = instance.enumerate_adapters(Backends::all()).await;
+        let adapter = adapters
+            .into_iter()
+            .find(|adapter| {
+                let info = adapter.get_info();
+                info.device == config.device_id && info.backend == backend
+            })
+            .ok_or_else(|| eyre!("no adapter found matching id: {config}"))?;
 
-                if let Some(init) = resource.init.as_deref() {
-                    buffer
-                        .slice(..)
-                        .get_mapped_range_mut()
-                        .copy_from_slice(init);
-                }
+        let spirv = config.variant == Some(Variant::Spirv);
 
-                buffer.unmap();
+        let mut required_features = wgpu_features(features);
+        if spirv {
+            required_features |= Features::EXPERIMENTAL_PASSTHROUGH_SHADERS;
+        }
 
-                resource_buffers.push(ResourceBuffer::Uniform {
-                    binding: resource.binding,
-                    buffer,
-                });
+        let device_descriptor = DeviceDescriptor {
+            required_features,
+            required_limits: Limits {
+                // This is needed to support swiftshader
+                max_storage_textures_per_shader_stage: 4,
+                // Immediates (push constants) can't be used without a non-zero size limit
+                max_immediate_size: if features.contains(&Feature::PushConstants) {
+                    adapter.limits().max_immediate_size
+                } else {
+                    0
+                },
+                ..Default::default()
+            },
+            // SAFETY: passthrough shaders are only used to compare the driver's compilation of
+            // naga's SPIR-V against the WGSL path, so bugs in the experimental feature are
+            // acceptable
+            experimental_features: if spirv {
+                unsafe { ExperimentalFeatures::enabled() }
+            } else {
+                ExperimentalFeatures::disabled()
+            },
+            trace: trace(options, config)?,
+            ..Default::default()
+        };
+
+        let (device, queue) = adapter.request_device(&device_descriptor).await?;
+
+        let lost = Arc::new(Mutex::new(None));
+
+        device.set_device_lost_callback({
+            let lost = lost.clone();
+            move |reason, message| {
+                // Devices are also reported as lost when they are destroyed, which isn't a failure
+                if reason == DeviceLostReason::Unknown {
+                    *lost.lock().unwrap() = Some(message);
+                }
             }
+        });
+
+        // Errors which aren't captured by an error scope would otherwise panic. Later errors are
+        // usually caused by the first (e.g. by using an invalid bind group), so only it is kept.
+        let uncaptured = Arc::new(Mutex::new(None));
+
+        device.on_uncaptured_error(Arc::new({
+            let uncaptured = uncaptured.clone();
+            move |error| {
+                let mut uncaptured = uncaptured.lock().unwrap();
+                if uncaptured.is_none() {
+                    *uncaptured = Some(match error {
+                        wgpu::Error::Validation { description, .. } => {
+                            BackendError::Validation(description)
+                        }
+                        error => BackendError::Runtime(error.to_string()),
+                    });
+                }
+            }
+        }));
+
+        Ok(Context {
+            device,
+            queue,
+            lost,
+            uncaptured,
+            spirv,
+            pipelines: PipelineCache::default(),
+        })
+    }
+
+    /// Compiles the shader into a pipeline, returning how long it took to create the shader module
+    /// and the pipeline.
+    async fn compile(
+        &self,
+        shader: &str,
+        meta: &PipelineDescription,
+    ) -> Result<(ComputePipeline, Duration, Duration)> {
+        let Context { device, spirv, .. } = self;
+
+        let mut preprocessed = preprocessor::preprocess(shader.to_owned());
+
+        // naga doesn't accept `enable subgroups;` yet - subgroup operations are instead allowed by
+        // requesting the feature when creating the device, so the directive is removed from the
+        // module before it is written back out
+        if meta.features.contains(&Feature::Subgroups) {
+            let mut module = parser::parse(&preprocessed);
+            module
+                .directives
+                .retain(|it| *it != Directive::Enable(Extension::Subgroups));
+
+            preprocessed = String::new();
+            ast::writer::Writer
+                .write_module(&mut preprocessed, &module)
+                .expect("writing to a string can't fail");
         }
+
+        // Backend shader compilation errors are reported as internal errors
+        let internal_scope = device.push_error_scope(ErrorFilter::Internal);
+        let validation_scope = device.push_error_scope(ErrorFilter::Validation);
+
+        let start = Instant::now();
+
+        let (shader_module, layout) = if *spirv {
+            let spirv = compile_spirv(&preprocessed, meta)?;
+
+            // SAFETY: the SPIR-V module is validated by naga before it is written, and the layout
+            // is created from the same pipeline description as the bind group
+            let shader_module = unsafe {
+                device.create_shader_module_passthrough(ShaderModuleDescriptorPassthrough {
+                    spirv: Some(Cow::Owned(spirv)),
+                    ..Default::default()
+                })
+            };
+
+            (shader_module, Some(create_pipeline_layout(device, meta)))
+        } else {
+            let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+                label: None,
+                source: ShaderSource::Wgsl(Cow::Owned(preprocessed)),
+            });
+
+            (shader_module, None)
+        };
+
+        let shader_module_time = start.elapsed();
+        let start = Instant::now();
+
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            entry_point: Some("main"),
+            label: None,
+            module: &shader_module,
+            layout: layout.as_ref(),
+            cache: None,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        });
+
+        let pipeline_time = start.elapsed();
+
+        let validation_error = validation_scope.pop().await;
+        let internal_error = internal_scope.pop().await;
+
+        if let Some(error) = validation_error.or(internal_error) {
+            return Err(BackendError::Compilation(error.to_string()).into());
+        }
+
+        Ok((pipeline, shader_module_time, pipeline_time))
     }
 
-    let bind_group_entries = resource_buffers
-        .iter()
-        .map(|res| match res {
-            ResourceBuffer::Storage {
-                binding,
-                gpu_buffer,
-                ..
-            } => BindGroupEntry {
-                binding: *binding,
-                resource: gpu_buffer.as_entire_binding(),
+    pub async fn run(&self, shader: &str, meta: &PipelineDescription) -> Result<ExecutionOutput> {
+        let Context {
+            device,
+            queue,
+            lost,
+            uncaptured,
+            pipelines,
+            ..
+        } = self;
+
+        let cached = pipelines.get(shader);
+        let cache_hit = cached.is_some();
+
+        let (pipeline, shader_module_time, pipeline_time) = match cached {
+            Some(pipeline) => (pipeline, Duration::ZERO, Duration::ZERO),
+            None => {
+                let (pipeline, shader_module_time, pipeline_time) =
+                    self.compile(shader, meta).await?;
+                let pipeline = pipelines.insert(shader, pipeline);
+                (pipeline, shader_module_time, pipeline_time)
+            }
+        };
+
+        let mut resource_buffers = vec![];
+
+        enum ResourceBuffer {
+            Storage {
+                binding: u32,
+                size: u64,
+                gpu_buffer: Buffer,
+                staging_buffer: Buffer,
             },
-            ResourceBuffer::Uniform {
-                binding, buffer, ..
-            } => BindGroupEntry {
-                binding: *binding,
-                resource: buffer.as_entire_binding(),
+            /// A uniform or read-only storage buffer, which isn't read back.
+            Input {
+                binding: u32,
+                buffer: Buffer,
             },
-        })
-        .collect::<Vec<_>>();
-
-    let bind_group = device.create_bind_group(&BindGroupDescriptor {
-        layout: &pipeline.get_bind_group_layout(0),
-        label: None,
-        entries: &bind_group_entries,
-    });
+            Texture {
+                binding: u32,
+                view: TextureView,
+            },
+            Sampler {
+                binding: u32,
+                sampler: Sampler,
+            },
+        }
 
-    let commands = {
-        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
-        {
-            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
-            pass.set_pipeline(&pipeline);
-            pass.set_bind_group(0, &bind_group, &[]);
-            pass.dispatch_workgroups(1, 1, 1);
+        for resource in &meta.resources {
+            let size = resource.size as u64;
+            match resource.kind {
+                ResourceKind::StorageBuffer => {
+                    let gpu_buffer = device.create_buffer(&BufferDescriptor {
+                        label: Some("Storage GPU Buffer"),
+                        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                        size,
+                        mapped_at_creation: resource.init.is_some(),
+                    });
+
+                    if let Some(init) = resource.init.as_deref() {
+                        gpu_buffer
+                            .slice(..)
+                            .get_mapped_range_mut()
+                            .copy_from_slice(init);
+                        gpu_buffer.unmap();
+                    }
+
+                    let staging_buffer = device.create_buffer(&BufferDescriptor {
+                        label: Some("Storage Staging Buffer"),
+                        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                        size,
+                        mapped_at_creation: false,
+                    });
+
+                    resource_buffers.push(ResourceBuffer::Storage {
+                        binding: resource.binding,
+                        size,
+                        gpu_buffer,
+                        staging_buffer,
+                    });
+                }
+                ResourceKind::UniformBuffer | ResourceKind::ReadOnlyStorageBuffer => {
+                    let (label, usage) = if resource.kind == ResourceKind::UniformBuffer {
+                        ("Uniform Buffer", BufferUsages::UNIFORM)
+                    } else {
+                        ("Read-only Storage Buffer", BufferUsages::STORAGE)
+                    };
+
+                    let buffer = device.create_buffer(&BufferDescriptor {
+                        label: Some(label),
+                        usage,
+                        size,
+                        mapped_at_creation: true,
+                    });
+
+                    if let Some(init) = resource.init.as_deref() {
+                        buffer
+                            .slice(..)
+                            .get_mapped_range_mut()
+                            .copy_from_slice(init);
+                    }
+
+                    buffer.unmap();
+
+                    resource_buffers.push(ResourceBuffer::Input {
+                        binding: resource.binding,
+                        buffer,
+                    });
+                }
+                ResourceKind::Texture(ref desc) => {
+                    resource_buffers.push(ResourceBuffer::Texture {
+                        binding: resource.binding,
+                        view: create_texture_view(device, queue, desc, resource.init.as_deref()),
+                    });
+                }
+                ResourceKind::Sampler { comparison } => {
+                    resource_buffers.push(ResourceBuffer::Sampler {
+                        binding: resource.binding,
+                        sampler: create_sampler(device, comparison),
+                    });
+                }
+            }
         }
 
-        for res in &resource_buffers {
-            if let ResourceBuffer::Storage {
-                size,
-                gpu_buffer,
-                staging_buffer,
-                ..
-            } = res
+        // Each resource has an entry in the resource buffers, so they can be matched up with
+        // their groups
+        let bind_groups = meta
+            .bind_groups()
+            .into_iter()
+            .map(|group| {
+                let entries = meta
+                    .resources
+                    .iter()
+                    .zip(&resource_buffers)
+                    .filter(|(resource, _)| resource.group == group)
+                    .map(|(_, res)| match res {
+                        ResourceBuffer::Storage {
+                            binding,
+                            gpu_buffer,
+                            ..
+                        } => BindGroupEntry {
+                            binding: *binding,
+                            resource: gpu_buffer.as_entire_binding(),
+                        },
+                        ResourceBuffer::Input {
+                            binding, buffer, ..
+                        } => BindGroupEntry {
+                            binding: *binding,
+                            resource: buffer.as_entire_binding(),
+                        },
+                        ResourceBuffer::Texture { binding, view } => BindGroupEntry {
+                            binding: *binding,
+                            resource: BindingResource::TextureView(view),
+                        },
+                        ResourceBuffer::Sampler { binding, sampler } => BindGroupEntry {
+                            binding: *binding,
+                            resource: BindingResource::Sampler(sampler),
+                        },
+                    })
+                    .collect::<Vec<_>>();
+
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    layout: &pipeline.get_bind_group_layout(group),
+                    label: None,
+                    entries: &entries,
+                });
+
+                (group, bind_group)
+            })
+            .collect::<Vec<_>>();
+
+        let commands = {
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
             {
-                encoder.copy_buffer_to_buffer(gpu_buffer, 0, staging_buffer, 0, *size);
+                let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+                pass.set_pipeline(&pipeline);
+                for (group, bind_group) in &bind_groups {
+                    pass.set_bind_group(*group, bind_group, &[]);
+                }
+                let [x, y, z] = meta.dispatch;
+                pass.dispatch_workgroups(x, y, z);
             }
-        }
 
-        encoder.finish()
-    };
+            for res in &resource_buffers {
+                if let ResourceBuffer::Storage {
+                    size,
+                    gpu_buffer,
+                    staging_buffer,
+                    ..
+                } = res
+                {
+                    encoder.copy_buffer_to_buffer(gpu_buffer, 0, staging_buffer, 0, *size);
+                }
+            }
 
-    let submission_index = queue.submit(std::iter::once(commands));
+            encoder.finish()
+        };
 
-    let mut pending_mappings = vec![];
+        let start = Instant::now();
+        let submission_index = queue.submit(std::iter::once(commands));
 
-    for res in &resource_buffers {
-        if let ResourceBuffer::Storage { staging_buffer, .. } = res {
-            let slice = staging_buffer.slice(..);
-            let (tx, rx) = futures::channel::oneshot::channel();
+        let mut pending_mappings = vec![];
 
-            slice.map_async(MapMode::Read, move |res| {
-                // ignore send errors if receiver dropped
-                let _ = tx.send(res);
-            });
+        for res in &resource_buffers {
+            if let ResourceBuffer::Storage { staging_buffer, .. } = res {
+                let slice = staging_buffer.slice(..);
+                let (tx, rx) = futures::channel::oneshot::channel();
+
+                slice.map_async(MapMode::Read, move |res| {
+                    // ignore send errors if receiver dropped
+                    let _ = tx.send(res);
+                });
 
-            pending_mappings.push((rx, slice, staging_buffer));
+                pending_mappings.push((rx, slice, staging_buffer));
+            }
         }
-    }
 
-    device.poll(Wait {
-        submission_index: Some(submission_index),
-        timeout: None,
-    })?;
+        let poll_result = device.poll(Wait {
+            submission_index: Some(submission_index),
+            timeout: None,
+        });
 
-    let mut results = vec![];
+        // Polling and mapping fail if the device was lost or an earlier operation failed, which is
+        // reported in preference to the errors from those
+        let check_device = || {
+            if let Some(message) = lost.lock().unwrap().take() {
+                return Err(BackendError::DeviceLost(message));
+            }
 
-    for (rx, slice, raw_buffer) in pending_mappings {
-        let map_result = rx.await?;
-        map_result?; // propagate mapping errors
+            match uncaptured.lock().unwrap().take() {
+                Some(error) => Err(error),
+                None => Ok(()),
+            }
+        };
 
-        let bytes = slice.get_mapped_range();
-        results.push(bytes.to_vec());
+        check_device()?;
+        poll_result?;
 
-        drop(bytes);
-        raw_buffer.unmap();
-    }
+        let mut results = vec![];
+
+        for (rx, slice, raw_buffer) in pending_mappings {
+            let map_result = rx.await?;
+            check_device()?;
+            map_result?; // propagate mapping errors
+
+            let bytes = slice.get_mapped_range();
+            results.push(bytes.to_vec());
+
+            drop(bytes);
+            raw_buffer.unmap();
+        }
 
-    Ok(results)
+        check_device()?;
+
+        Ok(ExecutionOutput {
+            buffers: results,
+            timings: Timings {
+                shader_module: shader_module_time,
+                pipeline: pipeline_time,
+                dispatch: start.elapsed(),
+            },
+            cache_hit: Some(cache_hit),
+        })
+    }
 }
@@ -0,0 +1,245 @@
+//! Long-lived execution processes, which run a stream of shaders on the same config so that the
+//! cost of starting a process and loading the driver is only paid once per config.
+
+use std::io::{BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use frontend::ExecutionError;
+use server_types::CacheStats;
+use types::ConfigId;
+
+use crate::cancel::{self, Cancellation};
+use crate::{ExecutionArgs, ExecutionOutput, HarnessHost, Outcome, WorkerOutput};
+
+/// Keeps the workers which are idle between executions. Workers that crash, time out or hang are
+/// dropped, and a new one is spawned the next time that their config is executed.
+#[derive(Default)]
+pub struct WorkerPool {
+    idle: Mutex<Vec<Worker>>,
+    watchdog: Option<Duration>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+impl WorkerPool {
+    pub fn new() -> WorkerPool {
+        WorkerPool::default()
+    }
+
+    /// Creates a pool whose workers are considered hung if an execution takes longer than
+    /// `watchdog`, even if it has no timeout or a longer one. A hung worker is killed, which tears
+    /// down its device, and the execution is reported as a hang.
+    pub fn with_watchdog(watchdog: Duration) -> WorkerPool {
+        WorkerPool {
+            watchdog: Some(watchdog),
+            ..WorkerPool::default()
+        }
+    }
+
+    /// Returns how often the workers reused a pipeline that they had already compiled. Only
+    /// successful executions on backends that cache pipelines are counted.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache_hits.load(Ordering::Relaxed),
+            misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn execute<Host: HarnessHost>(
+        &self,
+        config: &ConfigId,
+        args: ExecutionArgs,
+        timeout: Option<Duration>,
+        cancel: &Cancellation,
+    ) -> Result<Outcome, ExecutionError> {
+        let idle = {
+            let mut idle = self.idle.lock().expect("worker pool mutex poisoned");
+
+            // Workers may also have exited while they were idle, e.g. if they were killed
+            idle.retain_mut(|it| it.is_alive());
+
+            idle.iter()
+                .position(|it| it.config == *config)
+                .map(|index| idle.swap_remove(index))
+        };
+
+        let mut worker = match idle {
+            Some(worker) => worker,
+            None => Worker::spawn::<Host>(config)?,
+        };
+
+        let (limit, is_watchdog) = match (timeout, self.watchdog) {
+            (Some(timeout), Some(watchdog)) if watchdog < timeout => (Some(watchdog), true),
+            (None, Some(watchdog)) => (Some(watchdog), true),
+            (timeout, _) => (timeout, false),
+        };
+
+        let outcome = match worker.run(args, limit, cancel) {
+            Ok(Outcome::Timeout) if is_watchdog => Outcome::Hang,
+            Ok(outcome) => outcome,
+            Err(e) => {
+                // The worker may still be executing the shader, so it can't be reused
+                thread::spawn(move || drop(worker));
+                return Err(e);
+            }
+        };
+
+        if let Outcome::Success(ExecutionOutput {
+            cache_hit: Some(hit),
+            ..
+        }) = &outcome
+        {
+            let counter = if *hit {
+                &self.cache_hits
+            } else {
+                &self.cache_misses
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if !matches!(outcome, Outcome::Timeout | Outcome::Hang) && worker.is_alive() {
+            let mut idle = self.idle.lock().expect("worker pool mutex poisoned");
+            idle.push(worker);
+        } else {
+            // A worker whose device is hung may not exit until the driver gives up on it, so it is
+            // reaped on another thread instead of blocking the execution
+            thread::spawn(move || drop(worker));
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// An execution process which is started with `exec --worker`, and is killed when dropped.
+struct Worker {
+    config: ConfigId,
+    child: Child,
+    stdin: ChildStdin,
+    outputs: Receiver<WorkerOutput>,
+    stderr: Arc<Mutex<Vec<u8>>>,
+    stderr_reader: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    fn spawn<Host: HarnessHost>(config: &ConfigId) -> Result<Worker, ExecutionError> {
+        let mut child = Host::exec_command()
+            .arg("--worker")
+            .arg(config.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let mut stderr_pipe = child.stderr.take().unwrap();
+
+        // Outputs are read on another thread so that they can be waited for with a timeout. The
+        // channel is disconnected when the worker exits, e.g. because it crashed.
+        let (sender, outputs) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            while let Ok(output) =
+                bincode::decode_from_std_read(&mut stdout, bincode::config::standard())
+            {
+                if sender.send(output).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let stderr = Arc::new(Mutex::new(vec![]));
+        let stderr_reader = thread::spawn({
+            let stderr = stderr.clone();
+            move || {
+                let mut buffer = [0; 4096];
+                while let Ok(len @ 1..) = stderr_pipe.read(&mut buffer) {
+                    stderr.lock().unwrap().extend_from_slice(&buffer[..len]);
+                }
+            }
+        });
+
+        Ok(Worker {
+            config: config.clone(),
+            child,
+            stdin,
+            outputs,
+            stderr,
+            stderr_reader: Some(stderr_reader),
+        })
+    }
+
+    fn run(
+        &mut self,
+        args: ExecutionArgs,
+        timeout: Option<Duration>,
+        cancel: &Cancellation,
+    ) -> Result<Outcome, ExecutionError> {
+        // Anything that was logged by the previous execution belongs to it
+        self.stderr.lock().unwrap().clear();
+
+        let sent =
+            bincode::encode_into_std_write(args, &mut self.stdin, bincode::config::standard())
+                .is_ok()
+                && self.stdin.flush().is_ok();
+
+        // Writing only fails if the worker has exited, in which case it is reported as a crash
+        // once its output is closed
+        if !sent {
+            let _ = self.child.kill();
+        }
+
+        // The output is waited for in short slices, so that the worker can be killed if the run is
+        // cancelled
+        let start = Instant::now();
+        let output = loop {
+            let remaining = timeout.map(|it| it.saturating_sub(start.elapsed()));
+            if remaining == Some(Duration::ZERO) {
+                break Err(RecvTimeoutError::Timeout);
+            }
+
+            let slice = remaining.map_or(cancel::POLL_INTERVAL, |it| it.min(cancel::POLL_INTERVAL));
+            match self.outputs.recv_timeout(slice) {
+                Err(RecvTimeoutError::Timeout) => cancel.check()?,
+                output => break output,
+            }
+        };
+
+        Ok(match output {
+            Ok(WorkerOutput::Success(output)) => Outcome::Success(output),
+            Ok(WorkerOutput::Failure { exit_code, message }) => {
+                let mut stderr = std::mem::take(&mut *self.stderr.lock().unwrap());
+                writeln!(stderr, "{message}")?;
+                Outcome::Failure(Some(exit_code), stderr)
+            }
+            Err(RecvTimeoutError::Timeout) => Outcome::Timeout,
+            Err(RecvTimeoutError::Disconnected) => {
+                let status = self.child.wait()?;
+
+                // The worker has exited, so the rest of its stderr can be read to the end
+                if let Some(reader) = self.stderr_reader.take() {
+                    let _ = reader.join();
+                }
+
+                let stderr = std::mem::take(&mut *self.stderr.lock().unwrap());
+                Outcome::Failure(status.code(), stderr)
+            }
+        })
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
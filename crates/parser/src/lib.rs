@@ -4,7 +4,7 @@ use peeking_take_while::PeekableExt;
 use pest::iterators::Pair;
 use pest::prec_climber::{Assoc, Operator, PrecClimber};
 use pest::Parser;
-use rpds::HashTrieMap;
+use rpds::{HashTrieMap, HashTrieSet};
 use std::hash::Hash;
 use std::rc::Rc;
 use strum::IntoEnumIterator;
@@ -32,6 +32,9 @@ pub struct Environment {
     vars: HashTrieMap<String, DataType>,
     fns: HashTrieMap<String, Func>,
     types: HashTrieMap<String, Rc<StructDecl>>,
+    aliases: HashTrieMap<String, DataType>,
+    overrides: HashTrieSet<String>,
+    const_sizes: HashTrieMap<String, u32>,
 }
 
 fn builtins() -> HashTrieMap<String, Func> {
@@ -46,6 +49,9 @@ impl Environment {
             vars: HashTrieMap::new(),
             fns: builtins(),
             types: HashTrieMap::new(),
+            aliases: HashTrieMap::new(),
+            overrides: HashTrieSet::new(),
+            const_sizes: HashTrieMap::new(),
         }
     }
 
@@ -57,6 +63,81 @@ impl Environment {
         self.vars.insert_mut(name, ty);
     }
 
+    /// Returns the value of a constant that can be used as a size (e.g. an array element count).
+    pub fn const_size(&self, name: &str) -> Option<u32> {
+        self.const_sizes.get(name).copied()
+    }
+
+    /// Records the value of a constant if it is a non-negative integer, so that it can be used as a
+    /// size later on.
+    pub fn insert_const(&mut self, name: String, initializer: &ExprNode) {
+        let value = self.eval_size(initializer);
+
+        self.overrides.remove_mut(&name);
+
+        if let Some(value) = value {
+            self.const_sizes.insert_mut(name, value);
+        } else {
+            self.const_sizes.remove_mut(&name);
+        }
+    }
+
+    /// Evaluates an expression which may be used as a size. Only integer literals, other sizes,
+    /// arithmetic operators and calls to `min`, `max` and `clamp` are supported.
+    fn eval_size(&self, node: &ExprNode) -> Option<u32> {
+        match &node.expr {
+            Expr::Lit(Lit::I32(v)) => (*v).try_into().ok(),
+            Expr::Lit(Lit::U32(v)) => Some(*v),
+            Expr::Var(expr) => self.const_size(&expr.ident),
+            Expr::FnCall(expr) => {
+                let args = expr
+                    .args
+                    .iter()
+                    .map(|it| self.eval_size(it))
+                    .collect::<Option<Vec<_>>>()?;
+
+                match (expr.ident.as_str(), args.as_slice()) {
+                    ("min", [a, b]) => Some(*a.min(b)),
+                    ("max", [a, b]) => Some(*a.max(b)),
+                    ("clamp", [e, low, high]) if low <= high => Some(*e.clamp(low, high)),
+                    _ => None,
+                }
+            }
+            Expr::BinOp(expr) => {
+                let left = self.eval_size(&expr.left)?;
+                let right = self.eval_size(&expr.right)?;
+
+                match expr.op {
+                    BinOp::Plus => left.checked_add(right),
+                    BinOp::Minus => left.checked_sub(right),
+                    BinOp::Times => left.checked_mul(right),
+                    BinOp::Divide => left.checked_div(right),
+                    BinOp::Mod => left.checked_rem(right),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the given name refers to an override rather than a constant.
+    pub fn is_override(&self, name: &str) -> bool {
+        self.overrides.contains(name)
+    }
+
+    /// Records an override, whose default value can be used as a size in the same way as the
+    /// value of a constant.
+    pub fn insert_override(&mut self, name: String, initializer: Option<&ExprNode>) {
+        match initializer {
+            Some(initializer) => self.insert_const(name.clone(), initializer),
+            None => {
+                self.const_sizes.remove_mut(&name);
+            }
+        }
+
+        self.overrides.insert_mut(name);
+    }
+
     pub fn ty(&self, name: &str) -> Option<&Rc<StructDecl>> {
         self.types.get(name)
     }
@@ -65,6 +146,14 @@ impl Environment {
         self.types.insert_mut(name, decl);
     }
 
+    pub fn alias(&self, name: &str) -> Option<&DataType> {
+        self.aliases.get(name)
+    }
+
+    pub fn insert_alias(&mut self, name: String, ty: DataType) {
+        self.aliases.insert_mut(name, ty);
+    }
+
     pub fn func<'a>(
         &self,
         name: &str,
@@ -85,8 +174,15 @@ pub fn parse(input: &str) -> Module {
 }
 
 fn parse_translation_unit(pair: Pair<Rule>, env: &mut Environment) -> Module {
-    let decls = pair
-        .into_inner()
+    let mut pairs = pair.into_inner().peekable();
+
+    let directives = pairs
+        .by_ref()
+        .peeking_take_while(|pair| pair.as_rule() == Rule::global_directive)
+        .flat_map(parse_global_directive)
+        .collect::<Vec<_>>();
+
+    let decls = pairs
         .take_while(|pair| pair.as_rule() != Rule::EOI)
         .map(|pair| parse_global_decl(pair, env))
         .collect::<Vec<_>>();
@@ -94,11 +190,17 @@ fn parse_translation_unit(pair: Pair<Rule>, env: &mut Environment) -> Module {
     let mut functions = vec![];
     let mut structs = vec![];
     let mut consts = vec![];
+    let mut overrides = vec![];
+    let mut const_asserts = vec![];
     let mut vars = vec![];
 
     for decl in decls {
         match decl {
             GlobalDecl::Const(decl) => consts.push(decl),
+            GlobalDecl::Override(decl) => overrides.push(decl),
+            // Aliases are resolved while parsing, so they don't appear in the module.
+            GlobalDecl::TypeAlias => {}
+            GlobalDecl::ConstAssert(stmt) => const_asserts.push(stmt),
             GlobalDecl::Var(decl) => vars.push(decl),
             GlobalDecl::Struct(decl) => structs.push(decl),
             GlobalDecl::Fn(decl) => functions.push(decl),
@@ -106,15 +208,58 @@ fn parse_translation_unit(pair: Pair<Rule>, env: &mut Environment) -> Module {
     }
 
     Module {
+        directives,
         functions,
         structs,
         consts,
+        overrides,
+        const_asserts,
         vars,
     }
 }
 
+/// Parses a directive, which is split into one directive for each extension that it names.
+fn parse_global_directive(pair: Pair<Rule>) -> Vec<Directive> {
+    let pair = pair.into_inner().next().unwrap();
+    match pair.as_rule() {
+        Rule::enable_directive => pair
+            .into_inner()
+            .map(|pair| match pair.as_str() {
+                "subgroups" => Directive::Enable(Extension::Subgroups),
+                "f16" => Directive::Enable(Extension::F16),
+                name => Directive::Enable(Extension::Unknown(name.to_owned())),
+            })
+            .collect(),
+        Rule::requires_directive => pair
+            .into_inner()
+            .map(|pair| match pair.as_str() {
+                "packed_4x8_integer_dot_product" => {
+                    Directive::Requires(LanguageExtension::Packed4x8IntegerDotProduct)
+                }
+                name => Directive::Requires(LanguageExtension::Unknown(name.to_owned())),
+            })
+            .collect(),
+        Rule::diagnostic_directive => {
+            let mut pairs = pair.into_inner();
+            let severity = match pairs.next().unwrap().as_str() {
+                "error" => DiagnosticSeverity::Error,
+                "warning" => DiagnosticSeverity::Warning,
+                "info" => DiagnosticSeverity::Info,
+                "off" => DiagnosticSeverity::Off,
+                _ => unreachable!(),
+            };
+            let rule = pairs.next().unwrap().as_str().to_owned();
+            vec![Directive::Diagnostic(severity, rule)]
+        }
+        _ => unreachable!(),
+    }
+}
+
 enum GlobalDecl {
     Const(GlobalConstDecl),
+    Override(GlobalOverrideDecl),
+    TypeAlias,
+    ConstAssert(ConstAssertStatement),
     Var(GlobalVarDecl),
     Struct(Rc<StructDecl>),
     Fn(FnDecl),
@@ -123,7 +268,18 @@ enum GlobalDecl {
 fn parse_global_decl(pair: Pair<Rule>, env: &mut Environment) -> GlobalDecl {
     let pair = pair.into_inner().next().unwrap();
     match pair.as_rule() {
+        Rule::const_assert_statement => {
+            GlobalDecl::ConstAssert(parse_const_assert_statement(pair, env))
+        }
         Rule::global_constant_decl => GlobalDecl::Const(parse_global_const_decl(pair, env)),
+        Rule::global_override_decl => GlobalDecl::Override(parse_global_override_decl(pair, env)),
+        Rule::type_alias_decl => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str().to_owned();
+            let data_type = parse_type_decl(pairs.next().unwrap(), env);
+            env.insert_alias(name, data_type);
+            GlobalDecl::TypeAlias
+        }
         Rule::global_variable_decl => GlobalDecl::Var(parse_global_variable_decl(pair, env)),
         Rule::struct_decl => GlobalDecl::Struct(parse_struct_decl(pair, env)),
         Rule::function_decl => GlobalDecl::Fn(parse_function_decl(pair, env)),
@@ -145,9 +301,11 @@ fn parse_global_const_decl(pair: Pair<Rule>, env: &mut Environment) -> GlobalCon
     }
 
     let expr = parse_expression(pairs.next().unwrap(), env);
+    let expr = convert_initializer(expr, data_type.as_ref());
     let data_type = data_type.unwrap_or_else(|| expr.data_type.clone());
 
     env.insert_var(name.clone(), data_type.clone());
+    env.insert_const(name.clone(), &expr);
 
     GlobalConstDecl {
         name,
@@ -156,6 +314,62 @@ fn parse_global_const_decl(pair: Pair<Rule>, env: &mut Environment) -> GlobalCon
     }
 }
 
+fn parse_global_override_decl(pair: Pair<Rule>, env: &mut Environment) -> GlobalOverrideDecl {
+    let mut pairs = pair.into_inner().peekable();
+
+    let attrs = pairs
+        .by_ref()
+        .peeking_take_while(|pair| pair.as_rule() == Rule::attribute_list)
+        .flat_map(|pair| {
+            pair.into_inner().map(|pair| {
+                let mut pairs = pair.into_inner();
+                let name = pairs.next().unwrap().as_str();
+                let arg = pairs.next().unwrap();
+                match name {
+                    "id" => GlobalOverrideAttr::Id(
+                        parse_const_size(arg, env)
+                            .value()
+                            .expect("id must be an integer constant"),
+                    ),
+                    _ => panic!("invalid override attribute: {}", name),
+                }
+            })
+        })
+        .collect();
+
+    let name = pairs.next().unwrap().as_str().to_owned();
+    let mut data_type = None;
+
+    if let Some(pair) = pairs.peek() {
+        if pair.as_rule() == Rule::type_decl {
+            let pair = pairs.next().unwrap();
+            data_type = Some(parse_type_decl(pair, env));
+        }
+    }
+
+    let initializer = pairs
+        .next()
+        .map(|pair| convert_initializer(parse_expression(pair, env), data_type.as_ref()));
+
+    let data_type = data_type.unwrap_or_else(|| {
+        initializer
+            .as_ref()
+            .expect("override declaration must have type or initializer")
+            .data_type
+            .clone()
+    });
+
+    env.insert_var(name.clone(), data_type.clone());
+    env.insert_override(name.clone(), initializer.as_ref());
+
+    GlobalOverrideDecl {
+        attrs,
+        name,
+        data_type,
+        initializer,
+    }
+}
+
 fn parse_global_variable_decl(pair: Pair<Rule>, env: &mut Environment) -> GlobalVarDecl {
     let mut pairs = pair.into_inner().peekable();
 
@@ -185,12 +399,7 @@ fn parse_global_variable_decl(pair: Pair<Rule>, env: &mut Environment) -> Global
 
             let access_mode = if matches!(pairs.peek(), Some(access_mode) if access_mode.as_rule() == Rule::access_mode)
             {
-                Some(match pairs.next().unwrap().as_str() {
-                    "read" => AccessMode::Read,
-                    "write" => AccessMode::Write,
-                    "read_write" => AccessMode::ReadWrite,
-                    _ => unreachable!(),
-                })
+                Some(parse_access_mode(pairs.next().unwrap()))
             } else {
                 None
             };
@@ -202,7 +411,6 @@ fn parse_global_variable_decl(pair: Pair<Rule>, env: &mut Environment) -> Global
         }
     }
 
-    let qualifier = qualifier.expect("module scope var declaration must specify storage class");
     let name = pairs.next().unwrap().as_str().to_owned();
     let mut data_type = None;
     let mut expr = None;
@@ -216,7 +424,10 @@ fn parse_global_variable_decl(pair: Pair<Rule>, env: &mut Environment) -> Global
 
     if pairs.peek().is_some() {
         let pair = pairs.next().unwrap();
-        expr = Some(parse_expression(pair, env))
+        expr = Some(convert_initializer(
+            parse_expression(pair, env),
+            data_type.as_ref(),
+        ))
     }
 
     let data_type = data_type.unwrap_or_else(|| {
@@ -226,16 +437,32 @@ fn parse_global_variable_decl(pair: Pair<Rule>, env: &mut Environment) -> Global
             .clone()
     });
 
-    let mut ref_view = MemoryViewType::new(data_type.clone(), qualifier.storage_class);
-    if let Some(access_mode) = qualifier.access_mode {
-        ref_view.access_mode = access_mode;
-    }
+    // Textures and samplers are declared without a storage class, and aren't accessed through a
+    // reference.
+    let var_type = match &qualifier {
+        Some(qualifier) => {
+            let mut ref_view = MemoryViewType::new(data_type.clone(), qualifier.storage_class);
+            if let Some(access_mode) = qualifier.access_mode {
+                ref_view.access_mode = access_mode;
+            }
 
-    env.insert_var(name.clone(), DataType::Ref(ref_view));
+            DataType::Ref(ref_view)
+        }
+        None => {
+            assert!(
+                data_type.is_handle(),
+                "module scope var declaration must specify storage class"
+            );
+
+            data_type.clone()
+        }
+    };
+
+    env.insert_var(name.clone(), var_type);
 
     GlobalVarDecl {
         attrs,
-        qualifier: Some(qualifier),
+        qualifier,
         name,
         data_type,
         initializer: expr,
@@ -259,6 +486,7 @@ fn parse_struct_decl(pair: Pair<Rule>, env: &mut Environment) -> Rc<StructDecl>
                         let arg = pairs.next().unwrap().as_str();
                         match name {
                             "align" => StructMemberAttr::Align(arg.parse().unwrap()),
+                            "size" => StructMemberAttr::Size(arg.parse().unwrap()),
                             _ => panic!("invalid struct member attribute: {}", name),
                         }
                     })
@@ -300,11 +528,7 @@ fn parse_function_decl(pair: Pair<Rule>, env: &mut Environment) -> FnDecl {
                         _ => panic!("invalid argument for stage attr"),
                     }),
                     "workgroup_size" => FnAttr::WorkgroupSize(
-                        match parse_literal_expression(pairs.next().unwrap()).expr {
-                            Expr::Lit(Lit::I32(v)) => v.try_into().unwrap(),
-                            Expr::Lit(Lit::U32(v)) => v,
-                            _ => panic!("invalid argument for workgroup_size attr"),
-                        },
+                        pairs.map(|pair| parse_const_size(pair, env)).collect(),
                     ),
                     _ => panic!("invalid function attribute: {}", name),
                 }
@@ -317,11 +541,38 @@ fn parse_function_decl(pair: Pair<Rule>, env: &mut Environment) -> FnDecl {
         .by_ref()
         .peeking_take_while(|pair| pair.as_rule() == Rule::param)
         .map(|pair| {
-            let mut pairs = pair.into_inner();
+            let mut pairs = pair.into_inner().peekable();
+
+            let attrs = pairs
+                .by_ref()
+                .peeking_take_while(|pair| pair.as_rule() == Rule::attribute_list)
+                .flat_map(|pair| {
+                    pair.into_inner().map(|pair| {
+                        let mut pairs = pair.into_inner();
+                        let name = pairs.next().unwrap().as_str();
+                        match name {
+                            "builtin" => {
+                                FnInputAttr::Builtin(match pairs.next().unwrap().as_str() {
+                                    "local_invocation_id" => BuiltinValue::LocalInvocationId,
+                                    "local_invocation_index" => BuiltinValue::LocalInvocationIndex,
+                                    "global_invocation_id" => BuiltinValue::GlobalInvocationId,
+                                    "workgroup_id" => BuiltinValue::WorkgroupId,
+                                    "num_workgroups" => BuiltinValue::NumWorkgroups,
+                                    "subgroup_invocation_id" => BuiltinValue::SubgroupInvocationId,
+                                    "subgroup_size" => BuiltinValue::SubgroupSize,
+                                    value => panic!("invalid builtin value: {}", value),
+                                })
+                            }
+                            _ => panic!("invalid function parameter attribute: {}", name),
+                        }
+                    })
+                })
+                .collect();
+
             let name = pairs.next().unwrap().as_str().to_owned();
             let data_type = parse_type_decl(pairs.next().unwrap(), env);
             FnInput {
-                attrs: vec![],
+                attrs,
                 name,
                 data_type,
             }
@@ -366,8 +617,16 @@ fn parse_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
 
     match pair.as_rule() {
         Rule::let_statement => parse_let_statement(pair, env),
+        Rule::const_statement => parse_const_statement(pair, env),
+        Rule::const_assert_statement => parse_const_assert_statement(pair, env).into(),
         Rule::var_statement => parse_var_statement(pair, env),
         Rule::assignment_statement => parse_assignment_statement(pair, env),
+        Rule::increment_statement => {
+            Statement::Increment(parse_lhs_expression(pair.into_inner().next().unwrap(), env))
+        }
+        Rule::decrement_statement => {
+            Statement::Decrement(parse_lhs_expression(pair.into_inner().next().unwrap(), env))
+        }
         Rule::compound_statement => parse_compound_statement(pair, env),
         Rule::if_statement => parse_if_statement(pair, env),
         Rule::return_statement => parse_return_statement(pair, env),
@@ -377,6 +636,7 @@ fn parse_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
         Rule::fallthrough_statement => Statement::Fallthrough,
         Rule::switch_statement => parse_switch_statement(pair, env),
         Rule::for_statement => parse_for_statement(pair, env),
+        Rule::while_statement => parse_while_statement(pair, env),
         Rule::call_statement => parse_call_statement(pair, env),
         _ => unreachable!(),
     }
@@ -385,12 +645,41 @@ fn parse_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
 fn parse_let_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
     let mut pairs = pair.into_inner();
     let ident = pairs.next().unwrap().as_str().to_owned();
-    let initializer = parse_expression(pairs.next().unwrap(), env);
+
+    let mut pair = pairs.next().unwrap();
+    let mut data_type = None;
+    if pair.as_rule() == Rule::type_decl {
+        data_type = Some(parse_type_decl(pair, env));
+        pair = pairs.next().unwrap();
+    }
+
+    let initializer = convert_initializer(parse_expression(pair, env), data_type.as_ref());
     let stmt = LetDeclStatement::new(ident.clone(), initializer);
     env.insert_var(ident, stmt.inferred_type().clone());
     stmt.into()
 }
 
+fn parse_const_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
+    let mut pairs = pair.into_inner();
+    let ident = pairs.next().unwrap().as_str().to_owned();
+
+    let mut pair = pairs.next().unwrap();
+    let mut data_type = None;
+    if pair.as_rule() == Rule::type_decl {
+        data_type = Some(parse_type_decl(pair, env));
+        pair = pairs.next().unwrap();
+    }
+
+    let initializer = convert_initializer(parse_expression(pair, env), data_type.as_ref());
+    env.insert_var(ident.clone(), initializer.data_type.clone());
+    env.insert_const(ident.clone(), &initializer);
+    ConstDeclStatement::new(ident, initializer).into()
+}
+
+fn parse_const_assert_statement(pair: Pair<Rule>, env: &Environment) -> ConstAssertStatement {
+    ConstAssertStatement::new(parse_expression(pair.into_inner().next().unwrap(), env))
+}
+
 fn parse_var_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
     let mut pairs = pair.into_inner();
     let ident = pairs.next().unwrap().as_str().to_owned();
@@ -406,7 +695,10 @@ fn parse_var_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
     };
 
     let initializer = if let Some(Rule::expression) = pair.as_ref().map(|it| it.as_rule()) {
-        Some(parse_expression(pair.unwrap(), env))
+        Some(convert_initializer(
+            parse_expression(pair.unwrap(), env),
+            specified_type.as_ref(),
+        ))
     } else {
         None
     };
@@ -438,6 +730,8 @@ fn parse_assignment_statement(pair: Pair<Rule>, env: &Environment) -> Statement
             Rule::op_and_equal => AssignmentOp::And,
             Rule::op_or_equal => AssignmentOp::Or,
             Rule::op_xor_equal => AssignmentOp::Xor,
+            Rule::op_lshift_equal => AssignmentOp::LShift,
+            Rule::op_rshift_equal => AssignmentOp::RShift,
             _ => unreachable!(),
         },
         _ => unreachable!(),
@@ -498,9 +792,42 @@ fn parse_return_statement(pair: Pair<Rule>, env: &Environment) -> Statement {
 }
 
 fn parse_loop_statement(pair: Pair<Rule>, env: &Environment) -> Statement {
+    // The continuing block is in the same scope as the loop body.
+    let mut inner_env = env.clone();
+    let mut body = vec![];
+    let mut continuing = None;
+
+    for pair in pair.into_inner() {
+        if pair.as_rule() == Rule::continuing_statement {
+            continuing = Some(parse_continuing_statement(pair, &mut inner_env));
+        } else {
+            body.push(parse_statement(pair, &mut inner_env));
+        }
+    }
+
+    LoopStatement::new(body).with_continuing(continuing).into()
+}
+
+fn parse_continuing_statement(pair: Pair<Rule>, env: &mut Environment) -> ContinuingStatement {
+    let mut body = vec![];
+    let mut break_if = None;
+
+    for pair in pair.into_inner() {
+        if pair.as_rule() == Rule::break_if_statement {
+            break_if = Some(parse_expression(pair.into_inner().next().unwrap(), env));
+        } else {
+            body.push(parse_statement(pair, env));
+        }
+    }
+
+    ContinuingStatement::new(body, break_if)
+}
+
+fn parse_while_statement(pair: Pair<Rule>, env: &Environment) -> Statement {
     let mut pairs = pair.into_inner();
+    let condition = parse_paren_expression(pairs.next().unwrap(), env);
     let block = parse_compound_statement(pairs.next().unwrap(), env).into_compount_statement();
-    LoopStatement::new(block).into()
+    WhileStatement::new(condition, block).into()
 }
 
 fn parse_switch_statement(pair: Pair<Rule>, env: &Environment) -> Statement {
@@ -512,16 +839,25 @@ fn parse_switch_statement(pair: Pair<Rule>, env: &Environment) -> Statement {
     let mut default = None;
 
     for pair in pairs {
-        let mut pairs = pair.into_inner();
-        let pair = pairs.next().unwrap();
-
-        if pair.as_rule() == Rule::expression {
-            let selector = parse_expression(pair, env);
-            let body =
-                parse_compound_statement(pairs.next().unwrap(), env).into_compount_statement();
-            cases.push(SwitchCase { selector, body });
+        let mut selectors = pair.into_inner().collect::<Vec<_>>();
+        let body = selectors.pop().unwrap();
+
+        // A clause with several selectors is split into one case for each selector, with a copy
+        // of the body. If one of the selectors is `default`, the others are redundant since they
+        // would run the same body anyway.
+        if selectors.is_empty()
+            || selectors
+                .iter()
+                .any(|it| it.as_rule() == Rule::case_default)
+        {
+            default = Some(parse_compound_statement(body, env).into_compount_statement());
         } else {
-            default = Some(parse_compound_statement(pair, env).into_compount_statement());
+            for selector in selectors {
+                cases.push(SwitchCase {
+                    selector: parse_expression(selector, env),
+                    body: parse_compound_statement(body.clone(), env).into_compount_statement(),
+                });
+            }
         }
     }
 
@@ -558,7 +894,9 @@ fn parse_for_statement(pair: Pair<Rule>, env: &mut Environment) -> Statement {
             Statement::Assignment(stmt) => {
                 update = Some(ForLoopUpdate::Assignment(stmt));
             }
-            _ => panic!("only assignment statement is currently supported in for loop init"),
+            Statement::Increment(lhs) => update = Some(ForLoopUpdate::Increment(lhs)),
+            Statement::Decrement(lhs) => update = Some(ForLoopUpdate::Decrement(lhs)),
+            _ => panic!("unsupported statement in for loop update"),
         };
         pair = pairs.next().unwrap();
     }
@@ -728,6 +1066,7 @@ fn parse_primary_expression(pair: Pair<Rule>, env: &Environment) -> ExprNode {
     match pair.as_rule() {
         Rule::literal_expression => parse_literal_expression(pair),
         Rule::type_cons_expression => parse_type_cons_expression(pair, env),
+        Rule::bitcast_expression => parse_bitcast_expression(pair, env),
         Rule::call_expression => parse_call_expression(pair, env),
         Rule::var_expression => parse_var_expression(pair, env),
         Rule::paren_expression => parse_paren_expression(pair, env),
@@ -736,18 +1075,67 @@ fn parse_primary_expression(pair: Pair<Rule>, env: &Environment) -> ExprNode {
     }
 }
 
+fn parse_const_size(pair: Pair<Rule>, env: &Environment) -> ConstSize {
+    match pair.as_rule() {
+        Rule::literal_expression => match parse_literal_expression(pair).expr {
+            Expr::Lit(Lit::I32(v)) => ConstSize::Lit(v.try_into().unwrap()),
+            Expr::Lit(Lit::U32(v)) => ConstSize::Lit(v),
+            _ => panic!("size must be an integer"),
+        },
+        Rule::ident => {
+            let name = pair.as_str();
+
+            // The default value of an override without an initializer is unknown, as is the value
+            // of a constant whose initializer can't be evaluated here.
+            if env.is_override(name) {
+                ConstSize::Override(name.to_owned(), env.const_size(name))
+            } else if let Some(value) = env.const_size(name) {
+                ConstSize::Const(name.to_owned(), value)
+            } else {
+                let data_type = env.var(name).cloned().unwrap_or(ScalarType::U32.into());
+                ConstSize::Expr(Box::new(VarExpr::new(name).into_node(data_type)), None)
+            }
+        }
+        Rule::expression => {
+            let expr = convert_size_literals(parse_expression(pair, env));
+            let value = env.eval_size(&expr);
+            ConstSize::Expr(Box::new(expr), value)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Converts integer literals in a size expression to the type of the other operand, so that e.g.
+/// the `2` in `array<u32, n * 2>` is written as `2u` rather than `2i`.
+fn convert_size_literals(node: ExprNode) -> ExprNode {
+    let Expr::BinOp(expr) = node.expr else {
+        return node;
+    };
+
+    let mut left = convert_size_literals(*expr.left);
+    let mut right = convert_size_literals(*expr.right);
+
+    if matches!(right.expr, Expr::Lit(_)) {
+        right = convert_initializer(right, Some(&left.data_type));
+    } else if matches!(left.expr, Expr::Lit(_)) {
+        left = convert_initializer(left, Some(&right.data_type));
+    }
+
+    BinOpExpr::new(expr.op, left, right).into()
+}
+
 fn parse_literal_expression(pair: Pair<Rule>) -> ExprNode {
     let pair = pair.into_inner().next().unwrap();
     let (t, lit) = match pair.as_rule() {
         Rule::bool_literal => (ScalarType::Bool, Lit::Bool(pair.as_str().parse().unwrap())),
         Rule::uint_literal => (
             ScalarType::U32,
-            Lit::U32(pair.as_str().trim_end_matches('u').parse().unwrap()),
+            Lit::U32(parse_int(pair.as_str().trim_end_matches('u'))),
         ),
         Rule::int_literal => (
             ScalarType::I32,
             Lit::I32(if !pair.as_str().ends_with(')') {
-                pair.as_str().trim_end_matches('i').parse().unwrap()
+                parse_int(pair.as_str().trim_end_matches('i'))
             } else {
                 pair.as_str()
                     .trim_start_matches("i32(")
@@ -756,6 +1144,10 @@ fn parse_literal_expression(pair: Pair<Rule>) -> ExprNode {
                     .unwrap()
             }),
         ),
+        Rule::float_literal if pair.as_str().ends_with('h') => (
+            ScalarType::F16,
+            Lit::F16(pair.as_str().trim_end_matches('h').parse().unwrap()),
+        ),
         Rule::float_literal => (
             ScalarType::F32,
             Lit::F32(pair.as_str().trim_end_matches('f').parse().unwrap()),
@@ -769,16 +1161,109 @@ fn parse_literal_expression(pair: Pair<Rule>) -> ExprNode {
     }
 }
 
+/// Parses a decimal or hexadecimal integer literal, without its suffix.
+fn parse_int<T: TryFrom<i64>>(literal: &str) -> T {
+    let (negative, literal) = match literal.strip_prefix('-') {
+        Some(literal) => (true, literal),
+        None => (false, literal),
+    };
+
+    let value = match literal
+        .strip_prefix("0x")
+        .or_else(|| literal.strip_prefix("0X"))
+    {
+        Some(digits) => i64::from_str_radix(digits, 16),
+        None => literal.parse(),
+    }
+    .unwrap_or_else(|_| panic!("invalid integer literal: {}", literal));
+
+    let value = if negative { -value } else { value };
+    value
+        .try_into()
+        .unwrap_or_else(|_| panic!("integer literal out of range: {}", value))
+}
+
 fn parse_type_cons_expression(pair: Pair<Rule>, env: &Environment) -> ExprNode {
     let mut pairs = pair.into_inner();
     let t_decl = pairs.next().unwrap();
+    let args = pairs
+        .map(|pair| parse_expression(pair, env))
+        .collect::<Vec<_>>();
 
-    let t = parse_type_decl(t_decl, env);
-    let args = pairs.map(|pair| parse_expression(pair, env)).collect();
+    let t = if t_decl.as_rule() == Rule::t_vec_inferred {
+        let n = t_decl.as_str().trim_start_matches("vec").parse().unwrap();
+        DataType::Vector(n, infer_component_type(&args))
+    } else {
+        parse_type_decl(t_decl, env)
+    };
 
     TypeConsExpr::new(t, args).into()
 }
 
+/// Infers the component type of a vector constructor such as `vec3(...)` from its arguments.
+///
+/// Literals are typed as concrete values by the parser, so the type of the first argument which
+/// isn't a literal is used if there is one. Otherwise the arguments must all be literals, and the
+/// constructor is typed as `f32` if any of them is a float.
+fn infer_component_type(args: &[ExprNode]) -> ScalarType {
+    let is_lit = |arg: &&ExprNode| matches!(arg.expr, Expr::Lit(_));
+
+    if let Some(arg) = args.iter().find(|it| !is_lit(it)) {
+        return arg
+            .data_type
+            .as_scalar()
+            .expect("invalid vector component type");
+    }
+
+    let types = args.iter().filter_map(|it| it.data_type.as_scalar());
+    types
+        .clone()
+        .find(|it| *it == ScalarType::F32)
+        .or_else(|| types.clone().next())
+        .expect("vector constructor must have arguments")
+}
+
+/// Converts the initializer of a declaration to the declared type, if it has one.
+///
+/// Literals are typed as concrete values by the parser, rather than as abstract numerics, so an
+/// initializer such as the `1` in `let x: f32 = 1;` must be explicitly converted to be valid.
+/// Integer literals are converted directly, so that they can still be used as sizes.
+fn convert_initializer(initializer: ExprNode, data_type: Option<&DataType>) -> ExprNode {
+    let Some(ty @ (DataType::Scalar(_) | DataType::Vector(..))) = data_type else {
+        return initializer;
+    };
+
+    if *ty == initializer.data_type {
+        return initializer;
+    }
+
+    let lit = match (&initializer.expr, ty) {
+        (Expr::Lit(Lit::I32(v)), DataType::Scalar(ScalarType::U32)) => {
+            u32::try_from(*v).ok().map(Lit::U32)
+        }
+        (Expr::Lit(Lit::I32(v)), DataType::Scalar(ScalarType::F32)) => Some(Lit::F32(*v as f32)),
+        (Expr::Lit(Lit::I32(v)), DataType::Scalar(ScalarType::F16)) => Some(Lit::F16(*v as f32)),
+        _ => None,
+    };
+
+    match lit {
+        Some(lit) => ExprNode {
+            data_type: ty.clone(),
+            expr: Expr::Lit(lit),
+        },
+        None => TypeConsExpr::new(ty.clone(), vec![initializer]).into(),
+    }
+}
+
+fn parse_bitcast_expression(pair: Pair<Rule>, env: &Environment) -> ExprNode {
+    let mut pairs = pair.into_inner();
+
+    let t = parse_type_decl(pairs.next().unwrap(), env);
+    let inner = parse_paren_expression(pairs.next().unwrap(), env);
+
+    BitcastExpr::new(t, inner).into()
+}
+
 fn parse_call_expression(pair: Pair<Rule>, env: &Environment) -> ExprNode {
     let mut pairs = pair.into_inner();
 
@@ -826,30 +1311,81 @@ fn parse_type_decl(pair: Pair<Rule>, env: &Environment) -> DataType {
                 Rule::t_vec2 => 2,
                 Rule::t_vec3 => 3,
                 Rule::t_vec4 => 4,
+                Rule::t_vec_alias => {
+                    // Aliases are written as e.g. `vec3f`, with the size followed by the suffix of
+                    // the component type.
+                    let alias = t_vector.as_str().trim_start_matches("vec");
+                    let t = match &alias[1..] {
+                        "i" => ScalarType::I32,
+                        "u" => ScalarType::U32,
+                        "f" => ScalarType::F32,
+                        "h" => ScalarType::F16,
+                        _ => unreachable!(),
+                    };
+
+                    return DataType::Vector(alias[..1].parse().unwrap(), t);
+                }
                 _ => unreachable!(),
             };
 
             DataType::Vector(n, parse_t_scalar(t_vector.into_inner().next().unwrap()))
         }
+        Rule::texture_type_decl => {
+            let mut pairs = pair.into_inner();
+            let t_texture = pairs.next().unwrap();
+            let dim = match t_texture
+                .as_str()
+                .trim_start_matches("texture_")
+                .trim_start_matches("depth_")
+            {
+                "1d" => TextureDimension::D1,
+                "2d" => TextureDimension::D2,
+                "2d_array" => TextureDimension::D2Array,
+                "3d" => TextureDimension::D3,
+                "cube" => TextureDimension::Cube,
+                "cube_array" => TextureDimension::CubeArray,
+                _ => unreachable!(),
+            };
+
+            match t_texture.as_rule() {
+                Rule::t_texture => DataType::Texture(dim, parse_t_scalar(pairs.next().unwrap())),
+                Rule::t_depth_texture => DataType::DepthTexture(dim),
+                _ => unreachable!(),
+            }
+        }
+        Rule::t_sampler => match pair.as_str() {
+            "sampler" => DataType::Sampler,
+            "sampler_comparison" => DataType::SamplerComparison,
+            _ => unreachable!(),
+        },
+        Rule::t_atomic => DataType::Atomic(pair.into_inner().next().unwrap().as_rule().into()),
         Rule::array_type_decl => {
             let mut pairs = pair.into_inner();
             let pair = pairs.next().unwrap();
             DataType::Array(
                 Rc::new(parse_type_decl(pair, env)),
-                pairs.next().map(|it| it.as_str().parse().unwrap()),
+                pairs.next().map(|it| parse_const_size(it, env)),
             )
         }
         Rule::ptr_type_decl => {
             let mut pairs = pair.into_inner();
             let storage_class = parse_storage_class(pairs.next().unwrap());
             let inner = parse_type_decl(pairs.next().unwrap(), env);
-            DataType::Ptr(MemoryViewType::new(inner, storage_class))
+            let mut view = MemoryViewType::new(inner, storage_class);
+            if let Some(pair) = pairs.next() {
+                view.access_mode = parse_access_mode(pair);
+            }
+
+            DataType::Ptr(view)
         }
-        Rule::ident => DataType::Struct(
-            env.ty(pair.as_str())
-                .unwrap_or_else(|| panic!("type not found: {}", pair.as_str()))
-                .clone(),
-        ),
+        Rule::ident => match env.alias(pair.as_str()) {
+            Some(ty) => ty.clone(),
+            None => DataType::Struct(
+                env.ty(pair.as_str())
+                    .unwrap_or_else(|| panic!("type not found: {}", pair.as_str()))
+                    .clone(),
+            ),
+        },
         _ => panic!("{}", pair),
     }
 }
@@ -867,6 +1403,15 @@ fn parse_paren_expression(pair: Pair<Rule>, env: &Environment) -> ExprNode {
     parse_expression(pair, env)
 }
 
+fn parse_access_mode(pair: Pair<Rule>) -> AccessMode {
+    match pair.as_str() {
+        "read" => AccessMode::Read,
+        "write" => AccessMode::Write,
+        "read_write" => AccessMode::ReadWrite,
+        _ => unreachable!(),
+    }
+}
+
 fn parse_storage_class(pair: Pair<Rule>) -> StorageClass {
     match pair.as_str() {
         "function" => StorageClass::Function,
@@ -911,6 +1456,7 @@ impl From<Rule> for ScalarType {
             Rule::t_i32 => ScalarType::I32,
             Rule::t_u32 => ScalarType::U32,
             Rule::t_f32 => ScalarType::F32,
+            Rule::t_f16 => ScalarType::F16,
             _ => unreachable!(),
         }
     }
@@ -936,15 +1482,39 @@ mod tests {
         };
     }
 
+    test_case!(assignments);
+    test_case!(atomics);
+    test_case!(barriers);
+    test_case!(bitcasts);
+    test_case!(builtin_structs);
     test_case!(calls);
+    test_case!(const_asserts);
+    test_case!(consts);
+    test_case!(directives);
+    test_case!(f16);
     test_case!(floats);
+    test_case!(invocation_ids);
+    test_case!(layouts);
     test_case!(loops);
+    test_case!(overrides);
+    test_case!(packed_dot_products);
     test_case!(ptrs);
     test_case!(structs);
+    test_case!(texture_gather);
+    test_case!(textures);
+    test_case!(unknown_directives);
+    test_case!(user_shader);
+    test_case!(workgroup_size);
 
     test_case!(test_1);
     test_case!(test_2);
     test_case!(test_3);
     test_case!(test_4);
     test_case!(test_5);
+
+    #[test]
+    fn unknown_diagnostic_severity_is_an_error() {
+        let src = "diagnostic(fatal, derivative_uniformity);";
+        assert!(WGSLParser::parse(Rule::translation_unit, src).is_err());
+    }
 }
@@ -0,0 +1,21 @@
+var<private> a: i32;
+var<private> b: vec2<u32>;
+var<private> _c: i32;
+
+@compute @workgroup_size(1)
+fn main() {
+    var x = 1.0f;
+    a += 2i;
+    a -= 1i;
+    x *= 2.0f;
+    b |= vec2<u32>(1u);
+    b.x <<= 2u;
+    a >>= 1u;
+    _ = x + 1.0f;
+    _c = a;
+    a++;
+    b.y--;
+    for (var i = 0i; i < 4i; i++) {
+        a--;
+    }
+}
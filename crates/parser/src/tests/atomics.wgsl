@@ -0,0 +1,17 @@
+struct Counters {
+    a: atomic<u32>,
+    b: atomic<i32>,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> counters: Counters;
+
+var<workgroup> w: array<atomic<u32>, 2>;
+
+@compute @workgroup_size(1)
+fn main() {
+    atomicAdd(&counters.a, 1u);
+    let x = atomicMax(&counters.b, 2i);
+    atomicStore(&w[0], 3u);
+    let y = atomicLoad(&w[1]) + atomicExchange(&counters.a, 4u);
+}
@@ -0,0 +1,11 @@
+var<workgroup> workgroup0: vec2<u32>;
+
+@compute
+@workgroup_size(4)
+fn main() {
+    workgroupBarrier();
+    workgroup0 = vec2<u32>(1u, 2u);
+    let x = workgroupUniformLoad(&workgroup0);
+    storageBarrier();
+    let y = workgroupUniformLoad(&workgroup0).y + x.x;
+}
@@ -0,0 +1,5 @@
+fn main() {
+    var x: u32 = bitcast<u32>(1.0f);
+    var y: vec2<f32> = bitcast<vec2<f32>>(vec2<i32>(1i, -2i));
+    let z = bitcast<i32>(x) + 1i;
+}
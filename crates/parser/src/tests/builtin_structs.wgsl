@@ -0,0 +1,7 @@
+@compute @workgroup_size(1)
+fn main() {
+    let a = frexp(1.5f).fract + modf(-2.25f).whole;
+    let b = frexp(vec3<f32>(1f, 2f, 3f)).exp;
+    let c = modf(vec2<f32>(0.5f, 4.75f));
+    let d = c.fract.x;
+}
@@ -0,0 +1,10 @@
+const x: i32 = 3i;
+const y = true;
+
+const_assert x == 3i;
+const_assert (x >= 1i) && y;
+
+fn main() {
+    const z = 2u;
+    const_assert(z <= 2u);
+}
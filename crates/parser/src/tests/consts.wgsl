@@ -0,0 +1,18 @@
+const N: u32 = 4u;
+const K = 2i;
+
+var<private> a: array<i32, N>;
+
+@compute @workgroup_size(N)
+fn main() {
+    const m = 3i;
+    switch (K) {
+        case K: {
+            a[0] = m;
+        }
+        case m: {
+        }
+        default: {
+        }
+    }
+}
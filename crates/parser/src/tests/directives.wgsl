@@ -0,0 +1,8 @@
+enable subgroups;
+diagnostic(off, subgroup_uniformity);
+
+fn main() {
+    let x = subgroupAdd(1u);
+    let y = subgroupBroadcastFirst(subgroupMax(vec2<i32>(1, 2)));
+    let z = any(subgroupBallot(subgroupAny(true)) != vec4<u32>(0u));
+}
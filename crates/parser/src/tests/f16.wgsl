@@ -0,0 +1,9 @@
+enable f16;
+
+fn main() {
+    var x: f16 = 1.5h;
+    x = 2h;
+    x = -0.25h;
+    let v = vec2<f16>(x, 3.0h);
+    let y = f32(v.x) + 1.5f;
+}
@@ -0,0 +1,4 @@
+@compute @workgroup_size(4, 2)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_index) local_index: u32) {
+    let x = global_id.x + local_index;
+}
@@ -0,0 +1,17 @@
+struct Inner {
+    a: vec3<f32>,
+    @size(8) b: u32,
+}
+
+struct Outer {
+    @align(32) a: Inner,
+    b: array<Inner, 2>,
+    @align(16) @size(20) c: i32,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> s_output: Outer;
+
+fn main() {
+    s_output.b[1].b = s_output.a.b;
+}
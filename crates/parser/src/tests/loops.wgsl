@@ -3,4 +3,17 @@ fn main() {
         var x = 0;
         break;
     }
+
+    loop {
+        var i = 0;
+        continuing {
+            i = i + 1;
+            break if i >= 4;
+        }
+    }
+
+    var j = 0;
+    while (j < 4) {
+        j++;
+    }
 }
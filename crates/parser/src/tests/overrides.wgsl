@@ -0,0 +1,18 @@
+override wg: u32 = 64u;
+override n = 8u;
+override m: u32 = clamp(n, 1u, 4u);
+override depth: u32;
+
+var<workgroup> arr: array<u32, n>;
+var<workgroup> small: array<i32, m>;
+var<workgroup> doubled: array<u32, n * 2u>;
+
+@compute @workgroup_size(wg, 1, depth)
+fn main(@builtin(local_invocation_index) idx: u32) {
+    arr[idx] = idx;
+    small[idx] = i32(idx);
+    doubled[idx] = arr[idx];
+    for (var i = 0u; i < n; i++) {
+        arr[i] += 1u;
+    }
+}
@@ -0,0 +1,7 @@
+requires packed_4x8_integer_dot_product;
+
+fn main() {
+    let x = dot4U8Packed(16909060u, 4294967295u);
+    let y = dot4I8Packed(pack4xI8(vec4<i32>(-1, 2, -3, 4)), 2155905152u);
+    let z = vec2<i32>(dot4I8Packed(1u, 2u), i32(dot4U8Packed(3u, 4u)));
+}
@@ -0,0 +1,18 @@
+@group(0) @binding(2)
+var t_2d: texture_2d<f32>;
+
+@group(0) @binding(3)
+var t_depth: texture_depth_2d_array;
+
+@group(0) @binding(4)
+var s: sampler;
+
+@group(0) @binding(5)
+var s_cmp: sampler_comparison;
+
+@compute @workgroup_size(1)
+fn main() {
+    let a = textureGather(1i, t_2d, s, vec2<f32>(0.25f, 0.5f));
+    let b = textureGatherCompare(t_depth, s_cmp, vec2<f32>(0.5f, 0.5f), 1u, 0.5f);
+    let c = textureDimensions(t_depth) + vec2<u32>(textureNumLayers(t_depth));
+}
@@ -0,0 +1,18 @@
+@group(0) @binding(2)
+var t_1d: texture_1d<f32>;
+
+@group(0) @binding(3)
+var t_2d_array: texture_2d_array<i32>;
+
+@group(0) @binding(4)
+var t_3d: texture_3d<u32>;
+
+@group(0) @binding(5)
+var t_cube: texture_cube<f32>;
+
+@compute @workgroup_size(1)
+fn main() {
+    let a = textureDimensions(t_1d) + textureNumLevels(t_1d);
+    let b = textureDimensions(t_2d_array, 1u) * textureNumLayers(t_2d_array);
+    let c = textureDimensions(t_3d, 0i).xy + textureDimensions(t_cube);
+}
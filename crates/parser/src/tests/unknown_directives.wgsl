@@ -0,0 +1,6 @@
+enable f16, chromium_experimental_framebuffer_fetch;
+requires readonly_and_readwrite_storage_textures;
+
+fn main() {
+    let x = 1h;
+}
@@ -0,0 +1,37 @@
+/* A shader written by hand rather than by the generator, which uses syntax that the generator
+   /* never emits */. */
+enable f16, subgroups;
+
+const half = 2u;
+alias Counts = array<u32, half * 2>;
+
+@id(0) override scale: f32 = 2.0;
+override threshold = 0x10u;
+
+@group(0) @binding(0) var<storage, read_write> output: array<u32>;
+@group(0) @binding(1) var tex: texture_2d<f32>;
+@group(0) @binding(2) var samp: sampler;
+
+fn bump(p: ptr<function, Counts, read_write>, i: u32) {
+    (*p)[i] += 1u;
+}
+
+@compute @workgroup_size(4 * 2)
+fn main(@builtin(subgroup_size) size: u32) {
+    let x: f32 = 1;
+    const y: vec3f = vec3(1, 2, 3);
+    var<function> z = vec4(y, 1.5e-1) * scale;
+    let t = textureLoad(tex, vec2<i32>(0), 0) + textureSampleLevel(tex, samp, vec2f(0.5), 0.0);
+    var counts: Counts;
+
+    switch (size) {
+        case 1u, 2u: {
+            bump(&counts, 0u);
+        }
+        case 4u, default: {
+            bump(&counts, min(threshold, 3u));
+        }
+    }
+
+    output[size] = counts[0] + u32(x + z.x + t.y);
+}
@@ -0,0 +1,6 @@
+const WIDTH = 4u;
+
+@compute @workgroup_size(WIDTH, 2, 1)
+fn main() {
+    let x = 1;
+}
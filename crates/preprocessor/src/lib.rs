@@ -1,9 +1,3 @@
-pub use ast::writer::Options;
-
-pub fn preprocess(options: Options, shader: String) -> String {
-    if options.module_scope_constants {
-        panic!("module scope constants are not supported yet");
-    }
-
+pub fn preprocess(shader: String) -> String {
     shader
 }
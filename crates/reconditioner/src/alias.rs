@@ -0,0 +1,531 @@
+//! Legalization of aliased pointer arguments.
+//!
+//! WGSL doesn't allow a function to be called with two pointers into the same variable if either
+//! of them is written through. Rather than working out which calls actually write, every pointer
+//! argument whose root variable is also passed in an earlier argument is replaced with a pointer to
+//! a fresh temporary, which is initialized with a copy of the value that it pointed to. Writes
+//! through the replaced pointer are therefore discarded, but since the original program was
+//! invalid, this doesn't matter.
+//!
+//! Only aliasing between the arguments of a call is handled. Pointers which alias a module-scope
+//! variable that the callee accesses directly are still rejected by [`crate::analysis`].
+
+use std::collections::HashMap;
+
+use ast::types::{DataType, MemoryViewType};
+use ast::*;
+
+const TEMP_PREFIX: &str = "_wgslsmith_alias_";
+
+/// Replaces aliased pointer arguments in the functions of `module` with pointers to temporaries,
+/// returning the number of arguments which were replaced.
+pub fn legalize_aliasing(module: &mut Module) -> u32 {
+    let mut legalizer = Legalizer {
+        next_temp: first_unused_temp(module),
+        pointers: HashMap::new(),
+        pending: vec![],
+        private_temps: vec![],
+        header_var: None,
+        replaced: 0,
+    };
+
+    for function in &mut module.functions {
+        legalizer.pointers.clear();
+        legalizer.stmts(&mut function.body);
+    }
+
+    module.vars.extend(legalizer.private_temps);
+    legalizer.replaced
+}
+
+/// Returns the first temporary index which isn't used by the module, since temporaries may be
+/// left over from a previous reconditioning.
+fn first_unused_temp(module: &Module) -> u32 {
+    let mut names = module
+        .vars
+        .iter()
+        .map(|it| it.name.as_str())
+        .collect::<Vec<_>>();
+
+    for function in &module.functions {
+        declared_vars(&function.body, &mut names);
+    }
+
+    names
+        .into_iter()
+        .filter_map(|it| it.strip_prefix(TEMP_PREFIX)?.parse::<u32>().ok())
+        .max()
+        .map_or(0, |it| it + 1)
+}
+
+/// Collects the names of the variables declared by `var` statements in a block, including nested
+/// blocks. Temporaries aren't declared in any other way, so other identifiers are skipped.
+fn declared_vars<'a>(stmts: &'a [Statement], names: &mut Vec<&'a str>) {
+    for stmt in stmts {
+        match stmt {
+            Statement::VarDecl(stmt) => names.push(&stmt.ident),
+            Statement::Compound(stmts) => declared_vars(stmts, names),
+            Statement::If(stmt) => {
+                let mut stmt = stmt;
+
+                loop {
+                    declared_vars(&stmt.body, names);
+
+                    match stmt.else_.as_deref() {
+                        Some(Else::If(next)) => stmt = next,
+                        Some(Else::Else(stmts)) => break declared_vars(stmts, names),
+                        None => break,
+                    }
+                }
+            }
+            Statement::Switch(stmt) => {
+                for case in &stmt.cases {
+                    declared_vars(&case.body, names);
+                }
+
+                declared_vars(&stmt.default, names);
+            }
+            Statement::Loop(stmt) => {
+                declared_vars(&stmt.body, names);
+
+                if let Some(continuing) = &stmt.continuing {
+                    declared_vars(&continuing.body, names);
+                }
+            }
+            Statement::ForLoop(stmt) => declared_vars(&stmt.body, names),
+            Statement::While(stmt) => declared_vars(&stmt.body, names),
+            _ => {}
+        }
+    }
+}
+
+struct Legalizer {
+    next_temp: u32,
+    /// Root variables of the `let`-bound pointers which are in scope.
+    pointers: HashMap<String, String>,
+    /// Statements initializing the temporaries for the statement which is being legalized, which
+    /// are inserted before it.
+    pending: Vec<Statement>,
+    /// Temporaries for pointers into the `private` address space, which must be declared at module
+    /// scope.
+    private_temps: Vec<GlobalVarDecl>,
+    /// Variable declared in the header of the for-loop which is being legalized, which isn't in
+    /// scope where the temporaries for the header are initialized.
+    header_var: Option<String>,
+    replaced: u32,
+}
+
+impl Legalizer {
+    fn stmts(&mut self, stmts: &mut Vec<Statement>) {
+        let pointers = self.pointers.clone();
+        let pending = std::mem::take(&mut self.pending);
+
+        for mut stmt in std::mem::take(stmts) {
+            self.stmt(&mut stmt);
+            stmts.append(&mut self.pending);
+            stmts.push(stmt);
+        }
+
+        self.pending = pending;
+        self.pointers = pointers;
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::LetDecl(stmt) => {
+                self.expr(&mut stmt.initializer);
+
+                let root = match &stmt.initializer.data_type {
+                    DataType::Ptr(_) => self.root(&stmt.initializer),
+                    _ => None,
+                };
+
+                match root {
+                    Some(root) => self.pointers.insert(stmt.ident.clone(), root),
+                    None => self.pointers.remove(&stmt.ident),
+                };
+            }
+            Statement::ConstDecl(_) | Statement::ConstAssert(_) => {}
+            Statement::VarDecl(stmt) => {
+                if let Some(initializer) = &mut stmt.initializer {
+                    self.expr(initializer);
+                }
+
+                self.pointers.remove(&stmt.ident);
+            }
+            Statement::Assignment(stmt) => {
+                if let AssignmentLhs::Expr(lhs) = &mut stmt.lhs {
+                    self.lhs_expr(lhs);
+                }
+
+                self.expr(&mut stmt.rhs);
+            }
+            Statement::Increment(lhs) | Statement::Decrement(lhs) => self.lhs_expr(lhs),
+            Statement::Compound(stmts) => self.stmts(stmts),
+            Statement::If(stmt) => self.if_stmt(stmt),
+            Statement::Return(stmt) => {
+                if let Some(value) = &mut stmt.value {
+                    self.expr(value);
+                }
+            }
+            Statement::Loop(stmt) => {
+                self.stmts(&mut stmt.body);
+
+                if let Some(continuing) = &mut stmt.continuing {
+                    self.stmts(&mut continuing.body);
+
+                    // The break-if condition is evaluated after the continuing block, so its
+                    // temporaries can be initialized at the end of the block.
+                    if let Some(condition) = &mut continuing.break_if {
+                        let pending = std::mem::take(&mut self.pending);
+                        self.expr(condition);
+                        continuing.body.append(&mut self.pending);
+                        self.pending = pending;
+                    }
+                }
+            }
+            Statement::Switch(stmt) => {
+                self.expr(&mut stmt.selector);
+
+                for case in &mut stmt.cases {
+                    self.stmts(&mut case.body);
+                }
+
+                self.stmts(&mut stmt.default);
+            }
+            Statement::ForLoop(stmt) => {
+                // Temporaries for the header are initialized before the loop, so they are only
+                // copied once rather than on every iteration.
+                if let Some(ForLoopInit::VarDecl(decl)) = &mut stmt.header.init {
+                    if let Some(initializer) = &mut decl.initializer {
+                        self.expr(initializer);
+                    }
+
+                    self.header_var = Some(decl.ident.clone());
+                }
+
+                if let Some(condition) = &mut stmt.header.condition {
+                    self.expr(condition);
+                }
+
+                match &mut stmt.header.update {
+                    Some(ForLoopUpdate::Assignment(stmt)) => {
+                        if let AssignmentLhs::Expr(lhs) = &mut stmt.lhs {
+                            self.lhs_expr(lhs);
+                        }
+
+                        self.expr(&mut stmt.rhs);
+                    }
+                    Some(ForLoopUpdate::Increment(lhs) | ForLoopUpdate::Decrement(lhs)) => {
+                        self.lhs_expr(lhs)
+                    }
+                    None => {}
+                }
+
+                self.header_var = None;
+                self.stmts(&mut stmt.body);
+            }
+            Statement::While(stmt) => {
+                self.expr(&mut stmt.condition);
+                self.stmts(&mut stmt.body);
+            }
+            Statement::FnCall(stmt) => {
+                for arg in &mut stmt.args {
+                    self.expr(arg);
+                }
+
+                self.legalize_args(&mut stmt.args);
+            }
+            Statement::Break | Statement::Continue | Statement::Fallthrough => {}
+        }
+    }
+
+    fn if_stmt(&mut self, stmt: &mut IfStatement) {
+        self.expr(&mut stmt.condition);
+        self.stmts(&mut stmt.body);
+
+        match stmt.else_.as_deref_mut() {
+            Some(Else::If(stmt)) => self.if_stmt(stmt),
+            Some(Else::Else(stmts)) => self.stmts(stmts),
+            None => {}
+        }
+    }
+
+    fn lhs_expr(&mut self, node: &mut LhsExprNode) {
+        match &mut node.expr {
+            LhsExpr::Ident(_) => {}
+            LhsExpr::Postfix(inner, postfix) => {
+                self.lhs_expr(inner);
+
+                if let Postfix::Index(index) = postfix {
+                    self.expr(index);
+                }
+            }
+            LhsExpr::Deref(inner) | LhsExpr::AddressOf(inner) => self.lhs_expr(inner),
+        }
+    }
+
+    fn expr(&mut self, node: &mut ExprNode) {
+        match &mut node.expr {
+            Expr::Lit(_) | Expr::Var(_) => {}
+            Expr::TypeCons(expr) => {
+                for arg in &mut expr.args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Bitcast(expr) => self.expr(&mut expr.inner),
+            Expr::Postfix(expr) => {
+                self.expr(&mut expr.inner);
+
+                if let Postfix::Index(index) = &mut expr.postfix {
+                    self.expr(index);
+                }
+            }
+            Expr::UnOp(expr) => self.expr(&mut expr.inner),
+            Expr::BinOp(expr) => {
+                self.expr(&mut expr.left);
+                self.expr(&mut expr.right);
+            }
+            Expr::FnCall(expr) => {
+                for arg in &mut expr.args {
+                    self.expr(arg);
+                }
+
+                self.legalize_args(&mut expr.args);
+            }
+        }
+    }
+
+    /// Replaces each pointer argument which has the same root variable as an earlier argument
+    /// with a pointer to a temporary.
+    fn legalize_args(&mut self, args: &mut [ExprNode]) {
+        let mut roots = vec![];
+
+        for arg in args {
+            let DataType::Ptr(view) = &arg.data_type else {
+                continue;
+            };
+
+            let Some(root) = self.root(arg) else {
+                continue;
+            };
+
+            if !roots.contains(&root) {
+                roots.push(root);
+                continue;
+            }
+
+            if let Some(temp) = self.gen_temp(view.clone(), &root, arg) {
+                *arg = temp;
+                self.replaced += 1;
+            }
+        }
+    }
+
+    /// Declares a temporary which holds a copy of the value that `ptr` points to, and returns a
+    /// pointer to it.
+    ///
+    /// Returns `None` if temporaries can't be declared in the address space of `ptr`.
+    fn gen_temp(&mut self, view: MemoryViewType, root: &str, ptr: &ExprNode) -> Option<ExprNode> {
+        let ident = format!("{TEMP_PREFIX}{}", self.next_temp);
+        let data_type = view.inner.as_ref().clone();
+
+        // The root of a pointer in a for-loop header may be the loop variable, which can't be
+        // copied before the loop, so the temporary is zero-initialized instead.
+        let value = if self.header_var.as_deref() == Some(root) {
+            TypeConsExpr::new(data_type.clone(), vec![]).into()
+        } else {
+            match &ptr.expr {
+                Expr::UnOp(UnOpExpr {
+                    op: UnOp::AddressOf,
+                    inner,
+                }) => inner.as_ref().clone(),
+                _ => UnOpExpr::new(UnOp::Deref, ptr.clone()).into(),
+            }
+        };
+
+        match view.storage_class {
+            StorageClass::Function => {
+                self.pending
+                    .push(VarDeclStatement::new(&ident, None, Some(value)).into());
+            }
+            StorageClass::Private => {
+                self.private_temps.push(GlobalVarDecl {
+                    attrs: vec![],
+                    qualifier: Some(VarQualifier {
+                        storage_class: StorageClass::Private,
+                        access_mode: None,
+                    }),
+                    name: ident.clone(),
+                    data_type: data_type.clone(),
+                    initializer: None,
+                });
+
+                self.pending.push(
+                    AssignmentStatement::new(
+                        AssignmentLhs::name(&ident, data_type),
+                        AssignmentOp::Simple,
+                        value,
+                    )
+                    .into(),
+                );
+            }
+            _ => return None,
+        }
+
+        self.next_temp += 1;
+
+        let var = VarExpr::new(ident).into_node(DataType::Ref(view));
+        Some(UnOpExpr::new(UnOp::AddressOf, var).into())
+    }
+
+    /// Returns the variable that the given pointer expression points into.
+    fn root(&self, node: &ExprNode) -> Option<String> {
+        match &node.expr {
+            Expr::Var(expr) => Some(
+                self.pointers
+                    .get(&expr.ident)
+                    .unwrap_or(&expr.ident)
+                    .clone(),
+            ),
+            Expr::Postfix(expr) => self.root(&expr.inner),
+            Expr::UnOp(expr) => self.root(&expr.inner),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legalize(source: &str) -> (String, u32) {
+        let mut module = parser::parse(source);
+        let replaced = legalize_aliasing(&mut module);
+
+        let mut shader = String::new();
+        ast::writer::Writer
+            .write_module(&mut shader, &module)
+            .unwrap();
+
+        (shader, replaced)
+    }
+
+    #[test]
+    fn let_bound_pointers_alias_their_root() {
+        let (shader, replaced) = legalize(
+            r#"
+                fn f(a: ptr<function, i32>, b: ptr<function, i32>) {
+                    *a = *b;
+                }
+
+                @compute @workgroup_size(1)
+                fn main() {
+                    var x = 1;
+                    let p = &x;
+                    f(p, &x);
+                }
+            "#,
+        );
+
+        assert!(
+            shader.contains("    var _wgslsmith_alias_0 = x;\n    f(p, &_wgslsmith_alias_0);\n")
+        );
+        assert_eq!(replaced, 1);
+    }
+
+    #[test]
+    fn private_roots_get_module_scope_temporaries() {
+        let (shader, replaced) = legalize(
+            r#"
+                var<private> g: i32;
+
+                fn f(a: ptr<private, i32>, b: ptr<private, i32>) {
+                    *a = *b;
+                }
+
+                @compute @workgroup_size(1)
+                fn main() {
+                    f(&g, &g);
+                }
+            "#,
+        );
+
+        assert!(shader.contains("var<private> _wgslsmith_alias_0: i32;"));
+        assert!(shader.contains("    _wgslsmith_alias_0 = g;\n    f(&g, &_wgslsmith_alias_0);\n"));
+        assert_eq!(replaced, 1);
+    }
+
+    #[test]
+    fn for_loop_header_variables_get_zero_initialized_temporaries() {
+        let (shader, replaced) = legalize(
+            r#"
+                fn f(a: ptr<function, i32>, b: ptr<function, i32>) -> i32 {
+                    return *a + *b;
+                }
+
+                @compute @workgroup_size(1)
+                fn main() {
+                    for (var i = 0; f(&i, &i) < 1; i++) {
+                    }
+                }
+            "#,
+        );
+
+        assert!(shader.contains("    var _wgslsmith_alias_0 = i32();\n    for (var i = 0i;"));
+        assert!(shader.contains("(f(&i, &_wgslsmith_alias_0) < 1i)"));
+        assert_eq!(replaced, 1);
+    }
+
+    #[test]
+    fn workgroup_and_storage_roots_are_left_untouched() {
+        let source = r#"
+            @group(0) @binding(0) var<storage, read_write> s: i32;
+            var<workgroup> w: i32;
+
+            fn f(a: ptr<workgroup, i32>, b: ptr<workgroup, i32>) {
+                *a = *b;
+            }
+
+            fn g(a: ptr<storage, i32, read_write>, b: ptr<storage, i32, read_write>) {
+                *a = *b;
+            }
+
+            @compute @workgroup_size(1)
+            fn main() {
+                f(&w, &w);
+                g(&s, &s);
+            }
+        "#;
+
+        let (shader, replaced) = legalize(source);
+
+        assert!(shader.contains("    f(&w, &w);\n    g(&s, &s);\n"));
+        assert_eq!(replaced, 0);
+    }
+
+    #[test]
+    fn temporaries_from_a_previous_run_are_skipped() {
+        let (shader, replaced) = legalize(
+            r#"
+                fn f(a: ptr<function, i32>, b: ptr<function, i32>) {
+                    *a = *b;
+                }
+
+                @compute @workgroup_size(1)
+                fn main() {
+                    var x = 1;
+                    if (x > 0) {
+                        var _wgslsmith_alias_3 = 0;
+                    }
+                    f(&x, &x);
+                }
+            "#,
+        );
+
+        assert!(
+            shader.contains("    var _wgslsmith_alias_4 = x;\n    f(&x, &_wgslsmith_alias_4);\n")
+        );
+        assert_eq!(replaced, 1);
+    }
+}
@@ -201,6 +201,8 @@ fn visit_stmt<'a>(
 ) {
     match stmt {
         Statement::LetDecl(stmt) => visit_expr(analysis, scope, cx, &stmt.initializer),
+        Statement::ConstDecl(stmt) => visit_expr(analysis, scope, cx, &stmt.initializer),
+        Statement::ConstAssert(stmt) => visit_expr(analysis, scope, cx, &stmt.expr),
         Statement::VarDecl(stmt) => {
             if let Some(initializer) = &stmt.initializer {
                 visit_expr(analysis, scope, cx, initializer);
@@ -214,6 +216,7 @@ fn visit_stmt<'a>(
             visit_lhs(analysis, scope, cx, &stmt.lhs);
             visit_expr(analysis, scope, cx, &stmt.rhs);
         }
+        Statement::Increment(lhs) | Statement::Decrement(lhs) => visit_lhs_expr(scope, cx, lhs),
         Statement::Compound(block) => visit_stmt_block(analysis, scope, cx, block),
         Statement::If(stmt) => visit_if_stmt(analysis, scope, cx, stmt),
         Statement::Return(stmt) => {
@@ -221,7 +224,22 @@ fn visit_stmt<'a>(
                 visit_expr(analysis, scope, cx, value);
             }
         }
-        Statement::Loop(stmt) => visit_stmt_block(analysis, scope, cx, &stmt.body),
+        Statement::Loop(stmt) => {
+            let mut scope = scope.clone();
+
+            for stmt in &stmt.body {
+                visit_stmt(analysis, &mut scope, cx, stmt);
+            }
+
+            // The continuing block can refer to declarations in the loop body.
+            if let Some(continuing) = &stmt.continuing {
+                visit_stmt_block(analysis, &mut scope, cx, &continuing.body);
+
+                if let Some(condition) = &continuing.break_if {
+                    visit_expr(analysis, &mut scope, cx, condition);
+                }
+            }
+        }
         Statement::Break => {}
         Statement::Switch(stmt) => {
             visit_expr(analysis, scope, cx, &stmt.selector);
@@ -259,11 +277,18 @@ fn visit_stmt<'a>(
                         visit_lhs(analysis, &mut scope, cx, &stmt.lhs);
                         visit_expr(analysis, &mut scope, cx, &stmt.rhs);
                     }
+                    ForLoopUpdate::Increment(lhs) | ForLoopUpdate::Decrement(lhs) => {
+                        visit_lhs_expr(&mut scope, cx, lhs);
+                    }
                 }
             }
 
             visit_stmt_block(analysis, &mut scope, cx, &stmt.body);
         }
+        Statement::While(stmt) => {
+            visit_expr(analysis, scope, cx, &stmt.condition);
+            visit_stmt_block(analysis, scope, cx, &stmt.body);
+        }
         Statement::FnCall(stmt) => {
             visit_function_call(analysis, scope, cx, &stmt.ident, &stmt.args);
         }
@@ -291,11 +316,15 @@ fn visit_lhs<'a>(
     lhs: &'a AssignmentLhs,
 ) {
     if let AssignmentLhs::Expr(lhs) = &lhs {
-        let ident = find_lhs_ident(lhs);
-        let root_ident = scope.idents.get(ident);
-        if let Some(root_ident) = root_ident {
-            cx.accesses.insert((AccessType::Write, *root_ident));
-        }
+        visit_lhs_expr(scope, cx, lhs);
+    }
+}
+
+fn visit_lhs_expr<'a>(scope: &mut Scope<'a>, cx: &mut FnContext<'a>, lhs: &'a LhsExprNode) {
+    let ident = find_lhs_ident(lhs);
+    let root_ident = scope.idents.get(ident);
+    if let Some(root_ident) = root_ident {
+        cx.accesses.insert((AccessType::Write, *root_ident));
     }
 }
 
@@ -329,6 +358,9 @@ fn visit_expr<'a>(
                 visit_expr(analysis, scope, cx, arg);
             }
         }
+        Expr::Bitcast(expr) => {
+            visit_expr(analysis, scope, cx, &expr.inner);
+        }
         Expr::Var(expr) => {
             let ident = scope.idents.get(expr.ident.as_str());
             if let Some(root_ident) = ident {
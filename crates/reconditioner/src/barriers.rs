@@ -0,0 +1,376 @@
+//! Hoisting of barriers out of non-uniform control flow.
+//!
+//! `workgroupBarrier` and `storageBarrier` may only be called in uniform control flow, which is
+//! checked by the uniformity analysis of WGSL. Barriers in control flow which may be non-uniform
+//! are moved to just before the outermost statement that encloses them in uniform control flow,
+//! e.g. `if (x < 1) { workgroupBarrier(); }` becomes `workgroupBarrier(); if (x < 1) {}`. Barriers
+//! for which there is no such statement are removed.
+//!
+//! Like the generator, this uses a conservative approximation of the uniformity analysis. Control
+//! flow is only considered uniform in entrypoints, and never within or after a loop, since loops
+//! are bounded by counters in the private address space. Barriers in other functions are always
+//! removed.
+//!
+//! `workgroupUniformLoad` must also be called in uniform control flow, but it is out of scope: its
+//! result is a value, so it can't be moved without also moving the expression that uses it. The
+//! generator only emits it in uniform control flow, so calls are left as is.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use ast::*;
+
+use crate::Report;
+
+/// Moves barriers out of non-uniform control flow, recording the number of moved and removed
+/// barriers in the report.
+pub fn hoist_barriers(module: &mut Module, report: &mut Report) {
+    let mut uniform_idents: HashSet<String> = module
+        .vars
+        .iter()
+        .filter(|it| {
+            matches!(
+                &it.qualifier,
+                Some(VarQualifier {
+                    storage_class: StorageClass::Uniform,
+                    ..
+                }) | Some(VarQualifier {
+                    storage_class: StorageClass::Storage,
+                    access_mode: None | Some(AccessMode::Read),
+                })
+            )
+        })
+        .map(|it| it.name.clone())
+        .collect();
+
+    uniform_idents.extend(module.consts.iter().map(|it| it.name.clone()));
+    uniform_idents.extend(module.overrides.iter().map(|it| it.name.clone()));
+
+    let mut hoister = Hoister { report };
+
+    for function in &mut module.functions {
+        let is_entrypoint = function
+            .attrs
+            .iter()
+            .any(|it| matches!(it, FnAttr::Stage(ShaderStage::Compute)));
+
+        let removed = hoister.block(&mut function.body, is_entrypoint, uniform_idents.clone());
+        hoister.report.removed_barriers += removed.len() as u32;
+    }
+}
+
+struct Hoister<'a> {
+    report: &'a mut Report,
+}
+
+impl Hoister<'_> {
+    /// Hoists barriers within a block, whose control flow is initially uniform if `uniform` is
+    /// true.
+    ///
+    /// Returns the barriers which must be hoisted out of the block, since they are in non-uniform
+    /// control flow.
+    fn block(
+        &mut self,
+        stmts: &mut Vec<Statement>,
+        mut uniform: bool,
+        mut uniform_idents: HashSet<String>,
+    ) -> Vec<String> {
+        let mut hoisted = vec![];
+
+        for mut stmt in std::mem::take(stmts) {
+            if let Some(barrier) = barrier(&stmt) {
+                if uniform {
+                    stmts.push(stmt);
+                } else {
+                    hoisted.push(barrier.to_owned());
+                }
+
+                continue;
+            }
+
+            let mut barriers = self.stmt(&mut stmt, uniform, &mut uniform_idents);
+
+            if uniform {
+                self.report.hoisted_barriers += barriers.len() as u32;
+
+                // Barriers of the same kind that are hoisted to the same point are merged.
+                barriers.sort();
+                barriers.dedup();
+
+                for barrier in barriers {
+                    stmts.push(FnCallStatement::new(barrier, vec![]).into());
+                }
+            } else {
+                hoisted.extend(barriers);
+            }
+
+            uniform &= !diverges(&stmt);
+            stmts.push(stmt);
+        }
+
+        hoisted
+    }
+
+    fn stmt(
+        &mut self,
+        stmt: &mut Statement,
+        uniform: bool,
+        uniform_idents: &mut HashSet<String>,
+    ) -> Vec<String> {
+        match stmt {
+            Statement::LetDecl(stmt) => {
+                if is_uniform_expr(&stmt.initializer, uniform_idents) {
+                    uniform_idents.insert(stmt.ident.clone());
+                } else {
+                    uniform_idents.remove(&stmt.ident);
+                }
+
+                vec![]
+            }
+            Statement::ConstDecl(stmt) => {
+                uniform_idents.insert(stmt.ident.clone());
+                vec![]
+            }
+            Statement::VarDecl(stmt) => {
+                // Function scope variables aren't tracked, and are assumed to be non-uniform.
+                uniform_idents.remove(&stmt.ident);
+                vec![]
+            }
+            Statement::Compound(stmts) => self.block(stmts, uniform, uniform_idents.clone()),
+            Statement::If(stmt) => self.if_stmt(stmt, uniform, uniform_idents),
+            Statement::Switch(stmt) => {
+                let uniform = uniform && is_uniform_expr(&stmt.selector, uniform_idents);
+                let mut hoisted = vec![];
+
+                for case in &mut stmt.cases {
+                    hoisted.extend(self.block(&mut case.body, uniform, uniform_idents.clone()));
+                }
+
+                hoisted.extend(self.block(&mut stmt.default, uniform, uniform_idents.clone()));
+                hoisted
+            }
+            Statement::Loop(stmt) => {
+                let mut hoisted = self.block(&mut stmt.body, false, uniform_idents.clone());
+
+                if let Some(continuing) = &mut stmt.continuing {
+                    hoisted.extend(self.block(&mut continuing.body, false, uniform_idents.clone()));
+                }
+
+                hoisted
+            }
+            Statement::ForLoop(stmt) => self.block(&mut stmt.body, false, uniform_idents.clone()),
+            Statement::While(stmt) => self.block(&mut stmt.body, false, uniform_idents.clone()),
+            Statement::ConstAssert(_)
+            | Statement::Assignment(_)
+            | Statement::Increment(_)
+            | Statement::Decrement(_)
+            | Statement::Return(_)
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Fallthrough
+            | Statement::FnCall(_) => vec![],
+        }
+    }
+
+    fn if_stmt(
+        &mut self,
+        stmt: &mut IfStatement,
+        uniform: bool,
+        uniform_idents: &HashSet<String>,
+    ) -> Vec<String> {
+        let uniform = uniform && is_uniform_expr(&stmt.condition, uniform_idents);
+        let mut hoisted = self.block(&mut stmt.body, uniform, uniform_idents.clone());
+
+        match stmt.else_.as_deref_mut() {
+            Some(Else::If(stmt)) => hoisted.extend(self.if_stmt(stmt, uniform, uniform_idents)),
+            Some(Else::Else(stmts)) => {
+                hoisted.extend(self.block(stmts, uniform, uniform_idents.clone()))
+            }
+            None => {}
+        }
+
+        hoisted
+    }
+}
+
+/// Returns the name of the barrier builtin called by the given statement, if any.
+fn barrier(stmt: &Statement) -> Option<&str> {
+    let Statement::FnCall(stmt) = stmt else {
+        return None;
+    };
+
+    match BuiltinFn::from_str(&stmt.ident) {
+        Ok(BuiltinFn::WorkgroupBarrier | BuiltinFn::StorageBarrier) => Some(&stmt.ident),
+        _ => None,
+    }
+}
+
+/// Returns true if control flow after the given statement may be non-uniform, even if it was
+/// uniform before it.
+///
+/// This is the case for loops, and for any statement which contains a loop or may return early.
+fn diverges(stmt: &Statement) -> bool {
+    let block_diverges = |stmts: &[Statement]| stmts.iter().any(diverges);
+
+    match stmt {
+        Statement::Loop(_) | Statement::ForLoop(_) | Statement::While(_) => true,
+        Statement::Return(_) => true,
+        Statement::Compound(stmts) => block_diverges(stmts),
+        Statement::If(stmt) => if_diverges(stmt),
+        Statement::Switch(stmt) => {
+            stmt.cases.iter().any(|it| block_diverges(&it.body)) || block_diverges(&stmt.default)
+        }
+        _ => false,
+    }
+}
+
+fn if_diverges(stmt: &IfStatement) -> bool {
+    stmt.body.iter().any(diverges)
+        || match stmt.else_.as_deref() {
+            Some(Else::If(stmt)) => if_diverges(stmt),
+            Some(Else::Else(stmts)) => stmts.iter().any(diverges),
+            None => false,
+        }
+}
+
+/// Returns true if the value of an expression is known to be uniform, assuming that it is
+/// evaluated in uniform control flow. This matches the approximation used by the generator.
+fn is_uniform_expr(node: &ExprNode, uniform_idents: &HashSet<String>) -> bool {
+    let is_uniform = |it| is_uniform_expr(it, uniform_idents);
+
+    match &node.expr {
+        Expr::Lit(_) => true,
+        Expr::TypeCons(expr) => expr.args.iter().all(is_uniform),
+        Expr::Var(expr) => uniform_idents.contains(&expr.ident) || node.data_type.is_handle(),
+        Expr::Postfix(expr) => {
+            is_uniform(&expr.inner)
+                && match &expr.postfix {
+                    Postfix::Index(index) => is_uniform(index),
+                    Postfix::Member(_) => true,
+                }
+        }
+        Expr::UnOp(expr) => {
+            !matches!(expr.op, UnOp::AddressOf | UnOp::Deref) && is_uniform(&expr.inner)
+        }
+        Expr::BinOp(expr) => is_uniform(&expr.left) && is_uniform(&expr.right),
+        Expr::Bitcast(expr) => is_uniform(&expr.inner),
+        Expr::FnCall(expr) => match BuiltinFn::from_str(&expr.ident) {
+            Ok(BuiltinFn::WorkgroupUniformLoad) => true,
+            Ok(builtin) if !builtin.as_ref().starts_with("subgroup") => {
+                expr.args.iter().all(is_uniform)
+            }
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hoist(source: &str) -> (String, Report) {
+        let mut module = parser::parse(source);
+        let mut report = Report::default();
+        hoist_barriers(&mut module, &mut report);
+
+        let mut shader = String::new();
+        ast::writer::Writer
+            .write_module(&mut shader, &module)
+            .unwrap();
+
+        (shader, report)
+    }
+
+    #[test]
+    fn barriers_are_hoisted_out_of_non_uniform_if() {
+        let (shader, report) = hoist(
+            r#"
+                @compute @workgroup_size(1)
+                fn main(@builtin(local_invocation_index) idx: u32) {
+                    if (idx < 1u) {
+                        workgroupBarrier();
+                    }
+                }
+            "#,
+        );
+
+        assert!(shader.contains("    workgroupBarrier();\n    if ((idx < 1u)) {\n    }\n"));
+        assert_eq!(report.hoisted_barriers, 1);
+        assert_eq!(report.removed_barriers, 0);
+    }
+
+    #[test]
+    fn barriers_are_hoisted_out_of_non_uniform_switch() {
+        let (shader, report) = hoist(
+            r#"
+                @compute @workgroup_size(1)
+                fn main(@builtin(local_invocation_index) idx: u32) {
+                    switch (idx) {
+                        case 0u: {
+                            storageBarrier();
+                        }
+                        default: {
+                            storageBarrier();
+                            workgroupBarrier();
+                        }
+                    }
+                }
+            "#,
+        );
+
+        // Both storage barriers are hoisted to the same point, so they are merged.
+        assert!(
+            shader.contains("    storageBarrier();\n    workgroupBarrier();\n    switch (idx) {")
+        );
+        assert_eq!(shader.matches("storageBarrier").count(), 1);
+        assert_eq!(report.hoisted_barriers, 3);
+    }
+
+    #[test]
+    fn barriers_after_a_loop_or_return_are_removed() {
+        let (shader, report) = hoist(
+            r#"
+                @compute @workgroup_size(1)
+                fn main(@builtin(local_invocation_index) idx: u32) {
+                    loop {
+                        break;
+                    }
+                    workgroupBarrier();
+                }
+
+                @compute @workgroup_size(1)
+                fn other(@builtin(local_invocation_index) idx: u32) {
+                    if (idx < 1u) {
+                        return;
+                    }
+                    storageBarrier();
+                }
+            "#,
+        );
+
+        assert!(!shader.contains("Barrier"));
+        assert_eq!(report.hoisted_barriers, 0);
+        assert_eq!(report.removed_barriers, 2);
+    }
+
+    #[test]
+    fn barriers_in_other_functions_are_removed() {
+        let (shader, report) = hoist(
+            r#"
+                fn f() {
+                    workgroupBarrier();
+                }
+
+                @compute @workgroup_size(1)
+                fn main() {
+                    f();
+                    workgroupBarrier();
+                }
+            "#,
+        );
+
+        assert!(shader.contains("fn f() {\n}"));
+        assert!(shader.contains("    f();\n    workgroupBarrier();\n"));
+        assert_eq!(report.removed_barriers, 1);
+    }
+}
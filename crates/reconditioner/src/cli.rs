@@ -3,7 +3,7 @@ use std::io::Read;
 
 use clap::{Parser, ValueEnum};
 
-use crate::analysis;
+use crate::{analysis, WrapperKind};
 
 #[derive(Parser)]
 pub struct Options {
@@ -23,6 +23,47 @@ pub struct Options {
         require_value_delimiter(true)
     )]
     pub enable: Vec<Feature>,
+
+    /// Maximum number of iterations that each loop may execute.
+    #[clap(long, action, default_value = "1")]
+    pub loop_budget: u32,
+
+    /// Bound the total number of function calls and loop iterations executed by each invocation,
+    /// after which functions return zeros.
+    #[clap(long, action)]
+    pub fuel: Option<u32>,
+
+    /// Safe wrappers to leave out, keeping the corresponding operations unchanged.
+    #[clap(
+        long,
+        value_enum,
+        action,
+        use_value_delimiter(true),
+        require_value_delimiter(true)
+    )]
+    pub disable_wrappers: Vec<WrapperKind>,
+
+    /// Inline calls to safe wrappers at their call sites where possible, instead of calling
+    /// helper functions.
+    #[clap(long, action)]
+    pub inline_wrappers: bool,
+
+    /// Suffix the names of safe wrappers with a hash of their declaration.
+    #[clap(long, action)]
+    pub hash_wrapper_names: bool,
+
+    /// Replace NaN and infinite results of f32 operations with zero and the largest finite value.
+    #[clap(long, action)]
+    pub canonicalize_floats: bool,
+
+    /// Round f32 values to this many mantissa bits (at most 23) before they are stored to storage
+    /// buffers.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(0..=23))]
+    pub quantize_floats: Option<u32>,
+
+    /// Print a JSON report of the applied transformations to stderr.
+    #[clap(long, action)]
+    pub report: bool,
 }
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -34,13 +75,16 @@ pub fn run(options: Options) -> eyre::Result<()> {
     let input = read_shader_from_path(&options.input)?;
     let ast = parser::parse(&input);
 
-    let result = analysis::analyse(&ast);
-    if !result {
-        eprintln!("rejecting due to possible invalid aliasing");
-        std::process::exit(1);
-    }
-
-    let mut rec_opts = crate::Options::default();
+    let mut rec_opts = crate::Options {
+        loop_budget: options.loop_budget,
+        fuel: options.fuel,
+        disabled_wrappers: options.disable_wrappers.into_iter().collect(),
+        inline_wrappers: options.inline_wrappers,
+        hash_wrapper_names: options.hash_wrapper_names,
+        canonicalize_floats: options.canonicalize_floats,
+        quantize_floats: options.quantize_floats,
+        ..Default::default()
+    };
 
     if !options.enable.is_empty() {
         assert!(matches!(options.enable.as_slice(), [Feature::LoopLimiters]));
@@ -49,6 +93,17 @@ pub fn run(options: Options) -> eyre::Result<()> {
 
     let result = crate::recondition_with(ast, rec_opts);
 
+    // Aliased pointer arguments are legalized by the reconditioner, but other kinds of aliasing
+    // can only be rejected.
+    if !analysis::analyse(&result.ast) {
+        eprintln!("rejecting due to possible invalid aliasing");
+        std::process::exit(1);
+    }
+
+    if options.report {
+        eprintln!("{}", serde_json::to_string(&result.report)?);
+    }
+
     struct Output(Box<dyn std::io::Write>);
 
     impl std::fmt::Write for Output {
@@ -64,8 +119,8 @@ pub fn run(options: Options) -> eyre::Result<()> {
         path => Box::new(File::create(path)?),
     };
 
-    ast::writer::Writer::default()
-        .write_module(&mut Output(output), &result)
+    ast::writer::Writer
+        .write_module(&mut Output(output), &result.ast)
         .unwrap();
 
     Ok(())
@@ -2,6 +2,9 @@ mod builtin;
 mod helper;
 mod value;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+
 use ast::*;
 use builtin::*;
 use value::*;
@@ -73,6 +76,16 @@ fn in_float_range(f: f32) -> Option<f32> {
     }
 }
 
+/// f16 arithmetic is evaluated in f32, which is exact for the operands but not for the rounding
+/// of the result, so results are only kept if they are well within the range of f16.
+fn in_f16_range(f: f32) -> Option<f32> {
+    if f.abs() <= 0.1_f32 || f.abs() >= 2048_f32 {
+        None
+    } else {
+        Some(f)
+    }
+}
+
 #[derive(Clone)]
 pub struct ConNode {
     node: ExprNode,
@@ -108,6 +121,13 @@ pub fn concretize(ast: Module) -> Module {
 pub fn concretize_with(mut ast: Module, options: Options) -> Module {
     let evaluator = Evaluator::new(options);
 
+    // Concretize the module-scope constants, so that their values are known when they are used
+    ast.consts = ast
+        .consts
+        .into_iter()
+        .map(|decl| evaluator.concretize_global_const(decl))
+        .collect();
+
     // Concretize the functions
     let functions = ast
         .functions
@@ -125,45 +145,88 @@ struct Evaluator {
     // keep track of which internal variables are concretizable
     // as we traverse the AST
     _placeholder: bool,
+    // values of the constants that are currently in scope - expressions using these are
+    // const-expressions, so they must be evaluated like literals
+    consts: RefCell<HashMap<String, Value>>,
 }
 
 impl Evaluator {
     fn new(options: Options) -> Evaluator {
         Evaluator {
             _placeholder: options._placeholder,
+            consts: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn concretize_global_const(&self, decl: GlobalConstDecl) -> GlobalConstDecl {
+        let initializer = self.concretize_expr(decl.initializer);
+        self.declare(&decl.name, initializer.value.clone());
+        GlobalConstDecl {
+            initializer: initializer.into(),
+            ..decl
         }
     }
 
     fn concretize_fn(&self, mut decl: FnDecl) -> FnDecl {
+        let module_consts = self.consts.borrow().clone();
+
+        for input in &decl.inputs {
+            self.declare(&input.name, None);
+        }
+
         decl.body = decl
             .body
             .into_iter()
             .map(|s| self.concretize_stmt(s))
             .collect();
 
+        // Function-scope constants are not visible in other functions
+        *self.consts.borrow_mut() = module_consts;
+
         decl
     }
 
+    /// Records that `ident` has been declared, with a known value if it is a constant.
+    ///
+    /// Declarations with the same name in other scopes are not tracked, so a shadowed constant
+    /// is treated as unknown until the end of the function.
+    fn declare(&self, ident: &str, value: Option<Value>) {
+        let mut consts = self.consts.borrow_mut();
+        match value {
+            Some(value) => consts.insert(ident.to_owned(), value),
+            None => consts.remove(ident),
+        };
+    }
+
     fn concretize_stmt(&self, stmt: Statement) -> Statement {
         //TODO: if stmt contains var, return (since not concretizable)
 
         match stmt {
             Statement::LetDecl(LetDeclStatement { ident, initializer }) => {
-                LetDeclStatement::new(ident, self.concretize_expr(initializer)).into()
+                let initializer = self.concretize_expr(initializer);
+                self.declare(&ident, None);
+                LetDeclStatement::new(ident, initializer).into()
+            }
+            Statement::ConstDecl(ConstDeclStatement { ident, initializer }) => {
+                let initializer = self.concretize_expr(initializer);
+                self.declare(&ident, initializer.value.clone());
+                ConstDeclStatement::new(ident, initializer).into()
             }
+            Statement::ConstAssert(stmt) => stmt.into(),
             Statement::VarDecl(VarDeclStatement {
                 ident,
                 data_type,
                 initializer,
-            }) => VarDeclStatement::new(
-                ident,
-                data_type,
-                initializer.map(|e| self.concretize_expr(e).into()),
-            )
-            .into(),
+            }) => {
+                let initializer = initializer.map(|e| self.concretize_expr(e).into());
+                self.declare(&ident, None);
+                VarDeclStatement::new(ident, data_type, initializer).into()
+            }
             Statement::Assignment(AssignmentStatement { lhs, op, rhs }) => {
                 AssignmentStatement::new(lhs, op, self.concretize_expr(rhs)).into()
             }
+            Statement::Increment(lhs) => Statement::Increment(self.concretize_lhs_expr(lhs)),
+            Statement::Decrement(lhs) => Statement::Decrement(self.concretize_lhs_expr(lhs)),
             Statement::Compound(s) => {
                 Statement::Compound(s.into_iter().map(|s| self.concretize_stmt(s)).collect())
             }
@@ -204,10 +267,21 @@ impl Evaluator {
                     .collect(),
             )
             .into(),
-            Statement::Loop(LoopStatement { body }) => {
+            Statement::Loop(LoopStatement { body, continuing }) => {
                 LoopStatement::new(body.into_iter().map(|s| self.concretize_stmt(s)).collect())
+                    .with_continuing(continuing.map(|ContinuingStatement { body, break_if }| {
+                        ContinuingStatement::new(
+                            body.into_iter().map(|s| self.concretize_stmt(s)).collect(),
+                            break_if.map(|e| self.concretize_expr(e).into()),
+                        )
+                    }))
                     .into()
             }
+            Statement::While(WhileStatement { condition, body }) => WhileStatement::new(
+                self.concretize_expr(condition),
+                body.into_iter().map(|s| self.concretize_stmt(s)).collect(),
+            )
+            .into(),
             Statement::ForLoop(ForLoopStatement { header, body }) => ForLoopStatement::new(
                 ForLoopHeader {
                     init: header.init.map(|init| self.concretize_for_init(init)),
@@ -231,11 +305,11 @@ impl Evaluator {
                 ident,
                 data_type,
                 initializer,
-            }) => ForLoopInit::VarDecl(VarDeclStatement::new(
-                ident,
-                data_type,
-                initializer.map(|e| self.concretize_expr(e).into()),
-            )),
+            }) => {
+                let initializer = initializer.map(|e| self.concretize_expr(e).into());
+                self.declare(&ident, None);
+                ForLoopInit::VarDecl(VarDeclStatement::new(ident, data_type, initializer))
+            }
         }
     }
 
@@ -248,6 +322,12 @@ impl Evaluator {
                     self.concretize_expr(rhs),
                 ))
             }
+            ForLoopUpdate::Increment(lhs) => {
+                ForLoopUpdate::Increment(self.concretize_lhs_expr(lhs))
+            }
+            ForLoopUpdate::Decrement(lhs) => {
+                ForLoopUpdate::Decrement(self.concretize_lhs_expr(lhs))
+            }
         }
     }
 
@@ -301,6 +381,16 @@ impl Evaluator {
         match node.expr {
             Expr::Lit(lit) => self.concretize_lit(lit),
             Expr::TypeCons(expr) => self.concretize_typecons(node.data_type, expr),
+            Expr::Bitcast(expr) => {
+                let con_inner = self.concretize_expr(*expr.inner);
+
+                // The result of a bitcast is not evaluated, since it may be an arbitrary bit
+                // pattern which is canonicalized when reconditioning.
+                ConNode {
+                    node: BitcastExpr::new(expr.data_type, ExprNode::from(con_inner)).into(),
+                    value: None,
+                }
+            }
             Expr::UnOp(expr) => {
                 let con_inner = self.concretize_expr(*expr.inner);
 
@@ -331,18 +421,29 @@ impl Evaluator {
                     Postfix::Member(string) => Postfix::Member(string),
                 };
 
+                // Swizzles of const-expression vectors are also const-expressions.
+                let value = match (&concrete_inner.value, &concrete_postfix) {
+                    (Some(value), Postfix::Member(member))
+                        if concrete_inner.node.data_type.dereference().is_vector() =>
+                    {
+                        value.swizzle(member)
+                    }
+                    _ => None,
+                };
+
                 ConNode {
                     node: PostfixExpr::new(concrete_inner, concrete_postfix).into(),
-                    value: None,
+                    value,
                 }
             }
             Expr::Var(expr) => {
+                let value = self.consts.borrow().get(&expr.ident).cloned();
                 ConNode {
                     node: ExprNode {
                         data_type: node.data_type,
                         expr: expr.into(),
                     },
-                    value: None, //TODO
+                    value,
                 }
             }
         }
@@ -413,9 +514,26 @@ impl Evaluator {
         let new_val = if self.contains_none(&new_val) {
             None
         } else {
-            Some(Value::Vector(
-                new_val.into_iter().map(|v| v.unwrap()).collect(),
-            ))
+            let values = new_val.into_iter().map(|v| v.unwrap());
+            match data_type {
+                // Vector constructors may take smaller vectors as arguments, or splat a single
+                // scalar, so the value is flattened into one value per component.
+                DataType::Vector(n, _) => {
+                    let mut components = values
+                        .flat_map(|v| match v {
+                            Value::Vector(components) => components,
+                            v => vec![v],
+                        })
+                        .collect::<Vec<_>>();
+
+                    if components.len() == 1 {
+                        components = vec![components[0].clone(); n.into()];
+                    }
+
+                    Some(Value::Vector(components))
+                }
+                _ => Some(Value::Vector(values.collect())),
+            }
         };
 
         ConNode {
@@ -517,6 +635,10 @@ impl Evaluator {
                     node: Lit::F32(1_f32).into(),
                     value: Value::from_f32(Some(1_f32)),
                 },
+                ScalarType::F16 => ConNode {
+                    node: Lit::F16(1_f32).into(),
+                    value: Some(Value::Lit(Lit::F16(1_f32))),
+                },
                 ScalarType::Bool => ConNode {
                     node: Lit::Bool(true).into(),
                     value: Value::from_bool(Some(true)),
@@ -538,6 +660,14 @@ impl Evaluator {
                         .into(),
                     value: Some(Value::Vector(vec![1_f32.into(); size.into()])),
                 },
+                ScalarType::F16 => ConNode {
+                    node: TypeConsExpr::new(data_type, vec![Lit::F16(1_f32).into(); size.into()])
+                        .into(),
+                    value: Some(Value::Vector(vec![
+                        Value::Lit(Lit::F16(1_f32));
+                        size.into()
+                    ])),
+                },
                 ScalarType::Bool => ConNode {
                     node: TypeConsExpr::new(data_type, vec![Lit::Bool(true).into(); size.into()])
                         .into(),
@@ -595,6 +725,9 @@ impl Evaluator {
                         let result = binop_float(op, l_lit, r_lit);
                         Value::from_f32(result)
                     }
+                    (Lit::F16(l_lit), Lit::F16(r_lit)) => binop_float(op, l_lit, r_lit)
+                        .and_then(in_f16_range)
+                        .map(|it| Value::Lit(Lit::F16(it))),
                     _ => None,
                 }
             }
@@ -707,6 +840,7 @@ impl Evaluator {
                         }
                     }
                     Lit::F32(f) => Value::from_f32(Some(-f)),
+                    Lit::F16(f) => Some(Value::Lit(Lit::F16(-f))),
                     _ => {
                         panic!(); // can't negate other types
                     }
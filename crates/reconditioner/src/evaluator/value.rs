@@ -40,4 +40,29 @@ impl Value {
     pub fn from_bool(val: Option<bool>) -> Option<Value> {
         val.map(|i| Value::Lit(Lit::Bool(i)))
     }
+
+    /// Returns the components of a vector value which are selected by a swizzle (e.g. `zyx` or
+    /// `rrg`).
+    pub fn swizzle(&self, member: &str) -> Option<Value> {
+        let Value::Vector(components) = self else {
+            return None;
+        };
+
+        let mut selected = member
+            .chars()
+            .map(|c| match c {
+                'x' | 'r' => components.first().cloned(),
+                'y' | 'g' => components.get(1).cloned(),
+                'z' | 'b' => components.get(2).cloned(),
+                'w' | 'a' => components.get(3).cloned(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        if selected.len() == 1 {
+            selected.pop()
+        } else {
+            Some(Value::Vector(selected))
+        }
+    }
 }
@@ -0,0 +1,483 @@
+//! Inlining of safe wrappers at their call sites, so that reconditioning doesn't add helper
+//! functions to the module.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use ast::*;
+
+/// Maximum number of expression nodes that inlining a single call may duplicate.
+///
+/// Wrappers often use their arguments several times, so inlining calls with large arguments (or
+/// nested calls) would blow up the size of the program. Calls which exceed the limit are left as
+/// they are, and the corresponding wrappers are still emitted as functions.
+const MAX_DUPLICATED_NODES: usize = 16;
+
+/// Inlines calls to `wrappers` in the functions of `module`, returning the wrappers which are
+/// still called.
+pub fn inline_wrappers(wrappers: Vec<FnDecl>, module: &mut Module) -> Vec<FnDecl> {
+    let mut inliner = Inliner {
+        wrappers: wrappers
+            .into_iter()
+            .map(|it| (it.name.clone(), (it, false)))
+            .collect(),
+        constants: module
+            .consts
+            .iter()
+            .map(|it| it.name.clone())
+            .chain(module.overrides.iter().map(|it| it.name.clone()))
+            .collect(),
+    };
+
+    for function in &mut module.functions {
+        inliner.stmts(&mut function.body);
+    }
+
+    let mut remaining = inliner
+        .wrappers
+        .into_values()
+        .filter(|(_, used)| *used)
+        .map(|(decl, _)| decl)
+        .collect::<Vec<_>>();
+
+    remaining.sort_by(|a, b| a.name.cmp(&b.name));
+    remaining
+}
+
+struct Inliner {
+    /// Wrapper declarations by name, along with whether any calls to them remain.
+    wrappers: HashMap<String, (FnDecl, bool)>,
+    /// Names of module and function scope constants, and of overrides, which may be evaluated when
+    /// the pipeline is created. Shadowing isn't taken into account, so any variable with one of
+    /// these names is assumed to be constant.
+    constants: HashSet<String>,
+}
+
+impl Inliner {
+    fn stmts(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::LetDecl(stmt) => self.expr(&mut stmt.initializer),
+            Statement::ConstDecl(stmt) => {
+                self.constants.insert(stmt.ident.clone());
+            }
+            Statement::ConstAssert(_) => {}
+            Statement::VarDecl(stmt) => {
+                if let Some(initializer) = &mut stmt.initializer {
+                    self.expr(initializer);
+                }
+            }
+            Statement::Assignment(stmt) => {
+                if let AssignmentLhs::Expr(lhs) = &mut stmt.lhs {
+                    self.lhs_expr(lhs);
+                }
+
+                self.expr(&mut stmt.rhs);
+            }
+            Statement::Increment(lhs) | Statement::Decrement(lhs) => self.lhs_expr(lhs),
+            Statement::Compound(stmts) => self.stmts(stmts),
+            Statement::If(stmt) => self.if_stmt(stmt),
+            Statement::Return(stmt) => {
+                if let Some(value) = &mut stmt.value {
+                    self.expr(value);
+                }
+            }
+            Statement::Loop(stmt) => {
+                self.stmts(&mut stmt.body);
+
+                if let Some(continuing) = &mut stmt.continuing {
+                    self.stmts(&mut continuing.body);
+
+                    if let Some(condition) = &mut continuing.break_if {
+                        self.expr(condition);
+                    }
+                }
+            }
+            Statement::Switch(stmt) => {
+                self.expr(&mut stmt.selector);
+
+                for case in &mut stmt.cases {
+                    self.stmts(&mut case.body);
+                }
+
+                self.stmts(&mut stmt.default);
+            }
+            Statement::ForLoop(stmt) => {
+                if let Some(ForLoopInit::VarDecl(decl)) = &mut stmt.header.init {
+                    if let Some(initializer) = &mut decl.initializer {
+                        self.expr(initializer);
+                    }
+                }
+
+                if let Some(condition) = &mut stmt.header.condition {
+                    self.expr(condition);
+                }
+
+                match &mut stmt.header.update {
+                    Some(ForLoopUpdate::Assignment(stmt)) => {
+                        if let AssignmentLhs::Expr(lhs) = &mut stmt.lhs {
+                            self.lhs_expr(lhs);
+                        }
+
+                        self.expr(&mut stmt.rhs);
+                    }
+                    Some(ForLoopUpdate::Increment(lhs) | ForLoopUpdate::Decrement(lhs)) => {
+                        self.lhs_expr(lhs)
+                    }
+                    None => {}
+                }
+
+                self.stmts(&mut stmt.body);
+            }
+            Statement::While(stmt) => {
+                self.expr(&mut stmt.condition);
+                self.stmts(&mut stmt.body);
+            }
+            Statement::FnCall(stmt) => {
+                for arg in &mut stmt.args {
+                    self.expr(arg);
+                }
+            }
+            Statement::Break | Statement::Continue | Statement::Fallthrough => {}
+        }
+    }
+
+    fn if_stmt(&mut self, stmt: &mut IfStatement) {
+        self.expr(&mut stmt.condition);
+        self.stmts(&mut stmt.body);
+
+        match stmt.else_.as_deref_mut() {
+            Some(Else::If(stmt)) => self.if_stmt(stmt),
+            Some(Else::Else(stmts)) => self.stmts(stmts),
+            None => {}
+        }
+    }
+
+    fn lhs_expr(&mut self, node: &mut LhsExprNode) {
+        match &mut node.expr {
+            LhsExpr::Ident(_) => {}
+            LhsExpr::Postfix(inner, postfix) => {
+                self.lhs_expr(inner);
+
+                if let Postfix::Index(index) = postfix {
+                    self.expr(index);
+                }
+            }
+            LhsExpr::Deref(inner) | LhsExpr::AddressOf(inner) => self.lhs_expr(inner),
+        }
+    }
+
+    fn expr(&mut self, node: &mut ExprNode) {
+        match &mut node.expr {
+            Expr::Lit(_) | Expr::Var(_) => {}
+            Expr::TypeCons(expr) => {
+                for arg in &mut expr.args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Bitcast(expr) => self.expr(&mut expr.inner),
+            Expr::Postfix(expr) => {
+                self.expr(&mut expr.inner);
+
+                if let Postfix::Index(index) = &mut expr.postfix {
+                    self.expr(index);
+                }
+            }
+            Expr::UnOp(expr) => self.expr(&mut expr.inner),
+            Expr::BinOp(expr) => {
+                self.expr(&mut expr.left);
+                self.expr(&mut expr.right);
+            }
+            Expr::FnCall(expr) => {
+                // Arguments are inlined first, so that the size of a call includes any wrappers
+                // that were inlined into its arguments.
+                for arg in &mut expr.args {
+                    self.expr(arg);
+                }
+
+                if let Some(inlined) = self.inline_call(expr) {
+                    node.expr = inlined.expr;
+                }
+            }
+        }
+    }
+
+    /// Returns the body of the called wrapper with its parameters replaced by the arguments, or
+    /// `None` if the call shouldn't be inlined.
+    fn inline_call(&mut self, call: &FnCallExpr) -> Option<ExprNode> {
+        let (decl, _) = self.wrappers.get(&call.ident)?;
+        let inputs = decl
+            .inputs
+            .iter()
+            .map(|it| it.name.clone())
+            .collect::<Vec<_>>();
+        let body = return_expr(decl);
+
+        // Arguments which are evaluated more than once (or not at all) must not have side effects,
+        // and neither may all but one of the arguments, since the body might evaluate them in a
+        // different order.
+        let impure_args = call.args.iter().filter(|it| !is_pure(it)).count();
+
+        let mut bindings = HashMap::new();
+        let mut duplicated_nodes = 0;
+
+        for (input, arg) in inputs.iter().zip(&call.args) {
+            let uses = count_uses(&body, input);
+
+            if (uses != 1 || impure_args > 1) && !is_pure(arg) {
+                return self.keep_call(call);
+            }
+
+            duplicated_nodes += uses.saturating_sub(1) * size(arg);
+            bindings.insert(input.clone(), arg.clone());
+        }
+
+        // Wrappers rely on being evaluated at runtime, e.g. to check whether an addition overflows,
+        // whereas an inlined expression whose operands are constant would be evaluated at shader
+        // creation time and could fail to compile. Calls with only constant arguments are also
+        // kept, since otherwise the expression around the call could become constant.
+        let constant_params: HashSet<&str> = inputs
+            .iter()
+            .zip(&call.args)
+            .filter(|(_, arg)| !self.is_runtime(arg))
+            .map(|(input, _)| input.as_str())
+            .collect();
+
+        if duplicated_nodes > MAX_DUPLICATED_NODES
+            || constant_params.len() == call.args.len()
+            || !is_safe_to_fold(&body, &constant_params).1
+        {
+            return self.keep_call(call);
+        }
+
+        Some(substitute(&body, &bindings))
+    }
+
+    /// Marks the called wrapper as used, so that it is still emitted.
+    fn keep_call(&mut self, call: &FnCallExpr) -> Option<ExprNode> {
+        self.wrappers.get_mut(&call.ident).unwrap().1 = true;
+        None
+    }
+
+    /// Returns true if an expression is evaluated at runtime, since it reads a variable or calls a
+    /// function.
+    fn is_runtime(&self, node: &ExprNode) -> bool {
+        match &node.expr {
+            Expr::Lit(_) => false,
+            Expr::Var(expr) => !self.constants.contains(&expr.ident),
+            Expr::TypeCons(expr) => expr.args.iter().any(|it| self.is_runtime(it)),
+            Expr::Bitcast(expr) => self.is_runtime(&expr.inner),
+            Expr::Postfix(expr) => {
+                self.is_runtime(&expr.inner)
+                    || matches!(&expr.postfix, Postfix::Index(index) if self.is_runtime(index))
+            }
+            Expr::UnOp(expr) => self.is_runtime(&expr.inner),
+            Expr::BinOp(expr) => self.is_runtime(&expr.left) || self.is_runtime(&expr.right),
+            Expr::FnCall(expr) => {
+                BuiltinFn::from_str(&expr.ident).is_err()
+                    || expr.args.iter().any(|it| self.is_runtime(it))
+            }
+        }
+    }
+}
+
+/// Returns the expression returned by a wrapper, with any `let` declarations substituted into it.
+///
+/// Wrapper bodies consist of `let` declarations followed by a `return`, so the declarations can be
+/// substituted in order.
+fn return_expr(decl: &FnDecl) -> ExprNode {
+    let mut bindings = HashMap::new();
+
+    for stmt in &decl.body {
+        match stmt {
+            Statement::LetDecl(stmt) => {
+                let value = substitute(&stmt.initializer, &bindings);
+                bindings.insert(stmt.ident.clone(), value);
+            }
+            Statement::Return(ReturnStatement { value: Some(value) }) => {
+                return substitute(value, &bindings);
+            }
+            _ => unreachable!("unexpected statement in wrapper `{}`", decl.name),
+        }
+    }
+
+    unreachable!("wrapper `{}` has no return statement", decl.name)
+}
+
+/// Returns the number of references to `ident` in an expression.
+fn count_uses(node: &ExprNode, ident: &str) -> usize {
+    match &node.expr {
+        Expr::Lit(_) => 0,
+        Expr::Var(expr) => (expr.ident == ident) as usize,
+        Expr::TypeCons(expr) => expr.args.iter().map(|it| count_uses(it, ident)).sum(),
+        Expr::Bitcast(expr) => count_uses(&expr.inner, ident),
+        Expr::Postfix(expr) => {
+            count_uses(&expr.inner, ident)
+                + match &expr.postfix {
+                    Postfix::Index(index) => count_uses(index, ident),
+                    Postfix::Member(_) => 0,
+                }
+        }
+        Expr::UnOp(expr) => count_uses(&expr.inner, ident),
+        Expr::BinOp(expr) => count_uses(&expr.left, ident) + count_uses(&expr.right, ident),
+        Expr::FnCall(expr) => expr.args.iter().map(|it| count_uses(it, ident)).sum(),
+    }
+}
+
+/// Returns the number of nodes in an expression.
+fn size(node: &ExprNode) -> usize {
+    1 + match &node.expr {
+        Expr::Lit(_) | Expr::Var(_) => 0,
+        Expr::TypeCons(expr) => expr.args.iter().map(size).sum(),
+        Expr::Bitcast(expr) => size(&expr.inner),
+        Expr::Postfix(expr) => {
+            size(&expr.inner)
+                + match &expr.postfix {
+                    Postfix::Index(index) => size(index),
+                    Postfix::Member(_) => 0,
+                }
+        }
+        Expr::UnOp(expr) => size(&expr.inner),
+        Expr::BinOp(expr) => size(&expr.left) + size(&expr.right),
+        Expr::FnCall(expr) => expr.args.iter().map(size).sum(),
+    }
+}
+
+/// Returns true if evaluating an expression has no side effects.
+///
+/// Calls to user-defined functions and atomic builtins are conservatively assumed to have side
+/// effects, while the remaining builtins and the safe wrappers don't.
+fn is_pure(node: &ExprNode) -> bool {
+    match &node.expr {
+        Expr::Lit(_) | Expr::Var(_) => true,
+        Expr::TypeCons(expr) => expr.args.iter().all(is_pure),
+        Expr::Bitcast(expr) => is_pure(&expr.inner),
+        Expr::Postfix(expr) => {
+            is_pure(&expr.inner)
+                && match &expr.postfix {
+                    Postfix::Index(index) => is_pure(index),
+                    Postfix::Member(_) => true,
+                }
+        }
+        Expr::UnOp(expr) => is_pure(&expr.inner),
+        Expr::BinOp(expr) => is_pure(&expr.left) && is_pure(&expr.right),
+        Expr::FnCall(expr) => {
+            let is_pure_fn = expr.ident.starts_with("_wgslsmith_")
+                || (BuiltinFn::from_str(&expr.ident).is_ok() && !expr.ident.starts_with("atomic"));
+            is_pure_fn && expr.args.iter().all(is_pure)
+        }
+    }
+}
+
+/// Checks that the parts of a wrapper body which only depend on `constant_params` can be
+/// evaluated at shader creation time without failing, i.e. they don't contain any arithmetic that
+/// could overflow or builtins with restricted domains.
+///
+/// Returns whether the expression is constant, and whether it is safe.
+fn is_safe_to_fold(node: &ExprNode, constant_params: &HashSet<&str>) -> (bool, bool) {
+    let children = |nodes: &mut dyn Iterator<Item = &ExprNode>| {
+        nodes
+            .map(|it| is_safe_to_fold(it, constant_params))
+            .fold((true, true), |(c, s), (cc, cs)| (c && cc, s && cs))
+    };
+
+    let (is_constant, is_safe, is_safe_op) = match &node.expr {
+        Expr::Lit(_) => return (true, true),
+        Expr::Var(expr) => return (constant_params.contains(expr.ident.as_str()), true),
+        Expr::TypeCons(expr) => {
+            let (c, s) = children(&mut expr.args.iter());
+            (c, s, true)
+        }
+        Expr::Bitcast(expr) => {
+            let (c, s) = is_safe_to_fold(&expr.inner, constant_params);
+            (c, s, false)
+        }
+        Expr::Postfix(expr) => {
+            let (c, s) = match &expr.postfix {
+                Postfix::Index(index) => children(&mut [&*expr.inner, &**index].into_iter()),
+                Postfix::Member(_) => is_safe_to_fold(&expr.inner, constant_params),
+            };
+            (c, s, matches!(expr.postfix, Postfix::Member(_)))
+        }
+        Expr::UnOp(expr) => {
+            let (c, s) = is_safe_to_fold(&expr.inner, constant_params);
+            (c, s, expr.op != UnOp::Neg)
+        }
+        Expr::BinOp(expr) => {
+            let (c, s) = children(&mut [&*expr.left, &*expr.right].into_iter());
+            let is_arithmetic = matches!(
+                expr.op,
+                BinOp::Plus
+                    | BinOp::Minus
+                    | BinOp::Times
+                    | BinOp::Divide
+                    | BinOp::Mod
+                    | BinOp::LShift
+                    | BinOp::RShift
+            );
+            (c, s, !is_arithmetic)
+        }
+        Expr::FnCall(expr) => {
+            let (c, s) = children(&mut expr.args.iter());
+            let is_safe_fn = matches!(
+                expr.ident.as_str(),
+                "abs" | "all" | "any" | "max" | "min" | "select"
+            );
+            (c, s, is_safe_fn)
+        }
+    };
+
+    (is_constant, is_safe && (!is_constant || is_safe_op))
+}
+
+/// Replaces references to the variables in `bindings` with their values.
+fn substitute(node: &ExprNode, bindings: &HashMap<String, ExprNode>) -> ExprNode {
+    let expr = match &node.expr {
+        Expr::Var(expr) => match bindings.get(&expr.ident) {
+            Some(value) => return value.clone(),
+            None => Expr::Var(expr.clone()),
+        },
+        Expr::Lit(lit) => Expr::Lit(*lit),
+        Expr::TypeCons(expr) => Expr::TypeCons(TypeConsExpr::new(
+            expr.data_type.clone(),
+            expr.args
+                .iter()
+                .map(|it| substitute(it, bindings))
+                .collect(),
+        )),
+        Expr::Bitcast(expr) => Expr::Bitcast(BitcastExpr::new(
+            expr.data_type.clone(),
+            substitute(&expr.inner, bindings),
+        )),
+        Expr::Postfix(expr) => Expr::Postfix(PostfixExpr::new(
+            substitute(&expr.inner, bindings),
+            match &expr.postfix {
+                Postfix::Index(index) => Postfix::index(substitute(index, bindings)),
+                Postfix::Member(member) => Postfix::Member(member.clone()),
+            },
+        )),
+        Expr::UnOp(expr) => Expr::UnOp(UnOpExpr::new(expr.op, substitute(&expr.inner, bindings))),
+        Expr::BinOp(expr) => Expr::BinOp(BinOpExpr::new(
+            expr.op,
+            substitute(&expr.left, bindings),
+            substitute(&expr.right, bindings),
+        )),
+        Expr::FnCall(expr) => Expr::FnCall(FnCallExpr::new(
+            expr.ident.clone(),
+            expr.args
+                .iter()
+                .map(|it| substitute(it, bindings))
+                .collect(),
+        )),
+    };
+
+    ExprNode {
+        data_type: node.data_type.clone(),
+        expr,
+    }
+}
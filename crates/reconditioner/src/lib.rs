@@ -1,21 +1,51 @@
+mod alias;
+mod barriers;
+mod inline;
+mod overrides;
 mod safe_wrappers;
 
 pub mod analysis;
 pub mod cli;
 pub mod evaluator;
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Display;
 
 use ast::types::{DataType, MemoryViewType, ScalarType};
 use ast::*;
+use serde::Serialize;
 
 pub struct ReconditionResult {
     pub ast: Module,
-    pub loop_count: u32,
+    pub report: Report,
 }
 
-#[derive(Hash, PartialEq, Eq)]
+/// Summary of the transformations applied by the reconditioner, for measuring how much a program
+/// was changed.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Number of calls to each kind of wrapper. Clamped indices are counted under `index`.
+    pub wrappers: BTreeMap<WrapperKind, u32>,
+    /// Number of loops which were bounded with a counter.
+    pub loops: u32,
+    /// Number of checks of the fuel counter, if enabled.
+    pub fuel_checks: u32,
+    /// Number of calls to `atomicStore`, `atomicExchange` and `atomicCompareExchangeWeak`, which
+    /// leave a value in memory that depends on the order in which invocations execute them.
+    pub non_commutative_atomics: u32,
+    /// Number of pointer arguments which were replaced with pointers to temporaries, since they
+    /// aliased another argument of the same call.
+    pub aliased_pointers: u32,
+    /// Number of barriers which were moved out of non-uniform control flow.
+    pub hoisted_barriers: u32,
+    /// Number of barriers in non-uniform control flow which couldn't be moved, and were removed.
+    pub removed_barriers: u32,
+    /// Number of clamped overrides which were declared for overrides used as sizes or in loop
+    /// conditions.
+    pub clamped_overrides: u32,
+}
+
+#[derive(Clone, Hash, PartialEq, Eq)]
 enum Wrapper {
     Dot(DataType),
     ExtractBits(DataType),
@@ -25,11 +55,107 @@ enum Wrapper {
     Select(DataType, DataType),
     Mod(DataType),
     Index(DataType),
+    Bitcast(DataType, DataType),
+    Pack2x16Float,
+    Unpack2x16Float,
+    Positive(DataType),
+    Normalize(DataType),
+    Length(DataType),
+    Distance(DataType),
+    Clamp(DataType),
+    Smoothstep(DataType),
+    Ldexp(DataType),
+    AtomicResult(DataType),
+    Canonicalize(DataType),
+    Quantize(DataType, u32),
+    ShiftAmount(DataType),
+}
+
+/// The kinds of safe wrapper that the reconditioner can insert, which can be disabled individually
+/// (e.g. to reproduce a bug that the wrapper would otherwise hide).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WrapperKind {
+    Dot,
+    ExtractBits,
+    InsertBits,
+    /// Canonicalization of the results of floating point operations.
+    FloatOp,
+    FloatDivide,
+    Select,
+    /// Integer and floating point remainders.
+    Mod,
+    /// Clamping of array and vector indices.
+    Index,
+    /// Bitcasts to or from floating point types.
+    Bitcast,
+    #[clap(name = "pack2x16float")]
+    #[serde(rename = "pack2x16float")]
+    Pack2x16Float,
+    #[clap(name = "unpack2x16float")]
+    #[serde(rename = "unpack2x16float")]
+    Unpack2x16Float,
+    /// Replacement of non-positive arguments to `pow`, `log`, `log2`, `sqrt` and `inverseSqrt`.
+    Positive,
+    Normalize,
+    Length,
+    Distance,
+    Clamp,
+    Smoothstep,
+    Ldexp,
+    /// Replacement of the values returned by atomic builtins.
+    AtomicResult,
+    /// Replacement of NaN and infinite results, if enabled.
+    Canonicalize,
+    /// Rounding of floats stored to output buffers, if enabled.
+    Quantize,
+    /// Clamping of the components of vector shift amounts.
+    ShiftAmount,
 }
 
 impl Wrapper {
-    fn gen_fn_decl(&self) -> FnDecl {
-        let name = self.to_string();
+    fn kind(&self) -> WrapperKind {
+        match self {
+            Wrapper::Dot(_) => WrapperKind::Dot,
+            Wrapper::ExtractBits(_) => WrapperKind::ExtractBits,
+            Wrapper::InsertBits(_) => WrapperKind::InsertBits,
+            Wrapper::FloatOp(_) => WrapperKind::FloatOp,
+            Wrapper::FloatDivide(_) => WrapperKind::FloatDivide,
+            Wrapper::Select(_, _) => WrapperKind::Select,
+            Wrapper::Mod(_) => WrapperKind::Mod,
+            Wrapper::Index(_) => WrapperKind::Index,
+            Wrapper::Bitcast(_, _) => WrapperKind::Bitcast,
+            Wrapper::Pack2x16Float => WrapperKind::Pack2x16Float,
+            Wrapper::Unpack2x16Float => WrapperKind::Unpack2x16Float,
+            Wrapper::Positive(_) => WrapperKind::Positive,
+            Wrapper::Normalize(_) => WrapperKind::Normalize,
+            Wrapper::Length(_) => WrapperKind::Length,
+            Wrapper::Distance(_) => WrapperKind::Distance,
+            Wrapper::Clamp(_) => WrapperKind::Clamp,
+            Wrapper::Smoothstep(_) => WrapperKind::Smoothstep,
+            Wrapper::Ldexp(_) => WrapperKind::Ldexp,
+            Wrapper::AtomicResult(_) => WrapperKind::AtomicResult,
+            Wrapper::Canonicalize(_) => WrapperKind::Canonicalize,
+            Wrapper::Quantize(_, _) => WrapperKind::Quantize,
+            Wrapper::ShiftAmount(_) => WrapperKind::ShiftAmount,
+        }
+    }
+
+    /// Returns the name of the wrapper, optionally suffixed with a hash of its declaration.
+    fn name(&self, hashed: bool) -> String {
+        if !hashed {
+            return self.to_string();
+        }
+
+        let mut decl = String::new();
+        ast::writer::Writer
+            .write_func(&mut decl, &self.gen_fn_decl(self.to_string()))
+            .unwrap();
+
+        format!("{self}_{:08x}", fnv1a(decl.as_bytes()) as u32)
+    }
+
+    fn gen_fn_decl(&self, name: String) -> FnDecl {
         match self {
             Wrapper::Dot(ty) => safe_wrappers::dot(name, ty),
             Wrapper::ExtractBits(ty) => {
@@ -45,6 +171,20 @@ impl Wrapper {
             Wrapper::Select(ty, cond_ty) => safe_wrappers::select(name, ty, cond_ty),
             Wrapper::Mod(ty) => safe_wrappers::modulo(name, ty),
             Wrapper::Index(ty) => safe_wrappers::index(name, ty),
+            Wrapper::Bitcast(ty, source_ty) => safe_wrappers::bitcast(name, ty, source_ty),
+            Wrapper::Pack2x16Float => safe_wrappers::pack2x16float(name),
+            Wrapper::Unpack2x16Float => safe_wrappers::unpack2x16float(name),
+            Wrapper::Positive(ty) => safe_wrappers::positive(name, ty),
+            Wrapper::Normalize(ty) => safe_wrappers::normalize(name, ty),
+            Wrapper::Length(ty) => safe_wrappers::length(name, ty),
+            Wrapper::Distance(ty) => safe_wrappers::distance(name, ty),
+            Wrapper::Clamp(ty) => safe_wrappers::clamp(name, ty),
+            Wrapper::Smoothstep(ty) => safe_wrappers::smoothstep(name, ty),
+            Wrapper::Ldexp(ty) => safe_wrappers::ldexp(name, ty),
+            Wrapper::AtomicResult(ty) => safe_wrappers::atomic_result(name, ty),
+            Wrapper::Canonicalize(ty) => safe_wrappers::canonicalize(name, ty),
+            Wrapper::Quantize(ty, bits) => safe_wrappers::quantize(name, ty, *bits),
+            Wrapper::ShiftAmount(ty) => safe_wrappers::shift_amount(name, ty),
         }
     }
 }
@@ -52,11 +192,19 @@ impl Wrapper {
 impl Display for Wrapper {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // helper func
-        let write_type = |f: &mut std::fmt::Formatter<'_>, ty: &DataType| match ty {
-            DataType::Scalar(s) => write!(f, "{s}"),
-            DataType::Vector(n, s) => write!(f, "vec{n}_{s}"),
-            _ => unimplemented!("no wrappers available for expressions of type `{ty}`"),
-        };
+        fn write_type(f: &mut std::fmt::Formatter<'_>, ty: &DataType) -> std::fmt::Result {
+            match ty {
+                DataType::Scalar(s) => write!(f, "{s}"),
+                DataType::Vector(n, s) => write!(f, "vec{n}_{s}"),
+                // Only quantization wrappers are generated for structs and arrays.
+                DataType::Struct(decl) => write!(f, "{}", decl.name),
+                DataType::Array(element_type, Some(n)) => {
+                    write!(f, "array{n}_")?;
+                    write_type(f, element_type)
+                }
+                _ => unimplemented!("no wrappers available for expressions of type `{ty}`"),
+            }
+        }
 
         write!(f, "_wgslsmith_")?;
 
@@ -67,6 +215,14 @@ impl Display for Wrapper {
                 write!(f, "_")?;
                 write_type(f, cond_ty)
             }
+            Wrapper::Bitcast(ty, source_ty) => {
+                write!(f, "bitcast_")?;
+                write_type(f, ty)?;
+                write!(f, "_")?;
+                write_type(f, source_ty)
+            }
+            Wrapper::Pack2x16Float => write!(f, "pack2x16float"),
+            Wrapper::Unpack2x16Float => write!(f, "unpack2x16float"),
             other => {
                 let (name, ty) = match other {
                     Wrapper::Dot(ty) => ("dot", ty),
@@ -76,7 +232,21 @@ impl Display for Wrapper {
                     Wrapper::FloatDivide(ty) => ("div", ty),
                     Wrapper::Mod(ty) => ("mod", ty),
                     Wrapper::Index(ty) => ("index", ty),
-                    Wrapper::Select(..) => unreachable!(),
+                    Wrapper::Positive(ty) => ("positive", ty),
+                    Wrapper::Normalize(ty) => ("normalize", ty),
+                    Wrapper::Length(ty) => ("length", ty),
+                    Wrapper::Distance(ty) => ("distance", ty),
+                    Wrapper::Clamp(ty) => ("clamp", ty),
+                    Wrapper::Smoothstep(ty) => ("smoothstep", ty),
+                    Wrapper::Ldexp(ty) => ("ldexp", ty),
+                    Wrapper::AtomicResult(ty) => ("atomic_result", ty),
+                    Wrapper::Canonicalize(ty) => ("canonicalize", ty),
+                    Wrapper::Quantize(ty, _) => ("quantize", ty),
+                    Wrapper::ShiftAmount(ty) => ("shift_amount", ty),
+                    | Wrapper::Select(..)
+                    | Wrapper::Bitcast(..)
+                    | Wrapper::Pack2x16Float
+                    | Wrapper::Unpack2x16Float => unreachable!(),
                 };
 
                 write!(f, "{name}_")?;
@@ -86,33 +256,154 @@ impl Display for Wrapper {
     }
 }
 
-#[derive(Default)]
+/// 64-bit FNV-1a, which is used instead of the std hasher since its output must not change
+/// between runs or Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 pub struct Options {
     pub only_loops: bool,
+    /// The maximum number of iterations that each loop may execute in total.
+    ///
+    /// Loop counters are never reset, so this bounds the iterations across all executions of a
+    /// loop by an invocation, rather than per execution.
+    pub loop_budget: u32,
+    /// If set, a module-wide counter is decremented on each function call and loop iteration,
+    /// and functions return zeros once it reaches zero.
+    ///
+    /// This bounds the total amount of work done by an invocation, regardless of how loops and
+    /// calls are nested.
+    pub fuel: Option<u32>,
+    /// Wrappers which shouldn't be inserted, leaving the corresponding operations unchanged.
+    pub disabled_wrappers: HashSet<WrapperKind>,
+    /// Inline calls to wrappers where possible, rather than only emitting them as functions.
+    pub inline_wrappers: bool,
+    /// Suffix wrapper names with a hash of their declaration, so that wrappers from different
+    /// runs (or versions of the reconditioner) only share a name if they are identical.
+    pub hash_wrapper_names: bool,
+    /// Replace NaN and infinite results of `f32` operations with zero and the largest finite value
+    /// respectively, before they are passed through the float wrapper.
+    pub canonicalize_floats: bool,
+    /// If set, `f32` values are rounded to this many mantissa bits before they are stored to
+    /// storage buffers, so that outputs can be compared despite small precision differences
+    /// between implementations.
+    pub quantize_floats: Option<u32>,
 }
 
-pub fn recondition(ast: Module) -> Module {
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            only_loops: false,
+            loop_budget: 1,
+            fuel: None,
+            disabled_wrappers: HashSet::new(),
+            inline_wrappers: false,
+            hash_wrapper_names: false,
+            canonicalize_floats: false,
+            quantize_floats: None,
+        }
+    }
+}
+
+pub fn recondition(ast: Module) -> ReconditionResult {
     recondition_with(ast, Options::default())
 }
 
-pub fn recondition_with(mut ast: Module, options: Options) -> Module {
+pub fn recondition_with(mut ast: Module, options: Options) -> ReconditionResult {
+    let inline_wrappers = options.inline_wrappers;
     let mut reconditioner = Reconditioner::new(options);
 
     // Abstract numerics
     ast = evaluator::concretize(ast);
 
-    let functions = ast
+    // Aliased pointer arguments would make the module invalid, regardless of other reconditioning.
+    reconditioner.report.aliased_pointers = alias::legalize_aliasing(&mut ast);
+
+    // Overrides which control the amount of work done are clamped, since the harness may pick any
+    // value for them. This is done before barriers are hoisted, so that the clamped overrides are
+    // known to be uniform.
+    overrides::clamp_overrides(&mut ast, &mut reconditioner.report);
+
+    // Likewise for barriers in non-uniform control flow, which would fail uniformity analysis.
+    barriers::hoist_barriers(&mut ast, &mut reconditioner.report);
+
+    // Wrappers left over from a previous reconditioning (e.g. of a reduced variant) are set aside
+    // rather than reconditioned, and calls to them are treated as already safe.
+    let (existing_wrappers, functions): (Vec<_>, Vec<_>) = ast
         .functions
+        .into_iter()
+        .partition(|it| it.name.starts_with("_wgslsmith_"));
+
+    reconditioner.existing_wrappers = existing_wrappers.iter().map(|it| it.name.clone()).collect();
+
+    // Likewise, new loop counters are allocated after any existing ones, in the same array.
+    if let Some(i) = ast.vars.iter().position(|it| it.name == "LOOP_COUNTERS") {
+        if let DataType::Array(_, Some(n)) = ast.vars.remove(i).data_type {
+            reconditioner.loop_var = n.value().unwrap_or(0);
+        }
+    }
+
+    let existing_loops = reconditioner.loop_var;
+
+    reconditioner.storage_buffers = ast
+        .vars
+        .iter()
+        .filter(|it| matches!(&it.qualifier, Some(q) if q.storage_class == StorageClass::Storage))
+        .map(|it| it.name.clone())
+        .collect();
+
+    reconditioner.overrides = ast.overrides.iter().map(|it| it.name.clone()).collect();
+
+    if reconditioner.fuel.is_some() {
+        ast.vars.retain(|it| it.name != "FUEL");
+    }
+
+    ast.functions = functions
         .into_iter()
         .map(|f| reconditioner.recondition_fn(f))
-        .collect::<Vec<_>>();
+        .collect();
 
-    ast.functions = reconditioner
+    let mut wrappers = reconditioner
         .wrappers
         .iter()
-        .map(Wrapper::gen_fn_decl)
-        .chain(functions)
-        .collect();
+        .map(|(wrapper, name)| wrapper.gen_fn_decl(name.clone()))
+        .collect::<Vec<_>>();
+
+    // Existing wrappers are only kept if they are still called, and are replaced by newly
+    // generated wrappers with the same name so that each is only declared once.
+    let new_names = wrappers
+        .iter()
+        .map(|it| it.name.clone())
+        .collect::<HashSet<_>>();
+
+    wrappers.extend(existing_wrappers.into_iter().filter(|it| {
+        reconditioner.used_wrappers.contains(&it.name) && !new_names.contains(&it.name)
+    }));
+
+    // Wrappers are sorted by name so that the output doesn't depend on the order of the set.
+    wrappers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if inline_wrappers {
+        wrappers = inline::inline_wrappers(wrappers, &mut ast);
+    }
+
+    ast.functions.splice(0..0, wrappers);
+
+    if let Some(fuel) = reconditioner.fuel {
+        ast.vars.push(GlobalVarDecl {
+            attrs: vec![],
+            data_type: ScalarType::U32.into(),
+            name: "FUEL".into(),
+            initializer: Some(Lit::U32(fuel).into()),
+            qualifier: Some(VarQualifier {
+                storage_class: StorageClass::Private,
+                access_mode: None,
+            }),
+        });
+    }
 
     if reconditioner.loop_var > 0 {
         ast.vars.push(GlobalVarDecl {
@@ -127,33 +418,109 @@ pub fn recondition_with(mut ast: Module, options: Options) -> Module {
         });
     }
 
-    ast
+    ReconditionResult {
+        ast,
+        report: Report {
+            loops: reconditioner.loop_var - existing_loops,
+            ..reconditioner.report
+        },
+    }
 }
 
 struct Reconditioner {
     loop_var: u32,
-    wrappers: HashSet<Wrapper>,
+    loop_budget: u32,
+    fuel: Option<u32>,
+    return_type: Option<DataType>,
+    wrappers: HashMap<Wrapper, String>,
+    existing_wrappers: HashSet<String>,
+    used_wrappers: HashSet<String>,
+    hash_wrapper_names: bool,
+    canonicalize_floats: bool,
+    quantize_floats: Option<u32>,
+    storage_buffers: HashSet<String>,
+    /// Names of the overrides declared by the module, including the clamped companions.
+    overrides: HashSet<String>,
+    disabled_wrappers: HashSet<WrapperKind>,
     only_loops: bool,
+    report: Report,
 }
 
 impl Reconditioner {
     fn new(options: Options) -> Reconditioner {
         Reconditioner {
             loop_var: 0,
-            wrappers: HashSet::new(),
+            loop_budget: options.loop_budget,
+            fuel: options.fuel,
+            return_type: None,
+            wrappers: HashMap::new(),
+            existing_wrappers: HashSet::new(),
+            used_wrappers: HashSet::new(),
+            hash_wrapper_names: options.hash_wrapper_names,
+            canonicalize_floats: options.canonicalize_floats,
+            quantize_floats: options.quantize_floats,
+            storage_buffers: HashSet::new(),
+            overrides: HashSet::new(),
+            disabled_wrappers: options.disabled_wrappers,
             only_loops: options.only_loops,
+            report: Report::default(),
         }
     }
 
     fn recondition_fn(&mut self, mut decl: FnDecl) -> FnDecl {
-        decl.body = decl
-            .body
+        self.return_type = decl.output.as_ref().map(|it| it.data_type.clone());
+
+        // Entrypoints are only called once, so they don't need to consume fuel. Skipping the check
+        // also keeps control flow uniform at the start of the entrypoint, where barriers may be
+        // used.
+        let is_entrypoint = decl.attrs.iter().any(|it| matches!(it, FnAttr::Stage(_)));
+        let fuel_check = if is_entrypoint {
+            vec![]
+        } else {
+            self.gen_fuel_check()
+        };
+
+        decl.body = fuel_check
             .into_iter()
-            .map(|s| self.recondition_stmt(s))
+            .chain(decl.body.into_iter().map(|s| self.recondition_stmt(s)))
             .collect();
         decl
     }
 
+    /// Generates statements which return zero from the current function if the module-wide fuel
+    /// counter has run out, and otherwise decrement it.
+    ///
+    /// Nothing is generated if fuel is disabled.
+    fn gen_fuel_check(&mut self) -> Vec<Statement> {
+        if self.fuel.is_none() {
+            return vec![];
+        }
+
+        self.report.fuel_checks += 1;
+
+        let fuel_ty = DataType::Ref(MemoryViewType::new(ScalarType::U32, StorageClass::Private));
+
+        let fuel = VarExpr::new("FUEL").into_node(fuel_ty.clone());
+
+        let return_stmt = match &self.return_type {
+            Some(ty) => ReturnStatement::new(TypeConsExpr::new(ty.clone(), vec![])),
+            None => ReturnStatement::none(),
+        };
+
+        let exhausted_check = IfStatement::new(
+            BinOpExpr::new(BinOp::Equal, fuel.clone(), Lit::U32(0)),
+            vec![return_stmt.into()],
+        );
+
+        let fuel_decrement = AssignmentStatement::new(
+            AssignmentLhs::name("FUEL", fuel_ty),
+            AssignmentOp::Simple,
+            BinOpExpr::new(BinOp::Minus, fuel, Lit::U32(1)),
+        );
+
+        vec![exhausted_check.into(), fuel_decrement.into()]
+    }
+
     fn recondition_else(&mut self, els: Else) -> Else {
         match els {
             Else::If(IfStatement {
@@ -179,6 +546,10 @@ impl Reconditioner {
             Statement::LetDecl(LetDeclStatement { ident, initializer }) => {
                 LetDeclStatement::new(ident, self.recondition_expr(initializer)).into()
             }
+            // Const initializers and asserts must be const-expressions, so they can't contain calls
+            // to safe wrappers.
+            Statement::ConstDecl(stmt) => stmt.into(),
+            Statement::ConstAssert(stmt) => stmt.into(),
             Statement::VarDecl(VarDeclStatement {
                 ident,
                 data_type,
@@ -189,14 +560,12 @@ impl Reconditioner {
                 initializer.map(|e| self.recondition_expr(e)),
             )
             .into(),
-            Statement::Assignment(AssignmentStatement { lhs, op, rhs }) => {
-                AssignmentStatement::new(
-                    self.recondition_assignment_lhs(lhs),
-                    op,
-                    self.recondition_expr(rhs),
-                )
-                .into()
+            Statement::Assignment(stmt) => {
+                let stmt = self.recondition_assignment(stmt);
+                self.quantize_store(stmt).into()
             }
+            Statement::Increment(lhs) => Statement::Increment(self.recondition_inc_dec_lhs(lhs)),
+            Statement::Decrement(lhs) => Statement::Decrement(self.recondition_inc_dec_lhs(lhs)),
             Statement::Compound(s) => {
                 Statement::Compound(s.into_iter().map(|s| self.recondition_stmt(s)).collect())
             }
@@ -214,8 +583,10 @@ impl Reconditioner {
                 value: value.map(|e| self.recondition_expr(e)),
             }
             .into(),
-            Statement::Loop(LoopStatement { body }) => {
-                LoopStatement::new(self.recondition_loop_body(body)).into()
+            Statement::Loop(LoopStatement { body, continuing }) => {
+                LoopStatement::new(self.recondition_loop_body(body))
+                    .with_continuing(continuing.map(|it| self.recondition_continuing(it)))
+                    .into()
             }
             Statement::Break => Statement::Break,
             Statement::Switch(SwitchStatement {
@@ -251,7 +622,16 @@ impl Reconditioner {
                 self.recondition_loop_body(body),
             )
             .into(),
+            Statement::While(WhileStatement { condition, body }) => WhileStatement::new(
+                self.recondition_expr(condition),
+                self.recondition_loop_body(body),
+            )
+            .into(),
             Statement::FnCall(FnCallStatement { ident, args }) => {
+                if is_non_commutative_atomic(&ident) {
+                    self.report.non_commutative_atomics += 1;
+                }
+
                 Statement::FnCall(FnCallStatement::new(
                     ident,
                     args.into_iter()
@@ -280,16 +660,31 @@ impl Reconditioner {
 
     fn recondition_for_update(&mut self, update: ForLoopUpdate) -> ForLoopUpdate {
         match update {
-            ForLoopUpdate::Assignment(AssignmentStatement { lhs, op, rhs }) => {
-                ForLoopUpdate::Assignment(AssignmentStatement::new(
-                    self.recondition_assignment_lhs(lhs),
-                    op,
-                    self.recondition_expr(rhs),
-                ))
+            ForLoopUpdate::Assignment(stmt) => {
+                ForLoopUpdate::Assignment(self.recondition_assignment(stmt))
+            }
+            ForLoopUpdate::Increment(lhs) => {
+                ForLoopUpdate::Increment(self.recondition_inc_dec_lhs(lhs))
+            }
+            ForLoopUpdate::Decrement(lhs) => {
+                ForLoopUpdate::Decrement(self.recondition_inc_dec_lhs(lhs))
             }
         }
     }
 
+    fn recondition_continuing(&mut self, continuing: ContinuingStatement) -> ContinuingStatement {
+        let ContinuingStatement { body, break_if } = continuing;
+        ContinuingStatement::new(
+            body.into_iter().map(|s| self.recondition_stmt(s)).collect(),
+            break_if.map(|e| self.recondition_expr(e)),
+        )
+    }
+
+    /// Inserts a check at the start of a loop body which breaks out of the loop once it has
+    /// executed `loop_budget` iterations, followed by an increment of the loop's counter.
+    ///
+    /// The check comes before any `continue` in the body, so the loop terminates regardless of
+    /// its form (`loop`, `for` or `while`) or what its `continuing` block does.
     fn recondition_loop_body(&mut self, body: Vec<Statement>) -> Vec<Statement> {
         let id = self.loop_var();
 
@@ -305,7 +700,7 @@ impl Reconditioner {
                     VarExpr::new("LOOP_COUNTERS").into_node(counters_ty.clone()),
                     Postfix::index(Lit::U32(id)),
                 ),
-                Lit::U32(1),
+                Lit::U32(self.loop_budget),
             ),
             vec![Statement::Break],
         );
@@ -325,10 +720,86 @@ impl Reconditioner {
 
         std::iter::once(break_check.into())
             .chain(std::iter::once(counter_increment.into()))
+            .chain(self.gen_fuel_check())
             .chain(body.into_iter().map(|s| self.recondition_stmt(s)))
             .collect()
     }
 
+    fn recondition_assignment(&mut self, stmt: AssignmentStatement) -> AssignmentStatement {
+        let AssignmentStatement { lhs, op, rhs } = stmt;
+
+        let lhs = self.recondition_assignment_lhs(lhs);
+        let rhs = self.recondition_expr(rhs);
+
+        if self.only_loops {
+            return AssignmentStatement::new(lhs, op, rhs);
+        }
+
+        let (AssignmentLhs::Expr(lhs_expr), Some(bin_op)) = (&lhs, op.bin_op()) else {
+            return AssignmentStatement::new(lhs, op, rhs);
+        };
+
+        let data_type = lhs_expr.data_type.dereference().clone();
+
+        match (data_type.as_scalar().unwrap(), bin_op) {
+            // Shifting by at least the bit width is an error, but we can keep the compound
+            // assignment by bounding the shift value.
+            (_, BinOp::LShift | BinOp::RShift) => {
+                let rhs = self.recondition_shift_value(&data_type, rhs);
+                AssignmentStatement::new(lhs, op, rhs)
+            }
+            // The result of these operations must be passed through a safe wrapper, so we rewrite
+            // `x op= y` as `x = wrapper(x, y)`. Note that this evaluates the lhs twice, which is
+            // fine since index expressions have already been made safe.
+            (ScalarType::F32 | ScalarType::F16, _)
+            | (ScalarType::I32 | ScalarType::U32, BinOp::Mod) => {
+                let value =
+                    self.recondition_bin_op_expr(data_type, bin_op, lhs_expr.clone().into(), rhs);
+
+                AssignmentStatement::new(lhs, AssignmentOp::Simple, value)
+            }
+            // Integer arithmetic wraps on overflow, so everything else is safe as is.
+            _ => AssignmentStatement::new(lhs, op, rhs),
+        }
+    }
+
+    /// Rounds floats which are stored to a storage buffer by the given assignment, if
+    /// quantization is enabled.
+    ///
+    /// Compound assignments to floats have already been rewritten as simple assignments, so only
+    /// those need to be considered.
+    fn quantize_store(&mut self, stmt: AssignmentStatement) -> AssignmentStatement {
+        let Some(bits) = self.quantize_floats else {
+            return stmt;
+        };
+
+        let AssignmentStatement { lhs, op, rhs } = stmt;
+        let AssignmentLhs::Expr(lhs_expr) = &lhs else {
+            return AssignmentStatement::new(lhs, op, rhs);
+        };
+
+        let data_type = lhs_expr.data_type.dereference().clone();
+        if self.only_loops
+            || op != AssignmentOp::Simple
+            || !self.storage_buffers.contains(root_ident(lhs_expr))
+            || !safe_wrappers::contains_f32(&data_type)
+        {
+            return AssignmentStatement::new(lhs, op, rhs);
+        }
+
+        let rhs = self.wrap_expr(Wrapper::Quantize(data_type, bits), rhs);
+        AssignmentStatement::new(lhs, op, rhs)
+    }
+
+    fn recondition_inc_dec_lhs(&mut self, lhs: LhsExprNode) -> LhsExprNode {
+        if self.only_loops {
+            return lhs;
+        }
+
+        // Increments and decrements are only defined for integers, for which they wrap around.
+        self.recondition_lhs_expr(lhs)
+    }
+
     fn recondition_assignment_lhs(&mut self, lhs: AssignmentLhs) -> AssignmentLhs {
         if self.only_loops {
             return lhs;
@@ -348,15 +819,18 @@ impl Reconditioner {
                 let postfix = match postfix {
                     Postfix::Index(index) => {
                         let index = self.recondition_expr(*index);
-                        Postfix::index(self.recondition_array_index(&expr.data_type, index))
+                        let array = ExprNode::from(expr.as_ref().clone());
+                        Postfix::index(self.recondition_array_index(&array, index))
                     }
                     Postfix::Member(ident) => Postfix::Member(ident),
                 };
 
                 LhsExpr::Postfix(expr, postfix)
             }
-            LhsExpr::Deref(_) => todo!(),
-            LhsExpr::AddressOf(_) => todo!(),
+            LhsExpr::Deref(expr) => LhsExpr::Deref(Box::new(self.recondition_lhs_expr(*expr))),
+            LhsExpr::AddressOf(expr) => {
+                LhsExpr::AddressOf(Box::new(self.recondition_lhs_expr(*expr)))
+            }
         };
 
         LhsExprNode { expr, ..node }
@@ -381,15 +855,12 @@ impl Reconditioner {
                 match op {
                     UnOp::Neg => {
                         let data_type = inner.data_type.dereference().clone();
-                        let mut expr = UnOpExpr::new(UnOp::Neg, inner).into();
-                        if data_type.as_scalar().unwrap() == ScalarType::F32 {
-                            expr = FnCallExpr::new(
-                                self.safe_wrapper(Wrapper::FloatOp(data_type.clone())),
-                                vec![ExprNode { data_type, expr }],
-                            )
-                            .into();
+                        let expr = ExprNode::from(UnOpExpr::new(UnOp::Neg, inner));
+                        if data_type.is_float() {
+                            self.wrap_expr(Wrapper::FloatOp(data_type), expr).expr
+                        } else {
+                            expr.expr
                         }
-                        expr
                     }
                     _ => UnOpExpr::new(op, inner).into(),
                 }
@@ -406,41 +877,113 @@ impl Reconditioner {
                     .map(|e| self.recondition_expr(e))
                     .collect();
 
+                let ty = args.first().map(|it| it.data_type.dereference().clone());
                 let expr = match expr.ident.as_str() {
-                    "dot" if args[0].data_type.is_integer() => FnCallExpr::new(
-                        self.safe_wrapper(Wrapper::Dot(args[0].data_type.dereference().clone())),
-                        args,
-                    ),
-                    "extractBits" => FnCallExpr::new(
-                        self.safe_wrapper(Wrapper::ExtractBits(
-                            args[0].data_type.dereference().clone(),
-                        )),
-                        args,
-                    ),
-                    "insertBits" if args[0].data_type.is_integer() => FnCallExpr::new(
-                        self.safe_wrapper(Wrapper::InsertBits(
-                            args[0].data_type.dereference().clone(),
-                        )),
-                        args,
-                    ),
-                    "select" => FnCallExpr::new(
-                        self.safe_wrapper(Wrapper::Select(
-                            args[0].data_type.dereference().clone(),
-                            args[2].data_type.dereference().clone(),
-                        )),
-                        args,
-                    ),
+                    "dot" if args[0].data_type.is_integer() => {
+                        self.call_wrapper(Wrapper::Dot(ty.unwrap()), expr.ident, args)
+                    }
+                    "extractBits" => {
+                        self.call_wrapper(Wrapper::ExtractBits(ty.unwrap()), expr.ident, args)
+                    }
+                    "insertBits" if args[0].data_type.is_integer() => {
+                        self.call_wrapper(Wrapper::InsertBits(ty.unwrap()), expr.ident, args)
+                    }
+                    "clamp" => self.call_wrapper(Wrapper::Clamp(ty.unwrap()), expr.ident, args),
+                    "smoothstep" => {
+                        self.call_wrapper(Wrapper::Smoothstep(ty.unwrap()), expr.ident, args)
+                    }
+                    "ldexp" => self.call_wrapper(Wrapper::Ldexp(ty.unwrap()), expr.ident, args),
+                    "select" => {
+                        let cond_ty = args[2].data_type.dereference().clone();
+                        self.call_wrapper(Wrapper::Select(ty.unwrap(), cond_ty), expr.ident, args)
+                    }
+                    // The members of the result are exact, but subnormal and infinite arguments
+                    // would be decomposed differently depending on the GPU.
+                    "frexp" | "modf" => {
+                        let arg = args.into_iter().next().unwrap();
+                        let arg = self.wrap_expr(Wrapper::FloatOp(ty.unwrap()), arg);
+                        FnCallExpr::new(expr.ident, vec![arg])
+                    }
+                    // These are only defined for positive arguments (or bases, for `pow`), and the
+                    // results for small arguments are sensitive to flushing subnormals.
+                    "pow" | "log" | "log2" | "sqrt" | "inverseSqrt" => {
+                        let mut args = args.into_iter();
+                        let arg =
+                            self.wrap_expr(Wrapper::Positive(ty.unwrap()), args.next().unwrap());
+                        FnCallExpr::new(expr.ident, std::iter::once(arg).chain(args).collect())
+                    }
+                    // The results of these are indeterminate (or computed inconsistently) for
+                    // vectors with zero length.
+                    "normalize" => {
+                        self.call_wrapper(Wrapper::Normalize(ty.unwrap()), expr.ident, args)
+                    }
+                    "length" => self.call_wrapper(Wrapper::Length(ty.unwrap()), expr.ident, args),
+                    "distance" => {
+                        self.call_wrapper(Wrapper::Distance(ty.unwrap()), expr.ident, args)
+                    }
+                    "pack2x16float" => self.call_wrapper(Wrapper::Pack2x16Float, expr.ident, args),
+                    "unpack2x16float" => {
+                        self.call_wrapper(Wrapper::Unpack2x16Float, expr.ident, args)
+                    }
+                    // The values returned by atomics depend on the order in which invocations access
+                    // them, so they are replaced. Calls whose result is unused are left unchanged.
+                    "atomicLoad" | "atomicAdd" | "atomicSub" | "atomicMax" | "atomicMin"
+                    | "atomicAnd" | "atomicOr" | "atomicXor" | "atomicExchange" => {
+                        if is_non_commutative_atomic(&expr.ident) {
+                            self.report.non_commutative_atomics += 1;
+                        }
+
+                        let call = FnCallExpr::new(expr.ident, args).into_node(node.data_type);
+                        return self.wrap_expr(Wrapper::AtomicResult(call.data_type.clone()), call);
+                    }
+                    // The result struct can't be passed to a function, so its members are replaced
+                    // where they are accessed instead.
+                    "atomicCompareExchangeWeak" => {
+                        self.report.non_commutative_atomics += 1;
+                        return FnCallExpr::new(expr.ident, args).into_node(node.data_type);
+                    }
+                    ident if self.existing_wrappers.contains(ident) => {
+                        self.used_wrappers.insert(expr.ident.clone());
+
+                        // The result of a wrapper is already safe, but a previous reconditioning
+                        // may have wrapped its argument in another call to the same wrapper.
+                        if args.len() == 1 && is_call_to(&args[0], &expr.ident) {
+                            return args.into_iter().next().unwrap();
+                        }
+
+                        return FnCallExpr::new(expr.ident, args).into_node(node.data_type);
+                    }
                     _ => FnCallExpr::new(expr.ident, args),
                 };
 
-                if matches!(node.data_type.as_scalar(), Some(ScalarType::F32)) {
-                    FnCallExpr::new(
-                        self.safe_wrapper(Wrapper::FloatOp(node.data_type.clone())),
-                        vec![expr.into_node(node.data_type.clone())],
-                    )
-                    .into()
+                let expr = expr.into_node(node.data_type.clone());
+                if node.data_type.is_float() {
+                    self.wrap_float_result(node.data_type.clone(), expr).expr
                 } else {
-                    expr.into()
+                    expr.expr
+                }
+            }
+            Expr::Bitcast(expr) => {
+                let inner = self.recondition_expr(*expr.inner);
+                let source_ty = inner.data_type.dereference().clone();
+
+                // Bitcasts between integers preserve every bit pattern, but anything involving
+                // floats must go through a wrapper that canonicalizes NaN/inf/subnormal values.
+                let wrapper = Wrapper::Bitcast(expr.data_type.clone(), source_ty.clone());
+                if (!expr.data_type.is_float() && !source_ty.is_float())
+                    || !self.is_enabled(&wrapper)
+                {
+                    BitcastExpr::new(expr.data_type, inner).into()
+                } else {
+                    let expr = FnCallExpr::new(self.safe_wrapper(wrapper), vec![inner])
+                        .into_node(node.data_type.clone());
+
+                    if node.data_type.is_float() {
+                        self.wrap_expr(Wrapper::FloatOp(node.data_type.clone()), expr)
+                            .expr
+                    } else {
+                        expr.expr
+                    }
                 }
             }
             Expr::Postfix(expr) => {
@@ -448,11 +991,23 @@ impl Reconditioner {
                 let postfix = match expr.postfix {
                     Postfix::Index(index) => {
                         let index = self.recondition_expr(*index);
-                        Postfix::Index(Box::new(self.recondition_array_index(&e.data_type, index)))
+                        Postfix::Index(Box::new(self.recondition_array_index(&e, index)))
                     }
                     Postfix::Member(n) => Postfix::Member(n),
                 };
 
+                if is_atomic_compare_exchange_result(&e.data_type) {
+                    let member = PostfixExpr::new(e, postfix).into();
+                    let ty = node.data_type.dereference().clone();
+                    return self.wrap_expr(
+                        Wrapper::AtomicResult(ty),
+                        ExprNode {
+                            data_type: node.data_type,
+                            expr: member,
+                        },
+                    );
+                }
+
                 PostfixExpr::new(e, postfix).into()
             }
             e => e,
@@ -464,28 +1019,69 @@ impl Reconditioner {
         }
     }
 
-    fn recondition_array_index(&mut self, array_type: &DataType, index: ExprNode) -> ExprNode {
+    fn recondition_array_index(&mut self, array: &ExprNode, index: ExprNode) -> ExprNode {
+        let index_type = index.data_type.dereference().clone();
+        let wrapper = Wrapper::Index(index_type.clone());
+        if !self.is_enabled(&wrapper) || is_call_to(&index, &self.wrapper_name(&wrapper)) {
+            return index;
+        }
+
+        let size_lit = |size: u32| -> ExprNode {
+            match index_type.as_scalar().unwrap() {
+                ScalarType::I32 => Lit::I32(size as i32).into(),
+                ScalarType::U32 => Lit::U32(size).into(),
+                _ => unreachable!("index expression must be an integer"),
+            }
+        };
+
+        // Sizes which aren't known until the pipeline is created or the shader is run are
+        // converted to the index type, since they may be either signed or unsigned.
+        let convert = |size: ExprNode| -> ExprNode {
+            if size.data_type == index_type {
+                size
+            } else {
+                TypeConsExpr::new(index_type.clone(), vec![size]).into()
+            }
+        };
+
+        let array_type = &array.data_type;
         let size = match array_type.dereference() {
-            DataType::Array(_, Some(n)) => *n,
+            // The size of an array which is given by an override may differ from its default
+            // value, so its clamped companion is used as the size instead. Overrides which aren't
+            // clamped (e.g. since they have no default value) are used directly. The type of the
+            // override isn't known here, so it is always converted.
+            DataType::Array(_, Some(ConstSize::Override(name, _))) => {
+                let companion = overrides::size_companion(name);
+                let name = if self.overrides.contains(&companion) {
+                    companion
+                } else {
+                    name.clone()
+                };
+
+                TypeConsExpr::new(
+                    index_type.clone(),
+                    vec![VarExpr::new(name).into_node(index_type.clone())],
+                )
+                .into()
+            }
+            // A size given by an expression may also depend on overrides, so the expression is
+            // used as is.
+            DataType::Array(_, Some(ConstSize::Expr(expr, _))) => convert((**expr).clone()),
+            DataType::Array(_, Some(n)) => size_lit(n.value().unwrap()),
+            // The size of a runtime-sized array is only known when the shader is run, so it must
+            // be queried with `arrayLength`.
             DataType::Array(_, None) => {
-                todo!("runtime-sized arrays are not currently supported")
+                let ptr = UnOpExpr::new(UnOp::AddressOf, array.clone());
+                convert(
+                    FnCallExpr::new("arrayLength", vec![ptr.into()])
+                        .into_node(DataType::Scalar(ScalarType::U32)),
+                )
             }
-            DataType::Vector(n, _) => *n as u32,
+            DataType::Vector(n, _) => size_lit(*n as u32),
             _ => unreachable!("index operator cannot be applied to type `{array_type}`"),
         };
 
-        let index_type = index.data_type.dereference().clone();
-        let size_expr = match index_type.as_scalar().unwrap() {
-            ScalarType::I32 => Lit::I32(size as i32),
-            ScalarType::U32 => Lit::U32(size),
-            _ => unreachable!("index expression must be an integer"),
-        };
-
-        FnCallExpr::new(
-            self.safe_wrapper(Wrapper::Index(index_type.clone())),
-            vec![index, size_expr.into()],
-        )
-        .into_node(index_type)
+        FnCallExpr::new(self.safe_wrapper(wrapper), vec![index, size]).into_node(index_type)
     }
 
     fn recondition_shift_expr(
@@ -495,20 +1091,22 @@ impl Reconditioner {
         operand: ExprNode,
         shift_value: ExprNode,
     ) -> ExprNode {
-        let shift_type = shift_value.data_type.dereference();
-        let shift_bound: ExprNode = match ty {
-            DataType::Scalar(_) => Lit::U32(32).into(),
+        let shift_value = self.recondition_shift_value(&ty, shift_value);
+        ExprNode::from(BinOpExpr::new(shift_op, operand, shift_value))
+    }
+
+    /// Bounds a shift value so that it is less than the bit width of the shifted type.
+    ///
+    /// Vector shift values are passed through a wrapper which clamps each component instead.
+    fn recondition_shift_value(&mut self, ty: &DataType, shift_value: ExprNode) -> ExprNode {
+        match ty {
+            DataType::Scalar(_) => BinOpExpr::new(BinOp::Mod, shift_value, Lit::U32(32)).into(),
             DataType::Vector(_, _) => {
-                TypeConsExpr::new(shift_type.clone(), vec![Lit::U32(32).into()]).into()
+                let shift_type = shift_value.data_type.dereference().clone();
+                self.wrap_expr(Wrapper::ShiftAmount(shift_type), shift_value)
             }
             _ => unreachable!(),
-        };
-
-        ExprNode::from(BinOpExpr::new(
-            shift_op,
-            operand,
-            BinOpExpr::new(BinOp::Mod, shift_value, shift_bound),
-        ))
+        }
     }
 
     fn recondition_bin_op_expr(
@@ -526,10 +1124,12 @@ impl Reconditioner {
             ScalarType::I32 | ScalarType::U32 => {
                 self.recondition_integer_bin_op_expr(data_type, op, l, r)
             }
-            ScalarType::F32 if op == BinOp::Divide => {
+            ScalarType::F32 | ScalarType::F16 if matches!(op, BinOp::Divide | BinOp::Mod) => {
                 self.recondition_floating_point_div_expr(data_type, op, l, r)
             }
-            ScalarType::F32 => self.recondition_floating_point_bin_op_expr(data_type, op, l, r),
+            ScalarType::F32 | ScalarType::F16 => {
+                self.recondition_floating_point_bin_op_expr(data_type, op, l, r)
+            }
             ScalarType::Bool => BinOpExpr::new(op, l, r).into(),
         }
     }
@@ -541,12 +1141,16 @@ impl Reconditioner {
         l: ExprNode,
         r: ExprNode,
     ) -> ExprNode {
-        let name = match op {
-            BinOp::Mod => self.safe_wrapper(Wrapper::Mod(data_type.clone())),
+        let wrapper = match op {
+            BinOp::Mod => Wrapper::Mod(data_type.clone()),
             op => return BinOpExpr::new(op, l, r).into(),
         };
 
-        FnCallExpr::new(name, vec![l, r]).into_node(data_type)
+        if !self.is_enabled(&wrapper) {
+            return BinOpExpr::new(op, l, r).into();
+        }
+
+        FnCallExpr::new(self.safe_wrapper(wrapper), vec![l, r]).into_node(data_type)
     }
 
     fn recondition_floating_point_bin_op_expr(
@@ -556,11 +1160,7 @@ impl Reconditioner {
         l: ExprNode,
         r: ExprNode,
     ) -> ExprNode {
-        FnCallExpr::new(
-            self.safe_wrapper(Wrapper::FloatOp(data_type.clone())),
-            vec![BinOpExpr::new(op, l, r).into()],
-        )
-        .into_node(data_type)
+        self.wrap_float_result(data_type, BinOpExpr::new(op, l, r).into())
     }
 
     fn recondition_floating_point_div_expr(
@@ -572,9 +1172,31 @@ impl Reconditioner {
     ) -> ExprNode {
         let wrapper = match op {
             BinOp::Divide => Wrapper::FloatDivide(data_type.clone()),
+            BinOp::Mod => Wrapper::Mod(data_type.clone()),
             _ => unreachable!(),
         };
-        FnCallExpr::new(self.safe_wrapper(wrapper), vec![l, r]).into_node(data_type)
+
+        // The divisor is bounded away from zero by the wrapper, but the result may still be
+        // subnormal or zero, so it is also passed through the float wrapper.
+        let expr = if self.is_enabled(&wrapper) {
+            FnCallExpr::new(self.safe_wrapper(wrapper), vec![l, r]).into_node(data_type.clone())
+        } else {
+            BinOpExpr::new(op, l, r).into()
+        };
+
+        self.wrap_float_result(data_type, expr)
+    }
+
+    /// Passes the result of a float operation through the float wrapper, after replacing NaN and
+    /// infinities if canonicalization is enabled.
+    fn wrap_float_result(&mut self, data_type: DataType, node: ExprNode) -> ExprNode {
+        let node = if self.canonicalize_floats && data_type.as_scalar() == Some(ScalarType::F32) {
+            self.wrap_expr(Wrapper::Canonicalize(data_type.clone()), node)
+        } else {
+            node
+        };
+
+        self.wrap_expr(Wrapper::FloatOp(data_type), node)
     }
 
     fn loop_var(&mut self) -> u32 {
@@ -583,9 +1205,108 @@ impl Reconditioner {
         cur
     }
 
+    fn is_enabled(&self, wrapper: &Wrapper) -> bool {
+        !self.disabled_wrappers.contains(&wrapper.kind())
+    }
+
     fn safe_wrapper(&mut self, wrapper: Wrapper) -> String {
-        let ident = wrapper.to_string();
-        self.wrappers.insert(wrapper);
-        ident
+        *self.report.wrappers.entry(wrapper.kind()).or_default() += 1;
+        self.wrapper_name(&wrapper)
+    }
+
+    /// Returns the name of the given wrapper, which will be declared in the output module.
+    fn wrapper_name(&mut self, wrapper: &Wrapper) -> String {
+        if let Some(name) = self.wrappers.get(wrapper) {
+            return name.clone();
+        }
+
+        let name = wrapper.name(self.hash_wrapper_names);
+        self.wrappers.insert(wrapper.clone(), name.clone());
+        name
+    }
+
+    /// Calls the given wrapper with `args`, or the builtin `ident` if the wrapper is disabled.
+    fn call_wrapper(&mut self, wrapper: Wrapper, ident: String, args: Vec<ExprNode>) -> FnCallExpr {
+        if self.is_enabled(&wrapper) {
+            FnCallExpr::new(self.safe_wrapper(wrapper), args)
+        } else {
+            FnCallExpr::new(ident, args)
+        }
+    }
+
+    /// Passes `node` through the given single-argument wrapper, unless it is disabled.
+    ///
+    /// Expressions which are already calls to the wrapper (from a previous reconditioning) are
+    /// left as they are.
+    fn wrap_expr(&mut self, wrapper: Wrapper, node: ExprNode) -> ExprNode {
+        if !self.is_enabled(&wrapper) || is_call_to(&node, &self.wrapper_name(&wrapper)) {
+            return node;
+        }
+
+        let data_type = node.data_type.dereference().clone();
+        FnCallExpr::new(self.safe_wrapper(wrapper), vec![node]).into_node(data_type)
+    }
+}
+
+fn is_non_commutative_atomic(ident: &str) -> bool {
+    matches!(
+        ident,
+        "atomicStore" | "atomicExchange" | "atomicCompareExchangeWeak"
+    )
+}
+
+/// Returns `true` if `ty` is the type returned by `atomicCompareExchangeWeak`, whose members
+/// depend on the order in which invocations access the atomic.
+fn is_atomic_compare_exchange_result(ty: &DataType) -> bool {
+    matches!(ty.dereference(), DataType::Struct(decl)
+        if decl.name.starts_with("__atomic_compare_exchange_result"))
+}
+
+/// Returns the variable that is (partly) assigned to by the given lhs expression.
+fn root_ident(node: &LhsExprNode) -> &str {
+    match &node.expr {
+        LhsExpr::Ident(ident) => ident,
+        LhsExpr::Postfix(expr, _) => root_ident(expr),
+        LhsExpr::Deref(expr) | LhsExpr::AddressOf(expr) => root_ident(expr),
+    }
+}
+
+fn is_call_to(node: &ExprNode, ident: &str) -> bool {
+    matches!(&node.expr, Expr::FnCall(call) if call.ident == ident)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recondition_source(source: &str) -> ReconditionResult {
+        recondition(parser::parse(source))
+    }
+
+    #[test]
+    fn atomic_compare_exchange_result_members_are_wrapped() {
+        let result = recondition_source(
+            r#"
+                var<workgroup> a: atomic<u32>;
+
+                @compute @workgroup_size(1)
+                fn main() {
+                    let r = atomicCompareExchangeWeak(&a, 0u, 1u);
+                    let x = r.old_value;
+                    let y = atomicCompareExchangeWeak(&a, 1u, 2u).exchanged;
+                }
+            "#,
+        );
+
+        let mut shader = String::new();
+        ast::writer::Writer
+            .write_module(&mut shader, &result.ast)
+            .unwrap();
+
+        assert!(shader.contains("_wgslsmith_atomic_result_u32(r.old_value)"));
+        assert!(shader.contains(
+            "_wgslsmith_atomic_result_bool(atomicCompareExchangeWeak(&a, 1u, 2u).exchanged)"
+        ));
+        assert_eq!(result.report.non_commutative_atomics, 2);
     }
 }
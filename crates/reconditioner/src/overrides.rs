@@ -0,0 +1,368 @@
+//! Clamping of overrides which control how much work a shader does.
+//!
+//! The values of overrides are only known when the pipeline is created, so a harness may pick
+//! values which make the shader invalid or very slow, such as a huge workgroup size. Overrides
+//! which are used as workgroup sizes or array sizes are replaced by companion overrides which are
+//! clamped to between one and the default value, which is the size that the harness requests
+//! limits for. Integer overrides in loop conditions are likewise replaced by companions which are
+//! clamped to between zero and the default value, so that a loop can't run for more iterations than
+//! it would with the default values.
+//!
+//! Companions are declared as overrides whose initializers are computed from the original
+//! overrides, e.g. `override _wgslsmith_size_n: u32 = clamp(n, 1u, 64u);`.
+
+use std::collections::HashMap;
+
+use ast::types::{DataType, MemoryViewType};
+use ast::*;
+
+use crate::Report;
+
+const SIZE_PREFIX: &str = "_wgslsmith_size_";
+const BOUND_PREFIX: &str = "_wgslsmith_bound_";
+
+/// Replaces overrides used as sizes and in loop conditions with clamped companions, recording the
+/// number of companions declared in the report.
+pub fn clamp_overrides(module: &mut Module, report: &mut Report) {
+    let mut clamper = Clamper {
+        overrides: module
+            .overrides
+            .iter()
+            .filter_map(|it| match it.data_type {
+                DataType::Scalar(ty @ (ScalarType::I32 | ScalarType::U32)) => {
+                    Some((it.name.clone(), (ty, default_value(it))))
+                }
+                _ => None,
+            })
+            .collect(),
+        companions: module
+            .overrides
+            .iter()
+            .map(|it| it.name.clone())
+            .filter(|it| it.starts_with("_wgslsmith_"))
+            .collect(),
+        decls: vec![],
+    };
+
+    for var in &mut module.vars {
+        var.data_type = clamper.data_type(&var.data_type);
+    }
+
+    for function in &mut module.functions {
+        for attr in &mut function.attrs {
+            if let FnAttr::WorkgroupSize(sizes) = attr {
+                for size in sizes {
+                    *size = clamper.size(size);
+                }
+            }
+        }
+
+        for input in &mut function.inputs {
+            input.data_type = clamper.data_type(&input.data_type);
+        }
+
+        clamper.stmts(&mut function.body);
+    }
+
+    report.clamped_overrides += clamper.decls.len() as u32;
+    module.overrides.extend(clamper.decls);
+}
+
+/// Returns the name of the companion which replaces an override wherever it is used as a size.
+///
+/// The types of expressions aren't updated when sizes are replaced, so this must be used to find
+/// the actual size of an array whose type refers to the original override.
+pub fn size_companion(name: &str) -> String {
+    if name.starts_with("_wgslsmith_") {
+        name.to_owned()
+    } else {
+        format!("{SIZE_PREFIX}{name}")
+    }
+}
+
+/// Returns the default value of an override, if it is given by an integer literal.
+fn default_value(decl: &GlobalOverrideDecl) -> Option<i64> {
+    match decl.initializer.as_ref()?.expr {
+        Expr::Lit(Lit::I32(v)) => Some(v.into()),
+        Expr::Lit(Lit::U32(v)) => Some(v.into()),
+        _ => None,
+    }
+}
+
+struct Clamper {
+    /// Integer overrides by name, along with their types and default values.
+    overrides: HashMap<String, (ScalarType, Option<i64>)>,
+    /// Names of the companions which are already declared, in this run or a previous one.
+    companions: Vec<String>,
+    /// Declarations of the companions added by this run.
+    decls: Vec<GlobalOverrideDecl>,
+}
+
+impl Clamper {
+    /// Returns the size with any override replaced by its clamped companion.
+    fn size(&mut self, size: &ConstSize) -> ConstSize {
+        // Overrides without a default value aren't clamped, since there is no size to clamp to.
+        let ConstSize::Override(name, Some(default)) = size else {
+            return size.clone();
+        };
+
+        if name.starts_with("_wgslsmith_") {
+            return size.clone();
+        }
+
+        let default = (*default).max(1);
+        match self.companion(&size_companion(name), name, 1, default.into()) {
+            Some(companion) => ConstSize::Override(companion, Some(default)),
+            None => size.clone(),
+        }
+    }
+
+    /// Replaces overrides in the sizes of arrays within the given type.
+    fn data_type(&mut self, ty: &DataType) -> DataType {
+        match ty {
+            DataType::Array(element_type, size) => DataType::Array(
+                self.data_type(element_type).into(),
+                size.as_ref().map(|it| self.size(it)),
+            ),
+            DataType::Ptr(view) => DataType::Ptr(self.memory_view(view)),
+            DataType::Ref(view) => DataType::Ref(self.memory_view(view)),
+            ty => ty.clone(),
+        }
+    }
+
+    fn memory_view(&mut self, view: &MemoryViewType) -> MemoryViewType {
+        view.clone_with_type(self.data_type(&view.inner))
+    }
+
+    /// Declares a companion for the given override, clamped to `[min, max]`, if it doesn't exist
+    /// yet. Returns the name of the companion, or `None` if the override isn't an `i32` or `u32`.
+    fn companion(&mut self, companion: &str, name: &str, min: i64, max: i64) -> Option<String> {
+        let companion = companion.to_owned();
+
+        if self.companions.contains(&companion) {
+            return Some(companion);
+        }
+
+        let &(ty, _) = self.overrides.get(name)?;
+        let lit = |v: i64| -> ExprNode {
+            match ty {
+                ScalarType::I32 => Lit::I32(v as i32).into(),
+                _ => Lit::U32(v as u32).into(),
+            }
+        };
+
+        let initializer = FnCallExpr::new(
+            "clamp",
+            vec![
+                VarExpr::new(name).into_node(DataType::Scalar(ty)),
+                lit(min),
+                lit(max),
+            ],
+        )
+        .into_node(DataType::Scalar(ty));
+
+        self.decls.push(GlobalOverrideDecl {
+            attrs: vec![],
+            name: companion.clone(),
+            data_type: ty.into(),
+            initializer: Some(initializer),
+        });
+
+        self.companions.push(companion.clone());
+        Some(companion)
+    }
+
+    fn stmts(&mut self, stmts: &mut [Statement]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::Compound(stmts) => self.stmts(stmts),
+            Statement::If(stmt) => self.if_stmt(stmt),
+            Statement::Switch(stmt) => {
+                for case in &mut stmt.cases {
+                    self.stmts(&mut case.body);
+                }
+
+                self.stmts(&mut stmt.default);
+            }
+            Statement::Loop(stmt) => {
+                self.stmts(&mut stmt.body);
+
+                if let Some(continuing) = &mut stmt.continuing {
+                    self.stmts(&mut continuing.body);
+
+                    if let Some(condition) = &mut continuing.break_if {
+                        self.condition(condition);
+                    }
+                }
+            }
+            Statement::ForLoop(stmt) => {
+                if let Some(condition) = &mut stmt.header.condition {
+                    self.condition(condition);
+                }
+
+                self.stmts(&mut stmt.body);
+            }
+            Statement::While(stmt) => {
+                self.condition(&mut stmt.condition);
+                self.stmts(&mut stmt.body);
+            }
+            _ => {}
+        }
+    }
+
+    fn if_stmt(&mut self, stmt: &mut IfStatement) {
+        self.stmts(&mut stmt.body);
+
+        match stmt.else_.as_deref_mut() {
+            Some(Else::If(stmt)) => self.if_stmt(stmt),
+            Some(Else::Else(stmts)) => self.stmts(stmts),
+            None => {}
+        }
+    }
+
+    /// Replaces the overrides in a loop condition with their clamped companions.
+    fn condition(&mut self, node: &mut ExprNode) {
+        match &mut node.expr {
+            Expr::Lit(_) => {}
+            Expr::Var(expr) => {
+                let Some(&(_, Some(default))) = self.overrides.get(&expr.ident) else {
+                    return;
+                };
+
+                if expr.ident.starts_with("_wgslsmith_") {
+                    return;
+                }
+
+                let companion = format!("{BOUND_PREFIX}{}", expr.ident);
+                if let Some(companion) =
+                    self.companion(&companion, &expr.ident, default.min(0), default.max(0))
+                {
+                    expr.ident = companion;
+                }
+            }
+            Expr::TypeCons(expr) => {
+                for arg in &mut expr.args {
+                    self.condition(arg);
+                }
+            }
+            Expr::Bitcast(expr) => self.condition(&mut expr.inner),
+            Expr::Postfix(expr) => {
+                self.condition(&mut expr.inner);
+
+                if let Postfix::Index(index) = &mut expr.postfix {
+                    self.condition(index);
+                }
+            }
+            Expr::UnOp(expr) => self.condition(&mut expr.inner),
+            Expr::BinOp(expr) => {
+                self.condition(&mut expr.left);
+                self.condition(&mut expr.right);
+            }
+            Expr::FnCall(expr) => {
+                for arg in &mut expr.args {
+                    self.condition(arg);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(module: &Module) -> String {
+        let mut shader = String::new();
+        ast::writer::Writer
+            .write_module(&mut shader, module)
+            .unwrap();
+        shader
+    }
+
+    fn clamp(source: &str) -> (String, u32) {
+        let mut module = parser::parse(source);
+        let mut report = Report::default();
+        clamp_overrides(&mut module, &mut report);
+        (write(&module), report.clamped_overrides)
+    }
+
+    const SOURCE: &str = r#"
+        override wg: u32 = 64u;
+        override n: i32 = 8i;
+
+        var<workgroup> arr: array<u32, n>;
+
+        @compute @workgroup_size(wg)
+        fn main() {
+            for (var i = 0i; i < n; i++) {
+                arr[i] = 1u;
+            }
+        }
+    "#;
+
+    #[test]
+    fn array_sizes_are_clamped() {
+        let (shader, _) = clamp(SOURCE);
+        assert!(shader.contains("override _wgslsmith_size_n: i32 = clamp(n, 1i, 8i);"));
+        assert!(shader.contains("var<workgroup> arr: array<u32, _wgslsmith_size_n>;"));
+    }
+
+    #[test]
+    fn workgroup_sizes_are_clamped() {
+        let (shader, _) = clamp(SOURCE);
+        assert!(shader.contains("override _wgslsmith_size_wg: u32 = clamp(wg, 1u, 64u);"));
+        assert!(shader.contains("@workgroup_size(_wgslsmith_size_wg)"));
+    }
+
+    #[test]
+    fn loop_conditions_are_clamped() {
+        let (shader, clamped) = clamp(SOURCE);
+        assert!(shader.contains("override _wgslsmith_bound_n: i32 = clamp(n, 0i, 8i);"));
+        assert!(shader.contains("(i < _wgslsmith_bound_n)"));
+        assert_eq!(clamped, 3);
+    }
+
+    #[test]
+    fn companions_are_reused_by_a_second_run() {
+        let (shader, _) = clamp(SOURCE);
+        let (again, clamped) = clamp(&shader);
+        assert_eq!(again, shader);
+        assert_eq!(clamped, 0);
+    }
+
+    #[test]
+    fn overrides_without_defaults_are_left_alone() {
+        let (shader, clamped) = clamp(
+            r#"
+                override wg: u32;
+
+                @compute @workgroup_size(wg)
+                fn main() {
+                }
+            "#,
+        );
+
+        assert!(shader.contains("@workgroup_size(wg)"));
+        assert_eq!(clamped, 0);
+    }
+
+    #[test]
+    fn overrides_of_unknown_types_are_left_alone() {
+        // The type of an override isn't known if the module doesn't declare it.
+        let mut module = parser::parse(SOURCE);
+        module.overrides.retain(|it| it.name != "n");
+
+        let mut report = Report::default();
+        clamp_overrides(&mut module, &mut report);
+
+        let shader = write(&module);
+        assert!(shader.contains("var<workgroup> arr: array<u32, n>;"));
+        assert!(shader.contains("(i < n)"));
+        assert_eq!(report.clamped_overrides, 1);
+    }
+}
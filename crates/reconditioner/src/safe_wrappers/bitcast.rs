@@ -0,0 +1,138 @@
+use ast::{
+    BinOp, BinOpExpr, BitcastExpr, DataType, ExprNode, FnCallExpr, FnDecl, FnInput, FnOutput,
+    LetDeclStatement, Lit, ReturnStatement, ScalarType, Statement, TypeConsExpr, VarExpr,
+};
+
+/// Bit pattern of `1.0f`, used to replace float bit patterns that may not be preserved.
+const CANONICAL_BITS: u32 = 0x3f800000;
+
+/// Bit pattern of two `1.0h` values, used instead of [`CANONICAL_BITS`] when either side of the
+/// bitcast contains `f16` values. It is also a normal value when viewed as an `f32`.
+const CANONICAL_F16_BITS: u32 = 0x3c003c00;
+
+/// Bitcasts to or from `f32` or `f16` while replacing any subnormal (an exponent of all zeros and a
+/// non-zero mantissa) or inf/NaN (an exponent of all ones) bit pattern with a canonical value.
+///
+/// Implementations are free to flush subnormals and to change NaN payloads, so such values may
+/// otherwise produce different results on different platforms. Zeros of either sign are kept, since
+/// they are preserved exactly. The check is done on the integer representation so that it can't be
+/// optimised away by fast-math compilers.
+pub fn bitcast(name: String, ty: &DataType, source_ty: &DataType) -> FnDecl {
+    let bits_ty = bits_type(source_ty);
+    let u32_splat = |v: u32| TypeConsExpr::new(bits_ty.clone(), vec![Lit::U32(v).into()]);
+
+    let bits = || VarExpr::new("bits").into_node(bits_ty.clone());
+
+    let is_f16 = |ty: &DataType| ty.as_scalar() == Some(ScalarType::F16);
+    let is_f32 = |ty: &DataType| ty.as_scalar() == Some(ScalarType::F32);
+
+    // Each float in a 32-bit word, given as the suffix of the variables its exponent and mantissa
+    // are stored in, the offset of the float and the masks for the widths of its exponent and
+    // mantissa.
+    let mut floats: Vec<(&str, u32, u32, u32)> = vec![];
+    if is_f32(ty) || is_f32(source_ty) {
+        floats.push(("", 0, 0xff, 0x7fffff));
+    }
+    if is_f16(ty) || is_f16(source_ty) {
+        floats.push(("_lo", 0, 0x1f, 0x3ff));
+        floats.push(("_hi", 16, 0x1f, 0x3ff));
+    }
+
+    let canonical_bits = if is_f16(ty) || is_f16(source_ty) {
+        CANONICAL_F16_BITS
+    } else {
+        CANONICAL_BITS
+    };
+
+    let mut body: Vec<Statement> = vec![LetDeclStatement::new(
+        "bits",
+        BitcastExpr::new(
+            bits_ty.clone(),
+            VarExpr::new("v").into_node(source_ty.clone()),
+        ),
+    )
+    .into()];
+
+    let mut condition: Option<ExprNode> = None;
+    for (suffix, offset, exponent_mask, mantissa_mask) in floats {
+        let mantissa_bits = mantissa_mask.count_ones();
+        let field = |ident: String, offset: u32, mask: u32| {
+            let value = match offset {
+                0 => bits(),
+                offset => BinOpExpr::new(BinOp::RShift, bits(), u32_splat(offset)).into(),
+            };
+
+            LetDeclStatement::new(ident, BinOpExpr::new(BinOp::BitAnd, value, u32_splat(mask)))
+        };
+
+        let exponent_ident = format!("exponent{suffix}");
+        let mantissa_ident = format!("mantissa{suffix}");
+        body.push(
+            field(
+                exponent_ident.clone(),
+                offset + mantissa_bits,
+                exponent_mask,
+            )
+            .into(),
+        );
+        body.push(field(mantissa_ident.clone(), offset, mantissa_mask).into());
+
+        let exponent = || VarExpr::new(exponent_ident.clone()).into_node(bits_ty.clone());
+        let mantissa = VarExpr::new(mantissa_ident).into_node(bits_ty.clone());
+        let is_subnormal = BinOpExpr::new(
+            BinOp::BitAnd,
+            BinOpExpr::new(BinOp::Equal, exponent(), u32_splat(0)),
+            BinOpExpr::new(BinOp::NotEqual, mantissa, u32_splat(0)),
+        );
+        let is_special = BinOpExpr::new(
+            BinOp::BitOr,
+            is_subnormal,
+            BinOpExpr::new(BinOp::Equal, exponent(), u32_splat(exponent_mask)),
+        )
+        .into();
+
+        condition = Some(match condition {
+            Some(condition) => BinOpExpr::new(BinOp::BitOr, condition, is_special).into(),
+            None => is_special,
+        });
+    }
+
+    body.push(
+        ReturnStatement::new(BitcastExpr::new(
+            ty.clone(),
+            FnCallExpr::new(
+                "select",
+                vec![
+                    bits(),
+                    u32_splat(canonical_bits).into(),
+                    condition.expect("bitcast wrapper requires a float type"),
+                ],
+            )
+            .into_node(bits_ty.clone()),
+        ))
+        .into(),
+    );
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![FnInput::new("v", source_ty.clone())],
+        output: Some(FnOutput::new(ty.clone())),
+        body,
+    }
+}
+
+/// Returns the type with `u32` components which has the same size as `ty`.
+///
+/// `f16` vectors are packed two components to a word, so `vec2<f16>` and `vec4<f16>` correspond
+/// to `u32` and `vec2<u32>`.
+fn bits_type(ty: &DataType) -> DataType {
+    match ty {
+        DataType::Vector(2, ScalarType::F16) => ScalarType::U32.into(),
+        DataType::Vector(4, ScalarType::F16) => DataType::Vector(2, ScalarType::U32),
+        DataType::Scalar(ScalarType::F16) | DataType::Vector(_, ScalarType::F16) => {
+            unreachable!("`{ty}` cannot be bitcast")
+        }
+        ty => ty.map(ScalarType::U32),
+    }
+}
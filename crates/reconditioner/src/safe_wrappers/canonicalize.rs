@@ -0,0 +1,70 @@
+use ast::{
+    BinOp, BinOpExpr, BitcastExpr, DataType, FnCallExpr, FnDecl, FnInput, FnOutput,
+    LetDeclStatement, Lit, ReturnStatement, ScalarType, TypeConsExpr, VarExpr,
+};
+
+/// Bit mask that clears the sign bit of an `f32`.
+const MAGNITUDE_MASK: u32 = 0x7fffffff;
+
+/// Bit pattern of positive infinity. Any larger magnitude is a NaN.
+const INFINITY_BITS: u32 = 0x7f800000;
+
+/// Generates a wrapper which replaces NaN with zero and infinities with the largest finite value of
+/// the same sign.
+///
+/// The checks are done on the bit representation, since comparisons involving NaN or infinities
+/// may be optimised away by fast-math compilers.
+pub fn canonicalize(name: String, data_type: &DataType) -> FnDecl {
+    let bits_ty = data_type.map(ScalarType::U32);
+    let u32_splat = |v: u32| TypeConsExpr::new(bits_ty.clone(), vec![Lit::U32(v).into()]);
+    let f32_splat = |v: f32| TypeConsExpr::new(data_type.clone(), vec![Lit::F32(v).into()]);
+
+    let v = || VarExpr::new("v").into_node(data_type.clone());
+    let magnitude = || VarExpr::new("magnitude").into_node(bits_ty.clone());
+
+    let clamped = FnCallExpr::new(
+        "select",
+        vec![
+            v(),
+            BinOpExpr::new(
+                BinOp::Times,
+                FnCallExpr::new("sign", vec![v()]).into_node(data_type.clone()),
+                f32_splat(f32::MAX),
+            )
+            .into(),
+            BinOpExpr::new(BinOp::Equal, magnitude(), u32_splat(INFINITY_BITS)).into(),
+        ],
+    )
+    .into_node(data_type.clone());
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![FnInput::new("v", data_type.clone())],
+        output: Some(FnOutput::new(data_type.clone())),
+        body: vec![
+            LetDeclStatement::new(
+                "magnitude",
+                BinOpExpr::new(
+                    BinOp::BitAnd,
+                    BitcastExpr::new(bits_ty.clone(), v()),
+                    u32_splat(MAGNITUDE_MASK),
+                ),
+            )
+            .into(),
+            ReturnStatement::new(
+                FnCallExpr::new(
+                    "select",
+                    vec![
+                        clamped,
+                        f32_splat(0.0).into(),
+                        BinOpExpr::new(BinOp::Greater, magnitude(), u32_splat(INFINITY_BITS))
+                            .into(),
+                    ],
+                )
+                .into_node(data_type.clone()),
+            )
+            .into(),
+        ],
+    }
+}
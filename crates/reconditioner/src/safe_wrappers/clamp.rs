@@ -0,0 +1,33 @@
+use ast::*;
+
+/// Generates a wrapper for `clamp` which swaps `low` and `high` in any component where `low` is
+/// greater than `high`, which is an error for const-expressions and indeterminate for floats.
+pub fn clamp(name: String, data_type: &DataType) -> FnDecl {
+    let e = VarExpr::new("e").into_node(data_type.clone());
+    let low = VarExpr::new("low").into_node(data_type.clone());
+    let high = VarExpr::new("high").into_node(data_type.clone());
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![
+            FnInput::new("e", data_type.clone()),
+            FnInput::new("low", data_type.clone()),
+            FnInput::new("high", data_type.clone()),
+        ],
+        output: Some(FnOutput::new(data_type.clone())),
+        body: vec![ReturnStatement::new(
+            FnCallExpr::new(
+                "clamp",
+                vec![
+                    e,
+                    FnCallExpr::new("min", vec![low.clone(), high.clone()])
+                        .into_node(data_type.clone()),
+                    FnCallExpr::new("max", vec![low, high]).into_node(data_type.clone()),
+                ],
+            )
+            .into_node(data_type.clone()),
+        )
+        .into()],
+    }
+}
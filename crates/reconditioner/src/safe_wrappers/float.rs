@@ -1,9 +1,18 @@
 use ast::{
-    BinOp, BinOpExpr, DataType, FnCallExpr, FnDecl, FnInput, FnOutput, Lit, ReturnStatement,
+    BinOp, BinOpExpr, DataType, FnCallExpr, FnDecl, FnInput, FnOutput, ReturnStatement, ScalarType,
     TypeConsExpr, VarExpr,
 };
 
+use super::float_lit;
+
 pub fn float(name: String, data_type: &DataType) -> FnDecl {
+    // Beyond this magnitude consecutive integers are no longer representable, so results are
+    // likely to be rounded differently by different implementations.
+    let max = match data_type.as_scalar() {
+        Some(ScalarType::F16) => 2048.0,
+        _ => 16777216.0,
+    };
+
     FnDecl {
         attrs: vec![],
         name,
@@ -14,7 +23,8 @@ pub fn float(name: String, data_type: &DataType) -> FnDecl {
                 "select".to_owned(),
                 vec![
                     VarExpr::new("v").into_node(data_type.clone()),
-                    TypeConsExpr::new(data_type.clone(), vec![Lit::F32(10.0).into()]).into(),
+                    TypeConsExpr::new(data_type.clone(), vec![float_lit(data_type, 10.0).into()])
+                        .into(),
                     BinOpExpr::new(
                         BinOp::LogOr,
                         super::any(BinOpExpr::new(
@@ -24,7 +34,10 @@ pub fn float(name: String, data_type: &DataType) -> FnDecl {
                                 vec![VarExpr::new("v").into_node(data_type.clone())],
                             )
                             .into_node(data_type.clone()),
-                            TypeConsExpr::new(data_type.clone(), vec![Lit::F32(0.1).into()]),
+                            TypeConsExpr::new(
+                                data_type.clone(),
+                                vec![float_lit(data_type, 0.1).into()],
+                            ),
                         )),
                         super::any(BinOpExpr::new(
                             BinOp::GreaterEqual,
@@ -33,7 +46,10 @@ pub fn float(name: String, data_type: &DataType) -> FnDecl {
                                 vec![VarExpr::new("v").into_node(data_type.clone())],
                             )
                             .into_node(data_type.clone()),
-                            TypeConsExpr::new(data_type.clone(), vec![Lit::F32(16777216.0).into()]),
+                            TypeConsExpr::new(
+                                data_type.clone(),
+                                vec![float_lit(data_type, max).into()],
+                            ),
                         )),
                     )
                     .into(),
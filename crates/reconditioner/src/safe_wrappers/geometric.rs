@@ -0,0 +1,120 @@
+use ast::*;
+
+use super::float_lit;
+
+/// Generates a wrapper for `normalize` which returns the unit vector along the x axis if `v` has
+/// zero (or near-zero) length, for which the result would be indeterminate.
+pub fn normalize(name: String, data_type: &DataType) -> FnDecl {
+    let DataType::Vector(n, _) = data_type else {
+        unreachable!("normalize can only be applied to vectors")
+    };
+
+    let v = VarExpr::new("v").into_node(data_type.clone());
+
+    let unit = TypeConsExpr::new(
+        data_type.clone(),
+        (0..*n)
+            .map(|i| float_lit(data_type, if i == 0 { 1.0 } else { 0.0 }).into())
+            .collect(),
+    );
+
+    gen_wrapper(
+        name,
+        data_type,
+        data_type,
+        vec!["v"],
+        FnCallExpr::new(
+            "select",
+            vec![
+                FnCallExpr::new("normalize", vec![v.clone()]).into_node(data_type.clone()),
+                unit.into(),
+                is_near_zero(v),
+            ],
+        )
+        .into_node(data_type.clone()),
+    )
+}
+
+/// Generates a wrapper for `length` which returns zero if `v` has near-zero length.
+///
+/// The length of a zero vector is well defined, but implementations which compute it using an
+/// inverse square root may return NaN.
+pub fn length(name: String, data_type: &DataType) -> FnDecl {
+    let v = VarExpr::new("v").into_node(data_type.clone());
+    let scalar_ty = data_type.as_scalar().unwrap();
+
+    gen_wrapper(
+        name,
+        data_type,
+        &scalar_ty.into(),
+        vec!["v"],
+        FnCallExpr::new(
+            "select",
+            vec![
+                FnCallExpr::new("length", vec![v.clone()]).into_node(scalar_ty),
+                float_lit(data_type, 0.0).into(),
+                is_near_zero(v),
+            ],
+        )
+        .into_node(scalar_ty),
+    )
+}
+
+/// Generates a wrapper for `distance` which returns zero if `a` and `b` are (nearly) equal, in the
+/// same way as for `length`.
+pub fn distance(name: String, data_type: &DataType) -> FnDecl {
+    let a = VarExpr::new("a").into_node(data_type.clone());
+    let b = VarExpr::new("b").into_node(data_type.clone());
+    let scalar_ty = data_type.as_scalar().unwrap();
+
+    gen_wrapper(
+        name,
+        data_type,
+        &scalar_ty.into(),
+        vec!["a", "b"],
+        FnCallExpr::new(
+            "select",
+            vec![
+                FnCallExpr::new("distance", vec![a.clone(), b.clone()]).into_node(scalar_ty),
+                float_lit(data_type, 0.0).into(),
+                is_near_zero(BinOpExpr::new(BinOp::Minus, a, b).into()),
+            ],
+        )
+        .into_node(scalar_ty),
+    )
+}
+
+/// Returns a condition that holds if `v` has a length less than 0.1.
+///
+/// The squared length is compared instead, so that the condition doesn't depend on how an
+/// implementation computes square roots.
+fn is_near_zero(v: ExprNode) -> ExprNode {
+    let threshold = float_lit(&v.data_type, 0.01);
+    let scalar_ty = v.data_type.as_scalar().unwrap();
+    let squared_length = match &v.data_type {
+        DataType::Scalar(_) => BinOpExpr::new(BinOp::Times, v.clone(), v).into(),
+        DataType::Vector(..) => FnCallExpr::new("dot", vec![v.clone(), v]).into_node(scalar_ty),
+        ty => unreachable!("no length for type `{ty}`"),
+    };
+
+    BinOpExpr::new(BinOp::Less, squared_length, threshold).into()
+}
+
+fn gen_wrapper(
+    name: String,
+    data_type: &DataType,
+    return_type: &DataType,
+    inputs: Vec<&str>,
+    return_expr: ExprNode,
+) -> FnDecl {
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: inputs
+            .into_iter()
+            .map(|it| FnInput::new(it, data_type.clone()))
+            .collect(),
+        output: Some(FnOutput::new(return_type.clone())),
+        body: vec![ReturnStatement::new(return_expr).into()],
+    }
+}
@@ -0,0 +1,43 @@
+use ast::*;
+
+/// Generates a wrapper for `ldexp` which clamps the exponent to `[-127, 128]` (or `[-15, 16]` for
+/// `f16`).
+///
+/// The result is indeterminate if the exponent is greater than the bias of the float type plus
+/// one. The lower bound is only there to keep the exponent within the same range, since any
+/// smaller exponent produces a subnormal or zero result.
+pub fn ldexp(name: String, data_type: &DataType) -> FnDecl {
+    let exp_type = data_type.map(ScalarType::I32);
+    let bias = match data_type.as_scalar() {
+        Some(ScalarType::F16) => 15,
+        _ => 127,
+    };
+
+    let exp = FnCallExpr::new(
+        "clamp",
+        vec![
+            VarExpr::new("e2").into_node(exp_type.clone()),
+            TypeConsExpr::new(exp_type.clone(), vec![Lit::I32(-bias).into()]).into(),
+            TypeConsExpr::new(exp_type.clone(), vec![Lit::I32(bias + 1).into()]).into(),
+        ],
+    )
+    .into_node(exp_type.clone());
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![
+            FnInput::new("e1", data_type.clone()),
+            FnInput::new("e2", exp_type),
+        ],
+        output: Some(FnOutput::new(data_type.clone())),
+        body: vec![ReturnStatement::new(
+            FnCallExpr::new(
+                "ldexp",
+                vec![VarExpr::new("e1").into_node(data_type.clone()), exp],
+            )
+            .into_node(data_type.clone()),
+        )
+        .into()],
+    }
+}
@@ -1,25 +1,58 @@
+mod atomic;
+mod bitcast;
+mod canonicalize;
+mod clamp;
 mod dot;
 mod extract_bits;
 mod float;
 mod float_divide;
+mod geometric;
 mod index;
 mod insert_bits;
+mod ldexp;
 mod modulo;
+mod pack;
+mod positive;
+mod quantize;
 mod select;
+mod shift;
+mod smoothstep;
 
 use ast::{
     BinOp, BinOpExpr, DataType, ExprNode, FnCallExpr, Lit, Postfix, PostfixExpr, ScalarType,
 };
 
+pub use atomic::atomic_result;
+pub use bitcast::bitcast;
+pub use canonicalize::canonicalize;
+pub use clamp::clamp;
 pub use dot::dot;
 pub use extract_bits::extract_bits;
 pub use extract_bits::extract_bits_unsigned;
 pub use float::float;
 pub use float_divide::float_divide;
+pub use geometric::{distance, length, normalize};
 pub use index::index;
 pub use insert_bits::insert_bits;
+pub use ldexp::ldexp;
 pub use modulo::modulo;
+pub use pack::pack2x16float;
+pub use pack::unpack2x16float;
+pub use positive::positive;
+pub use quantize::{contains_f32, quantize};
 pub use select::select;
+pub use shift::shift_amount;
+pub use smoothstep::smoothstep;
+
+/// Returns a literal with the given value and the element type of `data_type`, which must be `f32`
+/// or `f16`.
+fn float_lit(data_type: &DataType, value: f32) -> Lit {
+    match data_type.as_scalar() {
+        Some(ScalarType::F32) => Lit::F32(value),
+        Some(ScalarType::F16) => Lit::F16(value),
+        _ => unreachable!("no float literals of type `{data_type}`"),
+    }
+}
 
 /// Wraps the given expression in a call to `any()` if it is a vector.
 ///
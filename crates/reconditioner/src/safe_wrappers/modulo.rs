@@ -7,6 +7,8 @@ pub fn modulo(name: String, data_type: &DataType) -> FnDecl {
     let condition = match data_type.as_scalar().unwrap() {
         ScalarType::I32 => gen_condition_for_i32(data_type),
         ScalarType::U32 => gen_condition_for_u32(data_type),
+        // Unlike for integers, the float remainder of a negative operand is well defined.
+        ScalarType::F32 | ScalarType::F16 => super::float_divide::gen_condition(data_type),
         ty => unreachable!("no divide wrapper for type {ty}"),
     };
 
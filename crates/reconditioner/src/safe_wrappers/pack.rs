@@ -0,0 +1,159 @@
+use ast::*;
+
+/// Largest finite f16 value.
+const F16_MAX: f32 = 65504.0;
+
+/// Smallest positive normal f16 value.
+const F16_MIN_NORMAL: f32 = 0.000_061_035_156;
+
+/// Bit pattern of `1.0` as an f16.
+const F16_ONE_BITS: u32 = 0x3c00;
+
+/// Wrapper for `pack2x16float` which makes sure that both components are exactly representable
+/// as finite, normal f16 values.
+///
+/// Converting NaN or an out-of-range value produces an indeterminate result, and implementations
+/// are free to pick either neighbour when rounding or to flush f16 subnormals, so the input is
+/// clamped and truncated to the f16 mantissa width, and NaN and small values are replaced with
+/// zero. NaN is detected on the integer representation, since `clamp` may return either bound (or
+/// NaN) for a NaN input.
+pub fn pack2x16float(name: String) -> FnDecl {
+    let f32_ty = DataType::Vector(2, ScalarType::F32);
+    let u32_ty = DataType::Vector(2, ScalarType::U32);
+
+    let f32_splat = |v: f32| TypeConsExpr::new(f32_ty.clone(), vec![Lit::F32(v).into()]);
+    let u32_splat = |v: u32| TypeConsExpr::new(u32_ty.clone(), vec![Lit::U32(v).into()]);
+
+    // NaN has an exponent of all ones and a non-zero mantissa.
+    let is_nan = BinOpExpr::new(
+        BinOp::Greater,
+        BinOpExpr::new(
+            BinOp::BitAnd,
+            BitcastExpr::new(u32_ty.clone(), VarExpr::new("v").into_node(f32_ty.clone())),
+            u32_splat(0x7fffffff),
+        ),
+        u32_splat(0x7f800000),
+    );
+
+    let finite = FnCallExpr::new(
+        "select",
+        vec![
+            VarExpr::new("v").into_node(f32_ty.clone()),
+            f32_splat(0.0).into(),
+            is_nan.into(),
+        ],
+    )
+    .into_node(f32_ty.clone());
+
+    let clamped = FnCallExpr::new(
+        "clamp",
+        vec![
+            finite,
+            f32_splat(-F16_MAX).into(),
+            f32_splat(F16_MAX).into(),
+        ],
+    )
+    .into_node(f32_ty.clone());
+
+    // f16 has 10 mantissa bits compared to 23 for f32, so we clear the lower 13 bits.
+    let truncated = BitcastExpr::new(
+        f32_ty.clone(),
+        BinOpExpr::new(
+            BinOp::BitAnd,
+            BitcastExpr::new(u32_ty.clone(), clamped),
+            u32_splat(!0x1fff),
+        ),
+    );
+
+    let v = || VarExpr::new("truncated").into_node(f32_ty.clone());
+
+    let is_small = BinOpExpr::new(
+        BinOp::Less,
+        FnCallExpr::new("abs", vec![v()]).into_node(f32_ty.clone()),
+        f32_splat(F16_MIN_NORMAL),
+    );
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![FnInput::new("v", f32_ty.clone())],
+        output: Some(FnOutput::new(ScalarType::U32)),
+        body: vec![
+            LetDeclStatement::new("truncated", truncated).into(),
+            ReturnStatement::new(
+                FnCallExpr::new(
+                    "pack2x16float",
+                    vec![FnCallExpr::new(
+                        "select",
+                        vec![v(), f32_splat(0.0).into(), is_small.into()],
+                    )
+                    .into_node(f32_ty.clone())],
+                )
+                .into_node(ScalarType::U32),
+            )
+            .into(),
+        ],
+    }
+}
+
+/// Wrapper for `unpack2x16float` which replaces any half with an exponent of all zeros
+/// (zero/subnormal) or all ones (inf/NaN) with `1.0`, so that the result is always a finite value
+/// that is preserved by all implementations.
+pub fn unpack2x16float(name: String) -> FnDecl {
+    let u32_ty = DataType::Scalar(ScalarType::U32);
+    let f32_ty = DataType::Vector(2, ScalarType::F32);
+
+    let var = |name: &str| VarExpr::new(name).into_node(u32_ty.clone());
+
+    let canonicalize = |half: ExprNode| -> ExprNode {
+        let exponent = BinOpExpr::new(
+            BinOp::BitAnd,
+            BinOpExpr::new(BinOp::RShift, half.clone(), Lit::U32(10)),
+            Lit::U32(0x1f),
+        );
+
+        let is_special = BinOpExpr::new(
+            BinOp::LogOr,
+            BinOpExpr::new(BinOp::Equal, exponent.clone(), Lit::U32(0)),
+            BinOpExpr::new(BinOp::Equal, exponent, Lit::U32(0x1f)),
+        );
+
+        FnCallExpr::new(
+            "select",
+            vec![half, Lit::U32(F16_ONE_BITS).into(), is_special.into()],
+        )
+        .into_node(u32_ty.clone())
+    };
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![FnInput::new("v", u32_ty.clone())],
+        output: Some(FnOutput::new(f32_ty.clone())),
+        body: vec![
+            LetDeclStatement::new(
+                "lo",
+                canonicalize(BinOpExpr::new(BinOp::BitAnd, var("v"), Lit::U32(0xffff)).into()),
+            )
+            .into(),
+            LetDeclStatement::new(
+                "hi",
+                canonicalize(BinOpExpr::new(BinOp::RShift, var("v"), Lit::U32(16)).into()),
+            )
+            .into(),
+            ReturnStatement::new(
+                FnCallExpr::new(
+                    "unpack2x16float",
+                    vec![BinOpExpr::new(
+                        BinOp::BitOr,
+                        var("lo"),
+                        BinOpExpr::new(BinOp::LShift, var("hi"), Lit::U32(16)),
+                    )
+                    .into()],
+                )
+                .into_node(f32_ty),
+            )
+            .into(),
+        ],
+    }
+}
@@ -0,0 +1,41 @@
+use ast::{
+    BinOp, BinOpExpr, DataType, FnCallExpr, FnDecl, FnInput, FnOutput, ReturnStatement,
+    TypeConsExpr, VarExpr,
+};
+
+use super::float_lit;
+
+/// Generates a wrapper which replaces any component of `v` that isn't positive with 1.0, so that
+/// it can be passed to builtins which are only defined for positive arguments (e.g. `log`).
+///
+/// Small positive values (including subnormals) are also replaced, since they may be flushed to
+/// zero by some GPUs. The comparison is written so that NaN is replaced as well.
+pub fn positive(name: String, data_type: &DataType) -> FnDecl {
+    let v = VarExpr::new("v").into_node(data_type.clone());
+
+    let condition = BinOpExpr::new(
+        BinOp::GreaterEqual,
+        v.clone(),
+        TypeConsExpr::new(data_type.clone(), vec![float_lit(data_type, 0.1).into()]),
+    );
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![FnInput::new("v", data_type.clone())],
+        output: Some(FnOutput::new(data_type.clone())),
+        body: vec![ReturnStatement::new(
+            FnCallExpr::new(
+                "select",
+                vec![
+                    TypeConsExpr::new(data_type.clone(), vec![float_lit(data_type, 1.0).into()])
+                        .into(),
+                    v,
+                    condition.into(),
+                ],
+            )
+            .into_node(data_type.clone()),
+        )
+        .into()],
+    }
+}
@@ -0,0 +1,102 @@
+use ast::{
+    BinOp, BinOpExpr, BitcastExpr, DataType, ExprNode, FnDecl, FnInput, FnOutput, Lit, Postfix,
+    PostfixExpr, ReturnStatement, ScalarType, TypeConsExpr, VarExpr,
+};
+
+/// Number of explicitly stored mantissa bits in an `f32`.
+pub const F32_MANTISSA_BITS: u32 = 23;
+
+/// Generates a wrapper which rounds each `f32` in `v` to the given number of mantissa bits, so
+/// that results which only differ in their least significant bits compare equal.
+///
+/// Structs and arrays are quantized member by member, and any other values (including `f16`) are
+/// left as they are.
+pub fn quantize(name: String, data_type: &DataType, bits: u32) -> FnDecl {
+    let v = VarExpr::new("v").into_node(data_type.clone());
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![FnInput::new("v", data_type.clone())],
+        output: Some(FnOutput::new(data_type.clone())),
+        body: vec![ReturnStatement::new(quantize_value(v, bits)).into()],
+    }
+}
+
+/// Returns `true` if values of the given type contain an `f32` which can be quantized.
+pub fn contains_f32(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Scalar(ScalarType::F32) | DataType::Vector(_, ScalarType::F32) => true,
+        DataType::Array(element_type, Some(_)) => contains_f32(element_type),
+        DataType::Struct(decl) => decl.members.iter().any(|it| contains_f32(&it.data_type)),
+        _ => false,
+    }
+}
+
+fn quantize_value(v: ExprNode, bits: u32) -> ExprNode {
+    if !contains_f32(&v.data_type) {
+        return v;
+    }
+
+    let data_type = v.data_type.clone();
+    match &data_type {
+        DataType::Scalar(_) | DataType::Vector(..) => round_mantissa(v, bits),
+        DataType::Array(_, Some(n)) => {
+            // The elements of an array can't be enumerated if its size couldn't be evaluated, so
+            // it is left as is.
+            let Some(n) = n.value() else {
+                return v;
+            };
+
+            TypeConsExpr::new(
+                data_type.clone(),
+                (0..n)
+                    .map(|i| {
+                        let element = PostfixExpr::new(v.clone(), Postfix::index(Lit::U32(i)));
+                        quantize_value(element.into(), bits)
+                    })
+                    .collect(),
+            )
+            .into()
+        }
+        DataType::Struct(decl) => TypeConsExpr::new(
+            data_type.clone(),
+            decl.members
+                .iter()
+                .map(|it| {
+                    let member = PostfixExpr::new(v.clone(), Postfix::member(&it.name));
+                    quantize_value(member.into(), bits)
+                })
+                .collect(),
+        )
+        .into(),
+        _ => unreachable!(),
+    }
+}
+
+/// Rounds the mantissa of each component of `v` to `bits` bits.
+///
+/// This is done by adding half of the last kept bit to the bit representation and masking off the
+/// rest, which rounds halfway cases away from zero, and carries into the exponent if the mantissa
+/// overflows.
+fn round_mantissa(v: ExprNode, bits: u32) -> ExprNode {
+    if bits >= F32_MANTISSA_BITS {
+        return v;
+    }
+
+    let dropped = F32_MANTISSA_BITS - bits;
+    let bits_ty = v.data_type.map(ScalarType::U32);
+    let u32_splat = |x: u32| TypeConsExpr::new(bits_ty.clone(), vec![Lit::U32(x).into()]);
+
+    let rounded = BinOpExpr::new(
+        BinOp::Plus,
+        BitcastExpr::new(bits_ty.clone(), v.clone()),
+        u32_splat(1 << (dropped - 1)),
+    );
+
+    BitcastExpr::new(
+        v.data_type.clone(),
+        BinOpExpr::new(BinOp::BitAnd, rounded, u32_splat(!0 << dropped)),
+    )
+    .into()
+}
@@ -0,0 +1,25 @@
+use ast::*;
+
+/// Generates a wrapper for the vector shift amount `v`, which clamps each component to at most 31.
+///
+/// Shifting by at least the bit width of the shifted type is an error, and implementations
+/// disagree on the result when only some components of the shift amount are out of range, so
+/// every component is bounded individually.
+pub fn shift_amount(name: String, data_type: &DataType) -> FnDecl {
+    let max = TypeConsExpr::new(data_type.clone(), vec![Lit::U32(31).into()]);
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![FnInput::new("v", data_type.clone())],
+        output: Some(FnOutput::new(data_type.clone())),
+        body: vec![ReturnStatement::new(
+            FnCallExpr::new(
+                "min",
+                vec![VarExpr::new("v").into_node(data_type.clone()), max.into()],
+            )
+            .into_node(data_type.clone()),
+        )
+        .into()],
+    }
+}
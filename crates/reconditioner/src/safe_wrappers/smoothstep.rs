@@ -0,0 +1,45 @@
+use ast::*;
+
+/// Generates a wrapper for `smoothstep` which swaps the edges in any component where `low` is
+/// greater than `high`, and falls back to `step` where they are equal, since the result is
+/// indeterminate in both cases.
+pub fn smoothstep(name: String, data_type: &DataType) -> FnDecl {
+    let low = VarExpr::new("low").into_node(data_type.clone());
+    let high = VarExpr::new("high").into_node(data_type.clone());
+    let x = VarExpr::new("x").into_node(data_type.clone());
+
+    let smoothstep = FnCallExpr::new(
+        "smoothstep",
+        vec![
+            FnCallExpr::new("min", vec![low.clone(), high.clone()]).into_node(data_type.clone()),
+            FnCallExpr::new("max", vec![low.clone(), high.clone()]).into_node(data_type.clone()),
+            x.clone(),
+        ],
+    )
+    .into_node(data_type.clone());
+
+    let step = FnCallExpr::new("step", vec![low.clone(), x]).into_node(data_type.clone());
+
+    FnDecl {
+        attrs: vec![],
+        name,
+        inputs: vec![
+            FnInput::new("low", data_type.clone()),
+            FnInput::new("high", data_type.clone()),
+            FnInput::new("x", data_type.clone()),
+        ],
+        output: Some(FnOutput::new(data_type.clone())),
+        body: vec![ReturnStatement::new(
+            FnCallExpr::new(
+                "select",
+                vec![
+                    smoothstep,
+                    step,
+                    BinOpExpr::new(BinOp::Equal, low, high).into(),
+                ],
+            )
+            .into_node(data_type.clone()),
+        )
+        .into()],
+    }
+}
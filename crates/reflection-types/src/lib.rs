@@ -1,4 +1,5 @@
 use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
 
 pub struct ResourceData<'a> {
     pub name: &'a str,
@@ -8,13 +9,168 @@ pub struct ResourceData<'a> {
 
 #[derive(Clone, Debug, Decode, Encode)]
 pub struct PipelineDescription {
+    /// The buffers come first, in the same order as their type descriptors, followed by the
+    /// textures and samplers.
     pub resources: Vec<PipelineResource>,
+    pub features: Vec<Feature>,
+    pub limits: Limits,
+    /// Number of workgroups to dispatch in the x, y and z dimensions.
+    pub dispatch: [u32; 3],
+}
+
+impl PipelineDescription {
+    /// Returns the indices of the bind groups that the resources are in, in ascending order.
+    pub fn bind_groups(&self) -> Vec<u32> {
+        let mut groups = self.resources.iter().map(|it| it.group).collect::<Vec<_>>();
+        groups.sort_unstable();
+        groups.dedup();
+        groups
+    }
+}
+
+/// Optional device features or WGSL language extensions that a pipeline may require.
+#[derive(Clone, Copy, Debug, Decode, Encode, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Subgroups,
+    #[serde(rename = "packed_4x8_integer_dot_product")]
+    Packed4x8IntegerDotProduct,
+    ShaderF16,
+    PushConstants,
+}
+
+/// Device limits that a pipeline may require.
+#[derive(Clone, Copy, Debug, Decode, Encode, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Limits {
+    pub max_compute_workgroup_size: [u32; 3],
+    pub max_compute_invocations_per_workgroup: u32,
+}
+
+impl Limits {
+    /// The limits required by a pipeline with a single invocation per workgroup.
+    pub const MIN: Limits = Limits {
+        max_compute_workgroup_size: [1, 1, 1],
+        max_compute_invocations_per_workgroup: 1,
+    };
+
+    /// The default limits of WebGPU, which every adapter supports.
+    pub const DEFAULT: Limits = Limits {
+        max_compute_workgroup_size: [256, 256, 64],
+        max_compute_invocations_per_workgroup: 256,
+    };
+
+    /// Returns the limits which satisfy both `self` and `other`.
+    pub fn max(self, other: Limits) -> Limits {
+        let [x, y, z] = self.max_compute_workgroup_size;
+        let [ox, oy, oz] = other.max_compute_workgroup_size;
+        Limits {
+            max_compute_workgroup_size: [x.max(ox), y.max(oy), z.max(oz)],
+            max_compute_invocations_per_workgroup: self
+                .max_compute_invocations_per_workgroup
+                .max(other.max_compute_invocations_per_workgroup),
+        }
+    }
+
+    /// Returns true if a device with the `available` limits satisfies these limits.
+    pub fn is_within(&self, available: &Limits) -> bool {
+        self.max_compute_workgroup_size
+            .iter()
+            .zip(available.max_compute_workgroup_size)
+            .all(|(required, available)| *required <= available)
+            && self.max_compute_invocations_per_workgroup
+                <= available.max_compute_invocations_per_workgroup
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::MIN
+    }
+}
+
+/// The features and limits required by a shader, which are recorded by the generator in the
+/// shader metadata.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Requirements {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<Feature>,
+    #[serde(default)]
+    pub limits: Limits,
 }
 
 #[derive(Clone, Debug, Decode, Encode, PartialEq, Eq)]
 pub enum ResourceKind {
+    /// A storage buffer that the shader can write to. These are read back after execution, and
+    /// the outputs are in the same order as the resources.
     StorageBuffer,
+    /// A storage buffer with the `read` access mode, which is only initialized like a uniform
+    /// buffer.
+    ReadOnlyStorageBuffer,
     UniformBuffer,
+    Texture(TextureDescriptor),
+    /// A sampler with nearest filtering, which compares against depth values using the `less`
+    /// function if `comparison` is set.
+    Sampler {
+        comparison: bool,
+    },
+}
+
+impl ResourceKind {
+    pub fn is_buffer(&self) -> bool {
+        matches!(
+            self,
+            ResourceKind::StorageBuffer
+                | ResourceKind::ReadOnlyStorageBuffer
+                | ResourceKind::UniformBuffer
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq)]
+pub enum TextureDimension {
+    D1,
+    D2,
+    D2Array,
+    D3,
+    Cube,
+    CubeArray,
+}
+
+/// The type of the values that are read from a texture, which determines its format.
+#[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq)]
+pub enum TextureSampleType {
+    Float,
+    Sint,
+    Uint,
+    Depth,
+}
+
+/// Describes a sampled texture that is bound to the pipeline.
+///
+/// The contents of the first mip level are given by the resource's initial data, and every other
+/// texel is zero.
+#[derive(Clone, Debug, Decode, Encode, PartialEq, Eq)]
+pub struct TextureDescriptor {
+    pub dimension: TextureDimension,
+    pub sample_type: TextureSampleType,
+    /// Width, height, and depth or number of array layers.
+    pub size: [u32; 3],
+    pub mip_level_count: u32,
+}
+
+impl TextureDescriptor {
+    /// Returns the size of a single texel, which is stored as `rgba8` or `depth16unorm`.
+    pub fn texel_size(&self) -> u32 {
+        match self.sample_type {
+            TextureSampleType::Depth => 2,
+            _ => 4,
+        }
+    }
+
+    /// Returns the size of the initial data for the first mip level, with tightly packed rows.
+    pub fn data_size(&self) -> usize {
+        self.size.iter().product::<u32>() as usize * self.texel_size() as usize
+    }
 }
 
 #[derive(Clone, Debug, Decode, Encode)]
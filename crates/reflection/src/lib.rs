@@ -1,5 +1,9 @@
-use ast::{Module, StorageClass, VarQualifier};
-pub use types::{PipelineDescription, PipelineResource, ResourceData, ResourceKind};
+use ast::types::{DataType, ScalarType};
+use ast::{AccessMode, Extension, FnAttr, LanguageExtension, Module, StorageClass, VarQualifier};
+pub use types::{
+    Feature, Limits, PipelineDescription, PipelineResource, Requirements, ResourceData,
+    ResourceKind, TextureDescriptor, TextureDimension, TextureSampleType,
+};
 
 pub fn reflect(
     module: &Module,
@@ -7,12 +11,64 @@ pub fn reflect(
 ) -> (PipelineDescription, Vec<common::Type>) {
     let mut resources = vec![];
     let mut types = vec![];
+    let mut handles = vec![];
 
     for var in &module.vars {
-        if let Some(VarQualifier { storage_class, .. }) = &var.qualifier {
-            let kind = match storage_class {
-                StorageClass::Uniform => ResourceKind::UniformBuffer,
-                StorageClass::Storage => ResourceKind::StorageBuffer,
+        if var.data_type.is_handle() {
+            let group = var
+                .group_index()
+                .expect("resource variable must have group attribute");
+
+            let binding = var
+                .binding_index()
+                .expect("resource variable must have binding attribute");
+
+            let kind = match &var.data_type {
+                DataType::Texture(dim, sampled_type) => {
+                    ResourceKind::Texture(default_texture_descriptor(*dim, Some(*sampled_type)))
+                }
+                DataType::DepthTexture(dim) => {
+                    ResourceKind::Texture(default_texture_descriptor(*dim, None))
+                }
+                DataType::Sampler => ResourceKind::Sampler { comparison: false },
+                DataType::SamplerComparison => ResourceKind::Sampler { comparison: true },
+                _ => unreachable!(),
+            };
+
+            // Texture contents are resized once the size of the texture is known.
+            let init = match kind {
+                ResourceKind::Texture(_) => init(ResourceData {
+                    name: &var.name,
+                    group,
+                    binding,
+                }),
+                _ => None,
+            };
+
+            handles.push(PipelineResource {
+                name: var.name.clone(),
+                kind,
+                group,
+                binding,
+                init,
+                size: 0,
+            });
+
+            continue;
+        }
+
+        if let Some(VarQualifier {
+            storage_class,
+            access_mode,
+        }) = &var.qualifier
+        {
+            // Storage buffers are read-only unless an access mode that allows writes is given
+            let kind = match (storage_class, access_mode) {
+                (StorageClass::Uniform, _) => ResourceKind::UniformBuffer,
+                (StorageClass::Storage, Some(AccessMode::Write | AccessMode::ReadWrite)) => {
+                    ResourceKind::StorageBuffer
+                }
+                (StorageClass::Storage, _) => ResourceKind::ReadOnlyStorageBuffer,
                 _ => continue,
             };
 
@@ -50,5 +106,86 @@ pub fn reflect(
         }
     }
 
-    (PipelineDescription { resources }, types)
+    // Textures and samplers are placed after the buffers, so that the buffers line up with their
+    // type descriptors.
+    resources.extend(handles);
+
+    let Requirements { features, limits } = requirements(module);
+
+    (
+        PipelineDescription {
+            resources,
+            features,
+            limits,
+            dispatch: [1, 1, 1],
+        },
+        types,
+    )
+}
+
+/// Returns the features and limits that are needed to run the module, as determined by its
+/// directives and the workgroup size of its entrypoint.
+pub fn requirements(module: &Module) -> Requirements {
+    let mut features = vec![];
+
+    if module.is_enabled(Extension::Subgroups) {
+        features.push(Feature::Subgroups);
+    }
+
+    if module.is_required(LanguageExtension::Packed4x8IntegerDotProduct) {
+        features.push(Feature::Packed4x8IntegerDotProduct);
+    }
+
+    let mut limits = Limits::MIN;
+
+    for attr in module.functions.iter().flat_map(|it| &it.attrs) {
+        if let FnAttr::WorkgroupSize(size) = attr {
+            // The y and z dimensions default to 1 if omitted, and dimensions whose size is unknown
+            // are assumed to be 1.
+            let mut workgroup_size = [1; 3];
+            for (dim, size) in workgroup_size.iter_mut().zip(size) {
+                *dim = size.value().unwrap_or(1);
+            }
+
+            limits = limits.max(Limits {
+                max_compute_workgroup_size: workgroup_size,
+                max_compute_invocations_per_workgroup: workgroup_size.iter().product(),
+            });
+        }
+    }
+
+    Requirements { features, limits }
+}
+
+/// Returns the smallest texture of the given type, which is used unless a size is given in the
+/// input data. Depth textures have no sampled type.
+fn default_texture_descriptor(
+    dim: ast::TextureDimension,
+    sampled_type: Option<ScalarType>,
+) -> TextureDescriptor {
+    let (dimension, layers) = match dim {
+        ast::TextureDimension::D1 => (TextureDimension::D1, 1),
+        ast::TextureDimension::D2 => (TextureDimension::D2, 1),
+        ast::TextureDimension::D2Array => (TextureDimension::D2Array, 1),
+        ast::TextureDimension::D3 => (TextureDimension::D3, 1),
+        ast::TextureDimension::Cube => (TextureDimension::Cube, 6),
+        ast::TextureDimension::CubeArray => (TextureDimension::CubeArray, 6),
+    };
+
+    let sample_type = match sampled_type {
+        Some(ScalarType::F32) => TextureSampleType::Float,
+        Some(ScalarType::I32) => TextureSampleType::Sint,
+        Some(ScalarType::U32) => TextureSampleType::Uint,
+        Some(ty @ (ScalarType::Bool | ScalarType::F16)) => {
+            panic!("invalid sampled type for texture: {ty}")
+        }
+        None => TextureSampleType::Depth,
+    };
+
+    TextureDescriptor {
+        dimension,
+        sample_type,
+        size: [1, 1, layers],
+        mip_level_count: 1,
+    }
 }
@@ -0,0 +1,83 @@
+//! Mismatches that are caused by bugs which are already known, so that a long fuzzing campaign
+//! against a driver with bugs that were filed (but not fixed) isn't flooded by them.
+//!
+//! A baseline is a TOML file that lists the known bugs:
+//!
+//! ```toml
+//! [[known]]
+//! config = "dawn:vk:.*"
+//! feature = "subgroup"
+//! bug = "dawn:1234"
+//! ```
+
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use eyre::Context;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::harness_runner::ConsensusEntry;
+
+#[derive(Default, Deserialize)]
+pub struct Baseline {
+    #[serde(default)]
+    pub known: Vec<KnownIssue>,
+}
+
+#[derive(Deserialize)]
+pub struct KnownIssue {
+    /// Matched against the ID of each config whose output is wrong because of the bug.
+    #[serde(with = "serde_regex")]
+    pub config: Regex,
+    /// Matched against the shader, if the bug only affects shaders that use a particular feature.
+    #[serde(default, with = "serde_regex")]
+    pub feature: Option<Regex>,
+    /// Link to the bug report, or any other note that is printed when the bug is hit.
+    #[serde(default)]
+    pub bug: Option<String>,
+}
+
+impl KnownIssue {
+    fn covers(&self, config: &str, shader: &str) -> bool {
+        self.config.is_match(config) && self.feature.as_ref().is_none_or(|it| it.is_match(shader))
+    }
+}
+
+impl Display for KnownIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.bug {
+            Some(bug) => write!(f, "{bug}"),
+            None => write!(f, "`{}`", self.config),
+        }
+    }
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> eyre::Result<Baseline> {
+        let bytes = std::fs::read(path)
+            .wrap_err_with(|| format!("failed to read baseline `{}`", path.display()))?;
+        toml::from_slice(&bytes)
+            .wrap_err_with(|| format!("failed to parse baseline `{}`", path.display()))
+    }
+
+    /// Returns the known issues that explain a mismatch, or `None` if it may be caused by a new
+    /// bug.
+    ///
+    /// A mismatch is explained if one of the outputs is assumed to be correct and every config
+    /// with a different output is covered by a known issue. Each output is tried in turn, since the
+    /// buggy configs may be in the majority (e.g. when a bug affects all Vulkan configs).
+    pub fn explain(&self, consensus: &[ConsensusEntry], shader: &str) -> Option<Vec<&KnownIssue>> {
+        (0..consensus.len()).find_map(|correct| {
+            let mut issues: Vec<&KnownIssue> = vec![];
+            let wrong = consensus.iter().enumerate().filter(|(i, _)| *i != correct);
+            for config in wrong.flat_map(|(_, entry)| &entry.configs) {
+                let issue = self.known.iter().find(|it| it.covers(config, shader))?;
+                if !issues.iter().any(|it| std::ptr::eq(*it, issue)) {
+                    issues.push(issue);
+                }
+            }
+            Some(issues)
+        })
+    }
+}
@@ -84,6 +84,7 @@ fn validate_tint(source: &str) -> eyre::Result<()> {
 }
 
 fn compile_naga(source: &str, backend: Backend) -> eyre::Result<String> {
+    use naga::back::pipeline_constants::process_overrides;
     use naga::back::{hlsl, msl, spv};
     use naga::front::wgsl;
     use naga::valid::{Capabilities, ValidationFlags, Validator};
@@ -92,6 +93,10 @@ fn compile_naga(source: &str, backend: Backend) -> eyre::Result<String> {
     let validation =
         Validator::new(ValidationFlags::default(), Capabilities::all()).validate(&module)?;
 
+    // Overrides must be replaced by constants before the module can be written by a backend. No
+    // pipeline constants are given, so each override takes its default value.
+    let (module, validation) = process_overrides(&module, &validation, None, &Default::default())?;
+
     let mut out = String::new();
 
     match backend {
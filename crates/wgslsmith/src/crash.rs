@@ -0,0 +1,229 @@
+//! Extracts a signature from the output of a harness that crashed, which stays the same for
+//! different shaders that trigger the same bug so that crashes can be deduplicated and bucketed.
+
+use std::fmt::{self, Display};
+
+use harness_types::ConfigId;
+use regex::Regex;
+
+/// Number of stack frames that are kept in a signature. Frames further from the crash are more
+/// likely to depend on how the shader was executed rather than on the bug.
+const MAX_FRAMES: usize = 5;
+
+/// Messages are truncated to this many characters, since e.g. validation errors can include
+/// arbitrarily long snippets of the shader.
+const MAX_MESSAGE_LEN: usize = 200;
+
+/// Message of the panic with which the harness exits after reporting failed executions, which is
+/// part of every crash and so says nothing about the bug.
+const EXECUTIONS_FAILED_MESSAGE: &str = "one or more executions failed";
+
+/// Frames from the standard library and the panic and error reporting machinery, which don't
+/// identify the code that crashed.
+const IGNORED_FRAME_PREFIXES: &[&str] = &[
+    "std::",
+    "core::",
+    "alloc::",
+    "<std::",
+    "<core::",
+    "<alloc::",
+    "backtrace::",
+    "color_eyre::",
+    "eyre::",
+    "rust_begin_unwind",
+    "rust_panic",
+    "__rust",
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrashSignature {
+    /// How the first failed execution was reported, e.g. `device lost`, or `None` if the harness
+    /// crashed before reporting any executions.
+    pub kind: Option<String>,
+    /// The config of the first failed execution.
+    pub config: Option<ConfigId>,
+    /// The assertion, panic or error message, with numbers and addresses replaced so that it
+    /// doesn't depend on the shader.
+    pub message: Option<String>,
+    /// The first error code that was reported, e.g. a `VkResult` or a failed `HRESULT`.
+    pub code: Option<String>,
+    /// The innermost frames of the backtrace, without the standard library and hashes.
+    pub frames: Vec<String>,
+}
+
+impl CrashSignature {
+    /// Extracts the signature of the first failure in the output of `wgslsmith run`.
+    pub fn from_output(output: &str) -> CrashSignature {
+        let ansi = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+        let output = ansi.replace_all(output, "");
+
+        let header = Regex::new(
+            r"^(compilation failure|validation failure|runtime failure|device lost|hang) \((\S+)\)$",
+        )
+        .unwrap();
+        let start = Regex::new(r"^\[[0-9:]+\] executing ").unwrap();
+
+        let mut lines = output.lines().map(str::trim_end);
+        let mut signature = CrashSignature::default();
+
+        // Everything before the first failure is the output of successful executions, and its
+        // stderr ends where the next execution is reported
+        let body = match lines.clone().position(|line| header.is_match(line)) {
+            Some(index) => {
+                let captures = header.captures(lines.nth(index).unwrap()).unwrap();
+                signature.kind = Some(captures[1].to_owned());
+                signature.config = captures[2].parse().ok();
+
+                lines
+                    .take_while(|line| {
+                        !header.is_match(line)
+                            && !start.is_match(line)
+                            && !line.contains(EXECUTIONS_FAILED_MESSAGE)
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None => lines.collect(),
+        };
+
+        signature.message = find_message(&body).map(|it| normalize(&it));
+        signature.code = find_code(&body);
+        signature.frames = find_frames(&body);
+
+        signature
+    }
+
+    /// Returns a short hash of the signature, which is stable across runs and machines. The device
+    /// ID of the config is left out, so that the same crash on different GPUs has the same key.
+    pub fn key(&self) -> String {
+        let config = self.config.as_ref().map(|it| ConfigId {
+            device_id: 0,
+            ..it.clone()
+        });
+
+        let fields = [
+            self.kind.clone().unwrap_or_default(),
+            config.map(|it| it.to_string()).unwrap_or_default(),
+            self.message.clone().unwrap_or_default(),
+            self.code.clone().unwrap_or_default(),
+            self.frames.join("\n"),
+        ];
+
+        // FNV-1a, since the hashers in std aren't guaranteed to be stable between releases
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in fields.join("\0").bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        format!("{hash:016x}")
+    }
+}
+
+impl Display for CrashSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "key: {}", self.key())?;
+
+        if let Some(kind) = &self.kind {
+            writeln!(f, "kind: {kind}")?;
+        }
+
+        if let Some(config) = &self.config {
+            writeln!(f, "config: {config}")?;
+        }
+
+        if let Some(message) = &self.message {
+            writeln!(f, "message: {message}")?;
+        }
+
+        if let Some(code) = &self.code {
+            writeln!(f, "code: {code}")?;
+        }
+
+        if !self.frames.is_empty() {
+            writeln!(f, "frames:")?;
+            for frame in &self.frames {
+                writeln!(f, "  {frame}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds the message that best describes the crash. Assertions are the most specific, followed by
+/// panics, and then the errors reported by the backend or the harness.
+fn find_message(lines: &[&str]) -> Option<String> {
+    let assertion = Regex::new(r"(?i)(assertion failed|assertion failure|check failed)").unwrap();
+    let panic = Regex::new(r"^\s*Message:\s+(.+)$|panicked at '(.*)', ").unwrap();
+    let error = Regex::new(
+        r"^((?:compilation failed|validation failed|runtime error|device lost|failed to create device): .+)$|^Error: (.+)$",
+    )
+    .unwrap();
+
+    if let Some(line) = lines.iter().find(|line| assertion.is_match(line)) {
+        return Some(line.trim().to_owned());
+    }
+
+    // Since Rust 1.73, the message of a panic is printed on the line after its location
+    if let Some(index) = lines
+        .iter()
+        .position(|line| line.starts_with("thread '") && line.ends_with(':'))
+    {
+        if let Some(line) = lines.get(index + 1) {
+            return Some(line.trim().to_owned());
+        }
+    }
+
+    [panic, error].iter().find_map(|regex| {
+        lines.iter().find_map(|line| {
+            let captures = regex.captures(line)?;
+            let message = captures.iter().skip(1).flatten().next()?;
+            Some(message.as_str().trim().to_owned())
+        })
+    })
+}
+
+fn find_code(lines: &[&str]) -> Option<String> {
+    let code =
+        Regex::new(r"\b(VK_ERROR_[A-Z0-9_]+|DXGI_ERROR_[A-Z_]+|E_[A-Z]+|0x8[0-9A-Fa-f]{7})\b")
+            .unwrap();
+
+    lines
+        .iter()
+        .find_map(|line| Some(code.captures(line)?[1].to_owned()))
+}
+
+/// Finds the frames of Rust backtraces and sanitizer stack traces, e.g. `3: harness::run::h1234`
+/// or `#3 0x5621 in dawn::native::Device::Tick`.
+fn find_frames(lines: &[&str]) -> Vec<String> {
+    let rust = Regex::new(r"^\s*\d+: (.+?)(?:::h[0-9a-f]{16})?$").unwrap();
+    let sanitizer = Regex::new(r"^\s*#\d+ 0x[0-9a-fA-F]+ in (\S+)").unwrap();
+
+    lines
+        .iter()
+        .filter_map(|line| {
+            let captures = rust.captures(line).or_else(|| sanitizer.captures(line))?;
+            Some(captures[1].to_owned())
+        })
+        .filter(|name| {
+            !IGNORED_FRAME_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .take(MAX_FRAMES)
+        .collect()
+}
+
+/// Replaces the parts of a message that vary between shaders and runs, such as sizes, indices,
+/// line numbers and addresses.
+fn normalize(message: &str) -> String {
+    let address = Regex::new(r"0x[0-9a-fA-F]+").unwrap();
+    let number = Regex::new(r"\d+").unwrap();
+    let whitespace = Regex::new(r"\s+").unwrap();
+
+    let message = address.replace_all(message, "0x_");
+    let message = number.replace_all(&message, "N");
+    let message = whitespace.replace_all(&message, " ");
+
+    message.trim().chars().take(MAX_MESSAGE_LEN).collect()
+}
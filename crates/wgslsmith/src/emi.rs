@@ -0,0 +1,199 @@
+mod transform;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write as _};
+use std::path::PathBuf;
+
+use clap::Parser;
+use eyre::eyre;
+use harness_types::ConfigId;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::config::Config;
+use crate::harness_runner::{self, get_targets, ExecutionResult, Target, TargetPath};
+
+#[derive(Parser)]
+pub struct Options {
+    /// Path to a shader generated by `wgslsmith gen` (use '-' for stdin).
+    #[clap(action, default_value = "-")]
+    input: String,
+
+    /// JSON string or path to a JSON file containing the shader inputs.
+    ///
+    /// By default this is read from the metadata comment on the first line of the shader.
+    #[clap(long, action)]
+    input_data: Option<String>,
+
+    /// Skip reconditioning, if the shader has already been reconditioned.
+    #[clap(long, action)]
+    reconditioned: bool,
+
+    /// Number of equivalent variants to derive from the shader.
+    #[clap(short = 'n', long, action, default_value = "5")]
+    variants: u32,
+
+    /// Seed for the random number generator used to derive variants.
+    #[clap(long, action)]
+    seed: Option<u64>,
+
+    /// Path to directory in which to save variants whose output differs from the original.
+    #[clap(short, long, action, default_value = "out/emi")]
+    output: PathBuf,
+
+    /// Address of harness server.
+    #[clap(short, long, action)]
+    server: Option<String>,
+
+    #[clap(short, long = "config", action)]
+    configs: Vec<ConfigId>,
+
+    #[clap(short = 't', long = "target", action)]
+    targets: Vec<TargetPath>,
+}
+
+/// Outcome of running a shader on every target.
+enum RunResult {
+    /// Output buffers produced by each config.
+    Outputs(BTreeMap<String, Vec<u8>>),
+    Crash(String),
+}
+
+pub fn run(config: Config, options: Options) -> eyre::Result<()> {
+    let targets = get_targets(&config, &options.server, &options.configs, &options.targets)?;
+
+    let source = read_shader_from_path(&options.input)?;
+    let (metadata, source) = match options.input_data.as_deref() {
+        Some(input_data) => {
+            let input_data = harness_frontend::read_input_data(&options.input, Some(input_data))?;
+            (serde_json::to_string(&input_data)?, source.as_str())
+        }
+        None => {
+            let (metadata, source) = source.split_once('\n').ok_or_else(|| {
+                eyre!("expected first line of shader to be a JSON metadata comment")
+            })?;
+            (metadata.trim_start_matches("//").trim().to_owned(), source)
+        }
+    };
+
+    let mut module = parser::parse(source);
+    if !options.reconditioned {
+        module = reconditioner::recondition(module).ast;
+
+        if !reconditioner::analysis::analyse(&module) {
+            return Err(eyre!("rejecting due to possible invalid aliasing"));
+        }
+    }
+
+    let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    println!("seed: {seed}");
+
+    let original = write_module(&module);
+    let expected = match run_shader(&targets, &original, &metadata)? {
+        RunResult::Outputs(outputs) => outputs,
+        RunResult::Crash(output) => {
+            eprintln!("{output}");
+            return Err(eyre!("original shader crashed"));
+        }
+    };
+
+    let mut failures = 0;
+    let mut stdout = StandardStream::stdout(ColorChoice::Auto);
+
+    for i in 0..options.variants {
+        let variant = transform::gen_variant(parser::parse(&original), &mut rng);
+        let variant = write_module(&variant);
+
+        let differences = match run_shader(&targets, &variant, &metadata)? {
+            // Configs which timed out are missing from the outputs, so they are ignored.
+            RunResult::Outputs(outputs) => expected
+                .iter()
+                .filter(|(config, expected)| outputs.get(*config).is_some_and(|it| it != *expected))
+                .map(|(config, _)| format!("output differs on {config}"))
+                .collect::<Vec<_>>(),
+            RunResult::Crash(output) => vec![format!("variant crashed:\n{output}")],
+        };
+
+        if differences.is_empty() {
+            println!("variant {i}: ok");
+            continue;
+        }
+
+        failures += 1;
+
+        let mut red = ColorSpec::new();
+        red.set_fg(Some(Color::Red));
+        stdout.set_color(&red)?;
+        writeln!(stdout, "variant {i}: not equivalent to original")?;
+        stdout.reset()?;
+
+        for difference in &differences {
+            println!("  {difference}");
+        }
+
+        let out = options.output.join(format!("{seed}-{i}"));
+        std::fs::create_dir_all(&out)?;
+        std::fs::write(out.join("original.wgsl"), &original)?;
+        std::fs::write(out.join("variant.wgsl"), &variant)?;
+        std::fs::write(out.join("inputs.json"), &metadata)?;
+        std::fs::write(out.join("differences.txt"), differences.join("\n"))?;
+
+        println!("  saved to {}", out.display());
+    }
+
+    if failures > 0 {
+        println!(
+            "{failures} of {} variants were not equivalent",
+            options.variants
+        );
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn run_shader(targets: &[Target], shader: &str, metadata: &str) -> eyre::Result<RunResult> {
+    let mut outputs = BTreeMap::new();
+
+    for target in targets {
+        let entries = match harness_runner::exec_shader(target, shader, metadata, |_| {})? {
+            ExecutionResult::Success(entry) => entry.into_iter().collect(),
+            ExecutionResult::Mismatch(entries) => entries,
+            ExecutionResult::Crash(output, _) => return Ok(RunResult::Crash(output)),
+            // Runs in which a config timed out are skipped, like runs without any outputs
+            ExecutionResult::Timeout(_) => vec![],
+        };
+
+        for entry in entries {
+            for config in entry.configs {
+                outputs.insert(config, entry.output.clone());
+            }
+        }
+    }
+
+    Ok(RunResult::Outputs(outputs))
+}
+
+fn write_module(module: &ast::Module) -> String {
+    let mut output = String::new();
+    ast::writer::Writer
+        .write_module(&mut output, module)
+        .unwrap();
+    output
+}
+
+fn read_shader_from_path(path: &str) -> eyre::Result<String> {
+    let mut input: Box<dyn Read> = match path {
+        "-" => Box::new(std::io::stdin()),
+        path => Box::new(File::open(path)?),
+    };
+
+    let mut shader = String::new();
+    input.read_to_string(&mut shader)?;
+
+    Ok(shader)
+}
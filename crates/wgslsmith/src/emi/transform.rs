@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+
+use ast::types::{DataType, MemoryViewType, ScalarType};
+use ast::{
+    AssignmentLhs, AssignmentOp, AssignmentStatement, BinOp, BinOpExpr, Else, Expr, ExprNode,
+    ForLoopStatement, IfStatement, Lit, LoopStatement, Module, Postfix, PostfixExpr, Statement,
+    StorageClass, SwitchStatement, UnOp, UnOpExpr, VarExpr, WhileStatement,
+};
+use rand::Rng;
+
+/// Probability of wrapping a statement in an always-true conditional.
+const WRAP_PROBABILITY: f64 = 0.3;
+
+/// Probability of duplicating a pure expression before the statement in which it is used.
+const DUPLICATE_PROBABILITY: f64 = 0.3;
+
+/// Derives a variant of `module` which is semantically equivalent to it.
+///
+/// The module is expected to have been reconditioned, since the transformations rely on it being
+/// free of undefined behaviour. Each variant is produced by applying the following randomly
+/// throughout the module:
+///
+/// - Wrapping statements in `if` statements whose condition is always true. When the shader reads
+///   an integer from the `u_input` uniform buffer the condition is computed from it, so that
+///   compilers can't trivially fold it away.
+/// - Duplicating side-effect free expressions in phony assignments (`_ = expr;`), which compute
+///   and discard the same value again.
+pub fn gen_variant(mut module: Module, rng: &mut impl Rng) -> Module {
+    let opaque_input = find_opaque_input(&module);
+
+    // Calls to user-defined functions may have side effects, except for the reconditioner's safe
+    // wrappers.
+    let impure_fns = module
+        .functions
+        .iter()
+        .filter(|it| !it.name.starts_with("_wgslsmith_"))
+        .map(|it| it.name.clone())
+        .collect();
+
+    let mut transformer = Transformer {
+        rng,
+        opaque_input,
+        impure_fns,
+    };
+
+    for function in &mut module.functions {
+        if function.name.starts_with("_wgslsmith_") {
+            continue;
+        }
+
+        let body = std::mem::take(&mut function.body);
+        function.body = transformer.transform_block(body);
+    }
+
+    module
+}
+
+struct Transformer<'a, R> {
+    rng: &'a mut R,
+    opaque_input: Option<ExprNode>,
+    impure_fns: HashSet<String>,
+}
+
+impl<R: Rng> Transformer<'_, R> {
+    fn transform_block(&mut self, block: Vec<Statement>) -> Vec<Statement> {
+        let mut output = vec![];
+        let mut wrapped = vec![];
+
+        for stmt in block {
+            let stmt = self.transform_stmt(stmt);
+            let duplicate = self.gen_duplicate(&stmt);
+
+            // Consecutive statements that are chosen for wrapping are placed in the same `if`.
+            if is_wrappable(&stmt) && self.rng.gen_bool(WRAP_PROBABILITY) {
+                wrapped.extend(duplicate);
+                wrapped.push(stmt);
+                continue;
+            }
+
+            if !wrapped.is_empty() {
+                output.push(self.gen_always_true_if(std::mem::take(&mut wrapped)));
+            }
+
+            output.extend(duplicate);
+            output.push(stmt);
+        }
+
+        if !wrapped.is_empty() {
+            output.push(self.gen_always_true_if(wrapped));
+        }
+
+        output
+    }
+
+    fn transform_stmt(&mut self, stmt: Statement) -> Statement {
+        match stmt {
+            Statement::Compound(block) => Statement::Compound(self.transform_block(block)),
+            Statement::If(stmt) => self.transform_if(stmt).into(),
+            Statement::Loop(LoopStatement { body, continuing }) => {
+                LoopStatement::new(self.transform_block(body))
+                    .with_continuing(continuing)
+                    .into()
+            }
+            Statement::While(WhileStatement { condition, body }) => {
+                WhileStatement::new(condition, self.transform_block(body)).into()
+            }
+            Statement::ForLoop(ForLoopStatement { header, body }) => {
+                ForLoopStatement::new(*header, self.transform_block(body)).into()
+            }
+            Statement::Switch(SwitchStatement {
+                selector,
+                mut cases,
+                default,
+            }) => {
+                for case in &mut cases {
+                    case.body = self.transform_block(std::mem::take(&mut case.body));
+                }
+
+                SwitchStatement::new(selector, cases, self.transform_block(default)).into()
+            }
+            stmt => stmt,
+        }
+    }
+
+    fn transform_if(&mut self, stmt: IfStatement) -> IfStatement {
+        let IfStatement {
+            condition,
+            body,
+            else_,
+        } = stmt;
+
+        let else_ = else_.map(|else_| match *else_ {
+            Else::If(stmt) => Else::If(self.transform_if(stmt)),
+            Else::Else(block) => Else::Else(self.transform_block(block)),
+        });
+
+        IfStatement::new(condition, self.transform_block(body)).with_else(else_)
+    }
+
+    /// Returns a phony assignment which recomputes a pure expression used by `stmt`, if one is
+    /// chosen.
+    fn gen_duplicate(&mut self, stmt: &Statement) -> Option<Statement> {
+        let expr = match stmt {
+            Statement::LetDecl(stmt) => &stmt.initializer,
+            Statement::VarDecl(stmt) => stmt.initializer.as_ref()?,
+            Statement::Assignment(stmt) => &stmt.rhs,
+            _ => return None,
+        };
+
+        if !self.is_pure(expr) || !self.rng.gen_bool(DUPLICATE_PROBABILITY) {
+            return None;
+        }
+
+        Some(
+            AssignmentStatement::new(AssignmentLhs::Phony, AssignmentOp::Simple, expr.clone())
+                .into(),
+        )
+    }
+
+    fn is_pure(&self, expr: &ExprNode) -> bool {
+        match &expr.expr {
+            Expr::Lit(_) | Expr::Var(_) => true,
+            Expr::TypeCons(expr) => expr.args.iter().all(|it| self.is_pure(it)),
+            Expr::Bitcast(expr) => self.is_pure(&expr.inner),
+            Expr::Postfix(expr) => {
+                self.is_pure(&expr.inner)
+                    && match &expr.postfix {
+                        Postfix::Index(index) => self.is_pure(index),
+                        Postfix::Member(_) => true,
+                    }
+            }
+            Expr::UnOp(expr) => self.is_pure(&expr.inner),
+            Expr::BinOp(expr) => self.is_pure(&expr.left) && self.is_pure(&expr.right),
+            Expr::FnCall(expr) => {
+                !self.impure_fns.contains(&expr.ident)
+                    && !expr.ident.starts_with("atomic")
+                    && expr.args.iter().all(|it| self.is_pure(it))
+            }
+        }
+    }
+
+    fn gen_always_true_if(&mut self, body: Vec<Statement>) -> Statement {
+        IfStatement::new(self.gen_opaque_true_expr(), body).into()
+    }
+
+    /// Generates a boolean expression which is true for any value in the input buffer.
+    fn gen_opaque_true_expr(&mut self) -> ExprNode {
+        let x = match &self.opaque_input {
+            Some(x) => x.clone(),
+            None => return Lit::Bool(true).into(),
+        };
+
+        let is_signed = x.data_type.dereference().is_signed_int();
+        let lit = |v: u32| {
+            if is_signed {
+                Lit::I32(v as i32)
+            } else {
+                Lit::U32(v)
+            }
+        };
+
+        match self.rng.gen_range(0..3) {
+            // Squares are always 0 or 1 modulo 4, even when the multiplication overflows.
+            0 => BinOpExpr::new(
+                BinOp::NotEqual,
+                BinOpExpr::new(
+                    BinOp::BitAnd,
+                    BinOpExpr::new(BinOp::Times, x.clone(), x),
+                    lit(3),
+                ),
+                lit(2),
+            )
+            .into(),
+            // The product of two consecutive integers is always even.
+            1 => BinOpExpr::new(
+                BinOp::Equal,
+                BinOpExpr::new(
+                    BinOp::BitAnd,
+                    BinOpExpr::new(
+                        BinOp::Times,
+                        x.clone(),
+                        BinOpExpr::new(BinOp::Plus, x, lit(1)),
+                    ),
+                    lit(1),
+                ),
+                lit(0),
+            )
+            .into(),
+            // A value never shares any bits with its complement.
+            _ => BinOpExpr::new(
+                BinOp::Equal,
+                BinOpExpr::new(BinOp::BitAnd, x.clone(), UnOpExpr::new(UnOp::BitNot, x)),
+                lit(0),
+            )
+            .into(),
+        }
+    }
+}
+
+/// Returns true if `stmt` can be moved into a nested block without changing the meaning of the
+/// program.
+///
+/// Declarations would go out of scope, and wrapping a statement which returns from the function
+/// could make the compiler think that the end of the function is reachable.
+fn is_wrappable(stmt: &Statement) -> bool {
+    !matches!(
+        stmt,
+        Statement::LetDecl(_)
+            | Statement::ConstDecl(_)
+            | Statement::ConstAssert(_)
+            | Statement::VarDecl(_)
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Fallthrough
+    ) && !contains_return(stmt)
+}
+
+fn contains_return(stmt: &Statement) -> bool {
+    let any = |block: &[Statement]| block.iter().any(contains_return);
+    match stmt {
+        Statement::Return(_) => true,
+        Statement::Compound(block) => any(block),
+        Statement::If(stmt) => if_contains_return(stmt),
+        Statement::Loop(stmt) => any(&stmt.body),
+        Statement::ForLoop(stmt) => any(&stmt.body),
+        Statement::While(stmt) => any(&stmt.body),
+        Statement::Switch(stmt) => stmt.cases.iter().any(|it| any(&it.body)) || any(&stmt.default),
+        _ => false,
+    }
+}
+
+fn if_contains_return(stmt: &IfStatement) -> bool {
+    stmt.body.iter().any(contains_return)
+        || match stmt.else_.as_deref() {
+            Some(Else::If(stmt)) => if_contains_return(stmt),
+            Some(Else::Else(block)) => block.iter().any(contains_return),
+            None => false,
+        }
+}
+
+/// Finds an integer scalar in the `u_input` uniform buffer, returning an expression which reads
+/// it.
+fn find_opaque_input(module: &Module) -> Option<ExprNode> {
+    let var = module.vars.iter().find(|it| it.name == "u_input")?;
+    let ty = DataType::Ref(MemoryViewType::new(
+        var.data_type.clone(),
+        StorageClass::Uniform,
+    ));
+
+    find_int_accessor(VarExpr::new("u_input").into_node(ty))
+}
+
+fn find_int_accessor(expr: ExprNode) -> Option<ExprNode> {
+    match expr.data_type.dereference() {
+        DataType::Scalar(ScalarType::I32 | ScalarType::U32) => Some(expr),
+        DataType::Vector(_, ScalarType::I32 | ScalarType::U32) => {
+            Some(PostfixExpr::new(expr, Postfix::member("x")).into())
+        }
+        DataType::Array(..) => {
+            find_int_accessor(PostfixExpr::new(expr, Postfix::index(Lit::I32(0))).into())
+        }
+        DataType::Struct(decl) => decl.members.iter().find_map(|member| {
+            find_int_accessor(PostfixExpr::new(expr.clone(), Postfix::member(&member.name)).into())
+        }),
+        _ => None,
+    }
+}
@@ -33,7 +33,7 @@ pub fn run(options: Options) -> eyre::Result<()> {
         path => Box::new(File::create(path)?),
     };
 
-    ast::writer::Writer::default()
+    ast::writer::Writer
         .write_module(&mut Output(output), &ast)
         .unwrap();
 
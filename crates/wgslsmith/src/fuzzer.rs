@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
 use std::io::{self, BufWriter, Write as _};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
@@ -24,9 +25,11 @@ use tui::text::Spans;
 use tui::widgets::{Block, Borders, Paragraph};
 use tui::Terminal;
 
+use crate::baseline::Baseline;
 use crate::config::Config;
 use crate::harness_runner::{
-    self, get_targets, ConsensusEntry, ExecutionResult, Target, TargetPath,
+    self, get_targets, ConsensusEntry, ExecutionDetails, ExecutionResult, Harness, Target,
+    TargetPath,
 };
 
 #[derive(Copy, Clone, ValueEnum)]
@@ -56,6 +59,21 @@ pub struct Options {
     #[clap(long, action)]
     ignore: Vec<Regex>,
 
+    /// Only save the first crash with each signature.
+    ///
+    /// The signature of a crash is extracted from the harness output, and is saved along with the
+    /// test case as `signature.txt`. Crashes that were saved to the output directory by earlier
+    /// runs are also taken into account.
+    #[clap(long, action)]
+    dedupe: bool,
+
+    /// Path to a baseline file listing known bugs, as combinations of configs and shader features.
+    ///
+    /// Mismatches that are explained by the baseline are reported as known mismatches and aren't
+    /// saved.
+    #[clap(long, action)]
+    baseline: Option<PathBuf>,
+
     /// Address of harness server.
     #[clap(short, long, action)]
     server: Option<String>,
@@ -63,6 +81,34 @@ pub struct Options {
     #[clap(long, action)]
     enable_pointers: bool,
 
+    #[clap(long, action)]
+    enable_subgroups: bool,
+
+    #[clap(long, action)]
+    enable_packed_dot_products: bool,
+
+    #[clap(long, action)]
+    enable_textures: bool,
+
+    #[clap(long, action)]
+    enable_barriers: bool,
+
+    /// Enable a random subset of language features for each generated shader.
+    #[clap(long, action)]
+    swarm: bool,
+
+    /// Write literals whose type is determined by their context as abstract literals.
+    #[clap(long, action)]
+    abstract_literals: bool,
+
+    /// Inject unreachable blocks of code into generated shaders.
+    #[clap(long, action)]
+    dead_code: bool,
+
+    /// Compute the output of generated shaders through a chain of nested calls of this depth.
+    #[clap(long, action)]
+    max_call_depth: Option<u32>,
+
     #[clap(short, long = "config", action)]
     configs: Vec<ConfigId>,
 
@@ -78,6 +124,14 @@ pub struct Options {
     /// This is mostly for debugging.
     #[clap(long, action)]
     save_failures: bool,
+
+    /// Execute saved crashes and mismatches again while recording wgpu API traces, which are saved
+    /// in the `trace` directory of the test case.
+    ///
+    /// Traces can be replayed with wgpu's `player` without wgslsmith. Only local targets are
+    /// traced, and the harness must be built with the `trace` feature.
+    #[clap(long, action)]
+    trace: bool,
 }
 
 fn gen_shader(options: &Options) -> eyre::Result<String> {
@@ -90,6 +144,38 @@ fn gen_shader(options: &Options) -> eyre::Result<String> {
             if options.enable_pointers {
                 cmd.arg("--enable-pointers");
             }
+
+            if options.enable_subgroups {
+                cmd.arg("--enable-subgroups");
+            }
+
+            if options.enable_packed_dot_products {
+                cmd.arg("--enable-packed-dot-products");
+            }
+
+            if options.enable_textures {
+                cmd.arg("--enable-textures");
+            }
+
+            if options.enable_barriers {
+                cmd.arg("--enable-barriers");
+            }
+
+            if options.swarm {
+                cmd.arg("--swarm");
+            }
+
+            if options.abstract_literals {
+                cmd.arg("--abstract-literals");
+            }
+
+            if options.dead_code {
+                cmd.arg("--dead-code");
+            }
+
+            if let Some(depth) = options.max_call_depth {
+                cmd.args(["--max-call-depth", &depth.to_string()]);
+            }
         })
         .stdout(Stdio::piped())
         .output()?;
@@ -131,8 +217,8 @@ impl ExecutionResult {
     ) -> bool {
         match self {
             ExecutionResult::Success(_) => false,
-            // ExecutionResult::Timeout => false,
-            ExecutionResult::Crash(output) => {
+            ExecutionResult::Timeout(_) => false,
+            ExecutionResult::Crash(output, _) => {
                 matches!(strategy, SaveStrategy::All | SaveStrategy::Crashes)
                     && !ignore.any(|it| it.is_match(output))
             }
@@ -152,7 +238,8 @@ fn save_shader(
     metadata: &str,
     output: Option<&str>,
     kind: Option<ExecutionResult>,
-) -> eyre::Result<()> {
+    details: &[ExecutionDetails],
+) -> eyre::Result<PathBuf> {
     let now = OffsetDateTime::now_utc().to_offset(unsafe { UTC_OFFSET }.unwrap());
     let mut filename = now.format(&format_description::parse(
         "[year]-[month]/[day]/[hour]-[minute]-[second]",
@@ -173,18 +260,65 @@ fn save_shader(
     if let Some(output) = output {
         std::fs::write(out.join("stderr.txt"), output.replace('\0', ""))?;
     }
-    if let Some(ExecutionResult::Mismatch(consensus_vec)) = kind {
+
+    // Each target reports the environment of the machine that it executed the shader on
+    let environments = details
+        .iter()
+        .filter_map(|it| it.environment.as_deref())
+        .collect::<Vec<_>>();
+    if !environments.is_empty() {
         std::fs::write(
-            out.join("consensus.json"),
-            consensus_vec
-                .iter()
-                .map(|e| format!("{:?} {:?}", e.configs, e.output))
-                .collect::<Vec<_>>()
-                .join("\n"),
+            out.join("environment.json"),
+            format!("[{}]", environments.join(",")),
         )?;
     }
 
-    Ok(())
+    // The outputs of configs are only compared within a target, so each has its own report
+    let reports = details
+        .iter()
+        .filter_map(|it| it.report.as_deref())
+        .collect::<Vec<_>>();
+    if !reports.is_empty() {
+        std::fs::write(out.join("report.json"), format!("[{}]", reports.join(",")))?;
+    }
+    if let Some(ExecutionResult::Crash(_, signature)) = &kind {
+        std::fs::write(out.join("signature.txt"), signature.to_string())?;
+    }
+
+    if let Some(ExecutionResult::Mismatch(consensus_vec)) = kind {
+        // The differing values are only known if the outputs came from the same harness, and
+        // otherwise the raw outputs are saved
+        let has_diffs = consensus_vec.iter().any(|e| !e.diffs.is_empty());
+        let consensus = consensus_vec
+            .iter()
+            .map(|e| {
+                if !has_diffs {
+                    return format!("{:?} {:?}", e.configs, e.output);
+                }
+
+                let Some(count) = e.diff_count else {
+                    return format!("{:?} reference", e.configs);
+                };
+
+                let mut entry = format!("{:?} {count} values differ", e.configs);
+                for diff in &e.diffs {
+                    let _ = write!(
+                        entry,
+                        "\n  {} {}: {} vs {}",
+                        diff.binding, diff.path, diff.value, diff.reference
+                    );
+                }
+                if count > e.diffs.len() {
+                    let _ = write!(entry, "\n  ... {} more", count - e.diffs.len());
+                }
+                entry
+            })
+            .collect::<Vec<_>>();
+
+        std::fs::write(out.join("consensus.json"), consensus.join("\n"))?;
+    }
+
+    Ok(out)
 }
 
 pub fn run(config: Config, options: Options) -> eyre::Result<()> {
@@ -239,7 +373,11 @@ pub fn run(config: Config, options: Options) -> eyre::Result<()> {
                         ui.state.saved_mismatches += 1;
                     }
                 }
-                // WorkerResultKind::Timeout => ui.state.timeouts += 1,
+                WorkerResultKind::KnownMismatch => {
+                    ui.state.mismatches += 1;
+                    ui.state.known_mismatches += 1;
+                }
+                WorkerResultKind::Timeout => ui.state.timeouts += 1,
                 WorkerResultKind::ReconditionFailure | WorkerResultKind::ExecutionFailure => {
                     ui.state.failures += 1
                 }
@@ -292,7 +430,9 @@ enum WorkerResultKind {
     Success,
     Crash,
     Mismatch,
-    // Timeout,
+    /// A mismatch that is explained by the baseline.
+    KnownMismatch,
+    Timeout,
     ReconditionFailure,
     ExecutionFailure,
 }
@@ -303,17 +443,61 @@ fn worker(
     targets: &[Target],
     on_message: &mut dyn FnMut(WorkerMessage),
 ) -> eyre::Result<()> {
+    let mut signatures = HashSet::new();
+    if options.dedupe {
+        saved_signatures(&options.output, &mut signatures)?;
+    }
+
+    let baseline = match &options.baseline {
+        Some(path) => Baseline::load(path)?,
+        None => Baseline::default(),
+    };
+
     loop {
         let mut logger = |line| on_message(WorkerMessage::Log(line));
-        let result = worker_iteration(&config, &options, targets, &mut logger)?;
+        let result = worker_iteration(
+            &config,
+            &options,
+            targets,
+            &baseline,
+            &mut signatures,
+            &mut logger,
+        )?;
         on_message(WorkerMessage::Result(result))
     }
 }
 
+/// Collects the keys of the crash signatures that were saved in `dir` by earlier runs.
+fn saved_signatures(dir: &Path, keys: &mut HashSet<String>) -> io::Result<()> {
+    // The output directory doesn't exist until the first test case is saved
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            saved_signatures(&path, keys)?;
+        } else if path.ends_with("signature.txt") {
+            let signature = std::fs::read_to_string(&path)?;
+            keys.extend(
+                signature
+                    .lines()
+                    .find_map(|line| line.strip_prefix("key: "))
+                    .map(str::to_owned),
+            );
+        }
+    }
+
+    Ok(())
+}
+
 fn worker_iteration(
     config: &Config,
     options: &Options,
     targets: &[Target],
+    baseline: &Baseline,
+    signatures: &mut HashSet<String>,
     logger: &mut dyn FnMut(String),
 ) -> eyre::Result<WorkerResult> {
     let shader = gen_shader(options)?;
@@ -334,13 +518,21 @@ fn worker_iteration(
     };
 
     let mut result = ExecutionResult::Success(None);
+    let mut details = vec![];
     let mut buffers_to_configs: HashMap<Vec<u8>, Vec<String>> = HashMap::new();
     for target in targets {
-        let exec_result =
-            harness_runner::exec_shader(target, &reconditioned, metadata, &mut *logger);
+        let exec_result = harness_runner::exec_shader_with_details(
+            target,
+            &reconditioned,
+            metadata,
+            &mut *logger,
+        );
 
         result = match exec_result {
-            Ok(result) => result,
+            Ok((result, target_details)) => {
+                details.push(target_details);
+                result
+            }
             Err(e) => {
                 if options.save_failures {
                     save_shader(
@@ -350,6 +542,7 @@ fn worker_iteration(
                         metadata,
                         Some(&format!("{e:#?}")),
                         None,
+                        &details,
                     )?;
                 }
                 return Ok(WorkerResult {
@@ -360,7 +553,7 @@ fn worker_iteration(
         };
 
         if let ExecutionResult::Success(ref e) = result {
-            // if not timeout/empty result
+            // if not empty result
             if let Some(entry) = e.as_ref() {
                 buffers_to_configs
                     .entry(entry.output.clone())
@@ -381,6 +574,8 @@ fn worker_iteration(
                 .map(|(buf, configs)| ConsensusEntry {
                     output: buf.clone(),
                     configs: configs.clone(),
+                    diffs: vec![],
+                    diff_count: None,
                 })
                 .collect();
             result = ExecutionResult::Mismatch(consensus_vec);
@@ -394,32 +589,67 @@ fn worker_iteration(
         }
     }
 
+    // Known bugs are reported, but saving them again would bury any new ones
+    if let ExecutionResult::Mismatch(consensus) = &result {
+        if let Some(issues) = baseline.explain(consensus, shader) {
+            let issues = issues.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+            logger(format!("known mismatch: {}", issues.join(", ")));
+            return Ok(WorkerResult {
+                kind: WorkerResultKind::KnownMismatch,
+                saved: false,
+            });
+        }
+    }
+
     let result_kind = match result {
         ExecutionResult::Success(_) => WorkerResultKind::Success,
-        ExecutionResult::Crash(_) => WorkerResultKind::Crash,
+        ExecutionResult::Crash(..) => WorkerResultKind::Crash,
         ExecutionResult::Mismatch(_) => WorkerResultKind::Mismatch,
-        // ExecutionResult::Timeout => WorkerResultKind::Timeout,
+        ExecutionResult::Timeout(_) => WorkerResultKind::Timeout,
     };
 
     let mut output = None;
-    if let ExecutionResult::Crash(out) = &result {
+    if let ExecutionResult::Crash(out, _) = &result {
         output = Some(out.as_str());
     }
 
-    let should_save = result.should_save(
+    let mut should_save = result.should_save(
         &options.strategy,
         options.ignore.iter().chain(&config.fuzzer.ignore),
     );
 
+    // Crashes with the same signature are most likely caused by the same bug
+    if let ExecutionResult::Crash(_, signature) = &result {
+        if should_save && options.dedupe && !signatures.insert(signature.key()) {
+            logger(format!("skipping duplicate crash {}", signature.key()));
+            should_save = false;
+        }
+    }
+
     if should_save {
-        save_shader(
+        let out = save_shader(
             &options.output,
             shader,
             &reconditioned,
             metadata,
             output,
             Some(result.clone()),
+            &details,
         )?;
+
+        if options.trace {
+            let trace_dir = out.join("trace");
+            for target in targets {
+                // The trace directory would be on the server for remote targets
+                if let Harness::Local(_) = target.harness {
+                    let result =
+                        harness_runner::trace_shader(target, &reconditioned, metadata, &trace_dir);
+                    if let Err(e) = result {
+                        eprintln!("failed to record trace: {e:#}");
+                    }
+                }
+            }
+        }
     }
 
     Ok(WorkerResult {
@@ -442,6 +672,7 @@ struct UiState {
     saved_crashes: usize,
     mismatches: usize,
     saved_mismatches: usize,
+    known_mismatches: usize,
     failures: usize,
 }
 
@@ -466,6 +697,7 @@ impl<B: Backend> Ui<B> {
             let saved_crashes = self.state.saved_crashes;
             let mismatches = self.state.mismatches;
             let saved_mismatches = self.state.saved_mismatches;
+            let known_mismatches = self.state.known_mismatches;
             let timeouts = self.state.timeouts;
             let failures = self.state.failures;
 
@@ -477,6 +709,7 @@ impl<B: Backend> Ui<B> {
                 Spans::from(format!("  saved:    {saved_crashes} ({:.2}%)", pc(saved_crashes, crashes))),
                 Spans::from(format!("mismatches: {mismatches} ({:.2}%)", pc(mismatches, count))),
                 Spans::from(format!("  saved:    {saved_mismatches} ({:.2}%)", pc(saved_mismatches, mismatches))),
+                Spans::from(format!("  known:    {known_mismatches} ({:.2}%)", pc(known_mismatches, mismatches))),
                 Spans::from(format!("timeouts:   {timeouts} ({:.2}%)", pc(timeouts, count))),
                 Spans::from(format!("failures:   {failures} ({:.2}%)", pc(failures, count))),
             ];
@@ -1,11 +1,12 @@
 use crate::config::Config;
+use crate::crash::CrashSignature;
 use bincode::{Decode, Encode};
 use eyre::eyre;
 use harness_types::ConfigId;
 use serde::Deserialize;
 use std::fmt::{Display, Write as _};
 use std::io::{self, BufRead, BufReader, BufWriter, Write as _};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
 use std::str::FromStr;
 use std::thread;
@@ -15,31 +16,46 @@ use tap::Tap;
 pub struct ConsensusEntry {
     pub(crate) output: Vec<u8>,
     pub(crate) configs: Vec<String>,
+    /// The values in which the output differs from the most common output of the harness, which
+    /// are only known when the entries come from the same harness.
+    #[serde(default)]
+    pub(crate) diffs: Vec<ConsensusDiff>,
+    /// The number of values that differ, of which only the first few are in `diffs`.
+    #[serde(default)]
+    pub(crate) diff_count: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
+pub struct ConsensusDiff {
+    pub(crate) binding: String,
+    pub(crate) path: String,
+    pub(crate) value: String,
+    pub(crate) reference: String,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ExecutionResult {
-    // Option, because there is no consensus if all configs timed out
+    // Option, because there is no consensus if no configs were executed
     Success(Option<ConsensusEntry>),
-    Crash(String),
+    // The output of the harness, along with the signature of the crash that is extracted from it
+    Crash(String, CrashSignature),
     Mismatch(Vec<ConsensusEntry>),
-    // TODO: Detect timeouts from running harness
-    // Might not actually be necessary since it's probably fine to treat them as successful runs
-    // Timeout,
+    // The configs that timed out, if the outputs of all other configs matched
+    Timeout(Vec<ConfigId>),
 }
 
 impl Display for ExecutionResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ExecutionResult::Success(_) => write!(f, "success"),
-            ExecutionResult::Crash(_) => write!(f, "crash"),
+            ExecutionResult::Crash(..) => write!(f, "crash"),
             ExecutionResult::Mismatch(_) => write!(f, "mismatch"),
-            // ExecutionResult::Timeout => write!(f, "timeout"),
+            ExecutionResult::Timeout(_) => write!(f, "timeout"),
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Harness {
     Local(PathBuf),
     Remote(String),
@@ -89,7 +105,7 @@ impl std::fmt::Display for TargetPath {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Target {
     pub harness: Harness,
     pub configs: Vec<ConfigId>,
@@ -155,15 +171,46 @@ pub fn exec_shader(
     metadata: &str,
     mut logger: impl FnMut(String),
 ) -> eyre::Result<ExecutionResult> {
-    exec_shader_impl(target, shader, metadata, &mut logger)
+    exec_shader_impl(target, shader, metadata, None, &mut logger).map(|(result, _)| result)
+}
+
+/// What the harness reported about an execution besides its result, as JSON.
+#[derive(Default)]
+pub struct ExecutionDetails {
+    /// The machine and drivers that the shader was executed with.
+    pub environment: Option<String>,
+    /// A `buffer_check::ComparisonReport` of how the outputs of the configs compared.
+    pub report: Option<String>,
+}
+
+/// Executes the shader, and also returns the details that the harness reported about it.
+pub fn exec_shader_with_details(
+    target: &Target,
+    shader: &str,
+    metadata: &str,
+    mut logger: impl FnMut(String),
+) -> eyre::Result<(ExecutionResult, ExecutionDetails)> {
+    exec_shader_impl(target, shader, metadata, None, &mut logger)
+}
+
+/// Executes the shader again while recording an API trace of each wgpu config into `trace_dir`.
+pub fn trace_shader(
+    target: &Target,
+    shader: &str,
+    metadata: &str,
+    trace_dir: &Path,
+) -> eyre::Result<ExecutionResult> {
+    exec_shader_impl(target, shader, metadata, Some(trace_dir), &mut |_| {})
+        .map(|(result, _)| result)
 }
 
 fn exec_shader_impl(
     target: &Target,
     shader: &str,
     metadata: &str,
+    trace_dir: Option<&Path>,
     logger: &mut dyn FnMut(String),
-) -> eyre::Result<ExecutionResult> {
+) -> eyre::Result<(ExecutionResult, ExecutionDetails)> {
     let harness = target.harness.clone();
     let configs = target.configs.clone();
     let mut cmd = match harness {
@@ -179,6 +226,10 @@ fn exec_shader_impl(
         cmd.args(["-c", &config.to_string()]);
     }
 
+    if let Some(trace_dir) = trace_dir {
+        cmd.arg("--trace-dir").arg(trace_dir);
+    }
+
     cmd.args(["--print-consensus"]);
 
     let mut harness = cmd
@@ -196,6 +247,8 @@ fn exec_shader_impl(
 
     let mut output = String::new();
     let mut consensus_list: Vec<ConsensusEntry> = Vec::new();
+    let mut timeouts: Vec<ConfigId> = Vec::new();
+    let mut details = ExecutionDetails::default();
 
     let status = wait_for_child_with_line_logger(harness, &mut |_, line| {
         if let Some(json_content) = line.strip_prefix("output-consensus: ") {
@@ -209,6 +262,25 @@ fn exec_shader_impl(
             }
             return;
         }
+        if let Some(json_content) = line.strip_prefix("output-environment: ") {
+            details.environment = Some(json_content.to_owned());
+            return;
+        }
+        if let Some(json_content) = line.strip_prefix("output-report: ") {
+            details.report = Some(json_content.to_owned());
+            return;
+        }
+        if let Some(json_content) = line.strip_prefix("output-timeouts: ") {
+            match serde_json::from_str::<Vec<String>>(json_content) {
+                Ok(parsed) => {
+                    timeouts = parsed.iter().filter_map(|it| it.parse().ok()).collect();
+                }
+                Err(e) => {
+                    writeln!(output, "!! Timeouts Parse Error: {e}").unwrap();
+                }
+            }
+            return;
+        }
         writeln!(output, "{line}").unwrap();
         logger(line);
     })?;
@@ -217,11 +289,20 @@ fn exec_shader_impl(
         None => return Err(eyre!("failed to get harness exit code")),
         Some(0) => ExecutionResult::Success(consensus_list.first().cloned()),
         Some(1) => ExecutionResult::Mismatch(consensus_list),
-        Some(101) => ExecutionResult::Crash(output),
+        Some(4) if timeouts.is_empty() => {
+            return Err(eyre!(
+                "harness reported a timeout without the configs that timed out"
+            ))
+        }
+        Some(4) => ExecutionResult::Timeout(timeouts),
+        Some(101) => {
+            let signature = CrashSignature::from_output(&output);
+            ExecutionResult::Crash(output, signature)
+        }
         Some(code) => return Err(eyre!("harness exited with unrecognised code `{code}`")),
     };
 
-    Ok(result)
+    Ok((result, details))
 }
 
 #[derive(PartialEq, Eq)]
@@ -1,6 +1,9 @@
+mod baseline;
 #[cfg(all(target_family = "unix", feature = "reducer"))]
 mod compiler;
 mod config;
+mod crash;
+mod emi;
 mod fmt;
 mod fuzzer;
 mod harness_runner;
@@ -14,13 +17,15 @@ mod validator;
 
 use std::fs;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use clap::Parser;
 use color_eyre::Help;
 use eyre::{eyre, Context};
 use harness_frontend::{read_shader_from_path, ExecutionError, ExecutionEvent};
-use harness_types::ConfigId;
+use harness_server_types::OutputMode;
+use harness_types::{AdapterFilter, BackendOptions, ConfigId, RetryPolicy, Variant};
 use reflection_types::PipelineDescription;
 
 #[derive(Parser)]
@@ -45,6 +50,8 @@ enum Cmd {
     /// Format a shader.
     Fmt(fmt::Options),
     Fuzz(fuzzer::Options),
+    /// Check that equivalent variants of a shader produce the same output as the original.
+    Emi(emi::Options),
     /// Reduce a shader.
     #[cfg(all(target_family = "unix", feature = "reducer"))]
     Reduce(reducer::Options),
@@ -67,10 +74,33 @@ enum Cmd {
     },
 }
 
+#[derive(Parser)]
+struct RemoteRunOptions {
+    #[clap(flatten)]
+    run: harness_frontend::cli::RunOptions,
+
+    /// Only receive a digest of each output from the server, and fetch one output for each
+    /// distinct digest at the end of the run.
+    ///
+    /// This saves sending the same output for every config when shaders have large output buffers,
+    /// since configs usually agree.
+    #[clap(long, action)]
+    digests: bool,
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Parser)]
 enum RemoteCmd {
     List,
-    Run(harness_frontend::cli::RunOptions),
+    Run(RemoteRunOptions),
+    /// Show the version of the server, how busy it is, and the configs that it can execute.
+    Status {
+        /// Format of the output.
+        ///
+        /// With `json`, the status is printed as a single JSON object.
+        #[clap(long, value_enum, default_value = "human")]
+        format: harness_frontend::OutputFormat,
+    },
 }
 
 fn main() -> eyre::Result<()> {
@@ -110,6 +140,7 @@ fn main() -> eyre::Result<()> {
         Cmd::Recondition(options) => reconditioner::cli::run(options),
         Cmd::Fmt(options) => fmt::run(options),
         Cmd::Fuzz(options) => fuzzer::run(config, options),
+        Cmd::Emi(options) => emi::run(config, options),
         #[cfg(all(target_family = "unix", feature = "reducer"))]
         Cmd::Reduce(options) => reducer::run(config, options),
         #[cfg(all(target_family = "unix", feature = "reducer"))]
@@ -134,8 +165,18 @@ fn main() -> eyre::Result<()> {
                     harness_frontend::Printer::new().print_all_configs(res.configs)?;
                     Ok(())
                 }
+                RemoteCmd::Status { format } => {
+                    let res = remote::status(address)?;
+                    remote::print_status(res, format)?;
+                    Ok(())
+                }
                 RemoteCmd::Run(options) => {
-                    struct Executor<'a>(&'a str);
+                    struct Executor<'a> {
+                        address: &'a str,
+                        outputs: OutputMode,
+                        /// The run that is in progress on the server, if any.
+                        job: Arc<Mutex<Option<u64>>>,
+                    }
 
                     impl harness_frontend::Executor for Executor<'_> {
                         fn execute(
@@ -143,23 +184,55 @@ fn main() -> eyre::Result<()> {
                             shader: &str,
                             pipeline_desc: &PipelineDescription,
                             configs: &[ConfigId],
+                            oracle: bool,
+                            variants: &[Variant],
+                            filter: &AdapterFilter,
+                            options: &BackendOptions,
                             timeout: Option<Duration>,
+                            retry: RetryPolicy,
                             _parallelism: Option<usize>,
                             on_event: &mut (dyn FnMut(ExecutionEvent) -> Result<(), ExecutionError>
                                       + Send),
                         ) -> Result<(), ExecutionError> {
-                            remote::execute(
-                                self.0,
+                            let result = remote::execute(
+                                self.address,
                                 shader.to_owned(),
                                 pipeline_desc.clone(),
                                 configs.to_owned(),
+                                oracle,
+                                variants.to_vec(),
+                                filter.clone(),
+                                options.clone(),
                                 timeout,
+                                retry,
+                                self.outputs,
+                                &mut |job| *self.job.lock().unwrap() = Some(job),
                                 on_event,
-                            )
+                            );
+
+                            *self.job.lock().unwrap() = None;
+                            result
                         }
                     }
 
-                    harness_frontend::cli::run(options, &Executor(address))
+                    let outputs = if options.digests {
+                        OutputMode::Digests {
+                            canonicalize_floats: options.run.canonicalize_floats,
+                        }
+                    } else {
+                        OutputMode::Buffers
+                    };
+
+                    let executor = Executor {
+                        address,
+                        outputs,
+                        job: Arc::new(Mutex::new(None)),
+                    };
+
+                    #[cfg(unix)]
+                    remote::cancel_on_interrupt(address.to_owned(), executor.job.clone())?;
+
+                    harness_frontend::cli::run(options.run, &executor)
                 }
             }
         }
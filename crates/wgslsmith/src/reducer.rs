@@ -1,7 +1,10 @@
-use std::ffi::OsStr;
+mod inputs;
+mod native;
+
+use std::ffi::{OsStr, OsString};
 use std::fs::Permissions;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::time::Instant;
 use std::{env, thread};
 
@@ -17,10 +20,11 @@ use crate::compiler::{Backend, Compiler};
 use crate::config::Config;
 use crate::harness_runner::TargetPath;
 
-#[derive(ValueEnum, Clone)]
+#[derive(ValueEnum, Clone, Debug)]
 pub enum ReductionKind {
     Crash,
     Mismatch,
+    Timeout,
 }
 
 #[derive(Parser)]
@@ -47,9 +51,10 @@ pub struct Options {
     #[clap(short, long, action)]
     server: Option<String>,
 
-    /// Config to use for reducing a crash.
+    /// Config to use for reducing a crash or timeout.
     ///
-    /// This is only valid if we're reducing a crash.
+    /// This is only valid if we're reducing a crash or timeout. For timeouts, the shader is only
+    /// interesting if this config times out.
     #[clap(long, action, conflicts_with("compiler"))]
     config: Option<String>,
 
@@ -66,8 +71,8 @@ pub struct Options {
 
     /// Regex to match crash output against.
     ///
-    /// This is only valid if we're reducing a crash.
-    #[clap(long, action, required_if_eq("kind", "crash"))]
+    /// This is only valid if we're reducing a crash. Either this or `--signature` is required.
+    #[clap(long, action)]
     regex: Option<Regex>,
 
     /// Inverse regex to match crash output against.
@@ -76,6 +81,14 @@ pub struct Options {
     #[clap(long, action)]
     inverse_regex: Option<Regex>,
 
+    /// Key of the crash signature to match, as saved in `signature.txt` by the fuzzer.
+    ///
+    /// This is only valid if we're reducing a crash with a config. Unlike a regex, the signature
+    /// ignores parts of the message that vary between shaders, and also matches the kind of
+    /// failure, the config and the stack trace.
+    #[clap(long, action, requires("config"))]
+    signature: Option<String>,
+
     /// Don't recondition shader before executing.
     ///
     /// This is only valid if we're reducing a crash.
@@ -90,11 +103,24 @@ pub struct Options {
     reducer: Option<Reducer>,
 
     /// This passed to the underlying reducer using the appropriate flag, to set how many threads it
-    /// should use.
+    /// should use. For the native reducer, this is the number of candidates that are tested at once.
     ///
     /// Can also be set in `wgslsmith.toml`, as `reducer.parallelism`.
     #[clap(long, action)]
     parallelism: Option<u32>,
+
+    /// Don't cache whether candidates are interesting.
+    ///
+    /// By default, the outcome of testing each candidate is cached in the output directory, so that
+    /// a candidate which the reducer produces more than once is only executed once.
+    #[clap(long, action)]
+    no_cache: bool,
+
+    /// Reduce the input data as well, once the shader has been reduced.
+    ///
+    /// The reduced input data is saved as `inputs.json` in the output directory.
+    #[clap(long, action)]
+    reduce_inputs: bool,
 }
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -103,6 +129,8 @@ pub enum Reducer {
     Cvise,
     Perses,
     Picire,
+    /// Built-in reducer which edits the AST of the shader, so no external tools are needed.
+    Native,
 }
 
 impl Reducer {
@@ -165,6 +193,8 @@ impl Reducer {
                     .arg("-j")
                     .arg(threads.to_string());
             })),
+            // The interestingness test is run directly, on the shader in the current directory
+            Reducer::Native => Ok(Command::new(Path::new(".").join(test.as_ref()))),
         }
     }
 
@@ -205,6 +235,14 @@ pub fn run(config: Config, options: Options) -> eyre::Result<()> {
 }
 
 fn thread_main(config: &Config, options: Options) -> eyre::Result<()> {
+    if let ReductionKind::Crash = options.kind {
+        if options.regex.is_none() && options.signature.is_none() {
+            return Err(eyre!(
+                "either --regex or --signature is required to reduce a crash"
+            ));
+        }
+    }
+
     let shader_path = Path::new(&options.shader);
     if !shader_path.exists() {
         return Err(eyre!("shader at {shader_path:?} does not exist"));
@@ -275,6 +313,10 @@ fn thread_main(config: &Config, options: Options) -> eyre::Result<()> {
         .as_deref()
         .or_else(|| config.default_remote());
 
+    // The test is run in a different directory for each candidate, so the cache needs an absolute
+    // path
+    let cache_dir = out_dir.canonicalize()?.join(".cache");
+
     let parallelism = options
         .parallelism
         .or(config.reducer.parallelism)
@@ -285,12 +327,16 @@ fn thread_main(config: &Config, options: Options) -> eyre::Result<()> {
         .tap_mut(|cmd| {
             cmd.current_dir(&out_dir)
                 .env("WGSLREDUCE_SHADER_NAME", shader_path.file_name().unwrap())
-                .env("WGSLREDUCE_METADATA_PATH", metadata_path);
+                .env("WGSLREDUCE_METADATA_PATH", &metadata_path);
 
             if let Some(server) = harness_server {
                 cmd.env("WGSLREDUCE_SERVER", server);
             }
 
+            if !options.no_cache {
+                cmd.env("WGSLREDUCE_CACHE", &cache_dir);
+            }
+
             if let Some(tmpdir) = &config.reducer.tmpdir {
                 cmd.env("TMPDIR", tmpdir);
             }
@@ -309,8 +355,15 @@ fn thread_main(config: &Config, options: Options) -> eyre::Result<()> {
 
     match options.kind {
         ReductionKind::Crash => {
-            cmd.env("WGSLREDUCE_KIND", "crash")
-                .env("WGSLREDUCE_REGEX", options.regex.unwrap().as_str());
+            cmd.env("WGSLREDUCE_KIND", "crash");
+
+            if let Some(regex) = options.regex {
+                cmd.env("WGSLREDUCE_REGEX", regex.as_str());
+            }
+
+            if let Some(signature) = options.signature {
+                cmd.env("WGSLREDUCE_SIGNATURE", signature);
+            }
 
             if let Some(inverse_regex) = options.inverse_regex {
                 cmd.env("WGSLREDUCE_INVERSE_REGEX", inverse_regex.as_str());
@@ -332,20 +385,55 @@ fn thread_main(config: &Config, options: Options) -> eyre::Result<()> {
         ReductionKind::Mismatch => {
             cmd.env("WGSLREDUCE_KIND", "mismatch");
         }
+        ReductionKind::Timeout => {
+            cmd.env("WGSLREDUCE_KIND", "timeout");
+
+            if let Some(config) = options.config {
+                cmd.env("WGSLREDUCE_CONFIG", config);
+            }
+        }
     }
 
     let start_time = Instant::now();
+    let result_path = out_dir.join(shader_name);
 
-    if !cmd.status()?.success() {
+    if let Reducer::Native = reducer {
+        let jobs = Jobs::new(&out_dir, &cmd, parallelism)?;
+
+        let source = std::fs::read_to_string(&result_path)?;
+        let reduced = native::reduce(&source, jobs.dirs.len(), |candidate, job| {
+            jobs.test(job, shader_name, candidate, None)
+        })?;
+
+        std::fs::write(&result_path, reduced)?;
+        jobs.remove()?;
+    } else if !cmd.status()?.success() {
         return Err(eyre!("reducer process did not exit successfully"));
     }
 
+    if options.reduce_inputs {
+        let jobs = Jobs::new(&out_dir, &cmd, parallelism)?;
+
+        let source = std::fs::read_to_string(&result_path)?;
+        let metadata = std::fs::read_to_string(&metadata_path)?;
+        let reduced = inputs::reduce(&source, &metadata, jobs.dirs.len(), |candidate, job| {
+            jobs.test(job, shader_name, &source, Some(candidate))
+        })?;
+
+        std::fs::write(out_dir.join("inputs.json"), reduced)?;
+        jobs.remove()?;
+    }
+
+    if cache_dir.exists() {
+        std::fs::remove_dir_all(&cache_dir)?;
+    }
+
     let end_time = Instant::now();
     let duration = end_time - start_time;
 
     println!("> reducer completed in {}s", duration.as_secs_f64());
 
-    let result_path = out_dir.join(shader_name).to_str().unwrap().to_owned();
+    let result_path = result_path.to_str().unwrap().to_owned();
     // let reconditioned_path = out_dir
     //     .join("reconditioned.wgsl")
     //     .to_str()
@@ -365,6 +453,75 @@ fn thread_main(config: &Config, options: Options) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Directories in which wgslsmith runs the interestingness test on candidates that it produces
+/// itself, one for each candidate that is tested at once so that concurrent tests don't overwrite
+/// each other's files.
+struct Jobs {
+    test_path: PathBuf,
+    envs: Vec<(OsString, OsString)>,
+    dirs: Vec<PathBuf>,
+}
+
+impl Jobs {
+    fn new(out_dir: &Path, cmd: &Command, count: u32) -> eyre::Result<Jobs> {
+        let out_dir = out_dir.canonicalize()?;
+        let dirs = (0..count.max(1))
+            .map(|i| {
+                let dir = out_dir.join(format!(".job-{i}"));
+                std::fs::create_dir(&dir)?;
+                Ok(dir)
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        let envs = cmd
+            .get_envs()
+            .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+            .collect();
+
+        Ok(Jobs {
+            test_path: out_dir.join("test.sh"),
+            envs,
+            dirs,
+        })
+    }
+
+    /// Runs the test on a shader in the directory of a job, with the input data if it is given
+    /// instead of the original input data.
+    fn test(
+        &self,
+        job: usize,
+        shader_name: &OsStr,
+        shader: &str,
+        inputs: Option<&str>,
+    ) -> eyre::Result<bool> {
+        let dir = &self.dirs[job];
+        std::fs::write(dir.join(shader_name), shader)?;
+
+        let mut cmd = Command::new(&self.test_path);
+        cmd.arg(shader_name)
+            .current_dir(dir)
+            .envs(self.envs.iter().cloned())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        if let Some(inputs) = inputs {
+            let inputs_path = dir.join("inputs.json");
+            std::fs::write(&inputs_path, inputs)?;
+            cmd.env("WGSLREDUCE_METADATA_PATH", inputs_path);
+        }
+
+        Ok(cmd.status()?.success())
+    }
+
+    fn remove(self) -> eyre::Result<()> {
+        for dir in self.dirs {
+            std::fs::remove_dir_all(dir)?;
+        }
+
+        Ok(())
+    }
+}
+
 fn setup_out_dir(out_dir: &Path, shader: &Path, reducer: &Reducer) -> eyre::Result<()> {
     // Create output dir
     if !out_dir.exists() {
@@ -0,0 +1,331 @@
+//! Reduction of the input data of a shader, so that a reduced shader isn't accompanied by buffers
+//! full of random bytes that have nothing to do with the bug.
+//!
+//! The input data is reduced by applying the following passes in order:
+//!
+//! - Removing the contents of buffers, which are then zero-initialized. This also removes the
+//!   contents of buffers that the shader no longer has.
+//! - Zeroing scalars, using delta debugging as in the [native](super::native) reducer.
+//! - Shrinking the remaining scalars towards zero.
+//! - Zeroing padding, and removing bytes past the end of buffers and trailing zeros.
+//!
+//! Only the contents of buffers are reduced. Other fields (e.g. texture sizes and the dispatch size)
+//! are kept as they are.
+
+use std::collections::BTreeMap;
+
+use ast::Lit;
+use common::{ScalarType, Type};
+use eyre::eyre;
+use serde_json::{Map, Value};
+
+use super::native::{first_interesting, smaller_lits};
+
+#[derive(Clone, PartialEq)]
+struct Inputs {
+    /// Contents of the buffers and textures, keyed by `group:binding`.
+    buffers: BTreeMap<String, Vec<u8>>,
+    /// The other fields of the input data, which aren't reduced.
+    other: Map<String, Value>,
+}
+
+impl Inputs {
+    fn parse(inputs: &str) -> eyre::Result<Inputs> {
+        let Value::Object(fields) = serde_json::from_str(inputs)? else {
+            return Err(eyre!("input data must be a JSON object"));
+        };
+
+        let mut buffers = BTreeMap::new();
+        let mut other = Map::new();
+
+        for (key, value) in fields {
+            match serde_json::from_value::<Vec<u8>>(value.clone()) {
+                Ok(buffer) if key.contains(':') => {
+                    buffers.insert(key, buffer);
+                }
+                _ => {
+                    other.insert(key, value);
+                }
+            }
+        }
+
+        Ok(Inputs { buffers, other })
+    }
+
+    fn to_json(&self) -> String {
+        let mut fields = self.other.clone();
+        for (key, buffer) in &self.buffers {
+            fields.insert(key.clone(), buffer.as_slice().into());
+        }
+
+        Value::Object(fields).to_string()
+    }
+
+    fn scalar(&self, scalar: &Scalar) -> u32 {
+        let buffer = &self.buffers[&scalar.key];
+        u32::from_le_bytes(buffer[scalar.offset..scalar.offset + 4].try_into().unwrap())
+    }
+
+    fn set_scalar(&mut self, scalar: &Scalar, value: u32) {
+        let buffer = self.buffers.get_mut(&scalar.key).unwrap();
+        buffer[scalar.offset..scalar.offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// A scalar in a buffer, including a component of a vector or matrix.
+#[derive(Clone)]
+struct Scalar {
+    key: String,
+    offset: usize,
+    scalar_type: ScalarType,
+}
+
+/// Reduces the input data of a shader while `is_interesting` holds for it, and returns the reduced
+/// input data. As with [`super::native::reduce`], up to `jobs` candidates are tested at once.
+pub fn reduce(
+    shader: &str,
+    inputs: &str,
+    jobs: usize,
+    is_interesting: impl Fn(&str, usize) -> eyre::Result<bool> + Sync,
+) -> eyre::Result<String> {
+    let current = Inputs::parse(inputs)?;
+
+    if !is_interesting(&current.to_json(), 0)? {
+        return Err(eyre!("shader is not interesting with the input data"));
+    }
+
+    let mut reducer = Reducer {
+        current,
+        types: buffer_types(shader),
+        jobs: jobs.max(1),
+        is_interesting,
+    };
+
+    reducer.edit_chunks(
+        |inputs, _| inputs.buffers.keys().cloned().collect(),
+        |inputs, keys| {
+            for key in keys {
+                inputs.buffers.remove(key);
+            }
+        },
+    )?;
+
+    reducer.edit_chunks(
+        |inputs, types| {
+            scalars(inputs, types)
+                .into_iter()
+                .filter(|it| inputs.scalar(it) != 0)
+                .collect()
+        },
+        |inputs, scalars| {
+            for scalar in scalars {
+                inputs.set_scalar(scalar, 0);
+            }
+        },
+    )?;
+
+    reducer.shrink_scalars()?;
+    reducer.trim()?;
+
+    let reduced = reducer.current.to_json();
+
+    println!("> inputs: {} bytes", reduced.len());
+
+    Ok(reduced)
+}
+
+struct Reducer<F> {
+    current: Inputs,
+    types: BTreeMap<String, Type>,
+    jobs: usize,
+    is_interesting: F,
+}
+
+impl<F: Fn(&str, usize) -> eyre::Result<bool> + Sync> Reducer<F> {
+    /// Tests the candidates concurrently, and keeps the first one that is still interesting.
+    fn try_candidates(&mut self, mut candidates: Vec<Inputs>) -> eyre::Result<Option<usize>> {
+        let json = candidates
+            .iter()
+            .map(|it| (*it != self.current).then(|| it.to_json()))
+            .collect::<Vec<_>>();
+
+        let Some(index) = first_interesting(&json, &self.is_interesting)? else {
+            return Ok(None);
+        };
+
+        self.current = candidates.swap_remove(index);
+        Ok(Some(index))
+    }
+
+    /// Applies `edit` to chunks of the items returned by `items`, halving the size of the chunks
+    /// until single items are tried. Items that were edited must no longer be returned by `items`.
+    fn edit_chunks<T>(
+        &mut self,
+        items: impl Fn(&Inputs, &BTreeMap<String, Type>) -> Vec<T>,
+        edit: impl Fn(&mut Inputs, &[T]),
+    ) -> eyre::Result<()> {
+        let mut current_items = items(&self.current, &self.types);
+        let mut chunk = current_items.len();
+
+        while chunk > 0 {
+            let mut start = 0;
+            while start < current_items.len() {
+                let len = current_items.len();
+                let candidates = (start..len)
+                    .step_by(chunk)
+                    .take(self.jobs)
+                    .map(|it| {
+                        let mut candidate = self.current.clone();
+                        edit(&mut candidate, &current_items[it..(it + chunk).min(len)]);
+                        candidate
+                    })
+                    .collect::<Vec<_>>();
+
+                let count = candidates.len();
+                match self.try_candidates(candidates)? {
+                    Some(index) => {
+                        start += index * chunk;
+                        current_items = items(&self.current, &self.types);
+                    }
+                    None => start += count * chunk,
+                }
+            }
+
+            chunk /= 2;
+        }
+
+        Ok(())
+    }
+
+    fn shrink_scalars(&mut self) -> eyre::Result<()> {
+        let scalars = scalars(&self.current, &self.types);
+        let mut index = 0;
+        let mut tried = 0;
+
+        // A scalar that shrank may shrink further, so it is tried again until it doesn't
+        loop {
+            let edits = scalars
+                .iter()
+                .enumerate()
+                .skip(index)
+                .flat_map(|(i, scalar)| {
+                    smaller_values(self.current.scalar(scalar), scalar.scalar_type)
+                        .into_iter()
+                        .map(move |it| (i, it))
+                })
+                .skip(tried)
+                .take(self.jobs)
+                .collect::<Vec<_>>();
+
+            if edits.is_empty() {
+                break;
+            }
+
+            let candidates = edits
+                .iter()
+                .map(|(i, value)| {
+                    let mut candidate = self.current.clone();
+                    candidate.set_scalar(&scalars[*i], *value);
+                    candidate
+                })
+                .collect::<Vec<_>>();
+
+            match self.try_candidates(candidates)? {
+                Some(shrunk) => {
+                    index = edits[shrunk].0;
+                    tried = 0;
+                }
+                None => tried += edits.len(),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn trim(&mut self) -> eyre::Result<()> {
+        let mut candidate = self.current.clone();
+
+        for (key, buffer) in &mut candidate.buffers {
+            if let Some(type_desc) = self.types.get(key) {
+                let mut trimmed = vec![0; type_desc.buffer_size() as usize];
+                for (offset, size) in type_desc.ranges() {
+                    let end = (offset + size).min(buffer.len());
+                    if offset < end {
+                        trimmed[offset..end].copy_from_slice(&buffer[offset..end]);
+                    }
+                }
+                *buffer = trimmed;
+            }
+
+            // Buffers are padded with zeros to their size, so trailing zeros don't change anything
+            let len = buffer
+                .iter()
+                .rposition(|it| *it != 0)
+                .map_or(0, |it| it + 1);
+            buffer.truncate(len);
+        }
+
+        self.try_candidates(vec![candidate])?;
+
+        Ok(())
+    }
+}
+
+/// Returns the types of the resources in the shader whose contents can be reduced, keyed by
+/// `group:binding`.
+fn buffer_types(shader: &str) -> BTreeMap<String, Type> {
+    let module = parser::parse(shader);
+
+    module
+        .vars
+        .iter()
+        .filter_map(|var| {
+            let key = format!("{}:{}", var.group_index()?, var.binding_index()?);
+            let type_desc = Type::try_from(&var.data_type).ok()?;
+            Some((key, type_desc))
+        })
+        .collect()
+}
+
+/// Returns the scalars in the buffers that have a known type, in order.
+fn scalars(inputs: &Inputs, types: &BTreeMap<String, Type>) -> Vec<Scalar> {
+    let mut scalars = vec![];
+
+    for (key, buffer) in &inputs.buffers {
+        let Some(type_desc) = types.get(key) else {
+            continue;
+        };
+
+        for (offset, scalar_type) in type_desc.scalars() {
+            if offset + 4 <= buffer.len() {
+                scalars.push(Scalar {
+                    key: key.clone(),
+                    offset,
+                    scalar_type,
+                });
+            }
+        }
+    }
+
+    scalars
+}
+
+/// Returns the bits of values that are simpler than the scalar with the given bits, in the same way
+/// as literals are shrunk by the native reducer.
+fn smaller_values(bits: u32, scalar_type: ScalarType) -> Vec<u32> {
+    let lit = match scalar_type {
+        ScalarType::I32 => Lit::I32(bits as i32),
+        ScalarType::U32 => Lit::U32(bits),
+        ScalarType::F32 => Lit::F32(f32::from_bits(bits)),
+    };
+
+    smaller_lits(lit)
+        .into_iter()
+        .filter_map(|lit| match lit {
+            Lit::I32(v) => Some(v as u32),
+            Lit::U32(v) => Some(v),
+            Lit::F32(v) => Some(v.to_bits()),
+            _ => None,
+        })
+        .collect()
+}
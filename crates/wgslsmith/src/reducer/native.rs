@@ -0,0 +1,523 @@
+//! A reducer which edits the AST of the shader, so that test cases can be reduced without an
+//! external reducer.
+//!
+//! The shader is reduced by repeatedly applying the following passes until none of them makes the
+//! shader smaller:
+//!
+//! - Deleting functions other than entry points, and global declarations other than resources.
+//! - Removing statements (along with any statements nested in them).
+//! - Replacing scalar and vector expressions with zero values.
+//! - Shrinking literals towards zero.
+//!
+//! The first three passes use delta debugging: they try to remove chunks of consecutive items,
+//! starting with all of them and halving the size of the chunks until single items are tried.
+
+use std::ops::Range;
+
+use ast::types::{DataType, ScalarType};
+use ast::{
+    Else, Expr, ExprNode, FnAttr, ForLoopInit, ForLoopUpdate, IfStatement, Lit, Module, Postfix,
+    Statement, TypeConsExpr, UnOp,
+};
+use eyre::eyre;
+
+/// An edit to a module, which applies to the items of one kind whose indices are in a range. Items
+/// are numbered in the order in which they appear in the shader, including items that are nested
+/// in other items of the same kind.
+#[derive(Clone, Debug)]
+enum Edit {
+    /// Leaves the module as it is, which counts the items in it.
+    None,
+    RemoveDecls(Range<usize>),
+    RemoveStmts(Range<usize>),
+    ReplaceExprs(Range<usize>),
+    ReplaceLit(usize, Lit),
+}
+
+/// The number of items of each kind that an edit visited.
+#[derive(Default)]
+struct Counts {
+    /// Global declarations other than resources and entry points.
+    decls: usize,
+    stmts: usize,
+    /// Expressions that can be replaced with a zero value.
+    exprs: usize,
+    lits: Vec<Lit>,
+}
+
+/// Reduces a shader while `is_interesting` holds for it, and returns the reduced shader.
+///
+/// Up to `jobs` candidates are tested at once, each on a different thread. `is_interesting` is
+/// also passed the index of the job, which is less than `jobs` and is only used by one test at a
+/// time (e.g. so that each job can write its candidate to a different directory). The first of
+/// the candidates that is interesting is kept, so the result doesn't depend on which test finishes
+/// first.
+pub fn reduce(
+    source: &str,
+    jobs: usize,
+    is_interesting: impl Fn(&str, usize) -> eyre::Result<bool> + Sync,
+) -> eyre::Result<String> {
+    // The shader is written out again so that edits are compared against the same formatting
+    let current = write_module(&parser::parse(source));
+
+    if !is_interesting(&current, 0)? {
+        return Err(eyre!("shader is not interesting"));
+    }
+
+    let mut reducer = Reducer {
+        current,
+        jobs: jobs.max(1),
+        is_interesting,
+    };
+
+    let mut round = 1;
+    loop {
+        let mut changed = false;
+        changed |= reducer.remove_chunks(Edit::RemoveDecls, |it| it.decls)?;
+        changed |= reducer.remove_chunks(Edit::RemoveStmts, |it| it.stmts)?;
+        changed |= reducer.remove_chunks(Edit::ReplaceExprs, |it| it.exprs)?;
+        changed |= reducer.shrink_lits()?;
+
+        println!("> round {round}: {} bytes", reducer.current.len());
+
+        if !changed {
+            break;
+        }
+
+        round += 1;
+    }
+
+    Ok(reducer.current)
+}
+
+struct Reducer<F> {
+    current: String,
+    jobs: usize,
+    is_interesting: F,
+}
+
+impl<F: Fn(&str, usize) -> eyre::Result<bool> + Sync> Reducer<F> {
+    fn counts(&self) -> Counts {
+        apply(&mut parser::parse(&self.current), &Edit::None)
+    }
+
+    /// Applies each of the edits to the current shader and tests the results concurrently. The
+    /// result of the first edit that is still interesting is kept, and its index is returned.
+    fn try_edits(&mut self, edits: &[Edit]) -> eyre::Result<Option<usize>> {
+        let mut candidates = edits
+            .iter()
+            .map(|edit| {
+                let mut module = parser::parse(&self.current);
+                apply(&mut module, edit);
+
+                let candidate = write_module(&module);
+                (candidate != self.current && parses(&candidate)).then_some(candidate)
+            })
+            .collect::<Vec<_>>();
+
+        let Some(index) = first_interesting(&candidates, &self.is_interesting)? else {
+            return Ok(None);
+        };
+
+        self.current = candidates[index].take().unwrap();
+        Ok(Some(index))
+    }
+
+    fn remove_chunks(
+        &mut self,
+        edit: fn(Range<usize>) -> Edit,
+        count: fn(&Counts) -> usize,
+    ) -> eyre::Result<bool> {
+        let mut changed = false;
+        let mut len = count(&self.counts());
+        let mut chunk = len;
+
+        while chunk > 0 {
+            let mut start = 0;
+            while start < len {
+                let edits = (start..len)
+                    .step_by(chunk)
+                    .take(self.jobs)
+                    .map(|it| edit(it..(it + chunk).min(len)))
+                    .collect::<Vec<_>>();
+
+                // The chunks before the one that was removed weren't, and the items after it move
+                // up to take its place
+                match self.try_edits(&edits)? {
+                    Some(index) => {
+                        changed = true;
+                        start += index * chunk;
+                        len = count(&self.counts());
+                    }
+                    None => start += edits.len() * chunk,
+                }
+            }
+
+            chunk /= 2;
+        }
+
+        Ok(changed)
+    }
+
+    fn shrink_lits(&mut self) -> eyre::Result<bool> {
+        let mut changed = false;
+        let mut index = 0;
+        let mut tried = 0;
+
+        // Shrinking a literal doesn't change the number of literals, so they keep their indices
+        loop {
+            let edits = self
+                .counts()
+                .lits
+                .iter()
+                .enumerate()
+                .skip(index)
+                .flat_map(|(i, lit)| smaller_lits(*lit).into_iter().map(move |it| (i, it)))
+                .skip(tried)
+                .take(self.jobs)
+                .collect::<Vec<_>>();
+
+            if edits.is_empty() {
+                break;
+            }
+
+            let replacements = edits
+                .iter()
+                .map(|(i, lit)| Edit::ReplaceLit(*i, *lit))
+                .collect::<Vec<_>>();
+
+            match self.try_edits(&replacements)? {
+                // A literal that shrank may shrink further
+                Some(replaced) => {
+                    changed = true;
+                    index = edits[replaced].0;
+                    tried = 0;
+                }
+                None => tried += edits.len(),
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+/// Tests the candidates concurrently, each on a different job, and returns the index of the first
+/// one that is interesting. Candidates that are `None` are skipped.
+pub(super) fn first_interesting(
+    candidates: &[Option<String>],
+    is_interesting: &(impl Fn(&str, usize) -> eyre::Result<bool> + Sync),
+) -> eyre::Result<Option<usize>> {
+    let results = std::thread::scope(|scope| {
+        let tests = candidates
+            .iter()
+            .enumerate()
+            .map(|(job, candidate)| {
+                let candidate = candidate.as_deref()?;
+                Some(scope.spawn(move || is_interesting(candidate, job)))
+            })
+            .collect::<Vec<_>>();
+
+        tests
+            .into_iter()
+            .map(|test| test.map(|it| it.join().unwrap()).unwrap_or(Ok(false)))
+            .collect::<eyre::Result<Vec<_>>>()
+    })?;
+
+    Ok(results.iter().position(|it| *it))
+}
+
+/// Returns literals that are simpler than `lit`, from simplest to least simple.
+pub(super) fn smaller_lits(lit: Lit) -> Vec<Lit> {
+    fn ints(v: i64) -> Vec<i64> {
+        let mut smaller = vec![];
+        for it in [0, 1, v / 2] {
+            if it.abs() < v.abs() && !smaller.contains(&it) {
+                smaller.push(it);
+            }
+        }
+        smaller
+    }
+
+    // Floats aren't halved, since that would take over a hundred steps to reach zero
+    fn floats(v: f64) -> Vec<f64> {
+        let mut smaller = vec![];
+        for it in [0.0, 1.0, v.trunc()] {
+            if it != v && (it.abs() < v.abs() || it == v.trunc()) && !smaller.contains(&it) {
+                smaller.push(it);
+            }
+        }
+        smaller
+    }
+
+    match lit {
+        Lit::Bool(v) => [Lit::Bool(false)].into_iter().filter(|_| v).collect(),
+        Lit::I32(v) => ints(v.into())
+            .into_iter()
+            .map(|it| Lit::I32(it as i32))
+            .collect(),
+        Lit::U32(v) => ints(v.into())
+            .into_iter()
+            .map(|it| Lit::U32(it as u32))
+            .collect(),
+        Lit::AbstractInt(v) => ints(v).into_iter().map(Lit::AbstractInt).collect(),
+        Lit::F32(v) => floats(v.into())
+            .into_iter()
+            .map(|it| Lit::F32(it as f32))
+            .collect(),
+        Lit::F16(v) => floats(v.into())
+            .into_iter()
+            .map(|it| Lit::F16(it as f32))
+            .collect(),
+        Lit::AbstractFloat(v) => floats(v).into_iter().map(Lit::AbstractFloat).collect(),
+    }
+}
+
+/// Returns the zero value of a type, if expressions of the type can be replaced with it.
+fn zero_value(data_type: &DataType) -> Option<ExprNode> {
+    match data_type.dereference() {
+        DataType::Scalar(ty) => Some(
+            match ty {
+                ScalarType::Bool => Lit::Bool(false),
+                ScalarType::I32 => Lit::I32(0),
+                ScalarType::U32 => Lit::U32(0),
+                ScalarType::F32 => Lit::F32(0.0),
+                ScalarType::F16 => Lit::F16(0.0),
+            }
+            .into(),
+        ),
+        ty @ DataType::Vector(..) => Some(TypeConsExpr::new(ty.clone(), vec![]).into()),
+        _ => None,
+    }
+}
+
+fn apply(module: &mut Module, edit: &Edit) -> Counts {
+    let mut editor = Editor {
+        edit,
+        counts: Counts::default(),
+    };
+
+    editor.visit_module(module);
+    editor.counts
+}
+
+struct Editor<'a> {
+    edit: &'a Edit,
+    counts: Counts,
+}
+
+impl Editor<'_> {
+    fn visit_module(&mut self, module: &mut Module) {
+        module.structs.retain(|_| self.keep_decl());
+
+        module.consts.retain_mut(|decl| {
+            let keep = self.keep_decl();
+            self.visit_expr(&mut decl.initializer);
+            keep
+        });
+
+        for decl in &mut module.overrides {
+            self.visit_opt_expr(&mut decl.initializer);
+        }
+
+        // Resources are kept, since the input data refers to them
+        module.vars.retain_mut(|decl| {
+            let keep = decl.group_index().is_some() || self.keep_decl();
+            self.visit_opt_expr(&mut decl.initializer);
+            keep
+        });
+
+        module.functions.retain_mut(|function| {
+            let is_entry_point = function
+                .attrs
+                .iter()
+                .any(|it| matches!(it, FnAttr::Stage(_)));
+            let keep = is_entry_point || self.keep_decl();
+            self.visit_block(&mut function.body);
+            keep
+        });
+    }
+
+    /// Counts a global declaration, and returns whether it is kept.
+    fn keep_decl(&mut self) -> bool {
+        let index = self.counts.decls;
+        self.counts.decls += 1;
+        !matches!(self.edit, Edit::RemoveDecls(range) if range.contains(&index))
+    }
+
+    fn visit_block(&mut self, block: &mut Vec<Statement>) {
+        block.retain_mut(|stmt| {
+            let index = self.counts.stmts;
+            self.counts.stmts += 1;
+
+            if matches!(self.edit, Edit::RemoveStmts(range) if range.contains(&index)) {
+                // The statements nested in this one are removed with it, but still keep their
+                // indices
+                self.counts.stmts += apply_to_stmt(stmt, &Edit::None).stmts;
+                return false;
+            }
+
+            self.visit_stmt(stmt);
+            true
+        });
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::LetDecl(stmt) => self.visit_expr(&mut stmt.initializer),
+            Statement::ConstDecl(stmt) => self.visit_expr(&mut stmt.initializer),
+            Statement::ConstAssert(stmt) => self.visit_expr(&mut stmt.expr),
+            Statement::VarDecl(stmt) => self.visit_opt_expr(&mut stmt.initializer),
+            Statement::Assignment(stmt) => self.visit_expr(&mut stmt.rhs),
+            Statement::Increment(_) | Statement::Decrement(_) => {}
+            Statement::Compound(block) => self.visit_block(block),
+            Statement::If(stmt) => self.visit_if(stmt),
+            Statement::Return(stmt) => self.visit_opt_expr(&mut stmt.value),
+            Statement::Loop(stmt) => {
+                self.visit_block(&mut stmt.body);
+                if let Some(continuing) = &mut stmt.continuing {
+                    self.visit_block(&mut continuing.body);
+                    self.visit_opt_expr(&mut continuing.break_if);
+                }
+            }
+            Statement::Break | Statement::Continue | Statement::Fallthrough => {}
+            // Case selectors are left alone, since replacing them would duplicate cases
+            Statement::Switch(stmt) => {
+                self.visit_expr(&mut stmt.selector);
+                for case in &mut stmt.cases {
+                    self.visit_block(&mut case.body);
+                }
+                self.visit_block(&mut stmt.default);
+            }
+            Statement::ForLoop(stmt) => {
+                if let Some(ForLoopInit::VarDecl(init)) = &mut stmt.header.init {
+                    self.visit_opt_expr(&mut init.initializer);
+                }
+                self.visit_opt_expr(&mut stmt.header.condition);
+                if let Some(ForLoopUpdate::Assignment(update)) = &mut stmt.header.update {
+                    self.visit_expr(&mut update.rhs);
+                }
+                self.visit_block(&mut stmt.body);
+            }
+            Statement::While(stmt) => {
+                self.visit_expr(&mut stmt.condition);
+                self.visit_block(&mut stmt.body);
+            }
+            Statement::FnCall(stmt) => {
+                for arg in &mut stmt.args {
+                    self.visit_expr(arg);
+                }
+            }
+        }
+    }
+
+    fn visit_if(&mut self, stmt: &mut IfStatement) {
+        self.visit_expr(&mut stmt.condition);
+        self.visit_block(&mut stmt.body);
+        match stmt.else_.as_deref_mut() {
+            Some(Else::If(stmt)) => self.visit_if(stmt),
+            Some(Else::Else(block)) => self.visit_block(block),
+            None => {}
+        }
+    }
+
+    fn visit_opt_expr(&mut self, expr: &mut Option<ExprNode>) {
+        if let Some(expr) = expr {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut ExprNode) {
+        if let Expr::Lit(lit) = &mut expr.expr {
+            let index = self.counts.lits.len();
+            self.counts.lits.push(*lit);
+
+            if let Edit::ReplaceLit(i, new) = self.edit {
+                if *i == index {
+                    *lit = *new;
+                }
+            }
+
+            return;
+        }
+
+        // Zero-value constructors are already as simple as they get
+        let is_zero = matches!(&expr.expr, Expr::TypeCons(it) if it.args.is_empty());
+        if let Some(zero) = zero_value(&expr.data_type).filter(|_| !is_zero) {
+            let index = self.counts.exprs;
+            self.counts.exprs += 1;
+
+            if matches!(self.edit, Edit::ReplaceExprs(range) if range.contains(&index)) {
+                let mut skipped = Editor {
+                    edit: &Edit::None,
+                    counts: Counts::default(),
+                };
+                skipped.visit_subexprs(expr);
+                self.counts.exprs += skipped.counts.exprs;
+
+                *expr = zero;
+                return;
+            }
+        }
+
+        self.visit_subexprs(expr);
+    }
+
+    fn visit_subexprs(&mut self, expr: &mut ExprNode) {
+        match &mut expr.expr {
+            Expr::Lit(_) | Expr::Var(_) => {}
+            Expr::TypeCons(expr) => {
+                for arg in &mut expr.args {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Bitcast(expr) => self.visit_expr(&mut expr.inner),
+            Expr::Postfix(expr) => {
+                self.visit_expr(&mut expr.inner);
+                if let Postfix::Index(index) = &mut expr.postfix {
+                    self.visit_expr(index);
+                }
+            }
+            // The operand of `&` has to refer to memory, so it can't be replaced with a value
+            Expr::UnOp(expr) if expr.op == UnOp::AddressOf => {}
+            Expr::UnOp(expr) => self.visit_expr(&mut expr.inner),
+            Expr::BinOp(expr) => {
+                self.visit_expr(&mut expr.left);
+                self.visit_expr(&mut expr.right);
+            }
+            Expr::FnCall(expr) => {
+                for arg in &mut expr.args {
+                    self.visit_expr(arg);
+                }
+            }
+        }
+    }
+}
+
+fn apply_to_stmt(stmt: &mut Statement, edit: &Edit) -> Counts {
+    let mut editor = Editor {
+        edit,
+        counts: Counts::default(),
+    };
+
+    editor.visit_stmt(stmt);
+    editor.counts
+}
+
+/// Returns whether the parser accepts the shader. An edit can leave a shader invalid (e.g. by
+/// removing a declaration that is still used), which the parser panics on, so the panic is caught
+/// without printing it.
+fn parses(source: &str) -> bool {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let result = std::panic::catch_unwind(|| parser::parse(source));
+    std::panic::set_hook(hook);
+    result.is_ok()
+}
+
+fn write_module(module: &Module) -> String {
+    let mut output = String::new();
+    ast::writer::Writer
+        .write_module(&mut output, module)
+        .unwrap();
+    output
+}
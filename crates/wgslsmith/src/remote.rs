@@ -1,67 +1,409 @@
-use std::net::{SocketAddr, TcpStream};
-use std::str::FromStr;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
 use std::time::Duration;
 
 use bincode::Decode;
 use eyre::{eyre, Context};
-use harness_frontend::{ExecutionError, ExecutionEvent};
-use harness_server_types::{ListResponse, Request, RunError, RunMessage, RunRequest};
-use harness_types::ConfigId;
+use harness_frontend::{environment_json, ExecutionError, ExecutionEvent, OutputFormat, Printer};
+use harness_server_types::{
+    CancelResponse, FetchRequest, FetchResponse, ListResponse, OutputMode, Request, RunError,
+    RunMessage, RunRequest, StatusResponse, PROTOCOL_VERSION,
+};
+use harness_types::{AdapterFilter, BackendOptions, ConfigId, RetryPolicy, Timings, Variant};
 use reflection_types::PipelineDescription;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use serde_json::json;
+
+/// Prefix of the address of a server which is connected to with TLS, e.g. `tls://gpu-box:1234`.
+const TLS_SCHEME: &str = "tls://";
+
+/// Environment variable with the path to a PEM file of CA certificates which are trusted to sign
+/// the certificates of TLS servers, in addition to the system's. This is needed for servers with
+/// self-signed certificates.
+const TLS_CA_VAR: &str = "WGSLSMITH_TLS_CA";
+
+/// A connection to a server, which may be encrypted with TLS.
+trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
 
 pub fn list(server: &str) -> eyre::Result<ListResponse> {
     decode_from_stream(&mut req(server, Request::List)?).map_err(Into::into)
 }
 
+pub fn status(server: &str) -> eyre::Result<StatusResponse> {
+    decode_from_stream(&mut req(server, Request::Status)?).map_err(Into::into)
+}
+
+/// Cancels a run on the server, and returns whether it was still in progress.
+pub fn cancel(server: &str, job: u64) -> eyre::Result<bool> {
+    let res: CancelResponse = decode_from_stream(&mut req(server, Request::Cancel(job))?)?;
+    Ok(res.cancelled)
+}
+
+/// Cancels the run that is stored in `job` when the process is interrupted, which stops its
+/// executions on the server. A second interrupt exits without waiting for the run to end, as does
+/// the first if the run hasn't started yet.
+#[cfg(unix)]
+pub fn cancel_on_interrupt(
+    server: String,
+    job: Arc<std::sync::Mutex<Option<u64>>>,
+) -> io::Result<()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+
+    let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM])?;
+
+    std::thread::spawn(move || {
+        let mut cancelling = false;
+        for _ in &mut signals {
+            let job = *job.lock().unwrap();
+            match job {
+                Some(job) if !cancelling => {
+                    cancelling = true;
+                    eprintln!("cancelling job {job} (interrupt again to exit without waiting)");
+                    if let Err(e) = cancel(&server, job) {
+                        eprintln!("failed to cancel job {job}: {e:#}");
+                        std::process::exit(130);
+                    }
+                }
+                _ => std::process::exit(130),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+pub fn print_status(status: StatusResponse, format: OutputFormat) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    if format == OutputFormat::Json {
+        let mut value = environment_json(&status.environment);
+        value["version"] = json!(status.version);
+        value["protocol_version"] = json!(status.protocol_version);
+        value["queued"] = json!(status.queued);
+        value["running"] = json!(status.running);
+        value["uptime_secs"] = json!(status.uptime.as_secs());
+        value["pipeline_cache"] = json!(status.pipeline_cache.map(|it| json!({
+            "hits": it.hits,
+            "misses": it.misses,
+        })));
+
+        serde_json::to_writer(&mut stdout, &value)?;
+        return writeln!(stdout);
+    }
+
+    let environment = status.environment;
+
+    let uptime = status.uptime.as_secs();
+    writeln!(
+        &mut stdout,
+        "version: {} (protocol {})",
+        status.version, status.protocol_version
+    )?;
+    writeln!(
+        &mut stdout,
+        "uptime: {}h {:02}m {:02}s",
+        uptime / 3600,
+        uptime / 60 % 60,
+        uptime % 60
+    )?;
+    writeln!(
+        &mut stdout,
+        "queue: {} running, {} waiting",
+        status.running, status.queued
+    )?;
+
+    if let Some(cache) = status.pipeline_cache {
+        writeln!(
+            &mut stdout,
+            "pipeline cache: {} hits, {} misses",
+            cache.hits, cache.misses
+        )?;
+    }
+
+    write!(&mut stdout, "os: {}", environment.os)?;
+
+    // Commits are shortened in the same way as by git
+    if let Some(commit) = &environment.dawn_commit {
+        write!(&mut stdout, ", dawn {}", &commit[..commit.len().min(7)])?;
+    }
+
+    if let Some(commit) = &environment.wgpu_commit {
+        write!(&mut stdout, ", wgpu {}", &commit[..commit.len().min(7)])?;
+    }
+
+    writeln!(&mut stdout)?;
+    writeln!(&mut stdout)?;
+
+    // The table is printed with colors, which needs its own handle to stdout
+    drop(stdout);
+
+    Printer::new().print_all_configs(environment.configs)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn execute(
     server: &str,
     shader: String,
     pipeline_desc: PipelineDescription,
     configs: Vec<ConfigId>,
+    oracle: bool,
+    variants: Vec<Variant>,
+    filter: AdapterFilter,
+    options: BackendOptions,
     timeout: Option<Duration>,
+    retry: RetryPolicy,
+    outputs: OutputMode,
+    on_job: &mut dyn FnMut(u64),
     on_event: &mut dyn FnMut(ExecutionEvent) -> Result<(), ExecutionError>,
 ) -> Result<(), ExecutionError> {
     let mut stream = req(
         server,
-        Request::Run(RunRequest {
+        Request::Run(Box::new(RunRequest {
             shader,
             pipeline_desc,
             configs,
+            oracle,
+            variants,
+            filter,
+            options,
             timeout,
-        }),
+            retry,
+            outputs,
+        })),
     )?;
 
+    // In digest mode, successful executions are only reported once their outputs are fetched at
+    // the end of the run
+    let mut job = None;
+    let mut digests = vec![];
+
     loop {
         match decode_from_stream(&mut stream)? {
+            RunMessage::Job(id) => {
+                job = Some(id);
+                on_job(id)
+            }
+            RunMessage::Environment(environment) => {
+                on_event(ExecutionEvent::Environment(environment))?
+            }
             RunMessage::UsingDefaultConfigs(configs) => {
                 on_event(ExecutionEvent::UsingDefaultConfigs(configs))?
             }
+            RunMessage::UsingOracle(config) => on_event(ExecutionEvent::UsingOracle(config))?,
+            RunMessage::ExecQueued(config, ahead) => {
+                on_event(ExecutionEvent::Queued(config, ahead))?
+            }
             RunMessage::ExecStart(config) => on_event(ExecutionEvent::Start(config))?,
-            RunMessage::ExecSuccess(config, buffers) => {
-                on_event(ExecutionEvent::Success(config, buffers))?
+            RunMessage::ExecUnsupported(config, reason) => {
+                on_event(ExecutionEvent::Unsupported(config, reason))?
+            }
+            RunMessage::ExecSuccess(config, buffers, timings) => {
+                on_event(ExecutionEvent::Success(config, buffers, timings))?
+            }
+            RunMessage::ExecDigest(config, digest, timings) => {
+                digests.push((config, digest, timings))
             }
-            RunMessage::ExecFailure(stderr) => on_event(ExecutionEvent::Failure(stderr))?,
-            RunMessage::ExecTimeout => on_event(ExecutionEvent::Timeout)?,
+            RunMessage::ExecCompilationFailure(config, stderr) => {
+                on_event(ExecutionEvent::CompilationFailure(config, stderr))?
+            }
+            RunMessage::ExecValidationFailure(config, stderr) => {
+                on_event(ExecutionEvent::ValidationFailure(config, stderr))?
+            }
+            RunMessage::ExecRuntimeFailure(config, stderr) => {
+                on_event(ExecutionEvent::RuntimeFailure(config, stderr))?
+            }
+            RunMessage::ExecDeviceLost(config, stderr) => {
+                on_event(ExecutionEvent::DeviceLost(config, stderr))?
+            }
+            RunMessage::ExecTimeout(config) => on_event(ExecutionEvent::Timeout(config))?,
+            RunMessage::ExecHang(config) => on_event(ExecutionEvent::Hang(config))?,
             RunMessage::End(result) => {
-                return result.map_err(|e| match e {
+                result.map_err(|e| match e {
                     RunError::NoDefaultConfigs => ExecutionError::NoDefaultConfigs,
+                    RunError::NoOracleConfig => ExecutionError::NoOracleConfig,
+                    RunError::Cancelled => ExecutionError::Cancelled,
                     RunError::InternalServerError => {
                         ExecutionError::Other(eyre!("internal server error"))
                     }
-                })
+                })?;
+
+                if let (Some(job), false) = (job, digests.is_empty()) {
+                    fetch_outputs(server, job, digests, on_event)?;
+                }
+
+                return Ok(());
             }
         }
     }
 }
 
-fn req(server: &str, req: Request) -> eyre::Result<TcpStream> {
-    let address = SocketAddr::from_str(server)?;
-    let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(10))
-        .wrap_err_with(|| format!("failed to connect to {server}"))?;
-    bincode::encode_into_std_write(req, &mut stream, bincode::config::standard())?;
+/// Fetches the output of one config for each distinct digest, and reports each execution as a
+/// success with the output of the config that it shares a digest with. Configs whose outputs
+/// match exactly are compared in the same way as if their own outputs had been sent, except that
+/// the contents of padding are those of the other config.
+fn fetch_outputs(
+    server: &str,
+    job: u64,
+    digests: Vec<(ConfigId, [u8; 32], Timings)>,
+    on_event: &mut dyn FnMut(ExecutionEvent) -> Result<(), ExecutionError>,
+) -> Result<(), ExecutionError> {
+    let mut representatives: Vec<(&[u8; 32], &ConfigId)> = vec![];
+    for (config, digest, _) in &digests {
+        if !representatives.iter().any(|(it, _)| *it == digest) {
+            representatives.push((digest, config));
+        }
+    }
+
+    let res: FetchResponse = decode_from_stream(&mut *req(
+        server,
+        Request::Fetch(FetchRequest {
+            job,
+            configs: representatives
+                .iter()
+                .map(|(_, config)| (*config).clone())
+                .collect(),
+        }),
+    )?)?;
+
+    if res.outputs.len() != representatives.len() {
+        return Err(eyre!("{server} sent the wrong number of outputs").into());
+    }
+
+    let outputs = representatives
+        .iter()
+        .zip(res.outputs)
+        .map(|((digest, _), buffers)| Some((**digest, buffers?)))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| eyre!("{server} no longer has the outputs of job {job}"))?;
+
+    for (config, digest, timings) in digests {
+        let (_, buffers) = outputs
+            .iter()
+            .find(|(it, _)| *it == digest)
+            .expect("every digest has an output");
+
+        on_event(ExecutionEvent::Success(config, buffers.clone(), timings))?;
+    }
+
+    Ok(())
+}
+
+fn req(server: &str, req: Request) -> eyre::Result<Box<dyn Connection>> {
+    let mut stream = connect(server)?;
+
+    let sent = send(&mut *stream, req);
+
+    // A server with another version may close the connection before the whole request has been
+    // sent, so the version is checked first to report why
+    check_version(&mut stream, server)?;
+    sent?;
+
     Ok(stream)
 }
 
-fn decode_from_stream<T: Decode>(stream: &mut TcpStream) -> Result<T, bincode::error::DecodeError> {
-    bincode::decode_from_std_read(stream, bincode::config::standard())
+fn send(mut stream: &mut dyn Connection, req: Request) -> eyre::Result<()> {
+    harness_server_types::write_version(&mut stream)?;
+    bincode::encode_into_std_write(req, &mut stream, bincode::config::standard())?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Checks that the server uses the same protocol, which it reports before responding, so that a
+/// mismatch isn't reported as a failure to decode its response.
+fn check_version(stream: &mut impl Read, server: &str) -> eyre::Result<()> {
+    let version = match harness_server_types::read_version(stream) {
+        Ok(version) => version,
+        // Servers from before the protocol was versioned can't decode the request, so they close
+        // the connection without responding
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+            ) =>
+        {
+            return Err(eyre!(
+                "{server} closed the connection without reporting its protocol version, it may be \
+                 older than the client (protocol v{PROTOCOL_VERSION})"
+            ))
+        }
+        Err(e) => {
+            return Err(e).wrap_err_with(|| format!("failed to read protocol version of {server}"))
+        }
+    };
+
+    match version {
+        Some(PROTOCOL_VERSION) => Ok(()),
+        Some(version) => Err(eyre!(
+            "incompatible protocol versions: client v{PROTOCOL_VERSION} vs server v{version}, \
+             both must be built from the same commit"
+        )),
+        None => Err(eyre!("{server} isn't a wgslsmith harness server")),
+    }
+}
+
+fn connect(server: &str) -> eyre::Result<Box<dyn Connection>> {
+    let (address, is_tls) = match server.strip_prefix(TLS_SCHEME) {
+        Some(address) => (address, true),
+        None => (server, false),
+    };
+
+    let socket_address = address
+        .to_socket_addrs()
+        .wrap_err_with(|| format!("invalid server address: {server}"))?
+        .next()
+        .ok_or_else(|| eyre!("{address} didn't resolve to any addresses"))?;
+
+    let mut stream = TcpStream::connect_timeout(&socket_address, Duration::from_secs(10))
+        .wrap_err_with(|| format!("failed to connect to {server}"))?;
+
+    if !is_tls {
+        return Ok(Box::new(stream));
+    }
+
+    // The server's certificate must be valid for the host that it was connected to
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let name = ServerName::try_from(host.to_owned())?;
+
+    let mut connection = ClientConnection::new(tls_config()?, name)?;
+    while connection.is_handshaking() {
+        connection
+            .complete_io(&mut stream)
+            .wrap_err_with(|| format!("TLS handshake with {server} failed"))?;
+    }
+
+    Ok(Box::new(StreamOwned::new(connection, stream)))
+}
+
+fn tls_config() -> eyre::Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+
+    // Certificates which can't be loaded are skipped, as by other TLS clients
+    roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+
+    if let Some(path) = std::env::var_os(TLS_CA_VAR) {
+        let certs = CertificateDer::pem_file_iter(&path)
+            .and_then(|it| it.collect::<Result<Vec<_>, _>>())
+            .wrap_err_with(|| format!("failed to read certificates from {path:?}"))?;
+        roots.add_parsable_certificates(certs);
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+fn decode_from_stream<T: Decode>(
+    mut stream: &mut dyn Connection,
+) -> Result<T, bincode::error::DecodeError> {
+    bincode::decode_from_std_read(&mut stream, bincode::config::standard())
 }
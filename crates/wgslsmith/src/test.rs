@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use ast::Module;
 use clap::Parser;
@@ -31,6 +31,11 @@ pub struct Options {
 
     #[clap(short, long, action)]
     quiet: bool,
+
+    /// Directory in which to cache whether shaders are interesting, so that a shader which was
+    /// already tested against the same inputs and targets isn't executed again.
+    #[clap(long, action)]
+    cache: Option<PathBuf>,
 }
 
 #[derive(Parser)]
@@ -47,12 +52,15 @@ pub struct CrashOptions {
     #[clap(long, value_enum, action)]
     backend: Option<Backend>,
 
-    #[clap(long, action, required_if_eq("kind", "crash"))]
+    #[clap(long, action)]
     regex: Option<Regex>,
 
     #[clap(long, action)]
     inverse_regex: Option<Regex>,
 
+    #[clap(long, action, requires("config"))]
+    signature: Option<String>,
+
     #[clap(long, action)]
     no_recondition: bool,
 }
@@ -60,7 +68,7 @@ pub struct CrashOptions {
 pub fn run(config: &Config, options: Options) -> eyre::Result<()> {
     let source = std::fs::read_to_string(&options.shader)?;
 
-    let input_path = if let Some(input_path) = options.input_data {
+    let input_path = if let Some(input_path) = options.input_data.clone() {
         input_path
     } else {
         let mut try_path = options
@@ -108,16 +116,66 @@ pub fn run(config: &Config, options: Options) -> eyre::Result<()> {
         &options.crash_options.targets,
     )?;
 
-    match options.kind {
+    if let ReductionKind::Crash = options.kind {
+        if options.crash_options.regex.is_none() && options.crash_options.signature.is_none() {
+            return Err(eyre!(
+                "either --regex or --signature is required to reduce a crash"
+            ));
+        }
+    }
+
+    // The shader is reconditioned up front, so that shaders which only differ in ways that
+    // reconditioning erases share a cache entry
+    let source = match options.kind {
+        ReductionKind::Crash if options.crash_options.no_recondition => source,
+        _ => recondition(parser::parse(&source)),
+    };
+
+    let cache_path = options
+        .cache
+        .as_ref()
+        .map(|dir| dir.join(cache_key(&options, &source, &metadata, &targets)));
+
+    if let Some(interesting) = cache_path.as_deref().and_then(read_cached) {
+        if !options.quiet {
+            println!("using cached result");
+        }
+
+        return if interesting {
+            println!("interesting :)");
+            Ok(())
+        } else {
+            Err(eyre!("shader is not interesting (cached)"))
+        };
+    }
+
+    let interesting = match options.kind {
         ReductionKind::Crash => reduce_crash(
             config,
-            options.crash_options,
-            source,
-            metadata,
+            &options.crash_options,
+            &source,
+            &metadata,
             &targets,
             options.quiet,
         )?,
-        ReductionKind::Mismatch => reduce_mismatch(source, metadata, &targets, options.quiet)?,
+        ReductionKind::Mismatch => reduce_mismatch(&source, &metadata, &targets, options.quiet)?,
+        ReductionKind::Timeout => reduce_timeout(
+            &source,
+            &metadata,
+            options.crash_options.config.as_ref(),
+            &targets,
+            options.quiet,
+        )?,
+    };
+
+    // Errors (e.g. a harness that couldn't be reached) are returned above, so only outcomes of
+    // executions that actually happened are cached
+    if let Some(cache_path) = &cache_path {
+        write_cached(cache_path, interesting)?;
+    }
+
+    if !interesting {
+        return Err(eyre!("shader is not interesting"));
     }
 
     println!("interesting :)");
@@ -125,29 +183,85 @@ pub fn run(config: &Config, options: Options) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Returns the hex-encoded hash of everything that the outcome of the test depends on.
+fn cache_key(options: &Options, source: &str, metadata: &str, targets: &[Target]) -> String {
+    let mut hasher = blake3::Hasher::new();
+
+    // Each part is prefixed with its length, so that the boundaries between them are unambiguous
+    let mut update = |part: &str| {
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    };
+
+    update(&format!("{:?}", options.kind));
+    update(source);
+    update(metadata);
+    update(&format!("{targets:?}"));
+
+    let crash_options = &options.crash_options;
+    let crash_options = [
+        crash_options.config.as_ref().map(|it| it.to_string()),
+        crash_options.compiler.as_ref().map(|it| it.to_string()),
+        crash_options.backend.as_ref().map(|it| it.to_string()),
+        crash_options.regex.as_ref().map(|it| it.to_string()),
+        crash_options
+            .inverse_regex
+            .as_ref()
+            .map(|it| it.to_string()),
+        crash_options.signature.clone(),
+    ];
+
+    for option in crash_options {
+        update(&format!("{option:?}"));
+    }
+
+    hasher.finalize().to_hex().to_string()
+}
+
+fn read_cached(path: &Path) -> Option<bool> {
+    match std::fs::read_to_string(path).ok()?.as_str() {
+        "interesting" => Some(true),
+        "not interesting" => Some(false),
+        _ => None,
+    }
+}
+
+fn write_cached(path: &Path, interesting: bool) -> eyre::Result<()> {
+    let dir = path.parent().unwrap();
+    std::fs::create_dir_all(dir)?;
+
+    // The result is written to a temporary file first, since other tests may be reading the
+    // cache at the same time
+    let tmp_path = dir.join(format!(".tmp-{}", std::process::id()));
+    let contents = if interesting {
+        "interesting"
+    } else {
+        "not interesting"
+    };
+
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
 fn reduce_crash(
     config: &Config,
-    options: CrashOptions,
-    source: String,
-    metadata: String,
+    options: &CrashOptions,
+    source: &str,
+    metadata: &str,
     targets: &[Target],
     quiet: bool,
-) -> eyre::Result<()> {
-    let regex = options.regex.unwrap();
-    let inverse_regex = options.inverse_regex;
-    let should_recondition = !options.no_recondition;
-
-    let source = if should_recondition {
-        recondition(parser::parse(&source))
-    } else {
-        source
-    };
+) -> eyre::Result<bool> {
+    let regex = options.regex.as_ref();
+    let inverse_regex = options.inverse_regex.as_ref();
+    let signature = options.signature.as_ref();
 
     let interesting = if options.config.is_some() {
         let mut any_crash_matched = false;
 
         for target in targets {
-            let result = harness_runner::exec_shader(target, &source, &metadata, |line| {
+            let result = harness_runner::exec_shader(target, source, metadata, |line| {
                 if !quiet {
                     println!("{line}");
                 }
@@ -157,7 +271,11 @@ fn reduce_crash(
                 eprintln!("{result:?}");
             }
 
-            if matches!(result, ExecutionResult::Crash(output) if (regex.is_match(&output)) && !inverse_regex.clone().map(|r| r.is_match(&output)).unwrap_or(false))
+            // The crash has to match both the regex and the signature, if they were given
+            if matches!(&result, ExecutionResult::Crash(output, crash)
+                if regex.is_none_or(|r| r.is_match(output))
+                    && !inverse_regex.is_some_and(|r| r.is_match(output))
+                    && signature.is_none_or(|it| *it == crash.key()))
             {
                 any_crash_matched = true;
                 break;
@@ -165,45 +283,39 @@ fn reduce_crash(
         }
         any_crash_matched
     } else {
-        let compiler = options.compiler.unwrap();
+        let compiler = options.compiler.clone().unwrap();
         let backend = options.backend.unwrap();
-        let compiled = compiler.compile(&source, backend)?;
+        let regex = regex.ok_or_else(|| eyre!("--regex is required to reduce a compiler crash"))?;
+        let compiled = compiler.compile(source, backend)?;
 
         match backend {
             Backend::Hlsl => {
-                remote_validate(config, &compiled, validator::Backend::Hlsl, &regex, quiet)?
+                remote_validate(config, &compiled, validator::Backend::Hlsl, regex, quiet)?
             }
             Backend::Msl => {
-                remote_validate(config, &compiled, validator::Backend::Msl, &regex, quiet)?
+                remote_validate(config, &compiled, validator::Backend::Msl, regex, quiet)?
             }
             Backend::Spirv => todo!(),
         }
     };
 
-    if !interesting {
-        return Err(eyre!("shader is not interesting"));
-    }
-
-    Ok(())
+    Ok(interesting)
 }
 
 fn reduce_mismatch(
-    source: String,
-    metadata: String,
+    source: &str,
+    metadata: &str,
     targets: &[Target],
     quiet: bool,
-) -> eyre::Result<()> {
-    let module = parser::parse(&source);
-    let reconditioned = recondition(module);
-
-    Compiler::Naga.validate(&reconditioned)?;
-    Compiler::Tint.validate(&reconditioned)?;
+) -> eyre::Result<bool> {
+    Compiler::Naga.validate(source)?;
+    Compiler::Tint.validate(source)?;
 
     let mut consensus: Option<Vec<u8>> = None;
     let mut mismatch_found = false;
 
     for target in targets {
-        let result = harness_runner::exec_shader(target, &reconditioned, &metadata, |line| {
+        let result = harness_runner::exec_shader(target, source, metadata, |line| {
             if !quiet {
                 println!("{line}");
             }
@@ -237,18 +349,53 @@ fn reduce_mismatch(
         }
     }
 
-    if !mismatch_found {
-        return Err(eyre!("shader is not interesting (no mismatch found)"));
+    if !mismatch_found && !quiet {
+        println!("no mismatch found");
     }
 
-    Ok(())
+    Ok(mismatch_found)
+}
+
+fn reduce_timeout(
+    source: &str,
+    metadata: &str,
+    config: Option<&ConfigId>,
+    targets: &[Target],
+    quiet: bool,
+) -> eyre::Result<bool> {
+    Compiler::Naga.validate(source)?;
+    Compiler::Tint.validate(source)?;
+
+    let mut timeout_found = false;
+
+    for target in targets {
+        let result = harness_runner::exec_shader(target, source, metadata, |line| {
+            if !quiet {
+                println!("{line}");
+            }
+        })?;
+
+        // If a config was given, only a timeout on that config is interesting
+        if let ExecutionResult::Timeout(configs) = result {
+            if config.is_none_or(|it| configs.contains(it)) {
+                timeout_found = true;
+                break;
+            }
+        }
+    }
+
+    if !timeout_found && !quiet {
+        println!("no timeout found");
+    }
+
+    Ok(timeout_found)
 }
 
 fn recondition(module: Module) -> String {
-    let reconditioned = reconditioner::recondition(module);
+    let reconditioned = reconditioner::recondition(module).ast;
     let mut formatted = String::new();
 
-    ast::writer::Writer::default()
+    ast::writer::Writer
         .write_module(&mut formatted, &reconditioned)
         .unwrap();
 
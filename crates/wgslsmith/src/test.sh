@@ -12,12 +12,21 @@ if [[ -n "${WGSLREDUCE_SERVER-}" ]]; then
     args+=("--server" "$WGSLREDUCE_SERVER")
 fi
 
+if [[ -n "${WGSLREDUCE_CACHE-}" ]]; then
+    args+=("--cache" "$WGSLREDUCE_CACHE")
+fi
+
 if [[ -n "${WGSLREDUCE_TARGETS-}" ]]; then
     args+=(${WGSLREDUCE_TARGETS-})
 fi
 
 if [[ "$WGSLREDUCE_KIND" == "crash" ]]; then
-    args+=("--regex" "$WGSLREDUCE_REGEX")
+    if [[ -n "${WGSLREDUCE_REGEX-}" ]]; then
+        args+=("--regex" "$WGSLREDUCE_REGEX")
+    fi
+    if [[ -n "${WGSLREDUCE_SIGNATURE-}" ]]; then
+        args+=("--signature" "$WGSLREDUCE_SIGNATURE")
+    fi
     if [[ -n "${WGSLREDUCE_INVERSE_REGEX-}" ]]; then
         args+=("--inverse-regex" "$WGSLREDUCE_INVERSE_REGEX")
     fi
@@ -34,6 +43,10 @@ if [[ "$WGSLREDUCE_KIND" == "crash" ]]; then
     if [[ ! -n "${WGSLREDUCE_RECONDITION}" ]]; then
         args+=("--no-recondition")
     fi
+elif [[ "$WGSLREDUCE_KIND" == "timeout" ]]; then
+    if [[ -n "${WGSLREDUCE_CONFIG-}" ]]; then
+        args+=("--config" "$WGSLREDUCE_CONFIG")
+    fi
 fi
 
 [WGSLSMITH] test -q "${args[@]}"